@@ -239,7 +239,14 @@ pub fn test_opts(config: &config) -> test::TestOpts {
         ratchet_metrics: config.ratchet_metrics.clone(),
         ratchet_noise_percent: config.ratchet_noise_percent.clone(),
         save_metrics: config.save_metrics.clone(),
-        test_shard: config.test_shard.clone()
+        save_failures: None,
+        rerun_failed: None,
+        test_shard: config.test_shard.clone(),
+        test_timeout: None,
+        bench_samples: None,
+        baseline: None,
+        max_regression: None,
+        format: test::PrettyOutput
     }
 }
 
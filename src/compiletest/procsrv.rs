@@ -49,12 +49,13 @@ pub fn run(lib_path: &str,
 
     let env = env + target_env(lib_path, prog);
     let mut proc = run::Process::new(prog, args, run::ProcessOptions {
-        env: Some(env),
+        env: run::Exactly(env),
         dir: None,
         in_fd: None,
         out_fd: None,
-        err_fd: None
-    });
+        err_fd: None,
+        detach: false
+    }).unwrap();
 
     for input in input.iter() {
         proc.input().write(input.as_bytes());
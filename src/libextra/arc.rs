@@ -244,6 +244,21 @@ impl<T:Send> MutexArc<T> {
         }
     }
 
+    /**
+     * As unsafe_access(), but returns None instead of blocking if another
+     * task holds the mutex. Never spins; the closure either runs at once or
+     * not at all.
+     */
+    #[inline]
+    pub unsafe fn unsafe_try_access<U>(&self, blk: &fn(x: &mut T) -> U) -> Option<U> {
+        let state = self.x.get();
+        do (&(*state).lock).try_lock {
+            check_poison(true, (*state).failed);
+            let _z = PoisonOnFail(&mut (*state).failed);
+            blk(&mut (*state).data)
+        }
+    }
+
     /**
      * Retrieves the data, blocking until all other references are dropped,
      * exactly as arc::unwrap.
@@ -292,6 +307,12 @@ impl<T:Freeze + Send> MutexArc<T> {
                                   -> U {
         unsafe { self.unsafe_access_cond(blk) }
     }
+
+    /// As unsafe_try_access but safe and Freeze.
+    #[inline]
+    pub fn try_access<U>(&self, blk: &fn(x: &mut T) -> U) -> Option<U> {
+        unsafe { self.unsafe_try_access(blk) }
+    }
 }
 
 // Common code for {mutex.access,rwlock.write}{,_cond}.
@@ -432,6 +453,36 @@ impl<T:Freeze + Send> RWArc<T> {
         }
     }
 
+    /**
+     * As write(), but returns None instead of blocking if other tasks are
+     * reading or writing, or waiting to.
+     */
+    #[inline]
+    pub fn try_write<U>(&self, blk: &fn(x: &mut T) -> U) -> Option<U> {
+        unsafe {
+            let state = self.x.get();
+            do (*borrow_rwlock(state)).try_write {
+                check_poison(false, (*state).failed);
+                let _z = PoisonOnFail(&mut (*state).failed);
+                blk(&mut (*state).data)
+            }
+        }
+    }
+
+    /**
+     * As read(), but returns None instead of blocking if a writer holds the
+     * lock or is waiting for it.
+     */
+    pub fn try_read<U>(&self, blk: &fn(x: &T) -> U) -> Option<U> {
+        unsafe {
+            let state = self.x.get();
+            do (*state).lock.try_read {
+                check_poison(false, (*state).failed);
+                blk(&(*state).data)
+            }
+        }
+    }
+
     /**
      * As write(), but with the ability to atomically 'downgrade' the lock.
      * See sync::rwlock.write_downgrade(). The RWWriteMode token must be used
@@ -493,6 +544,71 @@ impl<T:Freeze + Send> RWArc<T> {
         }
     }
 
+    /**
+     * As read(), but with the ability to atomically 'upgrade' the lock.
+     * See sync::rwlock.read_upgradeable(). The RWUpgradeMode token must be
+     * used to obtain the &T, and can be transformed into a RWWriteMode token
+     * by calling upgrade(), after which a &mut T can be obtained instead.
+     *
+     * # Example
+     *
+     * ```rust
+     * do arc.read_upgradeable |upgrade_token| {
+     *     let dirty = do upgrade_token.read |state| {
+     *         ... shared access with immutable state ...
+     *     };
+     *     if dirty {
+     *         let mut write_token = arc.upgrade(upgrade_token);
+     *         do write_token.write |state| {
+     *             ... exclusive access with mutable state ...
+     *         }
+     *     }
+     * }
+     * ```
+     *
+     * # Failure
+     *
+     * Failing before upgrading will not poison the Arc, as with read();
+     * failing after upgrading will, as with write().
+     */
+    pub fn read_upgradeable<U>(&self, blk: &fn(v: RWUpgradeMode<T>) -> U) -> U {
+        unsafe {
+            let state = self.x.get();
+            do (*borrow_rwlock(state)).read_upgradeable |upgrade_mode| {
+                check_poison(false, (*state).failed);
+                blk(RWUpgradeMode {
+                    data: &mut (*state).data,
+                    failed: &mut (*state).failed,
+                    token: upgrade_mode,
+                })
+            }
+        }
+    }
+
+    /// To be called inside of the read_upgradeable block.
+    pub fn upgrade<'a>(&self, token: RWUpgradeMode<'a, T>)
+                       -> RWWriteMode<'a, T> {
+        unsafe {
+            // The rwlock should assert that the token belongs to us for us.
+            let state = self.x.get();
+            let RWUpgradeMode {
+                data: data,
+                failed: failed,
+                token: t
+            } = token;
+            // Wait for the other readers to leave
+            let new_token = (*state).lock.upgrade(t);
+            // Upgrade ensured the token belonged to us. Just a sanity check.
+            assert!(borrow::ref_eq(&(*state).data, &*data));
+            // Produce new token
+            RWWriteMode {
+                data: data,
+                token: new_token,
+                poison: PoisonOnFail(failed)
+            }
+        }
+    }
+
     /**
      * Retrieves the data, blocking until all other references are dropped,
      * exactly as arc::unwrap.
@@ -532,6 +648,13 @@ pub struct RWReadMode<'self, T> {
     token: sync::RWLockReadMode<'self>,
 }
 
+/// The "upgradeable read permission" token used for RWArc.read_upgradeable().
+pub struct RWUpgradeMode<'self, T> {
+    data: &'self mut T,
+    failed: &'self mut bool,
+    token: sync::RWLockUpgradeMode<'self>,
+}
+
 impl<'self, T:Freeze + Send> RWWriteMode<'self, T> {
     /// Access the pre-downgrade RWArc in write mode.
     pub fn write<U>(&mut self, blk: &fn(x: &mut T) -> U) -> U {
@@ -587,6 +710,21 @@ impl<'self, T:Freeze + Send> RWReadMode<'self, T> {
     }
 }
 
+impl<'self, T:Freeze + Send> RWUpgradeMode<'self, T> {
+    /// Access the pre-upgrade RWArc in read mode.
+    pub fn read<U>(&self, blk: &fn(x: &T) -> U) -> U {
+        match *self {
+            RWUpgradeMode {
+                data: &ref data,
+                failed: _,
+                token: ref token
+            } => {
+                do token.read { blk(data) }
+            }
+        }
+    }
+}
+
 /****************************************************************************
  * Tests
  ****************************************************************************/
@@ -800,6 +938,121 @@ mod tests {
             assert_eq!(*one, 1);
         }
     }
+    #[test] #[should_fail]
+    fn test_rw_arc_poison_uw() {
+        let arc = RWArc::new(1);
+        let arc2 = arc.clone();
+        do task::try {
+            do arc2.read_upgradeable |upgrade_mode| {
+                let mut write_mode = arc2.upgrade(upgrade_mode);
+                do write_mode.write |one| {
+                    assert_eq!(*one, 2);
+                }
+            }
+        };
+        do arc.write |one| {
+            assert_eq!(*one, 1);
+        }
+    }
+    #[test]
+    fn test_rw_arc_no_poison_ur() {
+        let arc = RWArc::new(1);
+        let arc2 = arc.clone();
+        do task::try {
+            do arc2.read_upgradeable |upgrade_mode| {
+                do upgrade_mode.read |one| {
+                    assert_eq!(*one, 2);
+                }
+            }
+        };
+        do arc.write |one| {
+            assert_eq!(*one, 1);
+        }
+    }
+    #[test]
+    fn test_mutex_arc_try_access() {
+        let arc = MutexArc::new(1);
+        let arc2 = arc.clone();
+        let (p, c) = comm::stream();
+        let (p2, c2) = comm::stream();
+        do task::spawn {
+            do arc2.access |one| {
+                c.send(());
+                let _ = p2.recv();
+                *one = 2;
+            }
+            c.send(());
+        }
+        let _ = p.recv();
+        assert!(arc.try_access(|_| ()).is_none());
+        c2.send(());
+        let _ = p.recv();
+        assert_eq!(arc.try_access(|num| *num), Some(2));
+    }
+    #[test]
+    fn test_rw_arc_try_lock() {
+        let arc = RWArc::new(1);
+        let arc2 = arc.clone();
+        let (p, c) = comm::stream();
+        let (p2, c2) = comm::stream();
+        do task::spawn {
+            do arc2.read |_| {
+                c.send(());
+                let _ = p2.recv();
+            }
+            c.send(());
+        }
+        let _ = p.recv();
+        // Readers share, writers are turned away.
+        assert_eq!(arc.try_read(|num| *num), Some(1));
+        assert!(arc.try_write(|_| ()).is_none());
+        c2.send(());
+        let _ = p.recv();
+        assert_eq!(arc.try_write(|num| { *num += 1; *num }), Some(2));
+        do arc.write |_| {
+            assert!(arc.try_read(|_| ()).is_none());
+        }
+    }
+    #[test] #[should_fail]
+    fn test_rw_arc_poison_try_write() {
+        let arc = RWArc::new(1);
+        let arc2 = arc.clone();
+        do task::try {
+            do arc2.write |one| {
+                assert_eq!(*one, 2);
+            }
+        };
+        arc.try_write(|_| ());
+    }
+    #[test]
+    fn test_rw_upgrade() {
+        // Many tasks each check whether the value is still even, and if so
+        // upgrade and bump it. Only the first one should ever get to write;
+        // if the check and the write were not atomic, several would.
+        let arc = RWArc::new(0);
+        let mut children = ~[];
+        do 10.times {
+            let arc2 = arc.clone();
+            let mut builder = task::task();
+            builder.future_result(|r| children.push(r));
+            do builder.spawn {
+                do arc2.read_upgradeable |upgrade_mode| {
+                    let even = do upgrade_mode.read |num| { *num % 2 == 0 };
+                    task::deschedule();
+                    if even {
+                        let mut write_mode = arc2.upgrade(upgrade_mode);
+                        do write_mode.write |num| { *num += 1; }
+                    }
+                }
+            }
+        }
+        for r in children.iter() {
+            r.recv();
+        }
+        do arc.read |num| {
+            assert_eq!(*num, 1);
+        }
+    }
     #[test]
     fn test_rw_arc() {
         let arc = RWArc::new(0);
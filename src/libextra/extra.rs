@@ -108,6 +108,7 @@ pub mod stats;
 pub mod semver;
 pub mod fileinput;
 pub mod flate;
+pub mod tar;
 pub mod hex;
 pub mod uuid;
 
@@ -16,12 +16,16 @@ Simple compression
 
 #[allow(missing_doc)];
 
-use std::libc::{c_void, size_t, c_int};
+use std::io::{Reader, ReaderUtil, SeekStyle, Writer, WriterType};
+use std::io;
+use std::libc::{c_void, size_t, c_int, c_ulong};
 use std::libc;
+use std::num;
+use std::task;
 use std::vec;
 
 pub mod rustrt {
-    use std::libc::{c_int, c_void, size_t};
+    use std::libc::{c_int, c_ulong, c_void, size_t};
 
     #[link_name = "rustrt"]
     extern {
@@ -36,6 +40,29 @@ pub mod rustrt {
                                             pout_len: *mut size_t,
                                             flags: c_int)
                                             -> *c_void;
+
+        pub fn rust_tdefl_new(flags: c_int) -> *c_void;
+        pub fn rust_tdefl_free(d: *c_void);
+        pub fn tdefl_compress(d: *c_void,
+                              pin_buf: *c_void,
+                              pin_buf_size: *mut size_t,
+                              pout_buf: *mut c_void,
+                              pout_buf_size: *mut size_t,
+                              flush: c_int)
+                              -> c_int;
+
+        pub fn rust_tinfl_new() -> *c_void;
+        pub fn rust_tinfl_free(r: *c_void);
+        pub fn tinfl_decompress(r: *c_void,
+                                pin_buf_next: *u8,
+                                pin_buf_size: *mut size_t,
+                                pout_buf_start: *mut u8,
+                                pout_buf_next: *mut u8,
+                                pout_buf_size: *mut size_t,
+                                decomp_flags: u32)
+                                -> c_int;
+
+        pub fn mz_crc32(crc: c_ulong, ptr: *u8, buf_len: size_t) -> c_ulong;
     }
 }
 
@@ -45,6 +72,26 @@ static LZ_NORM : c_int = 0x80;  // LZ with 128 probes, "normal"
 static LZ_BEST : c_int = 0xfff; // LZ with 4095 probes, "best"
 static TINFL_FLAG_PARSE_ZLIB_HEADER : c_int = 0x1; // parse zlib header and adler32 checksum
 static TDEFL_WRITE_ZLIB_HEADER : c_int = 0x01000; // write zlib header and adler32 checksum
+static TINFL_FLAG_HAS_MORE_INPUT : c_int = 0x2; // more input follows the current buffer
+
+static TDEFL_NO_FLUSH : c_int = 0;
+static TDEFL_SYNC_FLUSH : c_int = 2;
+static TDEFL_FINISH : c_int = 4;
+static TDEFL_STATUS_DONE : c_int = 1;
+
+static TINFL_STATUS_DONE : c_int = 0;
+static TINFL_STATUS_NEEDS_MORE_INPUT : c_int = 1;
+static TINFL_STATUS_HAS_MORE_OUTPUT : c_int = 2;
+
+// The decompressor needs a window of at least this many bytes, and it has
+// to be a power of two.
+static TINFL_LZ_DICT_SIZE : uint = 32768;
+static BUF_SIZE : uint = 32768;
+
+static GZIP_FHCRC : u8 = 0x02;
+static GZIP_FEXTRA : u8 = 0x04;
+static GZIP_FNAME : u8 = 0x08;
+static GZIP_FCOMMENT : u8 = 0x10;
 
 fn deflate_bytes_internal(bytes: &[u8], flags: c_int) -> ~[u8] {
     #[fixed_stack_segment]; #[inline(never)];
@@ -102,11 +149,461 @@ pub fn inflate_bytes_zlib(bytes: &[u8]) -> ~[u8] {
     inflate_bytes_internal(bytes, TINFL_FLAG_PARSE_ZLIB_HEADER)
 }
 
+/// The framing around a deflate stream
+#[deriving(Clone, Eq)]
+pub enum Format {
+    /// A bare deflate stream, as produced by `deflate_bytes`
+    Raw,
+    /// A zlib stream, as produced by `deflate_bytes_zlib`
+    Zlib,
+    /// A gzip file. When reading, members that follow one another are
+    /// decompressed in turn, as gzip itself does.
+    Gzip,
+}
+
+fn crc32(crc: u32, bytes: &[u8]) -> u32 {
+    #[fixed_stack_segment]; #[inline(never)];
+
+    do bytes.as_imm_buf |b, len| {
+        unsafe {
+            rustrt::mz_crc32(crc as c_ulong, b, len as size_t) as u32
+        }
+    }
+}
+
+struct Deflater {
+    compressor: *c_void,
+    inner: @Writer,
+    format: Format,
+    crc: u32,
+    total: u64,
+    finished: bool,
+}
+
+impl Deflater {
+    fn compress(&mut self, input: &[u8], flush: c_int) {
+        #[fixed_stack_segment]; #[inline(never)];
+
+        let mut buf = vec::from_elem(BUF_SIZE, 0u8);
+        let mut pos = 0u;
+        loop {
+            let mut in_size = (input.len() - pos) as size_t;
+            let mut out_size = BUF_SIZE as size_t;
+            let status = unsafe {
+                rustrt::tdefl_compress(self.compressor,
+                                       vec::raw::to_ptr(input).offset(pos as int)
+                                           as *c_void,
+                                       &mut in_size,
+                                       vec::raw::to_mut_ptr(buf) as *mut c_void,
+                                       &mut out_size,
+                                       flush)
+            };
+            assert!(status >= 0);
+            pos += in_size as uint;
+            self.inner.write(buf.slice_to(out_size as uint));
+
+            let drained = (out_size as uint) < BUF_SIZE;
+            if status == TDEFL_STATUS_DONE ||
+               (flush != TDEFL_FINISH && pos == input.len() && drained) {
+                break;
+            }
+        }
+    }
+
+    fn write(&mut self, input: &[u8]) {
+        assert!(!self.finished);
+        if self.format == Gzip {
+            self.crc = crc32(self.crc, input);
+        }
+        self.total += input.len() as u64;
+        self.compress(input, TDEFL_NO_FLUSH);
+    }
+
+    fn finish(&mut self) {
+        if self.finished {
+            return;
+        }
+        self.finished = true;
+        self.compress([], TDEFL_FINISH);
+        if self.format == Gzip {
+            let inner = self.inner;
+            do io::u64_to_le_bytes(self.crc as u64, 4u) |v| { inner.write(v) }
+            do io::u64_to_le_bytes(self.total, 4u) |v| { inner.write(v) }
+        }
+        self.inner.flush();
+    }
+}
+
+#[unsafe_destructor]
+impl Drop for Deflater {
+    fn drop(&mut self) {
+        #[fixed_stack_segment]; #[inline(never)];
+
+        // Finishing writes to the inner writer, which may be what failed;
+        // a stream cut short by failure is left unfinished
+        if !task::failing() {
+            self.finish();
+        }
+        unsafe { rustrt::rust_tdefl_free(self.compressor); }
+    }
+}
+
+/// A writer that compresses everything written to it before passing it on.
+/// The stream is finished when `finish` is called or the writer is dropped,
+/// unless it is dropped because its task is failing.
+pub struct DeflateWriter {
+    priv state: @mut Deflater,
+}
+
+impl DeflateWriter {
+    pub fn new(inner: @Writer, format: Format) -> DeflateWriter {
+        #[fixed_stack_segment]; #[inline(never)];
+
+        let flags = match format {
+            Zlib => LZ_NORM | TDEFL_WRITE_ZLIB_HEADER,
+            Raw | Gzip => LZ_NORM
+        };
+        let compressor = unsafe { rustrt::rust_tdefl_new(flags) };
+        assert!(!compressor.is_null());
+        if format == Gzip {
+            // Deflate, no file name or modification time, unknown OS
+            inner.write([0x1f, 0x8b, 8, 0, 0, 0, 0, 0, 0, 0xff]);
+        }
+        DeflateWriter {
+            state: @mut Deflater {
+                compressor: compressor,
+                inner: inner,
+                format: format,
+                crc: 0,
+                total: 0,
+                finished: false
+            }
+        }
+    }
+
+    /// Writes out the rest of the compressed stream, including any trailer.
+    /// Nothing may be written afterwards.
+    pub fn finish(&self) {
+        self.state.finish()
+    }
+}
+
+impl Writer for DeflateWriter {
+    fn write(&self, v: &[u8]) {
+        self.state.write(v)
+    }
+
+    fn seek(&self, _: int, _: SeekStyle) {
+        fail2!("can't seek in a compressed stream")
+    }
+
+    /// Returns the number of uncompressed bytes written so far
+    fn tell(&self) -> uint {
+        self.state.total as uint
+    }
+
+    /// Flushes everything written so far through to the inner writer. This
+    /// costs a few bytes of output each time.
+    fn flush(&self) -> int {
+        if !self.state.finished {
+            self.state.compress([], TDEFL_SYNC_FLUSH);
+        }
+        self.state.inner.flush()
+    }
+
+    fn get_type(&self) -> WriterType {
+        io::File
+    }
+}
+
+struct Inflater {
+    decompressor: *c_void,
+    inner: @Reader,
+    format: Format,
+    // Compressed bytes read from `inner` but not yet decompressed
+    input: ~[u8],
+    in_pos: uint,
+    in_eof: bool,
+    // The window the decompressor writes into. The bytes between `out_pos`
+    // and `out_end` haven't been handed out yet.
+    dict: ~[u8],
+    dict_pos: uint,
+    out_pos: uint,
+    out_end: uint,
+    need_header: bool,
+    crc: u32,
+    member_size: u32,
+    total: u64,
+    finished: bool,
+}
+
+impl Inflater {
+    /// Makes sure there is unread input, returning false at the end of the
+    /// inner reader
+    fn fill(&mut self) -> bool {
+        if self.in_pos < self.input.len() {
+            return true;
+        }
+        if self.in_eof {
+            return false;
+        }
+        self.input = self.inner.read_bytes(BUF_SIZE);
+        self.in_pos = 0;
+        self.in_eof = self.input.is_empty();
+        !self.in_eof
+    }
+
+    fn next_byte(&mut self) -> u8 {
+        if !self.fill() {
+            fail2!("unexpected end of compressed stream");
+        }
+        self.in_pos += 1;
+        self.input[self.in_pos - 1]
+    }
+
+    fn next_le_u32(&mut self) -> u32 {
+        let mut n = 0u32;
+        for i in range(0u32, 4) {
+            n |= (self.next_byte() as u32) << (8 * i);
+        }
+        n
+    }
+
+    fn read_gzip_header(&mut self) {
+        if self.next_byte() != 0x1f || self.next_byte() != 0x8b {
+            fail2!("not a gzip stream");
+        }
+        if self.next_byte() != 8 {
+            fail2!("unsupported gzip compression method");
+        }
+        let flags = self.next_byte();
+        // Modification time, extra flags and OS
+        for _ in range(0, 6) {
+            self.next_byte();
+        }
+        if flags & GZIP_FEXTRA != 0 {
+            let len = (self.next_byte() as uint) | ((self.next_byte() as uint) << 8);
+            for _ in range(0, len) {
+                self.next_byte();
+            }
+        }
+        if flags & GZIP_FNAME != 0 {
+            while self.next_byte() != 0 {}
+        }
+        if flags & GZIP_FCOMMENT != 0 {
+            while self.next_byte() != 0 {}
+        }
+        if flags & GZIP_FHCRC != 0 {
+            self.next_byte();
+            self.next_byte();
+        }
+    }
+
+    /// Starts over with a fresh decompressor for the next gzip member
+    fn reset(&mut self) {
+        #[fixed_stack_segment]; #[inline(never)];
+
+        unsafe {
+            rustrt::rust_tinfl_free(self.decompressor);
+            self.decompressor = rustrt::rust_tinfl_new();
+        }
+        assert!(!self.decompressor.is_null());
+        self.dict_pos = 0;
+        self.crc = 0;
+        self.member_size = 0;
+        self.need_header = true;
+    }
+
+    fn end_member(&mut self) {
+        if self.format != Gzip {
+            self.finished = true;
+            return;
+        }
+        if self.next_le_u32() != self.crc || self.next_le_u32() != self.member_size {
+            fail2!("gzip checksum mismatch");
+        }
+        if self.fill() {
+            self.reset();
+        } else {
+            self.finished = true;
+        }
+    }
+
+    /// Decompresses some more input into the window. Must only be called
+    /// once all previous output has been handed out.
+    fn decompress(&mut self) {
+        #[fixed_stack_segment]; #[inline(never)];
+
+        if self.need_header {
+            self.read_gzip_header();
+            self.need_header = false;
+        }
+        self.fill();
+
+        let mut flags = if self.format == Zlib { TINFL_FLAG_PARSE_ZLIB_HEADER } else { 0 };
+        if !self.in_eof {
+            flags |= TINFL_FLAG_HAS_MORE_INPUT;
+        }
+        let mut in_size = (self.input.len() - self.in_pos) as size_t;
+        let mut out_size = (TINFL_LZ_DICT_SIZE - self.dict_pos) as size_t;
+        let status = unsafe {
+            let dict = vec::raw::to_mut_ptr(self.dict);
+            rustrt::tinfl_decompress(self.decompressor,
+                                     vec::raw::to_ptr(self.input).offset(self.in_pos as int),
+                                     &mut in_size,
+                                     dict,
+                                     dict.offset(self.dict_pos as int),
+                                     &mut out_size,
+                                     flags as u32)
+        };
+        self.in_pos += in_size as uint;
+
+        let start = self.dict_pos;
+        let produced = out_size as uint;
+        if self.format == Gzip {
+            self.crc = crc32(self.crc, self.dict.slice(start, start + produced));
+            self.member_size += produced as u32;
+        }
+        self.out_pos = start;
+        self.out_end = start + produced;
+        self.dict_pos = (start + produced) & (TINFL_LZ_DICT_SIZE - 1);
+
+        match status {
+            TINFL_STATUS_DONE => self.end_member(),
+            TINFL_STATUS_NEEDS_MORE_INPUT if self.in_eof => {
+                fail2!("unexpected end of compressed stream")
+            }
+            TINFL_STATUS_NEEDS_MORE_INPUT | TINFL_STATUS_HAS_MORE_OUTPUT => {}
+            _ => fail2!("corrupt compressed stream")
+        }
+    }
+}
+
+#[unsafe_destructor]
+impl Drop for Inflater {
+    fn drop(&mut self) {
+        #[fixed_stack_segment]; #[inline(never)];
+
+        unsafe { rustrt::rust_tinfl_free(self.decompressor); }
+    }
+}
+
+/// A reader that decompresses a stream read from another reader, a block
+/// at a time. It fails if the stream is corrupt or truncated. Any bytes
+/// following the end of the compressed stream may be consumed from the
+/// inner reader.
+pub struct InflateReader {
+    priv state: @mut Inflater,
+}
+
+impl InflateReader {
+    pub fn new(inner: @Reader, format: Format) -> InflateReader {
+        #[fixed_stack_segment]; #[inline(never)];
+
+        let decompressor = unsafe { rustrt::rust_tinfl_new() };
+        assert!(!decompressor.is_null());
+        InflateReader {
+            state: @mut Inflater {
+                decompressor: decompressor,
+                inner: inner,
+                format: format,
+                input: ~[],
+                in_pos: 0,
+                in_eof: false,
+                dict: vec::from_elem(TINFL_LZ_DICT_SIZE, 0u8),
+                dict_pos: 0,
+                out_pos: 0,
+                out_end: 0,
+                need_header: format == Gzip,
+                crc: 0,
+                member_size: 0,
+                total: 0,
+                finished: false
+            }
+        }
+    }
+}
+
+impl Reader for InflateReader {
+    fn read(&self, bytes: &mut [u8], len: uint) -> uint {
+        let st = self.state;
+        let mut count = 0;
+        while count < len {
+            if st.out_pos < st.out_end {
+                let n = num::min(len - count, st.out_end - st.out_pos);
+                vec::bytes::copy_memory(bytes.mut_slice(count, count + n),
+                                        st.dict.slice(st.out_pos, st.out_pos + n),
+                                        n);
+                st.out_pos += n;
+                count += n;
+            } else if st.finished {
+                break;
+            } else {
+                st.decompress();
+            }
+        }
+        st.total += count as u64;
+        count
+    }
+
+    fn read_byte(&self) -> int {
+        let mut buf = [0u8];
+        if self.read(buf, 1) == 0 { -1 } else { buf[0] as int }
+    }
+
+    fn eof(&self) -> bool {
+        self.state.finished && self.state.out_pos == self.state.out_end
+    }
+
+    fn seek(&self, _: int, _: SeekStyle) {
+        fail2!("can't seek in a compressed stream")
+    }
+
+    /// Returns the number of decompressed bytes read so far
+    fn tell(&self) -> uint {
+        self.state.total as uint
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use std::io::{Reader, ReaderUtil, SeekStyle, Writer, WriterType};
+    use std::io;
     use std::rand;
     use std::rand::Rng;
+    use std::vec;
+
+    fn random_words() -> ~[u8] {
+        let mut r = rand::rng();
+        let mut words = ~[];
+        do 20.times {
+            let range = r.gen_integer_range(1u, 10);
+            words.push(r.gen_vec::<u8>(range));
+        }
+        let mut input = ~[];
+        do 20000.times {
+            input.push_all(r.choose(words));
+        }
+        input
+    }
+
+    fn compress(input: &[u8], format: Format) -> ~[u8] {
+        do io::with_bytes_writer |wr| {
+            let w = DeflateWriter::new(wr, format);
+            // Odd sized writes, so blocks don't line up with the buffers
+            for chunk in input.chunk_iter(777) {
+                w.write(chunk);
+            }
+            w.finish();
+        }
+    }
+
+    fn decompress(input: &[u8], format: Format) -> ~[u8] {
+        do io::with_bytes_reader(input) |rdr| {
+            InflateReader::new(rdr, format).read_whole_stream()
+        }
+    }
 
     #[test]
     fn test_flate_round_trip() {
@@ -139,4 +636,109 @@ mod tests {
         let inflated = inflate_bytes(deflated);
         assert_eq!(inflated, bytes);
     }
+
+    #[test]
+    fn test_stream_round_trip() {
+        let input = random_words();
+        for &format in [Raw, Zlib, Gzip].iter() {
+            let compressed = compress(input, format);
+            assert!(compressed.len() < input.len());
+            assert_eq!(decompress(compressed, format), input.clone());
+        }
+    }
+
+    #[test]
+    fn test_stream_matches_buffers() {
+        let input = random_words();
+        assert_eq!(inflate_bytes(compress(input, Raw)), input.clone());
+        assert_eq!(inflate_bytes_zlib(compress(input, Zlib)), input.clone());
+        assert_eq!(decompress(deflate_bytes(input), Raw), input.clone());
+        assert_eq!(decompress(deflate_bytes_zlib(input), Zlib), input.clone());
+
+        // A gzip member is a raw stream between a header and a trailer
+        let gz = compress(input, Gzip);
+        assert_eq!(gz.slice_to(3), &[0x1f, 0x8b, 8]);
+        assert_eq!(inflate_bytes(gz.slice(10, gz.len() - 8)), input.clone());
+    }
+
+    #[test]
+    fn test_empty_stream() {
+        for &format in [Raw, Zlib, Gzip].iter() {
+            assert_eq!(decompress(compress([], format), format), ~[]);
+        }
+    }
+
+    #[test]
+    fn test_gzip_members() {
+        let mut gz = compress(bytes!("hello, "), Gzip);
+        gz.push_all(compress(bytes!("world"), Gzip));
+        assert_eq!(decompress(gz, Gzip), bytes!("hello, world").to_owned());
+    }
+
+    #[test]
+    fn test_gzip_header_fields() {
+        let mut gz = compress(bytes!("hello"), Gzip);
+        // Add a file name and a comment
+        gz[3] = 0x18;
+        let rest = gz.slice_from(10).to_owned();
+        gz.truncate(10);
+        gz.push_all(bytes!("hello.txt\x00a comment\x00"));
+        gz.push_all(rest);
+        assert_eq!(decompress(gz, Gzip), bytes!("hello").to_owned());
+    }
+
+    #[test]
+    #[should_fail]
+    fn test_gzip_bad_checksum() {
+        let mut gz = compress(bytes!("hello"), Gzip);
+        let len = gz.len();
+        gz[len - 8] ^= 1;
+        decompress(gz, Gzip);
+    }
+
+    #[test]
+    #[should_fail]
+    fn test_truncated_stream() {
+        let gz = compress(random_words(), Gzip);
+        decompress(gz.slice_to(gz.len() / 2), Gzip);
+    }
+
+    // Fails on every write, as a writer to a full disk might
+    struct FailingWriter;
+
+    impl Writer for FailingWriter {
+        fn write(&self, _: &[u8]) { fail2!("write failed") }
+        fn seek(&self, _: int, _: SeekStyle) {}
+        fn tell(&self) -> uint { 0 }
+        fn flush(&self) -> int { 0 }
+        fn get_type(&self) -> WriterType { io::File }
+    }
+
+    #[test]
+    #[should_fail]
+    fn test_drop_while_failing() {
+        let _w = DeflateWriter::new(@FailingWriter as @Writer, Raw);
+        // Finishing the stream on the way out would fail again, and abort
+        // the whole process rather than just fail this test
+        fail2!("something else failed");
+    }
+
+    #[test]
+    fn test_small_reads() {
+        let input = random_words();
+        let compressed = compress(input, Zlib);
+        do io::with_bytes_reader(compressed) |rdr| {
+            let r = InflateReader::new(rdr, Zlib);
+            let mut out = ~[];
+            let mut buf = vec::from_elem(100, 0u8);
+            loop {
+                let n = r.read(buf, 100);
+                if n == 0 { break }
+                out.push_all(buf.slice_to(n));
+            }
+            assert!(r.eof());
+            assert_eq!(r.tell(), input.len());
+            assert_eq!(out, input.clone());
+        }
+    }
 }
@@ -23,6 +23,12 @@
 //! argument following either a space or an equals sign. Single-character
 //! options don't require the space.
 //!
+//! Options created with `optmulti` or `optflagmulti` may be given any number
+//! of times, and every occurrence is kept, in order, whichever of an option's
+//! names it used. Use `opt_strs`, `opt_paths` or `opt_ints` to get all of
+//! their arguments, and `opt_count` to count flags. The single-valued
+//! accessors like `opt_str` only return the first argument.
+//!
 //! # Example
 //!
 //! The following example shows simple command line parsing for an application
@@ -78,6 +84,7 @@
 //! ~~~
 
 use std::cmp::Eq;
+use std::from_str::from_str;
 use std::result::{Err, Ok};
 use std::result;
 use std::option::{Some, None};
@@ -151,6 +158,8 @@ pub enum Fail_ {
     OptionMissing(~str),
     OptionDuplicated(~str),
     UnexpectedArgument(~str),
+    UnrecognizedCommand(~str),
+    ArgumentInvalid(~str, ~str),
 }
 
 /// The type of failure that occured.
@@ -162,6 +171,8 @@ pub enum FailType {
     OptionMissing_,
     OptionDuplicated_,
     UnexpectedArgument_,
+    UnrecognizedCommand_,
+    ArgumentInvalid_,
 }
 
 /// The result of parsing a command line with a set of options.
@@ -205,6 +216,12 @@ impl Matches {
         }
     }
 
+    /// Returns true if the options that were parsed include one named `nm`,
+    /// whether or not it was matched.
+    pub fn opt_defined(&self, nm: &str) -> bool {
+        find_opt(self.opts, Name::from_str(nm)).is_some()
+    }
+
     /// Returns true if an option was matched.
     pub fn opt_present(&self, nm: &str) -> bool {
         !self.opt_vals(nm).is_empty()
@@ -266,6 +283,67 @@ impl Matches {
     }
 
 
+    /// Returns the argument supplied to a matching option as a path, or
+    /// `None`.
+    pub fn opt_path(&self, nm: &str) -> Option<Path> {
+        self.opt_str(nm).map(|s| Path(s))
+    }
+
+    /// Returns a vector of the arguments provided to all matches of the given
+    /// option, as paths.
+    pub fn opt_paths(&self, nm: &str) -> ~[Path] {
+        self.opt_strs(nm).map(|s| Path(*s))
+    }
+
+    /// Returns the integer argument supplied to a matching option or `None`.
+    ///
+    /// Returns `Err(ArgumentInvalid)` if the argument is not an integer.
+    pub fn opt_int(&self, nm: &str) -> result::Result<Option<int>, Fail_> {
+        match self.opt_str(nm) {
+            None => Ok(None),
+            Some(s) => parse_int(nm, s).map(|i| Some(i))
+        }
+    }
+
+    /// Returns a vector of the integer arguments provided to all matches of
+    /// the given option.
+    ///
+    /// Returns `Err(ArgumentInvalid)` for the first argument that is not an
+    /// integer.
+    pub fn opt_ints(&self, nm: &str) -> result::Result<~[int], Fail_> {
+        let mut acc = ~[];
+        for s in self.opt_strs(nm).iter() {
+            match parse_int(nm, *s) {
+                Ok(i) => acc.push(i),
+                Err(f) => return Err(f)
+            }
+        }
+        Ok(acc)
+    }
+
+    /// Returns the value paired with the argument supplied to a matching
+    /// option, or `None`.
+    ///
+    /// Returns `Err(ArgumentInvalid)`, listing the allowed arguments, if the
+    /// argument isn't one of those in `values`.
+    pub fn opt_enum<T: Clone>(&self, nm: &str,
+                              values: &[(&str, T)]) -> result::Result<Option<T>, Fail_> {
+        match self.opt_str(nm) {
+            None => Ok(None),
+            Some(s) => {
+                for &(name, ref value) in values.iter() {
+                    if name == s.as_slice() {
+                        return Ok(Some(value.clone()));
+                    }
+                }
+                let names = values.map(|&(name, _)| format!("'{}'", name));
+                Err(ArgumentInvalid(nm.to_owned(),
+                                    format!("expected one of {}, found '{}'",
+                                            names.connect(", "), s)))
+            }
+        }
+    }
+
     /// Returns the matching string, a default, or none.
     ///
     /// Returns none if the option was not present, `def` if the option was
@@ -282,6 +360,14 @@ impl Matches {
 
 }
 
+fn parse_int(nm: &str, s: &str) -> result::Result<int, Fail_> {
+    match from_str::<int>(s) {
+        Some(i) => Ok(i),
+        None => Err(ArgumentInvalid(nm.to_owned(),
+                                    format!("expected an integer, found '{}'", s)))
+    }
+}
+
 fn is_arg(arg: &str) -> bool {
     arg.len() > 1 && arg[0] == '-' as u8
 }
@@ -384,6 +470,12 @@ impl Fail_ {
             UnexpectedArgument(ref nm) => {
                 format!("Option '{}' does not take an argument.", *nm)
             }
+            UnrecognizedCommand(ref nm) => {
+                format!("Unrecognized command: '{}'.", *nm)
+            }
+            ArgumentInvalid(ref nm, ref why) => {
+                format!("Invalid argument to option '{}': {}.", *nm, *why)
+            }
         }
     }
 }
@@ -522,8 +614,10 @@ pub fn getopts(args: &[~str], opts: &[Opt]) -> Result {
 /// A module which provides a way to specify descriptions and
 /// groups of short and long option names, together.
 pub mod groups {
-    use getopts::{HasArg, Long, Maybe, Multi, No, Occur, Opt, Optional, Req};
-    use getopts::{Short, Yes};
+    use getopts::{Fail_, HasArg, Long, Matches, Maybe, Multi, No, Occur, Opt, Optional, Req};
+    use getopts::{Short, UnrecognizedCommand, Yes};
+    use std::result::{Err, Ok};
+    use std::result;
 
     /// One group of options, e.g., both -h and --help, along with
     /// their shared description and properties.
@@ -587,6 +681,57 @@ pub mod groups {
         }
     }
 
+    /// A subcommand, like the `build` in `rustpkg build`, along with the
+    /// options that only it accepts.
+    #[deriving(Clone, Eq)]
+    pub struct Command {
+        /// Name of the command
+        name: ~str,
+        /// Description
+        desc: ~str,
+        /// Options accepted in addition to the global ones
+        opts: ~[OptGroup]
+    }
+
+    /// The result of parsing a command line with subcommands.
+    #[deriving(Clone, Eq)]
+    pub struct CommandMatches {
+        /// The command that was given, if any
+        command: Option<~str>,
+        /// Global and command options that matched, and the free arguments
+        /// following the command
+        matches: Matches
+    }
+
+    /// The failure to parse a command line with subcommands.
+    #[deriving(Clone, Eq)]
+    pub struct CommandFail {
+        /// The command whose options were being parsed, or `None` if the
+        /// failure came before a command was recognized
+        command: Option<~str>,
+        /// What went wrong
+        fail: Fail_
+    }
+
+    impl CommandFail {
+        /// Convert a `CommandFail` into an error string.
+        pub fn to_err_msg(self) -> ~str {
+            self.fail.to_err_msg()
+        }
+    }
+
+    /// The result of parsing a command line with a set of subcommands.
+    pub type CommandResult = result::Result<CommandMatches, CommandFail>;
+
+    /// Create a subcommand that accepts the given options.
+    pub fn command(name: &str, desc: &str, opts: ~[OptGroup]) -> Command {
+        Command {
+            name: name.to_owned(),
+            desc: desc.to_owned(),
+            opts: opts
+        }
+    }
+
     /// Create a long option that is required and takes an argument.
     pub fn reqopt(short_name: &str, long_name: &str, desc: &str, hint: &str) -> OptGroup {
         let len = short_name.len();
@@ -678,11 +823,127 @@ pub mod groups {
         ::getopts::getopts(args, opts.map(|x| x.long_to_short()))
     }
 
+    /// Parse command line args that start with a subcommand, like
+    /// `rustpkg build --no-link foo`.
+    ///
+    /// Only global options may come before the command. The first free
+    /// argument names the command, and the arguments after it are parsed
+    /// against the global options together with that command's own. If there
+    /// is no free argument, the arguments are parsed as global options only
+    /// and no command is returned.
+    pub fn getopts_subcommand(args: &[~str], global: &[OptGroup],
+                              commands: &[Command]) -> CommandResult {
+        let global_opts = global.map(|x| x.long_to_short());
+        let idx = match find_command(args, global_opts) {
+            Some(idx) => idx,
+            None => {
+                return match ::getopts::getopts(args, global_opts) {
+                    Ok(m) => Ok(CommandMatches { command: None, matches: m }),
+                    Err(f) => Err(CommandFail { command: None, fail: f })
+                };
+            }
+        };
+
+        // Required and duplicated options are checked once the whole command
+        // line is parsed below; this only rejects options that aren't global.
+        let prefix_opts = do global_opts.map |opt| {
+            Opt { occur: Multi, .. opt.clone() }
+        };
+        match ::getopts::getopts(args.slice_to(idx), prefix_opts) {
+            Ok(_) => (),
+            Err(f) => return Err(CommandFail { command: None, fail: f })
+        }
+
+        let name = args[idx].clone();
+        let command = match commands.iter().find(|c| c.name == name) {
+            Some(command) => command,
+            None => {
+                return Err(CommandFail { command: None, fail: UnrecognizedCommand(name) });
+            }
+        };
+        let opts = global_opts + command.opts.map(|x| x.long_to_short());
+        let rest = args.slice_to(idx).to_owned() + args.slice_from(idx + 1);
+        match ::getopts::getopts(rest, opts) {
+            Ok(m) => Ok(CommandMatches { command: Some(name), matches: m }),
+            Err(f) => Err(CommandFail { command: Some(name), fail: f })
+        }
+    }
+
+    /// Returns the index of the first free argument, skipping over options
+    /// and the values that they take.
+    fn find_command(args: &[~str], opts: &[Opt]) -> Option<uint> {
+        let mut i = 0;
+        while i < args.len() {
+            let cur = args[i].as_slice();
+            if cur == "--" {
+                return if i + 1 < args.len() { Some(i + 1) } else { None };
+            }
+            if !::getopts::is_arg(cur) {
+                return Some(i);
+            }
+            let next_is_value = i + 1 < args.len() && !::getopts::is_arg(args[i + 1]);
+            i += match value_hasarg(cur, opts) {
+                Yes => 2,
+                Maybe if next_is_value => 2,
+                _ => 1
+            };
+        }
+        None
+    }
+
+    /// Whether the option argument `arg` may take its value from the next
+    /// argument. Values given with `=`, or attached to a short option, don't.
+    fn value_hasarg(arg: &str, opts: &[Opt]) -> HasArg {
+        if arg[1] == '-' as u8 {
+            let tail = arg.slice_from(2);
+            if tail.contains_char('=') {
+                return No;
+            }
+            return match ::getopts::find_opt(opts, Long(tail.to_owned())) {
+                Some(id) => opts[id].hasarg,
+                None => No
+            };
+        }
+        // As in `getopts`, the characters after a short option that takes an
+        // argument are its value unless they start another option.
+        let mut hasarg = No;
+        for ch in arg.slice_from(1).iter() {
+            match ::getopts::find_opt(opts, Short(ch)) {
+                Some(id) => hasarg = opts[id].hasarg,
+                None if hasarg != No => return No,
+                None => ()
+            }
+        }
+        hasarg
+    }
+
     /// Derive a usage message from a set of long options.
     pub fn usage(brief: &str, opts: &[OptGroup]) -> ~str {
+        format!("{}\n\nOptions:\n{}\n", brief, option_rows(opts))
+    }
 
-        let desc_sep = "\n" + " ".repeat(24);
+    /// Derive a usage message for a program with subcommands, listing the
+    /// commands and the global options.
+    pub fn commands_usage(brief: &str, global: &[OptGroup], commands: &[Command]) -> ~str {
+        let rows = do commands.map |command| {
+            format_row(" ".repeat(4) + command.name, command.desc)
+        };
+        format!("{}\n\nCommands:\n{}\n\nOptions:\n{}\n",
+                brief, rows.connect("\n"), option_rows(global))
+    }
 
+    /// Derive a usage message for one subcommand, listing its own options
+    /// followed by the global ones.
+    pub fn command_usage(brief: &str, global: &[OptGroup], command: &Command) -> ~str {
+        let mut usage = brief.to_owned();
+        if !command.opts.is_empty() {
+            usage.push_str(format!("\n\nOptions:\n{}", option_rows(command.opts)));
+        }
+        usage.push_str(format!("\n\nGlobal options:\n{}\n", option_rows(global)));
+        usage
+    }
+
+    fn option_rows(opts: &[OptGroup]) -> ~str {
         let mut rows = opts.iter().map(|optref| {
             let OptGroup{short_name: short_name,
                          long_name: long_name,
@@ -725,39 +986,48 @@ pub mod groups {
                 }
             }
 
-            // FIXME: #5516 should be graphemes not codepoints
-            // here we just need to indent the start of the description
-            let rowlen = row.char_len();
-            if rowlen < 24 {
-                do (24 - rowlen).times {
-                    row.push_char(' ')
-                }
-            } else {
-                row.push_str(desc_sep)
-            }
+            format_row(row, desc)
+        });
+
+        rows.collect::<~[~str]>().connect("\n")
+    }
 
-            // Normalize desc to contain words separated by one space character
-            let mut desc_normalized_whitespace = ~"";
-            for word in desc.word_iter() {
-                desc_normalized_whitespace.push_str(word);
-                desc_normalized_whitespace.push_char(' ');
+    /// Pads `row` out to the description column and appends the wrapped
+    /// description.
+    fn format_row(row: ~str, desc: &str) -> ~str {
+        let desc_sep = "\n" + " ".repeat(24);
+        let mut row = row;
+
+        // FIXME: #5516 should be graphemes not codepoints
+        // here we just need to indent the start of the description
+        let rowlen = row.char_len();
+        if rowlen < 24 {
+            do (24 - rowlen).times {
+                row.push_char(' ')
             }
+        } else {
+            row.push_str(desc_sep)
+        }
 
-            // FIXME: #5516 should be graphemes not codepoints
-            let mut desc_rows = ~[];
-            do each_split_within(desc_normalized_whitespace, 54) |substr| {
-                desc_rows.push(substr.to_owned());
-                true
-            };
+        // Normalize desc to contain words separated by one space character
+        let mut desc_normalized_whitespace = ~"";
+        for word in desc.word_iter() {
+            desc_normalized_whitespace.push_str(word);
+            desc_normalized_whitespace.push_char(' ');
+        }
 
-            // FIXME: #5516 should be graphemes not codepoints
-            // wrapped description
-            row.push_str(desc_rows.connect(desc_sep));
+        // FIXME: #5516 should be graphemes not codepoints
+        let mut desc_rows = ~[];
+        do each_split_within(desc_normalized_whitespace, 54) |substr| {
+            desc_rows.push(substr.to_owned());
+            true
+        };
 
-            row
-        });
+        // FIXME: #5516 should be graphemes not codepoints
+        // wrapped description
+        row.push_str(desc_rows.connect(desc_sep));
 
-        format!("{}\n\nOptions:\n{}\n", brief, rows.collect::<~[~str]>().connect("\n"))
+        row
     }
 
     /// Splits a string into substrings with possibly internal whitespace,
@@ -872,7 +1142,9 @@ mod tests {
           UnrecognizedOption(_) => assert!(ft == UnrecognizedOption_),
           OptionMissing(_) => assert!(ft == OptionMissing_),
           OptionDuplicated(_) => assert!(ft == OptionDuplicated_),
-          UnexpectedArgument(_) => assert!(ft == UnexpectedArgument_)
+          UnexpectedArgument(_) => assert!(ft == UnexpectedArgument_),
+          UnrecognizedCommand(_) => assert!(ft == UnrecognizedCommand_),
+          ArgumentInvalid(_, _) => assert!(ft == ArgumentInvalid_)
         }
     }
 
@@ -1431,6 +1703,74 @@ mod tests {
         assert_eq!(matches_both.opts_str([~"encrypt", ~"e"]).unwrap(), ~"foo");
     }
 
+    #[test]
+    fn test_opt_path() {
+        let opts = ~[optopt("o"), optopt("L")];
+        let m = getopts([~"-o", ~"foo/bar"], opts).unwrap();
+        assert_eq!(m.opt_path("o"), Some(Path("foo/bar")));
+        assert_eq!(m.opt_path("L"), None);
+    }
+
+    #[test]
+    fn test_opt_int() {
+        let opts = ~[optopt("j"), optopt("n")];
+        let m = getopts([~"-j", ~"-4"], opts).unwrap();
+        assert_eq!(m.opt_int("j"), Ok(Some(-4)));
+        assert_eq!(m.opt_int("n"), Ok(None));
+
+        let m = getopts([~"-j", ~"four"], opts).unwrap();
+        match m.opt_int("j") {
+          Err(f) => {
+            assert_eq!(f.clone().to_err_msg(),
+                       ~"Invalid argument to option 'j': expected an integer, found 'four'.");
+            check_fail_type(f, ArgumentInvalid_);
+          }
+          _ => fail2!()
+        }
+    }
+
+    #[test]
+    fn test_opt_paths() {
+        let opts = ~[groups::optmulti("L", "library-path", "Desc", "PATH").long_to_short()];
+        let m = getopts([~"-L", ~"a", ~"--library-path=b", ~"-Lc"], opts).unwrap();
+        assert_eq!(m.opt_paths("L"), ~[Path("a"), Path("b"), Path("c")]);
+        assert_eq!(m.opt_paths("library-path"), m.opt_paths("L"));
+    }
+
+    #[test]
+    fn test_opt_ints() {
+        let opts = ~[optmulti("n"), optmulti("m")];
+        let m = getopts([~"-n", ~"1", ~"-n2"], opts).unwrap();
+        assert_eq!(m.opt_ints("n"), Ok(~[1, 2]));
+        assert_eq!(m.opt_ints("m"), Ok(~[]));
+
+        let m = getopts([~"-n", ~"1", ~"-n", ~"two"], opts).unwrap();
+        match m.opt_ints("n") {
+          Err(f) => check_fail_type(f, ArgumentInvalid_),
+          _ => fail2!()
+        }
+    }
+
+    #[test]
+    fn test_opt_enum() {
+        let opts = ~[optopt("color"), optopt("size")];
+        let values = [("auto", 0), ("always", 1), ("never", 2)];
+        let m = getopts([~"--color=never"], opts).unwrap();
+        assert_eq!(m.opt_enum("color", values), Ok(Some(2)));
+        assert_eq!(m.opt_enum("size", values), Ok(None));
+
+        let m = getopts([~"--color", ~"sometimes"], opts).unwrap();
+        match m.opt_enum("color", values) {
+          Err(f) => {
+            assert_eq!(f.clone().to_err_msg(),
+                       ~"Invalid argument to option 'color': expected one of 'auto', \
+                         'always', 'never', found 'sometimes'.");
+            check_fail_type(f, ArgumentInvalid_);
+          }
+          _ => fail2!()
+        }
+    }
+
     #[test]
     fn test_nospace() {
         let args = ~[~"-Lfoo", ~"-M."];
@@ -1639,4 +1979,162 @@ Options:
         debug2!("generated: <<{}>>", usage);
         assert!(usage == expected)
     }
+
+    fn subcommands() -> (~[OptGroup], ~[groups::Command]) {
+        let global = ~[
+            groups::optflag("v", "verbose", "Desc"),
+            groups::optopt("", "sysroot", "Desc", "PATH"),
+        ];
+        let commands = ~[
+            groups::command("build", "Build a crate", ~[
+                groups::optflag("", "no-link", "Desc"),
+                groups::optmulti("c", "cfg", "Desc", "SPEC"),
+            ]),
+            groups::command("list", "List crates", ~[]),
+        ];
+        (global, commands)
+    }
+
+    #[test]
+    fn test_groups_subcommand() {
+        let (global, commands) = subcommands();
+        let args = ~[~"-v", ~"build", ~"--no-link", ~"-cfoo", ~"bar"];
+        let m = groups::getopts_subcommand(args, global, commands).unwrap();
+        assert_eq!(m.command, Some(~"build"));
+        assert!(m.matches.opt_present("verbose"));
+        assert!(m.matches.opt_present("no-link"));
+        assert_eq!(m.matches.opt_strs("cfg"), ~[~"foo"]);
+        assert_eq!(m.matches.free, ~[~"bar"]);
+    }
+
+    #[test]
+    fn test_groups_subcommand_global_after_command() {
+        let (global, commands) = subcommands();
+        let args = ~[~"list", ~"--sysroot", ~"/tmp"];
+        let m = groups::getopts_subcommand(args, global, commands).unwrap();
+        assert_eq!(m.command, Some(~"list"));
+        assert_eq!(m.matches.opt_str("sysroot"), Some(~"/tmp"));
+        assert!(!m.matches.opt_defined("no-link"));
+        assert!(m.matches.free.is_empty());
+    }
+
+    #[test]
+    fn test_groups_subcommand_option_values() {
+        // The value of a global option isn't mistaken for the command
+        let (global, commands) = subcommands();
+        let args = ~[~"--sysroot", ~"list", ~"build"];
+        let m = groups::getopts_subcommand(args, global, commands).unwrap();
+        assert_eq!(m.command, Some(~"build"));
+        assert_eq!(m.matches.opt_str("sysroot"), Some(~"list"));
+    }
+
+    #[test]
+    fn test_groups_subcommand_missing() {
+        let (global, commands) = subcommands();
+        let m = groups::getopts_subcommand([~"-v"], global, commands).unwrap();
+        assert_eq!(m.command, None);
+        assert!(m.matches.opt_present("v"));
+    }
+
+    #[test]
+    fn test_groups_subcommand_unrecognized() {
+        let (global, commands) = subcommands();
+        match groups::getopts_subcommand([~"frob", ~"-v"], global, commands) {
+          Err(f) => {
+            assert_eq!(f.command, None);
+            check_fail_type(f.fail, UnrecognizedCommand_);
+          }
+          _ => fail2!()
+        }
+    }
+
+    #[test]
+    fn test_groups_subcommand_option_before_command() {
+        let (global, commands) = subcommands();
+        match groups::getopts_subcommand([~"--no-link", ~"build"], global, commands) {
+          Err(f) => {
+            assert_eq!(f.command, None);
+            check_fail_type(f.fail, UnrecognizedOption_);
+          }
+          _ => fail2!()
+        }
+    }
+
+    #[test]
+    fn test_groups_subcommand_option_for_other_command() {
+        let (global, commands) = subcommands();
+        match groups::getopts_subcommand([~"list", ~"--no-link"], global, commands) {
+          Err(f) => {
+            assert_eq!(f.command, Some(~"list"));
+            check_fail_type(f.fail, UnrecognizedOption_);
+          }
+          _ => fail2!()
+        }
+    }
+
+    #[test]
+    fn test_groups_subcommand_duplicated_global() {
+        let (global, commands) = subcommands();
+        match groups::getopts_subcommand([~"-v", ~"list", ~"-v"], global, commands) {
+          Err(f) => {
+            assert_eq!(f.command, Some(~"list"));
+            check_fail_type(f.fail, OptionDuplicated_);
+          }
+          _ => fail2!()
+        }
+    }
+
+    #[test]
+    fn test_groups_commands_usage() {
+        let (global, commands) = subcommands();
+
+        let expected =
+~"Usage: fruits <command>
+
+Commands:
+    build               Build a crate
+    list                List crates
+
+Options:
+    -v --verbose        Desc
+    --sysroot PATH      Desc
+";
+
+        let usage = groups::commands_usage("Usage: fruits <command>", global, commands);
+
+        debug2!("expected: <<{}>>", expected);
+        debug2!("generated: <<{}>>", usage);
+        assert_eq!(usage, expected);
+    }
+
+    #[test]
+    fn test_groups_command_usage() {
+        let (global, commands) = subcommands();
+
+        let expected =
+~"Usage: fruits build
+
+Options:
+    --no-link           Desc
+    -c --cfg SPEC       Desc
+
+Global options:
+    -v --verbose        Desc
+    --sysroot PATH      Desc
+";
+
+        let usage = groups::command_usage("Usage: fruits build", global, &commands[0]);
+        assert_eq!(usage, expected);
+
+        let expected =
+~"Usage: fruits list
+
+Global options:
+    -v --verbose        Desc
+    --sysroot PATH      Desc
+";
+
+        let usage = groups::command_usage("Usage: fruits list", global, &commands[1]);
+        assert_eq!(usage, expected);
+    }
 }
@@ -25,6 +25,7 @@ use std::io;
 use std::num;
 use std::str;
 use std::to_str;
+use std::vec;
 
 use serialize::Encodable;
 use serialize;
@@ -866,6 +867,445 @@ pub fn from_str(s: &str) -> Result<Json, Error> {
     parser.parse()
 }
 
+/// An event produced by a `StreamParser`. Values inside an object are
+/// preceded by a `Key` event naming them.
+#[deriving(Eq)]
+pub enum JsonEvent {
+    ObjectStart,
+    ObjectEnd,
+    ListStart,
+    ListEnd,
+    Key(~str),
+    BooleanValue(bool),
+    NumberValue(f64),
+    StringValue(~str),
+    NullValue,
+    ParseError(Error),
+}
+
+// Where a StreamParser is within the containers it has opened.
+enum StreamState {
+    ListFirst,
+    ListNext,
+    ObjectFirst,
+    ObjectNext,
+    ObjectValue,
+}
+
+/**
+ * A pull parser which turns JSON text into a sequence of `JsonEvent`s
+ * without building a `Json` tree, so that large documents can be processed
+ * in constant memory (aside from the nesting depth).
+ *
+ * The iterator ends after the top-level value has been closed. If the input
+ * is malformed, the last event is a `ParseError`, reported at the same place
+ * and with the same message `Parser` would use.
+ */
+pub struct StreamParser<T> {
+    priv parser: Parser<T>,
+    priv stack: ~[StreamState],
+    priv started: bool,
+    priv finished: bool,
+}
+
+/// Create a streaming parser reading from an Iterator<char>
+pub fn StreamParser<T : Iterator<char>>(rdr: ~T) -> StreamParser<T> {
+    StreamParser {
+        parser: Parser(rdr),
+        stack: ~[],
+        started: false,
+        finished: false,
+    }
+}
+
+impl<T : Iterator<char>> Iterator<JsonEvent> for StreamParser<T> {
+    fn next(&mut self) -> Option<JsonEvent> {
+        if self.finished {
+            return None;
+        }
+        let event = self.parse_event();
+        match event {
+            Some(ParseError(_)) | None => self.finished = true,
+            _ => ()
+        }
+        event
+    }
+}
+
+impl<T : Iterator<char>> StreamParser<T> {
+    /// The number of lists and objects that are currently open.
+    pub fn depth(&self) -> uint { self.stack.len() }
+
+    /**
+     * To be called right after an `ObjectStart` or `ListStart` event. Skips
+     * the rest of that object or list, up to and including its end. Returns
+     * the error that stopped it if the input is malformed.
+     */
+    pub fn skip_value(&mut self) -> Option<Error> {
+        let depth = self.depth();
+        while self.depth() >= depth {
+            match self.next() {
+                Some(ParseError(e)) => return Some(e),
+                None => break,
+                _ => ()
+            }
+        }
+        None
+    }
+
+    fn error_event(&self, msg: ~str) -> Option<JsonEvent> {
+        Some(ParseError(Error {
+            line: self.parser.line,
+            col: self.parser.col,
+            msg: @msg
+        }))
+    }
+
+    fn parse_event(&mut self) -> Option<JsonEvent> {
+        if self.stack.is_empty() {
+            if !self.started {
+                self.started = true;
+                return self.parse_start_value();
+            }
+            // Make sure there are no trailing characters.
+            self.parser.parse_whitespace();
+            return if self.parser.eof() {
+                None
+            } else {
+                self.error_event(~"trailing characters")
+            };
+        }
+
+        self.parser.parse_whitespace();
+        let top = self.stack.len() - 1;
+        match self.stack[top] {
+            ListFirst => {
+                if self.parser.ch == ']' {
+                    self.parser.bump();
+                    self.stack.pop();
+                    Some(ListEnd)
+                } else {
+                    self.stack[top] = ListNext;
+                    self.parse_start_value()
+                }
+            }
+            ListNext => {
+                if self.parser.eof() {
+                    return self.error_event(~"EOF while parsing list");
+                }
+                match self.parser.ch {
+                    ',' => { self.parser.bump(); self.parse_start_value() }
+                    ']' => { self.parser.bump(); self.stack.pop(); Some(ListEnd) }
+                    _ => self.error_event(~"expected `,` or `]`")
+                }
+            }
+            ObjectFirst => {
+                if self.parser.ch == '}' {
+                    self.parser.bump();
+                    self.stack.pop();
+                    Some(ObjectEnd)
+                } else {
+                    self.stack[top] = ObjectValue;
+                    self.parse_key()
+                }
+            }
+            ObjectNext => {
+                if self.parser.eof() {
+                    return self.error_event(~"EOF while parsing object");
+                }
+                match self.parser.ch {
+                    ',' => {
+                        self.parser.bump();
+                        self.stack[top] = ObjectValue;
+                        self.parse_key()
+                    }
+                    '}' => { self.parser.bump(); self.stack.pop(); Some(ObjectEnd) }
+                    _ => self.error_event(~"expected `,` or `}`")
+                }
+            }
+            ObjectValue => {
+                self.stack[top] = ObjectNext;
+                self.parse_start_value()
+            }
+        }
+    }
+
+    fn parse_key(&mut self) -> Option<JsonEvent> {
+        self.parser.parse_whitespace();
+        if self.parser.eof() {
+            return self.error_event(~"EOF while parsing object");
+        }
+        if self.parser.ch != '"' {
+            return self.error_event(~"key must be a string");
+        }
+        let key = match self.parser.parse_str() {
+            Ok(key) => key,
+            Err(e) => return Some(ParseError(e))
+        };
+        self.parser.parse_whitespace();
+        if self.parser.ch != ':' {
+            if self.parser.eof() {
+                return self.error_event(~"EOF while parsing object");
+            }
+            return self.error_event(~"expected `:`");
+        }
+        self.parser.bump();
+        Some(Key(key))
+    }
+
+    // Parses a scalar value, or the opening bracket of a list or object.
+    fn parse_start_value(&mut self) -> Option<JsonEvent> {
+        self.parser.parse_whitespace();
+
+        if self.parser.eof() { return self.error_event(~"EOF while parsing value"); }
+
+        let value = match self.parser.ch {
+          '[' => {
+            self.parser.bump();
+            self.stack.push(ListFirst);
+            return Some(ListStart);
+          }
+          '{' => {
+            self.parser.bump();
+            self.stack.push(ObjectFirst);
+            return Some(ObjectStart);
+          }
+          'n' => self.parser.parse_ident("ull", Null),
+          't' => self.parser.parse_ident("rue", Boolean(true)),
+          'f' => self.parser.parse_ident("alse", Boolean(false)),
+          '0' .. '9' | '-' => self.parser.parse_number(),
+          '"' =>
+            match self.parser.parse_str() {
+              Ok(s) => Ok(String(s)),
+              Err(e) => Err(e),
+            },
+          _ => return self.error_event(~"invalid syntax")
+        };
+        Some(match value {
+            Ok(Null) => NullValue,
+            Ok(Boolean(b)) => BooleanValue(b),
+            Ok(Number(n)) => NumberValue(n),
+            Ok(String(s)) => StringValue(s),
+            Ok(_) => fail2!("unexpected compound value"),
+            Err(e) => ParseError(e)
+        })
+    }
+}
+
+static READER_BUF_SIZE: uint = 4096;
+
+/// An Iterator<char> over the characters of an @io::Reader
+///
+/// The reader is read a buffer at a time and decoded as UTF-8. Input that
+/// isn't valid UTF-8, or that ends partway through a character, ends the
+/// iteration, which the parser reports as an unexpected end of input.
+pub struct ReaderChars {
+    priv rdr: @io::Reader,
+    priv buf: ~[u8],
+    priv pos: uint,
+    priv end: uint,
+}
+
+impl ReaderChars {
+    /// Makes sure at least `n` undecoded bytes are buffered, unless the
+    /// reader runs out first. Returns whether there are.
+    fn fill(&mut self, n: uint) -> bool {
+        if self.end - self.pos >= n { return true; }
+        if self.pos > 0 {
+            let (pos, end) = (self.pos, self.end);
+            for i in range(pos, end) {
+                self.buf[i - pos] = self.buf[i];
+            }
+            self.end = end - pos;
+            self.pos = 0;
+        }
+        while self.end < n {
+            let len = self.buf.len() - self.end;
+            let nread = self.rdr.read(self.buf.mut_slice_from(self.end), len);
+            if nread == 0 { return false; }
+            self.end += nread;
+        }
+        true
+    }
+}
+
+impl Iterator<char> for ReaderChars {
+    fn next(&mut self) -> Option<char> {
+        if !self.fill(1) { return None; }
+        let w = str::utf8_char_width(self.buf[self.pos]);
+        if w == 0 || !self.fill(w) { return None; }
+        let c = match str::from_utf8_slice_opt(self.buf.slice(self.pos, self.pos + w)) {
+            Some(s) => s.char_at(0),
+            None => return None
+        };
+        self.pos += w;
+        Some(c)
+    }
+}
+
+/// Creates a streaming parser which reads an @io::Reader as it goes
+pub fn stream_from_reader(rdr: @io::Reader) -> StreamParser<ReaderChars> {
+    StreamParser(~ReaderChars {
+        rdr: rdr,
+        buf: vec::from_elem(READER_BUF_SIZE, 0u8),
+        pos: 0,
+        end: 0,
+    })
+}
+
+/**
+ * Writes JSON to an @io::Writer piece by piece, without building a `Json`
+ * tree first. The writer only takes care of separating elements; it is up to
+ * the caller to close every list and object it opens, and to give a key
+ * before each value inside an object.
+ */
+pub struct StreamWriter {
+    priv wr: @io::Writer,
+    priv pretty: bool,
+    // For each open list or object, whether anything was written to it yet.
+    priv nonempty: ~[bool],
+    priv after_key: bool,
+}
+
+/// Creates a new streaming JSON writer writing to the writer specified.
+pub fn StreamWriter(wr: @io::Writer) -> StreamWriter {
+    StreamWriter {
+        wr: wr,
+        pretty: false,
+        nonempty: ~[],
+        after_key: false,
+    }
+}
+
+/**
+ * Creates a new streaming JSON writer which lays its output out exactly as
+ * `PrettyEncoder` does: one element per line, indented by two spaces per
+ * level of nesting.
+ *
+ * The output is stable as long as the caller writes keys in a stable order.
+ * `Json` objects are `TreeMap`s, so values written with `value()` always
+ * have their keys sorted; to get the same for a `HashMap`, write its
+ * `to_json()` rather than encoding it directly.
+ */
+pub fn PrettyStreamWriter(wr: @io::Writer) -> StreamWriter {
+    StreamWriter {
+        wr: wr,
+        pretty: true,
+        nonempty: ~[],
+        after_key: false,
+    }
+}
+
+impl StreamWriter {
+    pub fn start_object(&mut self) {
+        self.separate();
+        self.wr.write_char('{');
+        self.nonempty.push(false);
+    }
+
+    pub fn end_object(&mut self) {
+        self.close();
+        self.wr.write_char('}');
+    }
+
+    pub fn start_list(&mut self) {
+        self.separate();
+        self.wr.write_char('[');
+        self.nonempty.push(false);
+    }
+
+    pub fn end_list(&mut self) {
+        self.close();
+        self.wr.write_char(']');
+    }
+
+    /// Writes the key of the next value of the innermost object.
+    pub fn key(&mut self, key: &str) {
+        self.separate();
+        self.wr.write_str(escape_str(key));
+        self.wr.write_str(if self.pretty { ": " } else { ":" });
+        self.after_key = true;
+    }
+
+    /// Writes a complete value.
+    pub fn value(&mut self, value: &Json) {
+        self.encode(value);
+    }
+
+    /// Writes a complete value, encoding it directly from `value`.
+    pub fn encode<T: Encodable<Encoder> + Encodable<PrettyEncoder>>(&mut self,
+                                                                    value: &T) {
+        self.separate();
+        if self.pretty {
+            let mut encoder = PrettyEncoder(self.wr);
+            encoder.indent = self.indent();
+            encode_pretty(value, &mut encoder);
+        } else {
+            encode_compact(value, &mut Encoder(self.wr));
+        }
+    }
+
+    /**
+     * Writes the piece of JSON that `event` describes. Together with a
+     * `StreamParser` this can copy or filter a document without holding all
+     * of it in memory.
+     */
+    pub fn write_event(&mut self, event: &JsonEvent) {
+        match *event {
+            ObjectStart => self.start_object(),
+            ObjectEnd => self.end_object(),
+            ListStart => self.start_list(),
+            ListEnd => self.end_list(),
+            Key(ref k) => self.key(k.as_slice()),
+            BooleanValue(b) => self.value(&Boolean(b)),
+            NumberValue(n) => self.value(&Number(n)),
+            StringValue(ref s) => self.encode(s),
+            NullValue => self.value(&Null),
+            ParseError(ref e) => fail2!("can't write a parse error: {}", e.to_str()),
+        }
+    }
+
+    // Writes the comma before every element but the first of a container,
+    // and in pretty mode puts each element on a line of its own.
+    fn separate(&mut self) {
+        if self.after_key {
+            self.after_key = false;
+            return;
+        }
+        let n = self.nonempty.len();
+        if n > 0 {
+            if self.nonempty[n - 1] {
+                self.wr.write_char(',');
+            }
+            self.nonempty[n - 1] = true;
+            if self.pretty {
+                self.wr.write_char('\n');
+                self.wr.write_str(spaces(self.indent()));
+            }
+        }
+    }
+
+    // Ends the innermost container, up to its closing bracket.
+    fn close(&mut self) {
+        let nonempty = self.nonempty.pop();
+        if self.pretty && nonempty {
+            self.wr.write_char('\n');
+            self.wr.write_str(spaces(self.indent()));
+        }
+    }
+
+    fn indent(&self) -> uint { self.nonempty.len() * 2 }
+}
+
+// These pick the Encodable impl to use for StreamWriter::encode.
+fn encode_compact<T: Encodable<Encoder>>(value: &T, encoder: &mut Encoder) {
+    value.encode(encoder)
+}
+
+fn encode_pretty<T: Encodable<PrettyEncoder>>(value: &T, encoder: &mut PrettyEncoder) {
+    value.encode(encoder)
+}
+
 /// A structure to decode JSON to values in rust.
 pub struct Decoder {
     priv stack: ~[Json],
@@ -1936,6 +2376,134 @@ mod tests {
         assert_eq!(map.pop(&~"b"), Some(Frog(~"Henry", 349)));
     }
 
+    fn stream_events(s: &str) -> ~[JsonEvent] {
+        StreamParser(~s.iter()).collect()
+    }
+
+    #[test]
+    fn test_stream_parser() {
+        assert_eq!(stream_events("null"), ~[NullValue]);
+        assert_eq!(stream_events(" [] "), ~[ListStart, ListEnd]);
+        assert_eq!(stream_events("{\"a\": [1, true, \"x\"], \"b\": {}}"),
+                   ~[ObjectStart,
+                     Key(~"a"), ListStart, NumberValue(1.0), BooleanValue(true),
+                     StringValue(~"x"), ListEnd,
+                     Key(~"b"), ObjectStart, ObjectEnd,
+                     ObjectEnd]);
+    }
+
+    #[test]
+    fn test_stream_parser_errors() {
+        // The streaming parser reports the same errors as the tree parser.
+        let inputs = ["nulla", "[1,]", "[1 2]", "{\"a\" 1}", "{1: 2}",
+                      "{\"a\": 1 \"b\": 2}", "[\"\\q\"]",
+                      "{\n  \"foo\":\n \"bar\"", "[", ""];
+        for input in inputs.iter() {
+            let events = stream_events(*input);
+            let expected = from_str(*input).unwrap_err();
+            assert_eq!(events.last(), &ParseError(expected));
+        }
+    }
+
+    #[test]
+    fn test_stream_skip_value() {
+        let s = "[{\"a\": [1, [2]], \"b\": 3}, 4]";
+        let mut parser = StreamParser(~s.iter());
+        assert_eq!(parser.next(), Some(ListStart));
+        assert_eq!(parser.next(), Some(ObjectStart));
+        assert_eq!(parser.skip_value(), None);
+        assert_eq!(parser.depth(), 1);
+        assert_eq!(parser.next(), Some(NumberValue(4.0)));
+        assert_eq!(parser.next(), Some(ListEnd));
+        assert_eq!(parser.next(), None);
+    }
+
+    #[test]
+    fn test_stream_from_reader() {
+        let s = "{\"a\": [null, false]}";
+        let events: ~[JsonEvent] = do io::with_str_reader(s) |rdr| {
+            stream_from_reader(rdr).collect()
+        };
+        assert_eq!(events, stream_events(s));
+    }
+
+    #[test]
+    fn test_stream_from_reader_across_buffers() {
+        // Pad the input so that multibyte characters straddle the end of
+        // the reader's buffer.
+        let s = format!("[\"{}\", \"{}\"]",
+                        "a".repeat(super::READER_BUF_SIZE - 3),
+                        "\u00e9\u4e2d\U0001f600".repeat(super::READER_BUF_SIZE));
+        let events: ~[JsonEvent] = do io::with_str_reader(s) |rdr| {
+            stream_from_reader(rdr).collect()
+        };
+        assert_eq!(events, stream_events(s));
+    }
+
+    #[test]
+    fn test_stream_from_reader_invalid_utf8() {
+        let bytes = bytes!("[\"a", 0xff, "\"]");
+        let events: ~[JsonEvent] = do io::with_bytes_reader(bytes) |rdr| {
+            stream_from_reader(rdr).collect()
+        };
+        assert!(events.len() == 2);
+        assert_eq!(events[0], ListStart);
+        match events[1] {
+            ParseError(_) => (),
+            ref e => fail2!("expected a parse error, got {:?}", e)
+        }
+    }
+
+    #[test]
+    fn test_stream_writer() {
+        let s = "{\"a\":[1,true,null,\"x\\ny\"],\"b\":{\"c\":[],\"d\":{}}}";
+        let copy = do io::with_str_writer |wr| {
+            let mut writer = StreamWriter(wr);
+            for event in StreamParser(~s.iter()) {
+                writer.write_event(&event);
+            }
+        };
+        assert_eq!(copy.as_slice(), s);
+        assert_eq!(copy, from_str(s).unwrap().to_str());
+
+        let written = do io::with_str_writer |wr| {
+            let mut writer = StreamWriter(wr);
+            writer.start_list();
+            writer.value(&Number(1.0));
+            writer.start_object();
+            writer.key("k");
+            writer.encode(&~[~"v"]);
+            writer.end_object();
+            writer.encode(&Some(2));
+            writer.end_list();
+        };
+        assert_eq!(written, ~"[1,{\"k\":[\"v\"]},2]");
+    }
+
+    #[test]
+    fn test_pretty_stream_writer() {
+        let s = "{\"b\": [1, {}, []], \"a\": {\"d\": null, \"c\": \"x\"}}";
+        let copy = do io::with_str_writer |wr| {
+            let mut writer = PrettyStreamWriter(wr);
+            for event in StreamParser(~s.iter()) {
+                writer.write_event(&event);
+            }
+        };
+        assert_eq!(copy, ~"{\n  \"b\": [\n    1,\n    {},\n    []\n  ],\n  \"a\": {\n" +
+                          "    \"d\": null,\n    \"c\": \"x\"\n  }\n}");
+
+        // Whole values come out sorted and indented like PrettyEncoder's.
+        let written = do io::with_str_writer |wr| {
+            let mut writer = PrettyStreamWriter(wr);
+            writer.start_list();
+            writer.value(&from_str(s).unwrap());
+            writer.end_list();
+        };
+        let expected = List(~[from_str(s).unwrap()]).to_pretty_str();
+        assert_eq!(written, expected);
+        assert!(written.find_str("\"a\"").unwrap() < written.find_str("\"b\"").unwrap());
+    }
+
     #[test]
     fn test_multiline_errors() {
         assert_eq!(from_str("{\n  \"foo\":\n \"bar\""),
@@ -27,6 +27,23 @@
 //!
 //! An example version number with all five components is
 //! `0.8.1-rc.3.0+20130922.linux`.
+//!
+//! A `VersionReq` is a set of comparators that a version must all satisfy,
+//! such as `>=1.2, <2.0`. Each comparator is an operator followed by a
+//! version which may leave out its minor and patch numbers:
+//!
+//!  * `=1.2.3` (or just `1.2.3`) matches exactly that version, while `=1.2`
+//!    matches any `1.2.x`
+//!  * `>`, `>=`, `<` and `<=` compare by precedence, treating left-out
+//!    numbers as wildcards: `>1.2` means `>=1.3.0` and `<=1.2` means `<1.3.0`
+//!  * `~1.2.3` allows patch-level changes: `>=1.2.3, <1.3.0`
+//!  * `^1.2.3` allows changes that are compatible according to semantic
+//!    versioning: `>=1.2.3, <2.0.0`, or `>=0.2.3, <0.3.0` for `^0.2.3`
+//!  * `*` matches any version
+//!
+//! A pre-release version only matches a requirement if one of its
+//! comparators names a pre-release of the same major, minor and patch
+//! number, so that `>=1.0.0` does not pick up `2.0.0-alpha`.
 
 use std::char;
 use std::cmp;
@@ -247,6 +264,317 @@ pub fn parse(s: &str) -> Option<Version> {
     }
 }
 
+/// The operator of a comparator in a `VersionReq`.
+#[deriving(Clone, Eq)]
+#[allow(missing_doc)]
+pub enum Op {
+    Ex,         // =
+    Gt,         // >
+    GtEq,       // >=
+    Lt,         // <
+    LtEq,       // <=
+    Tilde,      // ~
+    Compatible, // ^
+}
+
+impl ToStr for Op {
+    fn to_str(&self) -> ~str {
+        match *self {
+            Ex => ~"=",
+            Gt => ~">",
+            GtEq => ~">=",
+            Lt => ~"<",
+            LtEq => ~"<=",
+            Tilde => ~"~",
+            Compatible => ~"^",
+        }
+    }
+}
+
+/// A single comparator of a `VersionReq`, such as `>=1.2`.
+#[deriving(Clone, Eq)]
+pub struct Predicate {
+    /// How versions are compared against this one.
+    op: Op,
+    major: uint,
+    /// The minor number, or `None` if it was left out.
+    minor: Option<uint>,
+    /// The patch number, or `None` if it was left out.
+    patch: Option<uint>,
+    /// The pre-release identifiers; only present along with a patch number.
+    pre: ~[Identifier],
+}
+
+impl Predicate {
+    /// Whether `v` satisfies this comparator, ignoring pre-release rules.
+    pub fn matches(&self, v: &Version) -> bool {
+        match self.op {
+            Ex => self.is_exact(v),
+            Gt => self.is_greater(v),
+            GtEq => self.is_exact(v) || self.is_greater(v),
+            Lt => !self.is_exact(v) && !self.is_greater(v),
+            LtEq => !self.is_greater(v),
+            Tilde => self.matches_tilde(v),
+            Compatible => self.matches_compatible(v),
+        }
+    }
+
+    // Whether `v` is one of the versions this predicate's partial version
+    // stands for.
+    fn is_exact(&self, v: &Version) -> bool {
+        if v.major != self.major {
+            return false;
+        }
+        match self.minor {
+            Some(minor) if v.minor != minor => return false,
+            Some(_) => (),
+            None => return true
+        }
+        match self.patch {
+            Some(patch) => v.patch == patch && v.pre == self.pre,
+            None => true
+        }
+    }
+
+    // Whether `v` has higher precedence than all of the versions this
+    // predicate's partial version stands for.
+    fn is_greater(&self, v: &Version) -> bool {
+        if v.major != self.major {
+            return v.major > self.major;
+        }
+        match self.minor {
+            Some(minor) if v.minor != minor => return v.minor > minor,
+            Some(_) => (),
+            None => return false
+        }
+        match self.patch {
+            Some(patch) if v.patch != patch => v.patch > patch,
+            Some(_) => pre_is_greater(&v.pre, &self.pre),
+            None => false
+        }
+    }
+
+    // Whether `v` has the same minor version as this one (if given) and is
+    // at least as recent as its patch version (if given).
+    fn at_least_patch(&self, v: &Version) -> bool {
+        match self.patch {
+            Some(patch) => v.patch > patch ||
+                (v.patch == patch && !pre_is_greater(&self.pre, &v.pre)),
+            None => true
+        }
+    }
+
+    fn matches_tilde(&self, v: &Version) -> bool {
+        v.major == self.major && match self.minor {
+            Some(minor) => v.minor == minor && self.at_least_patch(v),
+            None => true
+        }
+    }
+
+    fn matches_compatible(&self, v: &Version) -> bool {
+        if v.major != self.major {
+            return false;
+        }
+        match self.minor {
+            None => true,
+            // Before 1.0.0, every minor version may break compatibility, and
+            // before 0.1.0 so may every patch version.
+            Some(minor) if self.major == 0 && minor == 0 => {
+                v.minor == 0 && match self.patch {
+                    Some(patch) => v.patch == patch && self.at_least_patch(v),
+                    None => true
+                }
+            }
+            Some(minor) if self.major == 0 => {
+                v.minor == minor && self.at_least_patch(v)
+            }
+            Some(minor) => {
+                v.minor > minor || (v.minor == minor && self.at_least_patch(v))
+            }
+        }
+    }
+}
+
+// Whether a version with pre-release identifiers `a` has higher precedence
+// than one with the same numbers and identifiers `b`.
+fn pre_is_greater(a: &~[Identifier], b: &~[Identifier]) -> bool {
+    match (a.is_empty(), b.is_empty()) {
+        (true, true) => false,
+        (true, false) => true,
+        (false, true) => false,
+        (false, false) => *b < *a
+    }
+}
+
+impl ToStr for Predicate {
+    fn to_str(&self) -> ~str {
+        let mut s = format!("{}{}", self.op.to_str(), self.major);
+        match self.minor {
+            Some(minor) => s.push_str(format!(".{}", minor)),
+            None => ()
+        }
+        match self.patch {
+            Some(patch) => s.push_str(format!(".{}", patch)),
+            None => ()
+        }
+        if !self.pre.is_empty() {
+            s.push_str(format!("-{}", self.pre.map(|i| i.to_str()).connect(".")));
+        }
+        s
+    }
+}
+
+/// A set of comparators which a version must all satisfy, such as
+/// `>=1.2, <2.0`. See the module documentation for their meaning.
+#[deriving(Clone, Eq)]
+pub struct VersionReq {
+    /// The comparators; an empty list matches any version.
+    predicates: ~[Predicate],
+}
+
+impl VersionReq {
+    /// A requirement that any version satisfies.
+    pub fn any() -> VersionReq {
+        VersionReq { predicates: ~[] }
+    }
+
+    /// A requirement that only `v` (whatever its build metadata) satisfies.
+    pub fn exact(v: &Version) -> VersionReq {
+        VersionReq {
+            predicates: ~[Predicate {
+                op: Ex,
+                major: v.major,
+                minor: Some(v.minor),
+                patch: Some(v.patch),
+                pre: v.pre.clone(),
+            }]
+        }
+    }
+
+    /// Parse a comma-separated list of comparators, or `*`.
+    pub fn parse(s: &str) -> Option<VersionReq> {
+        let s = s.trim();
+        if s == "*" {
+            return Some(VersionReq::any());
+        }
+        let mut predicates = ~[];
+        for part in s.split_iter(',') {
+            match parse_predicate(part.trim()) {
+                Some(p) => predicates.push(p),
+                None => return None
+            }
+        }
+        Some(VersionReq { predicates: predicates })
+    }
+
+    /// Whether `v` satisfies every comparator of this requirement.
+    pub fn matches(&self, v: &Version) -> bool {
+        self.predicates.iter().all(|p| p.matches(v)) &&
+            (v.pre.is_empty() || self.predicates.iter().any(|p| {
+                !p.pre.is_empty() && p.major == v.major &&
+                    p.minor == Some(v.minor) && p.patch == Some(v.patch)
+            }))
+    }
+
+    /**
+     * Of the versions in `vs`, the one with the highest precedence that
+     * satisfies this requirement, if any.
+     */
+    pub fn best_match<'a>(&self, vs: &'a [Version]) -> Option<&'a Version> {
+        let mut best: Option<&'a Version> = None;
+        for v in vs.iter() {
+            if self.matches(v) && best.map_default(true, |b| *b < *v) {
+                best = Some(v);
+            }
+        }
+        best
+    }
+}
+
+impl ToStr for VersionReq {
+    fn to_str(&self) -> ~str {
+        if self.predicates.is_empty() {
+            ~"*"
+        } else {
+            self.predicates.map(|p| p.to_str()).connect(", ")
+        }
+    }
+}
+
+fn parse_predicate(s: &str) -> Option<Predicate> {
+    let ops = [(">=", GtEq), ("<=", LtEq), (">", Gt), ("<", Lt), ("=", Ex),
+               ("~", Tilde), ("^", Compatible)];
+    let mut op = Ex;
+    let mut rest = s;
+    for &(prefix, o) in ops.iter() {
+        if s.starts_with(prefix) {
+            op = o;
+            rest = s.slice_from(prefix.len()).trim_left();
+            break;
+        }
+    }
+
+    let (numbers, pre) = match rest.find('-') {
+        Some(i) => (rest.slice_to(i), Some(rest.slice_from(i + 1))),
+        None => (rest, None)
+    };
+    let numbers: ~[&str] = numbers.split_iter('.').collect();
+    if numbers.len() > 3 {
+        return None;
+    }
+    let mut parsed = ~[];
+    for (i, n) in numbers.iter().enumerate() {
+        // Minor and patch numbers may be written as wildcards.
+        if i > 0 && (*n == "*" || *n == "x") {
+            break;
+        }
+        if n.is_empty() || !n.iter().all(char::is_digit) {
+            return None;
+        }
+        match from_str::<uint>(*n) {
+            Some(n) => parsed.push(n),
+            None => return None
+        }
+    }
+    if parsed.len() < numbers.len() {
+        // Nothing may follow a wildcard.
+        if !numbers.slice_from(parsed.len()).iter().all(|n| *n == "*" || *n == "x") {
+            return None;
+        }
+    }
+
+    let pre = match pre {
+        None => ~[],
+        // A pre-release only makes sense on a complete version.
+        Some(_) if parsed.len() < 3 => return None,
+        Some(pre) => {
+            let mut ids = ~[];
+            for id in pre.split_iter('.') {
+                if id.is_empty() || !id.iter().all(char::is_alphanumeric) {
+                    return None;
+                }
+                ids.push(if id.iter().all(char::is_digit) {
+                    match from_str::<uint>(id) {
+                        Some(n) => Numeric(n),
+                        None => return None
+                    }
+                } else {
+                    AlphaNumeric(id.to_owned())
+                });
+            }
+            ids
+        }
+    };
+
+    Some(Predicate {
+        op: op,
+        major: parsed[0],
+        minor: if parsed.len() > 1 { Some(parsed[1]) } else { None },
+        patch: if parsed.len() > 2 { Some(parsed[2]) } else { None },
+        pre: pre,
+    })
+}
+
 #[test]
 fn test_parse() {
     assert_eq!(parse(""), None);
@@ -406,3 +734,90 @@ fn test_spec_order() {
         i += 1;
     }
 }
+
+#[cfg(test)]
+fn req(s: &str) -> VersionReq { VersionReq::parse(s).unwrap() }
+
+#[cfg(test)]
+fn matches(r: &str, v: &str) -> bool { req(r).matches(&parse(v).unwrap()) }
+
+#[test]
+fn test_req_parse() {
+    assert_eq!(VersionReq::parse(""), None);
+    assert_eq!(VersionReq::parse(">="), None);
+    assert_eq!(VersionReq::parse("1.2.3.4"), None);
+    assert_eq!(VersionReq::parse(">=1.2,"), None);
+    assert_eq!(VersionReq::parse("1.2-alpha"), None);
+    assert_eq!(VersionReq::parse("1.*.3"), None);
+    assert_eq!(VersionReq::parse("=a.b"), None);
+
+    assert_eq!(req("*").to_str(), ~"*");
+    assert_eq!(req("1.2").to_str(), ~"=1.2");
+    assert_eq!(req(">=1.2, <2.0").to_str(), ~">=1.2, <2.0");
+    assert_eq!(req(" ~ 1.2.3-beta.2 ").to_str(), ~"~1.2.3-beta.2");
+    assert_eq!(req("^0.1.x").to_str(), ~"^0.1");
+    assert_eq!(req(">=1.2, <2.0"), VersionReq {
+        predicates: ~[
+            Predicate { op: GtEq, major: 1, minor: Some(2), patch: None, pre: ~[] },
+            Predicate { op: Lt, major: 2, minor: Some(0), patch: None, pre: ~[] }
+        ]
+    });
+}
+
+#[test]
+fn test_req_matches() {
+    assert!(matches("*", "0.0.1"));
+    assert!(matches("1.2.3", "1.2.3+build"));
+    assert!(!matches("1.2.3", "1.2.4"));
+    assert!(matches("=1.2", "1.2.7"));
+    assert!(!matches("=1.2", "1.3.0"));
+
+    assert!(matches(">=1.2, <2.0", "1.2.0"));
+    assert!(matches(">=1.2, <2.0", "1.9.9"));
+    assert!(!matches(">=1.2, <2.0", "1.1.9"));
+    assert!(!matches(">=1.2, <2.0", "2.0.0"));
+    assert!(matches(">1.2", "1.3.0"));
+    assert!(!matches(">1.2", "1.2.9"));
+    assert!(matches("<=1.2", "1.2.9"));
+    assert!(!matches("<=1.2", "1.3.0"));
+
+    assert!(matches("~1.2.3", "1.2.5"));
+    assert!(!matches("~1.2.3", "1.2.2"));
+    assert!(!matches("~1.2.3", "1.3.0"));
+    assert!(matches("~1", "1.9.0"));
+
+    assert!(matches("^1.2.3", "1.9.0"));
+    assert!(!matches("^1.2.3", "1.2.2"));
+    assert!(!matches("^1.2.3", "2.0.0"));
+    assert!(matches("^0.2.3", "0.2.9"));
+    assert!(!matches("^0.2.3", "0.3.0"));
+    assert!(matches("^0.0.3", "0.0.3"));
+    assert!(!matches("^0.0.3", "0.0.4"));
+}
+
+#[test]
+fn test_req_prerelease() {
+    // Pre-releases only match when a comparator asks for them.
+    assert!(!matches(">=1.0.0", "2.0.0-alpha"));
+    assert!(!matches("*", "1.0.0-rc.1"));
+    assert!(matches(">=1.0.0-beta", "1.0.0-rc.1"));
+    assert!(matches(">=1.0.0-beta", "1.0.0"));
+    assert!(!matches(">=1.0.0-beta", "1.0.0-alpha"));
+    assert!(!matches(">=1.0.0-beta", "1.0.1-rc.1"));
+    assert!(matches("<1.0.0", "0.9.0"));
+    assert!(!matches("<1.0.0-rc.1", "1.0.0"));
+    assert!(matches("~1.2.3-beta", "1.2.3-beta.2"));
+    assert!(matches("~1.2.3-beta", "1.2.4"));
+}
+
+#[test]
+fn test_req_best_match() {
+    let vs = ~[parse("1.0.0").unwrap(), parse("1.4.2").unwrap(),
+               parse("2.0.0-alpha").unwrap(), parse("1.10.0").unwrap(),
+               parse("2.1.0").unwrap()];
+    assert_eq!(req("^1.0").best_match(vs.as_slice()), Some(&vs[3]));
+    assert_eq!(req(">=1.0").best_match(vs.as_slice()), Some(&vs[4]));
+    assert_eq!(req("<1.4").best_match(vs.as_slice()), Some(&vs[0]));
+    assert_eq!(req("^3.0").best_match(vs.as_slice()), None);
+    assert!(VersionReq::exact(&vs[1]).matches(&parse("1.4.2+b2").unwrap()));
+}
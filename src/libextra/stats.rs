@@ -75,6 +75,13 @@ pub trait Stats {
     /// Median absolute deviation as a percent of the median. See `median_abs_dev` and `median`.
     fn median_abs_dev_pct(self) -> f64;
 
+    /// Approximate 95% confidence interval for the median: the sample values whose ranks bound
+    /// the ranks the true median could take with 95% probability. Like the median, this makes no
+    /// assumption about the distribution of the samples, but it is wide for small samples.
+    ///
+    /// See: https://en.wikipedia.org/wiki/Median#Confidence_intervals
+    fn median_ci(self) -> (f64,f64);
+
     /// Percentile: the value below which `pct` percent of the values in `self` fall. For example,
     /// percentile(95.0) will return the value `v` such that that 95% of the samples `s` in `self`
     /// satisfy `s <= v`.
@@ -200,6 +207,19 @@ impl<'self> Stats for &'self [f64] {
         (self.median_abs_dev() / self.median()) * 100.0
     }
 
+    fn median_ci(self) -> (f64,f64) {
+        assert!(self.len() != 0);
+        let mut tmp = self.to_owned();
+        sort::tim_sort(tmp);
+        // The rank of the median is binomially distributed; use the normal approximation to
+        // find the 1-based ranks 1.96 standard deviations either side of it.
+        let n = tmp.len() as f64;
+        let half_width = 1.96 * n.sqrt() / 2.0;
+        let lo = cmp::max((n / 2.0 - half_width).floor(), 1.0);
+        let hi = cmp::min((1.0 + n / 2.0 + half_width).ceil(), n);
+        (tmp[lo as uint - 1], tmp[hi as uint - 1])
+    }
+
     fn percentile(self, pct: f64) -> f64 {
         let mut tmp = self.to_owned();
         sort::tim_sort(tmp);
@@ -934,6 +954,15 @@ mod tests {
         check(val, summ);
     }
 
+    #[test]
+    fn test_median_ci() {
+        let samples = range(0, 100).map(|i| (100 - i) as f64).to_owned_vec();
+        let samples: &[f64] = samples;
+        assert_eq!(samples.median_ci(), (40.0, 61.0));
+        let one: &[f64] = &[7.0];
+        assert_eq!(one.median_ci(), (7.0, 7.0));
+    }
+
     #[test]
     fn test_boxplot_nonpositive() {
         fn t(s: &Summary, expected: ~str) {
@@ -17,6 +17,7 @@
 
 
 use std::borrow;
+use std::cell::Cell;
 use std::comm;
 use std::comm::SendDeferred;
 use std::comm::{GenericPort, Peekable};
@@ -141,6 +142,34 @@ impl<Q:Send> Sem<Q> {
             }
         }
     }
+
+    // Takes a resource only if one is available right now; never blocks.
+    pub fn try_acquire(&self) -> bool {
+        unsafe {
+            do (**self).with |state| {
+                if state.count > 0 {
+                    state.count -= 1;
+                    true
+                } else {
+                    false
+                }
+            }
+        }
+    }
+
+    pub fn try_access<U>(&self, blk: &fn() -> U) -> Option<U> {
+        do task::unkillable {
+            if self.try_acquire() {
+                do (|| {
+                    do task::rekillable { Some(blk()) }
+                }).finally {
+                    self.release();
+                }
+            } else {
+                None
+            }
+        }
+    }
 }
 
 #[doc(hidden)]
@@ -360,6 +389,20 @@ impl Semaphore {
 
     /// Run a function with ownership of one of the semaphore's resources.
     pub fn access<U>(&self, blk: &fn() -> U) -> U { (&self.sem).access(blk) }
+
+    /**
+     * Acquire a resource only if one is available right now. Returns false,
+     * without blocking, if the caller would have had to wait.
+     */
+    pub fn try_acquire(&self) -> bool { (&self.sem).try_acquire() }
+
+    /**
+     * As access(), but returns None instead of blocking if no resource is
+     * available right now.
+     */
+    pub fn try_access<U>(&self, blk: &fn() -> U) -> Option<U> {
+        (&self.sem).try_access(blk)
+    }
 }
 
 /****************************************************************************
@@ -405,6 +448,14 @@ impl Mutex {
     pub fn lock_cond<U>(&self, blk: &fn(c: &Condvar) -> U) -> U {
         (&self.sem).access_cond(blk)
     }
+
+    /**
+     * Run a function with ownership of the mutex, but only if it can be taken
+     * without blocking. Returns None if another task holds the mutex.
+     */
+    pub fn try_lock<U>(&self, blk: &fn() -> U) -> Option<U> {
+        (&self.sem).try_access(blk)
+    }
 }
 
 /****************************************************************************
@@ -490,23 +541,75 @@ impl RWLock {
                 do (|| {
                     do task::rekillable { blk() }
                 }).finally {
+                    self.read_unlock();
+                }
+            }
+        }
+    }
+
+    /**
+     * As read(), but returns None instead of blocking if the rwlock cannot be
+     * taken in read mode right now, i.e. if a writer holds it or is waiting
+     * for it.
+     */
+    pub fn try_read<U>(&self, blk: &fn() -> U) -> Option<U> {
+        unsafe {
+            do task::unkillable {
+                let entered = (&self.order_lock).try_acquire() && {
                     let state = &mut *self.state.get();
-                    assert!(state.read_mode);
-                    let old_count = state.read_count.fetch_sub(1, atomics::Release);
-                    assert!(old_count > 0);
-                    if old_count == 1 {
-                        state.read_mode = false;
-                        // Note: this release used to be outside of a locked access
-                        // to exclusive-protected state. If this code is ever
-                        // converted back to such (instead of using atomic ops),
-                        // this access MUST NOT go inside the exclusive access.
-                        (&self.access_lock).release();
+                    let entered = if state.read_count.load(atomics::Acquire) != 0 {
+                        // Readers are inside, so the access lock belongs to
+                        // the reader cloud. If they all leave before we join
+                        // it, the access lock is free, and nobody but us can
+                        // take it because we hold the order lock.
+                        let old_count = state.read_count.fetch_add(1, atomics::Acquire);
+                        if old_count == 0 {
+                            (&self.access_lock).acquire();
+                            state.read_mode = true;
+                        }
+                        true
+                    } else if (&self.access_lock).try_acquire() {
+                        state.read_count.fetch_add(1, atomics::Acquire);
+                        state.read_mode = true;
+                        true
+                    } else {
+                        // A writer is inside.
+                        false
+                    };
+                    (&self.order_lock).release();
+                    entered
+                };
+                if entered {
+                    do (|| {
+                        do task::rekillable { Some(blk()) }
+                    }).finally {
+                        self.read_unlock();
                     }
+                } else {
+                    None
                 }
             }
         }
     }
 
+    // Leaves the reader cloud, letting writers in if we were the last reader.
+    fn read_unlock(&self) {
+        unsafe {
+            let state = &mut *self.state.get();
+            assert!(state.read_mode);
+            let old_count = state.read_count.fetch_sub(1, atomics::Release);
+            assert!(old_count > 0);
+            if old_count == 1 {
+                state.read_mode = false;
+                // Note: this release used to be outside of a locked access
+                // to exclusive-protected state. If this code is ever
+                // converted back to such (instead of using atomic ops),
+                // this access MUST NOT go inside the exclusive access.
+                (&self.access_lock).release();
+            }
+        }
+    }
+
     /**
      * Run a function with the rwlock in write mode. No calls to 'read' or
      * 'write' from other tasks will run concurrently with this one.
@@ -523,6 +626,29 @@ impl RWLock {
         }
     }
 
+    /**
+     * As write(), but returns None instead of blocking if the rwlock cannot
+     * be taken in write mode right now.
+     */
+    pub fn try_write<U>(&self, blk: &fn() -> U) -> Option<U> {
+        do task::unkillable {
+            let entered = (&self.order_lock).try_acquire() && {
+                let entered = (&self.access_lock).try_acquire();
+                (&self.order_lock).release();
+                entered
+            };
+            if entered {
+                do (|| {
+                    do task::rekillable { Some(blk()) }
+                }).finally {
+                    (&self.access_lock).release();
+                }
+            } else {
+                None
+            }
+        }
+    }
+
     /**
      * As write(), but also with a handle to a condvar. Waiting on this
      * condvar will allow readers and writers alike to take the rwlock before
@@ -658,6 +784,113 @@ impl RWLock {
         }
         RWLockReadMode { lock: token.lock, token: NonCopyable::new() }
     }
+
+    /**
+     * Run a function with the rwlock in read mode, with the ability to
+     * atomically 'upgrade' the lock; i.e., to become a writer without letting
+     * other writers get the lock in the meantime (such as unlocking and then
+     * re-locking as a writer would do). The block takes an "upgrade mode
+     * token" argument, which can be transformed into a "write mode token" by
+     * calling upgrade().
+     *
+     * An upgradeable reader is treated like a waiting writer: it shares the
+     * lock with the readers that were already inside, but readers, writers
+     * and upgradeable readers that arrive later will block until it finishes
+     * or upgrades. Two upgradeable readers can hence never deadlock by both
+     * trying to upgrade. The write mode token may also be downgraded again.
+     *
+     * # Example
+     *
+     * ```rust
+     * do lock.read_upgradeable |upgrade_token| {
+     *     do upgrade_token.read {
+     *         ... shared access ...
+     *     }
+     *     let write_token = lock.upgrade(upgrade_token);
+     *     do write_token.write {
+     *         ... exclusive access ...
+     *     }
+     * }
+     * ```
+     */
+    pub fn read_upgradeable<U>(&self, blk: &fn(v: RWLockUpgradeMode) -> U) -> U {
+        // Like write_downgrade, the exit path depends on what the caller did
+        // with the token. Whether it upgraded is kept here rather than in the
+        // shared state, because once it has, another upgradeable reader can
+        // get in and upgrade before this one is done.
+        let upgraded = Cell::new_empty();
+        do task::unkillable {
+            (&self.order_lock).acquire();
+            unsafe {
+                let state = &mut *self.state.get();
+                let old_count = state.read_count.fetch_add(1, atomics::Acquire);
+                if old_count == 0 {
+                    (&self.access_lock).acquire();
+                    state.read_mode = true;
+                }
+            }
+            do (|| {
+                do task::rekillable {
+                    blk(RWLockUpgradeMode { lock: self, upgraded: &upgraded,
+                                            token: NonCopyable::new() })
+                }
+            }).finally {
+                let state = unsafe { &mut *self.state.get() };
+                // If we never upgraded, we still hold the order lock.
+                if upgraded.is_empty() {
+                    (&self.order_lock).release();
+                }
+                let writer_or_last_reader;
+                if state.read_mode {
+                    // Releasing from read mode, either because we never
+                    // upgraded or because we upgraded and then downgraded.
+                    let old_count = state.read_count.fetch_sub(1, atomics::Release);
+                    assert!(old_count > 0);
+                    writer_or_last_reader = old_count == 1;
+                    if writer_or_last_reader {
+                        state.read_mode = false;
+                    }
+                } else {
+                    // Releasing from write mode after upgrading.
+                    writer_or_last_reader = true;
+                }
+                if writer_or_last_reader {
+                    (&self.access_lock).release();
+                }
+            }
+        }
+    }
+
+    /// To be called inside of the read_upgradeable block.
+    pub fn upgrade<'a>(&self, token: RWLockUpgradeMode<'a>)
+                       -> RWLockWriteMode<'a> {
+        if !borrow::ref_eq(self, token.lock) {
+            fail2!("Can't upgrade() with a different rwlock's upgrade_mode!");
+        }
+        unsafe {
+            do task::unkillable {
+                let state = &mut *self.state.get();
+                assert!(state.read_mode);
+                let old_count = state.read_count.fetch_sub(1, atomics::Release);
+                assert!(old_count > 0);
+                if old_count == 1 {
+                    // We were the only reader; the "reader cloud" access lock
+                    // is already ours, so keep it.
+                    state.read_mode = false;
+                } else {
+                    // Wait for the other readers to drain. Nobody can cut in
+                    // line, because every path to the access lock goes
+                    // through the order lock, which we are holding.
+                    (&self.access_lock).acquire();
+                }
+                token.upgraded.put_back(());
+                // Now that we are the writer, behave like write() does and
+                // let others queue up behind us.
+                (&self.order_lock).release();
+            }
+        }
+        RWLockWriteMode { lock: token.lock, token: NonCopyable::new() }
+    }
 }
 
 /// The "write permission" token used for rwlock.write_downgrade().
@@ -685,6 +918,16 @@ impl<'self> RWLockReadMode<'self> {
     pub fn read<U>(&self, blk: &fn() -> U) -> U { blk() }
 }
 
+/// The "upgradeable read permission" token used for rwlock.read_upgradeable().
+pub struct RWLockUpgradeMode<'self> { priv lock: &'self RWLock,
+                                      priv upgraded: &'self Cell<()>,
+                                      priv token: NonCopyable }
+
+impl<'self> RWLockUpgradeMode<'self> {
+    /// Access the pre-upgrade rwlock in read mode.
+    pub fn read<U>(&self, blk: &fn() -> U) -> U { blk() }
+}
+
 /****************************************************************************
  * Tests
  ****************************************************************************/
@@ -710,6 +953,16 @@ mod tests {
         s.acquire();
     }
     #[test]
+    fn test_sem_try_acquire() {
+        let s = Semaphore::new(1);
+        assert!(s.try_acquire());
+        assert!(!s.try_acquire());
+        assert!(s.try_access(|| ()).is_none());
+        s.release();
+        assert_eq!(s.try_access(|| 1), Some(1));
+        assert!(s.try_acquire());
+    }
+    #[test]
     fn test_sem_basic() {
         let s = Semaphore::new(1);
         do s.access { }
@@ -1056,7 +1309,7 @@ mod tests {
      * Reader/writer lock tests
      ************************************************************************/
     #[cfg(test)]
-    pub enum RWLockMode { Read, Write, Downgrade, DowngradeRead }
+    pub enum RWLockMode { Read, Write, Downgrade, DowngradeRead, UpgradeRead, Upgrade }
     #[cfg(test)]
     fn lock_rwlock_in_mode(x: &RWLock, mode: RWLockMode, blk: &fn()) {
         match mode {
@@ -1071,6 +1324,15 @@ mod tests {
                     let mode = x.downgrade(mode);
                     do mode.read { blk() };
                 },
+            UpgradeRead =>
+                do x.read_upgradeable |mode| {
+                    do mode.read { blk() };
+                },
+            Upgrade =>
+                do x.read_upgradeable |mode| {
+                    let mode = x.upgrade(mode);
+                    do mode.write { blk() };
+                },
         }
     }
     #[cfg(test)]
@@ -1310,6 +1572,140 @@ mod tests {
         rwlock_kill_helper(Downgrade, DowngradeRead);
         rwlock_kill_helper(Downgrade, DowngradeRead);
     }
+    #[test]
+    fn test_rwlock_upgrade() {
+        test_rwlock_exclusion(&RWLock::new(), Upgrade, Read);
+        test_rwlock_exclusion(&RWLock::new(), Read, Upgrade);
+        test_rwlock_exclusion(&RWLock::new(), Upgrade, Write);
+        test_rwlock_exclusion(&RWLock::new(), Upgrade, Upgrade);
+        test_rwlock_exclusion(&RWLock::new(), UpgradeRead, Write);
+        test_rwlock_exclusion(&RWLock::new(), Write, UpgradeRead);
+        // A reader that is already inside can share with an upgradeable one.
+        test_rwlock_handshake(&RWLock::new(), Read, UpgradeRead, false);
+        // Upgrading and then downgrading gets back to a readable lock.
+        let x = RWLock::new();
+        do x.read_upgradeable |upgrade_token| {
+            let write_token = x.upgrade(upgrade_token);
+            let read_token = x.downgrade(write_token);
+            do read_token.read { }
+        }
+        test_rwlock_exclusion(&x, Upgrade, Upgrade);
+        test_rwlock_handshake(&x, Read, Read, false);
+    }
+    #[test]
+    fn test_rwlock_upgrade_waits_for_readers() {
+        // The upgrade must wait for a reader which got in first, and no
+        // writer may slip in between the read and the write.
+        let x = RWLock::new();
+        let x2 = x.clone();
+        let (p, c) = comm::stream();
+        let (p2, c2) = comm::stream();
+        do task::spawn {
+            do x2.read {
+                c.send(());
+                let _ = p2.recv();
+                task::deschedule();
+            }
+        }
+        let _ = p.recv();
+        let (p3, c3) = comm::stream();
+        let c3 = comm::SharedChan::new(c3);
+        let writer = Cell::new((x.clone(), c3.clone()));
+        do x.read_upgradeable |upgrade_token| {
+            let (x3, c4) = writer.take();
+            do task::spawn {
+                // Will queue up behind the upgradeable reader.
+                do x3.write { c4.send(1); }
+            }
+            c2.send(());
+            let write_token = x.upgrade(upgrade_token);
+            do write_token.write { c3.send(0); }
+        }
+        assert_eq!(p3.recv(), 0);
+        assert_eq!(p3.recv(), 1);
+    }
+    #[test]
+    fn test_rwlock_concurrent_upgraders() {
+        // A second upgradeable reader gets in once the first has upgraded,
+        // and must be able to upgrade in turn after the first downgrades.
+        let x = RWLock::new();
+        let (p, c) = comm::stream();
+        let (p2, c2) = comm::stream();
+        let upgrader = Cell::new((x.clone(), c, c2));
+        do x.read_upgradeable |upgrade_token| {
+            let write_token = x.upgrade(upgrade_token);
+            let (x2, c, c2) = upgrader.take();
+            do task::spawn {
+                do x2.read_upgradeable |upgrade_token| {
+                    c.send(());
+                    let write_token = x2.upgrade(upgrade_token);
+                    do write_token.write { c2.send(()); }
+                }
+            }
+            // Let the second upgrader queue up behind this one
+            do 5.times { task::deschedule(); }
+            let read_token = x.downgrade(write_token);
+            do read_token.read { let _ = p.recv(); }
+        }
+        let _ = p2.recv();
+        // Neither of them may have left the lock held
+        do x.write { }
+        do x.read_upgradeable |upgrade_token| {
+            do x.upgrade(upgrade_token).write { }
+        }
+    }
+
+    #[test]
+    fn test_rwlock_kill_upgrader() {
+        rwlock_kill_helper(Upgrade, Read);
+        rwlock_kill_helper(Read, Upgrade);
+        rwlock_kill_helper(Upgrade, Write);
+        rwlock_kill_helper(UpgradeRead, Write);
+        rwlock_kill_helper(Write, UpgradeRead);
+        rwlock_kill_helper(UpgradeRead, Upgrade);
+    }
+    #[test]
+    fn test_mutex_try_lock() {
+        let m = Mutex::new();
+        assert_eq!(m.try_lock(|| 1), Some(1));
+        do m.lock {
+            assert!(m.try_lock(|| ()).is_none());
+        }
+        assert!(m.try_lock(|| ()).is_some());
+    }
+    #[test]
+    fn test_rwlock_try_lock() {
+        let x = RWLock::new();
+        do x.read {
+            assert!(x.try_read(|| ()).is_some());
+            assert!(x.try_write(|| ()).is_none());
+        }
+        do x.write {
+            assert!(x.try_read(|| ()).is_none());
+            assert!(x.try_write(|| ()).is_none());
+        }
+        do x.read_upgradeable |_| {
+            // An upgradeable reader keeps new readers out, like a writer.
+            assert!(x.try_read(|| ()).is_none());
+        }
+        assert!(x.try_write(|| ()).is_some());
+        // Readers which got in with try_read must let writers in afterwards.
+        do x.try_read { do x.try_read { } };
+        test_rwlock_exclusion(&x, Read, Write);
+    }
+    #[test] #[should_fail]
+    fn test_rwlock_upgrade_cant_swap() {
+        // Tests that you can't upgrade with a different rwlock's token.
+        let x = RWLock::new();
+        let y = RWLock::new();
+        do x.read_upgradeable |xread| {
+            let mut xopt = Some(xread);
+            do y.read_upgradeable |_yread| {
+                y.upgrade(xopt.take_unwrap());
+                error2!("oops, y.upgrade(x) should have failed!");
+            }
+        }
+    }
     #[test] #[should_fail]
     fn test_rwlock_downgrade_cant_swap() {
         // Tests that you can't downgrade with a different rwlock's token.
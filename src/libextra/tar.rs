@@ -0,0 +1,796 @@
+// Copyright 2013 The Rust Project Developers. See the COPYRIGHT
+// file at the top-level directory of this distribution and at
+// http://rust-lang.org/COPYRIGHT.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+/*!
+ * Reading and writing tar archives
+ *
+ * Only the POSIX ustar format is supported. That covers regular files,
+ * directories and symbolic links whose paths fit in 255 bytes. Archives
+ * written by GNU tar can be read as long as they don't use its long name
+ * extensions; entries of any other type are listed but not unpacked.
+ *
+ * # Example
+ *
+ * ~~~ {.rust}
+ * extern mod extra;
+ * use extra::tar;
+ * use std::io;
+ *
+ * // Archive everything except the build directory
+ * let wr = io::file_writer(&Path("foo.tar"), [io::Create, io::Truncate]).unwrap();
+ * let mut builder = tar::Builder::new(wr);
+ * builder.append_dir_all("foo", &Path("src/foo"),
+ *                        |p| p.components == ~[~"build"]).unwrap();
+ * builder.finish();
+ *
+ * // And unpack it again somewhere else
+ * let rdr = io::file_reader(&Path("foo.tar")).unwrap();
+ * tar::Archive::new(rdr).unpack(&Path("/tmp")).unwrap();
+ * ~~~
+ */
+
+use std::io::{Reader, ReaderUtil, Writer};
+use std::cmp;
+use std::io;
+use std::libc;
+use std::os;
+use std::str;
+use std::uint;
+use std::vec;
+
+use sort;
+
+static BLOCK_SIZE: uint = 512;
+// The most that's read from the archive at once
+static READ_CHUNK: uint = 64 * 1024;
+
+/// The kind of file an archive entry describes
+#[deriving(Clone, Eq)]
+pub enum EntryType {
+    RegularFile,
+    Directory,
+    Symlink,
+    /// Any other type, identified by its type flag
+    Other(u8),
+}
+
+impl EntryType {
+    fn from_byte(b: u8) -> EntryType {
+        match b as char {
+            '0' | '\0' => RegularFile,
+            '5' => Directory,
+            '2' => Symlink,
+            _ => Other(b)
+        }
+    }
+
+    fn to_byte(&self) -> u8 {
+        match *self {
+            RegularFile => '0' as u8,
+            Directory => '5' as u8,
+            Symlink => '2' as u8,
+            Other(b) => b
+        }
+    }
+}
+
+/// The metadata stored for each archive entry
+#[deriving(Clone, Eq)]
+pub struct Header {
+    /// The path of the entry, relative to the root of the archive
+    path: ~str,
+    /// Permission bits
+    mode: uint,
+    uid: uint,
+    gid: uint,
+    /// Length of the entry's data in bytes
+    size: u64,
+    /// Modification time in seconds since the epoch
+    mtime: i64,
+    kind: EntryType,
+    /// The target of a symbolic link
+    link_name: ~str,
+}
+
+impl Header {
+    /// Creates a header for an entry of the given type at `path`, owned by
+    /// root, with the usual permissions for its type and no data.
+    pub fn new(path: &str, kind: EntryType) -> Header {
+        Header {
+            path: path.to_owned(),
+            mode: match kind { Directory => 0x1ed, _ => 0x1a4 }, // 755, 644
+            uid: 0,
+            gid: 0,
+            size: 0,
+            mtime: 0,
+            kind: kind,
+            link_name: ~""
+        }
+    }
+
+    fn parse(block: &[u8]) -> Result<Header, ~str> {
+        let stored = match parse_octal(block.slice(148, 156)) {
+            Ok(n) => n,
+            Err(e) => return Err(e)
+        };
+        if stored != checksum(block) {
+            return Err(~"tar header checksum mismatch");
+        }
+
+        let mut path = match field_str(block.slice(0, 100)) {
+            Ok(s) => s,
+            Err(e) => return Err(e)
+        };
+        // Pre-POSIX archives have no magic, and reuse the prefix field
+        if block.slice(257, 262) == bytes!("ustar") {
+            match field_str(block.slice(345, 500)) {
+                Ok(ref prefix) if !prefix.is_empty() => {
+                    path = format!("{}/{}", *prefix, path);
+                }
+                Ok(_) => {}
+                Err(e) => return Err(e)
+            }
+        }
+        let link_name = match field_str(block.slice(157, 257)) {
+            Ok(s) => s,
+            Err(e) => return Err(e)
+        };
+
+        let mut fields = ~[];
+        for &(start, end) in [(100u, 108u), (108, 116), (116, 124),
+                              (124, 136), (136, 148)].iter() {
+            match parse_octal(block.slice(start, end)) {
+                Ok(n) => fields.push(n),
+                Err(e) => return Err(e)
+            }
+        }
+
+        Ok(Header {
+            path: path,
+            mode: fields[0] as uint,
+            uid: fields[1] as uint,
+            gid: fields[2] as uint,
+            size: fields[3],
+            mtime: fields[4] as i64,
+            kind: EntryType::from_byte(block[156]),
+            link_name: link_name
+        })
+    }
+
+    fn to_block(&self) -> Result<~[u8], ~str> {
+        let (prefix, name) = match split_path(self.path) {
+            Some(parts) => parts,
+            None => return Err(format!("path is too long for a tar header: {}",
+                                       self.path))
+        };
+        if self.link_name.len() > 100 {
+            return Err(format!("link target is too long for a tar header: {}",
+                               self.link_name));
+        }
+
+        let mut block = vec::from_elem(BLOCK_SIZE, 0u8);
+        write_str(block.mut_slice(0, 100), name);
+        let mtime = if self.mtime < 0 { 0 } else { self.mtime as u64 };
+        let fits = write_octal(block.mut_slice(100, 108), (self.mode & 0xfff) as u64)
+            && write_octal(block.mut_slice(108, 116), self.uid as u64)
+            && write_octal(block.mut_slice(116, 124), self.gid as u64)
+            && write_octal(block.mut_slice(124, 136), self.size)
+            && write_octal(block.mut_slice(136, 148), mtime);
+        if !fits {
+            return Err(format!("metadata of {} doesn't fit in a tar header",
+                               self.path));
+        }
+        block[156] = self.kind.to_byte();
+        write_str(block.mut_slice(157, 257), self.link_name);
+        write_str(block.mut_slice(257, 263), "ustar");
+        write_str(block.mut_slice(263, 265), "00");
+        write_str(block.mut_slice(345, 500), prefix);
+
+        // Six digits, a NUL and a space, as traditional tar writes it
+        let sum = checksum(block);
+        write_octal(block.mut_slice(148, 155), sum);
+        block[155] = ' ' as u8;
+        Ok(block)
+    }
+}
+
+/// A single archive entry and its contents
+pub struct Entry {
+    header: Header,
+    data: ~[u8],
+}
+
+impl Entry {
+    /// Creates the file, directory or symbolic link described by this entry
+    /// inside `dst`, creating any missing parent directories. Permission
+    /// bits are restored; ownership and modification times are not.
+    /// Entries of other types are skipped.
+    ///
+    /// Entries are never written through a symbolic link, so a link
+    /// unpacked earlier can't lead later entries outside of `dst`.
+    pub fn unpack_in(&self, dst: &Path) -> Result<(), ~str> {
+        let rel = match safe_path(self.header.path) {
+            Some(rel) => rel,
+            None => return Err(format!("refusing to unpack {} outside of {}",
+                                       self.header.path, dst.to_str()))
+        };
+        match symlink_on_path(dst, &rel) {
+            Some(link) => return Err(format!("refusing to unpack {} through the \
+                                              symbolic link {}",
+                                             self.header.path, link.to_str())),
+            None => ()
+        }
+        let target = dst.push_rel(&rel);
+
+        match self.header.kind {
+            Directory => {
+                if !make_dirs(&target) {
+                    return Err(format!("couldn't create directory {}",
+                                       target.to_str()));
+                }
+            }
+            RegularFile => {
+                if !make_dirs(&target.pop()) {
+                    return Err(format!("couldn't create directory {}",
+                                       target.pop().to_str()));
+                }
+                match io::file_writer(&target, [io::Create, io::Truncate]) {
+                    Ok(wr) => wr.write(self.data),
+                    Err(e) => return Err(e)
+                }
+            }
+            Symlink => {
+                if !make_dirs(&target.pop()) {
+                    return Err(format!("couldn't create directory {}",
+                                       target.pop().to_str()));
+                }
+                if !os::symlink(&Path(self.header.link_name), &target) {
+                    return Err(format!("couldn't create symlink {}",
+                                       target.to_str()));
+                }
+                return Ok(());
+            }
+            Other(_) => return Ok(())
+        }
+
+        if set_mode(&target, self.header.mode) {
+            Ok(())
+        } else {
+            Err(format!("couldn't set the permissions of {}", target.to_str()))
+        }
+    }
+}
+
+/// Reads entries from a tar archive
+pub struct Archive {
+    priv rdr: @Reader,
+    priv done: bool,
+}
+
+impl Archive {
+    pub fn new(rdr: @Reader) -> Archive {
+        Archive { rdr: rdr, done: false }
+    }
+
+    /// Reads the next entry, or returns None once the end of the archive
+    /// has been reached. After an error no further entries are read.
+    pub fn next_entry(&mut self) -> Result<Option<Entry>, ~str> {
+        if self.done {
+            return Ok(None);
+        }
+
+        let block = read_exact(self.rdr, BLOCK_SIZE);
+        // Some writers leave off the zero blocks that mark the end
+        if block.is_empty() || block.iter().all(|&b| b == 0) {
+            self.done = true;
+            return Ok(None);
+        }
+        if block.len() < BLOCK_SIZE {
+            return self.error(~"truncated tar header");
+        }
+        let header = match Header::parse(block) {
+            Ok(header) => header,
+            Err(e) => return self.error(e)
+        };
+
+        if header.size > uint::max_value as u64 {
+            return self.error(format!("tar entry {} is too large", header.path));
+        }
+        let size = header.size as uint;
+        let data = read_exact(self.rdr, size);
+        let pad = padding(size);
+        if data.len() < size || read_exact(self.rdr, pad).len() < pad {
+            return self.error(format!("truncated tar entry {}", header.path));
+        }
+        Ok(Some(Entry { header: header, data: data }))
+    }
+
+    /// Unpacks every entry of the archive into `dst`. See
+    /// `Entry::unpack_in` for what is restored.
+    pub fn unpack(&mut self, dst: &Path) -> Result<(), ~str> {
+        // Directory permissions are applied last, so that a read-only
+        // directory can still be filled in.
+        let mut dirs = ~[];
+        loop {
+            match self.next_entry() {
+                Ok(Some(entry)) => {
+                    let mut entry = entry;
+                    if entry.header.kind == Directory {
+                        let mode = entry.header.mode;
+                        entry.header.mode = 0x1c0 | mode; // u+rwx
+                        dirs.push((entry.header.clone(), mode));
+                    }
+                    match entry.unpack_in(dst) {
+                        Ok(()) => {}
+                        Err(e) => return Err(e)
+                    }
+                }
+                Ok(None) => break,
+                Err(e) => return Err(e)
+            }
+        }
+        for &(ref header, mode) in dirs.iter().invert() {
+            let target = dst.push_rel(&Path(header.path));
+            if !set_mode(&target, mode) {
+                return Err(format!("couldn't set the permissions of {}",
+                                   target.to_str()));
+            }
+        }
+        Ok(())
+    }
+
+    fn error<T>(&mut self, msg: ~str) -> Result<T, ~str> {
+        self.done = true;
+        Err(msg)
+    }
+}
+
+impl Iterator<Result<Entry, ~str>> for Archive {
+    fn next(&mut self) -> Option<Result<Entry, ~str>> {
+        match self.next_entry() {
+            Ok(Some(entry)) => Some(Ok(entry)),
+            Ok(None) => None,
+            Err(e) => Some(Err(e))
+        }
+    }
+}
+
+/// Writes a tar archive
+pub struct Builder {
+    priv wr: @Writer,
+}
+
+impl Builder {
+    pub fn new(wr: @Writer) -> Builder {
+        Builder { wr: wr }
+    }
+
+    /// Appends an entry with the given contents. The size recorded in the
+    /// archive is the length of `data`, whatever `header.size` says.
+    pub fn append(&mut self, header: &Header, data: &[u8]) -> Result<(), ~str> {
+        let mut header = header.clone();
+        header.size = data.len() as u64;
+        let block = match header.to_block() {
+            Ok(block) => block,
+            Err(e) => return Err(e)
+        };
+        self.wr.write(block);
+        self.wr.write(data);
+        self.wr.write(vec::from_elem(padding(data.len()), 0u8));
+        Ok(())
+    }
+
+    /// Appends the file, directory or symbolic link at `path` under the
+    /// name `name`. Directories are added without their contents. The
+    /// entry is owned by root, whoever owns `path`.
+    pub fn append_path(&mut self, name: &str, path: &Path) -> Result<(), ~str> {
+        let mut header;
+        let mut data = ~[];
+        if os::path_is_symlink(path) {
+            header = Header::new(name, Symlink);
+            header.mode = 0x1ff; // 777
+            header.link_name = match link_target(path) {
+                Some(target) => target.to_str(),
+                None => return Err(format!("couldn't read symlink {}",
+                                           path.to_str()))
+            };
+        } else if os::path_is_dir(path) {
+            header = Header::new(name, Directory);
+        } else {
+            header = Header::new(name, RegularFile);
+            data = match io::read_whole_file(path) {
+                Ok(data) => data,
+                Err(e) => return Err(e)
+            };
+        }
+        if !os::path_is_symlink(path) {
+            match path.get_mode() {
+                Some(mode) => header.mode = mode & 0xfff,
+                None => return Err(format!("couldn't stat {}", path.to_str()))
+            }
+        }
+        for &(secs, _) in path.get_mtime().iter() {
+            header.mtime = secs;
+        }
+        self.append(&header, data)
+    }
+
+    /// Appends the directory `src` under the name `name`, followed by
+    /// everything inside it, in sorted order. If `name` is empty the
+    /// contents are placed at the root of the archive. Anything for which
+    /// `ignore` returns true is left out, along with its contents; it is
+    /// passed the path relative to `src`.
+    pub fn append_dir_all(&mut self, name: &str, src: &Path,
+                          ignore: &fn(&Path) -> bool) -> Result<(), ~str> {
+        if !name.is_empty() {
+            match self.append_path(name, src) {
+                Ok(()) => {}
+                Err(e) => return Err(e)
+            }
+        }
+        self.append_children(name, src, &Path(""), ignore)
+    }
+
+    fn append_children(&mut self, name: &str, src: &Path, rel: &Path,
+                       ignore: &fn(&Path) -> bool) -> Result<(), ~str> {
+        let dir = src.push_rel(rel);
+        let mut children = os::list_dir(&dir);
+        sort::tim_sort(children);
+        for child in children.iter() {
+            let child_rel = rel.push(*child);
+            if ignore(&child_rel) {
+                continue;
+            }
+            let child_name = if name.is_empty() {
+                child_rel.to_str()
+            } else {
+                format!("{}/{}", name, child_rel.to_str())
+            };
+            let child_path = src.push_rel(&child_rel);
+            match self.append_path(child_name, &child_path) {
+                Ok(()) => {}
+                Err(e) => return Err(e)
+            }
+            if os::path_is_dir(&child_path) && !os::path_is_symlink(&child_path) {
+                match self.append_children(name, src, &child_rel, |p| ignore(p)) {
+                    Ok(()) => {}
+                    Err(e) => return Err(e)
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Writes the end-of-archive marker. Nothing should be appended after
+    /// this.
+    pub fn finish(&mut self) {
+        self.wr.write(vec::from_elem(2 * BLOCK_SIZE, 0u8));
+    }
+}
+
+// Reads `len` bytes, or fewer if the input ends first. `len` comes from
+// the archive, which may be lying about it, so memory is only taken for the
+// data as it turns up rather than all at once
+fn read_exact(rdr: @Reader, len: uint) -> ~[u8] {
+    let mut buf = vec::with_capacity(cmp::min(len, READ_CHUNK));
+    while buf.len() < len {
+        let chunk = rdr.read_bytes(cmp::min(len - buf.len(), READ_CHUNK));
+        if chunk.is_empty() {
+            break;
+        }
+        buf.push_all_move(chunk);
+    }
+    buf
+}
+
+fn padding(size: uint) -> uint {
+    (BLOCK_SIZE - size % BLOCK_SIZE) % BLOCK_SIZE
+}
+
+/// Sums the header bytes, counting the checksum field itself as spaces
+fn checksum(block: &[u8]) -> u64 {
+    let mut sum = 0u64;
+    for (i, &b) in block.iter().enumerate() {
+        sum += if i >= 148 && i < 156 { ' ' as u64 } else { b as u64 };
+    }
+    sum
+}
+
+fn parse_octal(field: &[u8]) -> Result<u64, ~str> {
+    let mut n = 0u64;
+    let mut seen_digit = false;
+    for &b in field.iter() {
+        match b as char {
+            '0' .. '7' => {
+                n = n * 8 + (b - '0' as u8) as u64;
+                seen_digit = true;
+            }
+            ' ' | '\0' if !seen_digit => {}
+            ' ' | '\0' => break,
+            _ => return Err(~"invalid number in tar header")
+        }
+    }
+    Ok(n)
+}
+
+/// Writes `n` as zero-padded octal followed by a NUL, returning false if it
+/// doesn't fit.
+fn write_octal(field: &mut [u8], n: u64) -> bool {
+    let digits = field.len() - 1;
+    let mut n = n;
+    for i in range(0, digits).invert() {
+        field[i] = '0' as u8 + (n & 7) as u8;
+        n >>= 3;
+    }
+    field[digits] = 0;
+    n == 0
+}
+
+fn field_str(field: &[u8]) -> Result<~str, ~str> {
+    let len = field.iter().position(|&b| b == 0).unwrap_or(field.len());
+    match str::from_utf8_opt(field.slice_to(len)) {
+        Some(s) => Ok(s),
+        None => Err(~"tar header contains a path that isn't valid UTF-8")
+    }
+}
+
+fn write_str(field: &mut [u8], s: &str) {
+    for (dst, src) in field.mut_iter().zip(s.as_bytes().iter()) {
+        *dst = *src;
+    }
+}
+
+/// Splits a path into the ustar prefix and name fields. Paths longer than
+/// 100 bytes have to be split at a separator.
+fn split_path<'a>(path: &'a str) -> Option<(&'a str, &'a str)> {
+    if path.len() <= 100 {
+        return Some(("", path));
+    }
+    let bytes = path.as_bytes();
+    for i in range(1, bytes.len()) {
+        if bytes[i] == '/' as u8 && i <= 155 && bytes.len() - i - 1 <= 100 {
+            return Some((path.slice_to(i), path.slice_from(i + 1)));
+        }
+    }
+    None
+}
+
+/// Turns an entry's path into a relative path, or None if it would escape
+/// the directory being unpacked into.
+fn safe_path(path: &str) -> Option<Path> {
+    let p = Path(path);
+    if p.is_absolute() || p.components.iter().any(|c| ".." == *c) {
+        None
+    } else {
+        Some(p)
+    }
+}
+
+/// The first of `rel`'s components, within `dst`, that is a symbolic link,
+/// including `rel` itself.
+fn symlink_on_path(dst: &Path, rel: &Path) -> Option<Path> {
+    let mut p = dst.clone();
+    for c in rel.components.iter() {
+        p = p.push(*c);
+        if os::path_is_symlink(&p) {
+            return Some(p);
+        }
+    }
+    None
+}
+
+#[cfg(unix)]
+fn link_target(p: &Path) -> Option<Path> {
+    os::readlink(p)
+}
+
+// Where a Windows link points can't be read, so it can't be archived
+#[cfg(windows)]
+fn link_target(_p: &Path) -> Option<Path> {
+    None
+}
+
+fn make_dirs(p: &Path) -> bool {
+    os::path_is_dir(p) || os::mkdir_recursive(p, 0x1ed) // 755
+}
+
+#[cfg(windows)]
+fn set_mode(p: &Path, mode: uint) -> bool {
+    #[fixed_stack_segment]; #[inline(never)];
+    unsafe {
+        do p.to_str().with_c_str |buf| {
+            libc::chmod(buf, (mode & 0xfff) as libc::c_int) == 0
+        }
+    }
+}
+
+#[cfg(not(windows))]
+fn set_mode(p: &Path, mode: uint) -> bool {
+    #[fixed_stack_segment]; #[inline(never)];
+    unsafe {
+        do p.to_str().with_c_str |buf| {
+            libc::chmod(buf, (mode & 0xfff) as libc::mode_t) == 0
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use super::{parse_octal, split_path};
+
+    use tempfile::TempDir;
+
+    use std::io::WriterUtil;
+    use std::io;
+    use std::os;
+    use std::vec;
+
+    fn archive_bytes(f: &fn(&mut Builder)) -> ~[u8] {
+        do io::with_bytes_writer |wr| {
+            let mut builder = Builder::new(wr);
+            f(&mut builder);
+            builder.finish();
+        }
+    }
+
+    fn read_all(bytes: &[u8]) -> ~[Entry] {
+        do io::with_bytes_reader(bytes) |rdr| {
+            Archive::new(rdr).map(|e| e.unwrap()).collect()
+        }
+    }
+
+    #[test]
+    fn test_octal() {
+        assert_eq!(parse_octal(bytes!("0000644\x00")), Ok(0x1a4));
+        assert_eq!(parse_octal(bytes!("   755 \x00")), Ok(0x1ed));
+        assert_eq!(parse_octal(bytes!("\x00\x00\x00\x00")), Ok(0));
+        assert!(parse_octal(bytes!("0000x44\x00")).is_err());
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let bytes = do archive_bytes |b| {
+            b.append(&Header::new("foo", Directory), []).unwrap();
+            let mut header = Header::new("foo/bar.rs", RegularFile);
+            header.mode = 0x1ed;
+            header.mtime = 1234567890;
+            b.append(&header, bytes!("fn main() {}\n")).unwrap();
+            let mut link = Header::new("foo/baz.rs", Symlink);
+            link.link_name = ~"bar.rs";
+            b.append(&link, []).unwrap();
+        };
+        assert_eq!(bytes.len() % 512, 0);
+
+        let entries = read_all(bytes);
+        assert_eq!(entries.len(), 3);
+        assert_eq!(entries[0].header.path, ~"foo");
+        assert_eq!(entries[0].header.kind, Directory);
+        assert_eq!(entries[1].header.path, ~"foo/bar.rs");
+        assert_eq!(entries[1].header.kind, RegularFile);
+        assert_eq!(entries[1].header.mode, 0x1ed);
+        assert_eq!(entries[1].header.size, 13);
+        assert_eq!(entries[1].header.mtime, 1234567890);
+        assert_eq!(entries[1].data, bytes!("fn main() {}\n").to_owned());
+        assert_eq!(entries[2].header.kind, Symlink);
+        assert_eq!(entries[2].header.link_name, ~"bar.rs");
+    }
+
+    #[test]
+    fn test_long_path() {
+        let long = format!("{}/{}", "a".repeat(90), "b".repeat(90));
+        assert!(split_path(long).is_some());
+        let bytes = do archive_bytes |b| {
+            b.append(&Header::new(long, RegularFile), bytes!("x")).unwrap();
+        };
+        assert_eq!(read_all(bytes)[0].header.path, long.clone());
+
+        let too_long = "c".repeat(101);
+        do archive_bytes |b| {
+            assert!(b.append(&Header::new(too_long, RegularFile), []).is_err());
+        };
+    }
+
+    #[test]
+    fn test_bad_checksum() {
+        let mut bytes = do archive_bytes |b| {
+            b.append(&Header::new("foo", RegularFile), bytes!("foo")).unwrap();
+        };
+        bytes[0] = 'g' as u8;
+        do io::with_bytes_reader(bytes) |rdr| {
+            let mut archive = Archive::new(rdr);
+            assert!(archive.next_entry().is_err());
+            assert!(archive.next().is_none());
+        }
+    }
+
+    #[test]
+    fn test_truncated() {
+        let bytes = do archive_bytes |b| {
+            b.append(&Header::new("foo", RegularFile),
+                     vec::from_elem(1000, 1u8)).unwrap();
+        };
+        do io::with_bytes_reader(bytes.slice_to(700)) |rdr| {
+            assert!(Archive::new(rdr).next_entry().is_err());
+        }
+    }
+
+    #[test]
+    fn test_size_beyond_the_data() {
+        // The data isn't allocated for up front, so this fails for being
+        // truncated rather than for running out of memory
+        let mut header = Header::new("big", RegularFile);
+        header.size = 0x1ffffffff;
+        let bytes = header.to_block().unwrap() + vec::from_elem(1000, 1u8);
+        do io::with_bytes_reader(bytes) |rdr| {
+            assert!(Archive::new(rdr).next_entry().is_err());
+        }
+    }
+
+    #[test]
+    fn test_unpack_refuses_parent_paths() {
+        let dir = TempDir::new("tar").unwrap();
+        let bytes = do archive_bytes |b| {
+            b.append(&Header::new("../evil", RegularFile), bytes!("x")).unwrap();
+        };
+        do io::with_bytes_reader(bytes) |rdr| {
+            assert!(Archive::new(rdr).unpack(dir.path()).is_err());
+        }
+        assert!(!os::path_exists(&dir.path().pop().push("evil")));
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn test_unpack_refuses_writing_through_symlinks() {
+        let dir = TempDir::new("tar").unwrap();
+        let outside = TempDir::new("tar_outside").unwrap();
+        let bytes = do archive_bytes |b| {
+            let mut link = Header::new("a", Symlink);
+            link.link_name = outside.path().to_str();
+            b.append(&link, []).unwrap();
+            b.append(&Header::new("a/passwd", RegularFile), bytes!("x")).unwrap();
+        };
+        do io::with_bytes_reader(bytes) |rdr| {
+            assert!(Archive::new(rdr).unpack(dir.path()).is_err());
+        }
+        assert!(os::path_is_symlink(&dir.path().push("a")));
+        assert!(!os::path_exists(&outside.path().push("passwd")));
+    }
+
+    #[test]
+    fn test_pack_and_unpack_dir() {
+        let src_dir = TempDir::new("tar_src").unwrap();
+        let src = src_dir.path();
+        assert!(os::mkdir_recursive(&src.push_many([~"src", ~"build"]), 0x1ed));
+        io::file_writer(&src.push_many([~"src", ~"lib.rs"]),
+                        [io::Create]).unwrap().write_str("pub fn f() {}");
+        io::file_writer(&src.push_many([~"src", ~"build", ~"lib.o"]),
+                        [io::Create]).unwrap().write_str("junk");
+        io::file_writer(&src.push("run.sh"),
+                        [io::Create]).unwrap().write_str("#!/bin/sh");
+        assert!(super::set_mode(&src.push("run.sh"), 0x1ed));
+
+        let bytes = do archive_bytes |b| {
+            b.append_dir_all("pkg", src, |p| p.filename() == Some("build")).unwrap();
+        };
+        let names = read_all(bytes).map(|e| e.header.path.clone());
+        assert_eq!(names, ~[~"pkg", ~"pkg/run.sh", ~"pkg/src", ~"pkg/src/lib.rs"]);
+
+        let dst_dir = TempDir::new("tar_dst").unwrap();
+        do io::with_bytes_reader(bytes) |rdr| {
+            Archive::new(rdr).unpack(dst_dir.path()).unwrap();
+        }
+        let pkg = dst_dir.path().push("pkg");
+        assert_eq!(io::read_whole_file_str(&pkg.push_many([~"src", ~"lib.rs"])),
+                   Ok(~"pub fn f() {}"));
+        assert!(!os::path_exists(&pkg.push_many([~"src", ~"build"])));
+        assert_eq!(pkg.push("run.sh").get_mode().unwrap() & 0x1ff, 0x1ed);
+    }
+}
@@ -14,11 +14,12 @@
 /// parallelism.
 
 
-use std::comm::{Chan, GenericChan, GenericPort};
+use std::comm::{Port, Chan, GenericChan, GenericSmartChan, GenericPort};
+use std::comm::{SharedChan, SharedPort};
 use std::comm;
 use std::task::SchedMode;
 use std::task;
-use std::vec;
+use std::util;
 
 #[cfg(test)] use std::task::SingleThreaded;
 
@@ -27,17 +28,27 @@ enum Msg<T> {
     Quit
 }
 
+/// How a pool hands out jobs to its tasks
+pub enum Dispatch {
+    /// Each task gets the next job in turn, whether or not it's busy
+    RoundRobin,
+    /// The tasks share one queue, so a job goes to whichever task is free
+    /// first. Better when jobs vary in how long they take.
+    SharedQueue
+}
+
 pub struct TaskPool<T> {
     channels: ~[Chan<Msg<T>>],
     next_index: uint,
+    n_tasks: uint,
+    // Each task sends the number of its jobs that failed when it quits
+    done: Port<uint>
 }
 
 #[unsafe_destructor]
 impl<T> Drop for TaskPool<T> {
     fn drop(&mut self) {
-        for channel in self.channels.iter() {
-            channel.send(Quit);
-        }
+        self.quit();
     }
 }
 
@@ -51,19 +62,41 @@ impl<T> TaskPool<T> {
                opt_sched_mode: Option<SchedMode>,
                init_fn_factory: ~fn() -> ~fn(uint) -> T)
                -> TaskPool<T> {
+        TaskPool::with_dispatch(n_tasks, opt_sched_mode, RoundRobin, init_fn_factory)
+    }
+
+    /// Like `new`, but with jobs handed out according to `dispatch`.
+    pub fn with_dispatch(n_tasks: uint,
+                         opt_sched_mode: Option<SchedMode>,
+                         dispatch: Dispatch,
+                         init_fn_factory: ~fn() -> ~fn(uint) -> T)
+                         -> TaskPool<T> {
         assert!(n_tasks >= 1);
 
-        let channels = do vec::from_fn(n_tasks) |i| {
-            let (port, chan) = comm::stream::<Msg<T>>();
-            let init_fn = init_fn_factory();
+        let (done_port, done_chan) = comm::stream();
+        let done_chan = SharedChan::new(done_chan);
 
-            let task_body: ~fn() = || {
-                let local_data = init_fn(i);
-                loop {
-                    match port.recv() {
-                        Execute(f) => f(&local_data),
-                        Quit => break
-                    }
+        // With a shared queue every task receives from the one port
+        let (shared_port, shared_chan) = comm::stream::<Msg<T>>();
+        let shared_port = SharedPort::new(shared_port);
+        let mut shared_chan = Some(shared_chan);
+
+        let mut channels = ~[];
+        for i in range(0, n_tasks) {
+            let init_fn = init_fn_factory();
+            let done_chan = done_chan.clone();
+
+            let task_body: ~fn() = match dispatch {
+                RoundRobin => {
+                    let (port, chan) = comm::stream::<Msg<T>>();
+                    channels.push(chan);
+                    let body: ~fn() = || run_jobs(&port, init_fn(i), &done_chan);
+                    body
+                }
+                SharedQueue => {
+                    let port = shared_port.clone();
+                    let body: ~fn() = || run_jobs(&port, init_fn(i), &done_chan);
+                    body
                 }
             };
 
@@ -79,20 +112,74 @@ impl<T> TaskPool<T> {
                     task.spawn(task_body);
                 }
             }
+        }
+        match dispatch {
+            RoundRobin => (),
+            SharedQueue => channels.push(shared_chan.take_unwrap())
+        }
 
-            chan
+        return TaskPool {
+            channels: channels,
+            next_index: 0,
+            n_tasks: n_tasks,
+            done: done_port
         };
-
-        return TaskPool { channels: channels, next_index: 0 };
     }
 
     /// Executes the function `f` on a task in the pool. The function
     /// receives a reference to the local data returned by the `init_fn`.
+    ///
+    /// If `f` fails, the failure stops at the job: the task goes on to the
+    /// next one, and `shutdown` counts the failure.
     pub fn execute(&mut self, f: ~fn(&T)) {
         self.channels[self.next_index].send(Execute(f));
         self.next_index += 1;
         if self.next_index == self.channels.len() { self.next_index = 0; }
     }
+
+    /// Lets the tasks finish the jobs they've been given, then stops them.
+    /// Returns the number of jobs that failed.
+    pub fn shutdown(self) -> uint {
+        let mut this = self;
+        this.quit();
+        let mut failed = 0;
+        for _ in range(0, this.n_tasks) {
+            match this.done.try_recv() {
+                Some(n) => failed += n,
+                // A task that didn't quit took its count with it
+                None => break
+            }
+        }
+        failed
+    }
+
+    // Tells every task to quit once it's done with the jobs queued before
+    fn quit(&mut self) {
+        let channels = util::replace(&mut self.channels, ~[]);
+        if channels.is_empty() {
+            return;
+        }
+        for i in range(0, self.n_tasks) {
+            channels[i % channels.len()].try_send(Quit);
+        }
+    }
+}
+
+// The body of a pool task: runs the jobs from `port` until told to quit,
+// then reports how many failed
+fn run_jobs<T, P: GenericPort<Msg<T>>>(port: &P, local_data: T, done: &SharedChan<uint>) {
+    let mut failed = 0;
+    loop {
+        match port.try_recv() {
+            Some(Execute(f)) => {
+                if task::try_block(|| f(&local_data)).is_err() {
+                    failed += 1;
+                }
+            }
+            Some(Quit) | None => break
+        }
+    }
+    done.send(failed);
 }
 
 #[test]
@@ -106,3 +193,52 @@ fn test_task_pool() {
         pool.execute(|i| println!("Hello from thread {}!", *i));
     }
 }
+
+#[test]
+fn test_task_pool_isolates_failure() {
+    let f: ~fn() -> ~fn(uint) -> uint = || {
+        let g: ~fn(uint) -> uint = |i| i;
+        g
+    };
+    let (port, chan) = comm::stream();
+    let chan = SharedChan::new(chan);
+    let mut pool = TaskPool::new(2, None, f);
+    for i in range(0u, 6) {
+        let chan = chan.clone();
+        do pool.execute |_| {
+            if i % 3 == 0 {
+                fail2!("job {} failed", i);
+            }
+            chan.send(i);
+        }
+    }
+    assert_eq!(pool.shutdown(), 2);
+    let mut total = 0;
+    for _ in range(0, 4) {
+        total += port.recv();
+    }
+    assert_eq!(total, 1 + 2 + 4 + 5);
+}
+
+#[test]
+fn test_task_pool_shared_queue() {
+    let f: ~fn() -> ~fn(uint) -> uint = || {
+        let g: ~fn(uint) -> uint = |i| i;
+        g
+    };
+    let (port, chan) = comm::stream();
+    let chan = SharedChan::new(chan);
+    let mut pool = TaskPool::with_dispatch(4, None, SharedQueue, f);
+    for i in range(0u, 20) {
+        let chan = chan.clone();
+        do pool.execute |_| {
+            chan.send(i);
+        }
+    }
+    assert_eq!(pool.shutdown(), 0);
+    let mut total = 0;
+    for _ in range(0, 20) {
+        total += port.recv();
+    }
+    assert_eq!(total, 190);
+}
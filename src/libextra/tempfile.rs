@@ -11,6 +11,7 @@
 //! Temporary files and directories
 
 
+use std::libc;
 use std::os;
 use std::rand::Rng;
 use std::rand;
@@ -18,7 +19,8 @@ use std::rand;
 /// A wrapper for a path to temporary directory implementing automatic
 /// scope-pased deletion.
 pub struct TempDir {
-    priv path: Option<Path>
+    priv path: Option<Path>,
+    priv keep: bool
 }
 
 impl TempDir {
@@ -37,7 +39,7 @@ impl TempDir {
         for _ in range(0u, 1000) {
             let p = tmpdir.push(r.gen_ascii_str(16) + suffix);
             if os::make_dir(&p, 0x1c0) { // 700
-                return Some(TempDir { path: Some(p) });
+                return Some(TempDir { path: Some(p), keep: false });
             }
         }
         None
@@ -55,11 +57,22 @@ impl TempDir {
     /// Unwrap the wrapped `std::path::Path` from the `TempDir` wrapper.
     /// This discards the wrapper so that the automatic deletion of the
     /// temporary directory is prevented.
-    pub fn unwrap(self) -> Path {
+    pub fn into_path(self) -> Path {
         let mut tmpdir = self;
         tmpdir.path.take_unwrap()
     }
 
+    /// The same as `into_path`.
+    pub fn unwrap(self) -> Path {
+        self.into_path()
+    }
+
+    /// Leave the temporary directory in place when the wrapper is destroyed,
+    /// for instance to look at what a failing test left in it.
+    pub fn keep(&mut self) {
+        self.keep = true;
+    }
+
     /// Access the wrapped `std::path::Path` to the temporary directory.
     pub fn path<'a>(&'a self) -> &'a Path {
         self.path.get_ref()
@@ -68,12 +81,90 @@ impl TempDir {
 
 impl Drop for TempDir {
     fn drop(&mut self) {
+        if self.keep { return }
         for path in self.path.iter() {
             os::remove_dir_recursive(path);
         }
     }
 }
 
+/// A wrapper for the path to a new, empty file with a unique name, which
+/// deletes the file when it goes out of scope.
+pub struct NamedTempFile {
+    priv path: Option<Path>,
+    priv keep: bool
+}
+
+impl NamedTempFile {
+    /// Attempts to create an empty file inside of `tmpdir` whose name will
+    /// have the suffix `suffix`. The file is only readable and writable by
+    /// the current user, and is deleted once the returned wrapper is
+    /// destroyed.
+    ///
+    /// The file is created only if nothing exists at the chosen path, so
+    /// it can't be one that another process is using.
+    ///
+    /// If no file can be created, None is returned.
+    pub fn new_in(tmpdir: &Path, suffix: &str) -> Option<NamedTempFile> {
+        #[fixed_stack_segment]; #[inline(never)];
+
+        if !tmpdir.is_absolute() {
+            let abs_tmpdir = os::make_absolute(tmpdir);
+            return NamedTempFile::new_in(&abs_tmpdir, suffix);
+        }
+
+        let mut r = rand::rng();
+        for _ in range(0u, 1000) {
+            let p = tmpdir.push(r.gen_ascii_str(16) + suffix);
+            let fd = do p.with_c_str |buf| {
+                unsafe {
+                    libc::open(buf, libc::O_RDWR | libc::O_CREAT | libc::O_EXCL,
+                               libc::S_IRUSR | libc::S_IWUSR)
+                }
+            };
+            if fd >= 0 {
+                unsafe { libc::close(fd); }
+                return Some(NamedTempFile { path: Some(p), keep: false });
+            }
+        }
+        None
+    }
+
+    /// Attempts to create an empty file inside of `os::tmpdir()` whose name
+    /// will have the suffix `suffix`. The file will be automatically deleted
+    /// once the returned wrapper is destroyed.
+    ///
+    /// If no file can be created, None is returned.
+    pub fn new(suffix: &str) -> Option<NamedTempFile> {
+        NamedTempFile::new_in(&os::tmpdir(), suffix)
+    }
+
+    /// Unwrap the path to the file, so that it isn't deleted.
+    pub fn into_path(self) -> Path {
+        let mut file = self;
+        file.path.take_unwrap()
+    }
+
+    /// Leave the file in place when the wrapper is destroyed.
+    pub fn keep(&mut self) {
+        self.keep = true;
+    }
+
+    /// Access the path to the file.
+    pub fn path<'a>(&'a self) -> &'a Path {
+        self.path.get_ref()
+    }
+}
+
+impl Drop for NamedTempFile {
+    fn drop(&mut self) {
+        if self.keep { return }
+        for path in self.path.iter() {
+            os::remove_file(path);
+        }
+    }
+}
+
 // the tests for this module need to change the path using change_dir,
 // and this doesn't play nicely with other tests so these unit tests are located
 // in src/test/run-pass/tempfile.rs
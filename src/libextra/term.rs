@@ -74,6 +74,12 @@ pub mod attr {
     }
 }
 
+/// Returns true if `out` is attached to a terminal rather than to a file or
+/// a pipe.
+pub fn isatty(out: @io::Writer) -> bool {
+    out.get_type() == io::Screen
+}
+
 #[cfg(not(target_os = "win32"))]
 fn cap_for_attr(attr: attr::Attr) -> &'static str {
     match attr {
@@ -209,6 +215,16 @@ impl Terminal {
         }
     }
 
+    /// Looks up the string capability `name`, e.g. "bold" or "smul", and
+    /// returns its expansion without parameters. Fails if the terminal
+    /// doesn't have the capability.
+    pub fn capability(&self, name: &str) -> Result<~[u8], ~str> {
+        match self.ti.strings.find_equiv(&name) {
+            Some(cap) => expand(*cap, [], &mut Variables::new()),
+            None => Err(format!("terminal has no `{}` capability", name))
+        }
+    }
+
     /// Resets all terminal attributes and color to the default.
     pub fn reset(&self) {
         let mut cap = self.ti.strings.find_equiv(&("sgr0"));
@@ -263,6 +279,105 @@ impl Terminal {
         false
     }
 
+    pub fn capability(&self, name: &str) -> Result<~[u8], ~str> {
+        Err(format!("terminal has no `{}` capability", name))
+    }
+
     pub fn reset(&self) {
     }
 }
+
+/// A writer that styles its output when it is going to a terminal that can
+/// show it, and writes plain text otherwise. Styling calls return false when
+/// they had no effect.
+pub struct StyledWriter {
+    priv out: @io::Writer,
+    priv term: Option<Terminal>
+}
+
+impl StyledWriter {
+    /// Styles output only if `out` is a terminal with a usable terminfo
+    /// entry.
+    pub fn new(out: @io::Writer) -> StyledWriter {
+        let term = if isatty(out) {
+            match Terminal::new(out) {
+                Ok(t) => Some(t),
+                Err(_) => None
+            }
+        } else {
+            None
+        };
+        StyledWriter { out: out, term: term }
+    }
+
+    /// Never styles output.
+    pub fn plain(out: @io::Writer) -> StyledWriter {
+        StyledWriter { out: out, term: None }
+    }
+
+    /// Returns whether styling is being written out at all.
+    pub fn is_styled(&self) -> bool {
+        self.term.is_some()
+    }
+
+    /// Sets the foreground color for the text that follows.
+    pub fn fg(&self, color: color::Color) -> bool {
+        match self.term {
+            Some(ref t) => t.fg(color),
+            None => false
+        }
+    }
+
+    /// Makes the text that follows bold.
+    pub fn bold(&self) -> bool {
+        match self.term {
+            Some(ref t) => t.attr(attr::Bold),
+            None => false
+        }
+    }
+
+    /// Goes back to unstyled text.
+    pub fn reset(&self) {
+        match self.term {
+            Some(ref t) => t.reset(),
+            None => {}
+        }
+    }
+
+    pub fn write_str(&self, s: &str) {
+        self.out.write_str(s)
+    }
+
+    pub fn write_line(&self, s: &str) {
+        self.out.write_line(s)
+    }
+
+    /// Writes `s` in the given color, then resets the style.
+    pub fn write_colored(&self, s: &str, color: color::Color) {
+        let styled = self.fg(color);
+        self.write_str(s);
+        if styled {
+            self.reset();
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::io;
+
+    #[test]
+    fn test_styled_writer_plain_for_files() {
+        let out = do io::with_str_writer |wr| {
+            assert!(!isatty(wr));
+            let w = StyledWriter::new(wr);
+            assert!(!w.is_styled());
+            assert!(!w.bold());
+            w.write_colored("ok", color::GREEN);
+            w.reset();
+            w.write_line("!");
+        };
+        assert_eq!(out, ~"ok!\n");
+    }
+}
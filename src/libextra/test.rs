@@ -29,13 +29,16 @@ use time::precise_time_ns;
 use treemap::TreeMap;
 
 use std::clone::Clone;
-use std::comm::{stream, SharedChan, GenericPort, GenericChan};
+use std::cmp;
+use std::comm::{stream, SharedChan, GenericPort, GenericChan, GenericSmartChan};
+use std::comm::{Chan, Peekable};
 use std::io;
 use std::result;
 use std::task;
 use std::to_str::ToStr;
 use std::f64;
 use std::os;
+use std::vec;
 
 
 // The name of a test. By convention this follows the rules for rust
@@ -155,6 +158,15 @@ pub fn test_main_static(args: &[~str], tests: &[TestDescAndFn]) {
     test_main(args, owned_tests)
 }
 
+/// How the console test runner reports progress
+#[deriving(Clone, Eq)]
+pub enum OutputFormat {
+    /// Human readable text, colored when writing to a terminal
+    PrettyOutput,
+    /// One JSON object per line for each event of the run
+    JsonOutput
+}
+
 pub struct TestOpts {
     filter: Option<~str>,
     run_ignored: bool,
@@ -163,12 +175,23 @@ pub struct TestOpts {
     ratchet_metrics: Option<Path>,
     ratchet_noise_percent: Option<f64>,
     save_metrics: Option<Path>,
+    save_failures: Option<Path>,
+    // The names read from the file given to --rerun-failed
+    rerun_failed: Option<~[~str]>,
     test_shard: Option<(uint,uint)>,
-    logfile: Option<Path>
+    test_timeout: Option<u64>,
+    bench_samples: Option<uint>,
+    baseline: Option<Path>,
+    max_regression: Option<f64>,
+    logfile: Option<Path>,
+    format: OutputFormat
 }
 
 type OptRes = Result<TestOpts, ~str>;
 
+/// How many samples each round of a benchmark takes, unless told otherwise
+pub static DEFAULT_BENCH_SAMPLES: uint = 50;
+
 fn optgroups() -> ~[getopts::groups::OptGroup] {
     ~[groups::optflag("", "ignored", "Run ignored tests"),
       groups::optflag("", "test", "Run tests and not benchmarks"),
@@ -176,6 +199,9 @@ fn optgroups() -> ~[getopts::groups::OptGroup] {
       groups::optflag("h", "help", "Display this message (longer with --help)"),
       groups::optopt("", "save-metrics", "Location to save bench metrics",
                      "PATH"),
+      groups::optopt("", "bench-samples", "Number of samples to take per round of \
+                         each benchmark (default 50). More samples narrow the \
+                         confidence interval of noisy benchmarks", "N"),
       groups::optopt("", "ratchet-metrics",
                      "Location to load and save metrics from. The metrics \
                       loaded are cause benchmarks to fail if they run too \
@@ -183,10 +209,31 @@ fn optgroups() -> ~[getopts::groups::OptGroup] {
       groups::optopt("", "ratchet-noise-percent",
                      "Tests within N% of the recorded metrics will be \
                       considered as passing", "PERCENTAGE"),
+      groups::optopt("", "save-failures",
+                     "Write the names of the tests that failed to PATH, one \
+                      per line", "PATH"),
+      groups::optopt("", "rerun-failed",
+                     "Run only the tests named in PATH, as written by \
+                      --save-failures. The same file may be given to both \
+                      to narrow it down while fixing", "PATH"),
+      groups::optopt("", "baseline",
+                     "Compare metrics against a baseline saved earlier with \
+                      --save-metrics and report how each one changed. The \
+                      baseline is left untouched", "PATH"),
+      groups::optopt("", "max-regression",
+                     "Fail if any metric regressed by more than this much \
+                      against the baseline", "PERCENTAGE"),
       groups::optopt("", "logfile", "Write logs to the specified file instead \
                           of stdout", "PATH"),
       groups::optopt("", "test-shard", "run shard A, of B shards, worth of the testsuite",
-                     "A.B")]
+                     "A.B"),
+      groups::optopt("", "shard", "Run only the Kth of N slices of the tests, counting \
+                         from 1. Every test is in exactly one slice", "K/N"),
+      groups::optopt("", "test-timeout", "Fail any test that runs longer than this \
+                         many seconds and carry on with the rest", "SECS"),
+      groups::optopt("", "format", "Output format: `pretty` (the default) or `json`, \
+                          which writes one JSON object per line for each event",
+                     "pretty|json")]
 }
 
 fn usage(binary: &str, helpstr: &str) {
@@ -256,8 +303,77 @@ pub fn parse_opts(args: &[~str]) -> Option<OptRes> {
     let save_metrics = matches.opt_str("save-metrics");
     let save_metrics = save_metrics.map(|s| Path(s));
 
+    let save_failures = matches.opt_str("save-failures");
+    let save_failures = save_failures.map(|s| Path(s));
+
+    // Read now, before the run, so that --save-failures can write the same
+    // file afterwards
+    let rerun_failed = match matches.opt_str("rerun-failed") {
+        None => None,
+        Some(s) => match load_failures(&Path(s)) {
+            Ok(names) => Some(names),
+            Err(e) => return Some(Err(format!("can't read failed tests from {}: {}", s, e)))
+        }
+    };
+
+    let baseline = matches.opt_str("baseline");
+    let baseline = baseline.map(|s| Path(s));
+
+    let max_regression = match matches.opt_str("max-regression") {
+        None => None,
+        Some(s) => match from_str::<f64>(s) {
+            Some(pct) if pct >= 0.0 => Some(pct),
+            _ => return Some(Err(format!("argument for --max-regression must be \
+                                          a non-negative percentage (was {})", s)))
+        }
+    };
+    if max_regression.is_some() && baseline.is_none() {
+        return Some(Err(~"--max-regression needs a --baseline to compare against"));
+    }
+
     let test_shard = matches.opt_str("test-shard");
     let test_shard = opt_shard(test_shard);
+    let test_shard = match (test_shard, matches.opt_str("shard")) {
+        (Some(_), Some(_)) => {
+            return Some(Err(~"--shard and --test-shard can't be used together"))
+        }
+        (Some((_, 0)), None) => {
+            return Some(Err(~"--test-shard needs at least one shard"))
+        }
+        (shard, None) => shard,
+        (None, Some(s)) => match parse_shard(s) {
+            Ok(shard) => Some(shard),
+            Err(msg) => return Some(Err(msg))
+        }
+    };
+
+    let test_timeout = match matches.opt_str("test-timeout") {
+        None => None,
+        Some(s) => match from_str::<u64>(s) {
+            Some(secs) if secs > 0 => Some(secs * 1000),
+            _ => return Some(Err(format!("argument for --test-timeout must be \
+                                          a positive number of seconds (was {})", s)))
+        }
+    };
+
+    let bench_samples = match matches.opt_str("bench-samples") {
+        None => None,
+        Some(s) => match from_str::<uint>(s) {
+            Some(n) if n > 0 => Some(n),
+            _ => return Some(Err(format!("argument for --bench-samples must be \
+                                          a positive number (was {})", s)))
+        }
+    };
+
+    let format = match matches.opt_str("format") {
+        None => PrettyOutput,
+        Some(s) => match s.as_slice() {
+            "pretty" => PrettyOutput,
+            "json" => JsonOutput,
+            _ => return Some(Err(format!("argument for --format must be \
+                                          `pretty` or `json` (was {})", s)))
+        }
+    };
 
     let test_opts = TestOpts {
         filter: filter,
@@ -267,8 +383,15 @@ pub fn parse_opts(args: &[~str]) -> Option<OptRes> {
         ratchet_metrics: ratchet_metrics,
         ratchet_noise_percent: ratchet_noise_percent,
         save_metrics: save_metrics,
+        save_failures: save_failures,
+        rerun_failed: rerun_failed,
         test_shard: test_shard,
-        logfile: logfile
+        test_timeout: test_timeout,
+        bench_samples: bench_samples,
+        baseline: baseline,
+        max_regression: max_regression,
+        logfile: logfile,
+        format: format
     };
 
     Some(Ok(test_opts))
@@ -289,10 +412,24 @@ pub fn opt_shard(maybestr: Option<~str>) -> Option<(uint,uint)> {
     }
 }
 
+/// Parses the argument to `--shard`, "K/N" with 1 <= K <= N, into the
+/// zero-based shard index and the number of shards.
+pub fn parse_shard(s: &str) -> Result<(uint, uint), ~str> {
+    let err = format!("argument for --shard must be K/N with 1 <= K <= N (was {})", s);
+    match s.split_iter('/').to_owned_vec() {
+        [k, n] => match (from_str::<uint>(k), from_str::<uint>(n)) {
+            (Some(k), Some(n)) if k >= 1 && k <= n => Ok((k - 1, n)),
+            _ => Err(err)
+        },
+        _ => Err(err)
+    }
+}
 
 #[deriving(Clone, Eq)]
 pub struct BenchSamples {
     ns_iter_summ: stats::Summary,
+    /// 95% confidence interval for `ns_iter_summ.median`
+    ns_iter_ci: (f64, f64),
     mb_s: uint
 }
 
@@ -308,8 +445,9 @@ pub enum TestResult {
 struct ConsoleTestState {
     out: @io::Writer,
     log_out: Option<@io::Writer>,
-    term: Option<term::Terminal>,
+    term: term::StyledWriter,
     use_color: bool,
+    format: OutputFormat,
     total: uint,
     passed: uint,
     failed: uint,
@@ -333,15 +471,12 @@ impl ConsoleTestState {
             None => None
         };
         let out = io::stdout();
-        let term = match term::Terminal::new(out) {
-            Err(_) => None,
-            Ok(t) => Some(t)
-        };
         ConsoleTestState {
             out: out,
             log_out: log_out,
             use_color: use_color(),
-            term: term,
+            term: term::StyledWriter::new(out),
+            format: opts.format,
             total: 0u,
             passed: 0u,
             failed: 0u,
@@ -391,31 +526,75 @@ impl ConsoleTestState {
     pub fn write_pretty(&self,
                         word: &str,
                         color: term::color::Color) {
-        match self.term {
-            None => self.out.write_str(word),
-            Some(ref t) => {
-                if self.use_color {
-                    t.fg(color);
-                }
-                self.out.write_str(word);
-                if self.use_color {
-                    t.reset();
-                }
-            }
+        if self.use_color {
+            self.term.write_colored(word, color);
+        } else {
+            self.out.write_str(word);
         }
     }
 
+    /// Writes one line of JSON output: an object with the event's name, the
+    /// name of the test it concerns if any, and the given fields.
+    pub fn write_json(&self,
+                      event: &str,
+                      test: Option<&TestDesc>,
+                      fields: ~[(~str, json::Json)]) {
+        let mut map = ~TreeMap::new();
+        map.insert(~"event", json::String(event.to_owned()));
+        for t in test.iter() {
+            map.insert(~"name", json::String(t.name.to_str()));
+        }
+        for (k, v) in fields.move_iter() {
+            map.insert(k, v);
+        }
+        self.out.write_line(json::Object(map).to_str());
+    }
+
     pub fn write_run_start(&mut self, len: uint) {
         self.total = len;
+        if self.format == JsonOutput {
+            self.write_json("run_start", None, ~[(~"count", json::Number(len as f64))]);
+            return;
+        }
         let noun = if len != 1 { &"tests" } else { &"test" };
         self.out.write_line(format!("\nrunning {} {}", len, noun));
     }
 
     pub fn write_test_start(&self, test: &TestDesc) {
+        if self.format == JsonOutput {
+            self.write_json("start", Some(test), ~[]);
+            return;
+        }
         self.out.write_str(format!("test {} ... ", test.name.to_str()));
     }
 
-    pub fn write_result(&self, result: &TestResult) {
+    pub fn write_result(&self, test: &TestDesc, result: &TestResult,
+                        failure: &Option<~str>) {
+        if self.format == JsonOutput {
+            let (event, fields) = match *result {
+                TrOk => ("ok", ~[]),
+                TrFailed => ("failed", match *failure {
+                    Some(ref msg) => ~[(~"message", json::String(msg.clone()))],
+                    None => ~[]
+                }),
+                TrIgnored => ("ignored", ~[]),
+                TrMetrics(ref mm) => ("metric", ~[(~"metrics", mm.to_json())]),
+                TrBench(ref bs) => {
+                    let (ci_low, ci_high) = bs.ns_iter_ci;
+                    ("bench", ~[
+                        (~"ns_per_iter", json::Number(bs.ns_iter_summ.median)),
+                        (~"deviation", json::Number(bs.ns_iter_summ.max -
+                                                    bs.ns_iter_summ.min)),
+                        (~"median_abs_dev", json::Number(bs.ns_iter_summ.median_abs_dev)),
+                        (~"ci_low", json::Number(ci_low)),
+                        (~"ci_high", json::Number(ci_high)),
+                        (~"mb_per_s", json::Number(bs.mb_s as f64))
+                    ])
+                }
+            };
+            self.write_json(event, Some(test), fields);
+            return;
+        }
         match *result {
             TrOk => self.write_ok(),
             TrFailed => self.write_failed(),
@@ -448,18 +627,31 @@ impl ConsoleTestState {
         }
     }
 
-    pub fn write_failures(&self) {
-        self.out.write_line("\nfailures:");
+    fn failure_names(&self) -> ~[~str] {
         let mut failures = ~[];
         for f in self.failures.iter() {
             failures.push(f.name.to_str());
         }
         sort::tim_sort(failures);
-        for name in failures.iter() {
+        failures
+    }
+
+    pub fn write_failures(&self) {
+        self.out.write_line("\nfailures:");
+        for name in self.failure_names().iter() {
             self.out.write_line(format!("    {}", name.to_str()));
         }
     }
 
+    /// Write the names of the failed tests to a file, one per line, for
+    /// `--rerun-failed` to read back.
+    pub fn save_failures(&self, p: &Path) {
+        let f = io::file_writer(p, [io::Create, io::Truncate]).unwrap();
+        for name in self.failure_names().iter() {
+            f.write_line(*name);
+        }
+    }
+
     pub fn write_metric_diff(&self, diff: &MetricDiff) {
         let mut noise = 0;
         let mut improved = 0;
@@ -467,6 +659,25 @@ impl ConsoleTestState {
         let mut added = 0;
         let mut removed = 0;
 
+        if self.format == JsonOutput {
+            for (k, v) in diff.iter() {
+                let (change, pct) = match *v {
+                    LikelyNoise => continue,
+                    MetricAdded => ("added", None),
+                    MetricRemoved => ("removed", None),
+                    Improvement(pct) => ("improved", Some(pct)),
+                    Regression(pct) => ("regressed", Some(pct))
+                };
+                let mut fields = ~[(~"metric", json::String((*k).clone())),
+                                   (~"change", json::String(change.to_owned()))];
+                for &pct in pct.iter() {
+                    fields.push((~"percent", json::Number(pct)));
+                }
+                self.write_json("metric_change", None, fields);
+            }
+            return;
+        }
+
         for (k, v) in diff.iter() {
             match *v {
                 LikelyNoise => noise += 1,
@@ -506,20 +717,116 @@ impl ConsoleTestState {
         }
     }
 
+    /// Reports how each metric changed since the baseline saved at `pth`.
+    /// Returns false if a metric regressed by more than `max_regression`
+    /// percent.
+    pub fn write_baseline_comparison(&self, pth: &Path,
+                                     max_regression: Option<f64>) -> bool {
+        if !os::path_exists(pth) {
+            match self.format {
+                PrettyOutput => {
+                    self.out.write_line(format!("\nno baseline found at {}",
+                                                pth.to_str()));
+                }
+                JsonOutput => {
+                    self.write_json("baseline_missing", None,
+                                    ~[(~"path", json::String(pth.to_str()))]);
+                }
+            }
+            return true;
+        }
+
+        let baseline = MetricMap::load(pth);
+        let diff = self.metrics.compare_to_old(&baseline, None);
+        if self.format == PrettyOutput {
+            self.out.write_line(format!("\ncomparing against baseline: {}",
+                                        pth.to_str()));
+        }
+
+        let mut exceeded = 0;
+        for (k, change) in diff.iter() {
+            let old = baseline.find(k);
+            let new = self.metrics.find(k);
+            let too_slow = match *change {
+                Regression(pct) => max_regression.map_default(false, |max| pct > max),
+                _ => false
+            };
+            if too_slow {
+                exceeded += 1;
+            }
+
+            if self.format == JsonOutput {
+                let mut fields = ~[(~"metric", json::String((*k).clone())),
+                                   (~"change", json::String(match *change {
+                                       LikelyNoise => ~"noise",
+                                       MetricAdded => ~"added",
+                                       MetricRemoved => ~"removed",
+                                       Improvement(_) => ~"improved",
+                                       Regression(_) => ~"regressed"
+                                   })),
+                                   (~"exceeded", json::Boolean(too_slow))];
+                for m in old.iter() {
+                    fields.push((~"old", json::Number(m.value)));
+                }
+                for m in new.iter() {
+                    fields.push((~"new", json::Number(m.value)));
+                }
+                self.write_json("baseline_change", None, fields);
+                continue;
+            }
+
+            self.out.write_str(format!("{}: ", *k));
+            match (old, new) {
+                (Some(o), Some(n)) => {
+                    let delta = (n.value - o.value) / o.value.max(&f64::epsilon) * 100.0;
+                    let sign = if delta >= 0.0 { "+" } else { "" };
+                    self.out.write_str(format!("{} -> {} ({}{:.2f}%) ",
+                                               o.value, n.value, sign, delta));
+                }
+                _ => ()
+            }
+            match *change {
+                LikelyNoise => self.out.write_str("noise"),
+                MetricAdded => self.write_added(),
+                MetricRemoved => self.write_removed(),
+                Improvement(_) => self.write_improved(),
+                Regression(_) => self.write_regressed()
+            }
+            self.out.write_str("\n");
+        }
+
+        if exceeded == 0 {
+            return true;
+        }
+        match self.format {
+            PrettyOutput => {
+                self.out.write_line(format!("{} metrics regressed by more than {}%",
+                                            exceeded, max_regression.unwrap()));
+            }
+            JsonOutput => ()
+        }
+        false
+    }
+
     pub fn write_run_finish(&self,
                             ratchet_metrics: &Option<Path>,
-                            ratchet_pct: Option<f64>) -> bool {
+                            ratchet_pct: Option<f64>,
+                            baseline: &Option<Path>,
+                            max_regression: Option<f64>) -> bool {
         assert!(self.passed + self.failed + self.ignored + self.measured == self.total);
 
         let ratchet_success = match *ratchet_metrics {
             None => true,
             Some(ref pth) => {
-                self.out.write_str(format!("\nusing metrics ratchet: {}\n", pth.to_str()));
-                match ratchet_pct {
-                    None => (),
-                    Some(pct) =>
-                    self.out.write_str(format!("with noise-tolerance forced to: {}%%\n",
-                                            pct as f64))
+                if self.format == PrettyOutput {
+                    self.out.write_str(format!("\nusing metrics ratchet: {}\n",
+                                               pth.to_str()));
+                    match ratchet_pct {
+                        None => (),
+                        Some(pct) =>
+                        self.out.write_str(format!("with noise-tolerance forced to: {}%%\n",
+                                                pct as f64))
+                    }
                 }
                 let (diff, ok) = self.metrics.ratchet(pth, ratchet_pct);
                 self.write_metric_diff(&diff);
@@ -527,13 +834,29 @@ impl ConsoleTestState {
             }
         };
 
+        let baseline_success = match *baseline {
+            None => true,
+            Some(ref pth) => self.write_baseline_comparison(pth, max_regression)
+        };
+
         let test_success = self.failed == 0u;
+        let success = ratchet_success && baseline_success && test_success;
+
+        if self.format == JsonOutput {
+            self.write_json("run_finish", None, ~[
+                (~"passed", json::Number(self.passed as f64)),
+                (~"failed", json::Number(self.failed as f64)),
+                (~"ignored", json::Number(self.ignored as f64)),
+                (~"measured", json::Number(self.measured as f64)),
+                (~"success", json::Boolean(success))
+            ]);
+            return success;
+        }
+
         if !test_success {
             self.write_failures();
         }
 
-        let success = ratchet_success && test_success;
-
         self.out.write_str("\ntest result: ");
         if success {
             // There's no parallelism at this point so it's safe to use color
@@ -558,15 +881,20 @@ pub fn fmt_metrics(mm: &MetricMap) -> ~str {
 }
 
 pub fn fmt_bench_samples(bs: &BenchSamples) -> ~str {
+    let (ci_low, ci_high) = bs.ns_iter_ci;
+    let spread = format!("MAD {}, 95% CI {}-{}",
+                         bs.ns_iter_summ.median_abs_dev as uint,
+                         ci_low as uint, ci_high as uint);
     if bs.mb_s != 0 {
-        format!("{} ns/iter (+/- {}) = {} MB/s",
+        format!("{} ns/iter (+/- {}) = {} MB/s; {}",
              bs.ns_iter_summ.median as uint,
              (bs.ns_iter_summ.max - bs.ns_iter_summ.min) as uint,
-             bs.mb_s)
+             bs.mb_s, spread)
     } else {
-        format!("{} ns/iter (+/- {})",
+        format!("{} ns/iter (+/- {}); {}",
              bs.ns_iter_summ.median as uint,
-             (bs.ns_iter_summ.max - bs.ns_iter_summ.min) as uint)
+             (bs.ns_iter_summ.max - bs.ns_iter_summ.min) as uint,
+             spread)
     }
 }
 
@@ -578,9 +906,9 @@ pub fn run_tests_console(opts: &TestOpts,
         match (*event).clone() {
             TeFiltered(ref filtered_tests) => st.write_run_start(filtered_tests.len()),
             TeWait(ref test) => st.write_test_start(test),
-            TeResult(test, result) => {
+            TeResult(test, result, failure) => {
                 st.write_log(&test, &result);
-                st.write_result(&result);
+                st.write_result(&test, &result, &failure);
                 match result {
                     TrOk => st.passed += 1,
                     TrIgnored => st.ignored += 1,
@@ -612,10 +940,22 @@ pub fn run_tests_console(opts: &TestOpts,
         None => (),
         Some(ref pth) => {
             st.metrics.save(pth);
-            st.out.write_str(format!("\nmetrics saved to: {}", pth.to_str()));
+            if st.format == PrettyOutput {
+                st.out.write_str(format!("\nmetrics saved to: {}", pth.to_str()));
+            }
         }
     }
-    return st.write_run_finish(&opts.ratchet_metrics, opts.ratchet_noise_percent);
+    match opts.save_failures {
+        None => (),
+        Some(ref pth) => {
+            st.save_failures(pth);
+            if st.format == PrettyOutput {
+                st.out.write_str(format!("\nfailures saved to: {}", pth.to_str()));
+            }
+        }
+    }
+    return st.write_run_finish(&opts.ratchet_metrics, opts.ratchet_noise_percent,
+                               &opts.baseline, opts.max_regression);
 }
 
 #[test]
@@ -638,8 +978,9 @@ fn should_sort_failures_before_printing_them() {
         let st = @ConsoleTestState {
             out: wr,
             log_out: None,
-            term: None,
+            term: term::StyledWriter::plain(wr),
             use_color: false,
+            format: PrettyOutput,
             total: 0u,
             passed: 0u,
             failed: 0u,
@@ -663,10 +1004,12 @@ fn use_color() -> bool { return get_concurrency() == 1; }
 enum TestEvent {
     TeFiltered(~[TestDesc]),
     TeWait(TestDesc),
-    TeResult(TestDesc, TestResult),
+    TeResult(TestDesc, TestResult, Option<~str>),
 }
 
-type MonitorMsg = (TestDesc, TestResult);
+/// A finished test, its result and the message it failed with, if it
+/// failed and the message is known.
+type MonitorMsg = (TestDesc, TestResult, Option<~str>);
 
 fn run_tests(opts: &TestOpts,
              tests: ~[TestDescAndFn],
@@ -706,15 +1049,15 @@ fn run_tests(opts: &TestOpts,
                 // that hang forever.
                 callback(TeWait(test.desc.clone()));
             }
-            run_test(!opts.run_tests, test, ch.clone());
+            run_test(!opts.run_tests, test, opts.test_timeout, None, ch.clone());
             pending += 1;
         }
 
-        let (desc, result) = p.recv();
+        let (desc, result, failure) = p.recv();
         if concurrency != 1 {
             callback(TeWait(desc.clone()));
         }
-        callback(TeResult(desc, result));
+        callback(TeResult(desc, result, failure));
         pending -= 1;
     }
 
@@ -722,9 +1065,9 @@ fn run_tests(opts: &TestOpts,
     // (this includes metric fns)
     for b in filtered_benchs_and_metrics.move_iter() {
         callback(TeWait(b.desc.clone()));
-        run_test(!opts.run_benchmarks, b, ch.clone());
-        let (test, result) = p.recv();
-        callback(TeResult(test, result));
+        run_test(!opts.run_benchmarks, b, None, opts.bench_samples, ch.clone());
+        let (test, result, failure) = p.recv();
+        callback(TeResult(test, result, failure));
     }
 }
 
@@ -771,6 +1114,17 @@ pub fn filter_tests(
         filtered.move_iter().filter_map(|x| filter_fn(x, filter_str)).collect()
     };
 
+    // Keep only the tests that failed last time, if asked to
+    filtered = match opts.rerun_failed {
+        None => filtered,
+        Some(ref names) => {
+            filtered.move_iter().filter(|t| {
+                let name = t.desc.name.to_str();
+                names.iter().any(|n| *n == name)
+            }).collect()
+        }
+    };
+
     // Maybe pull out the ignored test and unignore them
     filtered = if !opts.run_ignored {
         filtered
@@ -795,7 +1149,8 @@ pub fn filter_tests(
     }
     sort::quick_sort(filtered, lteq);
 
-    // Shard the remaining tests, if sharding requested.
+    // Shard the remaining tests, if sharding requested. They are sorted, so
+    // every shard gets the same tests on every run.
     match opts.test_shard {
         None => filtered,
         Some((a,b)) =>
@@ -806,64 +1161,194 @@ pub fn filter_tests(
     }
 }
 
+/// Read back the names of the tests saved with `--save-failures`.
+pub fn load_failures(p: &Path) -> Result<~[~str], ~str> {
+    do io::read_whole_file_str(p).map |contents| {
+        contents.line_iter()
+            .map(|l| l.trim())
+            .filter(|l| !l.is_empty())
+            .map(|l| l.to_owned())
+            .collect()
+    }
+}
+
+/// Runs a single test and sends its result down `monitor_ch`. A test that
+/// hasn't finished after `timeout` milliseconds is reported as failed.
+/// Benchmarks take `bench_samples` samples per round, or
+/// `DEFAULT_BENCH_SAMPLES`.
 pub fn run_test(force_ignore: bool,
                 test: TestDescAndFn,
+                timeout: Option<u64>,
+                bench_samples: Option<uint>,
                 monitor_ch: SharedChan<MonitorMsg>) {
 
     let TestDescAndFn {desc, testfn} = test;
 
     if force_ignore || desc.ignore {
-        monitor_ch.send((desc, TrIgnored));
+        monitor_ch.send((desc, TrIgnored, None));
         return;
     }
 
     fn run_test_inner(desc: TestDesc,
+                      timeout: Option<u64>,
                       monitor_ch: SharedChan<MonitorMsg>,
                       testfn: ~fn()) {
         let testfn_cell = ::std::cell::Cell::new(testfn);
         do task::spawn {
-            let mut result_future = None; // task::future_result(builder);
-
-            let mut task = task::task();
-            task.unlinked();
-            task.future_result(|r| { result_future = Some(r) });
-            task.spawn(testfn_cell.take());
+            let (failure_port, failure_chan) = stream();
+            let testfn = testfn_cell.take();
+            let body: ~fn() = || {
+                // Catch the failure to learn its message. If the task is
+                // killed instead, it fails without one.
+                match task::try_block(|| testfn()) {
+                    Ok(()) => (),
+                    Err(msg) => failure_chan.send(msg)
+                }
+            };
 
-            let task_result = result_future.unwrap().recv();
+            let task_result = match timeout {
+                None => {
+                    let mut result_future = None; // task::future_result(builder);
+                    let mut task = task::task();
+                    task.unlinked();
+                    task.future_result(|r| { result_future = Some(r) });
+                    task.spawn(body);
+                    result_future.unwrap().recv()
+                }
+                Some(ms) => match run_timed(body, ms) {
+                    Ok(task_result) => task_result,
+                    Err(msg) => {
+                        monitor_ch.send((desc.clone(), TrFailed, Some(msg)));
+                        return;
+                    }
+                }
+            };
+            let failure = failure_port.try_recv();
             let test_result = calc_result(&desc,
-                                          task_result == task::Success);
-            monitor_ch.send((desc.clone(), test_result));
+                                          task_result == task::Success &&
+                                          failure.is_none());
+            let failure = if test_result == TrFailed { failure } else { None };
+            monitor_ch.send((desc.clone(), test_result, failure));
         }
     }
 
+    let n_samples = bench_samples.unwrap_or(DEFAULT_BENCH_SAMPLES);
     match testfn {
         DynBenchFn(benchfn) => {
-            let bs = ::test::bench::benchmark(benchfn);
-            monitor_ch.send((desc, TrBench(bs)));
+            let bs = ::test::bench::benchmark_samples(n_samples, benchfn);
+            monitor_ch.send((desc, TrBench(bs), None));
             return;
         }
         StaticBenchFn(benchfn) => {
-            let bs = ::test::bench::benchmark(benchfn);
-            monitor_ch.send((desc, TrBench(bs)));
+            let bs = ::test::bench::benchmark_samples(n_samples, benchfn);
+            monitor_ch.send((desc, TrBench(bs), None));
             return;
         }
         DynMetricFn(f) => {
             let mut mm = MetricMap::new();
             f(&mut mm);
-            monitor_ch.send((desc, TrMetrics(mm)));
+            monitor_ch.send((desc, TrMetrics(mm), None));
             return;
         }
         StaticMetricFn(f) => {
             let mut mm = MetricMap::new();
             f(&mut mm);
-            monitor_ch.send((desc, TrMetrics(mm)));
+            monitor_ch.send((desc, TrMetrics(mm), None));
             return;
         }
-        DynTestFn(f) => run_test_inner(desc, monitor_ch, f),
-        StaticTestFn(f) => run_test_inner(desc, monitor_ch, || f())
+        DynTestFn(f) => run_test_inner(desc, timeout, monitor_ch, f),
+        StaticTestFn(f) => run_test_inner(desc, timeout, monitor_ch, || f())
     }
 }
 
+/// How long a test that timed out gets to stop once it has been killed.
+static KILL_GRACE_MS: u64 = 1000;
+
+/// Runs a test body that has `ms` milliseconds to finish, returning how its
+/// task ended. A test that runs out of time is killed, and the failure
+/// message to report for it is returned instead.
+///
+/// The test gets a scheduler of its own, so that one which never yields
+/// can't starve the timer or the rest of the runner. It is supervised by a
+/// task that fails, killing it, when told to. A killed task only unwinds
+/// once it next blocks or yields, so a test that spins can't be stopped;
+/// it is reported as such, and keeps the runner from exiting until it
+/// finishes.
+fn run_timed(body: ~fn(), ms: u64) -> Result<task::TaskResult, ~str> {
+    let body = ::std::cell::Cell::new(body);
+    let (result_port, result_chan) = stream();
+    let result_chan = SharedChan::new(result_chan);
+    let (kill_port, kill_chan) = stream();
+
+    let forward_chan = result_chan.clone();
+    let mut supervisor = task::task();
+    supervisor.unlinked();
+    do supervisor.spawn {
+        let mut result_future = None;
+        let mut test = task::task();
+        test.supervised();
+        test.sched_mode(task::SingleThreaded);
+        test.future_result(|r| { result_future = Some(r) });
+        test.spawn(body.take());
+
+        let result_future = ::std::cell::Cell::new(result_future.unwrap());
+        let forward_chan = forward_chan.clone();
+        let mut forwarder = task::task();
+        forwarder.unlinked();
+        do forwarder.spawn {
+            forward_chan.try_send(Some(result_future.take().recv()));
+        }
+
+        if kill_port.recv() {
+            fail2!("killing a test that timed out");
+        }
+    }
+
+    let timer = start_timer(ms, result_chan.clone());
+    let result = result_port.recv();
+    timer.try_send(());
+    match result {
+        Some(task_result) => {
+            kill_chan.send(false);
+            Ok(task_result)
+        }
+        None => {
+            kill_chan.send(true);
+            let timer = start_timer(KILL_GRACE_MS, result_chan);
+            let stopped = result_port.recv().is_some();
+            timer.try_send(());
+            if stopped {
+                Err(~"timed out")
+            } else {
+                Err(~"timed out, and could not be stopped")
+            }
+        }
+    }
+}
+
+/// Sends None down `chan` after `ms` milliseconds, unless something is
+/// sent down the returned channel first.
+fn start_timer(ms: u64,
+               chan: SharedChan<Option<task::TaskResult>>) -> Chan<()> {
+    use std::rt::io::timer;
+
+    let (done_port, done_chan) = stream::<()>();
+    let mut timer_task = task::task();
+    timer_task.unlinked();
+    do timer_task.spawn {
+        // Wake up now and then to stop early once the test has finished,
+        // rather than keeping a timer alive for the full timeout.
+        let mut waited = 0;
+        while waited < ms && !done_port.peek() {
+            let step = cmp::min(ms - waited, 100);
+            timer::sleep(step);
+            waited += step;
+        }
+        chan.try_send(None);
+    }
+    done_chan
+}
+
 fn calc_result(desc: &TestDesc, task_succeeded: bool) -> TestResult {
     if task_succeeded {
         if desc.should_fail { TrFailed }
@@ -1047,6 +1532,14 @@ impl BenchHarness {
 
     // This is a more statistics-driven benchmark algorithm
     pub fn auto_bench(&mut self, f: &fn(&mut BenchHarness)) -> stats::Summary {
+        stats::Summary::new(self.auto_bench_samples(DEFAULT_BENCH_SAMPLES, f))
+    }
+
+    /// Like `auto_bench`, taking `n_samples` samples per round, and
+    /// returning the ns/iter samples of the final round.
+    pub fn auto_bench_samples(&mut self, n_samples: uint,
+                              f: &fn(&mut BenchHarness)) -> ~[f64] {
+        assert!(n_samples > 0);
 
         // Initial bench run to get ballpark figure.
         let mut n = 1_u64;
@@ -1061,7 +1554,7 @@ impl BenchHarness {
         }
 
         let mut total_run = 0;
-        let samples : &mut [f64] = [0.0_f64, ..50];
+        let mut samples = vec::from_elem(n_samples, 0.0_f64);
         loop {
             let loop_start = precise_time_ns();
 
@@ -1095,13 +1588,13 @@ impl BenchHarness {
             if loop_run > 100_000_000 &&
                 summ.median_abs_dev_pct < 1.0 &&
                 summ.median - summ5.median < summ5.median_abs_dev {
-                return summ5;
+                return samples;
             }
 
             total_run += loop_run;
             // Longest we ever run for is 3s.
             if total_run > 3_000_000_000 {
-                return summ5;
+                return samples;
             }
 
             n *= 2;
@@ -1114,9 +1607,16 @@ impl BenchHarness {
 }
 
 pub mod bench {
-    use test::{BenchHarness, BenchSamples};
+    use stats;
+    use stats::Stats;
+    use test::{BenchHarness, BenchSamples, DEFAULT_BENCH_SAMPLES};
 
     pub fn benchmark(f: &fn(&mut BenchHarness)) -> BenchSamples {
+        benchmark_samples(DEFAULT_BENCH_SAMPLES, f)
+    }
+
+    /// Run a benchmark, taking `n_samples` samples per round.
+    pub fn benchmark_samples(n_samples: uint, f: &fn(&mut BenchHarness)) -> BenchSamples {
 
         let mut bs = BenchHarness {
             iterations: 0,
@@ -1125,7 +1625,8 @@ pub mod bench {
             bytes: 0
         };
 
-        let ns_iter_summ = bs.auto_bench(f);
+        let samples = bs.auto_bench_samples(n_samples, f);
+        let ns_iter_summ = stats::Summary::new(samples);
 
         let ns_iter = (ns_iter_summ.median as u64).max(&1);
         let iter_s = 1_000_000_000 / ns_iter;
@@ -1133,6 +1634,7 @@ pub mod bench {
 
         BenchSamples {
             ns_iter_summ: ns_iter_summ,
+            ns_iter_ci: samples.median_ci(),
             mb_s: mb_s as uint
         }
     }
@@ -1145,9 +1647,17 @@ mod tests {
                Metric, MetricMap, MetricAdded, MetricRemoved,
                Improvement, Regression, LikelyNoise,
                StaticTestName, DynTestName, DynTestFn};
-    use test::{TestOpts, run_test};
-
+    use test::{TestOpts, run_test, ConsoleTestState, JsonOutput, PrettyOutput};
+    use test::parse_shard;
+    use test::{BenchSamples, fmt_bench_samples};
+
+    use json;
+    use sort;
+    use stats;
+    use term;
+    use std::cell::Cell;
     use std::comm::{stream, SharedChan};
+    use std::io;
     use tempfile::TempDir;
 
     #[test]
@@ -1163,8 +1673,8 @@ mod tests {
         };
         let (p, ch) = stream();
         let ch = SharedChan::new(ch);
-        run_test(false, desc, ch);
-        let (_, res) = p.recv();
+        run_test(false, desc, None, None, ch);
+        let (_, res, _) = p.recv();
         assert!(res != TrOk);
     }
 
@@ -1181,8 +1691,8 @@ mod tests {
         };
         let (p, ch) = stream();
         let ch = SharedChan::new(ch);
-        run_test(false, desc, ch);
-        let (_, res) = p.recv();
+        run_test(false, desc, None, None, ch);
+        let (_, res, _) = p.recv();
         assert_eq!(res, TrIgnored);
     }
 
@@ -1199,8 +1709,8 @@ mod tests {
         };
         let (p, ch) = stream();
         let ch = SharedChan::new(ch);
-        run_test(false, desc, ch);
-        let (_, res) = p.recv();
+        run_test(false, desc, None, None, ch);
+        let (_, res, _) = p.recv();
         assert_eq!(res, TrOk);
     }
 
@@ -1217,11 +1727,139 @@ mod tests {
         };
         let (p, ch) = stream();
         let ch = SharedChan::new(ch);
-        run_test(false, desc, ch);
-        let (_, res) = p.recv();
+        run_test(false, desc, None, None, ch);
+        let (_, res, _) = p.recv();
         assert_eq!(res, TrFailed);
     }
 
+    #[test]
+    fn test_failure_message_is_reported() {
+        fn f() { fail2!("boom"); }
+        let desc = TestDescAndFn {
+            desc: TestDesc {
+                name: StaticTestName("whatever"),
+                ignore: false,
+                should_fail: false
+            },
+            testfn: DynTestFn(|| f()),
+        };
+        let (p, ch) = stream();
+        let ch = SharedChan::new(ch);
+        run_test(false, desc, None, None, ch);
+        let (_, res, failure) = p.recv();
+        assert_eq!(res, TrFailed);
+        assert!(failure.unwrap().contains("boom"));
+    }
+
+    #[test]
+    fn test_that_hangs_times_out() {
+        fn f() {
+            let (port, _chan) = stream::<()>();
+            port.recv();
+        }
+        let desc = TestDescAndFn {
+            desc: TestDesc {
+                name: StaticTestName("whatever"),
+                ignore: false,
+                should_fail: false
+            },
+            testfn: DynTestFn(|| f()),
+        };
+        let (p, ch) = stream();
+        let ch = SharedChan::new(ch);
+        run_test(false, desc, Some(200), None, ch);
+        let (_, res, failure) = p.recv();
+        assert_eq!(res, TrFailed);
+        assert_eq!(failure, Some(~"timed out"));
+    }
+
+    #[test]
+    fn test_that_times_out_is_killed() {
+        let (done_port, done_chan) = stream::<()>();
+        let done_chan = Cell::new(done_chan);
+        let f: ~fn() = || {
+            // Dropped as the test unwinds
+            let _done = done_chan.take();
+            let (port, _chan) = stream::<()>();
+            port.recv();
+        };
+        let desc = TestDescAndFn {
+            desc: TestDesc {
+                name: StaticTestName("whatever"),
+                ignore: false,
+                should_fail: false
+            },
+            testfn: DynTestFn(f),
+        };
+        let (p, ch) = stream();
+        let ch = SharedChan::new(ch);
+        run_test(false, desc, Some(200), None, ch);
+        let (_, res, failure) = p.recv();
+        assert_eq!(res, TrFailed);
+        assert_eq!(failure, Some(~"timed out"));
+        assert!(done_port.try_recv().is_none());
+    }
+
+    #[test]
+    fn test_within_timeout_passes() {
+        fn f() { }
+        let desc = TestDescAndFn {
+            desc: TestDesc {
+                name: StaticTestName("whatever"),
+                ignore: false,
+                should_fail: false
+            },
+            testfn: DynTestFn(|| f()),
+        };
+        let (p, ch) = stream();
+        let ch = SharedChan::new(ch);
+        run_test(false, desc, Some(60000), None, ch);
+        let (_, res, _) = p.recv();
+        assert_eq!(res, TrOk);
+    }
+
+    #[test]
+    fn parse_test_timeout_option() {
+        let args = ~[~"progname", ~"--test-timeout", ~"5"];
+        let opts = match parse_opts(args) {
+            Some(Ok(o)) => o,
+            _ => fail2!("Malformed arg in parse_test_timeout_option")
+        };
+        assert_eq!(opts.test_timeout, Some(5000));
+        let args = ~[~"progname", ~"--test-timeout", ~"0"];
+        match parse_opts(args) {
+            Some(Err(_)) => (),
+            _ => fail2!("a zero timeout should be rejected")
+        }
+    }
+
+    #[test]
+    fn parse_bench_samples_option() {
+        let args = ~[~"progname", ~"--bench-samples", ~"200"];
+        let opts = match parse_opts(args) {
+            Some(Ok(o)) => o,
+            _ => fail2!("Malformed arg in parse_bench_samples_option")
+        };
+        assert_eq!(opts.bench_samples, Some(200));
+        let args = ~[~"progname", ~"--bench-samples", ~"0"];
+        assert!(parse_opts(args).unwrap().is_err());
+    }
+
+    #[test]
+    fn bench_samples_report_spread() {
+        let mut samples = ~[];
+        for i in range(0, 100) {
+            samples.push((1000 + i) as f64);
+        }
+        let bs = BenchSamples {
+            ns_iter_summ: stats::Summary::new(samples),
+            ns_iter_ci: (1040.0, 1061.0),
+            mb_s: 0
+        };
+        assert_eq!(fmt_bench_samples(&bs),
+                   ~"1049 ns/iter (+/- 99); MAD 37, 95% CI 1040-1061");
+    }
+
     #[test]
     fn first_free_arg_should_be_a_filter() {
         let args = ~[~"progname", ~"filter"];
@@ -1242,6 +1880,77 @@ mod tests {
         assert!((opts.run_ignored));
     }
 
+    #[test]
+    fn parse_format_option() {
+        let args = ~[~"progname", ~"--format", ~"json"];
+        let opts = match parse_opts(args) {
+            Some(Ok(o)) => o,
+            _ => fail2!("Malformed arg in parse_format_option")
+        };
+        assert_eq!(opts.format, JsonOutput);
+
+        let args = ~[~"progname", ~"--format", ~"xml"];
+        match parse_opts(args) {
+            Some(Err(_)) => (),
+            _ => fail2!("--format xml should have been rejected")
+        }
+    }
+
+    #[test]
+    fn json_output_is_one_event_per_line() {
+        let s = do io::with_str_writer |wr| {
+            let st = ConsoleTestState {
+                out: wr,
+                log_out: None,
+                term: term::StyledWriter::plain(wr),
+                use_color: false,
+                format: JsonOutput,
+                total: 0u,
+                passed: 0u,
+                failed: 0u,
+                ignored: 0u,
+                measured: 0u,
+                metrics: MetricMap::new(),
+                failures: ~[]
+            };
+            let desc = TestDesc {
+                name: StaticTestName("a::b"),
+                ignore: false,
+                should_fail: false
+            };
+            st.write_test_start(&desc);
+            st.write_result(&desc, &TrFailed, &Some(~"boom"));
+            st.write_result(&desc, &TrIgnored, &None);
+        };
+
+        let events = s.line_iter().map(|l| json::from_str(l).unwrap()).to_owned_vec();
+        assert_eq!(events.len(), 3);
+        let expected = [("start", None), ("failed", Some(~"boom")), ("ignored", None)];
+        for (event, &(name, ref msg)) in events.iter().zip(expected.iter()) {
+            match *event {
+                json::Object(ref o) => {
+                    assert_eq!(o.find(&~"event"), Some(&json::String(name.to_owned())));
+                    assert_eq!(o.find(&~"name"), Some(&json::String(~"a::b")));
+                    assert_eq!(o.find(&~"message").map(|m| m.clone()),
+                               msg.clone().map(|m| json::String(m)));
+                }
+                _ => fail2!("expected a JSON object")
+            }
+        }
+    }
+
+    // A test called `name` that does nothing
+    fn dummy_test(name: ~str) -> TestDescAndFn {
+        TestDescAndFn {
+            desc: TestDesc {
+                name: DynTestName(name),
+                ignore: false,
+                should_fail: false
+            },
+            testfn: DynTestFn(|| {}),
+        }
+    }
+
     #[test]
     pub fn filter_for_ignored_option() {
         fn dummy() {}
@@ -1258,7 +1967,14 @@ mod tests {
             ratchet_noise_percent: None,
             ratchet_metrics: None,
             save_metrics: None,
-            test_shard: None
+            save_failures: None,
+            rerun_failed: None,
+            test_shard: None,
+            test_timeout: None,
+            bench_samples: None,
+            baseline: None,
+            max_regression: None,
+            format: PrettyOutput
         };
 
         let tests = ~[
@@ -1286,6 +2002,103 @@ mod tests {
         assert!(filtered[0].desc.ignore == false);
     }
 
+    #[test]
+    fn parse_shard_option() {
+        assert_eq!(parse_shard("1/1"), Ok((0, 1)));
+        assert_eq!(parse_shard("3/4"), Ok((2, 4)));
+        for bad in ["0/4", "5/4", "1/0", "1.4", "a/b", "1/2/3"].iter() {
+            assert!(parse_shard(*bad).is_err());
+        }
+
+        let args = ~[~"progname", ~"--shard", ~"2/3"];
+        let opts = match parse_opts(args) {
+            Some(Ok(o)) => o,
+            _ => fail2!("Malformed arg in parse_shard_option")
+        };
+        assert_eq!(opts.test_shard, Some((1, 3)));
+
+        let args = ~[~"progname", ~"--shard", ~"1/2", ~"--test-shard", ~"0.2"];
+        assert!(parse_opts(args).unwrap().is_err());
+    }
+
+    #[test]
+    fn shards_cover_every_test_once() {
+        let names = [~"d", ~"a", ~"e", ~"c", ~"b", ~"g", ~"f"];
+        let mut seen = ~[];
+        for k in range(0u, 3) {
+            let opts = TestOpts {
+                filter: None,
+                run_ignored: false,
+                logfile: None,
+                run_tests: true,
+                run_benchmarks: false,
+                ratchet_noise_percent: None,
+                ratchet_metrics: None,
+                save_metrics: None,
+                save_failures: None,
+                rerun_failed: None,
+                test_shard: Some((k, 3)),
+                test_timeout: None,
+                bench_samples: None,
+                baseline: None,
+                max_regression: None,
+                format: PrettyOutput
+            };
+            let tests = names.map(|name| dummy_test(name.clone()));
+            let shard = filter_tests(&opts, tests);
+            assert!(shard.len() == 2 || shard.len() == 3);
+            for t in shard.iter() {
+                seen.push(t.desc.name.to_str());
+            }
+        }
+        sort::tim_sort(seen);
+        assert_eq!(seen, ~[~"a", ~"b", ~"c", ~"d", ~"e", ~"f", ~"g"]);
+    }
+
+    #[test]
+    fn rerun_only_saved_failures() {
+        let dpth = TempDir::new("test-failures").expect("missing test for failures");
+        let pth = dpth.path().push("failures");
+
+        do io::with_str_writer |wr| {
+            let failed = |name: &'static str| TestDesc {
+                name: StaticTestName(name),
+                ignore: false,
+                should_fail: false
+            };
+            let st = ConsoleTestState {
+                out: wr,
+                log_out: None,
+                term: term::StyledWriter::plain(wr),
+                use_color: false,
+                format: PrettyOutput,
+                total: 0u,
+                passed: 0u,
+                failed: 0u,
+                ignored: 0u,
+                measured: 0u,
+                metrics: MetricMap::new(),
+                failures: ~[failed("c"), failed("a")]
+            };
+            st.save_failures(&pth);
+        };
+        assert_eq!(load_failures(&pth), Ok(~[~"a", ~"c"]));
+
+        let args = ~[~"progname", ~"--rerun-failed", pth.to_str()];
+        let opts = match parse_opts(args) {
+            Some(Ok(o)) => o,
+            _ => fail2!("couldn't parse --rerun-failed")
+        };
+        let tests = [~"a", ~"b", ~"c", ~"d"].map(|name| dummy_test(name.clone()));
+        let rerun = filter_tests(&opts, tests);
+        let names = rerun.map(|t| t.desc.name.to_str());
+        assert_eq!(names, ~[~"a", ~"c"]);
+
+        // A file that can't be read is a bad argument, not a failed run
+        let args = ~[~"progname", ~"--rerun-failed", dpth.path().push("missing").to_str()];
+        assert!(parse_opts(args).unwrap().is_err());
+    }
+
     #[test]
     pub fn sort_tests() {
         let opts = TestOpts {
@@ -1297,7 +2110,14 @@ mod tests {
             ratchet_noise_percent: None,
             ratchet_metrics: None,
             save_metrics: None,
-            test_shard: None
+            save_failures: None,
+            rerun_failed: None,
+            test_shard: None,
+            test_timeout: None,
+            bench_samples: None,
+            baseline: None,
+            max_regression: None,
+            format: PrettyOutput
         };
 
         let names =
@@ -1307,23 +2127,7 @@ mod tests {
              ~"test::first_free_arg_should_be_a_filter",
              ~"test::parse_ignored_flag", ~"test::filter_for_ignored_option",
              ~"test::sort_tests"];
-        let tests =
-        {
-            fn testfn() { }
-            let mut tests = ~[];
-            for name in names.iter() {
-                let test = TestDescAndFn {
-                    desc: TestDesc {
-                        name: DynTestName((*name).clone()),
-                        ignore: false,
-                        should_fail: false
-                    },
-                    testfn: DynTestFn(testfn),
-                };
-                tests.push(test);
-            }
-            tests
-        };
+        let tests = names.map(|name| dummy_test(name.clone()));
         let filtered = filter_tests(&opts, tests);
 
         let expected =
@@ -1389,6 +2193,58 @@ mod tests {
         assert_eq!(diff2.len(), 7);
     }
 
+    #[test]
+    fn compare_against_baseline() {
+        let dpth = TempDir::new("test-baseline").expect("missing test for baseline");
+        let pth = dpth.path().push("baseline.json");
+
+        let mut old = MetricMap::new();
+        old.insert_metric("fast", 1000.0, 2.0);
+        old.insert_metric("slow", 1000.0, 2.0);
+        old.insert_metric("gone", 1000.0, 2.0);
+        old.save(&pth);
+
+        let mut new = MetricMap::new();
+        new.insert_metric("fast", 900.0, 2.0);
+        new.insert_metric("slow", 1100.0, 2.0);
+
+        let check = |max: Option<f64>| -> (bool, ~str) {
+            let mut ok = false;
+            let s = do io::with_str_writer |wr| {
+                let st = ConsoleTestState {
+                    out: wr,
+                    log_out: None,
+                    term: term::StyledWriter::plain(wr),
+                    use_color: false,
+                    format: PrettyOutput,
+                    total: 0u,
+                    passed: 0u,
+                    failed: 0u,
+                    ignored: 0u,
+                    measured: 0u,
+                    metrics: new.clone(),
+                    failures: ~[]
+                };
+                ok = st.write_baseline_comparison(&pth, max);
+            };
+            (ok, s)
+        };
+
+        let (ok, s) = check(None);
+        assert!(ok);
+        assert!(s.contains("fast: 1000 -> 900 (-10.00%) improved"));
+        assert!(s.contains("slow: 1000 -> 1100 (+10.00%) regressed"));
+        assert!(s.contains("gone: removed"));
+        let (ok, _) = check(Some(20.0));
+        assert!(ok);
+        let (ok, s) = check(Some(5.0));
+        assert!(!ok);
+        assert!(s.contains("1 metrics regressed by more than 5%"));
+
+        // The baseline is never rewritten
+        assert_eq!(MetricMap::load(&pth), old);
+    }
+
     pub fn ratchet_test() {
 
         let dpth = TempDir::new("test-ratchet").expect("missing test for ratchet");
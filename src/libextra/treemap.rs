@@ -213,6 +213,16 @@ impl<K: TotalOrd, V> TreeMap<K, V> {
         }
     }
 
+    /// Return a lazy iterator over the key-value pairs whose keys are not less
+    /// than `lo` and less than `hi`.
+    /// If no keys in the map are in that range an empty iterator is returned.
+    pub fn range_iter<'a>(&'a self, lo: &K, hi: &K) -> TreeMapRangeIterator<'a, K, V> {
+        TreeMapRangeIterator {
+            iter: self.lower_bound_iter(lo),
+            end: self.lower_bound_iter(hi).next().map(|(k, _)| k)
+        }
+    }
+
     /// Get a lazy iterator that consumes the treemap.
     pub fn move_iter(self) -> TreeMapMoveIterator<K, V> {
         let TreeMap { root: root, length: length } = self;
@@ -292,6 +302,31 @@ impl<'self, K, V> Iterator<(&'self K, &'self V)> for TreeMapRevIterator<'self, K
     }
 }
 
+/// Lazy forward iterator over a range of a map
+pub struct TreeMapRangeIterator<'self, K, V> {
+    priv iter: TreeMapIterator<'self, K, V>,
+    // The first key past the end of the range, if it is in the map
+    priv end: Option<&'self K>
+}
+
+impl<'self, K: TotalOrd, V> Iterator<(&'self K, &'self V)> for TreeMapRangeIterator<'self, K, V> {
+    /// Advance the iterator to the next node (in order) and return a
+    /// tuple with a reference to the key and value. If there are no
+    /// more nodes in the range, return `None`.
+    fn next(&mut self) -> Option<(&'self K, &'self V)> {
+        match self.iter.next() {
+            Some((k, _)) if self.end.map_default(false, |end| k.cmp(end) != Less) => None,
+            next => next
+        }
+    }
+
+    #[inline]
+    fn size_hint(&self) -> (uint, Option<uint>) {
+        let (_, upper) = self.iter.size_hint();
+        (0, upper)
+    }
+}
+
 /// iter_traverse_left, iter_traverse_right and iter_traverse_complete are used to
 /// initialize TreeMapIterator pointing to element inside tree structure.
 ///
@@ -528,6 +563,14 @@ impl<T: TotalOrd> TreeSet<T> {
         TreeSetIterator{iter: self.map.upper_bound_iter(v)}
     }
 
+    /// Get a lazy iterator over the values that are not less than `lo` and
+    /// less than `hi`.
+    /// If no elements in the set are in that range empty iterator is returned.
+    #[inline]
+    pub fn range_iter<'a>(&'a self, lo: &T, hi: &T) -> TreeSetRangeIterator<'a, T> {
+        TreeSetRangeIterator{iter: self.map.range_iter(lo, hi)}
+    }
+
     /// Visit the values (in-order) representing the difference
     pub fn difference<'a>(&'a self, other: &'a TreeSet<T>) -> Difference<'a, T> {
         Difference{a: self.iter().peekable(), b: other.iter().peekable()}
@@ -556,6 +599,20 @@ pub struct TreeSetIterator<'self, T> {
     priv iter: TreeMapIterator<'self, T, ()>
 }
 
+/// Lazy forward iterator over a range of a set
+pub struct TreeSetRangeIterator<'self, T> {
+    priv iter: TreeMapRangeIterator<'self, T, ()>
+}
+
+impl<'self, T: TotalOrd> Iterator<&'self T> for TreeSetRangeIterator<'self, T> {
+    /// Advance the iterator to the next node (in order). If there are no more nodes in the
+    /// range, return `None`.
+    #[inline]
+    fn next(&mut self) -> Option<&'self T> {
+        do self.iter.next().map |(value, _)| { value }
+    }
+}
+
 /// Lazy backward iterator over a set
 pub struct TreeSetRevIterator<'self, T> {
     priv iter: TreeMapRevIterator<'self, T, ()>
@@ -1099,6 +1156,27 @@ mod test_treemap {
         assert_eq!(end_it.next(), None);
     }
 
+    #[test]
+    fn test_range_iter() {
+        let mut m = TreeMap::new();
+        for i in range(1, 100) {
+            assert!(m.insert(i * 2, i * 4));
+        }
+
+        for lo in range(0, 200) {
+            for hi in range(lo, 202) {
+                let keys: ~[int] = m.range_iter(&lo, &hi).map(|(&k, _)| k).collect();
+                let expected: ~[int] = range(lo, hi).filter(|k| *k >= 2 && *k <= 198 && *k % 2 == 0)
+                                                    .collect();
+                assert_eq!(keys, expected);
+            }
+        }
+
+        assert_eq!(m.range_iter(&10, &3).next(), None);
+        let (&k, &v) = m.range_iter(&10, &12).next().unwrap();
+        assert_eq!((k, v), (10, 20));
+    }
+
     #[test]
     fn test_rev_iter() {
         let mut m = TreeMap::new();
@@ -1391,6 +1469,23 @@ mod test_set {
         }
     }
 
+    #[test]
+    fn test_range_iter() {
+        let mut m = TreeSet::new();
+
+        assert!(m.insert(3));
+        assert!(m.insert(0));
+        assert!(m.insert(4));
+        assert!(m.insert(2));
+        assert!(m.insert(1));
+
+        let xs: ~[int] = m.range_iter(&1, &4).map(|&x| x).collect();
+        assert_eq!(xs, ~[1, 2, 3]);
+        let xs: ~[int] = m.range_iter(&3, &10).map(|&x| x).collect();
+        assert_eq!(xs, ~[3, 4]);
+        assert_eq!(m.range_iter(&2, &2).next(), None);
+    }
+
     fn check(a: &[int], b: &[int], expected: &[int],
              f: &fn(&TreeSet<int>, &TreeSet<int>, f: &fn(&int) -> bool) -> bool) {
         let mut set_a = TreeSet::new();
@@ -673,7 +673,10 @@ pub fn to_str(url: &Url) -> ~str {
     let authority = if url.host.is_empty() {
         ~""
     } else {
-        format!("//{}{}", user, url.host)
+        match url.port {
+            Some(ref port) => format!("//{}{}:{}", user, url.host, *port),
+            None => format!("//{}{}", user, url.host),
+        }
     };
 
     let query = if url.query.is_empty() {
@@ -864,6 +867,12 @@ mod tests {
         assert_eq!(from_str(url).unwrap().to_str(), url);
     }
 
+    #[test]
+    fn test_url_with_port_parse_and_format() {
+        let url = ~"ssh://git@example.com:2222/foo/bar.git#0.2";
+        assert_eq!(from_str(url).unwrap().to_str(), url);
+    }
+
     #[test]
     fn test_userless_url_parse_and_format() {
         let url = ~"http://rust-lang.org/doc?s=v#something";
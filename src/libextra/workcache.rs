@@ -12,7 +12,6 @@
 
 use digest::Digest;
 use json;
-use json::ToJson;
 use sha1::Sha1;
 use serialize::{Encoder, Encodable, Decoder, Decodable};
 use arc::{Arc,RWArc};
@@ -44,6 +43,12 @@ use std::{io, os, task};
 *    cfg    os      linux
 *    file   foo.c   <sha1>
 *    url    foo.com <etag>
+*    env    PATH    "/usr/bin:/bin"
+*    value  cc      "gcc 4.8"
+*
+* The `env` and `value` kinds are built in (see `declare_env` and
+* `declare_value`); a freshness function must be supplied for every other
+* kind.
 *
 * Works are conceptually single units, but we store them most of the time
 * in maps of the form (type,name) => value. These are WorkMaps.
@@ -115,6 +120,15 @@ struct KindMap(TreeMap<~str, ~str>);
 impl WorkMap {
     fn new() -> WorkMap { WorkMap(TreeMap::new()) }
 
+    // The number of works, counting each kind of a name separately
+    fn num_works(&self) -> uint {
+        let mut n = 0;
+        for (_, kindmap) in self.iter() {
+            n += kindmap.len();
+        }
+        n
+    }
+
     fn insert_work_key(&mut self, k: WorkKey, val: ~str) {
         let WorkKey { kind, name } = k;
         match self.find_mut(&name) {
@@ -128,22 +142,78 @@ impl WorkMap {
 }
 
 pub struct Database {
-    db_filename: Path,
+    // None if the database is only kept in memory
+    db_filename: Option<Path>,
     db_cache: TreeMap<~str, ~str>,
-    db_dirty: bool
+    db_dirty: bool,
+    // Held from when the database is loaded until it's saved, so that
+    // another process can't save its own results in between only to have
+    // them overwritten
+    db_lock: Option<os::FileLock>
 }
 
+// The version of the on-disk format that `save` writes. Version 0 databases
+// are a bare map from keys to cached results; later versions wrap that map
+// in an object that also records the version.
+static DB_VERSION: uint = 1;
+
 impl Database {
 
+    /// Open the database stored at `p`, failing if it can't be read.
     pub fn new(p: Path) -> Database {
-        let mut rslt = Database {
-            db_filename: p,
+        match Database::try_new(p) {
+            Ok(db) => db,
+            Err(e) => fail2!("{}", e)
+        }
+    }
+
+    /// Open the database stored at `p`, or return why it can't be used:
+    /// because it is corrupt, or was written by a newer version of workcache.
+    /// Databases written by older versions are migrated.
+    ///
+    /// Other processes that share the database wait for this one to be
+    /// dropped before they can open it.
+    pub fn try_new(p: Path) -> Result<Database, ~str> {
+        let mut rslt = Database::new_in_memory();
+        // With no directory to hold it yet, there's nothing to load, and
+        // the lock is only taken when saving
+        if os::path_is_dir(&p.dir_path()) {
+            rslt.db_lock = Some(lock_db(&p));
+        }
+        if os::path_exists(&p) {
+            match rslt.load(&p) {
+                Ok(()) => (),
+                Err(e) => return Err(e)
+            }
+        }
+        rslt.db_filename = Some(p);
+        Ok(rslt)
+    }
+
+    /// Create an empty database that is never saved, so that it can be used
+    /// without touching the filesystem.
+    pub fn new_in_memory() -> Database {
+        Database {
+            db_filename: None,
             db_cache: TreeMap::new(),
-            db_dirty: false
-        };
-        if os::path_exists(&rslt.db_filename) {
-            rslt.load();
+            db_dirty: false,
+            db_lock: None
         }
+    }
+
+    /// Discard the database stored at `p`, if there is one, and return an
+    /// empty database in its place. This recovers from a database that
+    /// `try_new` can't read.
+    pub fn reset(p: Path) -> Database {
+        let mut rslt = Database::new_in_memory();
+        if os::path_is_dir(&p.dir_path()) {
+            rslt.db_lock = Some(lock_db(&p));
+        }
+        if os::path_exists(&p) && !os::remove_file(&p) {
+            fail2!("Couldn't remove workcache database {}: {}",
+                   p.to_str(), os::last_os_error());
+        }
+        rslt.db_filename = Some(p);
         rslt
     }
 
@@ -158,6 +228,18 @@ impl Database {
         }
     }
 
+    /// The declared inputs of every result cached for `fn_name`.
+    pub fn declared_inputs_for(&self, fn_name: &str) -> ~[WorkMap] {
+        let mut rslt = ~[];
+        for (k, _) in self.db_cache.iter() {
+            let (name, declared_inputs): (~str, WorkMap) = json_decode(*k);
+            if name.as_slice() == fn_name {
+                rslt.push(declared_inputs);
+            }
+        }
+        rslt
+    }
+
     pub fn cache(&mut self,
                  fn_name: &str,
                  declared_inputs: &WorkMap,
@@ -173,27 +255,145 @@ impl Database {
     }
 
     // FIXME #4330: This should have &mut self and should set self.db_dirty to false.
-    fn save(&self) {
-        let f = io::file_writer(&self.db_filename, [io::Create, io::Truncate]).unwrap();
-        self.db_cache.to_json().to_pretty_writer(f);
+    fn save(&self, path: &Path) {
+        // Usually the lock has been held since the database was opened
+        let _lock = match self.db_lock {
+            Some(_) => None,
+            None => Some(lock_db(path))
+        };
+        // Written straight from the cache, with sorted keys, so that the file
+        // only changes where the cache did.
+        let res = do io::atomic_write(path) |f| {
+            let mut w = json::PrettyStreamWriter(f);
+            w.start_object();
+            w.key("cache");
+            w.start_object();
+            for (k, v) in self.db_cache.iter() {
+                w.key(k.as_slice());
+                w.encode(v);
+            }
+            w.end_object();
+            w.key("version");
+            w.value(&json::Number(DB_VERSION as f64));
+            w.end_object();
+        };
+        match res {
+            Ok(()) => (),
+            Err(e) => fail2!("Couldn't save workcache database {}: {}",
+                            path.to_str(), e)
+        }
     }
 
-    fn load(&mut self) {
+    fn load(&mut self, path: &Path) -> Result<(), ~str> {
         assert!(!self.db_dirty);
-        assert!(os::path_exists(&self.db_filename));
-        let f = io::file_reader(&self.db_filename);
-        match f {
-            Err(e) => fail2!("Couldn't load workcache database {}: {}",
-                            self.db_filename.to_str(), e.to_str()),
-            Ok(r) =>
-                match json::from_reader(r) {
-                    Err(e) => fail2!("Couldn't parse workcache database (from file {}): {}",
-                                    self.db_filename.to_str(), e.to_str()),
-                    Ok(r) => {
-                        let mut decoder = json::Decoder(r);
-                        self.db_cache = Decodable::decode(&mut decoder);
-                    }
+        assert!(os::path_exists(path));
+        assert!(self.db_lock.is_some());
+        let r = match io::file_reader(path) {
+            Err(e) => return Err(format!("Couldn't load workcache database {}: {}",
+                                         path.to_str(), e.to_str())),
+            Ok(r) => r
+        };
+        // The database can get large, so decode it as it is being parsed
+        // rather than building a json::Json for all of it first.
+        match decode_db(&mut json::stream_from_reader(r)) {
+            Ok(cache) => {
+                self.db_cache = cache;
+                Ok(())
             }
+            Err(e) => Err(format!("Couldn't read workcache database {}: {}",
+                                  path.to_str(), e))
+        }
+    }
+}
+
+// Other processes may share a database, so it is only read or written while
+// holding a lock on a file next to it. The lock isn't reentrant, so a
+// process can only have the database open once at a time.
+fn lock_db(path: &Path) -> os::FileLock {
+    let lock_path = Path(path.to_str() + ".lock");
+    match os::lock_file(&lock_path) {
+        Some(lock) => lock,
+        None => fail2!("Couldn't lock workcache database {}: {}",
+                      path.to_str(), os::last_os_error())
+    }
+}
+
+// Decode any version of the database format up to DB_VERSION. Version 0
+// was a bare map of cached results; since version 1 they are kept under
+// "cache", next to a "version" field.
+fn decode_db<T: Iterator<char>>(p: &mut json::StreamParser<T>)
+                                -> Result<TreeMap<~str, ~str>, ~str> {
+    match p.next() {
+        Some(json::ObjectStart) => (),
+        Some(json::ParseError(e)) => return Err(e.to_str()),
+        _ => return Err(~"expected an object")
+    }
+    let mut version = None;
+    let mut cache = None;
+    let mut bare = TreeMap::new();
+    loop {
+        let key = match p.next() {
+            Some(json::Key(key)) => key,
+            Some(json::ParseError(e)) => return Err(e.to_str()),
+            _ => break
+        };
+        let is_version = key.as_slice() == "version";
+        let is_cache = key.as_slice() == "cache";
+        match p.next() {
+            Some(json::NumberValue(n)) if is_version && n >= 0.0 && n == n.floor() => {
+                version = Some(n as uint);
+            }
+            Some(json::ObjectStart) if is_cache => {
+                match decode_entries(p) {
+                    Ok(entries) => cache = Some(entries),
+                    Err(e) => return Err(e)
+                }
+            }
+            Some(json::StringValue(v)) => {
+                if is_version {
+                    return Err(~"invalid format version");
+                }
+                bare.insert(key, v);
+            }
+            Some(json::ParseError(e)) => return Err(e.to_str()),
+            _ if is_version => return Err(~"invalid format version"),
+            _ => return Err(format!("invalid entry for {}", key))
+        }
+    }
+    // Make sure the rest of the file is well-formed too.
+    match p.next() {
+        Some(json::ParseError(e)) => return Err(e.to_str()),
+        _ => ()
+    }
+
+    match (version, cache) {
+        (None, None) => Ok(bare),
+        (None, Some(_)) => Err(~"invalid entry for cache"),
+        (Some(version), _) if version > DB_VERSION => {
+            Err(format!("format version {} is newer than this workcache's ({})",
+                        version, DB_VERSION))
+        }
+        // So far every version stores cached results the same way
+        (Some(_), Some(cache)) => Ok(cache),
+        (Some(_), None) => Err(~"missing cache")
+    }
+}
+
+// Decode the cached results of an object whose start was just parsed.
+fn decode_entries<T: Iterator<char>>(p: &mut json::StreamParser<T>)
+                                     -> Result<TreeMap<~str, ~str>, ~str> {
+    let mut rslt = TreeMap::new();
+    loop {
+        match p.next() {
+            Some(json::Key(k)) => {
+                match p.next() {
+                    Some(json::StringValue(v)) => { rslt.insert(k, v); }
+                    Some(json::ParseError(e)) => return Err(e.to_str()),
+                    _ => return Err(format!("invalid entry for {}", k))
+                }
+            }
+            Some(json::ParseError(e)) => return Err(e.to_str()),
+            _ => return Ok(rslt)
         }
     }
 }
@@ -202,29 +402,124 @@ impl Database {
 impl Drop for Database {
     fn drop(&mut self) {
         if self.db_dirty {
-            self.save();
+            match self.db_filename {
+                Some(ref p) => self.save(p),
+                None => ()
+            }
+        }
+    }
+}
+
+/// How often `exec` could reuse a cached result.
+#[deriving(Clone, Eq)]
+pub struct Stats {
+    hits: uint,
+    misses: uint
+}
+
+impl ToStr for Stats {
+    fn to_str(&self) -> ~str {
+        format!("{} cache hits, {} misses", self.hits, self.misses)
+    }
+}
+
+/// A work that changed since a result was cached.
+#[deriving(Clone, Eq)]
+pub struct Change {
+    /// "declared input", "discovered input" or "discovered output"
+    category: ~str,
+    kind: ~str,
+    name: ~str,
+    /// The value the result was cached with, or None if the work is new
+    old_value: Option<~str>,
+    /// The current value, or None if the work is gone or there is no way
+    /// to compute it
+    new_value: Option<~str>
+}
+
+/// Why `exec` did or didn't reuse a cached result.
+#[deriving(Clone, Eq)]
+pub enum Decision {
+    /// Every input and output was fresh
+    Fresh,
+    /// Nothing was cached for this function
+    NotCached,
+    /// This work changed since the result was cached. For a declared input,
+    /// this means nothing was cached for the current declared inputs
+    Stale(Change)
+}
+
+/// The decision made for one call to `exec`.
+#[deriving(Clone, Eq)]
+pub struct Explanation {
+    fn_name: ~str,
+    decision: Decision,
+    /// How many inputs there were to check: the declared inputs, and the
+    /// discovered ones too if a result was cached
+    inputs: uint
+}
+
+impl ToStr for Explanation {
+    fn to_str(&self) -> ~str {
+        match self.decision {
+            Fresh => format!("{}: up to date", self.fn_name),
+            NotCached => format!("{}: nothing cached", self.fn_name),
+            Stale(ref c) => {
+                let show = |v: &Option<~str>| match *v {
+                    Some(ref v) => v.clone(),
+                    None => ~"(none)"
+                };
+                format!("{}: {} {}:{} changed from {} to {}", self.fn_name,
+                        c.category, c.kind, c.name,
+                        show(&c.old_value), show(&c.new_value))
+            }
         }
     }
 }
 
 pub struct Logger {
-    // FIXME #4432: Fill in
-    a: ()
+    // Whether to keep an Explanation for every decision, not just count them
+    priv explain: bool,
+    priv stats: Stats,
+    priv explanations: ~[Explanation]
 }
 
 impl Logger {
 
     pub fn new() -> Logger {
-        Logger { a: () }
+        Logger {
+            explain: false,
+            stats: Stats { hits: 0, misses: 0 },
+            explanations: ~[]
+        }
     }
 
     pub fn info(&self, i: &str) {
         io::println(~"workcache: " + i);
     }
+
+    fn record(&mut self, fn_name: &str, decision: Decision, inputs: uint) {
+        match decision {
+            Fresh => self.stats.hits += 1,
+            _ => self.stats.misses += 1
+        }
+        if self.explain {
+            self.explanations.push(Explanation {
+                fn_name: fn_name.to_owned(),
+                decision: decision,
+                inputs: inputs
+            });
+        }
+    }
 }
 
 pub type FreshnessMap = TreeMap<~str,extern fn(&str,&str)->bool>;
 
+/// Map from kinds to a function that computes the current value of a work
+/// of that kind from its name, or None if it no longer exists. These are
+/// only used to explain why a result was stale.
+pub type DigestMap = TreeMap<~str,extern fn(&str)->Option<~str>>;
+
 #[deriving(Clone)]
 pub struct Context {
     db: RWArc<Database>,
@@ -236,7 +531,8 @@ pub struct Context {
     /// For example, in the file case, this would read the file off disk,
     /// hash it, and return the result of comparing the given hash and the
     /// read hash for equality.
-    freshness: Arc<FreshnessMap>
+    freshness: Arc<FreshnessMap>,
+    digests: Arc<DigestMap>
 }
 
 pub struct Prep<'self> {
@@ -278,6 +574,59 @@ fn digest<T:Encodable<json::Encoder>>(t: &T) -> ~str {
     (*sha).result_str()
 }
 
+// The value of an `env` work is the JSON encoding of the variable's value,
+// so that an unset variable differs from an empty one.
+fn env_value(name: &str) -> ~str {
+    json_encode(&os::getenv(name))
+}
+
+fn env_is_fresh(name: &str, val: &str) -> bool {
+    env_value(name).as_slice() == val
+}
+
+// A declared value is part of the key that results are cached under, so a
+// changed value finds a different entry rather than a stale one.
+fn value_is_fresh(_name: &str, _val: &str) -> bool {
+    true
+}
+
+// The works whose values differ between `old` and `new`, including those
+// only in one of them.
+fn diff_works(cat: &str, old: &WorkMap, new: &WorkMap) -> ~[Change] {
+    fn flatten(m: &WorkMap) -> TreeMap<WorkKey, ~str> {
+        let mut rslt = TreeMap::new();
+        for (name, kindmap) in m.iter() {
+            for (kind, v) in kindmap.iter() {
+                rslt.insert(WorkKey::new(*kind, *name), v.clone());
+            }
+        }
+        rslt
+    }
+    let old = flatten(old);
+    let new = flatten(new);
+    let change = |k: &WorkKey, old_value: Option<&~str>, new_value: Option<&~str>| Change {
+        category: cat.to_owned(),
+        kind: k.kind.clone(),
+        name: k.name.clone(),
+        old_value: old_value.map(|v| v.clone()),
+        new_value: new_value.map(|v| v.clone())
+    };
+
+    let mut rslt = ~[];
+    for (k, v) in old.iter() {
+        let new_value = new.find(k);
+        if new_value != Some(v) {
+            rslt.push(change(k, Some(v), new_value));
+        }
+    }
+    for (k, v) in new.iter() {
+        if !old.contains_key(k) {
+            rslt.push(change(k, None, Some(v)));
+        }
+    }
+    rslt
+}
+
 fn digest_file(path: &Path) -> ~str {
     let mut sha = ~Sha1::new();
     let s = io::read_whole_file_str(path);
@@ -297,11 +646,20 @@ impl Context {
                               lg: RWArc<Logger>,
                               cfg: Arc<json::Object>,
                               freshness: Arc<FreshnessMap>) -> Context {
+        Context::new_with_digests(db, lg, cfg, freshness, Arc::new(TreeMap::new()))
+    }
+
+    pub fn new_with_digests(db: RWArc<Database>,
+                            lg: RWArc<Logger>,
+                            cfg: Arc<json::Object>,
+                            freshness: Arc<FreshnessMap>,
+                            digests: Arc<DigestMap>) -> Context {
         Context {
             db: db,
             logger: lg,
             cfg: cfg,
-            freshness: freshness
+            freshness: freshness,
+            digests: digests
         }
     }
 
@@ -314,6 +672,29 @@ impl Context {
         blk(&mut p)
     }
 
+    /// How many results have been reused so far, and how many rebuilt.
+    pub fn stats(&self) -> Stats {
+        do self.logger.read |lg| { lg.stats.clone() }
+    }
+
+    /// Keep an explanation of every decision to reuse or rebuild a result
+    /// from now on, for `explanations` to return.
+    pub fn record_explanations(&self) {
+        do self.logger.write |lg| { lg.explain = true }
+    }
+
+    pub fn explanations(&self) -> ~[Explanation] {
+        do self.logger.read |lg| { lg.explanations.clone() }
+    }
+
+    // The current value of a work, if it can be worked out.
+    fn current_value(&self, kind: &str, name: &str) -> Option<~str> {
+        match self.digests.get().find(&kind.to_owned()) {
+            Some(f) => (*f)(name),
+            None if kind == "env" => Some(env_value(name)),
+            None => None
+        }
+    }
 }
 
 impl Exec {
@@ -334,6 +715,12 @@ impl Exec {
                                  dependency_val.to_owned());
     }
 
+    /// Record that the result depends on the environment variable `name`.
+    /// It is considered fresh while the variable keeps its current value.
+    pub fn discover_env(&mut self, name: &str) {
+        self.discover_input("env", name, env_value(name));
+    }
+
     // returns pairs of (kind, name)
     pub fn lookup_discovered_inputs(&self) -> ~[(~str, ~str)] {
         let mut rs = ~[];
@@ -373,14 +760,29 @@ impl<'self> Prep<'self> {
                                  val.to_owned());
     }
 
+    /// Declare the environment variable `name` as an input, with its current
+    /// value.
+    pub fn declare_env(&mut self, name: &str) {
+        self.declare_input("env", name, env_value(name));
+    }
+
+    /// Declare a named input that isn't stored anywhere the workcache can
+    /// check, like the version of a tool or a set of flags. Results cached
+    /// for one value are never used for another.
+    pub fn declare_value(&mut self, name: &str, val: &str) {
+        self.declare_input("value", name, val);
+    }
+
     fn is_fresh(&self, cat: &str, kind: &str,
                 name: &str, val: &str) -> bool {
         let k = kind.to_owned();
         let f = self.ctxt.freshness.get().find(&k);
         debug2!("freshness for: {}/{}/{}/{}", cat, kind, name, val)
         let fresh = match f {
-            None => fail2!("missing freshness-function for '{}'", kind),
-            Some(f) => (*f)(name, val)
+            Some(f) => (*f)(name, val),
+            None if kind == "env" => env_is_fresh(name, val),
+            None if kind == "value" => value_is_fresh(name, val),
+            None => fail2!("missing freshness-function for '{}'", kind)
         };
         do self.ctxt.logger.write |lg| {
             if fresh {
@@ -394,15 +796,68 @@ impl<'self> Prep<'self> {
         fresh
     }
 
-    fn all_fresh(&self, cat: &str, map: &WorkMap) -> bool {
+    // Only look for the current value of a stale work when explaining,
+    // since it can be as costly as hashing a file.
+    fn first_stale(&self, cat: &str, map: &WorkMap, explain: bool) -> Option<Change> {
         for (k_name, kindmap) in map.iter() {
             for (k_kind, v) in kindmap.iter() {
-               if ! self.is_fresh(cat, *k_kind, *k_name, *v) {
-                  return false;
+                if !self.is_fresh(cat, *k_kind, *k_name, *v) {
+                    let new_value = if explain {
+                        self.ctxt.current_value(*k_kind, *k_name)
+                    } else {
+                        None
+                    };
+                    return Some(Change {
+                        category: cat.to_owned(),
+                        kind: k_kind.clone(),
+                        name: k_name.clone(),
+                        old_value: Some(v.clone()),
+                        new_value: new_value
+                    });
+                }
+            }
+        }
+        None
+    }
+
+    fn decide(&self, cached: &Option<(WorkMap, WorkMap, ~str)>, explain: bool) -> Decision {
+        match *cached {
+            None if explain => self.changed_declared_input(),
+            None => NotCached,
+            Some((ref disc_in, ref disc_out, _)) => {
+                let maps = [("declared input", &self.declared_inputs),
+                            ("discovered input", disc_in),
+                            ("discovered output", disc_out)];
+                for &(cat, map) in maps.iter() {
+                    match self.first_stale(cat, map, explain) {
+                        Some(change) => return Stale(change),
+                        None => ()
+                    }
+                }
+                Fresh
+            }
+        }
+    }
+
+    // Nothing is cached for the current declared inputs, since they are part
+    // of the key. Explain that by a difference from the declared inputs of
+    // the closest result cached for the same function, if there is one.
+    fn changed_declared_input(&self) -> Decision {
+        let others = do self.ctxt.db.read |db| {
+            db.declared_inputs_for(self.fn_name)
+        };
+        let mut closest: Option<~[Change]> = None;
+        for old in others.iter() {
+            let changes = diff_works("declared input", old, &self.declared_inputs);
+            if !changes.is_empty() &&
+               closest.as_ref().map_default(true, |c| changes.len() < c.len()) {
+                closest = Some(changes);
             }
-          }
         }
-        return true;
+        match closest {
+            Some(changes) => Stale(changes[0].clone()),
+            None => NotCached
+        }
     }
 
     pub fn exec<T:Send +
@@ -412,33 +867,35 @@ impl<'self> Prep<'self> {
         self.exec_work(blk).unwrap()
     }
 
+    /// Like `exec`, but on a cache miss runs `blk` in the calling task
+    /// rather than a new one, so that it can share state with the caller
+    /// that can't be sent between tasks.
+    pub fn exec_here<T:Send +
+        Encodable<json::Encoder> +
+        Decodable<json::Decoder>>(
+            &'self self, blk: &fn(&mut Exec) -> T) -> T {
+        match self.lookup() {
+            Some(v) => v,
+            None => {
+                let mut exe = Exec {
+                    discovered_inputs: WorkMap::new(),
+                    discovered_outputs: WorkMap::new(),
+                };
+                let v = blk(&mut exe);
+                self.record(&exe, &v);
+                v
+            }
+        }
+    }
+
     fn exec_work<T:Send +
         Encodable<json::Encoder> +
         Decodable<json::Decoder>>( // FIXME(#5121)
             &'self self, blk: ~fn(&mut Exec) -> T) -> Work<'self, T> {
-        let mut bo = Some(blk);
-
-        debug2!("exec_work: looking up {} and {:?}", self.fn_name,
-               self.declared_inputs);
-        let cached = do self.ctxt.db.read |db| {
-            db.prepare(self.fn_name, &self.declared_inputs)
-        };
-
-        match cached {
-            Some((ref disc_in, ref disc_out, ref res))
-            if self.all_fresh("declared input",&self.declared_inputs) &&
-               self.all_fresh("discovered input", disc_in) &&
-               self.all_fresh("discovered output", disc_out) => {
-                debug2!("Cache hit!");
-                debug2!("Trying to decode: {:?} / {:?} / {}",
-                       disc_in, disc_out, *res);
-                Work::from_value(json_decode(*res))
-            }
-
-            _ => {
-                debug2!("Cache miss!");
+        match self.lookup() {
+            Some(v) => Work::from_value(v),
+            None => {
                 let (port, chan) = oneshot();
-                let blk = bo.take_unwrap();
                 let chan = Cell::new(chan);
 
 // What happens if the task fails?
@@ -455,6 +912,52 @@ impl<'self> Prep<'self> {
             }
         }
     }
+
+    // The cached result, if it is fresh. Either way the decision is logged.
+    fn lookup<T:Decodable<json::Decoder>>(&self) -> Option<T> {
+        debug2!("lookup: looking up {} and {:?}", self.fn_name,
+               self.declared_inputs);
+        let cached = do self.ctxt.db.read |db| {
+            db.prepare(self.fn_name, &self.declared_inputs)
+        };
+
+        let explain = do self.ctxt.logger.read |lg| { lg.explain };
+        let decision = self.decide(&cached, explain);
+        let fresh = decision == Fresh;
+        let inputs = self.declared_inputs.num_works() + match cached {
+            Some((ref disc_in, _, _)) => disc_in.num_works(),
+            None => 0
+        };
+        do self.ctxt.logger.write |lg| {
+            lg.record(self.fn_name, decision.clone(), inputs);
+        }
+
+        match cached {
+            Some((ref disc_in, ref disc_out, ref res)) if fresh => {
+                debug2!("Cache hit!");
+                debug2!("Trying to decode: {:?} / {:?} / {}",
+                       disc_in, disc_out, *res);
+                Some(json_decode(*res))
+            }
+            _ => {
+                debug2!("Cache miss!");
+                None
+            }
+        }
+    }
+
+    // Caches `v` as the result for the declared inputs, along with what
+    // was discovered while working it out.
+    fn record<T:Encodable<json::Encoder>>(&self, exe: &Exec, v: &T) {
+        let s = json_encode(v);
+        do self.ctxt.db.write |db| {
+            db.cache(self.fn_name,
+                     &self.declared_inputs,
+                     &exe.discovered_inputs,
+                     &exe.discovered_outputs,
+                     s);
+        }
+    }
 }
 
 impl<'self, T:Send +
@@ -475,14 +978,7 @@ impl<'self, T:Send +
             WorkValue(v) => v,
             WorkFromTask(prep, port) => {
                 let (exe, v) = port.recv();
-                let s = json_encode(&v);
-                do prep.ctxt.db.write |db| {
-                    db.cache(prep.fn_name,
-                             &prep.declared_inputs,
-                             &exe.discovered_inputs,
-                             &exe.discovered_outputs,
-                             s);
-                }
+                prep.record(&exe, &v);
                 v
             }
         }
@@ -536,3 +1032,222 @@ fn test() {
 
     io::println(s);
 }
+
+#[test]
+fn test_env_and_value_inputs() {
+    use std::os;
+
+    let db_path = os::self_exe_path().expect("workcache::test failed").pop()
+        .push("env-db.json");
+    if os::path_exists(&db_path) {
+        os::remove_file(&db_path);
+    }
+
+    let cx = Context::new(RWArc::new(Database::new(db_path)),
+                          RWArc::new(Logger::new()),
+                          Arc::new(TreeMap::new()));
+
+    // Each returns `tag` if it runs, or the tag of the run it was cached from
+    fn declared(cx: &Context, tag: &str, version: &str) -> ~str {
+        do cx.with_prep("declared") |prep| {
+            prep.declare_env("WORKCACHE_TEST_DECLARED");
+            prep.declare_value("version", version);
+            let tag = tag.to_owned();
+            do prep.exec |_exe| { tag.clone() }
+        }
+    }
+    fn discovered(cx: &Context, tag: &str) -> ~str {
+        do cx.with_prep("discovered") |prep| {
+            let tag = tag.to_owned();
+            do prep.exec |exe| {
+                exe.discover_env("WORKCACHE_TEST_DISCOVERED");
+                tag.clone()
+            }
+        }
+    }
+
+    os::setenv("WORKCACHE_TEST_DECLARED", "a");
+    assert_eq!(declared(&cx, "1", "1.0"), ~"1");
+    assert_eq!(declared(&cx, "2", "1.0"), ~"1");
+    assert_eq!(declared(&cx, "3", "2.0"), ~"3");
+    os::setenv("WORKCACHE_TEST_DECLARED", "b");
+    assert_eq!(declared(&cx, "4", "2.0"), ~"4");
+    os::unsetenv("WORKCACHE_TEST_DECLARED");
+    assert_eq!(declared(&cx, "5", "2.0"), ~"5");
+
+    os::setenv("WORKCACHE_TEST_DISCOVERED", "a");
+    assert_eq!(discovered(&cx, "1"), ~"1");
+    assert_eq!(discovered(&cx, "2"), ~"1");
+    os::setenv("WORKCACHE_TEST_DISCOVERED", "");
+    assert_eq!(discovered(&cx, "3"), ~"3");
+    assert_eq!(discovered(&cx, "4"), ~"3");
+}
+
+#[test]
+fn test_exec_here() {
+    use std::os;
+
+    let db_path = os::self_exe_path().expect("workcache::test failed").pop()
+        .push("here-db.json");
+    if os::path_exists(&db_path) {
+        os::remove_file(&db_path);
+    }
+
+    let cx = Context::new(RWArc::new(Database::new(db_path)),
+                          RWArc::new(Logger::new()),
+                          Arc::new(TreeMap::new()));
+
+    // Managed state can't be sent to another task, but can be used here
+    let runs = @mut 0;
+    let run = |version: &str| -> ~str {
+        do cx.with_prep("here") |prep| {
+            prep.declare_value("version", version);
+            do prep.exec_here |exe| {
+                exe.discover_env("WORKCACHE_TEST_HERE");
+                *runs += 1;
+                format!("run {}", *runs)
+            }
+        }
+    };
+
+    assert_eq!(run("1.0"), ~"run 1");
+    assert_eq!(run("1.0"), ~"run 1");
+    assert_eq!(run("2.0"), ~"run 2");
+    assert_eq!(*runs, 2);
+    assert_eq!(cx.stats().misses, 2);
+}
+
+#[test]
+fn test_database_versions() {
+    use std::io::WriterUtil;
+    use std::os;
+
+    let db_path = os::self_exe_path().expect("workcache::test failed").pop()
+        .push("versioned-db.json");
+    let write = |s: &str| {
+        io::file_writer(&db_path, [io::Create, io::Truncate]).unwrap().write_str(s);
+    };
+
+    // Version 0 databases are migrated, and saved in the current format
+    write("{\"k\": \"v\"}");
+    {
+        let mut db = Database::new(db_path.clone());
+        assert_eq!(db.db_cache.find(&~"k"), Some(&~"v"));
+        db.db_dirty = true;
+    }
+    let saved = json::from_str(io::read_whole_file_str(&db_path).unwrap()).unwrap();
+    match saved {
+        json::Object(ref obj) => {
+            assert_eq!(obj.find(&~"version"), Some(&json::Number(DB_VERSION as f64)));
+        }
+        _ => fail2!()
+    }
+    assert_eq!(Database::new(db_path.clone()).db_cache.find(&~"k"), Some(&~"v"));
+
+    // Newer and corrupt databases are refused, and can be reset
+    write("{\"version\": 1000, \"cache\": {}}");
+    assert!(Database::try_new(db_path.clone()).is_err());
+    write("{\"k\": ");
+    assert!(Database::try_new(db_path.clone()).is_err());
+    write("{\"version\": 1, \"cache\": {\"k\": 1}}");
+    assert!(Database::try_new(db_path.clone()).is_err());
+
+    let db = Database::reset(db_path.clone());
+    assert!(db.db_cache.is_empty());
+    assert!(!os::path_exists(&db_path));
+}
+
+#[test]
+fn test_database_output_is_stable() {
+    use std::os;
+
+    let db_path = os::self_exe_path().expect("workcache::test failed").pop()
+        .push("stable-db.json");
+    let save = |keys: &[&str]| -> ~str {
+        let mut db = Database::new_in_memory();
+        for k in keys.iter() {
+            db.db_cache.insert(k.to_owned(), ~"v");
+        }
+        db.save(&db_path);
+        io::read_whole_file_str(&db_path).unwrap()
+    };
+
+    let saved = save(["b", "a"]);
+    assert_eq!(saved, save(["a", "b"]));
+    assert_eq!(saved, ~"{\n  \"cache\": {\n    \"a\": \"v\",\n    \"b\": \"v\"\n  },\n" +
+                      "  \"version\": " + DB_VERSION.to_str() + "\n}");
+}
+
+#[test]
+fn test_database_locked_while_open() {
+    use std::os;
+
+    let db_path = os::self_exe_path().expect("workcache::test failed").pop()
+        .push("locked-db.json");
+    let lock_path = Path(db_path.to_str() + ".lock");
+    {
+        let mut db = Database::new(db_path.clone());
+        db.db_dirty = true;
+        // Another process couldn't slip its own results in before this
+        // one's are saved
+        assert!(os::try_lock_file(&lock_path).is_none());
+    }
+    assert!(os::path_exists(&db_path));
+    assert!(os::try_lock_file(&lock_path).is_some());
+}
+
+#[test]
+fn test_in_memory_database() {
+    let cx = Context::new(RWArc::new(Database::new_in_memory()),
+                          RWArc::new(Logger::new()),
+                          Arc::new(TreeMap::new()));
+
+    fn run(cx: &Context, tag: &str) -> ~str {
+        do cx.with_prep("in_memory") |prep| {
+            prep.declare_value("version", "1.0");
+            let tag = tag.to_owned();
+            do prep.exec |_exe| { tag.clone() }
+        }
+    }
+
+    assert_eq!(run(&cx, "1"), ~"1");
+    assert_eq!(run(&cx, "2"), ~"1");
+}
+
+#[test]
+fn test_explanations() {
+    use std::os;
+
+    let cx = Context::new(RWArc::new(Database::new_in_memory()),
+                          RWArc::new(Logger::new()),
+                          Arc::new(TreeMap::new()));
+    cx.record_explanations();
+
+    fn run(cx: &Context, version: &str) -> ~str {
+        do cx.with_prep("explained") |prep| {
+            prep.declare_value("version", version);
+            do prep.exec |exe| {
+                exe.discover_env("WORKCACHE_TEST_EXPLAINED");
+                ~""
+            }
+        }
+    }
+
+    os::setenv("WORKCACHE_TEST_EXPLAINED", "a");
+    run(&cx, "1.0");
+    run(&cx, "1.0");
+    os::setenv("WORKCACHE_TEST_EXPLAINED", "b");
+    run(&cx, "1.0");
+    run(&cx, "2.0");
+
+    assert_eq!(cx.stats(), Stats { hits: 1, misses: 3 });
+    // The declared version, and the discovered variable once it's cached
+    assert_eq!(cx.explanations().map(|e| e.inputs), ~[1, 2, 2, 1]);
+    let explanations = cx.explanations().map(|e| e.to_str());
+    assert_eq!(explanations, ~[
+        ~"explained: nothing cached",
+        ~"explained: up to date",
+        ~"explained: discovered input env:WORKCACHE_TEST_EXPLAINED changed from \
+          \"a\" to \"b\"",
+        ~"explained: declared input value:version changed from 1.0 to 2.0"]);
+}
@@ -16,12 +16,14 @@ use lib::llvm::llvm;
 use lib::llvm::ModuleRef;
 use lib;
 use metadata::common::LinkMeta;
-use metadata::{encoder, csearch, cstore, filesearch};
+use metadata::{encoder, csearch, cstore};
 use middle::trans::context::CrateContext;
 use middle::trans::common::gensym_name;
 use middle::ty;
 use util::ppaux;
 
+use extra::tempfile::TempDir;
+
 use std::c_str::ToCStr;
 use std::char;
 use std::hash::Streaming;
@@ -30,7 +32,7 @@ use std::io;
 use std::os::consts::{macos, freebsd, linux, android, win32};
 use std::os;
 use std::ptr;
-use std::rt::io::Writer;
+use std::rt::io::{Writer, io_error};
 use std::run;
 use std::str;
 use std::vec;
@@ -213,18 +215,25 @@ pub mod write {
     use lib::llvm::llvm;
     use lib::llvm::{ModuleRef, ContextRef};
     use lib;
+    use metadata::common::LinkMeta;
+
+    use extra::arc::Arc;
 
     use std::c_str::ToCStr;
+    use std::comm;
     use std::libc::{c_uint, c_int};
+    use std::os;
     use std::path::Path;
-    use std::run;
+    use std::ptr;
     use std::str;
+    use std::task;
 
     pub fn run_passes(sess: Session,
                       llcx: ContextRef,
                       llmod: ModuleRef,
                       output_type: output_type,
-                      output: &Path) {
+                      output: &Path,
+                      lm: &LinkMeta) {
         unsafe {
             llvm::LLVMInitializePasses();
 
@@ -258,28 +267,9 @@ pub mod write {
 
             configure_llvm(sess);
 
-            let OptLevel = match sess.opts.optimize {
-              session::No => lib::llvm::CodeGenLevelNone,
-              session::Less => lib::llvm::CodeGenLevelLess,
-              session::Default => lib::llvm::CodeGenLevelDefault,
-              session::Aggressive => lib::llvm::CodeGenLevelAggressive,
-            };
-            let use_softfp = sess.opts.debugging_opts & session::use_softfp != 0;
-
-            let tm = do sess.targ_cfg.target_strs.target_triple.with_c_str |T| {
-                do sess.opts.target_cpu.with_c_str |CPU| {
-                    do sess.opts.target_feature.with_c_str |Features| {
-                        llvm::LLVMRustCreateTargetMachine(
-                            T, CPU, Features,
-                            lib::llvm::CodeModelDefault,
-                            lib::llvm::RelocPIC,
-                            OptLevel,
-                            true,
-                            use_softfp
-                        )
-                    }
-                }
-            };
+            let target = TargetOpts::new(sess);
+            let OptLevel = target.opt_level;
+            let tm = create_target_machine(&target);
 
             // Create the two optimizing pass managers. These mirror what clang
             // does, and are by populated by LLVM's default PassManagerBuilder.
@@ -353,6 +343,10 @@ pub mod write {
                         WriteOutputFile(sess, tm, cpm, llmod, output.to_str(),
                                         lib::llvm::AssemblyFile);
                     }
+                    output_type_exe | output_type_object
+                            if sess.opts.codegen_units > 1 => {
+                        write_codegen_units(sess, llmod, target, output, lm);
+                    }
                     output_type_exe | output_type_object => {
                         WriteOutputFile(sess, tm, cpm, llmod, output.to_str(),
                                         lib::llvm::ObjectFile);
@@ -372,6 +366,170 @@ pub mod write {
         }
     }
 
+    // Everything needed to build a target machine. Codegen units are
+    // compiled in their own tasks, which can't see the session.
+    struct TargetOpts {
+        triple: ~str,
+        cpu: ~str,
+        features: ~str,
+        opt_level: lib::llvm::CodeGenOptLevel,
+        use_softfp: bool,
+    }
+
+    impl TargetOpts {
+        fn new(sess: Session) -> TargetOpts {
+            TargetOpts {
+                triple: sess.targ_cfg.target_strs.target_triple.clone(),
+                cpu: sess.opts.target_cpu.clone(),
+                features: sess.opts.target_feature.clone(),
+                opt_level: match sess.opts.optimize {
+                    session::No => lib::llvm::CodeGenLevelNone,
+                    session::Less => lib::llvm::CodeGenLevelLess,
+                    session::Default => lib::llvm::CodeGenLevelDefault,
+                    session::Aggressive => lib::llvm::CodeGenLevelAggressive,
+                },
+                use_softfp: sess.opts.debugging_opts & session::use_softfp != 0,
+            }
+        }
+    }
+
+    unsafe fn create_target_machine(target: &TargetOpts)
+                                    -> lib::llvm::TargetMachineRef {
+        do target.triple.with_c_str |T| {
+            do target.cpu.with_c_str |CPU| {
+                do target.features.with_c_str |Features| {
+                    llvm::LLVMRustCreateTargetMachine(
+                        T, CPU, Features,
+                        lib::llvm::CodeModelDefault,
+                        lib::llvm::RelocPIC,
+                        target.opt_level,
+                        true,
+                        target.use_softfp
+                    )
+                }
+            }
+        }
+    }
+
+    // Generates the object file for an optimized module in
+    // `sess.opts.codegen_units` pieces at once. The module goes through a
+    // bitcode file so that each task can load its own copy into its own
+    // context; each copy is cut down to one unit's share of the
+    // definitions and compiled to `<output>.<i>.o`, and the pieces are
+    // then linked into `output` with `ld -r`. The locals that get promoted
+    // to hidden symbols along the way are named after the crate.
+    unsafe fn write_codegen_units(sess: Session,
+                                  llmod: ModuleRef,
+                                  target: TargetOpts,
+                                  output: &Path,
+                                  lm: &LinkMeta) {
+        let units = sess.opts.codegen_units;
+        let prefix = format!("_rust_cgu.{}.{}.{}", lm.name, lm.vers, lm.extras_hash);
+        let bitcode = output.with_filetype("units.bc");
+        do bitcode.with_c_str |buf| {
+            llvm::LLVMWriteBitcodeToFile(llmod, buf);
+        }
+
+        let target = Arc::new(target);
+        let mut ports = ~[];
+        let mut objects = ~[];
+        for unit in range(0, units) {
+            let object = output.with_filetype(format!("{}.o", unit));
+            let (port, chan) = comm::oneshot();
+            let target = target.clone();
+            let bitcode = bitcode.clone();
+            let dest = object.clone();
+            let prefix = prefix.clone();
+            do task::spawn_sched(task::SingleThreaded) {
+                chan.send(write_codegen_unit(target.get(), &bitcode, unit,
+                                             units, prefix, &dest));
+            }
+            ports.push(port);
+            objects.push(object);
+        }
+        for (unit, port) in ports.move_iter().enumerate() {
+            match port.recv() {
+                Ok(()) => {}
+                Err(e) => sess.err(format!("could not generate code for \
+                                            codegen unit {}: {}", unit, e))
+            }
+        }
+        if !sess.opts.save_temps {
+            os::remove_file(&bitcode);
+        }
+        sess.abort_if_errors();
+
+        let cc_prog = super::get_cc_prog(sess);
+        let mut cc_args = sess.targ_cfg.target_strs.cc_args.clone();
+        cc_args.push_all([~"-nostdlib", ~"-Wl,-r", ~"-o", output.to_str()]);
+        cc_args.extend(&mut objects.iter().map(|o| o.to_str()));
+        let prog = super::run_tool(sess, cc_prog, cc_args, None);
+        if prog.status != 0 {
+            sess.err(format!("linking codegen units with `{}` failed with \
+                              code {}", cc_prog, prog.status));
+            sess.note(format!("{} arguments: {}",
+                        cc_prog, cc_args.connect(" ")));
+            sess.note(str::from_utf8(prog.error + prog.output));
+            sess.abort_if_errors();
+        }
+
+        if !sess.opts.save_temps {
+            for object in objects.iter() {
+                os::remove_file(object);
+            }
+        }
+    }
+
+    unsafe fn write_codegen_unit(target: &TargetOpts,
+                                 bitcode: &Path,
+                                 unit: uint,
+                                 units: uint,
+                                 prefix: &str,
+                                 output: &Path) -> Result<(), ~str> {
+        let last_error = || {
+            let cstr = llvm::LLVMRustGetLastError();
+            if cstr == ptr::null() {
+                ~"unknown LLVM error"
+            } else {
+                str::raw::from_c_str(cstr)
+            }
+        };
+
+        let llcx = llvm::LLVMContextCreate();
+        let llmod = do bitcode.with_c_str |buf| {
+            llvm::LLVMRustParseBitcodeFile(llcx, buf)
+        };
+        if llmod.is_null() {
+            llvm::LLVMContextDispose(llcx);
+            return Err(last_error());
+        }
+        do prefix.with_c_str |prefix| {
+            llvm::LLVMRustPartitionModule(llmod, unit as c_uint,
+                                          units as c_uint, prefix);
+        }
+
+        let tm = create_target_machine(target);
+        if tm.is_null() {
+            llvm::LLVMDisposeModule(llmod);
+            llvm::LLVMContextDispose(llcx);
+            return Err(last_error());
+        }
+        let cpm = llvm::LLVMCreatePassManager();
+        llvm::LLVMRustAddAnalysisPasses(tm, cpm, llmod);
+        llvm::LLVMRustAddLibraryInfo(cpm, llmod);
+        let written = do output.with_c_str |buf| {
+            llvm::LLVMRustWriteOutputFile(tm, cpm, llmod, buf,
+                                          lib::llvm::ObjectFile)
+        };
+        let result = if written { Ok(()) } else { Err(last_error()) };
+
+        llvm::LLVMDisposePassManager(cpm);
+        llvm::LLVMRustDisposeTargetMachine(tm);
+        llvm::LLVMDisposeModule(llmod);
+        llvm::LLVMContextDispose(llcx);
+        result
+    }
+
     pub fn run_assembler(sess: Session, assembly: &Path, object: &Path) {
         let cc_prog = super::get_cc_prog(sess);
 
@@ -380,7 +538,7 @@ pub mod write {
             ~"-o", object.to_str(),
             assembly.to_str()];
 
-        let prog = run::process_output(cc_prog, cc_args);
+        let prog = super::run_tool(sess, cc_prog, cc_args, None);
 
         if prog.status != 0 {
             sess.err(format!("building with `{}` failed with code {}",
@@ -879,6 +1037,28 @@ pub fn output_dll_filename(os: session::Os, lm: LinkMeta) -> ~str {
     format!("{}{}-{}-{}{}", dll_prefix, lm.name, lm.extras_hash, lm.vers, dll_suffix)
 }
 
+/// Static libraries are named like dynamic ones, but with a `.a` suffix, so
+/// the archive of a Rust crate can be found next to its dynamic library.
+pub fn output_staticlib_filename(os: session::Os, lm: LinkMeta) -> ~str {
+    Path(output_dll_filename(os, lm)).with_filetype("a").to_str()
+}
+
+pub fn get_ar_prog(sess: Session) -> ~str {
+    match sess.targ_cfg.os {
+        session::OsAndroid =>
+            match &sess.opts.android_cross_path {
+                &Some(ref path) => {
+                    format!("{}/bin/arm-linux-androideabi-ar", *path)
+                }
+                &None => {
+                    sess.fatal("need Android NDK path for building \
+                                static libraries (--android-cross-path)")
+                }
+            },
+        _ => ~"ar"
+    }
+}
+
 pub fn get_cc_prog(sess: Session) -> ~str {
     // In the future, FreeBSD will use clang as default compiler.
     // It would be flexible to use cc (system's default C compiler)
@@ -905,6 +1085,24 @@ pub fn get_cc_prog(sess: Session) -> ~str {
     }
 }
 
+/// The file the linker writes, given the output file the driver chose.
+/// Libraries get their hash and version in the name.
+pub fn output_filename(sess: Session, out_filename: &Path, lm: LinkMeta) -> Path {
+    if *sess.building_staticlib {
+        out_filename.dir_path().push(output_staticlib_filename(sess.targ_cfg.os, lm))
+    } else if *sess.building_library {
+        let long_libname = output_dll_filename(sess.targ_cfg.os, lm);
+        debug2!("link_meta.name:  {}", lm.name);
+        debug2!("long_libname: {}", long_libname);
+        debug2!("out_filename: {}", out_filename.to_str());
+        debug2!("dirname(out_filename): {}", out_filename.dir_path().to_str());
+
+        out_filename.dir_path().push(long_libname)
+    } else {
+        out_filename.clone()
+    }
+}
+
 // If the user wants an exe generated we need to invoke
 // cc to link the object file with some libs
 pub fn link_binary(sess: Session,
@@ -912,20 +1110,14 @@ pub fn link_binary(sess: Session,
                    out_filename: &Path,
                    lm: LinkMeta) {
 
+    if *sess.building_staticlib {
+        return link_staticlib(sess, obj_filename, out_filename, lm);
+    }
+
     let cc_prog = get_cc_prog(sess);
     // The invocations of cc share some flags across platforms
 
-    let output = if *sess.building_library {
-        let long_libname = output_dll_filename(sess.targ_cfg.os, lm);
-        debug2!("link_meta.name:  {}", lm.name);
-        debug2!("long_libname: {}", long_libname);
-        debug2!("out_filename: {}", out_filename.to_str());
-        debug2!("dirname(out_filename): {}", out_filename.dir_path().to_str());
-
-        out_filename.dir_path().push(long_libname)
-    } else {
-        out_filename.clone()
-    };
+    let output = output_filename(sess, out_filename, lm);
 
     debug2!("output: {}", output.to_str());
     let cc_args = link_args(sess, obj_filename, out_filename, lm);
@@ -935,7 +1127,7 @@ pub fn link_binary(sess: Session,
     }
 
     // We run 'cc' here
-    let prog = run::process_output(cc_prog, cc_args);
+    let prog = run_tool(sess, cc_prog, cc_args, None);
     if 0 != prog.status {
         sess.err(format!("linking with `{}` failed with code {}",
                       cc_prog, prog.status));
@@ -947,7 +1139,9 @@ pub fn link_binary(sess: Session,
 
     // Clean up on Darwin
     if sess.targ_cfg.os == session::OsMacos {
-        run::process_status("dsymutil", [output.to_str()]);
+        do io_error::cond.trap(|_| ()).inside {
+            run::process_status("dsymutil", [output.to_str()]);
+        }
     }
 
     // Remove the temporary object file if we aren't saving temps
@@ -959,6 +1153,168 @@ pub fn link_binary(sess: Session,
     }
 }
 
+// A static library is an archive of the crate's object file along with the
+// objects of every Rust crate it uses, so that C programs only have to link
+// it and the runtime (-lrustrt). The crates it uses have to have been built
+// as static libraries too; their archives are expected next to the dynamic
+// libraries their metadata was loaded from.
+fn link_staticlib(sess: Session,
+                  obj_filename: &Path,
+                  out_filename: &Path,
+                  lm: LinkMeta) {
+    let output = output_filename(sess, out_filename, lm);
+    debug2!("output: {}", output.to_str());
+
+    let mut archives = ~[];
+    for cratepath in cstore::get_used_crate_files(sess.cstore).iter() {
+        let archive = os::make_absolute(&cratepath.with_filetype("a"));
+        if os::path_exists(&archive) {
+            archives.push((archive, archive_member_for(cratepath)));
+        } else {
+            sess.err(format!("cannot bundle `{}` into a static library: \
+                              `{}` does not exist",
+                             cratepath.to_str(), archive.to_str()));
+            sess.note("build it with --staticlib, or as a library with \
+                       #[crate_type = \"staticlib\"]");
+        }
+    }
+    sess.abort_if_errors();
+
+    let tmpdir = match TempDir::new_in(&output.dir_path(), "staticlib") {
+        Some(d) => d,
+        None => sess.fatal(format!("couldn't create a temporary directory in `{}`",
+                                   output.dir_path().to_str()))
+    };
+
+    // The crate's object goes in under a name unique to the crate, so that
+    // libraries bundling this one can pick it out again
+    let member = tmpdir.path().push(archive_member_for(&output));
+    if !os::copy_file(obj_filename, &member) {
+        sess.fatal(format!("couldn't copy `{}` to `{}`",
+                           obj_filename.to_str(), member.to_str()));
+    }
+    // ar adds to an existing archive rather than replacing it
+    os::remove_file(&output);
+    run_ar(sess, "crs", &output, [member.to_str()], None);
+
+    // The archive of a crate this one uses bundles that crate's own
+    // dependencies too. Those are all used crates here as well, so only
+    // the crate's own object is taken from each archive; that way every
+    // crate's object is in the library exactly once.
+    for &(ref archive, ref member) in archives.iter() {
+        run_ar(sess, "x", archive, [member.clone()], Some(tmpdir.path()));
+        run_ar(sess, "q", &output, [tmpdir.path().push(member.as_slice()).to_str()], None);
+    }
+
+    // Stack growth requires __morestack to be linked statically. It's
+    // taken from libmorestack.a rather than from the archives above, which
+    // each bundle their own copy.
+    let morestack = tmpdir.path().push("morestack");
+    if !os::make_dir(&morestack, 0x1c0) { // 700
+        sess.fatal(format!("couldn't create directory `{}`", morestack.to_str()));
+    }
+    run_ar(sess, "x", &os::make_absolute(
+        &sess.filesearch.get_target_lib_path().push("libmorestack.a")),
+           [], Some(&morestack));
+    let members = os::list_dir_path(&morestack).map(|p| p.to_str());
+    if !members.is_empty() {
+        run_ar(sess, "q", &output, members, None);
+    }
+    run_ar(sess, "s", &output, [], None);
+
+    if !sess.opts.save_temps {
+        if ! os::remove_file(obj_filename) {
+            sess.warn(format!("failed to delete object file `{}`",
+                           obj_filename.to_str()));
+        }
+    }
+}
+
+// The name of a crate's own object inside its static library, given the
+// path of the crate's dynamic library or archive
+fn archive_member_for(cratepath: &Path) -> ~str {
+    format!("{}.o", cratepath.filestem().unwrap())
+}
+
+// The linkage the --linkage flags require for the crate `name`, if any
+fn required_linkage(sess: Session, name: &str) -> Option<session::Linkage> {
+    sess.opts.crate_linkage.iter()
+        .find(|&&(ref n, _)| n.as_slice() == name)
+        .map(|&(_, linkage)| linkage)
+}
+
+// Returns the static archive to link the crate `name`, found at `cratepath`,
+// from instead of its dynamic library, if the --linkage flags ask for one
+fn static_archive_for(sess: Session, name: &str, cratepath: &Path) -> Option<Path> {
+    let archive = cratepath.with_filetype("a");
+    match required_linkage(sess, name) {
+        Some(session::LinkDynamic) => None,
+        Some(session::LinkStatic) if !os::path_exists(&archive) => {
+            sess.err(format!("cannot link `{}` statically: `{}` does not exist",
+                             name, archive.to_str()));
+            sess.note("build it with --staticlib, or as a library with \
+                       #[crate_type = \"staticlib\"]");
+            None
+        }
+        Some(session::LinkStatic) => Some(archive),
+        None if sess.opts.linkage == session::LinkStatic &&
+                os::path_exists(&archive) => Some(archive),
+        None => None
+    }
+}
+
+// The names of the members of `archive`
+fn archive_members(sess: Session, archive: &Path) -> ~[~str] {
+    let ar_prog = get_ar_prog(sess);
+    let prog = run_tool(sess, ar_prog, [~"t", archive.to_str()], None);
+    if prog.status != 0 {
+        sess.err(format!("listing `{}` with `{}` failed with code {}",
+                         archive.to_str(), ar_prog, prog.status));
+        sess.note(str::from_utf8(prog.error + prog.output));
+        sess.abort_if_errors();
+    }
+    str::from_utf8(prog.output).line_iter().map(|l| l.to_owned()).collect()
+}
+
+// Runs `prog` in `dir`, if given, and collects its output. Not being able
+// to start it at all, for instance because it isn't installed, is fatal.
+fn run_tool(sess: Session, prog: &str, args: &[~str],
+            dir: Option<&Path>) -> run::ProcessOutput {
+    let mut error = None;
+    let process = do io_error::cond.trap(|e| error = Some(e)).inside {
+        run::Process::new(prog, args, run::ProcessOptions {
+            dir: dir,
+            .. run::ProcessOptions::new()
+        })
+    };
+    match process {
+        Some(process) => {
+            let mut process = process;
+            process.finish_with_output()
+        }
+        None => {
+            let reason = error.map_default(~"unknown error", |e| e.desc.to_owned());
+            sess.fatal(format!("could not exec `{}`: {}", prog, reason))
+        }
+    }
+}
+
+// Runs `ar <command> <archive> <files>`, in `dir` if given
+fn run_ar(sess: Session, command: &str, archive: &Path, files: &[~str],
+          dir: Option<&Path>) {
+    let ar_prog = get_ar_prog(sess);
+    let args = ~[command.to_owned(), archive.to_str()] + files;
+    debug2!("{} {}", ar_prog, args.connect(" "));
+    let prog = run_tool(sess, ar_prog, args, dir);
+    if prog.status != 0 {
+        sess.err(format!("building static library with `{}` failed with code {}",
+                      ar_prog, prog.status));
+        sess.note(format!("{} arguments: {}", ar_prog, args.connect(" ")));
+        sess.note(str::from_utf8(prog.error + prog.output));
+        sess.abort_if_errors();
+    }
+}
+
 pub fn link_args(sess: Session,
                  obj_filename: &Path,
                  out_filename: &Path,
@@ -975,12 +1331,7 @@ pub fn link_args(sess: Session,
     }
 
 
-    let output = if *sess.building_library {
-        let long_libname = output_dll_filename(sess.targ_cfg.os, lm);
-        out_filename.dir_path().push(long_libname)
-    } else {
-        out_filename.clone()
-    };
+    let output = output_filename(sess, out_filename, lm);
 
     // The default library location, we need this to find the runtime.
     // The location of crates will be determined as needed.
@@ -1000,18 +1351,62 @@ pub fn link_args(sess: Session,
     // # Crate linking
 
     let cstore = sess.cstore;
-    let r = cstore::get_used_crate_files(cstore);
-    for cratepath in r.iter() {
+    let r = cstore::get_used_crates(cstore);
+
+    // The static archives an executable links crates from, with their
+    // members. A crate's archive bundles the crates it uses, so those are
+    // linked from there rather than again on their own; each crate's
+    // objects are linked exactly once.
+    let mut archives = ~[];
+    if !*sess.building_library {
+        for &(name, ref cratepath) in r.iter() {
+            if cratepath.filetype() == Some(".rlib") { continue; }
+            match static_archive_for(sess, name, cratepath) {
+                Some(archive) => {
+                    let members = archive_members(sess, &archive);
+                    archives.push((name, archive, members));
+                }
+                None => {}
+            }
+        }
+    }
+
+    for &(name, ref cratepath) in r.iter() {
         if cratepath.filetype() == Some(".rlib") {
             args.push(cratepath.to_str());
             continue;
         }
+        let member = archive_member_for(cratepath);
+        let bundled = archives.iter().find(|&&(n, _, ref members)| {
+            n != name && members.iter().any(|m| *m == member)
+        });
+        match bundled {
+            Some(&(outer, ref archive, _)) => {
+                if required_linkage(sess, name) == Some(session::LinkDynamic) {
+                    sess.err(format!("cannot link `{}` dynamically: it is bundled \
+                                      into `{}`, which is linked statically",
+                                     name, archive.to_str()));
+                    sess.note(format!("link `{}` dynamically as well", outer));
+                }
+                continue;
+            }
+            None => {}
+        }
+        match archives.iter().find(|&&(n, _, _)| n == name) {
+            Some(&(_, ref archive, _)) => {
+                args.push(archive.to_str());
+                continue;
+            }
+            None => {}
+        }
         let dir = cratepath.dirname();
         if !dir.is_empty() { args.push("-L" + dir); }
         let libarg = unlib(sess.targ_cfg, cratepath.filestem().unwrap().to_owned());
         args.push("-l" + libarg);
     }
 
+    sess.abort_if_errors();
+
     let ula = cstore::get_used_link_args(cstore);
     for arg in ula.iter() { args.push(arg.to_owned()); }
 
@@ -1035,7 +1430,7 @@ pub fn link_args(sess: Session,
         args.push("-L" + path.to_str());
     }
 
-    let rustpath = filesearch::rust_path();
+    let rustpath = sess.filesearch.rust_path();
     for path in rustpath.iter() {
         args.push("-L" + path.to_str());
     }
@@ -1044,6 +1439,13 @@ pub fn link_args(sess: Session,
     let used_libs = cstore::get_used_libraries(cstore);
     for l in used_libs.iter() { args.push(~"-l" + *l); }
 
+    // Native libraries the build system asked for on behalf of this crate
+    let crate_linker_args = sess.opts.crate_linker_args;
+    for path in crate_linker_args.search_paths.iter() {
+        args.push("-L" + path.to_str());
+    }
+    for l in crate_linker_args.libs.iter() { args.push(~"-l" + *l); }
+
     if *sess.building_library {
         args.push(lib_cmd);
 
@@ -1099,7 +1501,14 @@ pub fn link_args(sess: Session,
     args.push_all(rpath::get_rpath_flags(sess, &output));
 
     // Finally add all the linker arguments provided on the command line
+    // and by the build system
     args.push_all(sess.opts.linker_args);
+    args.push_all(crate_linker_args.args);
+
+    // Unless the target wants some of its own after those
+    for spec in sess.opts.target_spec.iter() {
+        args.push_all(spec.post_link_args);
+    }
 
     return args;
 }
@@ -119,7 +119,9 @@ pub fn get_rpath_relative_to_output(os: session::Os,
         session::OsWin32 => unreachable!()
     };
 
-    Path(prefix).push_rel(&os::make_absolute(output).get_relative_to(&os::make_absolute(lib)))
+    let output_dir = os::make_absolute(output).dir_path();
+    let lib_dir = os::make_absolute(lib).dir_path();
+    Path(prefix).push_rel(&lib_dir.relative_from(&output_dir).unwrap())
 }
 
 fn get_absolute_rpaths(libs: &[Path]) -> ~[Path] {
@@ -0,0 +1,166 @@
+// Copyright 2013 The Rust Project Developers. See the COPYRIGHT
+// file at the top-level directory of this distribution and at
+// http://rust-lang.org/COPYRIGHT.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+/*!
+ * Target specifications loaded from JSON files, for targets the compiler's
+ * own tables don't know how to build for. A spec is passed with
+ * `--target=path/to/spec.json` and looks like:
+ *
+ *     {
+ *         "llvm-target": "thumbv7m-unknown-linux-gnueabi",
+ *         "data-layout": "e-p:32:32:32-...",
+ *         "linker": "arm-none-eabi-gcc",
+ *         "pre-link-args": ["-mthumb"],
+ *         "post-link-args": ["-Wl,--gc-sections"],
+ *         "cpu": "cortex-m3",
+ *         "features": "+soft-float"
+ *     }
+ *
+ * Only `llvm-target` is required; the architecture and operating system are
+ * read from it as for any other triple, and everything left out falls back
+ * to what the compiler would use for that triple.
+ */
+
+use std::io;
+
+use extra::json;
+
+#[deriving(Clone)]
+pub struct TargetSpec {
+    llvm_target: ~str,
+    data_layout: Option<~str>,
+    linker: Option<~str>,
+    // Passed to the linker before any other argument
+    pre_link_args: ~[~str],
+    // Passed to the linker after every other argument
+    post_link_args: ~[~str],
+    cpu: Option<~str>,
+    features: Option<~str>,
+}
+
+/// Whether the argument to `--target` names a spec file rather than a triple
+pub fn is_spec_path(target: &str) -> bool {
+    target.ends_with(".json")
+}
+
+/// Reads the target specification in the file `path`
+pub fn load(path: &Path) -> Result<TargetSpec, ~str> {
+    let contents = match io::read_whole_file_str(path) {
+        Ok(s) => s,
+        Err(e) => return Err(format!("couldn't read target specification \
+                                      `{}`: {}", path.to_str(), e))
+    };
+    match parse(contents) {
+        Ok(spec) => Ok(spec),
+        Err(e) => Err(format!("invalid target specification `{}`: {}",
+                              path.to_str(), e))
+    }
+}
+
+// Unwraps the result of reading one field, or returns its error
+macro_rules! field(($e:expr) => (
+    match $e { Ok(v) => v, Err(e) => return Err(e) }
+))
+
+/// Parses a target specification from its JSON text
+pub fn parse(s: &str) -> Result<TargetSpec, ~str> {
+    let obj = match json::from_str(s) {
+        Ok(json::Object(obj)) => obj,
+        Ok(_) => return Err(~"expected a JSON object"),
+        Err(e) => return Err(e.to_str())
+    };
+
+    for (key, _) in obj.iter() {
+        if !KEYS.contains(&key.as_slice()) {
+            return Err(format!("unknown key `{}`", *key));
+        }
+    }
+
+    let llvm_target = match field!(string_field(obj, "llvm-target")) {
+        Some(t) => t,
+        None => return Err(~"missing `llvm-target`")
+    };
+    Ok(TargetSpec {
+        llvm_target: llvm_target,
+        data_layout: field!(string_field(obj, "data-layout")),
+        linker: field!(string_field(obj, "linker")),
+        pre_link_args: field!(strings_field(obj, "pre-link-args")),
+        post_link_args: field!(strings_field(obj, "post-link-args")),
+        cpu: field!(string_field(obj, "cpu")),
+        features: field!(string_field(obj, "features")),
+    })
+}
+
+fn string_field(obj: &json::Object, key: &str) -> Result<Option<~str>, ~str> {
+    match obj.find(&key.to_owned()) {
+        None => Ok(None),
+        Some(&json::String(ref s)) => Ok(Some(s.clone())),
+        Some(_) => Err(format!("`{}` should be a string", key))
+    }
+}
+
+fn strings_field(obj: &json::Object, key: &str) -> Result<~[~str], ~str> {
+    let list = match obj.find(&key.to_owned()) {
+        None => return Ok(~[]),
+        Some(&json::List(ref list)) => list,
+        Some(_) => return Err(format!("`{}` should be a list of strings", key))
+    };
+    let mut result = ~[];
+    for item in list.iter() {
+        match *item {
+            json::String(ref s) => result.push(s.clone()),
+            _ => return Err(format!("`{}` should be a list of strings", key))
+        }
+    }
+    Ok(result)
+}
+
+static KEYS: &'static [&'static str] = &[
+    "llvm-target", "data-layout", "linker", "pre-link-args", "post-link-args",
+    "cpu", "features",
+];
+
+#[cfg(test)]
+mod test {
+    use super::parse;
+
+    #[test]
+    fn test_parse_minimal() {
+        let spec = parse("{\"llvm-target\": \"arm-unknown-linux-gnueabi\"}").unwrap();
+        assert_eq!(spec.llvm_target, ~"arm-unknown-linux-gnueabi");
+        assert!(spec.data_layout.is_none());
+        assert!(spec.pre_link_args.is_empty());
+    }
+
+    #[test]
+    fn test_parse_full() {
+        let spec = parse("{\"llvm-target\": \"arm-unknown-linux-gnueabi\",
+                           \"linker\": \"arm-none-eabi-gcc\",
+                           \"pre-link-args\": [\"-mthumb\"],
+                           \"post-link-args\": [\"-Wl,--gc-sections\"],
+                           \"cpu\": \"cortex-m3\"}").unwrap();
+        assert_eq!(spec.linker, Some(~"arm-none-eabi-gcc"));
+        assert_eq!(spec.pre_link_args, ~[~"-mthumb"]);
+        assert_eq!(spec.post_link_args, ~[~"-Wl,--gc-sections"]);
+        assert_eq!(spec.cpu, Some(~"cortex-m3"));
+        assert!(spec.features.is_none());
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        assert!(parse("{}").is_err());
+        assert!(parse("[]").is_err());
+        assert!(parse("{\"llvm-target\": 1}").is_err());
+        assert!(parse("{\"llvm-target\": \"x86_64-unknown-linux-gnu\",
+                        \"pre-link-args\": \"-m64\"}").is_err());
+        assert!(parse("{\"llvm-target\": \"x86_64-unknown-linux-gnu\",
+                        \"linkr\": \"gcc\"}").is_err());
+    }
+}
@@ -10,6 +10,7 @@
 
 
 use back::link;
+use back::target_spec;
 use back::{arm, x86, x86_64, mips};
 use driver::session::{Aggressive};
 use driver::session::{Session, Session_, No, Less, Default};
@@ -42,6 +43,7 @@ use syntax::parse::token;
 use syntax::print::{pp, pprust};
 use syntax;
 
+#[deriving(Clone, Eq)]
 pub enum PpMode {
     PpmNormal,
     PpmExpanded,
@@ -50,6 +52,31 @@ pub enum PpMode {
     PpmExpandedIdentified
 }
 
+static pp_modes: &'static [(&'static str, PpMode)] = &'static [
+    ("normal", PpmNormal),
+    ("expanded", PpmExpanded),
+    ("typed", PpmTyped),
+    ("identified", PpmIdentified),
+    ("expanded,identified", PpmExpandedIdentified)];
+
+impl PpMode {
+    /// The name `--pretty` knows this mode by
+    pub fn name(&self) -> &'static str {
+        for &(name, ppm) in pp_modes.iter() {
+            if ppm == *self { return name }
+        }
+        unreachable!()
+    }
+
+    /// Whether the crate is printed after macro expansion
+    pub fn expands(&self) -> bool {
+        match *self {
+            PpmExpanded | PpmExpandedIdentified | PpmTyped => true,
+            PpmNormal | PpmIdentified => false
+        }
+    }
+}
+
 /**
  * The name used for source code that doesn't originate in a file
  * (e.g. source from stdin or a string)
@@ -113,7 +140,7 @@ pub fn build_configuration(sess: Session) ->
 }
 
 // Convert strings provided as --cfg [cfgspec] into a crate_cfg
-fn parse_cfgspecs(cfgspecs: ~[~str], demitter: @diagnostic::Emitter)
+pub fn parse_cfgspecs(cfgspecs: ~[~str], demitter: @diagnostic::Emitter)
                   -> ast::CrateConfig {
     do cfgspecs.move_iter().map |s| {
         let sess = parse::new_parse_sess(Some(demitter));
@@ -134,6 +161,9 @@ pub fn phase_1_parse_input(sess: Session, cfg: ast::CrateConfig, input: &input)
     time(sess.time_passes(), "parsing", (), |_| {
         match *input {
             file_input(ref file) => {
+                if sess.deterministic() {
+                    sess.codemap.set_path_prefix(file.dir_path().to_str());
+                }
                 parse::parse_crate_from_file(&(*file), cfg.clone(), sess.parse_sess)
             }
             str_input(src) => {
@@ -156,8 +186,10 @@ pub fn phase_2_configure_and_expand(sess: Session,
                                     mut crate: ast::Crate) -> ast::Crate {
     let time_passes = sess.time_passes();
 
-    *sess.building_library = session::building_library(sess.opts.crate_type,
-                                                       &crate, sess.opts.test);
+    let crate_type = session::output_crate_type(sess.opts.crate_type, &crate,
+                                                sess.opts.test);
+    *sess.building_library = crate_type != session::bin_crate;
+    *sess.building_staticlib = crate_type == session::staticlib_crate;
 
     time(time_passes, "gated feature checking", (), |_|
          front::feature_gate::check_crate(sess, &crate));
@@ -219,6 +251,7 @@ pub fn phase_3_run_analysis_passes(sess: Session,
          creader::read_crates(sess.diagnostic(), crate, sess.cstore,
                               sess.filesearch,
                               session::sess_os_to_meta_os(sess.targ_cfg.os),
+                              sess.targ_cfg.target_strs.target_triple.to_managed(),
                               sess.opts.is_static,
                               token::get_ident_interner()));
 
@@ -359,7 +392,8 @@ pub fn phase_5_run_llvm_passes(sess: Session,
                                     trans.context,
                                     trans.module,
                                     output_type,
-                                    &asm_filename));
+                                    &asm_filename,
+                                    &trans.link));
 
         link::write::run_assembler(sess, &asm_filename, &outputs.obj_filename);
 
@@ -373,7 +407,8 @@ pub fn phase_5_run_llvm_passes(sess: Session,
                                     trans.context,
                                     trans.module,
                                     sess.opts.output_type,
-                                    &outputs.obj_filename));
+                                    &outputs.obj_filename,
+                                    &trans.link));
     }
 }
 
@@ -436,8 +471,9 @@ pub fn compile_input(sess: Session, cfg: ast::CrateConfig, input: &input,
             phase_2_configure_and_expand(sess, cfg, crate)
         };
         let analysis = phase_3_run_analysis_passes(sess, &expanded_crate);
-        if stop_after_phase_3(sess) { return; }
         let outputs = build_output_filenames(input, outdir, output, [], sess);
+        write_out_deps(sess, outputs);
+        if stop_after_phase_3(sess) { return; }
         let trans = phase_4_translate_to_llvm(sess, expanded_crate,
                                               &analysis, outputs);
         (outputs, trans)
@@ -447,6 +483,38 @@ pub fn compile_input(sess: Session, cfg: ast::CrateConfig, input: &input,
     phase_6_link_output(sess, &trans, outputs);
 }
 
+/// The source files read so far in compiling a crate: the crate file, its
+/// modules and anything `include!`d. Files read by `include_str!` and
+/// `include_bin!` aren't tracked.
+pub fn source_files(sess: Session) -> ~[Path] {
+    sess.codemap.files.iter()
+        // Source that doesn't come from a file has a name in angle brackets
+        .filter(|fm| !fm.name.starts_with("<"))
+        .map(|fm| Path(fm.name))
+        .collect()
+}
+
+/// Write the source files of the crate as a dependency rule for make, if
+/// asked to with `--dep-info`.
+fn write_out_deps(sess: Session, outputs: &OutputFilenames) {
+    let (write, ref file) = sess.opts.write_dependency_info;
+    if !write { return; }
+
+    let target = &outputs.out_filename;
+    let deps_file = match *file {
+        Some(ref p) => p.clone(),
+        None => target.with_filetype("d")
+    };
+    // Spaces in file names need escaping
+    let escape = |p: &Path| p.to_str().replace(" ", "\\ ");
+    let deps = source_files(sess).map(|p| escape(p));
+    match io::file_writer(&deps_file, [io::Create, io::Truncate]) {
+        Ok(w) => w.write_str(format!("{}: {}\n", escape(target), deps.connect(" "))),
+        Err(e) => sess.fatal(format!("error writing dependency info to `{}`: {}",
+                                     deps_file.to_str(), e))
+    }
+}
+
 struct IdentifiedAnnotation {
     contents: (),
 }
@@ -525,14 +593,21 @@ pub fn pretty_print_input(sess: Session,
     }
 
     let crate = phase_1_parse_input(sess, cfg.clone(), input);
-
-    let (crate, is_expanded) = match ppm {
-        PpmExpanded | PpmExpandedIdentified | PpmTyped => {
-            (phase_2_configure_and_expand(sess, cfg, crate), true)
-        }
-        _ => (crate, false)
+    let crate = if ppm.expands() {
+        phase_2_configure_and_expand(sess, cfg, crate)
+    } else {
+        crate
     };
+    pretty_print_crate(sess, input, &crate, ppm, io::stdout());
+}
 
+/// Pretty-prints `crate`, parsed from `input`, to `out`. The crate must
+/// already be expanded if `ppm` expands macros.
+pub fn pretty_print_crate(sess: Session,
+                          input: &input,
+                          crate: &ast::Crate,
+                          ppm: PpMode,
+                          out: @io::Writer) {
     let annotation = match ppm {
         PpmIdentified | PpmExpandedIdentified => {
             @IdentifiedAnnotation {
@@ -540,7 +615,7 @@ pub fn pretty_print_input(sess: Session,
             } as @pprust::pp_ann
         }
         PpmTyped => {
-            let analysis = phase_3_run_analysis_passes(sess, &crate);
+            let analysis = phase_3_run_analysis_passes(sess, crate);
             @TypedAnnotation {
                 analysis: analysis
             } as @pprust::pp_ann
@@ -553,12 +628,12 @@ pub fn pretty_print_input(sess: Session,
         pprust::print_crate(sess.codemap,
                             token::get_ident_interner(),
                             sess.span_diagnostic,
-                            &crate,
+                            crate,
                             source_name(input),
                             rdr,
-                            io::stdout(),
+                            out,
                             annotation,
-                            is_expanded);
+                            ppm.expands());
     }
 }
 
@@ -615,12 +690,18 @@ pub fn build_target_config(sopts: @session::options,
       abi::Mips => (ast::ty_i32, ast::ty_u32)
     };
     let target_triple = sopts.target_triple.clone();
-    let target_strs = match arch {
+    let mut target_strs = match arch {
       abi::X86 => x86::get_target_strs(target_triple, os),
       abi::X86_64 => x86_64::get_target_strs(target_triple, os),
       abi::Arm => arm::get_target_strs(target_triple, os),
       abi::Mips => mips::get_target_strs(target_triple, os)
     };
+    for spec in sopts.target_spec.iter() {
+        for data_layout in spec.data_layout.iter() {
+            target_strs.data_layout = data_layout.clone();
+        }
+        target_strs.cc_args.push_all(spec.pre_link_args);
+    }
     let target_cfg = @session::config {
         os: os,
         arch: arch,
@@ -649,6 +730,8 @@ pub fn build_session_options(binary: @str,
                              -> @session::options {
     let crate_type = if matches.opt_present("lib") {
         session::lib_crate
+    } else if matches.opt_present("staticlib") {
+        session::staticlib_crate
     } else if matches.opt_present("bin") {
         session::bin_crate
     } else {
@@ -664,12 +747,9 @@ pub fn build_session_options(binary: @str,
     for level in lint_levels.iter() {
         let level_name = lint::level_to_str(*level);
 
-        // FIXME: #4318 Instead of to_ascii and to_str_ascii, could use
-        // to_ascii_move and to_str_move to not do a unnecessary copy.
-        let level_short = level_name.slice_chars(0, 1);
-        let level_short = level_short.to_ascii().to_upper().to_str_ascii();
-        let flags = vec::append(matches.opt_strs(level_short),
-                                matches.opt_strs(level_name));
+        // The short and long forms of each level are one option, so this
+        // sees every occurrence of both.
+        let flags = matches.opt_strs(level_name);
         for lint_name in flags.iter() {
             let lint_name = lint_name.replace("-", "_");
             match lint_dict.find_equiv(&lint_name) {
@@ -684,6 +764,14 @@ pub fn build_session_options(binary: @str,
         }
     }
 
+    let cap_lints = do matches.opt_str("cap-lints").map |name| {
+        match lint_levels.iter().find(|&&l| lint::level_to_str(l) == name.as_slice()) {
+            Some(&level) => level,
+            None => early_error(demitter, format!("--cap-lints expects allow, warn, \
+                                                   deny or forbid, found `{}`", name))
+        }
+    };
+
     let mut debugging_opts = 0u;
     let debug_flags = matches.opt_strs("Z");
     let debug_map = session::debugging_opts_map();
@@ -721,10 +809,27 @@ pub fn build_session_options(binary: @str,
         } else if matches.opt_present("emit-llvm") {
             link::output_type_bitcode
         } else { link::output_type_exe };
-    let sysroot_opt = matches.opt_str("sysroot").map(|m| @Path(m));
-    let target = matches.opt_str("target").unwrap_or(host_triple());
-    let target_cpu = matches.opt_str("target-cpu").unwrap_or(~"generic");
-    let target_feature = matches.opt_str("target-feature").unwrap_or(~"");
+    let sysroot_opt = matches.opt_path("sysroot").map(|p| @p);
+    let target_spec = match matches.opt_str("target") {
+        Some(ref t) if target_spec::is_spec_path(t.as_slice()) => {
+            match target_spec::load(&Path(t.as_slice())) {
+                Ok(spec) => Some(spec),
+                Err(e) => early_error(demitter, e)
+            }
+        }
+        _ => None
+    };
+    // Flags on the command line win over the defaults in a spec file
+    let target = match target_spec {
+        Some(ref spec) => spec.llvm_target.clone(),
+        None => matches.opt_str("target").unwrap_or(host_triple())
+    };
+    let target_cpu = matches.opt_str("target-cpu").or_else(|| {
+        target_spec.as_ref().and_then(|spec| spec.cpu.clone())
+    }).unwrap_or(~"generic");
+    let target_feature = matches.opt_str("target-feature").or_else(|| {
+        target_spec.as_ref().and_then(|spec| spec.features.clone())
+    }).unwrap_or(~"");
     let save_temps = matches.opt_present("save-temps");
     let opt_level = {
         if (debugging_opts & session::no_opt) != 0 {
@@ -734,17 +839,22 @@ pub fn build_session_options(binary: @str,
                 early_error(demitter, "-O and --opt-level both provided");
             }
             Default
-        } else if matches.opt_present("opt-level") {
-            match matches.opt_str("opt-level").unwrap() {
-              ~"0" => No,
-              ~"1" => Less,
-              ~"2" => Default,
-              ~"3" => Aggressive,
-              _ => {
-                early_error(demitter, "optimization level needs to be between 0-3")
-              }
+        } else {
+            let levels = [("0", No), ("1", Less), ("2", Default), ("3", Aggressive)];
+            match matches.opt_enum("opt-level", levels) {
+              Ok(level) => level.unwrap_or(No),
+              Err(f) => early_error(demitter, f.to_err_msg())
             }
-        } else { No }
+        }
+    };
+    let codegen_units = match matches.opt_str("codegen-units") {
+        None => 1,
+        Some(n) => match from_str::<uint>(n) {
+            Some(n) if n > 0 => n,
+            _ => early_error(demitter, format!("--codegen-units expects a \
+                                                positive integer, found `{}`",
+                                               n))
+        }
     };
     let gc = debugging_opts & session::gc != 0;
     let jit = debugging_opts & session::jit != 0;
@@ -754,8 +864,33 @@ pub fn build_session_options(binary: @str,
 
     let statik = debugging_opts & session::statik != 0;
 
-    let addl_lib_search_paths = matches.opt_strs("L").map(|s| Path(*s));
-    let linker = matches.opt_str("linker");
+    let addl_lib_search_paths = matches.opt_paths("L");
+    let linker = matches.opt_str("linker").or_else(|| {
+        target_spec.as_ref().and_then(|spec| spec.linker.clone())
+    });
+    let mut linkage = session::LinkDynamic;
+    let mut crate_linkage = ~[];
+    for l in matches.opt_strs("linkage").iter() {
+        match l.as_slice() {
+            "prefer-dynamic" => linkage = session::LinkDynamic,
+            "prefer-static" => linkage = session::LinkStatic,
+            l => {
+                let (name, kind) = match l.find('=') {
+                    Some(i) => (l.slice_to(i), l.slice_from(i + 1)),
+                    None => (l, "")
+                };
+                let kind = match kind {
+                    "dynamic" => session::LinkDynamic,
+                    "static" => session::LinkStatic,
+                    _ => early_error(demitter, format!("--linkage expects \
+                                                        prefer-dynamic, prefer-static, \
+                                                        CRATE=dynamic or CRATE=static, \
+                                                        found `{}`", l))
+                };
+                crate_linkage.push((name.to_owned(), kind));
+            }
+        }
+    }
     let linker_args = matches.opt_strs("link-args").flat_map( |a| {
         a.split_iter(' ').map(|arg| arg.to_owned()).collect()
     });
@@ -763,6 +898,8 @@ pub fn build_session_options(binary: @str,
     let cfg = parse_cfgspecs(matches.opt_strs("cfg"), demitter);
     let test = matches.opt_present("test");
     let android_cross_path = matches.opt_str("android-cross-path");
+    let write_dependency_info = (matches.opt_present("dep-info"),
+                                 matches.opt_str("dep-info").map(|p| Path(p)));
 
     let custom_passes = match matches.opt_str("passes") {
         None => ~[],
@@ -786,19 +923,27 @@ pub fn build_session_options(binary: @str,
         is_static: statik,
         gc: gc,
         optimize: opt_level,
+        codegen_units: codegen_units,
         custom_passes: custom_passes,
         llvm_args: llvm_args,
         debuginfo: debuginfo,
         extra_debuginfo: extra_debuginfo,
         lint_opts: lint_opts,
+        cap_lints: cap_lints,
         save_temps: save_temps,
         jit: jit,
         output_type: output_type,
         addl_lib_search_paths: @mut addl_lib_search_paths,
+        search_rust_path: false,
+        maybe_rust_path: None,
         linker: linker,
         linker_args: linker_args,
+        crate_linker_args: @mut session::CrateLinkerArgs::new(),
+        linkage: linkage,
+        crate_linkage: crate_linkage,
         maybe_sysroot: sysroot_opt,
         target_triple: target,
+        target_spec: target_spec,
         target_cpu: target_cpu,
         target_feature: target_feature,
         cfg: cfg,
@@ -807,7 +952,8 @@ pub fn build_session_options(binary: @str,
         parse_only: parse_only,
         no_trans: no_trans,
         debugging_opts: debugging_opts,
-        android_cross_path: android_cross_path
+        android_cross_path: android_cross_path,
+        write_dependency_info: write_dependency_info
     };
     return sopts;
 }
@@ -822,19 +968,52 @@ pub fn build_session(sopts: @session::options, demitter: @diagnostic::Emitter)
     build_session_(sopts, codemap, demitter, span_diagnostic_handler)
 }
 
+/// Builds a session for a tool that embeds the compiler. Each diagnostic is
+/// passed through `filter`, which can change or suppress it, before being
+/// printed with `out`.
+pub fn build_filtered_session(sopts: @session::options,
+                              filter: @diagnostic::DiagnosticFilter,
+                              out: @diagnostic::Emitter)
+                              -> Session {
+    build_session(sopts, @diagnostic::FilteringEmitter {
+        filter: filter,
+        out: out
+    } as @diagnostic::Emitter)
+}
+
 pub fn build_session_(sopts: @session::options,
                       cm: @codemap::CodeMap,
                       demitter: @diagnostic::Emitter,
                       span_diagnostic_handler: @mut diagnostic::span_handler)
                       -> Session {
     let target_cfg = build_target_config(sopts, demitter);
+    mk_session(sopts, target_cfg, cm, span_diagnostic_handler)
+}
+
+/// Builds a session for another crate compiled alongside the one `sess` was
+/// built for, such as a binary that links against the library `sess` just
+/// produced. The target configuration, codemap and diagnostics are shared
+/// with `sess`; the crate store and the other per-crate state start empty.
+/// `sopts` must name the same target as `sess`.
+pub fn build_sibling_session(sess: Session, sopts: @session::options) -> Session {
+    assert_eq!(sopts.target_triple, sess.opts.target_triple);
+    mk_session(sopts, sess.targ_cfg, sess.codemap, sess.span_diagnostic)
+}
+
+fn mk_session(sopts: @session::options,
+              target_cfg: @session::config,
+              cm: @codemap::CodeMap,
+              span_diagnostic_handler: @mut diagnostic::span_handler)
+              -> Session {
     let p_s = parse::new_parse_sess_special_handler(span_diagnostic_handler,
                                                     cm);
     let cstore = @mut cstore::mk_cstore(token::get_ident_interner());
     let filesearch = filesearch::mk_filesearch(
         &sopts.maybe_sysroot,
         sopts.target_triple,
-        sopts.addl_lib_search_paths);
+        sopts.addl_lib_search_paths,
+        sopts.search_rust_path,
+        sopts.maybe_rust_path.clone());
     @Session_ {
         targ_cfg: target_cfg,
         opts: sopts,
@@ -847,20 +1026,33 @@ pub fn build_session_(sopts: @session::options,
         span_diagnostic: span_diagnostic_handler,
         filesearch: filesearch,
         building_library: @mut false,
+        building_staticlib: @mut false,
         working_dir: os::getcwd(),
         lints: @mut HashMap::new(),
         node_id: @mut 1
     }
 }
 
+/// The diagnostic format asked for with --error-format, if it's a valid one
+pub fn error_format(matches: &getopts::Matches) -> Option<diagnostic::ErrorFormat> {
+    match matches.opt_str("error-format") {
+        Some(name) => diagnostic::parse_error_format(name),
+        None => Some(diagnostic::HumanReadable)
+    }
+}
+
+/// The pretty-printing mode called `name`, if there is one
+pub fn pretty_mode(name: &str) -> Option<PpMode> {
+    for &(n, ppm) in pp_modes.iter() {
+        if n == name { return Some(ppm) }
+    }
+    None
+}
+
 pub fn parse_pretty(sess: Session, name: &str) -> PpMode {
-    match name {
-      &"normal" => PpmNormal,
-      &"expanded" => PpmExpanded,
-      &"typed" => PpmTyped,
-      &"expanded,identified" => PpmExpandedIdentified,
-      &"identified" => PpmIdentified,
-      _ => {
+    match pretty_mode(name) {
+      Some(ppm) => ppm,
+      None => {
         sess.fatal("argument to `pretty` must be one of `normal`, \
                     `expanded`, `typed`, `identified`, \
                     or `expanded,identified`");
@@ -873,18 +1065,35 @@ pub fn optgroups() -> ~[getopts::groups::OptGroup] {
  ~[
   optflag("",  "bin", "Compile an executable crate (default)"),
   optflag("c", "",    "Compile and assemble, but do not link"),
+  optopt("", "cap-lints", "Lower every lint above LEVEL to LEVEL, whatever
+                          the command line or the crate asks for; for
+                          building other people's crates", "LEVEL"),
   optmulti("", "cfg", "Configure the compilation
                           environment", "SPEC"),
+  optopt("", "codegen-units",
+                        "Split the crate into N pieces and generate code
+                          for them in parallel", "N"),
+  optflagopt("", "dep-info",
+                        "Output dependency info to <filename> after compiling,
+                          or next to the output with a .d extension", "FILENAME"),
   optflag("",  "emit-llvm",
                         "Produce an LLVM assembly file if used with -S option;
                          produce an LLVM bitcode file otherwise"),
+  optopt("", "error-format",
+                        "How to print diagnostics: human (the default)
+                          or json, one object per line", "FORMAT"),
   optflag("h", "help","Display this message"),
   optmulti("L", "",   "Add a directory to the library search path",
                               "PATH"),
-  optflag("",  "lib", "Compile a library crate"),
+  optflag("",  "lib", "Compile a library crate (a static one if the crate
+                          says #[crate_type = \"staticlib\"])"),
   optopt("", "linker", "Program to use for linking instead of the default.", "LINKER"),
   optmulti("",  "link-args", "FLAGS is a space-separated list of flags
                             passed to the linker", "FLAGS"),
+  optmulti("", "linkage", "How an executable links Rust libraries:
+                            prefer-dynamic (the default) or prefer-static,
+                            or CRATE=dynamic or CRATE=static for one crate",
+                            "LINKAGE"),
   optflag("",  "ls",  "List the symbols defined by a library crate"),
   optflag("", "no-trans",
                         "Run all passes except translation; no output"),
@@ -900,7 +1109,7 @@ pub fn optgroups() -> ~[getopts::groups::OptGroup] {
                            separated", "ARGS"),
   optopt( "",  "out-dir",
                         "Write output to compiler-chosen filename
-                          in <dir>", "DIR"),
+                          in <dir>, along with any intermediate files", "DIR"),
   optflag("", "parse-only",
                         "Parse only; do not compile, assemble, or link"),
   optflagopt("", "pretty",
@@ -914,13 +1123,17 @@ pub fn optgroups() -> ~[getopts::groups::OptGroup] {
   optflag("", "save-temps",
                         "Write intermediate files (.bc, .opt.bc, .o)
                           in addition to normal output"),
+  optflag("", "staticlib",
+                        "Compile a static library crate, bundling the Rust
+                          crates it uses into one archive"),
   optopt("", "sysroot",
                         "Override the system root", "PATH"),
   optflag("", "test", "Build a test harness"),
   optopt("", "target",
                         "Target triple cpu-manufacturer-kernel[-os]
                           to compile for (see chapter 3.4 of http://www.sourceware.org/autobook/
-                          for details)", "TRIPLE"),
+                          for details), or a JSON target specification
+                          file ending in .json", "TRIPLE"),
   optopt("", "target-cpu",
                         "Select target processor (llc -mcpu=help
                           for details)", "CPU"),
@@ -943,6 +1156,15 @@ pub fn optgroups() -> ~[getopts::groups::OptGroup] {
  ]
 }
 
+/// Where the compiler puts what it produces. Everything goes in the same
+/// directory: --out-dir if given, else next to the input.
+///
+/// * `out_filename` is the final output: the executable, the library
+///   before the linker puts its hash and version in the name, or, when
+///   nothing is linked, the object, assembly or bitcode file.
+/// * `obj_filename` is the object, assembly or bitcode file LLVM writes.
+///   --save-temps keeps it, along with `<stem>.bc` and `<stem>.no-opt.bc`
+///   beside it, and --dep-info writes `<stem>.d` beside the final output.
 pub struct OutputFilenames {
     out_filename: Path,
     obj_filename: Path
@@ -1002,30 +1224,44 @@ pub fn build_output_filenames(input: &input,
               // version
           }
 
-          if *sess.building_library {
-              out_path = dirpath.push(os::dll_filename(stem));
-              obj_path = dirpath.push(stem).with_filetype(obj_suffix);
+          obj_path = dirpath.push(stem).with_filetype(obj_suffix);
+          out_path = if stop_after_codegen {
+              // Nothing gets linked, so the object, assembly or bitcode
+              // file is the output
+              obj_path.clone()
+          } else if *sess.building_staticlib {
+              // Named like a dynamic library but with a `.a` suffix, with
+              // the hash and version added the same way
+              dirpath.push(os::dll_filename(stem)).with_filetype("a")
+          } else if *sess.building_library {
+              // The linker puts the hash and version in the name; see
+              // `link::output_filename`
+              dirpath.push(os::dll_filename(stem))
           } else {
-              out_path = dirpath.push(stem);
-              obj_path = dirpath.push(stem).with_filetype(obj_suffix);
-          }
+              dirpath.push(stem)
+          };
       }
 
       Some(ref out_file) => {
-        out_path = (*out_file).clone();
+        // A relative output file goes under --out-dir, like everything else
+        let out_file = match *odir {
+            Some(ref d) if !out_file.is_absolute() => d.push_rel(out_file),
+            Some(_) => {
+                sess.warn("ignoring --out-dir flag due to absolute -o flag.");
+                (*out_file).clone()
+            }
+            None => (*out_file).clone()
+        };
+        out_path = out_file.clone();
         obj_path = if stop_after_codegen {
-            (*out_file).clone()
+            out_file
         } else {
-            (*out_file).with_filetype(obj_suffix)
+            out_file.with_filetype(obj_suffix)
         };
 
         if *sess.building_library {
             sess.warn("ignoring specified output filename for library.");
         }
-
-        if *odir != None {
-            sess.warn("ignoring --out-dir flag due to -o flag.");
-        }
       }
     }
 
@@ -1051,6 +1287,7 @@ mod test {
 
     use driver::driver::{build_configuration, build_session};
     use driver::driver::{build_session_options, optgroups};
+    use driver::driver::{OutputFilenames, build_output_filenames, file_input};
 
     use extra::getopts::groups::getopts;
     use syntax::attr;
@@ -1099,4 +1336,60 @@ mod test {
         assert!(test_items.next().is_some());
         assert!(test_items.next().is_none());
     }
+
+    fn outputs_for(args: ~[~str]) -> ~OutputFilenames {
+        let matches = &match getopts(args, optgroups()) {
+            Ok(m) => m,
+            Err(f) => fail2!("outputs_for: {}", f.to_err_msg())
+        };
+        let sessopts = build_session_options(
+            @"rustc",
+            matches,
+            @diagnostic::DefaultEmitter as @diagnostic::Emitter);
+        let sess = build_session(sessopts,
+                                 @diagnostic::DefaultEmitter as
+                                    @diagnostic::Emitter);
+        build_output_filenames(&file_input(Path("src/foo.rs")),
+                               &matches.opt_path("out-dir"),
+                               &matches.opt_path("o"),
+                               [],
+                               sess)
+    }
+
+    // Every kind of unlinked output lands in --out-dir, named after the
+    // crate, and is the output the driver reports
+    #[test]
+    fn test_unlinked_outputs_go_in_out_dir() {
+        let kinds = [(~[~"-c"], "o"),
+                     (~[~"-S"], "s"),
+                     (~[~"-S", ~"--emit-llvm"], "ll"),
+                     (~[~"--emit-llvm"], "bc")];
+        for &(ref flags, ext) in kinds.iter() {
+            let outputs = outputs_for(*flags + ~[~"--out-dir", ~"out"]);
+            assert_eq!(outputs.obj_filename, Path("out").push("foo").with_filetype(ext));
+            assert_eq!(outputs.out_filename, outputs.obj_filename);
+        }
+    }
+
+    #[test]
+    fn test_relative_output_file_goes_in_out_dir() {
+        let outputs = outputs_for(~[~"--out-dir", ~"out", ~"-o", ~"bar"]);
+        assert_eq!(outputs.out_filename, Path("out/bar"));
+        assert_eq!(outputs.obj_filename, Path("out/bar.o"));
+    }
+
+    #[test]
+    fn test_codegen_units() {
+        let units_for = |args: ~[~str]| {
+            let matches = &match getopts(args, optgroups()) {
+              Ok(m) => m,
+              Err(f) => fail2!("test_codegen_units: {}", f.to_err_msg())
+            };
+            build_session_options(@"rustc", matches,
+                                  @diagnostic::DefaultEmitter as
+                                      @diagnostic::Emitter).codegen_units
+        };
+        assert_eq!(units_for(~[]), 1);
+        assert_eq!(units_for(~[~"--codegen-units", ~"4"]), 4);
+    }
 }
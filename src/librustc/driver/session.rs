@@ -10,6 +10,7 @@
 
 
 use back::link;
+use back::target_spec::TargetSpec;
 use back::target_strs;
 use back;
 use driver::driver::host_triple;
@@ -34,10 +35,11 @@ use std::hashmap::HashMap;
 #[deriving(Eq)]
 pub enum Os { OsWin32, OsMacos, OsLinux, OsAndroid, OsFreebsd, }
 
-#[deriving(Clone)]
+#[deriving(Clone, Eq)]
 pub enum crate_type {
     bin_crate,
     lib_crate,
+    staticlib_crate,
     unknown_crate,
 }
 
@@ -80,6 +82,12 @@ pub static no_vectorize_loops:      uint = 1 << 27;
 pub static no_vectorize_slp:        uint = 1 << 28;
 pub static no_prepopulate_passes:   uint = 1 << 29;
 pub static use_softfp:              uint = 1 << 30;
+// Makes the file names in fail!() messages, file!() and debug info relative
+// to the crate root's directory, and records "." as the compilation directory
+// in debug info. Still left in the output: files outside that directory (such
+// as ones named by absolute paths in include!()), the rustc version in debug
+// info, and anything the linker adds itself, like build IDs.
+pub static deterministic:           uint = 1 << 31;
 
 pub fn debugging_opts_map() -> ~[(&'static str, &'static str, uint)] {
     ~[("verbose", "in general, enable more debug printouts", verbose),
@@ -135,6 +143,10 @@ pub fn debugging_opts_map() -> ~[(&'static str, &'static str, uint)] {
       "Don't run LLVM's SLP vectorization passes",
       no_vectorize_slp),
      ("soft-float", "Generate software floating point library calls", use_softfp),
+     ("deterministic",
+      "Keep where the sources live out of the output, so that building \
+        the same sources gives the same crate",
+      deterministic),
     ]
 }
 
@@ -146,6 +158,45 @@ pub enum OptLevel {
     Aggressive // -O3
 }
 
+impl OptLevel {
+    /// The number --opt-level takes for this level
+    pub fn to_uint(&self) -> uint {
+        match *self {
+            No => 0,
+            Less => 1,
+            Default => 2,
+            Aggressive => 3
+        }
+    }
+}
+
+/// Linker arguments a build system supplies for just the crate being
+/// compiled. Unlike `#[link_args]`, these aren't recorded in the crate's
+/// metadata, so crates linking to this one don't pick them up.
+#[deriving(Clone)]
+pub struct CrateLinkerArgs {
+    // Native libraries, passed as -l
+    libs: ~[~str],
+    // Directories to find them in, passed as -L
+    search_paths: ~[Path],
+    // Anything else, passed to the linker as is
+    args: ~[~str],
+}
+
+impl CrateLinkerArgs {
+    pub fn new() -> CrateLinkerArgs {
+        CrateLinkerArgs { libs: ~[], search_paths: ~[], args: ~[] }
+    }
+}
+
+/// How an executable links the Rust libraries it uses
+#[deriving(Clone, Eq)]
+pub enum Linkage {
+    LinkDynamic,
+    // Link the library's static archive, as built with --staticlib
+    LinkStatic,
+}
+
 #[deriving(Clone)]
 pub struct options {
     // The crate config requested for the session, which may be combined
@@ -154,21 +205,44 @@ pub struct options {
     is_static: bool,
     gc: bool,
     optimize: OptLevel,
+    // How many pieces to split the crate's code into for LLVM code
+    // generation, each compiled in its own task
+    codegen_units: uint,
     custom_passes: ~[~str],
     llvm_args: ~[~str],
     debuginfo: bool,
     extra_debuginfo: bool,
     lint_opts: ~[(lint::lint, lint::level)],
+    // The highest level any lint may have, whatever asked for more
+    cap_lints: Option<lint::level>,
     save_temps: bool,
     jit: bool,
     output_type: back::link::output_type,
     addl_lib_search_paths: @mut ~[Path], // This is mutable for rustpkg, which
                                          // updates search paths based on the
                                          // parsed code
+    // Whether the RUST_PATH workspaces are searched for libraries along with
+    // the other search paths, rather than only as a last resort. rustpkg
+    // sets this; see filesearch::mk_filesearch
+    search_rust_path: bool,
+    // The workspaces searched as the RUST_PATH, when they aren't the ones
+    // this process's RUST_PATH and working directory give. rustpkg sets
+    // this for runs on behalf of another directory or environment.
+    maybe_rust_path: Option<~[Path]>,
     linker: Option<~str>,
     linker_args: ~[~str],
+    // Mutable for the same reason as addl_lib_search_paths
+    crate_linker_args: @mut CrateLinkerArgs,
+    // The preferred linkage; libraries without a static archive are still
+    // linked dynamically
+    linkage: Linkage,
+    // Linkage required for particular crates, by name
+    crate_linkage: ~[(~str, Linkage)],
     maybe_sysroot: Option<@Path>,
     target_triple: ~str,
+    // Set when --target named a spec file; target_triple is then its
+    // llvm-target
+    target_spec: Option<TargetSpec>,
     target_cpu: ~str,
     target_feature: ~str,
     // User-specified cfg meta items. The compiler itself will add additional
@@ -182,6 +256,9 @@ pub struct options {
     no_trans: bool,
     debugging_opts: uint,
     android_cross_path: Option<~str>,
+    // Whether to write dependency info for make, and where to. The default
+    // is next to the output, with a .d extension.
+    write_dependency_info: (bool, Option<Path>),
 }
 
 pub struct crate_metadata {
@@ -212,6 +289,8 @@ pub struct Session_ {
     span_diagnostic: @mut diagnostic::span_handler,
     filesearch: @filesearch::FileSearch,
     building_library: @mut bool,
+    // Set along with building_library when the library is a static archive
+    building_staticlib: @mut bool,
     working_dir: Path,
     lints: @mut HashMap<ast::NodeId, ~[(lint::lint, codemap::Span, ~str)]>,
     node_id: @mut uint,
@@ -339,6 +418,7 @@ impl Session_ {
     pub fn no_vectorize_slp(&self) -> bool {
         self.debugging_opt(no_vectorize_slp)
     }
+    pub fn deterministic(&self) -> bool { self.debugging_opt(deterministic) }
 
     // pointless function, now...
     pub fn str_of(&self, id: ast::Ident) -> @str {
@@ -363,19 +443,27 @@ pub fn basic_options() -> @options {
         is_static: false,
         gc: false,
         optimize: No,
+        codegen_units: 1,
         custom_passes: ~[],
         llvm_args: ~[],
         debuginfo: false,
         extra_debuginfo: false,
         lint_opts: ~[],
+        cap_lints: None,
         save_temps: false,
         jit: false,
         output_type: link::output_type_exe,
         addl_lib_search_paths: @mut ~[],
+        search_rust_path: false,
+        maybe_rust_path: None,
         linker: None,
         linker_args: ~[],
+        crate_linker_args: @mut CrateLinkerArgs::new(),
+        linkage: LinkDynamic,
+        crate_linkage: ~[],
         maybe_sysroot: None,
         target_triple: host_triple(),
+        target_spec: None,
         target_cpu: ~"generic",
         target_feature: ~"",
         cfg: ~[],
@@ -385,6 +473,7 @@ pub fn basic_options() -> @options {
         no_trans: false,
         debugging_opts: 0u,
         android_cross_path: None,
+        write_dependency_info: (false, None),
     }
 }
 
@@ -394,27 +483,45 @@ pub fn expect<T:Clone>(sess: Session, opt: Option<T>, msg: &fn() -> ~str)
     diagnostic::expect(sess.diagnostic(), opt, msg)
 }
 
-pub fn building_library(req_crate_type: crate_type,
-                        crate: &ast::Crate,
-                        testing: bool) -> bool {
+/// Decides what kind of output a crate compiles to. `--lib` builds a
+/// library of whichever kind the crate's `crate_type` attribute asks for,
+/// so a `#[crate_type = "staticlib"]` crate is still archived.
+pub fn output_crate_type(req_crate_type: crate_type,
+                         crate: &ast::Crate,
+                         testing: bool) -> crate_type {
+    let attr_crate_type = || {
+        match syntax::attr::first_attr_value_str_by_name(crate.attrs,
+                                                         "crate_type") {
+          Some(s) if "lib" == s => lib_crate,
+          Some(s) if "staticlib" == s => staticlib_crate,
+          _ => bin_crate
+        }
+    };
     match req_crate_type {
-      bin_crate => false,
-      lib_crate => true,
+      bin_crate => bin_crate,
+      staticlib_crate => staticlib_crate,
+      lib_crate => {
+        match attr_crate_type() {
+          staticlib_crate => staticlib_crate,
+          _ => lib_crate
+        }
+      }
       unknown_crate => {
         if testing {
-            false
+            bin_crate
         } else {
-            match syntax::attr::first_attr_value_str_by_name(
-                crate.attrs,
-                "crate_type") {
-              Some(s) => "lib" == s,
-              _ => false
-            }
+            attr_crate_type()
         }
       }
     }
 }
 
+pub fn building_library(req_crate_type: crate_type,
+                        crate: &ast::Crate,
+                        testing: bool) -> bool {
+    output_crate_type(req_crate_type, crate, testing) != bin_crate
+}
+
 pub fn sess_os_to_meta_os(os: Os) -> metadata::loader::Os {
     use metadata::loader;
 
@@ -430,7 +537,7 @@ pub fn sess_os_to_meta_os(os: Os) -> metadata::loader::Os {
 #[cfg(test)]
 mod test {
     use driver::session::{bin_crate, building_library, lib_crate};
-    use driver::session::{unknown_crate};
+    use driver::session::{output_crate_type, staticlib_crate, unknown_crate};
 
     use syntax::ast;
     use syntax::attr;
@@ -448,6 +555,10 @@ mod test {
         if with_lib {
             attrs.push(make_crate_type_attr(@"lib"));
         }
+        make_crate_with_attrs(attrs)
+    }
+
+    fn make_crate_with_attrs(attrs: ~[ast::Attribute]) -> @ast::Crate {
         @ast::Crate {
             module: ast::_mod { view_items: ~[], items: ~[] },
             attrs: attrs,
@@ -497,4 +608,18 @@ mod test {
         let crate = make_crate(false, false);
         assert!(building_library(lib_crate, crate, true));
     }
+
+    #[test]
+    fn staticlib_crate_type_attr_results_in_staticlib_output() {
+        let crate = make_crate_with_attrs(~[make_crate_type_attr(@"staticlib")]);
+        assert_eq!(output_crate_type(unknown_crate, crate, false), staticlib_crate);
+        assert_eq!(output_crate_type(lib_crate, crate, false), staticlib_crate);
+        assert!(building_library(unknown_crate, crate, false));
+    }
+
+    #[test]
+    fn staticlib_option_overrides_lib_crate_type() {
+        let crate = make_crate(false, true);
+        assert_eq!(output_crate_type(staticlib_crate, crate, false), staticlib_crate);
+    }
 }
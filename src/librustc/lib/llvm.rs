@@ -1419,6 +1419,20 @@ pub mod llvm {
         pub fn LLVMRustCreateMemoryBufferWithContentsOfFile(Path: *c_char)
             -> MemoryBufferRef;
 
+        /** Reads a module from a bitcode file into the given context.
+            Returns null on failure. */
+        pub fn LLVMRustParseBitcodeFile(C: ContextRef, Path: *c_char)
+            -> ModuleRef;
+
+        /** Strips a module down to codegen unit `Unit` of `Units`, keeping
+            the definitions that belong to it and declaring the rest. Local
+            definitions are renamed under `Prefix`, which should be unique
+            to the crate. */
+        pub fn LLVMRustPartitionModule(M: ModuleRef,
+                                       Unit: c_uint,
+                                       Units: c_uint,
+                                       Prefix: *c_char);
+
         /** Returns a string describing the last error caused by an LLVMRust*
             call. */
         pub fn LLVMRustGetLastError() -> *c_char;
@@ -192,6 +192,13 @@ pub static tag_path_elt_pretty_name: uint = 0x87;
 pub static tag_path_elt_pretty_name_ident: uint = 0x88;
 pub static tag_path_elt_pretty_name_extra: uint = 0x89;
 
+// The cfg the crate was built with, as a list of meta items
+pub static tag_crate_cfg: uint = 0x8a;
+
+// The target triple the crate was built for, and its --opt-level
+pub static tag_crate_triple: uint = 0x8b;
+pub static tag_crate_opt_level: uint = 0x8c;
+
 pub struct LinkMeta {
     name: @str,
     vers: @str,
@@ -34,6 +34,7 @@ pub fn read_crates(diag: @mut span_handler,
                    cstore: @mut cstore::CStore,
                    filesearch: @FileSearch,
                    os: loader::Os,
+                   triple: @str,
                    statik: bool,
                    intr: @ident_interner) {
     let e = @mut Env {
@@ -41,6 +42,7 @@ pub fn read_crates(diag: @mut span_handler,
         filesearch: filesearch,
         cstore: cstore,
         os: os,
+        triple: triple,
         statik: statik,
         crate_cache: @mut ~[],
         next_crate_num: 1,
@@ -117,6 +119,7 @@ struct Env {
     filesearch: @FileSearch,
     cstore: @mut cstore::CStore,
     os: loader::Os,
+    triple: @str,
     statik: bool,
     crate_cache: @mut ~[cache_entry],
     next_crate_num: ast::CrateNum,
@@ -145,11 +148,23 @@ fn visit_view_item(e: @mut Env, i: &ast::view_item) {
               Some((p, _path_str_style)) => {
                   let p_path = Path(p);
                   match p_path.filestem() {
-                      Some(s) =>
+                      Some(s) => {
+                          // A package's library may have been installed under
+                          // an alias, which is what the `extern mod` calls it
+                          let name = if ident.as_slice() != s &&
+                                        !package_crate_exists(e, s.to_managed(), p,
+                                                              *meta_items, i.span) &&
+                                        package_crate_exists(e, ident, p,
+                                                             *meta_items, i.span) {
+                              ident
+                          } else {
+                              s.to_managed()
+                          };
                           vec::append(
                               ~[attr::mk_name_value_item_str(@"package_id", p),
-                               attr::mk_name_value_item_str(@"name", s.to_managed())],
-                              *meta_items),
+                               attr::mk_name_value_item_str(@"name", name)],
+                              *meta_items)
+                      }
                       None => e.diag.span_bug(i.span, "Bad package path in `extern mod` item")
                   }
             }
@@ -251,6 +266,32 @@ fn existing_match(e: &Env, metas: &[@ast::MetaItem], hash: &str)
     return None;
 }
 
+// Whether there's a library named `name` for the package `package_id`,
+// loaded already or waiting to be found. This only probes, so nothing is
+// reported if there are none or several.
+fn package_crate_exists(e: &Env, name: @str, package_id: @str,
+                        meta_items: &[@ast::MetaItem], span: Span) -> bool {
+    let metas = vec::append(~[attr::mk_name_value_item_str(@"package_id", package_id),
+                              attr::mk_name_value_item_str(@"name", name)],
+                            meta_items);
+    if existing_match(e, metas, "").is_some() {
+        return true;
+    }
+    let load_ctxt = loader::Context {
+        diag: e.diag,
+        filesearch: e.filesearch,
+        span: span,
+        ident: name,
+        metas: metas,
+        hash: @"",
+        os: e.os,
+        triple: e.triple,
+        is_static: e.statik,
+        intr: e.intr
+    };
+    loader::library_crate_exists(&load_ctxt)
+}
+
 fn resolve_crate(e: @mut Env,
                  ident: @str,
                  metas: ~[@ast::MetaItem],
@@ -269,6 +310,7 @@ fn resolve_crate(e: @mut Env,
             metas: metas,
             hash: hash,
             os: e.os,
+            triple: e.triple,
             is_static: e.statik,
             intr: e.intr
         };
@@ -309,7 +351,7 @@ fn resolve_crate(e: @mut Env,
 
         let cstore = e.cstore;
         cstore::set_crate_data(cstore, cnum, cmeta);
-        cstore::add_used_crate_file(cstore, &cfilename);
+        cstore::add_used_crate_file(cstore, cname, &cfilename);
         return cnum;
       }
       Some(cnum) => {
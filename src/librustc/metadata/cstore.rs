@@ -37,7 +37,7 @@ pub struct crate_metadata {
 pub struct CStore {
     priv metas: HashMap <ast::CrateNum, @crate_metadata>,
     priv extern_mod_crate_map: extern_mod_crate_map,
-    priv used_crate_files: ~[Path],
+    priv used_crate_files: ~[(@str, Path)],
     priv used_libraries: ~[@str],
     priv used_link_args: ~[@str],
     intr: @ident_interner
@@ -89,13 +89,18 @@ pub fn iter_crate_data(cstore: &CStore,
     }
 }
 
-pub fn add_used_crate_file(cstore: &mut CStore, lib: &Path) {
-    if !cstore.used_crate_files.contains(lib) {
-        cstore.used_crate_files.push((*lib).clone());
+pub fn add_used_crate_file(cstore: &mut CStore, name: @str, lib: &Path) {
+    if !cstore.used_crate_files.iter().any(|&(_, ref p)| p == lib) {
+        cstore.used_crate_files.push((name, (*lib).clone()));
     }
 }
 
 pub fn get_used_crate_files(cstore: &CStore) -> ~[Path] {
+    cstore.used_crate_files.map(|&(_, ref p)| p.clone())
+}
+
+/// The crates to link against, along with the names they were linked as
+pub fn get_used_crates(cstore: &CStore) -> ~[(@str, Path)] {
     // XXX(pcwalton): Bad copy.
     return cstore.used_crate_files.clone();
 }
@@ -1433,6 +1433,33 @@ pub fn get_crate_vers(data: @~[u8]) -> @str {
     }
 }
 
+/// The cfg the crate was compiled with. Crates built before the cfg was
+/// recorded report an empty list.
+pub fn get_crate_cfg(data: @~[u8]) -> ~[@ast::MetaItem] {
+    let cratedoc = reader::Doc(data);
+    match reader::maybe_get_doc(cratedoc, tag_crate_cfg) {
+        Some(cfgdoc) => get_meta_items(cfgdoc),
+        None => ~[]
+    }
+}
+
+/// The target triple the crate was built for. Crates built before this was
+/// recorded don't have one.
+pub fn get_crate_triple(data: @~[u8]) -> Option<@str> {
+    let cratedoc = reader::Doc(data);
+    do reader::maybe_get_doc(cratedoc, tag_crate_triple).map |doc| {
+        doc.as_str_slice().to_managed()
+    }
+}
+
+/// The --opt-level the crate was built with, if it was recorded
+pub fn get_crate_opt_level(data: @~[u8]) -> Option<uint> {
+    let cratedoc = reader::Doc(data);
+    do reader::maybe_get_doc(cratedoc, tag_crate_opt_level).and_then |doc| {
+        from_str(doc.as_str_slice())
+    }
+}
+
 pub fn list_crate_metadata(intr: @ident_interner, bytes: @~[u8],
                            out: @io::Writer) {
     let hash = get_crate_hash(bytes);
@@ -1476,6 +1476,25 @@ fn encode_attributes(ebml_w: &mut writer::Encoder, attrs: &[Attribute]) {
     ebml_w.end_tag();
 }
 
+fn encode_crate_cfg(ebml_w: &mut writer::Encoder, cfg: &[@MetaItem]) {
+    ebml_w.start_tag(tag_crate_cfg);
+    for mi in cfg.iter() {
+        encode_meta_item(ebml_w, *mi);
+    }
+    ebml_w.end_tag();
+}
+
+fn encode_crate_target(ecx: &EncodeContext, ebml_w: &mut writer::Encoder) {
+    let sess = ecx.tcx.sess;
+    ebml_w.start_tag(tag_crate_triple);
+    ebml_w.writer.write(sess.targ_cfg.target_strs.target_triple.as_bytes());
+    ebml_w.end_tag();
+
+    ebml_w.start_tag(tag_crate_opt_level);
+    ebml_w.writer.write(sess.opts.optimize.to_uint().to_str().as_bytes());
+    ebml_w.end_tag();
+}
+
 // So there's a special crate attribute called 'link' which defines the
 // metadata that Rust cares about for linking crates. This attribute requires
 // 'name' and 'vers' items, so if the user didn't provide them we will throw
@@ -1770,6 +1789,8 @@ pub fn encode_metadata(parms: EncodeParams, crate: &Crate) -> ~[u8] {
     let mut i = *wr.pos;
     let crate_attrs = synthesize_crate_attrs(&ecx, crate);
     encode_attributes(&mut ebml_w, crate_attrs);
+    encode_crate_cfg(&mut ebml_w, crate.config);
+    encode_crate_target(&ecx, &mut ebml_w);
     ecx.stats.attr_bytes = *wr.pos - i;
 
     i = *wr.pos;
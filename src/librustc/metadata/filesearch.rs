@@ -36,16 +36,50 @@ pub trait FileSearch {
     fn for_each_lib_search_path(&self, f: &fn(&Path) -> FileMatch);
     fn get_target_lib_path(&self) -> Path;
     fn get_target_lib_file_path(&self, file: &Path) -> Path;
+    fn rust_path(&self) -> ~[Path];
 }
 
+/// Without `search_rust_path`, the workspaces in the RUST_PATH are only
+/// searched for a library that can't be found anywhere else. With it, their
+/// `lib/<target triple>` directories are searched right after the
+/// additional search paths, the way rustpkg looks for installed packages.
+/// Either way, the search of the RUST_PATH stops at the first workspace
+/// with a match. The RUST_PATH is `maybe_rust_path` if it's given, and
+/// otherwise the one `rust_path` finds.
 pub fn mk_filesearch(maybe_sysroot: &Option<@Path>,
                      target_triple: &str,
-                     addl_lib_search_paths: @mut ~[Path])
+                     addl_lib_search_paths: @mut ~[Path],
+                     search_rust_path: bool,
+                     maybe_rust_path: Option<~[Path]>)
                   -> @FileSearch {
     struct FileSearchImpl {
         sysroot: @Path,
         addl_lib_search_paths: @mut ~[Path],
-        target_triple: ~str
+        target_triple: ~str,
+        search_rust_path: bool,
+        maybe_rust_path: Option<~[Path]>
+    }
+    impl FileSearchImpl {
+        fn for_each_rust_path_lib_dir(&self,
+                                      visited_dirs: &mut HashSet<~str>,
+                                      f: &fn(&Path) -> FileMatch) -> FileMatch {
+            for path in self.rust_path().iter() {
+                let tlib_path = rust_path_lib_dir(path, self.target_triple);
+                debug2!("is {} in visited_dirs? {:?}", tlib_path.to_str(),
+                        visited_dirs.contains(&tlib_path.to_str()));
+
+                if !visited_dirs.contains(&tlib_path.to_str()) {
+                    visited_dirs.insert(tlib_path.to_str());
+                    // Don't keep searching the RUST_PATH if one match turns up --
+                    // if we did, we'd get a "multiple matching crates" error
+                    match f(&tlib_path) {
+                       FileMatches => return FileMatches,
+                       FileDoesntMatch => ()
+                    }
+                }
+            }
+            FileDoesntMatch
+        }
     }
     impl FileSearch for FileSearchImpl {
         fn sysroot(&self) -> @Path { self.sysroot }
@@ -63,6 +97,14 @@ pub fn mk_filesearch(maybe_sysroot: &Option<@Path>,
                 visited_dirs.insert(path.to_str());
             }
 
+            if self.search_rust_path {
+                debug2!("filesearch: searching RUST_PATH workspaces");
+                match self.for_each_rust_path_lib_dir(&mut visited_dirs, |p| f(p)) {
+                    FileMatches => found = true,
+                    FileDoesntMatch => ()
+                }
+            }
+
             debug2!("filesearch: searching target lib path");
             let tlib_path = make_target_lib_path(self.sysroot,
                                         self.target_triple);
@@ -73,26 +115,9 @@ pub fn mk_filesearch(maybe_sysroot: &Option<@Path>,
                 }
             }
             visited_dirs.insert(tlib_path.to_str());
-            // Try RUST_PATH
-            if !found {
-                let rustpath = rust_path();
-                for path in rustpath.iter() {
-                    let tlib_path = make_rustpkg_target_lib_path(path, self.target_triple);
-                    debug2!("is {} in visited_dirs? {:?}", tlib_path.to_str(),
-                            visited_dirs.contains(&tlib_path.to_str()));
-
-                    if !visited_dirs.contains(&tlib_path.to_str()) {
-                        visited_dirs.insert(tlib_path.to_str());
-                        // Don't keep searching the RUST_PATH if one match turns up --
-                        // if we did, we'd get a "multiple matching crates" error
-                        match f(&tlib_path) {
-                           FileMatches => {
-                               break;
-                           }
-                           FileDoesntMatch => ()
-                        }
-                    }
-                }
+            // Fall back on RUST_PATH
+            if !found && !self.search_rust_path {
+                self.for_each_rust_path_lib_dir(&mut visited_dirs, |p| f(p));
             }
         }
         fn get_target_lib_path(&self) -> Path {
@@ -101,6 +126,12 @@ pub fn mk_filesearch(maybe_sysroot: &Option<@Path>,
         fn get_target_lib_file_path(&self, file: &Path) -> Path {
             self.get_target_lib_path().push_rel(file)
         }
+        fn rust_path(&self) -> ~[Path] {
+            match self.maybe_rust_path {
+                Some(ref rp) => rp.clone(),
+                None => rust_path()
+            }
+        }
     }
 
     let sysroot = get_sysroot(maybe_sysroot);
@@ -108,7 +139,9 @@ pub fn mk_filesearch(maybe_sysroot: &Option<@Path>,
     @FileSearchImpl {
         sysroot: sysroot,
         addl_lib_search_paths: addl_lib_search_paths,
-        target_triple: target_triple.to_owned()
+        target_triple: target_triple.to_owned(),
+        search_rust_path: search_rust_path,
+        maybe_rust_path: maybe_rust_path
     } as @FileSearch
 }
 
@@ -145,9 +178,10 @@ fn make_target_lib_path(sysroot: &Path,
     sysroot.push_rel(&relative_target_lib_path(target_triple))
 }
 
-fn make_rustpkg_target_lib_path(dir: &Path,
-                        target_triple: &str) -> Path {
-    dir.push_rel(&Path(libdir()).push(target_triple.to_owned()))
+/// The directory rustpkg installs libraries for `target_triple` into in the
+/// workspace `workspace`
+pub fn rust_path_lib_dir(workspace: &Path, target_triple: &str) -> Path {
+    workspace.push_rel(&Path(libdir()).push(target_triple.to_owned()))
 }
 
 pub fn get_or_default_sysroot() -> Path {
@@ -180,21 +214,30 @@ pub fn get_rust_path() -> Option<~str> {
 /// DIR/.rust for any DIR that's the current working directory
 /// or an ancestor of it
 pub fn rust_path() -> ~[Path] {
-    let mut env_rust_path: ~[Path] = match get_rust_path() {
+    rust_path_in(get_rust_path(), &os::getcwd())
+}
+
+/// As `rust_path`, for a program whose RUST_PATH is `env_path` and whose
+/// working directory is `cwd`, which needn't be this process's. Relative
+/// entries in `env_path` are taken relative to `cwd`.
+pub fn rust_path_in(env_path: Option<~str>, cwd: &Path) -> ~[Path] {
+    let mut env_rust_path: ~[Path] = match env_path {
         Some(env_path) => {
             let env_path_components: ~[&str] =
                 env_path.split_str_iter(PATH_ENTRY_SEPARATOR).collect();
-            env_path_components.map(|&s| Path(s))
+            env_path_components.map(|&s| {
+                let p = Path(s);
+                if p.is_absolute { p } else { cwd.push_rel(&p) }
+            })
         }
         None => ~[]
     };
-    let cwd = os::getcwd();
     // now add in default entries
     let cwd_dot_rust = cwd.push(".rust");
     if !env_rust_path.contains(&cwd_dot_rust) {
         env_rust_path.push(cwd_dot_rust);
     }
-    if !env_rust_path.contains(&cwd) {
+    if !env_rust_path.contains(cwd) {
         env_rust_path.push(cwd.clone());
     }
     do cwd.each_parent() |p| {
@@ -19,6 +19,7 @@ use metadata::filesearch;
 use syntax::codemap::Span;
 use syntax::diagnostic::span_handler;
 use syntax::parse::token::ident_interner;
+use syntax::parse::token;
 use syntax::print::pprust;
 use syntax::{ast, attr};
 use syntax::attr::AttrMetaMethods;
@@ -50,6 +51,9 @@ pub struct Context {
     metas: ~[@ast::MetaItem],
     hash: @str,
     os: Os,
+    // The target the crate being compiled is for; libraries built for
+    // other targets are passed over
+    triple: @str,
     is_static: bool,
     intr: @ident_interner
 }
@@ -65,11 +69,19 @@ pub fn load_library_crate(cx: &Context) -> (~str, @~[u8]) {
     }
 }
 
-fn find_library_crate(cx: &Context) -> Option<(~str, @~[u8])> {
+pub fn find_library_crate(cx: &Context) -> Option<(~str, @~[u8])> {
     attr::require_unique_names(cx.diag, cx.metas);
     find_library_crate_aux(cx, libname(cx), cx.filesearch)
 }
 
+/// Whether a library matching `cx` exists for its target. Unlike
+/// `find_library_crate`, this reports nothing, so it can be used to probe
+/// for a crate that may legitimately be missing or ambiguous.
+pub fn library_crate_exists(cx: &Context) -> bool {
+    let (matches, _) = search_library_crates(cx, libname(cx), cx.filesearch);
+    !matches.is_empty()
+}
+
 fn libname(cx: &Context) -> (~str, ~str) {
     if cx.is_static { return (~"lib", ~".rlib"); }
     let (dll_prefix, dll_suffix) = match cx.os {
@@ -83,15 +95,18 @@ fn libname(cx: &Context) -> (~str, ~str) {
     (dll_prefix.to_owned(), dll_suffix.to_owned())
 }
 
-fn find_library_crate_aux(
+// The libraries matching `cx` for its target, and the paths and targets of
+// those that match but were built for another target
+fn search_library_crates(
     cx: &Context,
     (prefix, suffix): (~str, ~str),
     filesearch: @filesearch::FileSearch
-) -> Option<(~str, @~[u8])> {
+) -> (~[(~str, @~[u8])], ~[(~str, @str)]) {
     let crate_name = crate_name_from_metas(cx.metas);
     // want: crate_name.dir_part() + prefix + crate_name.file_part + "-"
     let prefix = format!("{}{}-", prefix, crate_name);
     let mut matches = ~[];
+    let mut other_targets = ~[];
     filesearch::search(filesearch, |path| -> FileMatch {
       let path_str = path.filename();
       match path_str {
@@ -106,9 +121,20 @@ fn find_library_crate_aux(
                                   path.to_str());
                               FileDoesntMatch
                           } else {
-                              debug2!("found {} with matching metadata", path.to_str());
-                              matches.push((path.to_str(), cvec));
-                              FileMatches
+                              match decoder::get_crate_triple(cvec) {
+                                  Some(triple) if triple != cx.triple => {
+                                      debug2!("skipping {}, it was built for {}",
+                                          path.to_str(), triple);
+                                      other_targets.push((path.to_str(), triple));
+                                      FileDoesntMatch
+                                  }
+                                  _ => {
+                                      debug2!("found {} with matching metadata",
+                                          path.to_str());
+                                      matches.push((path.to_str(), cvec));
+                                      FileMatches
+                                  }
+                              }
                           },
                       _ => {
                           debug2!("could not load metadata for {}", path.to_str());
@@ -122,7 +148,27 @@ fn find_library_crate_aux(
       }
     });
 
+    (matches, other_targets)
+}
+
+fn find_library_crate_aux(
+    cx: &Context,
+    libname: (~str, ~str),
+    filesearch: @filesearch::FileSearch
+) -> Option<(~str, @~[u8])> {
+    let crate_name = crate_name_from_metas(cx.metas);
+    let (matches, other_targets) = search_library_crates(cx, libname, filesearch);
     match matches.len() {
+        0 if !other_targets.is_empty() => {
+            cx.diag.span_err(
+                cx.span, format!("`{}` was only found built for other targets \
+                                  than `{}`", crate_name, cx.triple));
+            for &(ref path, triple) in other_targets.iter() {
+                cx.diag.handler().note(format!("{} is for `{}`", *path, triple));
+            }
+            cx.diag.handler().abort_if_errors();
+            None
+        }
         0 => None,
         1 => Some(matches[0]),
         _ => {
@@ -277,3 +323,79 @@ pub fn list_file_metadata(intr: @ident_interner,
       }
     }
 }
+
+/// A dependency of a compiled crate, as recorded in its metadata.
+#[deriving(Clone)]
+pub struct DepInfo {
+    name: @str,
+    vers: @str,
+    hash: @str
+}
+
+/// What a compiled library says about itself: enough for tools like
+/// rustpkg to reason about installed crates without running the compiler.
+#[deriving(Clone)]
+pub struct CrateInfo {
+    name: @str,
+    vers: @str,
+    hash: @str,
+    package_id: Option<@str>,
+    deps: ~[DepInfo],
+    cfg: ~[@ast::MetaItem],
+    triple: Option<@str>,
+    opt_level: Option<uint>,
+    /// All of the crate's attributes, for tools that give meaning to
+    /// attributes of their own
+    attrs: ~[ast::Attribute]
+}
+
+impl CrateInfo {
+    /// Explains why a crate compiled for `triple` can't link against this
+    /// library, if it can't. `cfg` lists cfg items requested for every
+    /// crate in the build, which the library has to have been built with
+    /// too.
+    pub fn incompatibility(&self, triple: &str,
+                           cfg: &[@ast::MetaItem]) -> Option<~str> {
+        match self.triple {
+            Some(t) if t.as_slice() != triple => {
+                return Some(format!("it was built for `{}`, not `{}`", t, triple));
+            }
+            _ => {}
+        }
+        for needed in cfg.iter() {
+            if !attr::contains(self.cfg, *needed) {
+                return Some(format!("it was built without `--cfg {}`",
+                                    pprust::meta_item_to_str(*needed,
+                                        token::get_ident_interner())));
+            }
+        }
+        None
+    }
+}
+
+/// Reads the metadata of the library at `path`. Returns None if the file
+/// can't be read or has no metadata section.
+pub fn read_crate_info(os: Os, path: &Path) -> Option<CrateInfo> {
+    do get_metadata_section(os, path).map |data| {
+        let attrs = decoder::get_crate_attributes(data);
+        let linkage_metas = attr::find_linkage_metas(attrs);
+        let deps = do decoder::get_crate_deps(data).map |dep| {
+            DepInfo {
+                name: token::ident_to_str(&dep.name),
+                vers: dep.vers,
+                hash: dep.hash
+            }
+        };
+        CrateInfo {
+            name: crate_name_from_metas(linkage_metas),
+            vers: decoder::get_crate_vers(data),
+            hash: decoder::get_crate_hash(data),
+            package_id: package_id_from_metas(linkage_metas),
+            deps: deps,
+            cfg: decoder::get_crate_cfg(data),
+            triple: decoder::get_crate_triple(data),
+            opt_level: decoder::get_crate_opt_level(data),
+            attrs: attrs.clone()
+        }
+    }
+}
@@ -346,6 +346,10 @@ impl Context {
     }
 
     fn set_level(&mut self, lint: lint, level: level, src: LintSource) {
+        let level = match self.tcx.sess.opts.cap_lints {
+            Some(cap) => cmp::min(level, cap),
+            None => level
+        };
         if level == allow {
             self.cur.remove(&(lint as uint));
         } else {
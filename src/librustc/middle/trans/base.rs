@@ -1136,7 +1136,8 @@ pub fn trans_trace(bcx: @mut Block, sp_opt: Option<Span>, trace_str: @str) {
       Some(sp) => {
         let sess = bcx.sess();
         let loc = sess.parse_sess.cm.lookup_char_pos(sp.lo);
-        (C_cstr(bcx.ccx(), loc.file.name), loc.line as int)
+        let filename = sess.parse_sess.cm.display_filename(loc.file.name);
+        (C_cstr(bcx.ccx(), filename), loc.line as int)
       }
       None => {
         (C_cstr(bcx.ccx(), @"<runtime>"), 0)
@@ -2929,6 +2930,8 @@ pub fn create_module_map(ccx: &mut CrateContext) -> (ValueRef, uint, uint) {
     for (k, _) in ccx.module_data.iter() {
         keys.push(k.to_managed());
     }
+    // Hash map order changes from run to run, and the map shouldn't
+    sort::quick_sort(keys, |a, b| a <= b);
 
     for key in keys.iter() {
             let val = *ccx.module_data.find_equiv(key).unwrap();
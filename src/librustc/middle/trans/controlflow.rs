@@ -338,7 +338,8 @@ fn trans_fail_value(bcx: @mut Block,
       Some(sp) => {
         let sess = bcx.sess();
         let loc = sess.parse_sess.cm.lookup_char_pos(sp.lo);
-        (C_cstr(bcx.ccx(), loc.file.name),
+        let filename = sess.parse_sess.cm.display_filename(loc.file.name);
+        (C_cstr(bcx.ccx(), filename),
          loc.line as int)
       }
       None => {
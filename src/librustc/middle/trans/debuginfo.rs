@@ -106,6 +106,7 @@ use std::c_str::ToCStr;
 use std::hashmap::HashMap;
 use std::hashmap::HashSet;
 use std::libc::{c_uint, c_ulonglong, c_longlong};
+use std::path;
 use std::ptr;
 use std::unstable::atomics;
 use std::vec;
@@ -855,11 +856,12 @@ fn create_DIArray(builder: DIBuilderRef, arr: &[DIDescriptor]) -> DIArray {
 
 fn compile_unit_metadata(cx: @mut CrateContext) {
     let dcx = debug_context(cx);
-    let crate_name: &str = dcx.crate_file;
+    let crate_file = cx.sess.codemap.display_filename(dcx.crate_file.to_managed());
+    let crate_name: &str = crate_file;
 
     debug2!("compile_unit_metadata: {:?}", crate_name);
 
-    let work_dir = cx.sess.working_dir.to_str();
+    let work_dir = debug_work_dir(cx);
     let producer = format!("rustc version {}", env!("CFG_VERSION"));
 
     do crate_name.with_c_str |crate_name| {
@@ -961,6 +963,16 @@ fn declare_local(bcx: @mut Block,
     }
 }
 
+// The compilation directory recorded in debug info. -Z deterministic leaves
+// out the real one, since it would differ between checkouts.
+fn debug_work_dir(cx: &CrateContext) -> ~str {
+    if cx.sess.deterministic() {
+        ~"."
+    } else {
+        cx.sess.working_dir.to_str()
+    }
+}
+
 fn file_metadata(cx: &mut CrateContext, full_path: &str) -> DIFile {
     match debug_context(cx).created_files.find_equiv(&full_path) {
         Some(file_metadata) => return *file_metadata,
@@ -969,12 +981,20 @@ fn file_metadata(cx: &mut CrateContext, full_path: &str) -> DIFile {
 
     debug2!("file_metadata: {}", full_path);
 
-    let work_dir = cx.sess.working_dir.to_str();
+    let display_path = cx.sess.codemap.display_filename(full_path.to_managed());
+    let work_dir = debug_work_dir(cx);
+    // Only a work_dir that's a whole leading directory is stripped, so with
+    // -Z deterministic "./a.rs" becomes "a.rs" but ".a.rs" is kept
+    let is_sep = |c: char| {
+        if cfg!(windows) { path::windows::is_sep(c) } else { path::posix::is_sep(c) }
+    };
     let file_name =
-        if full_path.starts_with(work_dir) {
-            full_path.slice(work_dir.len() + 1u, full_path.len())
+        if display_path.starts_with(work_dir) &&
+           display_path.len() > work_dir.len() &&
+           is_sep(display_path.char_at(work_dir.len())) {
+            display_path.slice(work_dir.len() + 1u, display_path.len())
         } else {
-            full_path
+            display_path.as_slice()
         };
 
     let file_metadata =
@@ -27,7 +27,7 @@
 extern mod extra;
 extern mod syntax;
 
-use driver::driver::{host_triple, optgroups, early_error};
+use driver::driver::{host_triple, optgroups, early_error, error_format};
 use driver::driver::{str_input, file_input, build_session_options};
 use driver::driver::{build_session, build_configuration, parse_pretty};
 use driver::driver::{PpMode, pretty_print_input, list_metadata};
@@ -98,6 +98,7 @@ pub mod back {
     pub mod x86_64;
     pub mod rpath;
     pub mod target_strs;
+    pub mod target_spec;
 }
 
 pub mod metadata;
@@ -215,9 +216,12 @@ pub fn run_compiler(args: &[~str], demitter: @diagnostic::Emitter) {
         return;
     }
 
+    if matches.opt_present("error-format") && error_format(matches).is_none() {
+        early_error(demitter, "unknown error format: expected `human` or `json`");
+    }
+
     // Display the available lint options if "-W help" or only "-W" is given.
-    let lint_flags = vec::append(matches.opt_strs("W"),
-                                 matches.opt_strs("warn"));
+    let lint_flags = matches.opt_strs("warn");
 
     let show_lint_options = lint_flags.iter().any(|x| x == &~"help") ||
         (matches.opt_present("W") && lint_flags.is_empty());
@@ -258,8 +262,8 @@ pub fn run_compiler(args: &[~str], demitter: @diagnostic::Emitter) {
 
     let sopts = build_session_options(binary, matches, demitter);
     let sess = build_session(sopts, demitter);
-    let odir = matches.opt_str("out-dir").map(|o| Path(o));
-    let ofile = matches.opt_str("o").map(|o| Path(o));
+    let odir = matches.opt_path("out-dir");
+    let ofile = matches.opt_path("o");
     let cfg = build_configuration(sess);
     let pretty = do matches.opt_default("pretty", "normal").map |a| {
         parse_pretty(sess, a)
@@ -294,7 +298,8 @@ pub enum monitor_msg {
 }
 
 struct RustcEmitter {
-    ch_capture: comm::SharedChan<monitor_msg>
+    ch_capture: comm::SharedChan<monitor_msg>,
+    out: @diagnostic::Emitter
 }
 
 impl diagnostic::Emitter for RustcEmitter {
@@ -306,7 +311,7 @@ impl diagnostic::Emitter for RustcEmitter {
             self.ch_capture.send(fatal)
         }
 
-        diagnostic::DefaultEmitter.emit(cmsp, msg, lvl)
+        self.out.emit(cmsp, msg, lvl)
     }
 }
 
@@ -322,7 +327,7 @@ diagnostic emitter which records when we hit a fatal error. If the task
 fails without recording a fatal error then we've encountered a compiler
 bug and need to present an error.
 */
-pub fn monitor(f: ~fn(@diagnostic::Emitter)) {
+pub fn monitor(format: diagnostic::ErrorFormat, f: ~fn(@diagnostic::Emitter)) {
     use std::comm::*;
 
     // XXX: This is a hack for newsched since it doesn't support split stacks.
@@ -347,6 +352,7 @@ pub fn monitor(f: ~fn(@diagnostic::Emitter)) {
         // go through this function.
         let demitter = @RustcEmitter {
             ch_capture: ch.clone(),
+            out: format.emitter(),
         } as @diagnostic::Emitter;
 
         struct finally {
@@ -369,7 +375,8 @@ pub fn monitor(f: ~fn(@diagnostic::Emitter)) {
         result::Err(_) => {
             // Task failed without emitting a fatal diagnostic
             if p.recv() == done {
-                diagnostic::DefaultEmitter.emit(
+                let out = format.emitter();
+                out.emit(
                     None,
                     diagnostic::ice_msg("unexpected failure"),
                     diagnostic::error);
@@ -382,9 +389,7 @@ pub fn monitor(f: ~fn(@diagnostic::Emitter)) {
                      to github.com/mozilla/rust/issues"
                 ];
                 for note in xs.iter() {
-                    diagnostic::DefaultEmitter.emit(None,
-                                                    *note,
-                                                    diagnostic::note)
+                    out.emit(None, *note, diagnostic::note)
                 }
             }
             // Fail so the process returns a failure code
@@ -399,7 +404,13 @@ pub fn main() {
 
 pub fn main_args(args: &[~str]) -> int {
     let owned_args = args.to_owned();
-    do monitor |demitter| {
+    // The format has to be known before the options are checked, so that
+    // errors in them are reported in it too
+    let format = match getopts::groups::getopts(args.tail(), optgroups()) {
+        Ok(ref matches) => error_format(matches).unwrap_or(diagnostic::HumanReadable),
+        Err(_) => diagnostic::HumanReadable
+    };
+    do monitor(format) |demitter| {
         run_compiler(owned_args, demitter);
     }
 
@@ -138,6 +138,7 @@ fn external_path(w: &mut io::Writer, p: &clean::Path, print_all: bool,
             match *cache.extern_locations.get(&crate) {
                 render::Remote(ref s) => Some(s.clone()),
                 render::Local => Some("../".repeat(loc.len())),
+                render::OtherRoot(ref s) => Some("../".repeat(loc.len()) + *s),
                 render::Unknown => None,
             }
         },
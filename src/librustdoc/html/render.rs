@@ -39,6 +39,7 @@ use std::comm;
 use std::fmt;
 use std::hashmap::{HashMap, HashSet};
 use std::local_data;
+use std::os;
 use std::rt::io::buffered::BufferedWriter;
 use std::rt::io::file::{FileInfo, DirectoryInfo};
 use std::rt::io::file;
@@ -104,6 +105,9 @@ pub enum ExternalLocation {
     Remote(~str),
     /// This external crate can be found in the local doc/ folder
     Local,
+    /// This external crate was documented into another doc root, found at
+    /// this path relative to the local doc/ folder
+    OtherRoot(~str),
     /// The external crate could not be found.
     Unknown,
 }
@@ -204,8 +208,10 @@ struct IndexItem {
 local_data_key!(pub cache_key: RWArc<Cache>)
 local_data_key!(pub current_location_key: ~[~str])
 
-/// Generates the documentation for `crate` into the directory `dst`
-pub fn run(mut crate: clean::Crate, dst: Path) {
+/// Generates the documentation for `crate` into the directory `dst`. Links to
+/// external crates documented into any of `doc_roots` (such as the doc/
+/// folders of a rustpkg workspace) point there.
+pub fn run(mut crate: clean::Crate, dst: Path, doc_roots: &[Path]) {
     let mut cx = Context {
         dst: dst,
         current: ~[],
@@ -306,7 +312,7 @@ pub fn run(mut crate: clean::Crate, dst: Path) {
     }
 
     for (&n, e) in crate.externs.iter() {
-        cache.extern_locations.insert(n, extern_location(e, &cx.dst));
+        cache.extern_locations.insert(n, extern_location(e, &cx.dst, doc_roots));
     }
 
     // And finally render the whole crate's documentation
@@ -353,13 +359,27 @@ fn clean_srcpath(src: &str, f: &fn(&str)) {
 
 /// Attempts to find where an external crate is located, given that we're
 /// rendering in to the specified source destination.
-fn extern_location(e: &clean::ExternalCrate, dst: &Path) -> ExternalLocation {
+fn extern_location(e: &clean::ExternalCrate, dst: &Path,
+                   doc_roots: &[Path]) -> ExternalLocation {
     // See if there's documentation generated into the local directory
     let local_location = dst.push(e.name);
     if local_location.is_dir() {
         return Local;
     }
 
+    // Next, look through the other doc roots we were given. Links to them are
+    // relative so that a tree of doc roots can be moved around as a whole.
+    for root in doc_roots.iter() {
+        if root.push(e.name).is_dir() {
+            // A root that can't be reached by a relative path, such as one
+            // on another drive, is no use to links
+            match os::make_absolute(root).relative_from(&os::make_absolute(dst)) {
+                Some(rel) => return OtherRoot(rel.to_str() + "/"),
+                None => {}
+            }
+        }
+    }
+
     // Failing that, see if there's an attribute specifying where to find this
     // external crate
     for attr in e.attrs.iter() {
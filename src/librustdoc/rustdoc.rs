@@ -91,6 +91,8 @@ pub fn opts() -> ~[groups::OptGroup] {
         optopt("o", "output", "where to place the output", "PATH"),
         optmulti("L", "library-path", "directory to add to crate search path",
                  "DIR"),
+        optmulti("", "doc-root", "directory holding the documentation of other \
+                                  crates to link to", "DIR"),
         optmulti("", "plugin-path", "directory to load plugins from", "DIR"),
         optmulti("", "passes", "space separated list of passes to also run, a \
                                 value of `list` will print available passes",
@@ -137,7 +139,8 @@ pub fn main_args(args: &[~str]) -> int {
     let output = matches.opt_str("o").map(|s| Path(s));
     match matches.opt_str("w") {
         Some(~"html") | None => {
-            html::render::run(crate, output.unwrap_or(Path("doc")))
+            let doc_roots = matches.opt_strs("doc-root").map(|s| Path(s.as_slice()));
+            html::render::run(crate, output.unwrap_or(Path("doc")), doc_roots)
         }
         Some(~"json") => {
             json_output(crate, res, output.unwrap_or(Path("doc.json")))
@@ -188,7 +191,7 @@ fn rust_input(cratefile: &str, matches: &getopts::Matches) -> Output {
     let mut plugins = matches.opt_strs("plugins");
 
     // First, parse the crate and extract all relevant information.
-    let libs = Cell::new(matches.opt_strs("L").map(|s| Path(*s)));
+    let libs = Cell::new(matches.opt_paths("L"));
     let cr = Cell::new(Path(cratefile));
     info2!("starting to run rustc");
     let crate = do std::task::try {
@@ -14,6 +14,8 @@ use package_id::*;
 use package_source::*;
 use target::*;
 use version::Version;
+use messages::warn;
+use path_util::rust_path;
 use workcache_support::*;
 
 pub use source_control::{safe_git_clone, git_clone_url};
@@ -21,7 +23,7 @@ pub use source_control::{safe_git_clone, git_clone_url};
 use std::os;
 use extra::arc::{Arc,RWArc};
 use extra::workcache;
-use extra::workcache::{Database, Logger, FreshnessMap};
+use extra::workcache::{Database, Logger, FreshnessMap, DigestMap};
 use extra::treemap::TreeMap;
 
 /// Convenience functions intended for calling from pkg.rs
@@ -36,7 +38,17 @@ pub fn new_default_context(c: workcache::Context, p: Path) -> BuildContext {
             cfgs: ~[],
             rustc_flags: RustcFlags::default(),
             use_rust_path_hack: false,
-            sysroot: p
+            symlink_executables: false,
+            install_docs: false,
+            offline: false,
+            force_uninstall: false,
+            verbose: false,
+            timeout: None,
+            sysroot: p,
+            cwd: os::getcwd(),
+            env: ~[],
+            rust_path: rust_path(),
+            locked_workspaces: RWArc::new(~[])
         },
         workcache_context: c
     }
@@ -52,10 +64,28 @@ fn binary_is_fresh(path: &str, in_hash: &str) -> bool {
     os::path_exists(&path) && in_hash == digest_only_date(&path)
 }
 
+// The current values of files and binaries, to explain rebuilds with
+fn file_digest(path: &str) -> Option<~str> {
+    let path = Path(path);
+    if os::path_exists(&path) { Some(digest_file_with_date(&path)) } else { None }
+}
+
+fn binary_digest(path: &str) -> Option<~str> {
+    let path = Path(path);
+    if os::path_exists(&path) { Some(digest_only_date(&path)) } else { None }
+}
+
 pub fn new_workcache_context(p: &Path) -> workcache::Context {
     let db_file = p.push("rustpkg_db.json"); // ??? probably wrong
     debug2!("Workcache database file: {}", db_file.to_str());
-    let db = RWArc::new(Database::new(db_file));
+    let db = match Database::try_new(db_file.clone()) {
+        Ok(db) => db,
+        Err(e) => {
+            warn(format!("{}; discarding it", e));
+            Database::reset(db_file)
+        }
+    };
+    let db = RWArc::new(db);
     let lg = RWArc::new(Logger::new());
     let cfg = Arc::new(TreeMap::new());
     let mut freshness: FreshnessMap = TreeMap::new();
@@ -63,7 +93,10 @@ pub fn new_workcache_context(p: &Path) -> workcache::Context {
     // knows about
     freshness.insert(~"file", file_is_fresh);
     freshness.insert(~"binary", binary_is_fresh);
-    workcache::Context::new_with_freshness(db, lg, cfg, Arc::new(freshness))
+    let mut digests: DigestMap = TreeMap::new();
+    digests.insert(~"file", file_digest);
+    digests.insert(~"binary", binary_digest);
+    workcache::Context::new_with_digests(db, lg, cfg, Arc::new(freshness), Arc::new(digests))
 }
 
 pub fn build_lib(sysroot: Path, root: Path, name: ~str, version: Version,
@@ -0,0 +1,194 @@
+// Copyright 2013 The Rust Project Developers. See the COPYRIGHT
+// file at the top-level directory of this distribution and at
+// http://rust-lang.org/COPYRIGHT.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+// Shell completion scripts, generated from the same tables of commands and
+// options that the command line is parsed with. Package IDs are completed
+// with the names of installed packages, which the scripts get by running
+// `rustpkg list` when they're needed.
+
+use extra::getopts::groups::{Command, OptGroup};
+use extra::getopts::{Yes, No, Maybe};
+
+// The commands whose arguments are package IDs
+static PKG_ID_COMMANDS: &'static [&'static str] =
+    &["build", "clean", "do", "fetch", "info", "install", "prefer", "test", "uninstall",
+      "unprefer"];
+
+// Lists the installed packages, one per line. A package ID has no spaces in
+// it, unlike list's heading and any messages printed along with it.
+static LIST_PACKAGES: &'static str = "rustpkg list 2>/dev/null | grep -E '^[^[:space:]]+$'";
+
+/// The shells there are completion scripts for
+pub static SHELLS: &'static [&'static str] = &["bash", "zsh"];
+
+/// The completion script for `shell`, or None if there isn't one
+pub fn script(shell: &str, global: &[OptGroup], commands: &[Command]) -> Option<~str> {
+    match shell {
+        "bash" => Some(bash(global, commands)),
+        "zsh" => Some(zsh(global, commands)),
+        _ => None
+    }
+}
+
+fn bash(global: &[OptGroup], commands: &[Command]) -> ~str {
+    let mut s = ~"# bash completion for rustpkg, generated by `rustpkg completions bash`
+
+_rustpkg_packages() {
+    ";
+    s.push_str(LIST_PACKAGES);
+    s.push_str("
+}
+
+_rustpkg() {
+    local cur cmd i
+    cur=\"${COMP_WORDS[COMP_CWORD]}\"
+    cmd=\"\"
+    for ((i = 1; i < COMP_CWORD; i++)); do
+        case \"${COMP_WORDS[i]}\" in
+";
+    let takes_arg = flags_taking_args(global);
+    if !takes_arg.is_empty() {
+        s.push_str(format!("            {}) ((i++)) ;;\n", takes_arg.connect("|")));
+    }
+    s.push_str("            -*) ;;
+            *) cmd=\"${COMP_WORDS[i]}\"; break ;;
+        esac
+    done
+    case \"$cmd\" in
+");
+    let names = commands.map(|c| c.name.clone());
+    s.push_str(format!("        \"\") COMPREPLY=($(compgen -W \"{} {}\" -- \"$cur\")) ;;\n",
+                       flags(global).connect(" "), names.connect(" ")));
+    for c in commands.iter() {
+        let words = if PKG_ID_COMMANDS.contains(&c.name.as_slice()) {
+            ~"$(_rustpkg_packages)"
+        } else if "completions" == c.name {
+            SHELLS.connect(" ")
+        } else {
+            ~""
+        };
+        s.push_str(format!("        {})
+            if [[ \"$cur\" == -* ]]; then
+                COMPREPLY=($(compgen -W \"{}\" -- \"$cur\"))
+            else
+                COMPREPLY=($(compgen -W \"{}\" -- \"$cur\"))
+            fi ;;\n", c.name, (flags(global) + flags(c.opts)).connect(" "), words));
+    }
+    s.push_str("    esac
+}
+
+complete -o default -F _rustpkg rustpkg
+");
+    s
+}
+
+fn zsh(global: &[OptGroup], commands: &[Command]) -> ~str {
+    let mut s = ~"#compdef rustpkg
+# zsh completion for rustpkg, generated by `rustpkg completions zsh`
+
+_rustpkg() {
+    local -a cmds opts words_to_use
+    local cmd i
+    cmds=(
+";
+    for c in commands.iter() {
+        s.push_str(format!("        {}\n", described(c.name, c.desc)));
+    }
+    s.push_str("    )
+    for ((i = 2; i < CURRENT; i++)); do
+        case \"${words[i]}\" in
+");
+    let takes_arg = flags_taking_args(global);
+    if !takes_arg.is_empty() {
+        s.push_str(format!("            {}) ((i++)) ;;\n", takes_arg.connect("|")));
+    }
+    s.push_str("            -*) ;;
+            *) cmd=\"${words[i]}\"; break ;;
+        esac
+    done
+    opts=(
+");
+    push_described_flags(&mut s, global);
+    s.push_str("    )
+    case \"$cmd\" in
+        \"\")
+            if [[ \"$PREFIX\" == -* ]]; then
+                _describe -t options 'option' opts
+            else
+                _describe -t commands 'rustpkg command' cmds
+            fi
+            return ;;
+");
+    for c in commands.iter() {
+        s.push_str(format!("        {})\n            opts+=(\n", c.name));
+        push_described_flags(&mut s, c.opts);
+        s.push_str("            )\n");
+        if PKG_ID_COMMANDS.contains(&c.name.as_slice()) {
+            s.push_str(format!("            words_to_use=($\\{(f)\"$({})\"\\}) ;;\n",
+                               LIST_PACKAGES));
+        } else if "completions" == c.name {
+            s.push_str(format!("            words_to_use=({}) ;;\n", SHELLS.connect(" ")));
+        } else {
+            s.push_str("            ;;\n");
+        }
+    }
+    s.push_str("    esac
+    if [[ \"$PREFIX\" == -* ]]; then
+        _describe -t options 'option' opts
+    else
+        compadd -a words_to_use
+    fi
+}
+
+_rustpkg \"$@\"
+");
+    s
+}
+
+// Every way to write each option in `opts`
+fn flags(opts: &[OptGroup]) -> ~[~str] {
+    let mut result = ~[];
+    for o in opts.iter() {
+        if !o.long_name.is_empty() {
+            result.push(format!("--{}", o.long_name));
+        }
+        if !o.short_name.is_empty() {
+            result.push(format!("-{}", o.short_name));
+        }
+    }
+    result
+}
+
+// The ways to write the options in `opts` that are followed by an argument
+// as a separate word
+fn flags_taking_args(opts: &[OptGroup]) -> ~[~str] {
+    let mut taking = ~[];
+    for o in opts.iter() {
+        match o.hasarg {
+            Yes => taking.push(o.clone()),
+            No | Maybe => ()
+        }
+    }
+    flags(taking)
+}
+
+fn push_described_flags(s: &mut ~str, opts: &[OptGroup]) {
+    for o in opts.iter() {
+        for f in flags([o.clone()]).iter() {
+            s.push_str(format!("        {}\n", described(*f, o.desc)));
+        }
+    }
+}
+
+// An entry for zsh's _describe: `name:description`, quoted for the shell
+fn described(name: &str, desc: &str) -> ~str {
+    let entry = format!("{}:{}", name.replace(":", "\\:"), desc);
+    format!("'{}'", entry.replace("'", "'\\''"))
+}
@@ -10,9 +10,13 @@
 
 // Context data structure used by rustpkg
 
-use std::{io, os};
+use std::os;
+use extra::arc::RWArc;
 use extra::workcache;
+use rustc::driver::driver::PpMode;
 use rustc::driver::session::{OptLevel, No};
+use rustc::metadata::filesearch;
+use path_util::default_workspace;
 
 #[deriving(Clone)]
 pub struct Context {
@@ -25,8 +29,41 @@ pub struct Context {
     // FOO/src/bar-0.1 instead of FOO). The flag doesn't affect where
     // rustpkg stores build artifacts.
     use_rust_path_hack: bool,
+    // If symlink_executables is true, installed executables are symlinks
+    // to the built ones, so rebuilding updates them without reinstalling
+    symlink_executables: bool,
+    // If install_docs is true, install generates the package's
+    // documentation and installs it too
+    install_docs: bool,
+    // If offline is true, packages whose sources aren't in a workspace
+    // already are reported as missing rather than fetched
+    offline: bool,
+    // If force_uninstall is true, uninstall removes installed files even
+    // if they've changed since they were installed
+    force_uninstall: bool,
+    // If verbose is true, what package scripts and rustdoc print is shown
+    // as they print it
+    verbose: bool,
+    // How many seconds the programs rustpkg runs may take before they're
+    // killed, or None to let them run for as long as they like
+    timeout: Option<u64>,
     // The root directory containing the Rust standard libraries
-    sysroot: Path
+    sysroot: Path,
+    // The directory rustpkg was run in. It's kept here rather than read
+    // from the process, so that rustpkg can be run in-process (see
+    // `rustpkg::run`) without changing the process's working directory.
+    cwd: Path,
+    // Variables set for this run of rustpkg on top of the process's
+    // environment. The processes rustpkg starts get them too.
+    env: ~[(~str, ~str)],
+    // The workspaces to look for packages in, as `rust_path_in` finds them
+    // for `cwd` and `env`
+    rust_path: ~[Path],
+    // The lock files of the workspaces this run has locked so far. It's
+    // shared with the contexts that dependencies are built in, so that
+    // they can lock their own workspaces without waiting on this run's
+    // locks. See `workspace::lock_workspaces`.
+    locked_workspaces: RWArc<~[~str]>
 }
 
 #[deriving(Clone)]
@@ -51,6 +88,14 @@ impl BuildContext {
         self.context.flag_strs()
     }
 
+    /// The context to build a dependency of the package being built in.
+    /// Its lints are silenced, since they aren't the user's to fix.
+    pub fn for_dependency(&self) -> BuildContext {
+        let mut cx = self.clone();
+        cx.context.rustc_flags.cap_lints = Some(~"allow");
+        cx
+    }
+
     pub fn compile_upto(&self) -> StopBefore {
         self.context.compile_upto()
     }
@@ -59,6 +104,7 @@ impl BuildContext {
 /*
 Deliberately unsupported rustc flags:
    --bin, --lib           inferred from crate file names
+   --staticlib            use #[crate_type = "staticlib"] in lib.rs
    -L                     inferred from extern mods
    --out-dir              inferred from RUST_PATH
    --test                 use `rustpkg test`
@@ -85,6 +131,10 @@ pub struct RustcFlags {
     target: Option<~str>,
     // Target CPU (defaults to rustc's default target CPU)
     target_cpu: Option<~str>,
+    // How executables link Rust libraries; passed to rustc with --linkage
+    linkage: ~[~str],
+    // Highest lint level, passed to rustc with --cap-lints
+    cap_lints: Option<~str>,
     // Any -Z features
     experimental_features: Option<~[~str]>
 }
@@ -99,6 +149,8 @@ impl Clone for RustcFlags {
             save_temps: self.save_temps,
             target: self.target.clone(),
             target_cpu: self.target_cpu.clone(),
+            linkage: self.linkage.clone(),
+            cap_lints: self.cap_lints.clone(),
             experimental_features: self.experimental_features.clone()
         }
     }
@@ -112,7 +164,7 @@ pub enum StopBefore {
     LLVMAssemble, // -S --emit-llvm
     Assemble, // -S without --emit-llvm
     Trans,    // --no-trans
-    Pretty,   // --pretty
+    Pretty(PpMode), // --pretty
     Analysis, // --parse-only
 }
 
@@ -144,6 +196,36 @@ impl Context {
     pub fn compile_upto(&self) -> StopBefore {
         self.rustc_flags.compile_upto
     }
+
+    /// The value of the variable `name` in the environment rustpkg was
+    /// run with
+    pub fn getenv(&self, name: &str) -> Option<~str> {
+        getenv_in(self.env, name)
+    }
+
+    /// The workspace to fetch and install packages into when no other one
+    /// is called for
+    pub fn default_workspace(&self) -> Path {
+        default_workspace(self.rust_path)
+    }
+
+    /// Whether `p` is one of the workspaces in the RUST_PATH
+    pub fn in_rust_path(&self, p: &Path) -> bool {
+        self.rust_path.contains(p)
+    }
+}
+
+/// The RUST_PATH that rustpkg works with when run in `cwd` with the
+/// variables in `env` set
+pub fn rust_path_in(cwd: &Path, env: &[(~str, ~str)]) -> ~[Path] {
+    filesearch::rust_path_in(getenv_in(env, "RUST_PATH"), cwd)
+}
+
+fn getenv_in(env: &[(~str, ~str)], name: &str) -> Option<~str> {
+    match env.iter().find(|&&(ref k, _)| k.as_slice() == name) {
+        Some(&(_, ref v)) => Some(v.clone()),
+        None => os::getenv(name)
+    }
 }
 
 /// We assume that if ../../rustc exists, then we're running
@@ -173,6 +255,11 @@ impl RustcFlags {
             Some(ref l) => ~[~"--target-cpu", l.clone()],
             None        => ~[]
         };
+        let linkage_flags = self.linkage.flat_map(|l| ~[~"--linkage", l.clone()]);
+        let cap_lints_flag = match self.cap_lints {
+            Some(ref l) => ~[~"--cap-lints", l.clone()],
+            None        => ~[]
+        };
         let z_flags = match self.experimental_features {
             Some(ref ls)    => ls.flat_map(|s| ~[~"-Z", s.clone()]),
             None            => ~[]
@@ -182,14 +269,15 @@ impl RustcFlags {
             + save_temps_flag
             + target_flag
             + target_cpu_flag
+            + linkage_flags
+            + cap_lints_flag
             + z_flags + (match self.compile_upto {
             LLVMCompileBitcode => ~[~"--emit-llvm"],
             LLVMAssemble => ~[~"--emit-llvm", ~"-S"],
             Link => ~[~"-c"],
             Trans => ~[~"--no-trans"],
             Assemble => ~[~"-S"],
-            // n.b. Doesn't support all flavors of --pretty (yet)
-            Pretty => ~[~"--pretty"],
+            Pretty(ppm) => ~[format!("--pretty={}", ppm.name())],
             Analysis => ~[~"--parse-only"],
             Nothing => ~[]
         })
@@ -204,90 +292,9 @@ impl RustcFlags {
             save_temps: false,
             target: None,
             target_cpu: None,
+            linkage: ~[],
+            cap_lints: None,
             experimental_features: None
         }
     }
 }
-
-/// Returns true if any of the flags given are incompatible with the cmd
-pub fn flags_forbidden_for_cmd(flags: &RustcFlags,
-                        cfgs: &[~str],
-                        cmd: &str, user_supplied_opt_level: bool) -> bool {
-    let complain = |s| {
-        println!("The {} option can only be used with the `build` command:
-                  rustpkg [options..] build {} [package-ID]", s, s);
-    };
-
-    if flags.linker.is_some() && cmd != "build" && cmd != "install" {
-        io::println("The --linker option can only be used with the build or install commands.");
-        return true;
-    }
-    if flags.link_args.is_some() && cmd != "build" && cmd != "install" {
-        io::println("The --link-args option can only be used with the build or install commands.");
-        return true;
-    }
-
-    if !cfgs.is_empty() && cmd != "build" && cmd != "install" {
-        io::println("The --cfg option can only be used with the build or install commands.");
-        return true;
-    }
-
-    if user_supplied_opt_level && cmd != "build" && cmd != "install" {
-        io::println("The -O and --opt-level options can only be used with the build \
-                    or install commands.");
-        return true;
-    }
-
-    if flags.save_temps  && cmd != "build" && cmd != "install" {
-        io::println("The --save-temps option can only be used with the build \
-                    or install commands.");
-        return true;
-    }
-
-    if flags.target.is_some()  && cmd != "build" && cmd != "install" {
-        io::println("The --target option can only be used with the build \
-                    or install commands.");
-        return true;
-    }
-    if flags.target_cpu.is_some()  && cmd != "build" && cmd != "install" {
-        io::println("The --target-cpu option can only be used with the build \
-                    or install commands.");
-        return true;
-    }
-    if flags.experimental_features.is_some() && cmd != "build" && cmd != "install" {
-        io::println("The -Z option can only be used with the build or install commands.");
-        return true;
-    }
-
-    match flags.compile_upto {
-        Link if cmd != "build" => {
-            complain("--no-link");
-            true
-        }
-        Trans if cmd != "build" => {
-            complain("--no-trans");
-            true
-        }
-        Assemble if cmd != "build" => {
-            complain("-S");
-            true
-        }
-        Pretty if cmd != "build" => {
-            complain("--pretty");
-            true
-        }
-        Analysis if cmd != "build" => {
-            complain("--parse-only");
-            true
-        }
-        LLVMCompileBitcode if cmd != "build" => {
-            complain("--emit-llvm");
-            true
-        }
-        LLVMAssemble if cmd != "build" => {
-            complain("--emit-llvm");
-            true
-        }
-        _ => false
-    }
-}
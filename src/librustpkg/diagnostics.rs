@@ -0,0 +1,112 @@
+// Copyright 2013 The Rust Project Developers. See the COPYRIGHT
+// file at the top-level directory of this distribution and at
+// http://rust-lang.org/COPYRIGHT.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+// The warnings printed while compiling a crate, kept so that they can be
+// printed again when the crate is fresh and isn't recompiled. Otherwise a
+// second `rustpkg build` would look clean just because nothing was built.
+//
+// They're kept in the build directory, next to the crate's output, as
+// `<crate>.diagnostics.json`. Replaying a diagnostic prints its location
+// and message, but not the excerpt of the source under it.
+
+use std::{io, os};
+use extra::json;
+use extra::serialize::{Encodable, Decodable};
+use syntax::diagnostic;
+use package_id::PkgId;
+use path_util::{U_RWX, target_build_dir};
+
+#[deriving(Clone, Eq, Encodable, Decodable)]
+pub struct RecordedDiagnostic {
+    // "warning", "note" or "error"
+    level: ~str,
+    // As rustc prints a span, or empty if there's none
+    location: ~str,
+    message: ~str
+}
+
+impl RecordedDiagnostic {
+    pub fn new(diag: &diagnostic::Diagnostic) -> RecordedDiagnostic {
+        let level = match diag.level {
+            diagnostic::fatal | diagnostic::error => ~"error",
+            diagnostic::warning => ~"warning",
+            diagnostic::note => ~"note"
+        };
+        let location = match diag.span {
+            Some(ref sp) => format!("{}:{}:{}: {}:{}", sp.file_name, sp.line_start,
+                                    sp.column_start, sp.line_end, sp.column_end),
+            None => ~""
+        };
+        RecordedDiagnostic { level: level, location: location, message: diag.message.clone() }
+    }
+
+    fn replay(&self) {
+        let level = match self.level.as_slice() {
+            "warning" => diagnostic::warning,
+            "note" => diagnostic::note,
+            _ => diagnostic::error
+        };
+        diagnostic::print_diagnostic(self.location, level, self.message);
+    }
+}
+
+/// Where the diagnostics from compiling `crate` in the package `pkg_id`
+/// are kept, when it's built in `workspace`
+pub fn diagnostics_file(workspace: &Path, pkg_id: &PkgId, crate: &Path) -> Path {
+    let stem = crate.filestem().expect("diagnostics_file: crate has no file name");
+    target_build_dir(workspace).push_rel(&pkg_id.path)
+        .push(format!("{}.diagnostics.json", stem))
+}
+
+/// Records `diags` as the diagnostics from the latest compilation of the
+/// crate whose diagnostics are kept in `file`, replacing earlier ones
+pub fn write_diagnostics(file: &Path, diags: &[RecordedDiagnostic]) {
+    if diags.is_empty() {
+        if os::path_exists(file) && !os::remove_file(file) {
+            debug2!("Couldn't remove stale diagnostics {}", file.to_str());
+        }
+        return;
+    }
+    if !os::path_is_dir(&file.dir_path()) && !os::mkdir_recursive(&file.dir_path(), U_RWX) {
+        debug2!("Couldn't create {}", file.dir_path().to_str());
+        return;
+    }
+    let diags = diags.to_owned();
+    let result = do io::atomic_write(file) |f| {
+        let mut encoder = json::Encoder(f);
+        diags.encode(&mut encoder);
+    };
+    match result {
+        Ok(()) => (),
+        // Only costs the warnings of a later, cached build
+        Err(e) => debug2!("Couldn't record diagnostics in {}: {}", file.to_str(), e)
+    }
+}
+
+/// Prints the diagnostics kept in `file` again, if there are any
+pub fn replay_diagnostics(file: &Path) {
+    if !os::path_exists(file) {
+        return;
+    }
+    let json = match io::file_reader(file) {
+        Ok(r) => json::from_reader(r).map_err_move(|e| e.to_str()),
+        Err(e) => Err(e)
+    };
+    match json {
+        Ok(json) => {
+            let mut decoder = json::Decoder(json);
+            let diags: ~[RecordedDiagnostic] = Decodable::decode(&mut decoder);
+            for d in diags.iter() {
+                d.replay();
+            }
+        }
+        Err(e) => debug2!("Couldn't read diagnostics {}: {}", file.to_str(), e)
+    }
+}
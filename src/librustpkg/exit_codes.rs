@@ -11,4 +11,5 @@
 pub static COPY_FAILED_CODE: int = 65;
 pub static BAD_FLAG_CODE: int    = 67;
 pub static NONEXISTENT_PACKAGE_CODE: int = 68;
+pub static UPDATE_FAILED_CODE: int = 69;
 
@@ -10,19 +10,24 @@
 
 // Listing installed packages
 
-use rustc::metadata::filesearch::rust_path;
+use rustc::metadata::loader::DepInfo;
 use path_util::*;
+use util::library_info;
 use std::os;
 
-pub fn list_installed_packages(f: &fn(&PkgId) -> bool) -> bool  {
-    let workspaces = rust_path();
-    for p in workspaces.iter() {
+pub fn list_installed_packages(rust_path: &[Path], f: &fn(&PkgId) -> bool) -> bool  {
+    for p in rust_path.iter() {
         let binfiles = os::list_dir(&p.push("bin"));
         for exec in binfiles.iter() {
+            // bin/ can hold other files, like the libraries executables
+            // need on Windows. The stem leaves off any .exe.
+            if !is_executable(&p.push("bin").push(*exec)) {
+                continue;
+            }
             let p = Path(*exec);
             let exec_path = p.filestem();
             do exec_path.iter().advance |s| {
-                f(&PkgId::new(*s))
+                f(&PkgId::new_in(*s, rust_path))
             };
         }
         let libfiles = os::list_dir(&p.push("lib"));
@@ -33,11 +38,13 @@ pub fn list_installed_packages(f: &fn(&PkgId) -> bool) -> bool  {
                 Some(basename) => {
                     debug2!("parent = {}, child = {}",
                             p.push("lib").to_str(), lib.to_str());
-                    let rel_p = p.push("lib/").get_relative_to(&lib);
+                    let lib_dir = p.push("lib");
+                    let rel_p = lib_dir.push_rel(&lib).dir_path().relative_from(&lib_dir)
+                                       .expect("library outside of its lib directory");
                     debug2!("Rel: {}", rel_p.to_str());
                     let rel_path = rel_p.push(basename).to_str();
                     debug2!("Rel name: {}", rel_path);
-                    f(&PkgId::new(rel_path));
+                    f(&PkgId::new_in(rel_path, rust_path));
                 }
                 None => ()
             }
@@ -50,7 +57,8 @@ pub fn has_library(p: &Path) -> Option<~str> {
     let files = os::list_dir(p);
     for q in files.iter() {
         let as_path = Path(*q);
-        if as_path.filetype() == Some(os::consts::DLL_SUFFIX) {
+        if as_path.filetype() == Some(os::consts::DLL_SUFFIX) ||
+           as_path.filetype() == Some(STATICLIB_SUFFIX) {
             let stuff : &str = as_path.filestem().expect("has_library: weird path");
             let mut stuff2 = stuff.split_str_iter(&"-");
             let stuff3: ~[&str] = stuff2.collect();
@@ -62,9 +70,9 @@ pub fn has_library(p: &Path) -> Option<~str> {
     None
 }
 
-pub fn package_is_installed(p: &PkgId) -> bool {
+pub fn package_is_installed(rust_path: &[Path], p: &PkgId) -> bool {
     let mut is_installed = false;
-    do list_installed_packages() |installed| {
+    do list_installed_packages(rust_path) |installed| {
         if installed == p {
             is_installed = true;
         }
@@ -72,3 +80,32 @@ pub fn package_is_installed(p: &PkgId) -> bool {
     };
     is_installed
 }
+
+/// Returns the dependencies of the library `lib` whose installed copy in a
+/// workspace of `rust_path` doesn't have the hash `lib` was compiled against, along with the
+/// path of that copy. rustc would either pick the wrong one or refuse to
+/// choose between them.
+pub fn conflicting_dependencies(rust_path: &[Path], lib: &Path) -> ~[(DepInfo, Path)] {
+    let mut conflicts = ~[];
+    let info = match library_info(lib) {
+        Some(info) => info,
+        None => return conflicts
+    };
+    for dep in info.deps.iter() {
+        for ws in rust_path.iter() {
+            let installed = installed_library_in_workspace(&Path(dep.name), ws);
+            for other in installed.iter() {
+                match library_info(other) {
+                    Some(ref other_info) if other_info.name == dep.name
+                                         && other_info.hash != dep.hash => {
+                        debug2!("{} wants {} {} but {} has {}", lib.to_str(), dep.name,
+                                dep.hash, other.to_str(), other_info.hash);
+                        conflicts.push((dep.clone(), other.clone()));
+                    }
+                    _ => ()
+                }
+            }
+        }
+    }
+    conflicts
+}
@@ -24,16 +24,7 @@ pub fn error(msg: &str) {
 }
 
 fn pretty_message<'a>(msg: &'a str, prefix: &'a str, color: term::color::Color, out: @io::Writer) {
-    let term = term::Terminal::new(out);
-    match term {
-        Ok(ref t) => {
-            t.fg(color);
-            out.write_str(prefix);
-            t.reset();
-        },
-        _ => {
-            out.write_str(prefix);
-        }
-    }
+    let out = term::StyledWriter::new(out);
+    out.write_colored(prefix, color);
     out.write_line(msg);
 }
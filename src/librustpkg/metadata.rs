@@ -0,0 +1,173 @@
+// Copyright 2013 The Rust Project Developers. See the COPYRIGHT
+// file at the top-level directory of this distribution and at
+// http://rust-lang.org/COPYRIGHT.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+// Package metadata declared in a crate's source, and the dependencies
+// it asks for
+
+use syntax::ast;
+use syntax::attr;
+use syntax::attr::AttrMetaMethods;
+use syntax::parse;
+use syntax::parse::token;
+use syntax::visit;
+use syntax::visit::Visitor;
+use version::{Version, try_parsing_version};
+
+/// What a package says about itself with a crate attribute like
+/// `#[pkg(name = "foo", vers = "0.3", license = "MIT")];`.
+/// Anything it leaves out is inferred as before, from the package ID
+/// and the version control system. The license doesn't affect the build;
+/// `rustpkg info` reads it back from the installed library.
+#[deriving(Clone)]
+pub struct PkgMetadata {
+    name: Option<~str>,
+    vers: Option<Version>
+}
+
+impl PkgMetadata {
+    pub fn new() -> PkgMetadata {
+        PkgMetadata { name: None, vers: None }
+    }
+}
+
+/// Reads the `pkg` attribute of the crate in `crate_file`. Only the
+/// crate's attributes are parsed, so this is cheap enough to do before
+/// deciding what to build.
+pub fn read_pkg_metadata(crate_file: &Path) -> PkgMetadata {
+    let sess = parse::new_parse_sess(None);
+    let attrs = parse::parse_crate_attrs_from_file(crate_file, ~[], sess);
+    let mut metadata = PkgMetadata::new();
+    for attr in attrs.iter().filter(|a| "pkg" == a.name()) {
+        let items = match attr.meta_item_list() {
+            Some(items) => items,
+            None => {
+                sess.span_diagnostic.span_err(attr.span,
+                                              "expected #[pkg(key = \"value\", ...)]");
+                continue;
+            }
+        };
+        for mi in items.iter() {
+            match mi.name_str_pair() {
+                Some((key, value)) => match key.as_slice() {
+                    "name"    => metadata.name = Some(value.to_owned()),
+                    "vers"    => match try_parsing_version(value) {
+                        Some(v) => metadata.vers = Some(v),
+                        None => sess.span_diagnostic.span_err(mi.span,
+                                    format!("`{}` isn't a version number like 0.3", value))
+                    },
+                    "license" => (),
+                    _         => sess.span_diagnostic.span_err(mi.span,
+                                     format!("unknown pkg key `{}`", key))
+                },
+                None => sess.span_diagnostic.span_err(mi.span,
+                                                      "expected a `key = \"value\"` pair in pkg")
+            }
+        }
+    }
+    sess.span_diagnostic.handler().abort_if_errors();
+    metadata
+}
+
+/// The license declared by the `pkg` attribute among `attrs`, the
+/// attributes of a compiled library
+pub fn declared_license(attrs: &[ast::Attribute]) -> Option<~str> {
+    pkg_value(attrs, "license")
+}
+
+/// The name declared by the `pkg` attribute of the crate in `crate_file`.
+/// Unlike `read_pkg_metadata`, this says nothing about a malformed
+/// attribute, since it's used to look through other packages' crates.
+pub fn declared_name(crate_file: &Path) -> Option<~str> {
+    let sess = parse::new_parse_sess(None);
+    let attrs = parse::parse_crate_attrs_from_file(crate_file, ~[], sess);
+    pkg_value(attrs, "name")
+}
+
+fn pkg_value(attrs: &[ast::Attribute], key: &str) -> Option<~str> {
+    for attr in attrs.iter().filter(|a| "pkg" == a.name()) {
+        for items in attr.meta_item_list().iter() {
+            for mi in items.iter() {
+                match mi.name_str_pair() {
+                    Some((k, value)) if key == k.as_slice() => {
+                        return Some(value.to_owned());
+                    }
+                    _ => ()
+                }
+            }
+        }
+    }
+    None
+}
+
+/// A library that a crate asks for with `extern mod`
+#[deriving(Clone, Eq)]
+pub struct ExternMod {
+    /// Its package ID or name
+    name: ~str,
+    /// Whether every `extern mod` for it is under a `cfg` attribute, on
+    /// itself or on an item around it, so that it may only be needed in
+    /// some configurations
+    conditional: bool
+}
+
+struct ExternModVisitor {
+    extern_mods: ~[ExternMod],
+    // How many of the items around the current one have a `cfg`
+    cfg_depth: uint
+}
+
+impl Visitor<()> for ExternModVisitor {
+    fn visit_item(&mut self, item: @ast::item, env: ()) {
+        let has_cfg = attr::contains_name(item.attrs, "cfg");
+        if has_cfg { self.cfg_depth += 1; }
+        visit::walk_item(self, item, env);
+        if has_cfg { self.cfg_depth -= 1; }
+    }
+
+    fn visit_view_item(&mut self, vi: &ast::view_item, env: ()) {
+        match vi.node {
+            ast::view_item_extern_mod(ident, path_opt, _, _) => {
+                let name = match path_opt {
+                    Some((p, _)) => p,
+                    None => token::ident_to_str(&ident)
+                };
+                let extern_mod = ExternMod {
+                    name: name.to_owned(),
+                    conditional: self.cfg_depth > 0 || attr::contains_name(vi.attrs, "cfg")
+                };
+                add_extern_mod(&mut self.extern_mods, extern_mod);
+            }
+            _ => ()
+        }
+        visit::walk_view_item(self, vi, env)
+    }
+}
+
+/// Adds `extern_mod` to `extern_mods` unless it's there already. A library
+/// is only conditional if every `extern mod` for it is.
+pub fn add_extern_mod(extern_mods: &mut ~[ExternMod], extern_mod: ExternMod) {
+    match extern_mods.mut_iter().find(|m| m.name == extern_mod.name) {
+        Some(m) => m.conditional = m.conditional && extern_mod.conditional,
+        None => extern_mods.push(extern_mod)
+    }
+}
+
+/// The libraries that the `extern mod`s in the crate in `crate_file`, and
+/// in the modules it loads, ask for. The crate is only parsed, so this
+/// doesn't need the libraries to exist. `extern mod`s that are configured
+/// out are included too, marked as conditional.
+pub fn read_extern_mods(crate_file: &Path) -> ~[ExternMod] {
+    let sess = parse::new_parse_sess(None);
+    let crate = parse::parse_crate_from_file(crate_file, ~[], sess);
+    sess.span_diagnostic.handler().abort_if_errors();
+    let mut visitor = ExternModVisitor { extern_mods: ~[], cfg_depth: 0 };
+    visit::walk_crate(&mut visitor, &crate, ());
+    visitor.extern_mods
+}
@@ -0,0 +1,127 @@
+// Copyright 2013 The Rust Project Developers. See the COPYRIGHT
+// file at the top-level directory of this distribution and at
+// http://rust-lang.org/COPYRIGHT.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+// Repositories faked with plain directories, so that the test suite can
+// exercise rustpkg's handling of version control without running git.
+//
+// A mock repository is a directory containing a `.mockvcs` directory. Tagging
+// it copies its working tree to `.mockvcs/tags/<tag>`, and checking out a tag
+// copies the snapshot back. Cloning copies the whole directory, so a clone
+// gets the working tree as it is, committed or not; there are no commits.
+// It's only compiled into the test build, whose in-process runs of rustpkg
+// are the only ones that see these repositories.
+
+use std::os;
+use extra::sort;
+use path_util::{U_RWX, make_dir_rwx_recursive};
+use source_control::VersionControl;
+
+static MOCK_DIR: &'static str = ".mockvcs";
+
+pub struct MockVcs;
+
+impl VersionControl for MockVcs {
+    fn is_repo(&self, p: &Path) -> bool {
+        os::path_is_dir(&p.push(MOCK_DIR))
+    }
+
+    fn clone_repo(&self, source: &str, target: &Path) -> Result<(), ~str> {
+        let source = Path(source);
+        if !self.is_repo(&source) {
+            return Err(format!("{} isn't a mock repository", source.to_str()));
+        }
+        copy_tree(&source, target, true)
+    }
+
+    fn checkout(&self, repo: &Path, rev: &str) -> Result<(), ~str> {
+        let snapshot = tags_dir(repo).push(rev);
+        if !os::path_is_dir(&snapshot) {
+            return Err(format!("no tag {} in {}", rev, repo.to_str()));
+        }
+        match clear_working_tree(repo) {
+            Ok(()) => copy_tree(&snapshot, repo, false),
+            Err(e) => Err(e)
+        }
+    }
+
+    fn pull(&self, source: &Path, target: &Path) -> Result<(), ~str> {
+        if !os::remove_dir_recursive(target) {
+            return Err(format!("couldn't remove {}", target.to_str()));
+        }
+        self.clone_repo(source.to_str(), target)
+    }
+
+    fn tags(&self, repo: &Path) -> Result<~[~str], ~str> {
+        if !self.is_repo(repo) {
+            return Err(format!("{} isn't a mock repository", repo.to_str()));
+        }
+        // In the order git lists them
+        let mut tags = os::list_dir(&tags_dir(repo));
+        sort::quick_sort3(tags);
+        Ok(tags)
+    }
+}
+
+/// Makes the directory `p`, relative to the current directory or absolute,
+/// into an empty mock repository
+pub fn init(p: &Path) -> bool {
+    make_dir_rwx_recursive(&tags_dir(p))
+}
+
+/// Tags the working tree of the mock repository `repo` as `tag`
+pub fn tag(repo: &Path, tag: &str) -> Result<(), ~str> {
+    copy_tree(repo, &tags_dir(repo).push(tag), false)
+}
+
+fn tags_dir(repo: &Path) -> Path {
+    repo.push_many([MOCK_DIR, "tags"])
+}
+
+// Copies the directory `from` into `to`, creating `to` if need be. The
+// repository's own data is only copied with `include_mock_dir`.
+fn copy_tree(from: &Path, to: &Path, include_mock_dir: bool) -> Result<(), ~str> {
+    fn not_mock_dir(p: &Path) -> bool { *p != Path(MOCK_DIR) }
+
+    if !os::path_is_dir(to) && !os::mkdir_recursive(to, U_RWX) {
+        return Err(format!("couldn't create {}", to.to_str()));
+    }
+    // Copies get new times, as a checkout's files would
+    let options = os::CopyOptions {
+        preserve_times: false,
+        overwrite: true,
+        filter: if include_mock_dir { None } else { Some(not_mock_dir) }
+    };
+    match os::copy_dir_recursive(from, to, options) {
+        Ok(()) => Ok(()),
+        Err(errors) => {
+            let (ref p, ref e) = errors[0];
+            Err(format!("couldn't copy {} to {}: {}", p.to_str(), to.to_str(), *e))
+        }
+    }
+}
+
+// Removes everything in the repository `repo` but the repository's own data
+fn clear_working_tree(repo: &Path) -> Result<(), ~str> {
+    for name in os::list_dir(repo).iter() {
+        if MOCK_DIR == *name {
+            continue;
+        }
+        let p = repo.push(*name);
+        let removed = if os::path_is_dir(&p) {
+            os::remove_dir_recursive(&p)
+        } else {
+            os::remove_file(&p)
+        };
+        if !removed {
+            return Err(format!("couldn't remove {}", p.to_str()));
+        }
+    }
+    Ok(())
+}
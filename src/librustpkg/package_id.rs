@@ -8,8 +8,11 @@
 // option. This file may not be copied, modified, or distributed
 // except according to those terms.
 
-use version::{try_getting_version, try_getting_local_version,
-              Version, NoVersion, split_version};
+use version::{try_getting_version, try_getting_version_from_url, try_getting_local_version,
+              Version, ExactRevision, NoVersion, split_version};
+use path_util::rust_path;
+use extra::url;
+use extra::url::Url;
 use std::rt::io::Writer;
 use std::hash::Streaming;
 use std::hash;
@@ -33,7 +36,10 @@ pub struct PkgId {
     /// of package IDs whose short names aren't valid Rust identifiers.
     short_name: ~str,
     /// The requested package version.
-    version: Version
+    version: Version,
+    /// Where to fetch the package from, if the ID was given as a URL such
+    /// as ssh://git@example.com/foo/bar. See `remote_url`.
+    remote: Option<Url>
 }
 
 impl Eq for PkgId {
@@ -44,8 +50,21 @@ impl Eq for PkgId {
 
 impl PkgId {
     pub fn new(s: &str) -> PkgId {
+        PkgId::new_in(s, rust_path())
+    }
+
+    /// As `new`, but looks for local checkouts of the package (to learn its
+    /// version) in the workspaces of `rust_path`.
+    pub fn new_in(s: &str, rust_path: &[Path]) -> PkgId {
         use conditions::bad_pkg_id::cond;
 
+        if s.contains("://") {
+            return match url::from_str(s) {
+                Ok(u) => PkgId::from_url(u, rust_path),
+                Err(e) => cond.raise((Path(s), format!("invalid URL: {}", e)))
+            };
+        }
+
         let mut given_version = None;
 
         // Did the user request a specific version?
@@ -70,7 +89,7 @@ impl PkgId {
 
         let version = match given_version {
             Some(v) => v,
-            None => match try_getting_local_version(&path) {
+            None => match try_getting_local_version(&path, rust_path) {
                 Some(v) => v,
                 None => match try_getting_version(&path) {
                     Some(v) => v,
@@ -82,7 +101,61 @@ impl PkgId {
         PkgId {
             path: path.clone(),
             short_name: short_name.to_owned(),
-            version: version
+            version: version,
+            remote: None
+        }
+    }
+
+    // A package given by URL is known locally by its host and path (minus
+    // any .git suffix), and its fragment, like the part after '#' in a path
+    // ID, names the version.
+    fn from_url(u: Url, rust_path: &[Path]) -> PkgId {
+        use conditions::bad_pkg_id::cond;
+
+        let mut u = u;
+        let local = format!("{}{}", u.host, u.path);
+        let local = if local.ends_with(".git") {
+            local.slice_to(local.len() - 4).to_owned()
+        } else {
+            local
+        };
+        let path = Path(local);
+        if u.host.is_empty() || path.components.len() < 2 {
+            return cond.raise((path, ~"URL pkgid must have a host and a path"));
+        }
+        let short_name = path.filestem().expect(format!("Strange path! {}", u.to_str()));
+
+        let version = match u.fragment.take() {
+            // n.b. for now, assuming an exact revision is intended, not a SemVer
+            Some(v) => ExactRevision(v),
+            None => match try_getting_local_version(&path, rust_path) {
+                Some(v) => v,
+                None => match try_getting_version_from_url(u.to_str()) {
+                    Some(v) => v,
+                    None => NoVersion
+                }
+            }
+        };
+
+        PkgId {
+            path: path.clone(),
+            short_name: short_name.to_owned(),
+            version: version,
+            remote: Some(u)
+        }
+    }
+
+    /// The URL this package's sources can be fetched from: the one it was
+    /// given as, or otherwise https:// followed by its path.
+    pub fn remote_url(&self) -> Url {
+        match self.remote {
+            Some(ref u) => u.clone(),
+            None => {
+                let components = self.path.components();
+                Url::new(~"https", None, components[0].clone(), None,
+                         format!("/{}", components.slice_from(1).connect("/")),
+                         ~[], None)
+            }
         }
     }
 
@@ -96,6 +169,25 @@ impl PkgId {
         format!("{}{}", self.short_name, self.version.to_str())
     }
 
+    /// The same package, but with its library named `alias` rather than
+    /// after the last component of its path. For example,
+    /// `github.com/foo/rust-http` aliased as `http` builds a library that's
+    /// linked with `extern mod http = "github.com/foo/rust-http";`.
+    pub fn with_alias(&self, alias: &str) -> PkgId {
+        PkgId { short_name: alias.to_owned(), .. self.clone() }
+    }
+
+    /// The last component of the ID's path. It names the package's source
+    /// directory, whatever the package's library is aliased as.
+    pub fn path_name<'a>(&'a self) -> &'a str {
+        self.path.filestem().expect(format!("Strange path! {}", self.path.to_str()))
+    }
+
+    /// True if the ID's short name isn't the one its path gives it
+    pub fn has_alias(&self) -> bool {
+        self.short_name.as_slice() != self.path_name()
+    }
+
     /// True if the ID has multiple components
     pub fn is_complex(&self) -> bool {
         self.short_name != self.path.to_str()
@@ -158,3 +250,26 @@ pub fn hash(data: ~str) -> ~str {
     hasher.result_str()
 }
 
+#[test]
+fn test_pkgid_alias() {
+    let id = PkgId::new("github.com/foo/rust-http");
+    assert!(!id.has_alias());
+    let aliased = id.with_alias("http");
+    assert_eq!(aliased.short_name, ~"http");
+    assert!(aliased.has_alias());
+    // Still the same package
+    assert!(aliased == id);
+}
+
+#[test]
+fn test_pkgid_from_url() {
+    let id = PkgId::new("ssh://git@example.com:2222/foo/bar.git#0.2");
+    assert_eq!(id.path, Path("example.com/foo/bar"));
+    assert_eq!(id.short_name, ~"bar");
+    assert!(id.version == ExactRevision(~"0.2"));
+    assert_eq!(id.remote_url().to_str(), ~"ssh://git@example.com:2222/foo/bar.git");
+
+    let id = PkgId::new("example.com/foo/bar#0.2");
+    assert!(id.remote.is_none());
+    assert_eq!(id.remote_url().to_str(), ~"https://example.com/foo/bar");
+}
@@ -16,16 +16,44 @@ use std::path::Path;
 use std::os;
 use context::*;
 use crate::Crate;
+use diagnostics::{diagnostics_file, replay_diagnostics};
 use messages::*;
-use source_control::{safe_git_clone, git_clone_url, DirToUse, CheckedOutSources};
+use metadata::{ExternMod, add_extern_mod, read_pkg_metadata, read_extern_mods};
+use version::NoVersion;
+use source_control::{safe_git_clone, git_clone_url, git_url, DirToUse, CheckedOutSources};
 use source_control::make_read_only;
-use path_util::{find_dir_using_rust_path_hack, make_dir_rwx_recursive};
-use path_util::{target_build_dir, versionize};
-use util::compile_crate;
+use path_util::{find_dir_using_rust_path_hack, make_dir_rwx_recursive, rust_path};
+use path_util::{built_library_in_workspace, target_build_dir, versionize};
+use util::{compile_crate, compile_input, Sibling};
 use workcache_support;
 use workcache_support::crate_tag;
 use extra::workcache;
 
+// Declares what a crate's unit of work depends on besides its discovered
+// inputs
+fn declare_crate_inputs(ctx: &BuildContext, prep: &mut workcache::Prep, path: &Path,
+                        cfgs: &[~str]) {
+    debug2!("Building crate {}, declaring it as an input", path.to_str());
+    prep.declare_input("file", path.to_str(),
+                       workcache_support::digest_file_with_date(path));
+    // Changing any of these calls for a rebuild too
+    prep.declare_env("RUST_PATH");
+    prep.declare_value("cfgs", cfgs.connect(" "));
+    prep.declare_value("rustc-flags", ctx.flag_strs().connect(" "));
+    prep.declare_value("sysroot", ctx.sysroot_to_use().to_str());
+    // So does a different compiler: the one linked into rustpkg, and the
+    // sysroot's rustc, which is replaced along with the libraries that
+    // crates are compiled against
+    prep.declare_value("compiler", format!("{} {}",
+                                           option_env!("CFG_VERSION").unwrap_or("unknown"),
+                                           option_env!("CFG_VER_HASH").unwrap_or("")));
+    let rustc = ctx.sysroot_to_use().push_many([~"bin", "rustc" + os::EXE_SUFFIX]);
+    if os::path_exists(&rustc) {
+        prep.declare_input("binary", rustc.to_str(),
+                           workcache_support::digest_only_date(&rustc));
+    }
+}
+
 // An enumeration of the unpacked source of a package workspace.
 // This contains a list of files found in the source workspace.
 #[deriving(Clone)]
@@ -70,18 +98,45 @@ condition! {
 
 impl PkgSrc {
 
-    pub fn new(mut source_workspace: Path,
+    pub fn new(source_workspace: Path,
                destination_workspace: Path,
                use_rust_path_hack: bool,
                id: PkgId) -> PkgSrc {
+        PkgSrc::new_with(source_workspace, destination_workspace, use_rust_path_hack, false,
+                         &os::getcwd(), rust_path(), id)
+    }
+
+    /// As `new`, but for the run of rustpkg that `cx` describes: relative
+    /// paths are taken from its working directory, and the RUST_PATH is
+    /// its. If `offline` is true, a package whose sources aren't in a
+    /// workspace already (including sources fetched earlier) is reported as
+    /// nonexistent rather than fetched.
+    pub fn new_in(cx: &Context,
+                  source_workspace: Path,
+                  destination_workspace: Path,
+                  use_rust_path_hack: bool,
+                  offline: bool,
+                  id: PkgId) -> PkgSrc {
+        PkgSrc::new_with(source_workspace, destination_workspace, use_rust_path_hack, offline,
+                         &cx.cwd, cx.rust_path, id)
+    }
+
+    fn new_with(mut source_workspace: Path,
+                destination_workspace: Path,
+                use_rust_path_hack: bool,
+                offline: bool,
+                cwd: &Path,
+                rust_path: &[Path],
+                id: PkgId) -> PkgSrc {
         use conditions::nonexistent_package::cond;
 
         debug2!("Checking package source for package ID {}, \
-                workspace = {} -> {}, use_rust_path_hack = {:?}",
+                workspace = {} -> {}, use_rust_path_hack = {:?}, offline = {:?}",
                 id.to_str(),
                 source_workspace.to_str(),
                 destination_workspace.to_str(),
-                use_rust_path_hack);
+                use_rust_path_hack,
+                offline);
 
         let mut destination_workspace = destination_workspace.clone();
 
@@ -95,12 +150,12 @@ impl PkgSrc {
             // We search for sources under both src/ and build/ , because build/ is where
             // automatically-checked-out sources go.
             let result = source_workspace.push("src").push_rel(&id.path.pop()).push(format!("{}-{}",
-                                                         id.short_name, id.version.to_str()));
+                                                         id.path_name(), id.version.to_str()));
             to_try.push(result);
             to_try.push(source_workspace.push("src").push_rel(&id.path));
 
             let result = build_dir.push("src").push_rel(&id.path.pop()).push(format!("{}-{}",
-                                                         id.short_name, id.version.to_str()));
+                                                         id.path_name(), id.version.to_str()));
             to_try.push(result.clone());
             output_names.push(result);
             let other_result = build_dir.push("src").push_rel(&id.path);
@@ -123,14 +178,17 @@ impl PkgSrc {
                 // See if any of the prefixes of this package ID form a valid package ID
                 // That is, is this a package ID that points into the middle of a workspace?
                 for (prefix, suffix) in id.prefixes_iter() {
-                    let package_id = PkgId::new(prefix.to_str());
+                    let package_id = PkgId::new_in(prefix.to_str(), rust_path);
                     let path = build_dir.push_rel(&package_id.path);
                     debug2!("in loop: checking if {} is a directory", path.to_str());
                     if os::path_is_dir(&path) {
-                        let ps = PkgSrc::new(source_workspace,
-                                             destination_workspace,
-                                             use_rust_path_hack,
-                                             PkgId::new(prefix.to_str()));
+                        let ps = PkgSrc::new_with(source_workspace,
+                                                  destination_workspace,
+                                                  use_rust_path_hack,
+                                                  offline,
+                                                  cwd,
+                                                  rust_path,
+                                                  package_id);
                         match ps {
                             PkgSrc {
                                 source_workspace: source,
@@ -158,9 +216,14 @@ impl PkgSrc {
 
                 // Ok, no prefixes work, so try fetching from git
                 let mut ok_d = None;
+                if offline {
+                    // Leave nowhere to fetch the sources into
+                    debug2!("Not fetching {}: offline", id.to_str());
+                    output_names = ~[];
+                }
                 for w in output_names.iter() {
                     debug2!("Calling fetch_git on {}", w.to_str());
-                    let target_dir_opt = PkgSrc::fetch_git(w, &id);
+                    let target_dir_opt = PkgSrc::fetch_git(cwd, w, &id);
                     for p in target_dir_opt.iter() {
                         ok_d = Some(p.clone());
                         build_in_destination = true;
@@ -190,7 +253,7 @@ impl PkgSrc {
                     Some(d) => d,
                     None => {
                         if use_rust_path_hack {
-                            match find_dir_using_rust_path_hack(&id) {
+                            match find_dir_using_rust_path_hack(rust_path, &id) {
                                 Some(d) => d,
                                 None => {
                                     cond.raise((id.clone(),
@@ -199,6 +262,12 @@ impl PkgSrc {
                                 }
                             }
                         }
+                        else if offline {
+                            cond.raise((id.clone(),
+                                ~"sources for the package aren't in any workspace, \
+                                and it can't be fetched while offline; \
+                                run `rustpkg fetch` for it first"))
+                        }
                         else {
                             cond.raise((id.clone(),
                                 ~"supplied path for package dir does not \
@@ -235,16 +304,17 @@ impl PkgSrc {
     /// fetching it and caching it in a local directory. Return the cached directory
     /// if this was successful, None otherwise. Similarly, if the package id
     /// refers to a git repo on the local version, also check it out.
-    /// (right now we only support git)
-    pub fn fetch_git(local: &Path, pkgid: &PkgId) -> Option<Path> {
+    /// (right now we only support git) A local repo is looked for relative
+    /// to `cwd`.
+    pub fn fetch_git(cwd: &Path, local: &Path, pkgid: &PkgId) -> Option<Path> {
         use conditions::git_checkout_failed::cond;
 
-        debug2!("Checking whether {} (path = {}) exists locally. Cwd = {}, does it? {:?}",
-                pkgid.to_str(), pkgid.path.to_str(),
-                os::getcwd().to_str(),
-                os::path_exists(&pkgid.path));
+        let local_repo = cwd.push_rel(&pkgid.path);
+        debug2!("Checking whether {} (path = {}) exists locally. Does it? {:?}",
+                pkgid.to_str(), local_repo.to_str(),
+                os::path_exists(&local_repo));
 
-        match safe_git_clone(&pkgid.path, &pkgid.version, local) {
+        match safe_git_clone(&local_repo, &pkgid.version, local) {
             CheckedOutSources => {
                 make_read_only(local);
                 Some(local.clone())
@@ -255,7 +325,7 @@ impl PkgSrc {
                     return None;
                 }
 
-                let url = format!("https://{}", pkgid.path.to_str());
+                let url = git_url(&pkgid.remote_url());
                 debug2!("Fetching package: git clone {} {} [version={}]",
                         url, clone_target.to_str(), pkgid.version.to_str());
 
@@ -273,10 +343,26 @@ impl PkgSrc {
 
                 // Move clone_target to local.
                 // First, create all ancestor directories.
-                let moved = make_dir_rwx_recursive(&local.pop())
-                    && os::rename_file(&clone_target, local);
-                if moved { Some(local.clone()) }
-                    else { None }
+                if !make_dir_rwx_recursive(&local.pop()) {
+                    return None;
+                }
+                if os::rename_file(&clone_target, local) {
+                    return Some(local.clone());
+                }
+                // The temporary directory may be on another filesystem,
+                // in which case the clone has to be copied instead.
+                match os::copy_dir_recursive(&clone_target, local, os::CopyOptions::new()) {
+                    Ok(()) => {
+                        os::remove_dir_recursive(&clone_target);
+                        Some(local.clone())
+                    }
+                    Err(errors) => {
+                        for &(ref path, ref msg) in errors.iter() {
+                            debug2!("Couldn't copy {}: {}", path.to_str(), *msg);
+                        }
+                        None
+                    }
+                }
             }
         }
     }
@@ -296,7 +382,7 @@ impl PkgSrc {
 
     /// True if the given path's stem is self's pkg ID's stem
     fn stem_matches(&self, p: &Path) -> bool {
-        p.filestem().map_default(false, |p| { p == self.id.short_name.as_slice() })
+        p.filestem().map_default(false, |p| { p == self.id.path_name() })
     }
 
     pub fn push_crate(cs: &mut ~[Crate], prefix: uint, p: &Path) {
@@ -319,8 +405,20 @@ impl PkgSrc {
         use conditions::missing_pkg_files::cond;
 
         let prefix = self.start_dir.components.len();
-        debug2!("Matching against {}", self.id.short_name);
-        do os::walk_dir(&self.start_dir) |pth| {
+        debug2!("Matching against {}", self.id.path_name());
+        let mut walker = os::walk_dir(&self.start_dir);
+        loop {
+            let entry = match walker.next() {
+                Some(entry) => entry,
+                None => break
+            };
+            // Version control metadata never holds crates
+            if entry.kind == os::Directory && entry.path.filename().map_default(false,
+                    |name| name.starts_with(".")) {
+                walker.skip_subtree();
+                continue;
+            }
+            let pth = &entry.path;
             let maybe_known_crate_set = match pth.filename() {
                 Some(filename) if filter(filename) => match filename {
                     "lib.rs" => Some(&mut self.libs),
@@ -336,8 +434,7 @@ impl PkgSrc {
                 Some(crate_set) => PkgSrc::push_crate(crate_set, prefix, pth),
                 None => ()
             }
-            true
-        };
+        }
 
         let crate_sets = [&self.libs, &self.mains, &self.tests, &self.benchs];
         if crate_sets.iter().all(|crate_set| crate_set.is_empty()) {
@@ -356,6 +453,74 @@ impl PkgSrc {
                self.benchs.len())
     }
 
+    /// Takes the package's name and version from a `#[pkg(...)]` attribute
+    /// in its library crate (or, failing that, its main crate) when it
+    /// declares them, instead of the ones inferred from the package ID.
+    /// An alias the package was asked for by still beats a declared name.
+    /// When only the tests are being built, the library and main crates
+    /// haven't been looked for, so they're looked for at the top of the
+    /// package's directory.
+    pub fn read_declared_metadata(&mut self) {
+        let crate_file = match self.libs.iter().chain(self.mains.iter()).next() {
+            Some(crate) => self.start_dir.push_rel(&crate.file),
+            None => match [~"lib.rs", ~"main.rs"].iter()
+                              .map(|f| self.start_dir.push(*f))
+                              .find(|f| os::path_exists(f)) {
+                Some(f) => f,
+                None => return
+            }
+        };
+        let metadata = read_pkg_metadata(&crate_file);
+        for name in metadata.name.iter() {
+            debug2!("{} declares the name {}", self.id.to_str(), *name);
+            if self.id.has_alias() {
+                debug2!("... but it's aliased as {}", self.id.short_name);
+            } else {
+                self.id.short_name = name.clone();
+            }
+        }
+        for vers in metadata.vers.iter() {
+            match self.id.version {
+                NoVersion => (),
+                ref inferred if *inferred != *vers => {
+                    warn(format!("{} declares version {}, which overrides {}",
+                                 self.id.short_name, vers.to_str(), inferred.to_str()));
+                }
+                _ => ()
+            }
+            self.id.version = vers.clone();
+        }
+    }
+
+    /// The libraries that the package's crates, and its package script,
+    /// ask for with `extern mod`. The crates are only parsed, so this
+    /// works before any of those libraries have been fetched.
+    pub fn extern_mods(&mut self) -> ~[ExternMod] {
+        use conditions::missing_pkg_files::cond;
+
+        // A package with no crates of its own may still have a script
+        do cond.trap(|_| ()).inside {
+            self.find_crates();
+        }
+        let mut files: ~[Path] = self.libs.iter()
+            .chain(self.mains.iter())
+            .chain(self.tests.iter())
+            .chain(self.benchs.iter())
+            .map(|c| self.start_dir.push_rel(&c.file))
+            .collect();
+        for script in self.package_script_option().iter() {
+            files.push(script.clone());
+        }
+        let mut extern_mods = ~[];
+        for file in files.iter() {
+            debug2!("Reading the extern mods in {}", file.to_str());
+            for extern_mod in read_extern_mods(file).move_iter() {
+                add_extern_mod(&mut extern_mods, extern_mod);
+            }
+        }
+        extern_mods
+    }
+
     fn build_crates(&self,
                     ctx: &BuildContext,
                     crates: &[Crate],
@@ -367,10 +532,11 @@ impl PkgSrc {
             let path_str = path.to_str();
             let cfgs = crate.cfgs + cfgs;
 
+            // If the work doesn't miss the cache the crate isn't compiled,
+            // and its warnings have to come from the last time it was
+            let misses = ctx.workcache_context.stats().misses;
             do ctx.workcache_context.with_prep(crate_tag(&path)) |prep| {
-                debug2!("Building crate {}, declaring it as an input", path.to_str());
-                prep.declare_input("file", path.to_str(),
-                                   workcache_support::digest_file_with_date(&path));
+                declare_crate_inputs(ctx, prep, &path, cfgs);
                 let subpath = path.clone();
                 let subcfgs = cfgs.clone();
                 let subpath_str = path_str.clone();
@@ -392,6 +558,70 @@ impl PkgSrc {
                     result
                 }
             };
+            if ctx.workcache_context.stats().misses == misses {
+                replay_diagnostics(&diagnostics_file(self.build_workspace(), &self.id, &path));
+            }
+        }
+    }
+
+    /// Builds the package's library and then its binaries, so that the
+    /// binaries can be compiled in sessions derived from the library's and
+    /// link against the library just built. Each crate is still a unit of
+    /// work of its own, the same one `build_crates` would use, but the work
+    /// is done in this task, since a session can't be sent to another.
+    fn build_lib_and_mains(&self,
+                           ctx: &BuildContext,
+                           lib: &Crate,
+                           mains: &[Crate],
+                           cfgs: &[~str]) {
+        let lib_path = self.start_dir.push_rel(&lib.file).normalize();
+        let lib_cfgs = lib.cfgs + cfgs;
+        debug2!("build_lib_and_mains: compiling {} and {} mains",
+                lib_path.to_str(), mains.len());
+
+        // Only set if the library misses the cache and is compiled here
+        let mut compiled = None;
+        let misses = ctx.workcache_context.stats().misses;
+        do ctx.workcache_context.with_prep(crate_tag(&lib_path)) |prep| {
+            declare_crate_inputs(ctx, prep, &lib_path, lib_cfgs);
+            do prep.exec_here |exec| {
+                let (result, sess, diags) = compile_input(ctx, exec, &self.id, &lib_path,
+                                                          self.build_workspace(), lib.flags,
+                                                          lib_cfgs, false, Lib, None);
+                let result_str = result.to_str();
+                compiled = Some((result, sess, diags));
+                result_str
+            }
+        };
+        if ctx.workcache_context.stats().misses == misses {
+            replay_diagnostics(&diagnostics_file(self.build_workspace(), &self.id, &lib_path));
+        }
+
+        let sibling = match compiled {
+            Some((Some(lib), sess, diags)) => Some(Sibling { lib: lib, sess: Some((sess, diags)) }),
+            Some((None, _, _)) => None,
+            None => do built_library_in_workspace(&self.id, self.build_workspace()).map |lib| {
+                Sibling { lib: lib, sess: None }
+            }
+        };
+
+        for main in mains.iter() {
+            let path = self.start_dir.push_rel(&main.file).normalize();
+            let cfgs = main.cfgs + cfgs;
+            debug2!("build_lib_and_mains: compiling {}", path.to_str());
+            let misses = ctx.workcache_context.stats().misses;
+            do ctx.workcache_context.with_prep(crate_tag(&path)) |prep| {
+                declare_crate_inputs(ctx, prep, &path, cfgs);
+                do prep.exec_here |exec| {
+                    let (result, _, _) = compile_input(ctx, exec, &self.id, &path,
+                                                       self.build_workspace(), main.flags,
+                                                       cfgs, false, Main, sibling.as_ref());
+                    result.to_str()
+                }
+            };
+            if ctx.workcache_context.stats().misses == misses {
+                replay_diagnostics(&diagnostics_file(self.build_workspace(), &self.id, &path));
+            }
         }
     }
 
@@ -423,9 +653,16 @@ impl PkgSrc {
         let benchs = self.benchs.clone();
         debug2!("Building libs in {}, destination = {}",
                self.source_workspace.to_str(), self.build_workspace().to_str());
-        self.build_crates(build_context, libs, cfgs, Lib);
-        debug2!("Building mains");
-        self.build_crates(build_context, mains, cfgs, Main);
+        if libs.len() == 1 && !mains.is_empty() {
+            // The binaries almost certainly link against the library, so
+            // build them together
+            self.build_lib_and_mains(build_context, &libs[0], mains, cfgs);
+        }
+        else {
+            self.build_crates(build_context, libs, cfgs, Lib);
+            debug2!("Building mains");
+            self.build_crates(build_context, mains, cfgs, Main);
+        }
         debug2!("Building tests");
         self.build_crates(build_context, tests, cfgs, Test);
         debug2!("Building benches");
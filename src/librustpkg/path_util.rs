@@ -14,6 +14,7 @@ pub use package_id::PkgId;
 pub use target::{OutputType, Main, Lib, Test, Bench, Target, Build, Install};
 pub use version::{Version, NoVersion, split_version_general, try_parsing_version};
 pub use rustc::metadata::filesearch::rust_path;
+use rustc::metadata::filesearch::rust_path_lib_dir;
 use rustc::driver::driver::host_triple;
 
 use std::libc;
@@ -21,23 +22,21 @@ use std::libc::consts::os::posix88::{S_IRUSR, S_IWUSR, S_IXUSR};
 use std::os::mkdir_recursive;
 use std::os;
 use messages::*;
+use receipt::read_receipt;
 
-pub fn default_workspace() -> Path {
-    let p = rust_path();
-    if p.is_empty() {
+/// The first workspace in `rust_path`, which is created if it doesn't
+/// exist yet
+pub fn default_workspace(rust_path: &[Path]) -> Path {
+    if rust_path.is_empty() {
         fail2!("Empty RUST_PATH");
     }
-    let result = p[0];
+    let result = rust_path[0].clone();
     if !os::path_is_dir(&result) {
         os::mkdir_recursive(&result, U_RWX);
     }
     result
 }
 
-pub fn in_rust_path(p: &Path) -> bool {
-    rust_path().contains(p)
-}
-
 pub static U_RWX: i32 = (S_IRUSR | S_IWUSR | S_IXUSR) as i32;
 
 /// Creates a directory that is readable, writeable,
@@ -66,7 +65,8 @@ pub fn workspace_contains_package_id_(pkgid: &PkgId, workspace: &Path,
     let src_dir = workspace_to_src_dir(workspace);
 
     let mut found = None;
-    do os::walk_dir(&src_dir) |p| {
+    for entry in os::walk_dir(&src_dir) {
+        let p = &entry.path;
         if os::path_is_dir(p) {
             if *p == src_dir.push_rel(&pkgid.path) || {
                 let pf = p.filename();
@@ -75,7 +75,7 @@ pub fn workspace_contains_package_id_(pkgid: &PkgId, workspace: &Path,
                     match split_version_general(g, '-') {
                         None => false,
                         Some((ref might_match, ref vers)) => {
-                            *might_match == pkgid.short_name
+                            might_match.as_slice() == pkgid.path_name()
                                 && (pkgid.version == *vers || pkgid.version == NoVersion)
                         }
                     }
@@ -84,9 +84,8 @@ pub fn workspace_contains_package_id_(pkgid: &PkgId, workspace: &Path,
                 found = Some(p.clone());
             }
 
-        };
-        true
-    };
+        }
+    }
 
     if found.is_some() {
         debug2!("Found {} in {}", pkgid.to_str(), workspace.to_str());
@@ -103,9 +102,10 @@ pub fn target_build_dir(workspace: &Path) -> Path {
 }
 
 /// Return the target-specific lib subdirectory, pushed onto `base`;
-/// doesn't check that it exists or create it
+/// doesn't check that it exists or create it. This is where rustc's
+/// filesearch looks for libraries in the RUST_PATH.
 fn target_lib_dir(workspace: &Path) -> Path {
-    workspace.push("lib").push(host_triple())
+    rust_path_lib_dir(workspace, host_triple())
 }
 
 /// Return the bin subdirectory, pushed onto `base`;
@@ -115,6 +115,51 @@ fn target_bin_dir(workspace: &Path) -> Path {
     workspace.push("bin")
 }
 
+/// Return the directory that <pkgid>'s generated documentation goes in,
+/// in <workspace>'s build directory;
+/// doesn't check that it exists or create it
+pub fn built_doc_dir(pkgid: &PkgId, workspace: &Path) -> Path {
+    target_build_dir(workspace).push_rel(&pkgid.path).push("doc")
+}
+
+/// Return the directory that <pkgid>'s documentation is installed in,
+/// under <workspace>'s doc subdirectory;
+/// doesn't check that it exists or create it
+pub fn target_doc_dir(pkgid: &PkgId, workspace: &Path) -> Path {
+    workspace.push("doc").push_rel(&pkgid.path)
+}
+
+/// Removes the directory <pkgid>'s documentation was installed in, once
+/// its files are gone, along with the directories above it, up to <workspace>'s
+/// doc subdirectory, that it leaves empty
+pub fn remove_doc_dir(pkgid: &PkgId, workspace: &Path) {
+    let doc_root = workspace.push("doc");
+    let mut dir = target_doc_dir(pkgid, workspace);
+    if !remove_empty_dirs(&dir) {
+        return;
+    }
+    dir = dir.pop();
+    while dir != doc_root && os::list_dir(&dir).is_empty() && os::remove_dir(&dir) {
+        dir = dir.pop();
+    }
+}
+
+// Removes `dir` and the directories under it, as long as there are no
+// files in any of them. Returns whether `dir` is gone.
+fn remove_empty_dirs(dir: &Path) -> bool {
+    if !os::path_is_dir(dir) {
+        return !os::path_exists(dir);
+    }
+    let mut empty = true;
+    for name in os::list_dir(dir).iter() {
+        let p = dir.push(*name);
+        if !(os::path_is_dir(&p) && remove_empty_dirs(&p)) {
+            empty = false;
+        }
+    }
+    empty && os::remove_dir(dir)
+}
+
 /// Figure out what the executable name for <pkgid> in <workspace>'s build
 /// directory is, and if the file exists, return it.
 pub fn built_executable_in_workspace(pkgid: &PkgId, workspace: &Path) -> Option<Path> {
@@ -158,6 +203,24 @@ fn output_in_workspace(pkgid: &PkgId, workspace: &Path, what: OutputType) -> Opt
     }
 }
 
+/// Returns the object, assembly or bitcode file that rustc wrote for <pkgid>
+/// in <workspace>'s build directory when it was told not to link, if the
+/// file exists. rustc names it after the crate, with `extension` saying
+/// which kind of file it is ("o", "s", "ll" or "bc").
+pub fn built_unlinked_output_in_workspace(pkgid: &PkgId, workspace: &Path,
+                                          extension: &str) -> Option<Path> {
+    let result = target_build_dir(workspace).push_rel(&pkgid.path)
+        .push(format!("{}.{}", pkgid.short_name, extension));
+    debug2!("built_unlinked_output_in_workspace: checking whether {} exists",
+           result.to_str());
+    if os::path_exists(&result) {
+        Some(result)
+    }
+    else {
+        None
+    }
+}
+
 /// Figure out what the library name for <pkgid> in <workspace>'s build
 /// directory is, and if the file exists, return it.
 pub fn built_library_in_workspace(pkgid: &PkgId, workspace: &Path) -> Option<Path> {
@@ -175,9 +238,26 @@ pub fn installed_library_in_workspace(pkg_path: &Path, workspace: &Path) -> Opti
                                                  workspace,
                                                  "lib",
                                                  &NoVersion)
+            .or_else(|| aliased_library_in_workspace(pkg_path, workspace))
     }
 }
 
+// A library installed under an alias isn't named after its package's path,
+// but the package's install receipt still lists it
+fn aliased_library_in_workspace(pkg_path: &Path, workspace: &Path) -> Option<Path> {
+    let receipt = match read_receipt(workspace, pkg_path) {
+        Ok(Some(receipt)) => receipt,
+        _ => return None
+    };
+    let lib_dir = os::make_absolute(&target_lib_dir(workspace)).normalize();
+    receipt.files.iter().map(|f| Path(f.path)).find(|p| {
+        p.dir_path().normalize() == lib_dir && os::path_exists(p) &&
+            p.filename().map_default(false, |f| f.starts_with(os::consts::DLL_PREFIX)) &&
+            p.filetype().map_default(false, |t| t == os::consts::DLL_SUFFIX ||
+                                                t == STATICLIB_SUFFIX)
+    })
+}
+
 /// `workspace` is used to figure out the directory to search.
 /// `short_name` is taken as the link name of the library.
 pub fn library_in_workspace(path: &Path, short_name: &str, where: Target,
@@ -204,13 +284,25 @@ pub fn system_library(sysroot: &Path, lib_name: &str) -> Option<Path> {
     library_in(lib_name, &NoVersion, &sysroot.push("lib"))
 }
 
+/// rustc names a static library like the dynamic library it would build for
+/// the same crate, but with this suffix
+pub static STATICLIB_SUFFIX: &'static str = ".a";
+
+/// Finds the library named `short_name` in `dir_to_search`, preferring a
+/// dynamic library (which rustc can link other crates against) to a static one
 fn library_in(short_name: &str, version: &Version, dir_to_search: &Path) -> Option<Path> {
+    library_with_suffix_in(short_name, version, dir_to_search, os::consts::DLL_SUFFIX)
+        .or_else(|| library_with_suffix_in(short_name, version, dir_to_search,
+                                           STATICLIB_SUFFIX))
+}
+
+fn library_with_suffix_in(short_name: &str, version: &Version, dir_to_search: &Path,
+                          lib_filetype: &str) -> Option<Path> {
     debug2!("Listing directory {}", dir_to_search.to_str());
     let dir_contents = os::list_dir(dir_to_search);
     debug2!("dir has {:?} entries", dir_contents.len());
 
     let lib_prefix = format!("{}{}", os::consts::DLL_PREFIX, short_name);
-    let lib_filetype = os::consts::DLL_SUFFIX;
 
     debug2!("lib_prefix = {} and lib_filetype = {}", lib_prefix, lib_filetype);
 
@@ -402,9 +494,10 @@ pub fn uninstall_package_from(workspace: &Path, pkgid: &PkgId) {
         os::remove_file(&installed_bin);
         did_something = true;
     }
-    let installed_lib = target_library_in_workspace(pkgid, workspace);
-    if os::path_exists(&installed_lib) {
-        os::remove_file(&installed_lib);
+    // Installed libraries have the crate's hash in their names, so they
+    // have to be looked for rather than named
+    for installed_lib in installed_library_in_workspace(&pkgid.path, workspace).iter() {
+        os::remove_file(installed_lib);
         did_something = true;
     }
     if !did_something {
@@ -419,9 +512,8 @@ fn dir_has_file(dir: &Path, file: &str) -> bool {
     os::path_exists(&dir.push(file))
 }
 
-pub fn find_dir_using_rust_path_hack(p: &PkgId) -> Option<Path> {
-    let rp = rust_path();
-    for dir in rp.iter() {
+pub fn find_dir_using_rust_path_hack(rust_path: &[Path], p: &PkgId) -> Option<Path> {
+    for dir in rust_path.iter() {
         // Require that the parent directory match the package ID
         // Note that this only matches if the package ID being searched for
         // has a name that's a single component
@@ -454,6 +546,22 @@ pub fn versionize(p: &Path, v: &Version) -> Path {
 }
 
 
+/// True if `p` is a file that can be run. Windows has no executable bit
+/// (its C library makes one up from the extension), so there a file is
+/// executable if it's named like one.
+#[cfg(target_os = "win32")]
+pub fn is_executable(p: &Path) -> bool {
+    os::path_exists(p) && !os::path_is_dir(p) && p.filetype() == Some(os::EXE_SUFFIX)
+}
+
+#[cfg(not(target_os = "win32"))]
+pub fn is_executable(p: &Path) -> bool {
+    match p.get_mode() {
+        Some(mode) => !os::path_is_dir(p) && mode & S_IXUSR as uint == S_IXUSR as uint,
+        None => false
+    }
+}
+
 #[cfg(target_os = "win32")]
 pub fn chmod_read_only(p: &Path) -> bool {
     #[fixed_stack_segment];
@@ -475,3 +583,25 @@ pub fn chmod_read_only(p: &Path) -> bool {
     }
 }
 
+/// Gives `p` the permissions `mode`, as returned by `Path::get_mode`
+#[cfg(target_os = "win32")]
+pub fn set_mode(p: &Path, mode: uint) -> bool {
+    #[fixed_stack_segment];
+    unsafe {
+        do p.to_str().with_c_str |src_buf| {
+            libc::chmod(src_buf, mode as libc::c_int) == 0 as libc::c_int
+        }
+    }
+}
+
+/// Gives `p` the permissions `mode`, as returned by `Path::get_mode`
+#[cfg(not(target_os = "win32"))]
+pub fn set_mode(p: &Path, mode: uint) -> bool {
+    #[fixed_stack_segment];
+    unsafe {
+        do p.to_str().with_c_str |src_buf| {
+            libc::chmod(src_buf, mode as libc::mode_t) == 0 as libc::c_int
+        }
+    }
+}
+
@@ -0,0 +1,153 @@
+// Copyright 2013 The Rust Project Developers. See the COPYRIGHT
+// file at the top-level directory of this distribution and at
+// http://rust-lang.org/COPYRIGHT.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+// Install receipts: what installing a package wrote into a workspace, so
+// that uninstalling or upgrading it removes exactly those files.
+//
+// A package's receipt lives at `.rustpkg/receipts/<package path>.json` in
+// the workspace it was installed into. There's one per package path, not
+// per version, since a workspace only has one version of a package
+// installed at a time.
+
+use std::{io, os};
+use extra::json;
+use extra::sha1::Sha1;
+use extra::digest::Digest;
+use extra::serialize::{Encodable, Decodable};
+use path_util::U_RWX;
+
+#[deriving(Clone, Eq, Encodable, Decodable)]
+pub struct Receipt {
+    // The package ID, with its version, as PkgId::to_str gives it
+    id: ~str,
+    files: ~[InstalledFile]
+}
+
+#[deriving(Clone, Eq, Encodable, Decodable)]
+pub struct InstalledFile {
+    // Absolute
+    path: ~str,
+    hash: ~str
+}
+
+impl Receipt {
+    /// A receipt for the package `id`, which installed the files `files`.
+    /// The files are hashed as they are now.
+    pub fn new(id: ~str, files: &[Path]) -> Receipt {
+        Receipt {
+            id: id,
+            files: files.map(|p| InstalledFile {
+                path: os::make_absolute(p).to_str(),
+                hash: hash_installed(p)
+            })
+        }
+    }
+
+    /// The installed files that have been changed or replaced since they
+    /// were installed. Files that are gone don't count.
+    pub fn modified_files(&self) -> ~[Path] {
+        self.files.iter()
+            .filter(|f| os::path_exists(&Path(f.path)) || os::path_is_symlink(&Path(f.path)))
+            .filter(|f| hash_installed(&Path(f.path)) != f.hash)
+            .map(|f| Path(f.path))
+            .collect()
+    }
+
+    /// Removes the installed files, except for those in `keep`. Returns
+    /// the files that couldn't be removed.
+    pub fn remove_files(&self, keep: &[Path]) -> ~[Path] {
+        let mut failed = ~[];
+        for f in self.files.iter() {
+            let p = Path(f.path);
+            if keep.contains(&p) || !(os::path_exists(&p) || os::path_is_symlink(&p)) {
+                continue;
+            }
+            debug2!("Removing installed file {}", p.to_str());
+            if !os::remove_file(&p) {
+                failed.push(p);
+            }
+        }
+        failed
+    }
+}
+
+/// Where the receipt for the package at `pkg_path` in `workspace` goes
+pub fn receipt_path(workspace: &Path, pkg_path: &Path) -> Path {
+    let p = workspace.push_many([~".rustpkg", ~"receipts"]).push_rel(pkg_path);
+    p.with_filename(format!("{}.json", p.filename().expect("receipt_path: empty package path")))
+}
+
+/// Reads the receipt for the package at `pkg_path` in `workspace`, if it
+/// was installed with one
+pub fn read_receipt(workspace: &Path, pkg_path: &Path) -> Result<Option<Receipt>, ~str> {
+    let path = receipt_path(workspace, pkg_path);
+    if !os::path_exists(&path) {
+        return Ok(None);
+    }
+    let json = match io::file_reader(&path) {
+        Ok(r) => json::from_reader(r).map_err_move(|e| e.to_str()),
+        Err(e) => Err(e)
+    };
+    let json = match json {
+        Ok(json) => json,
+        Err(e) => return Err(format!("Couldn't read install receipt {}: {}", path.to_str(), e))
+    };
+    let mut decoder = json::Decoder(json);
+    Ok(Some(Decodable::decode(&mut decoder)))
+}
+
+/// Records `receipt` as the receipt for the package at `pkg_path` in
+/// `workspace`, replacing any earlier one
+pub fn write_receipt(workspace: &Path, pkg_path: &Path, receipt: &Receipt) -> Result<(), ~str> {
+    let path = receipt_path(workspace, pkg_path);
+    if !os::path_is_dir(&path.dir_path()) && !os::mkdir_recursive(&path.dir_path(), U_RWX) {
+        return Err(format!("Couldn't create {}", path.dir_path().to_str()));
+    }
+    do io::atomic_write(&path) |f| {
+        let mut encoder = json::PrettyEncoder(f);
+        receipt.encode(&mut encoder);
+    }
+}
+
+/// Deletes the receipt for the package at `pkg_path` in `workspace`
+pub fn remove_receipt(workspace: &Path, pkg_path: &Path) -> bool {
+    let path = receipt_path(workspace, pkg_path);
+    !os::path_exists(&path) || os::remove_file(&path)
+}
+
+// Hashes an installed file's contents. An executable installed as a symlink
+// to the built one is hashed by where it points, since rebuilding is meant
+// to change what it points to.
+fn hash_installed(p: &Path) -> ~str {
+    let mut sha = Sha1::new();
+    match link_target(p) {
+        Some(target) => {
+            sha.input_str("symlink:");
+            sha.input_str(target.to_str());
+        }
+        None => match io::read_whole_file(p) {
+            Ok(contents) => sha.input(contents),
+            // Can't match anything hashed when the file was installed
+            Err(_) => return ~""
+        }
+    }
+    sha.result_str()
+}
+
+#[cfg(unix)]
+fn link_target(p: &Path) -> Option<Path> {
+    os::readlink(p)
+}
+
+// Windows links can't be read, so they're hashed by what they point to
+#[cfg(windows)]
+fn link_target(_p: &Path) -> Option<Path> {
+    None
+}
@@ -24,45 +24,60 @@ extern mod extra;
 extern mod rustc;
 extern mod syntax;
 
-use std::{io, os, result, run, str, task};
+use std::{io, os, result, str, task};
 pub use std::path::Path;
 
+use extra::arc::RWArc;
 use extra::workcache;
 use rustc::driver::{driver, session};
 use rustc::metadata::filesearch;
-use rustc::metadata::filesearch::rust_path;
-use extra::{getopts};
+use rustc::metadata::filesearch::rust_path_lib_dir;
+use extra::getopts::groups;
+use extra::getopts::groups::{Command, CommandMatches, OptGroup, command, getopts_subcommand};
+use extra::getopts::groups::{optflag, optmulti, optopt};
 use syntax::{ast, diagnostic};
+use syntax::parse::token;
+use syntax::print::pprust;
 use util::*;
 use messages::{error, warn, note};
 use path_util::{build_pkg_id_in_workspace, built_test_in_workspace};
-use path_util::{U_RWX, in_rust_path};
-use path_util::{built_executable_in_workspace, built_library_in_workspace, default_workspace};
+use path_util::U_RWX;
+use path_util::{built_executable_in_workspace, built_library_in_workspace};
 use path_util::{target_executable_in_workspace, target_library_in_workspace};
-use source_control::{CheckedOutSources, is_git_dir, make_read_only};
+use path_util::{installed_library_in_workspace, system_library};
+use path_util::{built_doc_dir, target_doc_dir};
+use source_control::{CheckedOutSources, vcs_at, make_read_only};
 use workspace::{each_pkg_parent_workspace, pkg_parent_workspaces, cwd_to_workspace};
-use workspace::determine_destination;
-use context::{Context, BuildContext,
+use workspace::{determine_destination, lock_workspaces, resolve_declared_name};
+use context::{Context, BuildContext, rust_path_in,
                        RustcFlags, Trans, Link, Nothing, Pretty, Analysis, Assemble,
                        LLVMAssemble, LLVMCompileBitcode};
 use package_id::PkgId;
 use package_source::PkgSrc;
+use receipt::Receipt;
 use target::{WhatToBuild, Everything, is_lib, is_main, is_test, is_bench, Tests};
 // use workcache_support::{discover_outputs, digest_only_date};
 use workcache_support::digest_only_date;
-use exit_codes::{COPY_FAILED_CODE, BAD_FLAG_CODE};
+use exit_codes::{COPY_FAILED_CODE, BAD_FLAG_CODE, UPDATE_FAILED_CODE};
 
 pub mod api;
+mod completions;
 mod conditions;
 mod context;
 mod crate;
+mod diagnostics;
 mod exit_codes;
 mod installed_packages;
 mod messages;
+mod metadata;
+#[cfg(test)]
+mod mock_vcs;
 mod package_id;
 mod package_source;
 mod path_util;
+mod receipt;
 mod search;
+mod self_update;
 mod source_control;
 mod target;
 #[cfg(test)]
@@ -138,6 +153,7 @@ impl<'self> PkgScript<'self> {
     /// Returns a pair of an exit code and list of configs (obtained by
     /// calling the package script's configs() function if it exists
     fn run_custom(&mut self, exec: &mut workcache::Exec,
+                  cx: &Context,
                   sysroot: &Path) -> (~[~str], ExitCode) {
         let sess = self.sess;
 
@@ -156,16 +172,30 @@ impl<'self> PkgScript<'self> {
         debug2!("Running program: {} {} {}", exe.to_str(),
                sysroot.to_str(), "install");
         // Discover the output
-        exec.discover_output("binary", exe.to_str(), digest_only_date(&exe));
+        exec.discover_output("binary", exe.normalize().to_str(), digest_only_date(&exe));
         // FIXME #7401 should support commands besides `install`
-        let status = run::process_status(exe.to_str(), [sysroot.to_str(), ~"install"]);
+        let status = match process_status_in(cx, exe.to_str(),
+                                             [sysroot.to_str(), ~"install"]) {
+            Ok(status) => status,
+            Err(e) => {
+                error(e);
+                return (~[], 1);
+            }
+        };
         if status != 0 {
             return (~[], status);
         }
         else {
             debug2!("Running program (configs): {} {} {}",
                    exe.to_str(), sysroot.to_str(), "configs");
-            let output = run::process_output(exe.to_str(), [sysroot.to_str(), ~"configs"]);
+            let output = match process_output_in(cx, exe.to_str(),
+                                                 [sysroot.to_str(), ~"configs"]) {
+                Ok(output) => output,
+                Err(e) => {
+                    error(e);
+                    return (~[], 1);
+                }
+            };
             // Run the configs() function to get the configs
             let cfgs = str::from_utf8_slice(output.output).word_iter()
                 .map(|w| w.to_owned()).collect();
@@ -179,14 +209,20 @@ impl<'self> PkgScript<'self> {
 }
 
 pub trait CtxMethods {
-    fn run(&self, cmd: &str, args: ~[~str]);
+    /// Returns the exit code: the test binary's status for `test`, and
+    /// otherwise 0
+    fn run(&self, cmd: &str, args: ~[~str]) -> ExitCode;
     fn do_cmd(&self, _cmd: &str, _pkgname: &str);
     /// Returns a pair of the selected package ID, and the destination workspace
     fn build_args(&self, args: ~[~str], what: &WhatToBuild) -> Option<(PkgId, Path)>;
     /// Returns the destination workspace
     fn build(&self, pkg_src: &mut PkgSrc, what: &WhatToBuild);
     fn clean(&self, workspace: &Path, id: &PkgId);
-    fn info(&self);
+    /// Fetches the sources of the package and, transitively, of the
+    /// packages it depends on, without building anything
+    fn fetch(&self, pkg_src: PkgSrc);
+    /// Prints what an installed library's metadata says about it
+    fn info(&self, id: &PkgId);
     /// Returns a pair. First component is a list of installed paths,
     /// second is a list of declared and discovered inputs
     fn install(&self, src: PkgSrc, what: &WhatToBuild) -> (~[Path], ~[(~str, ~str)]);
@@ -196,7 +232,8 @@ pub trait CtxMethods {
                         target_workspace: &Path,
                         id: &PkgId) -> ~[~str];
     fn prefer(&self, _id: &str, _vers: Option<~str>);
-    fn test(&self, id: &PkgId, workspace: &Path);
+    /// Returns the test binary's exit status
+    fn test(&self, id: &PkgId, workspace: &Path) -> ExitCode;
     fn uninstall(&self, _id: &str, _vers: Option<~str>);
     fn unprefer(&self, _id: &str, _vers: Option<~str>);
     fn init(&self);
@@ -205,11 +242,15 @@ pub trait CtxMethods {
 impl CtxMethods for BuildContext {
     fn build_args(&self, args: ~[~str], what: &WhatToBuild) -> Option<(PkgId, Path)> {
         if args.len() < 1 {
-            match cwd_to_workspace() {
+            match cwd_to_workspace(&self.context) {
                 None if self.context.use_rust_path_hack => {
-                    let cwd = os::getcwd();
-                    let pkgid = PkgId::new(cwd.components[cwd.components.len() - 1]);
-                    let mut pkg_src = PkgSrc::new(cwd, default_workspace(), true, pkgid);
+                    let cwd = self.context.cwd.clone();
+                    let pkgid = PkgId::new_in(cwd.components[cwd.components.len() - 1],
+                                              self.context.rust_path);
+                    let dest_ws = self.context.default_workspace();
+                    let _locks = lock_workspaces(&self.context, [cwd.clone(), dest_ws.clone()]);
+                    let mut pkg_src = PkgSrc::new_in(&self.context, cwd, dest_ws, true,
+                                                     self.context.offline, pkgid);
                     self.build(&mut pkg_src, what);
                     match pkg_src {
                         PkgSrc { destination_workspace: ws,
@@ -218,9 +259,11 @@ impl CtxMethods for BuildContext {
                         }
                     }
                 }
-                None => { usage::build(); None }
+                None => { command_usage("build"); None }
                 Some((ws, pkgid)) => {
-                    let mut pkg_src = PkgSrc::new(ws.clone(), ws, false, pkgid);
+                    let _locks = lock_workspaces(&self.context, [ws.clone()]);
+                    let mut pkg_src = PkgSrc::new_in(&self.context, ws.clone(), ws, false,
+                                                     self.context.offline, pkgid);
                     self.build(&mut pkg_src, what);
                     match pkg_src {
                         PkgSrc { destination_workspace: ws,
@@ -233,71 +276,131 @@ impl CtxMethods for BuildContext {
         } else {
             // The package id is presumed to be the first command-line
             // argument
-            let pkgid = PkgId::new(args[0].clone());
-            let mut dest_ws = default_workspace();
+            let pkgid = resolve_declared_name(&self.context,
+                                              pkgid_from_args(&self.context, args));
+            let mut dest_ws = self.context.default_workspace();
+            if pkg_parent_workspaces(&self.context, &pkgid).is_empty() {
+                // Not in any workspace: as with install, fetch it into
+                // the default workspace, or use sources fetched earlier
+                let _locks = lock_workspaces(&self.context, [dest_ws.clone()]);
+                let mut pkg_src = PkgSrc::new_in(&self.context, dest_ws.clone(), dest_ws.clone(),
+                                                 false, self.context.offline, pkgid.clone());
+                self.build(&mut pkg_src, what);
+                return Some((pkg_src.id.clone(), dest_ws));
+            }
+            // The ID the package was built under, which takes any name it
+            // declares for itself
+            let mut built_id = pkgid.clone();
             do each_pkg_parent_workspace(&self.context, &pkgid) |workspace| {
                 debug2!("found pkg {} in workspace {}, trying to build",
                        pkgid.to_str(), workspace.to_str());
-                dest_ws = determine_destination(os::getcwd(),
-                                                self.context.use_rust_path_hack,
-                                                workspace);
-                let mut pkg_src = PkgSrc::new(workspace.clone(), dest_ws.clone(),
-                                              false, pkgid.clone());
+                dest_ws = determine_destination(&self.context, workspace);
+                let _locks = lock_workspaces(&self.context, [workspace.clone(), dest_ws.clone()]);
+                let mut pkg_src = PkgSrc::new_in(&self.context, workspace.clone(),
+                                                 dest_ws.clone(), false, self.context.offline,
+                                                 pkgid.clone());
                 self.build(&mut pkg_src, what);
+                built_id = pkg_src.id.clone();
                 true
             };
             // n.b. If this builds multiple packages, it only returns the workspace for
             // the last one. The whole building-multiple-packages-with-the-same-ID is weird
             // anyway and there are no tests for it, so maybe take it out
-            Some((pkgid, dest_ws))
+            Some((built_id, dest_ws))
         }
     }
-    fn run(&self, cmd: &str, args: ~[~str]) {
+    fn run(&self, cmd: &str, args: ~[~str]) -> ExitCode {
         match cmd {
             "build" => {
                 self.build_args(args, &Everything);
             }
             "clean" => {
                 if args.len() < 1 {
-                    match cwd_to_workspace() {
-                        None => { usage::clean(); return }
+                    match cwd_to_workspace(&self.context) {
+                        None => { command_usage("clean"); return 0 }
                         // tjc: Maybe clean should clean all the packages in the
                         // current workspace, though?
-                        Some((ws, pkgid)) => self.clean(&ws, &pkgid)
+                        Some((ws, pkgid)) => {
+                            let _locks = lock_workspaces(&self.context, [ws.clone()]);
+                            self.clean(&ws, &pkgid)
+                        }
                     }
 
                 }
                 else {
                     // The package id is presumed to be the first command-line
                     // argument
-                    let pkgid = PkgId::new(args[0].clone());
-                    let cwd = os::getcwd();
+                    let pkgid = PkgId::new_in(args[0].clone(), self.context.rust_path);
+                    let cwd = self.context.cwd.clone();
+                    let _locks = lock_workspaces(&self.context, [cwd.clone()]);
                     self.clean(&cwd, &pkgid); // tjc: should use workspace, not cwd
                 }
             }
             "do" => {
                 if args.len() < 2 {
-                    return usage::do_cmd();
+                    command_usage("do");
+                    return 0;
                 }
 
                 self.do_cmd(args[0].clone(), args[1].clone());
             }
+            "fetch" => {
+                if args.len() < 1 {
+                    match cwd_to_workspace(&self.context) {
+                        None => { command_usage("fetch"); return 0 }
+                        Some((ws, pkgid)) => {
+                            let _locks = lock_workspaces(&self.context, [ws.clone()]);
+                            self.fetch(PkgSrc::new_in(&self.context, ws.clone(), ws, false,
+                                                      false, pkgid));
+                        }
+                    }
+                } else {
+                    let pkgid = PkgId::new_in(args[0].clone(), self.context.rust_path);
+                    let workspaces = pkg_parent_workspaces(&self.context, &pkgid);
+                    if workspaces.is_empty() {
+                        let d = self.context.default_workspace();
+                        let _locks = lock_workspaces(&self.context, [d.clone()]);
+                        self.fetch(PkgSrc::new_in(&self.context, d.clone(), d, false, false,
+                                                  pkgid));
+                    } else {
+                        for workspace in workspaces.iter() {
+                            let _locks = lock_workspaces(&self.context, [workspace.clone()]);
+                            self.fetch(PkgSrc::new_in(&self.context,
+                                                      workspace.clone(), workspace.clone(),
+                                                      self.context.use_rust_path_hack, false,
+                                                      pkgid.clone()));
+                        }
+                    }
+                }
+            }
             "info" => {
-                self.info();
+                if args.len() < 1 {
+                    command_usage("info");
+                    return 0;
+                }
+                self.info(&PkgId::new_in(args[0], self.context.rust_path));
             }
             "install" => {
                if args.len() < 1 {
-                    match cwd_to_workspace() {
+                    match cwd_to_workspace(&self.context) {
                         None if self.context.use_rust_path_hack => {
-                            let cwd = os::getcwd();
+                            let cwd = self.context.cwd.clone();
                             let inferred_pkgid =
-                                PkgId::new(cwd.components[cwd.components.len() - 1]);
-                            self.install(PkgSrc::new(cwd, default_workspace(),
-                                                     true, inferred_pkgid), &Everything);
+                                PkgId::new_in(cwd.components[cwd.components.len() - 1],
+                                              self.context.rust_path);
+                            let dest_ws = self.context.default_workspace();
+                            let _locks = lock_workspaces(&self.context,
+                                                         [cwd.clone(), dest_ws.clone()]);
+                            self.install(PkgSrc::new_in(&self.context, cwd, dest_ws,
+                                                        true, self.context.offline,
+                                                        inferred_pkgid),
+                                         &Everything);
                         }
-                        None  => { usage::install(); return; }
+                        None  => { command_usage("install"); return 0; }
                         Some((ws, pkgid))                => {
-                            let pkg_src = PkgSrc::new(ws.clone(), ws.clone(), false, pkgid);
+                            let _locks = lock_workspaces(&self.context, [ws.clone()]);
+                            let pkg_src = PkgSrc::new_in(&self.context, ws.clone(), ws.clone(),
+                                                         false, self.context.offline, pkgid);
                             self.install(pkg_src, &Everything);
                       }
                   }
@@ -305,24 +408,29 @@ impl CtxMethods for BuildContext {
                 else {
                     // The package id is presumed to be the first command-line
                     // argument
-                    let pkgid = PkgId::new(args[0]);
+                    let pkgid = resolve_declared_name(&self.context,
+                                                      pkgid_from_args(&self.context, args));
                     let workspaces = pkg_parent_workspaces(&self.context, &pkgid);
                     debug2!("package ID = {}, found it in {:?} workspaces",
                            pkgid.to_str(), workspaces.len());
                     if workspaces.is_empty() {
-                        let d = default_workspace();
-                        let src = PkgSrc::new(d.clone(), d, false, pkgid.clone());
+                        let d = self.context.default_workspace();
+                        let _locks = lock_workspaces(&self.context, [d.clone()]);
+                        let src = PkgSrc::new_in(&self.context, d.clone(), d, false,
+                                                 self.context.offline, pkgid.clone());
                         self.install(src, &Everything);
                     }
                     else {
                         for workspace in workspaces.iter() {
-                            let dest = determine_destination(os::getcwd(),
-                                                             self.context.use_rust_path_hack,
-                                                             workspace);
-                            let src = PkgSrc::new(workspace.clone(),
-                                                  dest,
-                                                  self.context.use_rust_path_hack,
-                                                  pkgid.clone());
+                            let dest = determine_destination(&self.context, workspace);
+                            let _locks = lock_workspaces(&self.context,
+                                                         [workspace.clone(), dest.clone()]);
+                            let src = PkgSrc::new_in(&self.context,
+                                                     workspace.clone(),
+                                                     dest,
+                                                     self.context.use_rust_path_hack,
+                                                     self.context.offline,
+                                                     pkgid.clone());
                             self.install(src, &Everything);
                         };
                     }
@@ -330,14 +438,15 @@ impl CtxMethods for BuildContext {
             }
             "list" => {
                 io::println("Installed packages:");
-                do installed_packages::list_installed_packages |pkg_id| {
+                do installed_packages::list_installed_packages(self.context.rust_path) |pkg_id| {
                     println(pkg_id.path.to_str());
                     true
                 };
             }
             "prefer" => {
                 if args.len() < 1 {
-                    return usage::uninstall();
+                    command_usage("prefer");
+                    return 0;
                 }
 
                 self.prefer(args[0], None);
@@ -348,7 +457,7 @@ impl CtxMethods for BuildContext {
                 match maybe_id_and_workspace {
                     Some((pkg_id, workspace)) => {
                         // Assuming it's built, run the tests
-                        self.test(&pkg_id, &workspace);
+                        return self.test(&pkg_id, &workspace);
                     }
                     None => {
                         error("Testing failed because building the specified package failed.");
@@ -357,27 +466,29 @@ impl CtxMethods for BuildContext {
             }
             "init" => {
                 if args.len() != 0 {
-                    return usage::init();
+                    command_usage("init");
+                    return 0;
                 } else {
                     self.init();
                 }
             }
             "uninstall" => {
                 if args.len() < 1 {
-                    return usage::uninstall();
+                    command_usage("uninstall");
+                    return 0;
                 }
 
-                let pkgid = PkgId::new(args[0]);
-                if !installed_packages::package_is_installed(&pkgid) {
+                let pkgid = PkgId::new_in(args[0], self.context.rust_path);
+                if !installed_packages::package_is_installed(self.context.rust_path, &pkgid) {
                     warn(format!("Package {} doesn't seem to be installed! \
                                   Doing nothing.", args[0]));
-                    return;
+                    return 0;
                 }
                 else {
-                    let rp = rust_path();
-                    assert!(!rp.is_empty());
+                    assert!(!self.context.rust_path.is_empty());
                     do each_pkg_parent_workspace(&self.context, &pkgid) |workspace| {
-                        path_util::uninstall_package_from(workspace, &pkgid);
+                        let _locks = lock_workspaces(&self.context, [workspace.clone()]);
+                        uninstall_from(workspace, &pkgid, self.context.force_uninstall);
                         note(format!("Uninstalled package {} (was installed in {})",
                                   pkgid.to_str(), workspace.to_str()));
                         true
@@ -386,13 +497,15 @@ impl CtxMethods for BuildContext {
             }
             "unprefer" => {
                 if args.len() < 1 {
-                    return usage::unprefer();
+                    command_usage("unprefer");
+                    return 0;
                 }
 
                 self.unprefer(args[0], None);
             }
             _ => fail2!("I don't know the command `{}`", cmd)
         }
+        0
     }
 
     fn do_cmd(&self, _cmd: &str, _pkgname: &str)  {
@@ -406,15 +519,17 @@ impl CtxMethods for BuildContext {
         let workspace = pkg_src.source_workspace.clone();
         let pkgid = pkg_src.id.clone();
 
-        debug2!("build: workspace = {} (in Rust path? {:?} is git dir? {:?} \
+        debug2!("build: workspace = {} (in Rust path? {:?} is a repository? {:?} \
                 pkgid = {} pkgsrc start_dir = {}", workspace.to_str(),
-               in_rust_path(&workspace), is_git_dir(&workspace.push_rel(&pkgid.path)),
+               self.context.in_rust_path(&workspace),
+               vcs_at(&workspace.push_rel(&pkgid.path)).is_some(),
                pkgid.to_str(), pkg_src.start_dir.to_str());
 
-        // If workspace isn't in the RUST_PATH, and it's a git repo,
+        // If workspace isn't in the RUST_PATH, and it's a repository,
         // then clone it into the first entry in RUST_PATH, and repeat
-        if !in_rust_path(&workspace) && is_git_dir(&workspace.push_rel(&pkgid.path)) {
-            let out_dir = default_workspace().push("src").push_rel(&pkgid.path);
+        if !self.context.in_rust_path(&workspace) &&
+            vcs_at(&workspace.push_rel(&pkgid.path)).is_some() {
+            let out_dir = self.context.default_workspace().push("src").push_rel(&pkgid.path);
             let git_result = source_control::safe_git_clone(&workspace.push_rel(&pkgid.path),
                                                             &pkgid.version,
                                                             &out_dir);
@@ -422,13 +537,15 @@ impl CtxMethods for BuildContext {
                 CheckedOutSources => make_read_only(&out_dir),
                 _ => cond.raise((pkgid.path.to_str(), out_dir.clone()))
             };
-            let default_ws = default_workspace();
+            let default_ws = self.context.default_workspace();
             debug2!("Calling build recursively with {:?} and {:?}", default_ws.to_str(),
                    pkgid.to_str());
-            return self.build(&mut PkgSrc::new(default_ws.clone(),
-                                               default_ws,
-                                               false,
-                                               pkgid.clone()), what_to_build);
+            return self.build(&mut PkgSrc::new_in(&self.context,
+                                                  default_ws.clone(),
+                                                  default_ws,
+                                                  false,
+                                                  false,
+                                                  pkgid.clone()), what_to_build);
         }
 
         // Is there custom build logic? If so, use it
@@ -445,6 +562,7 @@ impl CtxMethods for BuildContext {
                     let package_script_path_clone = package_script_path.clone();
                     let sub_ws = workspace.clone();
                     let sub_id = pkgid.clone();
+                    let sub_cx = self.context.clone();
                     declare_package_script_dependency(prep, &*pkg_src);
                     do prep.exec |exec| {
                         let mut pscript = PkgScript::parse(@sub_sysroot.clone(),
@@ -452,7 +570,7 @@ impl CtxMethods for BuildContext {
                                                           &sub_ws,
                                                           &sub_id);
 
-                        pscript.run_custom(exec, &sub_sysroot)
+                        pscript.run_custom(exec, &sub_cx, &sub_sysroot)
                     }
                 };
                 debug2!("Command return code = {:?}", hook_result);
@@ -497,6 +615,7 @@ impl CtxMethods for BuildContext {
                     }
                 }
             }
+            pkg_src.read_declared_metadata();
             // Build it!
             pkg_src.build(self, cfgs);
         }
@@ -518,14 +637,112 @@ impl CtxMethods for BuildContext {
         note(format!("Cleaned package {}", id.to_str()));
     }
 
-    fn info(&self) {
-        // stub
-        fail2!("info not yet implemented");
+    fn fetch(&self, pkg_src: PkgSrc) {
+        use conditions::nonexistent_package::cond;
+
+        let mut seen = ~[pkg_src.id.path.to_str()];
+        let mut to_scan = ~[pkg_src];
+        while !to_scan.is_empty() {
+            let mut pkg_src = to_scan.pop();
+            note(format!("Sources for {} are in {}",
+                         pkg_src.id.to_str(), pkg_src.start_dir.to_str()));
+            for extern_mod in pkg_src.extern_mods().move_iter() {
+                let name = extern_mod.name;
+                // The package's own library, the standard libraries, and
+                // packages already taken care of
+                if name == pkg_src.id.short_name
+                    || system_library(&self.sysroot(), name.as_slice()).is_some()
+                    || seen.contains(&name) {
+                    continue;
+                }
+                seen.push(name.clone());
+                let dep_id = PkgId::new_in(name, self.context.rust_path);
+                // Dependencies are looked for as building them would: in
+                // the first workspace that has them, or else fetched into
+                // the default workspace
+                let workspaces = pkg_parent_workspaces(&self.context, &dep_id);
+                let dest = if workspaces.is_empty() {
+                    self.context.default_workspace()
+                } else {
+                    workspaces[0].clone()
+                };
+                let _locks = lock_workspaces(&self.context, [dest.clone()]);
+                if !extern_mod.conditional {
+                    to_scan.push(PkgSrc::new_in(&self.context, dest.clone(), dest,
+                                                self.context.use_rust_path_hack, false,
+                                                dep_id));
+                    continue;
+                }
+                // A dependency behind a cfg may only exist for some targets,
+                // so one that can't be found doesn't stop the fetch
+                let mut missing = None;
+                let dep_src = do cond.trap(|(_, msg)| {
+                    if missing.is_none() {
+                        missing = Some(msg);
+                    }
+                    Path("")
+                }).inside {
+                    PkgSrc::new_in(&self.context, dest.clone(), dest.clone(),
+                                   self.context.use_rust_path_hack, false, dep_id.clone())
+                };
+                match missing {
+                    None => to_scan.push(dep_src),
+                    Some(msg) => warn(format!("skipping {}, which {} only needs in some \
+                                               configurations: {}",
+                                              dep_id.to_str(), pkg_src.id.to_str(), msg))
+                }
+            }
+        }
     }
 
-    fn install(&self, mut pkg_src: PkgSrc, what: &WhatToBuild) -> (~[Path], ~[(~str, ~str)]) {
+    fn info(&self, id: &PkgId) {
+        let mut lib = None;
+        for ws in self.context.rust_path.iter() {
+            if lib.is_none() {
+                lib = installed_library_in_workspace(&id.path, ws);
+            }
+        }
+        let lib = match lib {
+            Some(lib) => lib,
+            None => {
+                error(format!("{} is not installed as a library", id.to_str()));
+                return;
+            }
+        };
+        let info = match library_info(&lib) {
+            Some(info) => info,
+            None => {
+                error(format!("couldn't read the metadata in {}", lib.to_str()));
+                return;
+            }
+        };
+        println!("name: {}", info.name);
+        println!("version: {}", info.vers);
+        println!("hash: {}", info.hash);
+        for pkg_id in info.package_id.iter() {
+            println!("package id: {}", *pkg_id);
+        }
+        for license in metadata::declared_license(info.attrs).iter() {
+            println!("license: {}", *license);
+        }
+        println!("path: {}", lib.to_str());
+        for triple in info.triple.iter() {
+            println!("target: {}", *triple);
+        }
+        for level in info.opt_level.iter() {
+            println!("opt level: {}", *level);
+        }
+        println("dependencies:");
+        for dep in info.deps.iter() {
+            println!("    {}-{} ({})", dep.name, dep.vers, dep.hash);
+        }
+        println("cfg:");
+        for mi in info.cfg.iter() {
+            println!("    {}", pprust::meta_item_to_str(*mi, token::get_ident_interner()));
+        }
+    }
 
-        let id = pkg_src.id.clone();
+    fn install(&self, mut pkg_src: PkgSrc, what: &WhatToBuild) -> (~[Path], ~[(~str, ~str)]) {
 
         let mut installed_files = ~[];
         let mut inputs = ~[];
@@ -535,6 +752,13 @@ impl CtxMethods for BuildContext {
         // workcache only knows about *crates*. Building a package
         // just means inferring all the crates in it, then building each one.
         self.build(&mut pkg_src, what);
+        if self.context.install_docs {
+            generate_docs(self, &pkg_src);
+        }
+
+        // Building may have replaced the ID's name and version with ones
+        // the package declares for itself
+        let id = pkg_src.id.clone();
 
         let to_do = ~[pkg_src.libs.clone(), pkg_src.mains.clone(),
                       pkg_src.tests.clone(), pkg_src.benchs.clone()];
@@ -575,39 +799,82 @@ impl CtxMethods for BuildContext {
         let target_exec = target_executable_in_workspace(id, target_workspace);
         let target_lib = maybe_library.as_ref()
             .map(|_| target_library_in_workspace(id, target_workspace));
+        // Documentation generated for the package, whether by this install
+        // or an earlier one, goes along with it
+        let doc_dir = built_doc_dir(id, build_workspace);
+        let docs: ~[Path] = if os::path_is_dir(&doc_dir) {
+            os::walk_dir(&doc_dir).filter(|e| !os::path_is_dir(&e.path))
+                .map(|e| e.path.clone()).collect()
+        } else {
+            ~[]
+        };
+        let target_docs = target_doc_dir(id, target_workspace);
 
         debug2!("target_exec = {} target_lib = {:?} \
                maybe_executable = {:?} maybe_library = {:?}",
                target_exec.to_str(), target_lib,
                maybe_executable, maybe_library);
 
-        do self.workcache_context.with_prep(id.install_tag()) |prep| {
+        for lib in maybe_library.iter() {
+            let conflicts = installed_packages::conflicting_dependencies(self.context.rust_path,
+                                                                         lib);
+            for &(ref dep, ref other) in conflicts.iter() {
+                warn(format!("{} was built against {}-{} with hash {}, but {} has a \
+                              different hash", id.to_str(), dep.name, dep.vers, dep.hash,
+                             other.to_str()));
+            }
+        }
+
+        // Installing by symlink is cached apart from installing by copy, so
+        // that switching between them reinstalls
+        let symlink = self.context.symlink_executables;
+        let install_tag = if symlink {
+            format!("{}+symlink", id.install_tag())
+        } else {
+            id.install_tag()
+        };
+        let install_tag = if docs.is_empty() { install_tag } else { install_tag + "+docs" };
+        do self.workcache_context.with_prep(install_tag) |prep| {
             for ee in maybe_executable.iter() {
                 prep.declare_input("binary",
-                                   ee.to_str(),
+                                   ee.normalize().to_str(),
                                    workcache_support::digest_only_date(ee));
             }
             for ll in maybe_library.iter() {
                 prep.declare_input("binary",
-                                   ll.to_str(),
+                                   ll.normalize().to_str(),
                                    workcache_support::digest_only_date(ll));
             }
+            for doc in docs.iter() {
+                prep.declare_input("file",
+                                   doc.normalize().to_str(),
+                                   workcache_support::digest_file_with_date(doc));
+            }
             let subex = maybe_executable.clone();
             let sublib = maybe_library.clone();
             let sub_target_ex = target_exec.clone();
             let sub_target_lib = target_lib.clone();
+            let sub_workspace = target_workspace.clone();
+            let sub_id = id.clone();
+            let sub_docs = docs.clone();
+            let sub_doc_dir = doc_dir.clone();
+            let sub_target_docs = target_docs.clone();
 
             do prep.exec |exe_thing| {
                 let mut outputs = ~[];
 
+                // What an earlier install of the package wrote goes first,
+                // so that upgrading doesn't leave the old version behind
+                remove_previous_install(&sub_workspace, &sub_id);
+
                 for exec in subex.iter() {
-                    debug2!("Copying: {} -> {}", exec.to_str(), sub_target_ex.to_str());
+                    debug2!("Installing: {} -> {}", exec.to_str(), sub_target_ex.to_str());
                     if !(os::mkdir_recursive(&sub_target_ex.dir_path(), U_RWX) &&
-                         os::copy_file(exec, &sub_target_ex)) {
+                         install_executable(exec, &sub_target_ex, symlink)) {
                         cond.raise(((*exec).clone(), sub_target_ex.clone()));
                     }
                     exe_thing.discover_output("binary",
-                        sub_target_ex.to_str(),
+                        sub_target_ex.normalize().to_str(),
                         workcache_support::digest_only_date(&sub_target_ex));
                     outputs.push(sub_target_ex.to_str());
                 }
@@ -623,10 +890,31 @@ impl CtxMethods for BuildContext {
                     }
                     debug2!("3. discovering output {}", target_lib.to_str());
                     exe_thing.discover_output("binary",
-                                              target_lib.to_str(),
+                                              target_lib.normalize().to_str(),
                                               workcache_support::digest_only_date(&target_lib));
                     outputs.push(target_lib.to_str());
                 }
+                for doc in sub_docs.iter() {
+                    let rel = doc.relative_from(&sub_doc_dir).expect("doc outside doc dir");
+                    let target_doc = sub_target_docs.push_rel(&rel);
+                    debug2!("Installing: {} -> {}", doc.to_str(), target_doc.to_str());
+                    if !(os::mkdir_recursive(&target_doc.dir_path(), U_RWX) &&
+                         os::copy_file(doc, &target_doc)) {
+                        cond.raise(((*doc).clone(), target_doc.clone()));
+                    }
+                    exe_thing.discover_output("file",
+                                              target_doc.to_str(),
+                                              workcache_support::digest_file_with_date(
+                                                  &target_doc));
+                    outputs.push(target_doc.to_str());
+                }
+
+                let installed = outputs.map(|p| Path(*p));
+                let receipt = Receipt::new(sub_id.to_str(), installed);
+                match receipt::write_receipt(&sub_workspace, &sub_id.path, &receipt) {
+                    Ok(()) => (),
+                    Err(e) => warn(format!("Couldn't record what was installed: {}", e))
+                }
                 outputs
             }
         }
@@ -636,26 +924,30 @@ impl CtxMethods for BuildContext {
         fail2!("prefer not yet implemented");
     }
 
-    fn test(&self, pkgid: &PkgId, workspace: &Path)  {
+    fn test(&self, pkgid: &PkgId, workspace: &Path) -> ExitCode {
         match built_test_in_workspace(pkgid, workspace) {
             Some(test_exec) => {
                 debug2!("test: test_exec = {}", test_exec.to_str());
-                let status = run::process_status(test_exec.to_str(), [~"--test"]);
-                os::set_exit_status(status);
+                match process_status_in(&self.context, test_exec.to_str(), [~"--test"]) {
+                    Ok(status) => status,
+                    Err(e) => fail2!("{}", e)
+                }
             }
             None => {
                 error(format!("Internal error: test executable for package ID {} in workspace {} \
                            wasn't built! Please report this as a bug.",
                            pkgid.to_str(), workspace.to_str()));
+                0
             }
         }
     }
 
     fn init(&self) {
-        os::mkdir_recursive(&Path("src"),   U_RWX);
-        os::mkdir_recursive(&Path("lib"),   U_RWX);
-        os::mkdir_recursive(&Path("bin"),   U_RWX);
-        os::mkdir_recursive(&Path("build"), U_RWX);
+        let cwd = &self.context.cwd;
+        os::mkdir_recursive(&cwd.push("src"),   U_RWX);
+        os::mkdir_recursive(&cwd.push("lib"),   U_RWX);
+        os::mkdir_recursive(&cwd.push("bin"),   U_RWX);
+        os::mkdir_recursive(&cwd.push("build"), U_RWX);
     }
 
     fn uninstall(&self, _id: &str, _vers: Option<~str>)  {
@@ -667,82 +959,341 @@ impl CtxMethods for BuildContext {
     }
 }
 
+/// The package ID that `args`, the arguments to `build` or `install`, start
+/// with. It may be followed by `as <name>`, to build the package's library
+/// under that name rather than the last component of its path.
+fn pkgid_from_args(cx: &Context, args: &[~str]) -> PkgId {
+    let pkgid = PkgId::new_in(args[0], cx.rust_path);
+    if args.len() >= 3 && args[1].as_slice() == "as" {
+        pkgid.with_alias(args[2])
+    } else {
+        pkgid
+    }
+}
+
 pub fn main() {
-    io::println("WARNING: The Rust package manager is experimental and may be unstable");
+    // On stderr, so that it doesn't end up in output meant for other
+    // programs, like `version --json` or a completion script
+    io::stderr().write_line("WARNING: The Rust package manager is experimental and may be \
+                             unstable");
     os::set_exit_status(main_args(os::args()));
 }
 
+/// Runs rustpkg with the arguments `args` (not including the program name),
+/// as though it had been started in the directory `cwd` with the variables
+/// in `env` added to its environment, and returns its exit code.
+///
+/// Neither the process's working directory nor its environment is changed:
+/// rustpkg looks for packages relative to `cwd` and with `env`'s RUST_PATH,
+/// and the programs it runs are started in `cwd` with `env` added to theirs.
+/// So runs can go on at the same time, in different tasks.
+pub fn run(args: &[~str], env: &[(~str, ~str)], cwd: &Path) -> ExitCode {
+    let args = ~[~"rustpkg"] + args;
+    let env = env.to_owned();
+    let cwd = cwd.clone();
+    // Failure outside main_args' own task::try still mustn't take the
+    // caller down
+    match do task::try { main_args_in(args, env, &cwd) } {
+        Ok(status) => status,
+        Err(()) => COPY_FAILED_CODE
+    }
+}
+
+/// Options that every command accepts
+fn global_opts() -> ~[OptGroup] {
+    ~[optflag("h", "help", "Display this message"),
+      optflag("v", "version", "Print version info and exit"),
+      optflag("r", "rust-path-hack", "Look for packages in RUST_PATH outside of workspaces"),
+      optopt("", "sysroot", "Override the system root", "PATH")]
+}
+
+/// Options that `build` and `install` pass on to rustc
+fn rustc_opts() -> ~[OptGroup] {
+    ~[optmulti("c", "cfg", "Pass a cfg flag to the package script", "SPEC"),
+      optopt("", "linker", "Use a linker other than the system linker", "PATH"),
+      optmulti("", "link-args", "Extra arguments to pass to the linker", "ARGS"),
+      optopt("", "opt-level", "Set the optimization level (0 <= n <= 3)", "LEVEL"),
+      optflag("O", "", "Equivalent to --opt-level=2"),
+      optflag("", "save-temps", "Don't delete temporary files"),
+      optopt("", "target", "Set the target triple", "TRIPLE"),
+      optopt("", "target-cpu", "Set the target CPU", "CPU"),
+      optmulti("", "linkage", "Link Rust libraries statically or dynamically", "LINKAGE"),
+      optmulti("Z", "", "Enable an experimental rustc feature", "FLAG")]
+}
+
+/// Options that stop `build` before it produces a library or executable
+fn partial_build_opts() -> ~[OptGroup] {
+    ~[optflag("", "no-link", "Compile and assemble, but don't link"),
+      optflag("", "no-trans", "Parse and translate, but don't generate any code"),
+      optflag("", "pretty", "Pretty-print the code into the build directory, but \
+                             don't generate output. --pretty=MODE prints in MODE \
+                             rather than the normal mode"),
+      optflag("", "parse-only", "Parse the code, but don't typecheck or generate code"),
+      optflag("S", "assembly", "Generate assembly code, but don't assemble or link it"),
+      optflag("", "emit-llvm", "Generate LLVM bitcode, or LLVM assembly with -S")]
+}
+
+/// Options that only `build` accepts
+fn build_opts() -> ~[OptGroup] {
+    ~[optflag("", "explain", "Say why each crate was or wasn't rebuilt")]
+}
+
+/// Options that only `env` accepts
+fn env_opts() -> ~[OptGroup] {
+    ~[optmulti("c", "cfg", "Show the cfgs as if this had been passed to build", "SPEC")]
+}
+
+/// Options that only `version` accepts
+fn version_opts() -> ~[OptGroup] {
+    ~[optflag("", "json", "Print the versions as JSON")]
+}
+
+/// Options that only `self-update` accepts
+fn self_update_opts() -> ~[OptGroup] {
+    ~[optopt("", "channel", "Update from this channel instead of $RUSTPKG_CHANNEL", "URL"),
+      optopt("", "manifest-sha256",
+             "The SHA-256 the channel's manifest must have, instead of $RUSTPKG_CHANNEL_SHA256",
+             "HASH"),
+      optflag("", "with-libs", "Update the standard libraries too"),
+      optflag("", "user", "Install into the default workspace, not the sysroot")]
+}
+
+/// Options that only `uninstall` accepts
+fn uninstall_opts() -> ~[OptGroup] {
+    ~[optflag("f", "force", "Remove installed files even if they've changed")]
+}
+
+/// Options that stop `build` and `install` from fetching packages
+fn offline_opts() -> ~[OptGroup] {
+    ~[optflag("", "offline", "Don't fetch packages; use only the sources in workspaces")]
+}
+
+/// Options that make `build` and `install` show more of what they do
+fn verbose_opts() -> ~[OptGroup] {
+    ~[optflag("", "verbose", "Show the output of package scripts and rustdoc as it's produced")]
+}
+
+/// Options that limit how long `build`, `install` and `test` let the
+/// programs they run take
+fn timeout_opts() -> ~[OptGroup] {
+    ~[optopt("", "timeout", "Kill package scripts, rustdoc and tests that run for longer \
+                             than this", "SECS")]
+}
+
+/// Options that only `install` accepts
+fn install_opts() -> ~[OptGroup] {
+    ~[optflag("", "symlink", "Install executables as symlinks to the built ones"),
+      optflag("", "with-docs", "Generate the package's documentation and install it too")]
+}
+
+fn commands() -> ~[Command] {
+    ~[command("build", "Build a package",
+              rustc_opts() + partial_build_opts() + build_opts() + offline_opts() +
+              verbose_opts() + timeout_opts()),
+      command("clean", "Remove a package's build files", ~[]),
+      command("completions", "Print a shell completion script", ~[]),
+      command("do", "Run a custom command from a package script", ~[]),
+      command("env", "Show where rustpkg looks for packages and installs them", env_opts()),
+      command("fetch", "Fetch a package and its dependencies without building them", ~[]),
+      command("info", "Show information about a package", ~[]),
+      command("init", "Create a workspace in the current directory", ~[]),
+      command("install", "Build and install a package",
+              rustc_opts() + install_opts() + offline_opts() + verbose_opts() +
+              timeout_opts()),
+      command("list", "List installed packages", ~[]),
+      command("prefer", "Make a version of a package the default", ~[]),
+      command("self-update", "Install a newer rustpkg from a channel", self_update_opts()),
+      command("test", "Build and run a package's tests", timeout_opts()),
+      command("uninstall", "Remove an installed package", uninstall_opts()),
+      command("unprefer", "Undo the effect of `prefer`", ~[]),
+      command("version", "Show the versions of rustpkg and rustc", version_opts())]
+}
+
+/// Prints the help for `cmd`: what it does, from `usage`, and then the
+/// options it's parsed with
+fn command_usage(cmd: &str) {
+    let brief = match cmd {
+        "build" => usage::build(),
+        "clean" => usage::clean(),
+        "completions" => usage::completions(),
+        "do" => usage::do_cmd(),
+        "env" => usage::env(),
+        "fetch" => usage::fetch(),
+        "info" => usage::info(),
+        "install" => usage::install(),
+        "list"    => usage::list(),
+        "prefer" => usage::prefer(),
+        "self-update" => usage::self_update(),
+        "test" => usage::test(),
+        "init" => usage::init(),
+        "uninstall" => usage::uninstall(),
+        "unprefer" => usage::unprefer(),
+        "version" => usage::version(),
+        _ => return general_usage()
+    };
+    match commands().move_iter().find(|c| c.name.as_slice() == cmd) {
+        Some(command) => io::print(groups::command_usage(brief, global_opts(), &command)),
+        None => general_usage()
+    }
+}
+
+/// Prints the list of commands and the global options
+fn general_usage() {
+    io::print(groups::commands_usage(usage::general(), global_opts(), commands()));
+}
+
 pub fn main_args(args: &[~str]) -> int {
-    let opts = ~[getopts::optflag("h"), getopts::optflag("help"),
-                                        getopts::optflag("no-link"),
-                                        getopts::optflag("no-trans"),
-                 // n.b. Ignores different --pretty options for now
-                                        getopts::optflag("pretty"),
-                                        getopts::optflag("parse-only"),
-                 getopts::optflag("S"), getopts::optflag("assembly"),
-                 getopts::optmulti("c"), getopts::optmulti("cfg"),
-                 getopts::optflag("v"), getopts::optflag("version"),
-                 getopts::optflag("r"), getopts::optflag("rust-path-hack"),
-                                        getopts::optopt("sysroot"),
-                                        getopts::optflag("emit-llvm"),
-                                        getopts::optopt("linker"),
-                                        getopts::optopt("link-args"),
-                                        getopts::optopt("opt-level"),
-                 getopts::optflag("O"),
-                                        getopts::optflag("save-temps"),
-                                        getopts::optopt("target"),
-                                        getopts::optopt("target-cpu"),
-                 getopts::optmulti("Z")                                   ];
-    let matches = &match getopts::getopts(args, opts) {
-        result::Ok(m) => m,
-        result::Err(f) => {
-            error(format!("{}", f.to_err_msg()));
+    main_args_in(args, [], &os::getcwd())
+}
+
+/// As `main_args`, but as though rustpkg had been started in the directory
+/// `cwd` with the variables in `env` added to its environment. See `run`.
+fn main_args_in(args: &[~str], env: &[(~str, ~str)], cwd: &Path) -> int {
+    let binary = args[0].clone();
+    // `--pretty` is a plain flag, so that getopts doesn't take the package
+    // ID following it for the mode. A mode can be given as `--pretty=MODE`,
+    // which is split off here, since getopts won't take an argument to a
+    // flag.
+    let mut pretty_mode = None;
+    let args: ~[~str] = args.tail().iter().map(|a| {
+        if a.starts_with("--pretty=") {
+            pretty_mode = Some(a.slice_from("--pretty=".len()).to_owned());
+            ~"--pretty"
+        } else {
+            a.clone()
+        }
+    }).collect();
+    // Free arguments before the command are ignored; the test suite passes
+    // the sysroot that way.
+    let mut args = args.as_slice();
+    match args.iter().position(|a| util::is_cmd(*a)) {
+        Some(i) if args.slice_to(i).iter().all(|a| !a.starts_with("-")) => {
+            args = args.slice_from(i);
+        }
+        _ => ()
+    }
 
-            return 1;
+    let (cmd, matches) = match getopts_subcommand(args, global_opts(), commands()) {
+        result::Ok(CommandMatches { command: command, matches: matches }) => (command, matches),
+        result::Err(f) => {
+            let cmd = f.command.clone();
+            error(f.to_err_msg());
+            return match cmd {
+                Some(cmd) => {
+                    command_usage(cmd);
+                    BAD_FLAG_CODE
+                }
+                None => {
+                    general_usage();
+                    1
+                }
+            };
         }
     };
-    let help = matches.opt_present("h") ||
-                   matches.opt_present("help");
-    let no_link = matches.opt_present("no-link");
-    let no_trans = matches.opt_present("no-trans");
-    let supplied_sysroot = matches.opt_val("sysroot");
-    let generate_asm = matches.opt_present("S") ||
-        matches.opt_present("assembly");
-    let parse_only = matches.opt_present("parse-only");
-    let pretty = matches.opt_present("pretty");
-    let emit_llvm = matches.opt_present("emit-llvm");
-
-    if matches.opt_present("v") ||
-       matches.opt_present("version") {
-        rustc::version(args[0]);
+    let matches = &matches;
+    let help = matches.opt_present("help");
+
+    if matches.opt_present("version") {
+        rustc::version(binary);
         return 0;
     }
 
-    let use_rust_path_hack = matches.opt_present("r") ||
-                             matches.opt_present("rust-path-hack");
+    let cmd = match cmd {
+        None => {
+            general_usage();
+            return if help { 0 } else { 1 };
+        }
+        Some(cmd) => {
+            if help {
+                command_usage(cmd);
+                return 0;
+            }
+            cmd
+        }
+    };
+
+    // Most commands don't accept the rustc options at all
+    let opt_present: &fn(&str) -> bool = |nm| {
+        matches.opt_defined(nm) && matches.opt_present(nm)
+    };
+    let opt_str: &fn(&str) -> Option<~str> = |nm| {
+        if matches.opt_defined(nm) { matches.opt_str(nm) } else { None }
+    };
+    let opt_strs: &fn(&str) -> ~[~str] = |nm| {
+        if matches.opt_defined(nm) { matches.opt_strs(nm) } else { ~[] }
+    };
+
+    let no_link = opt_present("no-link");
+    let no_trans = opt_present("no-trans");
+    let supplied_sysroot = matches.opt_path("sysroot");
+    let generate_asm = opt_present("assembly");
+    let parse_only = opt_present("parse-only");
+    let pretty = if opt_present("pretty") {
+        Some(pretty_mode.unwrap_or(~"normal"))
+    } else {
+        None
+    };
+    let pretty = match pretty {
+        None => None,
+        Some(mode) => match driver::pretty_mode(mode) {
+            Some(ppm) => Some(ppm),
+            None => {
+                error(format!("unknown pretty-printing mode `{}`", mode));
+                command_usage(cmd);
+                return BAD_FLAG_CODE;
+            }
+        }
+    };
+    let emit_llvm = opt_present("emit-llvm");
+
+    let use_rust_path_hack = matches.opt_present("rust-path-hack");
+    let explain = opt_present("explain");
+    let symlink_executables = opt_present("symlink");
+    let install_docs = opt_present("with-docs");
+    let offline = opt_present("offline");
+    let force_uninstall = opt_present("force");
+    let verbose = opt_present("verbose");
+    let timeout = match opt_str("timeout") {
+        None => None,
+        Some(secs) => match from_str::<u64>(secs.as_slice()) {
+            Some(secs) if secs > 0 => Some(secs),
+            _ => {
+                error(format!("expected a number of seconds for --timeout, not `{}`", secs));
+                command_usage(cmd);
+                return BAD_FLAG_CODE;
+            }
+        }
+    };
 
-    let linker = matches.opt_str("linker");
-    let link_args = matches.opt_str("link-args");
-    let cfgs = matches.opt_strs("cfg") + matches.opt_strs("c");
-    let mut user_supplied_opt_level = true;
-    let opt_level = match matches.opt_str("opt-level") {
-        Some(~"0") => session::No,
-        Some(~"1") => session::Less,
-        Some(~"2") => session::Default,
-        Some(~"3") => session::Aggressive,
-        _ if matches.opt_present("O") => session::Default,
-        _ => {
-            user_supplied_opt_level = false;
-            session::No
+    let linker = opt_str("linker");
+    // --link-args may be repeated; rustc splits the result on spaces
+    let link_args = opt_strs("link-args");
+    let link_args = if link_args.is_empty() { None } else { Some(link_args.connect(" ")) };
+    let cfgs = opt_strs("cfg");
+    let levels = [("0", session::No), ("1", session::Less),
+                  ("2", session::Default), ("3", session::Aggressive)];
+    let opt_level = if !matches.opt_defined("opt-level") {
+        session::No
+    } else {
+        match matches.opt_enum("opt-level", levels) {
+            result::Ok(Some(level)) => level,
+            result::Ok(None) if opt_present("O") => session::Default,
+            result::Ok(None) => session::No,
+            result::Err(f) => {
+                error(f.to_err_msg());
+                command_usage(cmd);
+                return BAD_FLAG_CODE;
+            }
         }
     };
 
-    let save_temps = matches.opt_present("save-temps");
-    let target     = matches.opt_str("target");
-    let target_cpu = matches.opt_str("target-cpu");
+    let save_temps = opt_present("save-temps");
+    let target     = opt_str("target");
+    let target_cpu = opt_str("target-cpu");
+    let linkage    = opt_strs("linkage");
     let experimental_features = {
-        let strs = matches.opt_strs("Z");
-        if matches.opt_present("Z") {
+        let strs = opt_strs("Z");
+        if opt_present("Z") {
             Some(strs)
         }
         else {
@@ -750,14 +1301,6 @@ pub fn main_args(args: &[~str]) -> int {
         }
     };
 
-    let mut args = matches.free.clone();
-    args.shift();
-
-    if (args.len() < 1) {
-        usage::general();
-        return 1;
-    }
-
     let rustc_flags = RustcFlags {
         linker: linker,
         link_args: link_args,
@@ -766,8 +1309,8 @@ pub fn main_args(args: &[~str]) -> int {
             Trans
         } else if no_link {
             Link
-        } else if pretty {
-            Pretty
+        } else if pretty.is_some() {
+            Pretty(pretty.unwrap())
         } else if parse_only {
             Analysis
         } else if emit_llvm && generate_asm {
@@ -782,91 +1325,427 @@ pub fn main_args(args: &[~str]) -> int {
         save_temps: save_temps,
         target: target,
         target_cpu: target_cpu,
+        linkage: linkage,
+        cap_lints: None,
         experimental_features: experimental_features
     };
 
-    let mut cmd_opt = None;
-    for a in args.iter() {
-        if util::is_cmd(*a) {
-            cmd_opt = Some(a);
-            break;
-        }
+    let remaining_args = matches.free.clone();
+    // Only `build` and `install` take `<pkgid> as <name>`
+    if (cmd.as_slice() == "build" || cmd.as_slice() == "install") &&
+       remaining_args.len() >= 2 && remaining_args[1].as_slice() == "as" &&
+       (remaining_args.len() < 3 || remaining_args[2].is_empty()) {
+        error(format!("expected a name after `{} as`", remaining_args[0]));
+        command_usage(cmd);
+        return BAD_FLAG_CODE;
     }
-    let cmd = match cmd_opt {
-        None => {
-            usage::general();
-            return 0;
-        }
-        Some(cmd) => {
-            let bad_option = context::flags_forbidden_for_cmd(&rustc_flags,
-                                                              cfgs,
-                                                              *cmd,
-                                                              user_supplied_opt_level);
-            if help || bad_option {
-                match *cmd {
-                    ~"build" => usage::build(),
-                    ~"clean" => usage::clean(),
-                    ~"do" => usage::do_cmd(),
-                    ~"info" => usage::info(),
-                    ~"install" => usage::install(),
-                    ~"list"    => usage::list(),
-                    ~"prefer" => usage::prefer(),
-                    ~"test" => usage::test(),
-                    ~"init" => usage::init(),
-                    ~"uninstall" => usage::uninstall(),
-                    ~"unprefer" => usage::unprefer(),
-                    _ => usage::general()
-                };
-                if bad_option {
-                    return BAD_FLAG_CODE;
-                }
-                else {
-                    return 0;
-                }
-            } else {
-                cmd
-            }
-        }
-    };
-
-    // Pop off all flags, plus the command
-    let remaining_args = args.iter().skip_while(|s| !util::is_cmd(**s));
-    // I had to add this type annotation to get the code to typecheck
-    let mut remaining_args: ~[~str] = remaining_args.map(|s| (*s).clone()).collect();
-    remaining_args.shift();
+    let sysroot_supplied = supplied_sysroot.is_some();
     let sroot = match supplied_sysroot {
-        Some(getopts::Val(s)) => Path(s),
-        _ => filesearch::get_or_default_sysroot()
+        Some(s) => if s.is_absolute { s } else { cwd.push_rel(&s) },
+        None => filesearch::get_or_default_sysroot()
     };
 
     debug2!("Using sysroot: {}", sroot.to_str());
-    debug2!("Will store workcache in {}", default_workspace().to_str());
+
+    let context = Context {
+        cfgs: cfgs,
+        rustc_flags: rustc_flags,
+        use_rust_path_hack: use_rust_path_hack,
+        symlink_executables: symlink_executables,
+        install_docs: install_docs,
+        offline: offline,
+        force_uninstall: force_uninstall,
+        verbose: verbose,
+        timeout: timeout,
+        sysroot: sroot.clone(), // Currently, only tests override this
+        cwd: cwd.clone(),
+        env: env.to_owned(),
+        rust_path: rust_path_in(cwd, env),
+        locked_workspaces: RWArc::new(~[])
+    };
+
+    // None of printing the environment, versions or completions and
+    // updating rustpkg involves any packages
+    if cmd.as_slice() == "env" {
+        print_env(&context, sysroot_supplied);
+        return 0;
+    }
+    if cmd.as_slice() == "version" {
+        print_versions(&context, opt_present("json"));
+        return 0;
+    }
+    if cmd.as_slice() == "completions" {
+        let script = if matches.free.len() == 1 {
+            completions::script(matches.free[0].as_slice(), global_opts(), commands())
+        } else {
+            None
+        };
+        return match script {
+            Some(s) => {
+                io::print(s);
+                0
+            }
+            None => {
+                command_usage("completions");
+                BAD_FLAG_CODE
+            }
+        };
+    }
+    if cmd.as_slice() == "self-update" {
+        let channel = match opt_str("channel").or_else(|| context.getenv("RUSTPKG_CHANNEL")) {
+            Some(c) => c,
+            None => {
+                error("no channel to update from; pass --channel or set RUSTPKG_CHANNEL");
+                return BAD_FLAG_CODE;
+            }
+        };
+        let sha256 = match opt_str("manifest-sha256")
+                               .or_else(|| context.getenv("RUSTPKG_CHANNEL_SHA256")) {
+            Some(h) => h,
+            None => {
+                error("the channel's manifest can't be trusted without its SHA-256; \
+                       pass --manifest-sha256 or set RUSTPKG_CHANNEL_SHA256");
+                return BAD_FLAG_CODE;
+            }
+        };
+        let root = if opt_present("user") { context.default_workspace() } else { sroot };
+        return update_self(channel.as_slice(), sha256.as_slice(), &root,
+                           opt_present("with-libs"));
+    }
+
+    debug2!("Will store workcache in {}", context.default_workspace().to_str());
 
     let rm_args = remaining_args.clone();
     let sub_cmd = cmd.clone();
     // Wrap the rest in task::try in case of a condition failure in a task
     let result = do task::try {
-        BuildContext {
-            context: Context {
-                cfgs: cfgs.clone(),
-                rustc_flags: rustc_flags.clone(),
-                use_rust_path_hack: use_rust_path_hack,
-                sysroot: sroot.clone(), // Currently, only tests override this
-            },
-            workcache_context: api::default_context(default_workspace()).workcache_context
-        }.run(sub_cmd, rm_args.clone())
+        let workcache_context =
+            api::default_context(context.default_workspace()).workcache_context;
+        if explain {
+            workcache_context.record_explanations();
+        }
+        let status = BuildContext {
+            context: context.clone(),
+            workcache_context: workcache_context.clone()
+        }.run(sub_cmd, rm_args.clone());
+        if explain {
+            for e in workcache_context.explanations().iter() {
+                note(workcache_support::describe_explanation(e));
+            }
+            note(workcache_context.stats().to_str());
+        }
+        status
     };
     // FIXME #9262: This is using the same error code for all errors,
     // and at least one test case succeeds if rustpkg returns COPY_FAILED_CODE,
     // when actually, it might set the exit code for that even if a different
     // unhandled condition got raised.
-    if result.is_err() { return COPY_FAILED_CODE; }
-    return 0;
+    match result {
+        Ok(status) => status,
+        Err(()) => COPY_FAILED_CODE
+    }
+}
+
+/// Prints the settings rustpkg works with, and where each came from
+fn print_env(cx: &Context, sysroot_supplied: bool) {
+    use workspace::is_workspace;
+
+    let exists = |p: &Path| if os::path_is_dir(p) { "exists" } else { "doesn't exist" };
+
+    // Relative entries are taken relative to the working directory, as
+    // rust_path_in takes them
+    let from_env = cx.getenv("RUST_PATH").map_default(~[], |rp| {
+        rp.split_iter(if cfg!(windows) { ';' } else { ':' }).map(|s| {
+            let p = Path(s);
+            if p.is_absolute { p } else { cx.cwd.push_rel(&p) }
+        }).collect()
+    });
+    println("RUST_PATH:");
+    for p in cx.rust_path.iter() {
+        let source = if from_env.contains(p) { "from $RUST_PATH" } else { "default" };
+        println!("    {} ({}, {})", p.to_str(), source, exists(p));
+    }
+
+    println!("sysroot: {} ({})", cx.sysroot.to_str(),
+             if sysroot_supplied { "from --sysroot" } else { "where rustpkg is" });
+
+    // Where a package found from here would be installed to
+    let (source, how) = match cwd_to_workspace(cx) {
+        Some((ws, _)) => (ws, "the package in the current directory"),
+        None if is_workspace(&cx.cwd) => (cx.cwd.clone(), "packages in the current directory"),
+        None => (cx.default_workspace(), "packages found elsewhere")
+    };
+    let destination = determine_destination(cx, &source);
+    println!("destination workspace: {} (for {})", destination.to_str(), how);
+    println!("rust-path-hack: {}", if cx.use_rust_path_hack { "on" } else { "off" });
+
+    if cx.cfgs.is_empty() {
+        println("cfgs: (none)");
+    } else {
+        println!("cfgs: {}", cx.cfgs.connect(" "));
+    }
+
+    // rustpkg has no configuration file; these are the variables it reads
+    println("settings:");
+    for var in ["RUST_PATH", "RUSTPKG_CHANNEL", "RUSTPKG_CHANNEL_SHA256"].iter() {
+        match cx.getenv(*var) {
+            Some(v) => println!("    {} = {} (environment)", *var, v),
+            None => println!("    {} is unset", *var)
+        }
+    }
+}
+
+/// Takes the version from `line`, the first line of the output of
+/// `argv0 --version`. That's "<argv0> <version>", where rustc prints the
+/// path it was run as, which can have spaces in it, and the version can
+/// too.
+fn rustc_version_from(line: &str, argv0: &str) -> ~str {
+    if line.starts_with(argv0) && line.slice_from(argv0.len()).starts_with(" ") {
+        return line.slice_from(argv0.len() + 1).to_owned();
+    }
+    // Otherwise rustc was run as something else, but at least its name
+    // can't have spaces in it
+    match line.find(' ') {
+        Some(i) => line.slice_from(i + 1).to_owned(),
+        None => line.to_owned()
+    }
+}
+
+/// Prints the versions of rustpkg and of the rustc in `cx`'s sysroot, and
+/// the host triple, for bug reports and build logs
+fn print_versions(cx: &Context, as_json: bool) {
+    use extra::json;
+    use extra::treemap::TreeMap;
+
+    let version = option_env!("CFG_VERSION").unwrap_or("unknown version");
+    let commit = option_env!("CFG_VER_HASH");
+    let sysroot = cx.sysroot_to_use();
+    let rustc = sysroot.push_many([~"bin", "rustc" + os::EXE_SUFFIX]);
+    let rustc_version = if os::path_exists(&rustc) {
+        match process_output_in(cx, rustc.to_str(), [~"--version"]) {
+            Ok(output) => {
+                let output = str::from_utf8(output.output);
+                output.line_iter().next().map(|l| rustc_version_from(l, rustc.to_str()))
+            }
+            Err(e) => {
+                warn(e);
+                None
+            }
+        }
+    } else {
+        None
+    };
+    let host = driver::host_triple();
+
+    if as_json {
+        let to_json = |s: Option<&str>| match s {
+            Some(s) => json::String(s.to_owned()),
+            None => json::Null
+        };
+        let mut obj = ~TreeMap::new();
+        obj.insert(~"rustpkg", json::String(version.to_owned()));
+        obj.insert(~"commit", to_json(commit));
+        obj.insert(~"rustc", to_json(rustc_version.as_ref().map(|v| v.as_slice())));
+        obj.insert(~"sysroot", json::String(sysroot.to_str()));
+        obj.insert(~"host", json::String(host));
+        println(json::Object(obj).to_pretty_str());
+    } else {
+        println!("rustpkg {}", version);
+        for c in commit.iter() {
+            println!("commit: {}", *c);
+        }
+        match rustc_version {
+            Some(v) => println!("rustc: {}", v),
+            None => println!("rustc: not found in {}", sysroot.to_str())
+        }
+        println!("sysroot: {}", sysroot.to_str());
+        println!("host: {}", host);
+    }
+}
+
+/// Installs a newer rustpkg, and the standard libraries if `with_libs`,
+/// from the channel `channel`, whose manifest has the SHA-256
+/// `manifest_sha256`, into `root`
+fn update_self(channel: &str, manifest_sha256: &str, root: &Path, with_libs: bool) -> int {
+    use self_update::{self_update, RUSTPKG_COMPONENT, STD_COMPONENT, Updated, UpToDate};
+
+    let components = if with_libs {
+        ~[RUSTPKG_COMPONENT, STD_COMPONENT]
+    } else {
+        ~[RUSTPKG_COMPONENT]
+    };
+    let current = option_env!("CFG_VERSION").unwrap_or("unknown version");
+    match self_update(channel, manifest_sha256, root, components, current) {
+        Ok(Updated(v)) => {
+            note(format!("Updated to {} in {}", v, root.to_str()));
+            0
+        }
+        Ok(UpToDate(v)) => {
+            note(format!("Already up to date; the newest version in {} is {}", channel, v));
+            0
+        }
+        Err(e) => {
+            error(e);
+            UPDATE_FAILED_CODE
+        }
+    }
+}
+
+/// Runs rustdoc on the library of the package in `pkg_src`, which has just
+/// been built, putting the documentation in the package's build directory
+/// for `install_no_build` to install. Failing to generate it is only warned
+/// about, so as not to stop the package being installed.
+fn generate_docs(cx: &BuildContext, pkg_src: &PkgSrc) {
+    let crate = match pkg_src.libs.iter().next() {
+        Some(crate) => crate.clone(),
+        None => return
+    };
+    let rustdoc = cx.sysroot_to_use().push_many([~"bin", "rustdoc" + os::EXE_SUFFIX]);
+    if !os::path_exists(&rustdoc) {
+        warn(format!("Not generating documentation for {}: there's no rustdoc at {}",
+                     pkg_src.id.to_str(), rustdoc.to_str()));
+        return;
+    }
+    let out_dir = built_doc_dir(&pkg_src.id, pkg_src.build_workspace());
+    // Documentation for items the package no longer has mustn't linger
+    if os::path_exists(&out_dir) && !os::remove_dir_recursive(&out_dir) {
+        warn(format!("Couldn't remove the old documentation in {}", out_dir.to_str()));
+    }
+    // Links to other packages' documentation are relative, so the docs are
+    // rendered next to where they'll be installed, at the same depth, and
+    // only then moved into the build directory
+    let target_docs = target_doc_dir(&pkg_src.id, &pkg_src.destination_workspace);
+    let render_dir = target_docs.with_filename(
+        format!(".{}.rustpkg-doc", target_docs.filename().unwrap_or(~"")));
+    if os::path_exists(&render_dir) {
+        os::remove_dir_recursive(&render_dir);
+    }
+    // rustdoc only creates the output directory itself
+    os::mkdir_recursive(&render_dir.pop(), U_RWX);
+    os::mkdir_recursive(&out_dir.pop(), U_RWX);
+    let mut args = ~[pkg_src.start_dir.push_rel(&crate.file).to_str(),
+                     ~"-o", render_dir.to_str()];
+    // The library's dependencies are installed in the RUST_PATH
+    let workspaces = cx.context.rust_path.as_slice();
+    for ws in workspaces.iter() {
+        args.push(~"-L");
+        args.push(rust_path_lib_dir(ws, driver::host_triple()).to_str());
+    }
+    // and so is their documentation, if any, which is linked to. Each
+    // package's doc directory is a root holding the docs of its crates.
+    let mut doc_roots = ~[];
+    do installed_packages::list_installed_packages(workspaces) |id| {
+        for ws in workspaces.iter() {
+            let root = target_doc_dir(id, ws);
+            if root != target_docs && os::path_is_dir(&root) && !doc_roots.contains(&root) {
+                doc_roots.push(root);
+            }
+        }
+        true
+    };
+    for root in doc_roots.iter() {
+        args.push(~"--doc-root");
+        args.push(root.to_str());
+    }
+    debug2!("Running {} {}", rustdoc.to_str(), args.connect(" "));
+    let output = match process_output_in(&cx.context, rustdoc.to_str(), args) {
+        Ok(output) => output,
+        Err(e) => {
+            warn(format!("Couldn't generate documentation for {}: {}", pkg_src.id.to_str(), e));
+            return;
+        }
+    };
+    if output.status != 0 {
+        warn(format!("Couldn't generate documentation for {}:\n{}{}", pkg_src.id.to_str(),
+                     str::from_utf8(output.output), str::from_utf8(output.error)));
+    } else {
+        match os::copy_dir_recursive(&render_dir, &out_dir, os::CopyOptions::new()) {
+            Ok(()) => (),
+            Err(errors) => {
+                for &(ref p, ref e) in errors.iter() {
+                    warn(format!("Couldn't copy {}: {}", p.to_str(), *e));
+                }
+                // Rather than install half of it
+                os::remove_dir_recursive(&out_dir);
+            }
+        }
+    }
+    os::remove_dir_recursive(&render_dir);
+}
+
+/// Removes the files recorded in the install receipt for `id` in
+/// `workspace`, except for those changed since they were installed
+fn remove_previous_install(workspace: &Path, id: &PkgId) {
+    match receipt::read_receipt(workspace, &id.path) {
+        Ok(Some(previous)) => {
+            let modified = previous.modified_files();
+            for p in modified.iter() {
+                warn(format!("{} has changed since {} was installed; leaving it",
+                             p.to_str(), previous.id));
+            }
+            for p in previous.remove_files(modified).iter() {
+                warn(format!("Couldn't remove {}", p.to_str()));
+            }
+            path_util::remove_doc_dir(id, workspace);
+        }
+        Ok(None) => (),
+        Err(e) => warn(e)
+    }
+}
+
+/// Uninstalls the package `pkgid` from `workspace`. If it was installed
+/// with a receipt, exactly the files it lists are removed, and none of them
+/// unless they're unchanged or `force` is true.
+fn uninstall_from(workspace: &Path, pkgid: &PkgId, force: bool) {
+    let installed = match receipt::read_receipt(workspace, &pkgid.path) {
+        Ok(Some(installed)) => installed,
+        Ok(None) => return path_util::uninstall_package_from(workspace, pkgid),
+        Err(e) => {
+            warn(e);
+            return path_util::uninstall_package_from(workspace, pkgid);
+        }
+    };
+    let modified = installed.modified_files();
+    if !modified.is_empty() && !force {
+        for p in modified.iter() {
+            error(format!("{} has changed since it was installed", p.to_str()));
+        }
+        fail2!("Not uninstalling {}; use --force to remove changed files too",
+               pkgid.to_str());
+    }
+    for p in installed.remove_files([]).iter() {
+        warn(format!("Couldn't remove {}", p.to_str()));
+    }
+    path_util::remove_doc_dir(pkgid, workspace);
+    if !receipt::remove_receipt(workspace, &pkgid.path) {
+        warn(format!("Couldn't remove the install receipt for {}", pkgid.to_str()));
+    }
+}
+
+/// Installs the executable `exec` as `target`: as a symlink to it if
+/// `symlink` is true and the platform allows it, and as a copy otherwise.
+/// Whatever was installed as `target` before is replaced.
+fn install_executable(exec: &Path, target: &Path, symlink: bool) -> bool {
+    // Neither a symlink nor a copy can be made over an existing symlink
+    // without following it, into the build directory
+    if os::path_is_symlink(target) || (symlink && os::path_exists(target)) {
+        if !os::remove_file(target) {
+            return false;
+        }
+    }
+    if symlink {
+        if os::symlink(&os::make_absolute(exec), target) {
+            return true;
+        }
+        debug2!("Couldn't symlink {} to {}; copying it instead",
+                target.to_str(), exec.to_str());
+    }
+    os::copy_file(exec, target)
 }
 
 fn declare_package_script_dependency(prep: &mut workcache::Prep, pkg_src: &PkgSrc) {
     match pkg_src.package_script_option() {
-        Some(ref p) => prep.declare_input("file", p.to_str(),
+        Some(ref p) => prep.declare_input("file", p.normalize().to_str(),
                                       workcache_support::digest_file_with_date(p)),
         None => ()
     }
@@ -0,0 +1,429 @@
+// Copyright 2013 The Rust Project Developers. See the COPYRIGHT
+// file at the top-level directory of this distribution and at
+// http://rust-lang.org/COPYRIGHT.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+/*!
+ * `rustpkg self-update`: installing a newer rustpkg, and optionally the
+ * standard libraries, from a channel.
+ *
+ * A channel is a directory, or a repository that rustpkg can clone, that
+ * holds a `channel.json` manifest next to the files it lists:
+ *
+ *     {
+ *         "version": "0.9",
+ *         "components": {
+ *             "rustpkg": [{"path": "bin/rustpkg", "sha1": "..."},
+ *                         {"path": "lib/librustpkg-...-0.9.so", "sha1": "..."}],
+ *             "std": [{"path": "lib/rustc/.../lib/libstd-...-0.9.so",
+ *                      "sha1": "..."}]
+ *         }
+ *     }
+ *
+ * Each path is relative both to the channel and to the root being updated,
+ * which is the sysroot or a workspace.
+ *
+ * The manifest itself isn't trusted: it comes from wherever the channel
+ * does, so whoever can change the files can change their hashes too. It's
+ * pinned instead by its SHA-256, which the user gets from the channel's
+ * maintainers by some trusted means, such as their HTTPS site, and passes
+ * with `--manifest-sha256`. The manifest must match that, and every file
+ * its hash, before any of them is installed.
+ *
+ * Installing is all or nothing. Every file is first written next to its
+ * destination, and only once they all are are they renamed into place.
+ * If a rename fails, those already done are undone.
+ */
+
+use std::{cmp, io, os, str};
+use extra::json;
+use extra::sha1::Sha1;
+use extra::sha2::Sha256;
+use extra::digest::Digest;
+use extra::tempfile::TempDir;
+use path_util::{U_RWX, set_mode};
+use source_control::vcs_for_source;
+
+static MANIFEST: &'static str = "channel.json";
+
+/// The component that's always installed
+pub static RUSTPKG_COMPONENT: &'static str = "rustpkg";
+/// The component installed with --with-libs
+pub static STD_COMPONENT: &'static str = "std";
+
+#[deriving(Clone)]
+pub struct ChannelFile {
+    path: ~str,
+    sha1: ~str
+}
+
+#[deriving(Clone)]
+pub struct Manifest {
+    version: ~str,
+    components: ~[(~str, ~[ChannelFile])]
+}
+
+impl Manifest {
+    fn component<'a>(&'a self, name: &str) -> Option<&'a [ChannelFile]> {
+        for &(ref n, ref files) in self.components.iter() {
+            if name == n.as_slice() {
+                return Some(files.as_slice());
+            }
+        }
+        None
+    }
+}
+
+pub enum UpdateResult {
+    // Installed the given version
+    Updated(~str),
+    // The channel's version, which isn't newer than this one
+    UpToDate(~str)
+}
+
+/// Installs the components `components` from the channel `channel` into
+/// `root`, if the channel has a newer version than `current_version`. The
+/// channel's manifest must have the SHA-256 `manifest_sha256`.
+pub fn self_update(channel: &str, manifest_sha256: &str, root: &Path, components: &[&str],
+                   current_version: &str) -> Result<UpdateResult, ~str> {
+    // Kept until the update is done, when it's removed
+    let mut _scratch = None;
+    let channel_dir = if os::path_is_dir(&Path(channel)) {
+        Path(channel)
+    } else {
+        let scratch = match TempDir::new("rustpkg-channel") {
+            Some(d) => d,
+            None => return Err(~"couldn't create a temporary directory to fetch into")
+        };
+        let dir = scratch.path().push("channel");
+        match vcs_for_source(channel).clone_repo(channel, &dir) {
+            Ok(()) => (),
+            Err(e) => return Err(format!("couldn't fetch channel {}: {}", channel, e))
+        }
+        _scratch = Some(scratch);
+        dir
+    };
+
+    let manifest = match io::read_whole_file(&channel_dir.push(MANIFEST)) {
+        Ok(bytes) => {
+            // Nothing in the manifest means anything until it's known to
+            // be the one its maintainers published
+            let mut sha = Sha256::new();
+            sha.input(bytes);
+            if sha.result_str().as_slice() != manifest_sha256 {
+                return Err(format!("the manifest of channel {} doesn't have the SHA-256 {}",
+                                   channel, manifest_sha256));
+            }
+            match str::from_utf8_opt(bytes) {
+                Some(s) => match parse_manifest(s) {
+                    Ok(m) => m,
+                    Err(e) => return Err(format!("invalid manifest in channel {}: {}",
+                                                 channel, e))
+                },
+                None => return Err(format!("invalid manifest in channel {}: not UTF-8",
+                                           channel))
+            }
+        }
+        Err(e) => return Err(format!("couldn't read the manifest of channel {}: {}",
+                                     channel, e))
+    };
+
+    match is_newer(manifest.version, current_version) {
+        Ok(true) => (),
+        Ok(false) => return Ok(UpToDate(manifest.version.clone())),
+        Err(e) => return Err(format!("{} in channel {}", e, channel))
+    }
+
+    let mut files = ~[];
+    for name in components.iter() {
+        match manifest.component(*name) {
+            Some(fs) => files.push_all(fs),
+            None => return Err(format!("channel {} has no component `{}`", channel, *name))
+        }
+    }
+
+    // Nothing is installed unless everything checks out. The contents are
+    // kept, and it's those that are installed, rather than the files in the
+    // channel, which could have changed since they were checked.
+    let mut verified = ~[];
+    for f in files.iter() {
+        let p = channel_dir.push_rel(&Path(f.path));
+        let contents = match io::read_whole_file(&p) {
+            Ok(contents) => contents,
+            Err(_) => return Err(format!("couldn't read {} in channel {}", f.path, channel))
+        };
+        let mut sha = Sha1::new();
+        sha.input(contents);
+        if sha.result_str() != f.sha1 {
+            return Err(format!("checksum mismatch for {} in channel {}", f.path, channel));
+        }
+        let mode = match p.get_mode() {
+            Some(mode) => mode,
+            None => return Err(format!("couldn't read {} in channel {}", f.path, channel))
+        };
+        verified.push((f.path.clone(), contents, mode));
+    }
+
+    let mut staged = ~[];
+    for &(ref path, ref contents, mode) in verified.iter() {
+        let to = root.push_rel(&Path(*path));
+        debug2!("self-update: staging {}", to.to_str());
+        match stage_file(*contents, mode, &to) {
+            Some(tmp) => staged.push((tmp, to)),
+            None => {
+                for &(ref tmp, _) in staged.iter() {
+                    os::remove_file(tmp);
+                }
+                return Err(format!("couldn't write {}", to.to_str()));
+            }
+        }
+    }
+    match commit_staged(staged) {
+        Ok(()) => Ok(Updated(manifest.version.clone())),
+        Err(to) => Err(format!("couldn't install {}; nothing was changed", to.to_str()))
+    }
+}
+
+/// Whether the channel's version `channel` is newer than the running
+/// version `current`, which may be followed by the commit it was built
+/// from. A release is newer than its prereleases, so 0.9 is newer than
+/// 0.9-pre, but 0.9-pre isn't newer than 0.9. A version that doesn't parse
+/// is an error, so that it can't be used to install an older rustpkg.
+fn is_newer(channel: &str, current: &str) -> Result<bool, ~str> {
+    let current = current.split_iter(' ').next().unwrap_or("");
+    match (Release::parse(channel), Release::parse(current)) {
+        (Some(c), Some(r)) => Ok(c > r),
+        (None, _) => Err(format!("unrecognized version `{}`", channel)),
+        (_, None) => Err(format!("can't compare with this rustpkg's version `{}`", current))
+    }
+}
+
+// A version such as 0.9 or 0.9-pre: its numbers, and its prerelease tag
+#[deriving(Eq)]
+struct Release {
+    numbers: ~[uint],
+    pre: Option<~str>
+}
+
+impl Release {
+    fn parse(s: &str) -> Option<Release> {
+        let (numbers, pre) = match s.find('-') {
+            Some(i) if i + 1 < s.len() => {
+                (s.slice_to(i), Some(s.slice_from(i + 1).to_owned()))
+            }
+            Some(_) => return None,
+            None => (s, None)
+        };
+        let mut parsed = ~[];
+        for n in numbers.split_iter('.') {
+            match from_str::<uint>(n) {
+                Some(n) => parsed.push(n),
+                None => return None
+            }
+        }
+        Some(Release { numbers: parsed, pre: pre })
+    }
+}
+
+impl Ord for Release {
+    fn lt(&self, other: &Release) -> bool {
+        // Missing numbers count as zeroes, so 0.9 and 0.9.0 are the same
+        let len = cmp::max(self.numbers.len(), other.numbers.len());
+        for i in range(0, len) {
+            let a = if i < self.numbers.len() { self.numbers[i] } else { 0 };
+            let b = if i < other.numbers.len() { other.numbers[i] } else { 0 };
+            if a != b {
+                return a < b;
+            }
+        }
+        match (&self.pre, &other.pre) {
+            (&Some(ref a), &Some(ref b)) => a < b,
+            (&Some(_), &None) => true,
+            (&None, _) => false
+        }
+    }
+}
+
+// The file next to `to` that `to` is written to, or moved to, with `suffix`
+fn sibling(to: &Path, suffix: &str) -> Path {
+    to.with_filename(format!(".{}.{}", to.filename().unwrap_or(~""), suffix))
+}
+
+// Writes `contents` to a temporary file next to `to`, which installing
+// renames over `to`, so that a running executable is replaced rather than
+// written over. Returns the temporary file.
+fn stage_file(contents: &[u8], mode: uint, to: &Path) -> Option<Path> {
+    let tmp = sibling(to, "new");
+    if !os::path_is_dir(&to.dir_path()) && !os::mkdir_recursive(&to.dir_path(), U_RWX) {
+        return None;
+    }
+    match io::file_writer(&tmp, [io::Create, io::Truncate]) {
+        Ok(w) => w.write(contents),
+        Err(_) => return None
+    }
+    if set_mode(&tmp, mode) {
+        Some(tmp)
+    } else {
+        os::remove_file(&tmp);
+        None
+    }
+}
+
+// Renames each staged file over its destination, first moving aside any
+// file that's there. If a rename fails, the files already installed are
+// put back as they were and the destination that failed is returned.
+fn commit_staged(staged: &[(Path, Path)]) -> Result<(), Path> {
+    // The destinations done so far, with where their old files were moved
+    let mut done: ~[(&Path, Option<Path>)] = ~[];
+    let mut failed = None;
+    for &(ref tmp, ref to) in staged.iter() {
+        let old = if os::path_exists(to) {
+            let old = sibling(to, "old");
+            // Left from an earlier update, if it couldn't be removed then
+            if os::path_exists(&old) {
+                os::remove_file(&old);
+            }
+            if !os::rename_file(to, &old) {
+                failed = Some(to.clone());
+                break;
+            }
+            Some(old)
+        } else {
+            None
+        };
+        if !os::rename_file(tmp, to) {
+            // Put this one back before the rest
+            for old in old.iter() {
+                os::rename_file(old, to);
+            }
+            failed = Some(to.clone());
+            break;
+        }
+        done.push((to, old));
+    }
+
+    match failed {
+        None => {
+            // A running executable can't always be removed, on Windows, so
+            // an old file that's left behind isn't an error
+            for &(_, ref old) in done.iter() {
+                for old in old.iter() {
+                    os::remove_file(old);
+                }
+            }
+            Ok(())
+        }
+        Some(to) => {
+            for &(ref to, ref old) in done.rev_iter() {
+                match *old {
+                    Some(ref old) => { os::rename_file(old, *to); }
+                    None => { os::remove_file(*to); }
+                }
+            }
+            for &(ref tmp, _) in staged.iter() {
+                if os::path_exists(tmp) {
+                    os::remove_file(tmp);
+                }
+            }
+            Err(to)
+        }
+    }
+}
+
+/// Parses a channel manifest from its JSON text
+pub fn parse_manifest(s: &str) -> Result<Manifest, ~str> {
+    let obj = match json::from_str(s) {
+        Ok(json::Object(obj)) => obj,
+        Ok(_) => return Err(~"expected a JSON object"),
+        Err(e) => return Err(e.to_str())
+    };
+    let version = match obj.find(&~"version") {
+        Some(&json::String(ref v)) => v.clone(),
+        _ => return Err(~"`version` should be a string")
+    };
+    let components = match obj.find(&~"components") {
+        Some(&json::Object(ref cs)) => cs,
+        _ => return Err(~"`components` should be an object")
+    };
+    let mut result = ~[];
+    for (name, files) in components.iter() {
+        let files = match *files {
+            json::List(ref files) => files,
+            _ => return Err(format!("component `{}` should be a list of files", *name))
+        };
+        let mut component = ~[];
+        for file in files.iter() {
+            match parse_file(file) {
+                Some(f) => component.push(f),
+                None => return Err(format!("each file in component `{}` should have \
+                                            a `path` and a `sha1`", *name))
+            }
+        }
+        result.push((name.clone(), component));
+    }
+    Ok(Manifest { version: version, components: result })
+}
+
+fn parse_file(file: &json::Json) -> Option<ChannelFile> {
+    let obj = match *file {
+        json::Object(ref obj) => obj,
+        _ => return None
+    };
+    match (obj.find(&~"path"), obj.find(&~"sha1")) {
+        (Some(&json::String(ref path)), Some(&json::String(ref sha1))) => {
+            // Files go under the root being updated, nowhere else
+            let p = Path(*path);
+            if p.is_absolute() || p.components.iter().any(|c| ".." == *c) {
+                return None;
+            }
+            Some(ChannelFile { path: path.clone(), sha1: sha1.clone() })
+        }
+        _ => None
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{is_newer, parse_manifest};
+
+    #[test]
+    fn test_is_newer() {
+        assert_eq!(is_newer("0.9", "0.8 (abcdef 2013-10-01)"), Ok(true));
+        assert_eq!(is_newer("0.10", "0.9"), Ok(true));
+        assert_eq!(is_newer("0.9", "0.9-pre (abcdef 2013-10-01)"), Ok(true));
+        assert_eq!(is_newer("0.10-pre", "0.9"), Ok(true));
+        assert_eq!(is_newer("0.9-rc1", "0.9-pre"), Ok(true));
+        assert_eq!(is_newer("0.9", "0.9"), Ok(false));
+        assert_eq!(is_newer("0.9.0", "0.9"), Ok(false));
+        assert_eq!(is_newer("0.8", "0.9"), Ok(false));
+        // A prerelease isn't newer than its release
+        assert_eq!(is_newer("0.9-pre", "0.9"), Ok(false));
+        // Versions that can't be compared aren't installed
+        assert!(is_newer("nightly", "0.9").is_err());
+        assert!(is_newer("0.9x", "0.9").is_err());
+        assert!(is_newer("0.9-", "0.9").is_err());
+        assert!(is_newer("0.9", "unknown version").is_err());
+    }
+
+    #[test]
+    fn test_parse_manifest() {
+        let m = parse_manifest("{\"version\": \"0.9\", \"components\": {
+                                  \"rustpkg\": [{\"path\": \"bin/rustpkg\",
+                                                 \"sha1\": \"abc\"}]}}").unwrap();
+        assert_eq!(m.version, ~"0.9");
+        let files = m.component("rustpkg").unwrap();
+        assert_eq!(files.len(), 1);
+        assert_eq!(files[0].path, ~"bin/rustpkg");
+        assert!(m.component("std").is_none());
+
+        assert!(parse_manifest("{\"version\": \"0.9\"}").is_err());
+        assert!(parse_manifest("{\"version\": \"0.9\", \"components\": {
+                                  \"rustpkg\": [{\"path\": \"../../bin/sh\",
+                                                 \"sha1\": \"abc\"}]}}").is_err());
+    }
+}
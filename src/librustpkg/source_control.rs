@@ -8,15 +8,118 @@
 // option. This file may not be copied, modified, or distributed
 // except according to those terms.
 
-// Utils for working with version control repositories. Just git right now.
+// Utils for working with version control repositories. Git is the only real
+// system; see `mock_vcs` for the repositories the test suite fakes.
 
-use std::{io, os, run, str};
-use std::run::{ProcessOutput, ProcessOptions, Process};
+use std::{io, os, str};
 use extra::tempfile::TempDir;
+use extra::url::Url;
 use version::*;
 use path_util::chmod_read_only;
+use util::process_output;
+#[cfg(test)]
+use mock_vcs::MockVcs;
 
-/// Attempts to clone `source`, a local git repository, into `target`, a local
+/// What rustpkg needs from a version control system
+pub trait VersionControl {
+    /// Whether `p` is the root of a checkout this system manages
+    fn is_repo(&self, p: &Path) -> bool;
+    /// Clones `source`, a local path or a URL, into `target`, which mustn't
+    /// exist yet
+    fn clone_repo(&self, source: &str, target: &Path) -> Result<(), ~str>;
+    /// Checks out the revision or tag `rev` in the checkout `repo`
+    fn checkout(&self, repo: &Path, rev: &str) -> Result<(), ~str>;
+    /// Brings the checkout `target` up to date with `source`
+    fn pull(&self, source: &Path, target: &Path) -> Result<(), ~str>;
+    /// The tags in the checkout `repo`, in order
+    fn tags(&self, repo: &Path) -> Result<~[~str], ~str>;
+}
+
+pub struct Git;
+
+impl VersionControl for Git {
+    fn is_repo(&self, p: &Path) -> bool {
+        is_git_dir(p)
+    }
+
+    fn clone_repo(&self, source: &str, target: &Path) -> Result<(), ~str> {
+        debug2!("Running: git clone {} {}", source, target.to_str());
+        run_git([~"clone", source.to_owned(), target.to_str()])
+    }
+
+    fn checkout(&self, repo: &Path, rev: &str) -> Result<(), ~str> {
+        debug2!("Running: git --work-tree={} --git-dir={} checkout {}",
+                repo.to_str(), repo.push(".git").to_str(), rev);
+        run_git([format!("--work-tree={}", repo.to_str()),
+                 format!("--git-dir={}", repo.push(".git").to_str()),
+                 ~"checkout", rev.to_owned()])
+    }
+
+    fn pull(&self, source: &Path, target: &Path) -> Result<(), ~str> {
+        debug2!("Running: git --work-tree={} --git-dir={} pull --no-edit {}",
+                target.to_str(), target.push(".git").to_str(), source.to_str());
+        run_git([format!("--work-tree={}", target.to_str()),
+                 format!("--git-dir={}", target.push(".git").to_str()),
+                 ~"pull", ~"--no-edit", source.to_str()])
+    }
+
+    fn tags(&self, repo: &Path) -> Result<~[~str], ~str> {
+        let git_dir = repo.push(".git");
+        let outp = match process_output("git", [format!("--git-dir={}", git_dir.to_str()),
+                                                ~"tag", ~"-l"]) {
+            Ok(outp) => outp,
+            Err(e) => return Err(e)
+        };
+        debug2!("git --git-dir={} tag -l ~~~> {:?}", git_dir.to_str(), outp.status);
+        if outp.status != 0 {
+            return Err(str::from_utf8(outp.error));
+        }
+        let output_text = str::from_utf8(outp.output);
+        Ok(output_text.line_iter().filter(|l| !l.is_whitespace())
+                      .map(|l| l.to_owned()).collect())
+    }
+}
+
+// Runs git with `args`, returning its output if it fails
+fn run_git(args: &[~str]) -> Result<(), ~str> {
+    let outp = match process_output("git", args) {
+        Ok(outp) => outp,
+        Err(e) => return Err(e)
+    };
+    if outp.status == 0 {
+        Ok(())
+    } else {
+        Err(str::from_utf8(outp.output) + str::from_utf8(outp.error))
+    }
+}
+
+/// The version control system managing the checkout at `p`, if any
+pub fn vcs_at(p: &Path) -> Option<@VersionControl> {
+    match mock_vcs_at(p) {
+        Some(vcs) => Some(vcs),
+        None if Git.is_repo(p) => Some(@Git as @VersionControl),
+        None => None
+    }
+}
+
+// The test suite's fake repositories, which are only recognized in tests
+#[cfg(test)]
+fn mock_vcs_at(p: &Path) -> Option<@VersionControl> {
+    if MockVcs.is_repo(p) { Some(@MockVcs as @VersionControl) } else { None }
+}
+
+#[cfg(not(test))]
+fn mock_vcs_at(_p: &Path) -> Option<@VersionControl> {
+    None
+}
+
+/// The version control system to clone `source` with: whatever manages it,
+/// if it's a local checkout, and otherwise git
+pub fn vcs_for_source(source: &str) -> @VersionControl {
+    vcs_at(&Path(source)).unwrap_or(@Git as @VersionControl)
+}
+
+/// Attempts to clone `source`, a local repository, into `target`, a local
 /// directory that doesn't exist.
 /// Returns `DirToUse(p)` if the clone fails, where `p` is a newly created temporary
 /// directory (that the callee may use, for example, to check out remote sources into).
@@ -27,31 +130,22 @@ pub fn safe_git_clone(source: &Path, v: &Version, target: &Path) -> CloneResult
                 source.to_str(), target.to_str());
         // Ok to use target here; we know it will succeed
         assert!(os::path_is_dir(source));
-        assert!(is_git_dir(source));
+        let vcs = vcs_at(source).expect(format!("{} isn't a repository", source.to_str()));
 
         if !os::path_exists(target) {
-            debug2!("Running: git clone {} {}", source.to_str(), target.to_str());
-            let outp = run::process_output("git", [~"clone", source.to_str(), target.to_str()]);
-            if outp.status != 0 {
-                io::println(str::from_utf8_owned(outp.output.clone()));
-                io::println(str::from_utf8_owned(outp.error));
-                return DirToUse(target.clone());
-            }
-                else {
-                match v {
-                    &ExactRevision(ref s) => {
-                        debug2!("`Running: git --work-tree={} --git-dir={} checkout {}",
-                                *s, target.to_str(), target.push(".git").to_str());
-                        let outp = run::process_output("git",
-                            [format!("--work-tree={}", target.to_str()),
-                             format!("--git-dir={}", target.push(".git").to_str()),
-                             ~"checkout", format!("{}", *s)]);
-                        if outp.status != 0 {
-                            io::println(str::from_utf8_owned(outp.output.clone()));
-                            io::println(str::from_utf8_owned(outp.error));
+            match vcs.clone_repo(source.to_str(), target) {
+                Err(e) => {
+                    io::println(e);
+                    return DirToUse(target.clone());
+                }
+                Ok(()) => match v {
+                    &ExactRevision(ref s) => match vcs.checkout(target, s.as_slice()) {
+                        Err(e) => {
+                            io::println(e);
                             return DirToUse(target.clone());
                         }
-                    }
+                        Ok(()) => ()
+                    },
                     _ => ()
                 }
             }
@@ -59,13 +153,7 @@ pub fn safe_git_clone(source: &Path, v: &Version, target: &Path) -> CloneResult
             // Check that no version was specified. There's no reason to not handle the
             // case where a version was requested, but I haven't implemented it.
             assert!(*v == NoVersion);
-            debug2!("Running: git --work-tree={} --git-dir={} pull --no-edit {}",
-                    target.to_str(), target.push(".git").to_str(), source.to_str());
-            let args = [format!("--work-tree={}", target.to_str()),
-                        format!("--git-dir={}", target.push(".git").to_str()),
-                        ~"pull", ~"--no-edit", source.to_str()];
-            let outp = run::process_output("git", args);
-            assert!(outp.status == 0);
+            assert!(vcs.pull(source, target).is_ok());
         }
         CheckedOutSources
     } else {
@@ -88,46 +176,45 @@ pub enum CloneResult {
 
 pub fn make_read_only(target: &Path) {
     // Now, make all the files in the target dir read-only
-    do os::walk_dir(target) |p| {
-        if !os::path_is_dir(p) {
-            assert!(chmod_read_only(p));
-        };
-        true
-    };
+    for entry in os::walk_dir(target) {
+        if entry.kind == os::RegularFile {
+            assert!(chmod_read_only(&entry.path));
+        }
+    }
+}
+
+/// The address to give git for cloning from `url`. Git doesn't know about
+/// fragments; rustpkg uses them for the version, which is checked out
+/// separately.
+pub fn git_url(url: &Url) -> ~str {
+    let mut url = url.clone();
+    url.fragment = None;
+    url.to_str()
 }
 
 /// Source can be either a URL or a local file path.
 pub fn git_clone_url(source: &str, target: &Path, v: &Version) {
     use conditions::git_checkout_failed::cond;
 
-    let outp = run::process_output("git", [~"clone", source.to_str(), target.to_str()]);
-    if outp.status != 0 {
-         debug2!("{}", str::from_utf8_owned(outp.output.clone()));
-         debug2!("{}", str::from_utf8_owned(outp.error));
-         cond.raise((source.to_owned(), target.clone()))
-    }
-    else {
-        match v {
-            &ExactRevision(ref s) | &Tagged(ref s) => {
-                    let outp = process_output_in_cwd("git", [~"checkout", format!("{}", *s)],
-                                                         target);
-                    if outp.status != 0 {
-                        debug2!("{}", str::from_utf8_owned(outp.output.clone()));
-                        debug2!("{}", str::from_utf8_owned(outp.error));
-                        cond.raise((source.to_owned(), target.clone()))
-                    }
-            }
+    let vcs = vcs_for_source(source);
+    match vcs.clone_repo(source, target) {
+        Err(e) => {
+            debug2!("{}", e);
+            cond.raise((source.to_owned(), target.clone()))
+        }
+        Ok(()) => match v {
+            &ExactRevision(ref s) | &Tagged(ref s) => match vcs.checkout(target, s.as_slice()) {
+                Err(e) => {
+                    debug2!("{}", e);
+                    cond.raise((source.to_owned(), target.clone()))
+                }
+                Ok(()) => ()
+            },
             _ => ()
         }
     }
 }
 
-fn process_output_in_cwd(prog: &str, args: &[~str], cwd: &Path) -> ProcessOutput {
-    let mut prog = Process::new(prog, args, ProcessOptions{ dir: Some(cwd)
-                                ,..ProcessOptions::new()});
-    prog.finish_with_output()
-}
-
 pub fn is_git_dir(p: &Path) -> bool {
     os::path_is_dir(&p.push(".git"))
 }
@@ -10,9 +10,10 @@
 
 // rustpkg unit tests
 
-use context::{BuildContext, Context, RustcFlags};
+use context::{BuildContext, Context, RustcFlags, rust_path_in};
 use std::{io, os, run, str, task};
 use extra::arc::Arc;
+use extra::json;
 use extra::arc::RWArc;
 use extra::tempfile::TempDir;
 use extra::workcache;
@@ -23,12 +24,15 @@ use std::run::ProcessOutput;
 use installed_packages::list_installed_packages;
 use package_id::{PkgId};
 use version::{ExactRevision, NoVersion, Version, Tagged};
+use receipt::{read_receipt, receipt_path};
+use workspace::{workspace_lock_file, lock_workspaces};
 use path_util::{target_executable_in_workspace, target_test_in_workspace,
                target_bench_in_workspace, make_dir_rwx, U_RWX,
                library_in_workspace, installed_library_in_workspace,
                built_bench_in_workspace, built_test_in_workspace,
                built_library_in_workspace, built_executable_in_workspace, target_build_dir,
-               chmod_read_only};
+               built_unlinked_output_in_workspace, chmod_read_only, is_executable,
+               target_doc_dir};
 use rustc::back::link::get_cc_prog;
 use rustc::metadata::filesearch::rust_path;
 use rustc::driver::driver::{build_session, build_session_options, host_triple, optgroups};
@@ -36,12 +40,14 @@ use syntax::diagnostic;
 use target::*;
 use package_source::PkgSrc;
 use source_control::{CheckedOutSources, DirToUse, safe_git_clone};
-use exit_codes::{BAD_FLAG_CODE, COPY_FAILED_CODE};
+use mock_vcs;
+use exit_codes::{BAD_FLAG_CODE, COPY_FAILED_CODE, UPDATE_FAILED_CODE};
 use util::datestamp;
+use rustc_version_from;
 
-fn fake_ctxt(sysroot: Path, workspace: &Path) -> BuildContext {
+fn fake_ctxt(sysroot: Path) -> BuildContext {
     let context = workcache::Context::new(
-        RWArc::new(Database::new(workspace.push("rustpkg_db.json"))),
+        RWArc::new(Database::new_in_memory()),
         RWArc::new(Logger::new()),
         Arc::new(TreeMap::new()));
     BuildContext {
@@ -51,7 +57,17 @@ fn fake_ctxt(sysroot: Path, workspace: &Path) -> BuildContext {
             rustc_flags: RustcFlags::default(),
 
             use_rust_path_hack: false,
-            sysroot: sysroot
+            symlink_executables: false,
+            install_docs: false,
+            offline: false,
+            force_uninstall: false,
+            verbose: false,
+            timeout: None,
+            sysroot: sysroot,
+            cwd: os::getcwd(),
+            env: ~[],
+            rust_path: rust_path(),
+            locked_workspaces: RWArc::new(~[])
         }
     }
 }
@@ -61,7 +77,8 @@ fn fake_pkg() -> PkgId {
     PkgId {
         path: Path(sn),
         short_name: sn,
-        version: NoVersion
+        version: NoVersion,
+        remote: None
     }
 }
 
@@ -69,7 +86,8 @@ fn git_repo_pkg() -> PkgId {
     PkgId {
         path: Path("mockgithub.com/catamorphism/test-pkg"),
         short_name: ~"test-pkg",
-        version: NoVersion
+        version: NoVersion,
+        remote: None
     }
 }
 
@@ -77,7 +95,8 @@ fn git_repo_pkg_with_tag(a_tag: ~str) -> PkgId {
     PkgId {
         path: Path("mockgithub.com/catamorphism/test-pkg"),
         short_name: ~"test-pkg",
-        version: Tagged(a_tag)
+        version: Tagged(a_tag),
+        remote: None
     }
 }
 
@@ -137,12 +156,13 @@ fn mk_temp_workspace(short_name: &Path, version: &Version) -> (TempDir, Path) {
 fn run_git(args: &[~str], env: Option<~[(~str, ~str)]>, cwd: &Path, err_msg: &str) {
     let cwd = (*cwd).clone();
     let mut prog = run::Process::new("git", args, run::ProcessOptions {
-        env: env,
+        env: match env { Some(env) => run::Exactly(env), None => run::InheritEnv },
         dir: Some(&cwd),
         in_fd: None,
         out_fd: None,
-        err_fd: None
-    });
+        err_fd: None,
+        detach: false
+    }).unwrap();
     let rslt = prog.finish_with_output();
     if rslt.status != 0 {
         fail2!("{} [git returned {:?}, output = {}, error = {}]", err_msg,
@@ -169,11 +189,6 @@ fn init_git_repo(p: &Path) -> TempDir {
     tmp
 }
 
-fn add_all_and_commit(repo: &Path) {
-    git_add_all(repo);
-    git_commit(repo, ~"floop");
-}
-
 fn git_commit(repo: &Path, msg: ~str) {
     run_git([~"commit", ~"--author=tester <test@mozilla.com>", ~"-m", msg],
             None, repo, format!("Couldn't commit in {}", repo.to_str()));
@@ -191,6 +206,52 @@ fn add_git_tag(repo: &Path, tag: ~str) {
             format!("Couldn't add git tag {} in {}", tag, repo.to_str()));
 }
 
+/// Like `init_git_repo`, but makes a mock repository, which rustpkg handles
+/// without running git
+fn init_repo(p: &Path) -> TempDir {
+    assert!(!p.is_absolute());
+    let tmp = TempDir::new("mock_local").expect("couldn't create temp dir");
+    let work_dir = tmp.path().push_rel(p);
+    assert!(mock_vcs::init(&work_dir));
+    writeFile(&work_dir.push("README"), "");
+    tmp
+}
+
+/// Tags the current contents of the mock repository `repo`
+fn add_tag(repo: &Path, tag: ~str) {
+    assert!(repo.is_absolute());
+    match mock_vcs::tag(repo, tag) {
+        Ok(()) => (),
+        Err(e) => fail2!("Couldn't add tag {} in {}: {}", tag, repo.to_str(), e)
+    }
+}
+
+// Windows has no executable bit, so these only check the others there
+#[cfg(target_os = "win32")]
+fn is_rwx(p: &Path) -> bool {
+    use std::libc::consts::os::posix88::{S_IRUSR, S_IWUSR};
+
+    match p.get_mode() {
+        None => return false,
+        Some(m) =>
+            ((m & S_IRUSR as uint) == S_IRUSR as uint
+            && (m & S_IWUSR as uint) == S_IWUSR as uint)
+    }
+}
+
+#[cfg(target_os = "win32")]
+fn is_read_only(p: &Path) -> bool {
+    use std::libc::consts::os::posix88::{S_IRUSR, S_IWUSR};
+
+    match p.get_mode() {
+        None => return false,
+        Some(m) =>
+            ((m & S_IRUSR as uint) == S_IRUSR as uint
+            && (m & S_IWUSR as uint) == 0 as uint)
+    }
+}
+
+#[cfg(not(target_os = "win32"))]
 fn is_rwx(p: &Path) -> bool {
     use std::libc::consts::os::posix88::{S_IRUSR, S_IWUSR, S_IXUSR};
 
@@ -203,6 +264,7 @@ fn is_rwx(p: &Path) -> bool {
     }
 }
 
+#[cfg(not(target_os = "win32"))]
 fn is_read_only(p: &Path) -> bool {
     use std::libc::consts::os::posix88::{S_IRUSR, S_IWUSR, S_IXUSR};
 
@@ -242,9 +304,9 @@ fn rustpkg_exec() -> Path {
     }
 }
 
-fn command_line_test(args: &[~str], cwd: &Path) -> ProcessOutput {
+fn command_line_test(args: &[~str], cwd: &Path) {
     match command_line_test_with_env(args, cwd, None) {
-        Success(r) => r,
+        Success => (),
         Fail(error) => fail2!("Command line test failed with error {}", error)
     }
 }
@@ -258,7 +320,7 @@ fn command_line_test_expect_fail(args: &[~str],
                                  env: Option<~[(~str, ~str)]>,
                                  expected_exitcode: int) {
     match command_line_test_with_env(args, cwd, env) {
-        Success(_) => fail2!("Should have failed with {}, but it succeeded", expected_exitcode),
+        Success => fail2!("Should have failed with {}, but it succeeded", expected_exitcode),
         Fail(error) if error == expected_exitcode => (), // ok
         Fail(other) => fail2!("Expected to fail with {}, but failed with {} instead",
                               expected_exitcode, other)
@@ -266,15 +328,77 @@ fn command_line_test_expect_fail(args: &[~str],
 }
 
 enum ProcessResult {
-    Success(ProcessOutput),
+    Success,
     Fail(int) // exit code
 }
 
-/// Runs `rustpkg` (based on the directory that this executable was
-/// invoked from) with the given arguments, in the given working directory.
-/// Returns the process's output.
+/// Runs rustpkg in this process, through the same entry point as the
+/// `rustpkg` binary, with the given arguments in the given working
+/// directory. The variables in `env` are added to its environment.
 fn command_line_test_with_env(args: &[~str], cwd: &Path, env: Option<~[(~str, ~str)]>)
     -> ProcessResult {
+    let env = env.unwrap_or(~[]);
+    debug2!("{} cd {}; rustpkg {}",
+            env.map(|&(ref k, ref v)| format!("{}={}", *k, *v)).connect(","),
+            cwd.to_str(), args.connect(" "));
+    assert!(os::path_is_dir(cwd));
+    match super::run(args, env, cwd) {
+        0 => Success,
+        status => {
+            debug2!("Command {:?} failed with exit code {}", args, status);
+            Fail(status)
+        }
+    }
+}
+
+/// A directory outside every workspace, for commands that shouldn't depend
+/// on where they're run. It isn't the current directory, since in-process
+/// runs change that while they last.
+fn outside_workspace() -> Path {
+    test_sysroot()
+}
+
+/// Runs the installed `rustpkg` (based on the directory that this
+/// executable was invoked from) with the given arguments, in the given
+/// working directory, and fails unless it succeeds. Returns the process's
+/// output. Only tests that look at what rustpkg prints need this.
+fn command_line_test_subprocess(args: &[~str], cwd: &Path) -> ProcessOutput {
+    command_line_test_subprocess_with_env(args, cwd, None)
+}
+
+fn command_line_test_subprocess_with_env(args: &[~str], cwd: &Path,
+                                         env: Option<~[(~str, ~str)]>) -> ProcessOutput {
+    let output = rustpkg_subprocess(args, cwd, env);
+    if output.status != 0 {
+        fail2!("Command {} {:?} failed with exit code {:?}; its output was --- {} ---",
+               rustpkg_exec().to_str(), args, output.status,
+               str::from_utf8(output.output) + str::from_utf8(output.error));
+    }
+    output
+}
+
+/// Runs the installed `rustpkg` and expects it to fail with
+/// `expected_exitcode`, saying `expected_msg` on stderr. Returns the
+/// process's output.
+fn command_line_test_expect_error(args: &[~str],
+                                  cwd: &Path,
+                                  expected_exitcode: int,
+                                  expected_msg: &str) -> ProcessOutput {
+    let output = rustpkg_subprocess(args, cwd, None);
+    let error = str::from_utf8(output.error);
+    if output.status != expected_exitcode || !error.contains(expected_msg) {
+        fail2!("Expected rustpkg {:?} to fail with {} saying `{}`, but it exited with {} \
+                and its output was --- {} ---",
+               args, expected_exitcode, expected_msg, output.status,
+               str::from_utf8(output.output) + error);
+    }
+    output
+}
+
+/// Runs the installed `rustpkg` with the given arguments in the given
+/// working directory, whatever the outcome
+fn rustpkg_subprocess(args: &[~str], cwd: &Path,
+                      env: Option<~[(~str, ~str)]>) -> ProcessOutput {
     let cmd = rustpkg_exec().to_str();
     let env_str = match env {
         Some(ref pairs) => pairs.map(|&(ref k, ref v)| { format!("{}={}", *k, *v) }).connect(","),
@@ -284,32 +408,19 @@ fn command_line_test_with_env(args: &[~str], cwd: &Path, env: Option<~[(~str, ~s
     assert!(os::path_is_dir(&*cwd));
     let cwd = (*cwd).clone();
     let mut prog = run::Process::new(cmd, args, run::ProcessOptions {
-        env: env.map(|e| e + os::env()),
+        env: match env { Some(env) => run::InheritPlus(env), None => run::InheritEnv },
         dir: Some(&cwd),
         in_fd: None,
         out_fd: None,
-        err_fd: None
-    });
+        err_fd: None,
+        detach: false
+    }).unwrap();
     let output = prog.finish_with_output();
     debug2!("Output from command {} with args {:?} was {} \\{{}\\}[{:?}]",
                     cmd, args, str::from_utf8(output.output),
                    str::from_utf8(output.error),
                    output.status);
-/*
-By the way, rustpkg *won't* return a nonzero exit code if it fails --
-see #4547
-So tests that use this need to check the existence of a file
-to make sure the command succeeded
-*/
-    if output.status != 0 {
-        debug2!("Command {} {:?} failed with exit code {:?}; its output was --- {} ---",
-              cmd, args, output.status,
-              str::from_utf8(output.output) + str::from_utf8(output.error));
-        Fail(output.status)
-    }
-    else {
-        Success(output)
-    }
+    output
 }
 
 fn create_local_package(pkgid: &PkgId) -> TempDir {
@@ -444,8 +555,7 @@ fn llvm_bitcode_file_exists(repo: &Path, short_name: &str) -> bool {
 }
 
 fn file_exists(repo: &Path, short_name: &str, extension: &str) -> bool {
-    os::path_exists(&target_build_dir(repo).push_many([short_name.to_owned(),
-                                     format!("{}.{}", short_name, extension)]))
+    built_unlinked_output_in_workspace(&PkgId::new(short_name), repo, extension).is_some()
 }
 
 fn assert_built_library_exists(repo: &Path, short_name: &str) {
@@ -463,8 +573,8 @@ fn built_library_exists(repo: &Path, short_name: &str) -> bool {
 
 fn command_line_test_output(args: &[~str]) -> ~[~str] {
     let mut result = ~[];
-    let p_output = command_line_test(args, &os::getcwd());
-    let test_output = str::from_utf8(p_output.output);
+    let p_output = command_line_test_subprocess(args, &outside_workspace());
+    let test_output = str::from_utf8_owned(p_output.output);
     for s in test_output.split_iter('\n') {
         result.push(s.to_owned());
     }
@@ -473,12 +583,9 @@ fn command_line_test_output(args: &[~str]) -> ~[~str] {
 
 fn command_line_test_output_with_env(args: &[~str], env: ~[(~str, ~str)]) -> ~[~str] {
     let mut result = ~[];
-    let p_output = match command_line_test_with_env(args,
-        &os::getcwd(), Some(env)) {
-        Fail(_) => fail2!("Command-line test failed"),
-        Success(r) => r
-    };
-    let test_output = str::from_utf8(p_output.output);
+    let p_output = command_line_test_subprocess_with_env(args, &outside_workspace(),
+                                                         Some(env));
+    let test_output = str::from_utf8_owned(p_output.output);
     for s in test_output.split_iter('\n') {
         result.push(s.to_owned());
     }
@@ -508,7 +615,7 @@ fn touch_source_file(workspace: &Path, pkgid: &PkgId) {
     for p in contents.iter() {
         if p.filetype() == Some(".rs") {
             // should be able to do this w/o a process
-            if run::process_output("touch", [p.to_str()]).status != 0 {
+            if run::process_output("touch", [p.to_str()]).unwrap().status != 0 {
                 let _ = cond.raise((pkg_src_dir.clone(), ~"Bad path"));
             }
         }
@@ -561,7 +668,7 @@ fn test_install_valid() {
     let temp_pkg_id = fake_pkg();
     let (temp_workspace, _pkg_dir) = mk_temp_workspace(&temp_pkg_id.path, &NoVersion);
     let temp_workspace = temp_workspace.path();
-    let ctxt = fake_ctxt(sysroot, temp_workspace);
+    let ctxt = fake_ctxt(sysroot);
     debug2!("temp_workspace = {}", temp_workspace.to_str());
     // should have test, bench, lib, and main
     let src = PkgSrc::new(temp_workspace.clone(),
@@ -585,11 +692,6 @@ fn test_install_valid() {
     let bench = target_bench_in_workspace(&temp_pkg_id, temp_workspace);
     debug2!("bench = {}", bench.to_str());
     assert!(!os::path_exists(&bench));
-
-    // Make sure the db isn't dirty, so that it doesn't try to save()
-    // asynchronously after the temporary directory that it wants to save
-    // to has been deleted.
-    ctxt.workcache_context.db.write(|db| db.db_dirty = false);
 }
 
 #[test]
@@ -598,7 +700,7 @@ fn test_install_invalid() {
     let pkgid = fake_pkg();
     let temp_workspace = TempDir::new("test").expect("couldn't create temp dir");
     let temp_workspace = temp_workspace.path().clone();
-    let ctxt = fake_ctxt(sysroot, &temp_workspace);
+    let ctxt = fake_ctxt(sysroot);
 
     // Uses task::try because of #9001
     let result = do task::try {
@@ -614,6 +716,8 @@ fn test_install_invalid() {
 }
 
 // Tests above should (maybe) be converted to shell out to rustpkg, too
+// This is the one test that uses a real git repository; the others that need
+// a repository use mock ones, which don't need git to be installed
 #[test]
 fn test_install_git() {
     let temp_pkg_id = git_repo_pkg();
@@ -707,7 +811,7 @@ fn test_package_ids_must_be_relative_path_like() {
 #[test]
 fn test_package_version() {
     let local_path = "mockgithub.com/catamorphism/test_pkg_version";
-    let repo = init_git_repo(&Path(local_path));
+    let repo = init_repo(&Path(local_path));
     let repo = repo.path();
     let repo_subdir = repo.push_many([~"mockgithub.com", ~"catamorphism", ~"test_pkg_version"]);
     debug2!("Writing files in: {}", repo_subdir.to_str());
@@ -719,7 +823,7 @@ fn test_package_version() {
               "#[test] pub fn f() { (); }");
     writeFile(&repo_subdir.push("bench.rs"),
               "#[bench] pub fn f() { (); }");
-    add_git_tag(&repo_subdir, ~"0.4");
+    add_tag(&repo_subdir, ~"0.4");
 
     // It won't pick up the 0.4 version because the dir isn't in the RUST_PATH, but...
     let temp_pkg_id = PkgId::new("mockgithub.com/catamorphism/test_pkg_version");
@@ -743,7 +847,7 @@ fn test_package_version() {
 #[test]
 fn test_package_request_version() {
     let local_path = "mockgithub.com/catamorphism/test_pkg_version";
-    let repo = init_git_repo(&Path(local_path));
+    let repo = init_repo(&Path(local_path));
     let repo = repo.path();
     let repo_subdir = repo.push_many([~"mockgithub.com", ~"catamorphism", ~"test_pkg_version"]);
     debug2!("Writing files in: {}", repo_subdir.to_str());
@@ -756,9 +860,9 @@ fn test_package_request_version() {
     writeFile(&repo_subdir.push("bench.rs"),
               "#[bench] pub fn f() { (); }");
     writeFile(&repo_subdir.push("version-0.3-file.txt"), "hi");
-    add_git_tag(&repo_subdir, ~"0.3");
+    add_tag(&repo_subdir, ~"0.3");
     writeFile(&repo_subdir.push("version-0.4-file.txt"), "hello");
-    add_git_tag(&repo_subdir, ~"0.4");
+    add_tag(&repo_subdir, ~"0.4");
 
     command_line_test([~"install", format!("{}\\#0.3", local_path)], repo);
 
@@ -791,7 +895,7 @@ fn rustpkg_install_url_2() {
 
 #[test]
 fn rustpkg_library_target() {
-    let foo_repo = init_git_repo(&Path("foo"));
+    let foo_repo = init_repo(&Path("foo"));
     let foo_repo = foo_repo.path();
     let package_dir = foo_repo.push("foo");
 
@@ -805,7 +909,7 @@ fn rustpkg_library_target() {
     writeFile(&package_dir.push("bench.rs"),
               "#[bench] pub fn f() { (); }");
 
-    add_git_tag(&package_dir, ~"1.0");
+    add_tag(&package_dir, ~"1.0");
     command_line_test([~"install", ~"foo"], foo_repo);
     assert_lib_exists(&foo_repo.push(".rust"), &Path("foo"), ExactRevision(~"1.0"));
 }
@@ -886,7 +990,7 @@ fn rust_path_test() {
     debug2!("dir = {}", dir.to_str());
     writeFile(&dir.push("main.rs"), "fn main() { let _x = (); }");
 
-    let cwd = os::getcwd();
+    let cwd = outside_workspace();
     debug2!("cwd = {}", cwd.to_str());
                                      // use command_line_test_with_env
     command_line_test_with_env([~"install", ~"foo"],
@@ -896,17 +1000,15 @@ fn rust_path_test() {
 }
 
 #[test]
-#[ignore] // FIXME(#9184) tests can't change the cwd (other tests are sad then)
 fn rust_path_contents() {
     let dir = TempDir::new("rust_path").expect("rust_path_contents failed");
     let abc = &dir.path().push_many([~"A", ~"B", ~"C"]);
     assert!(os::mkdir_recursive(&abc.push(".rust"), U_RWX));
     assert!(os::mkdir_recursive(&abc.pop().push(".rust"), U_RWX));
     assert!(os::mkdir_recursive(&abc.pop().pop().push(".rust"), U_RWX));
-    assert!(os::change_dir(abc));
 
-    let p = rust_path();
-    let cwd = os::getcwd().push(".rust");
+    let p = rust_path_in(abc, []);
+    let cwd = abc.push(".rust");
     let parent = cwd.pop().pop().push(".rust");
     let grandparent = cwd.pop().pop().pop().push(".rust");
     assert!(p.contains(&cwd));
@@ -919,12 +1021,10 @@ fn rust_path_contents() {
 
 #[test]
 fn rust_path_parse() {
-    os::setenv("RUST_PATH", "/a/b/c:/d/e/f:/g/h/i");
-    let paths = rust_path();
+    let paths = rust_path_in(&os::getcwd(), [(~"RUST_PATH", ~"/a/b/c:/d/e/f:/g/h/i")]);
     assert!(paths.contains(&Path("/g/h/i")));
     assert!(paths.contains(&Path("/d/e/f")));
     assert!(paths.contains(&Path("/a/b/c")));
-    os::unsetenv("RUST_PATH");
 }
 
 #[test]
@@ -1003,7 +1103,7 @@ fn install_check_duplicates() {
         }
         false
     };
-    list_installed_packages(check_dups);
+    list_installed_packages(rust_path(), check_dups);
 }
 
 #[test]
@@ -1020,6 +1120,96 @@ fn no_rebuilding() {
     assert_eq!(date, newdate);
 }
 
+#[test]
+fn build_main_against_own_lib() {
+    let p_id = PkgId::new("foo");
+    let workspace = create_local_package(&p_id);
+    let workspace = workspace.path();
+    writeFile(&workspace.push_many([~"src", p_id.to_str(), ~"main.rs"]),
+              "extern mod foo;\nfn main() { foo::f(); }");
+    command_line_test([~"build", ~"foo"], workspace);
+    assert!(built_library_in_workspace(&p_id, workspace).is_some());
+    assert!(built_executable_in_workspace(&p_id, workspace).is_some());
+    // The binary should have used the library it was built with, rather
+    // than installing the package to find it
+    assert!(!lib_exists(workspace, &p_id.path, NoVersion));
+}
+
+#[test]
+fn changing_main_does_not_rebuild_own_lib() {
+    let p_id = PkgId::new("foo");
+    let workspace = create_local_package(&p_id);
+    let workspace = workspace.path();
+    writeFile(&workspace.push_many([~"src", p_id.to_str(), ~"main.rs"]),
+              "extern mod foo;\nfn main() { foo::f(); }");
+    command_line_test([~"build", ~"foo"], workspace);
+    let lib = built_library_in_workspace(&p_id, workspace)
+        .expect("changing_main_does_not_rebuild_own_lib");
+    let lib_date = datestamp(&lib);
+
+    // The library and each binary are cached separately, so a change to
+    // the binary leaves the library alone. Make the library read-only so
+    // that rebuilding it would fail.
+    frob_source_file(workspace, &p_id, "main.rs");
+    assert!(chmod_read_only(&lib));
+    match command_line_test_partial([~"build", ~"foo"], workspace) {
+        Success => (),
+        Fail(status) => fail2!("changing_main_does_not_rebuild_own_lib: build failed with \
+                                status {}; did it rebuild the library?", status)
+    }
+    assert_eq!(lib_date, datestamp(&lib));
+}
+
+#[test]
+fn build_explain() {
+    let p_id = PkgId::new("foo");
+    let workspace = create_local_package(&p_id);
+    let workspace = workspace.path();
+    command_line_test([~"build", ~"foo"], workspace);
+    let output = command_line_test_subprocess([~"build", ~"--explain", ~"foo"], workspace);
+    let output = str::from_utf8(output.output);
+    assert!(output.contains("inputs are unchanged"));
+    assert!(!output.contains("rebuilt because"));
+    assert!(output.contains("cache hits"));
+}
+
+#[test]
+fn build_explain_rebuilds() {
+    let p_id = PkgId::new("foo");
+    let workspace = create_local_package(&p_id);
+    let workspace = workspace.path();
+    command_line_test([~"build", ~"foo"], workspace);
+
+    let output = command_line_test_subprocess([~"build", ~"--explain", ~"--save-temps", ~"foo"],
+                                              workspace);
+    let output = str::from_utf8(output.output);
+    assert!(output.contains("rebuilt because the rustc flags changed from `` to `--save-temps`"));
+
+    let lib = built_library_in_workspace(&p_id, workspace).expect("build_explain_rebuilds");
+    assert!(os::remove_file(&lib));
+    let output = command_line_test_subprocess([~"build", ~"--explain", ~"--save-temps", ~"foo"],
+                                              workspace);
+    let output = str::from_utf8(output.output);
+    assert!(output.contains(format!("rebuilt because output {} is missing",
+                                    lib.normalize().to_str())));
+}
+
+#[test]
+fn rebuild_when_module_outside_package_changes() {
+    let p_id = PkgId::new("foo");
+    let workspace = create_local_package(&p_id);
+    let workspace = workspace.path();
+    let shared = workspace.push("shared.rs");
+    writeFile(&shared, "pub fn g() {}");
+    writeFile(&workspace.push_many([~"src", p_id.to_str(), ~"lib.rs"]),
+              "#[path=\"../../shared.rs\"] mod shared;\npub fn f() { shared::g(); }");
+    command_line_test([~"build", ~"foo"], workspace);
+    writeFile(&shared, "pub fn g() { let _x = (); }");
+    let output = command_line_test_subprocess([~"build", ~"--explain", ~"foo"], workspace);
+    let output = str::from_utf8(output.output);
+    assert!(output.contains(format!("rebuilt because {} changed", shared.normalize().to_str())));
+}
+
 #[test]
 fn no_rebuilding_dep() {
     let p_id = PkgId::new("foo");
@@ -1036,7 +1226,7 @@ fn no_rebuilding_dep() {
     assert!(chmod_read_only(&bar_lib));
 
     match command_line_test_partial([~"build", ~"foo"], workspace) {
-        Success(*) => (), // ok
+        Success => (), // ok
         Fail(status) if status == 65 => fail2!("no_rebuilding_dep failed: it tried to rebuild bar"),
         Fail(_) => fail2!("no_rebuilding_dep failed for some other reason")
     }
@@ -1099,36 +1289,439 @@ fn test_build_hooks() {
 
 
 #[test]
-#[ignore(reason = "info not yet implemented")]
 fn test_info() {
-    let expected_info = ~"package foo"; // fill in
-    let workspace = create_local_package(&PkgId::new("foo"));
-    let output = command_line_test([~"info", ~"foo"], workspace.path());
-    assert_eq!(str::from_utf8(output.output), expected_info);
+    let dir = TempDir::new("test_info").expect("test_info failed");
+    let dir = dir.path();
+    let foo = PkgId::new("foo");
+    let bar = PkgId::new("bar");
+    let foo_dir = create_local_package_in(&foo, dir);
+    create_local_package_in(&bar, dir);
+    writeFile(&foo_dir.push("lib.rs"),
+              "#[pkg(license = \"MIT\")];\nextern mod bar;\npub fn g() { bar::f(); }");
+    command_line_test([~"install", ~"foo"], dir);
+
+    let env_arg = ~[(~"RUST_PATH", dir.to_str())];
+    let info_output = command_line_test_output_with_env([~"info", ~"foo"], env_arg);
+    debug2!("info output: {:?}", info_output);
+    assert!(info_output.iter().any(|x| *x == ~"name: foo"));
+    assert!(info_output.iter().any(|x| *x == ~"version: 0.1"));
+    assert!(info_output.iter().any(|x| *x == ~"license: MIT"));
+    assert!(info_output.iter().any(|x| x.starts_with("    bar-0.1 (")));
+    assert!(info_output.iter().any(|x| x.starts_with("    target_os = ")));
+    assert!(info_output.iter().any(|x| x.starts_with("target: ")));
+    assert!(info_output.iter().any(|x| *x == ~"opt level: 0"));
 }
 
 #[test]
 fn test_uninstall() {
     let workspace = create_local_package(&PkgId::new("foo"));
     command_line_test([~"uninstall", ~"foo"], workspace.path());
-    let output = command_line_test([~"list"], workspace.path());
-    assert!(!str::from_utf8(output.output).contains("foo"));
+    let output = command_line_test_subprocess([~"list"], workspace.path());
+    assert!(!str::from_utf8_slice(output.output).contains("foo"));
+}
+
+#[test]
+fn test_uninstall_removes_library() {
+    let p_id = PkgId::new("foo");
+    let workspace = create_local_package(&p_id);
+    let workspace = workspace.path();
+    command_line_test([~"install", ~"foo"], workspace);
+    assert!(installed_library_in_workspace(&p_id.path, workspace).is_some());
+    command_line_test([~"uninstall", ~"foo"], workspace);
+    assert!(installed_library_in_workspace(&p_id.path, workspace).is_none());
+}
+
+#[test]
+fn test_uninstall_uses_receipt() {
+    let p_id = PkgId::new("foo");
+    let workspace = create_local_package(&p_id);
+    let workspace = workspace.path();
+    command_line_test([~"install", ~"foo"], workspace);
+    let receipt_file = receipt_path(workspace, &p_id.path);
+    assert!(os::path_exists(&receipt_file));
+    let exec = target_executable_in_workspace(&p_id, workspace);
+    assert!(os::path_exists(&exec));
+
+    command_line_test([~"uninstall", ~"foo"], workspace);
+    assert!(!os::path_exists(&exec));
+    assert!(installed_library_in_workspace(&p_id.path, workspace).is_none());
+    assert!(!os::path_exists(&receipt_file));
+}
+
+#[test]
+fn install_with_docs() {
+    let p_id = PkgId::new("foo");
+    let workspace = create_local_package(&p_id);
+    let workspace = workspace.path();
+    writeFile(&workspace.push_many([~"src", ~"foo-0.1", ~"lib.rs"]),
+              "/// Does nothing\npub fn f() {}");
+    command_line_test([~"install", ~"--with-docs", ~"foo"], workspace);
+    let doc_dir = target_doc_dir(&p_id, workspace);
+    let docs: ~[Path] = os::walk_dir(&doc_dir).filter(|e| !os::path_is_dir(&e.path))
+        .map(|e| e.path.clone()).collect();
+    assert!(!docs.is_empty());
+    let receipt = read_receipt(workspace, &p_id.path).unwrap().unwrap();
+    for doc in docs.iter() {
+        let doc = os::make_absolute(doc).to_str();
+        assert!(receipt.files.iter().any(|f| f.path == doc));
+    }
+
+    command_line_test([~"uninstall", ~"foo"], workspace);
+    assert!(!os::path_exists(&doc_dir));
+}
+
+#[test]
+fn install_with_docs_links_to_dependencies() {
+    let p_id = PkgId::new("foo");
+    let dep_id = PkgId::new("bar");
+    let workspace = create_local_package_with_dep(&p_id, &dep_id);
+    let workspace = workspace.path();
+    writeFile(&workspace.push_many([~"src", ~"bar-0.1", ~"lib.rs"]), "pub struct T;");
+    writeFile(&workspace.push_many([~"src", ~"foo-0.1", ~"lib.rs"]),
+              "extern mod bar;\npub fn g() -> bar::T { bar::T }");
+    command_line_test([~"install", ~"--with-docs", ~"bar"], workspace);
+    command_line_test([~"install", ~"--with-docs", ~"foo"], workspace);
+    // The link has to work from where foo's docs were installed, which
+    // isn't where they were generated
+    let page = target_doc_dir(&p_id, workspace).push_many([~"foo", ~"fn.g.html"]);
+    let contents = io::read_whole_file_str(&page).unwrap();
+    assert!(contents.contains("../../bar/bar/"));
+}
+
+#[test]
+fn fetch_then_build_offline() {
+    let repo = init_repo(&Path("mockgithub.com/catamorphism/fetch_top"));
+    let repo = repo.path();
+    let top_dir = repo.push_many([~"mockgithub.com", ~"catamorphism", ~"fetch_top"]);
+    let dep_dir = repo.push_many([~"mockgithub.com", ~"catamorphism", ~"fetch_dep"]);
+    assert!(mock_vcs::init(&dep_dir));
+    writeFile(&dep_dir.push("lib.rs"), "pub fn f() {}");
+    add_tag(&dep_dir, ~"0.1");
+    writeFile(&top_dir.push("main.rs"),
+              "extern mod fetch_dep = \"mockgithub.com/catamorphism/fetch_dep\";\n\
+               fn main() { fetch_dep::f(); }");
+    add_tag(&top_dir, ~"0.1");
+
+    // Nothing has been fetched yet
+    command_line_test_expect_fail([~"build", ~"--offline",
+                                   ~"mockgithub.com/catamorphism/fetch_top"],
+                                  repo, None, COPY_FAILED_CODE);
+
+    command_line_test([~"fetch", ~"mockgithub.com/catamorphism/fetch_top"], repo);
+    let ws = repo.push(".rust");
+    let top_id = PkgId::new("mockgithub.com/catamorphism/fetch_top");
+    let dep_id = PkgId::new("mockgithub.com/catamorphism/fetch_dep");
+    assert!(built_library_in_workspace(&dep_id, &ws).is_none());
+    assert!(built_executable_in_workspace(&top_id, &ws).is_none());
+
+    command_line_test([~"build", ~"--offline", ~"mockgithub.com/catamorphism/fetch_top"],
+                      repo);
+    assert!(built_executable_in_workspace(&top_id, &ws).is_some());
+}
+
+#[test]
+fn fetch_skips_missing_conditional_dep() {
+    let repo = init_repo(&Path("mockgithub.com/catamorphism/fetch_cfg"));
+    let repo = repo.path();
+    let top_dir = repo.push_many([~"mockgithub.com", ~"catamorphism", ~"fetch_cfg"]);
+    writeFile(&top_dir.push("main.rs"),
+              "#[cfg(target_os = \"no_such_os\")]\n\
+               extern mod no_such_dep;\n\
+               fn main() {}");
+    add_tag(&top_dir, ~"0.1");
+
+    // The dependency can't be found, but isn't needed on this target
+    let output = command_line_test_subprocess([~"fetch",
+                                               ~"mockgithub.com/catamorphism/fetch_cfg"],
+                                              repo);
+    assert!(str::from_utf8(output.output).contains("skipping no_such_dep"));
+}
+
+#[test]
+fn test_uninstall_refuses_changed_files() {
+    let p_id = PkgId::new("foo");
+    let workspace = create_local_package(&p_id);
+    let workspace = workspace.path();
+    command_line_test([~"install", ~"foo"], workspace);
+    let exec = target_executable_in_workspace(&p_id, workspace);
+    writeFile(&exec, "changed by hand");
+
+    command_line_test_expect_fail([~"uninstall", ~"foo"], workspace, None, COPY_FAILED_CODE);
+    assert!(os::path_exists(&exec));
+    assert!(installed_library_in_workspace(&p_id.path, workspace).is_some());
+
+    command_line_test([~"uninstall", ~"--force", ~"foo"], workspace);
+    assert!(!os::path_exists(&exec));
+    assert!(installed_library_in_workspace(&p_id.path, workspace).is_none());
+}
+
+#[test]
+fn test_upgrade_removes_old_version() {
+    let p_id = PkgId::new("foo");
+    let workspace = create_local_package(&p_id);
+    let workspace = workspace.path();
+    let lib_rs = workspace.push_many([~"src", ~"foo-0.1", ~"lib.rs"]);
+    writeFile(&lib_rs, "#[pkg(vers = \"0.1\")];\npub fn f() {}");
+    command_line_test([~"install", ~"foo"], workspace);
+    let old_lib = installed_library_in_workspace(&p_id.path, workspace)
+        .expect("test_upgrade_removes_old_version: 0.1 wasn't installed");
+
+    writeFile(&lib_rs, "#[pkg(vers = \"0.2\")];\npub fn f() {}");
+    command_line_test([~"install", ~"foo"], workspace);
+    let new_lib = installed_library_in_workspace(&p_id.path, workspace)
+        .expect("test_upgrade_removes_old_version: 0.2 wasn't installed");
+    assert!(new_lib != old_lib);
+    assert!(!os::path_exists(&old_lib));
+}
+
+#[test]
+fn test_version() {
+    let output = command_line_test_output([~"version"]);
+    assert!(output[0].starts_with("rustpkg "));
+    assert!(output.iter().any(|l| l.starts_with("rustc: ")));
+    assert!(output.contains(&format!("host: {}", host_triple())));
+
+    let output = command_line_test_output([~"version", ~"--json"]).connect("\n");
+    let obj = match json::from_str(output) {
+        Ok(json::Object(obj)) => obj,
+        other => fail2!("test_version: expected a JSON object, got {:?}", other)
+    };
+    assert_eq!(obj.find(&~"host"), Some(&json::String(host_triple())));
+    assert!(obj.contains_key(&~"rustpkg"));
+    // rustc is run by its absolute path, which it prints before its version
+    match obj.find(&~"rustc") {
+        Some(&json::String(ref v)) => assert!(!v.contains("rustc")),
+        Some(&json::Null) => (),
+        other => fail2!("test_version: unexpected rustc version {:?}", other)
+    }
+}
+
+#[test]
+fn test_rustc_version_from() {
+    assert_eq!(rustc_version_from("/usr/local/bin/rustc 0.9-pre (abcdef 2013-10-01)",
+                                  "/usr/local/bin/rustc"),
+               ~"0.9-pre (abcdef 2013-10-01)");
+    assert_eq!(rustc_version_from("/opt/my rust/bin/rustc 0.9", "/opt/my rust/bin/rustc"),
+               ~"0.9");
+    assert_eq!(rustc_version_from("rustc 0.9 (abcdef)", "/usr/bin/rustc"), ~"0.9 (abcdef)");
+}
+
+#[test]
+#[ignore(cfg(windows))]
+fn test_env() {
+    let dir = TempDir::new("test_env").expect("test_env failed");
+    let dir = dir.path();
+    let missing = dir.push("missing");
+    let rust_path = format!("{}:{}", dir.to_str(), missing.to_str());
+    let output = command_line_test_output_with_env([~"env", ~"--cfg", ~"quux"],
+                                                   ~[(~"RUST_PATH", rust_path)]);
+    assert!(output.contains(&format!("    {} (from $RUST_PATH, exists)", dir.to_str())));
+    assert!(output.contains(&format!("    {} (from $RUST_PATH, doesn't exist)",
+                                     missing.to_str())));
+    assert!(output.iter().any(|l| l.starts_with("sysroot: ")));
+    assert!(output.iter().any(|l| l.starts_with("destination workspace: ")));
+    assert!(output.contains(&~"cfgs: quux"));
+    assert!(output.iter().any(|l| l.starts_with("    RUST_PATH = ")));
+}
+
+#[test]
+fn test_command_help() {
+    // The options listed are the ones the command is parsed with
+    let output = command_line_test_output([~"install", ~"--help"]).connect("\n");
+    assert!(output.contains("rustpkg install [options..]"));
+    assert!(output.contains("--with-docs"));
+    assert!(output.contains("--sysroot"));
+    assert!(!output.contains("--explain"));
+}
+
+#[test]
+fn test_completions() {
+    let bash = command_line_test_output([~"completions", ~"bash"]).connect("\n");
+    // Commands and options come from the tables the command line is parsed with
+    assert!(bash.contains("self-update"));
+    assert!(bash.contains("--symlink"));
+    assert!(bash.contains("--sysroot) ((i++))"));
+    assert!(bash.contains("complete -o default -F _rustpkg rustpkg"));
+    // Only lines that look like package IDs are offered, not list's heading
+    assert!(bash.contains("rustpkg list 2>/dev/null | grep -E '^[^[:space:]]+$'"));
+
+    let zsh = command_line_test_output([~"completions", ~"zsh"]).connect("\n");
+    assert!(zsh.starts_with("#compdef rustpkg"));
+    assert!(zsh.contains("'--save-temps:Don'\\''t delete temporary files'"));
+
+    command_line_test_expect_fail([~"completions", ~"fish"], &outside_workspace(), None,
+                                  BAD_FLAG_CODE);
+}
+
+// Makes a channel for self-update in `dir`, with the version `version` and a
+// `rustpkg` component holding `files`, given as paths and contents. Returns
+// the SHA-256 of its manifest.
+fn mk_channel(dir: &Path, version: &str, files: &[(&str, &str)]) -> ~str {
+    use extra::sha1::Sha1;
+    use extra::sha2::Sha256;
+    use extra::digest::Digest;
+
+    let mut entries = ~[];
+    for &(path, contents) in files.iter() {
+        let p = dir.push_rel(&Path(path));
+        assert!(os::mkdir_recursive(&p.dir_path(), U_RWX));
+        writeFile(&p, contents);
+        let mut sha = Sha1::new();
+        sha.input(io::read_whole_file(&p).unwrap());
+        entries.push(format!("\\{\"path\": \"{}\", \"sha1\": \"{}\"\\}",
+                             path, sha.result_str()));
+    }
+    let manifest = dir.push("channel.json");
+    writeFile(&manifest,
+              format!("\\{\"version\": \"{}\", \"components\": \\{\"rustpkg\": [{}]\\}\\}",
+                      version, entries.connect(", ")));
+    let mut sha = Sha256::new();
+    sha.input(io::read_whole_file(&manifest).unwrap());
+    sha.result_str()
+}
+
+fn self_update_args(channel: &Path, manifest_sha256: &str) -> ~[~str] {
+    ~[~"self-update", ~"--user", ~"--channel", channel.to_str(),
+      ~"--manifest-sha256", manifest_sha256.to_owned()]
+}
+
+#[test]
+fn test_self_update() {
+    let channel = TempDir::new("test_self_update_channel").expect("test_self_update");
+    let channel = channel.path();
+    let sha256 = mk_channel(channel, "999.0", [("bin/rustpkg", "a newer rustpkg")]);
+    let dest = TempDir::new("test_self_update_dest").expect("test_self_update");
+    let dest = dest.path();
+
+    command_line_test_with_env(self_update_args(channel, sha256),
+                               dest, Some(~[(~"RUST_PATH", dest.to_str())]));
+    let installed = dest.push_many([~"bin", ~"rustpkg"]);
+    assert_eq!(io::read_whole_file_str(&installed), Ok(~"a newer rustpkg\n"));
+    assert!(!os::path_exists(&dest.push_many([~"bin", ~".rustpkg.new"])));
+}
+
+#[test]
+fn test_self_update_bad_checksum() {
+    let channel = TempDir::new("test_self_update_channel").expect("test_self_update");
+    let channel = channel.path();
+    let sha256 = mk_channel(channel, "999.0", [("bin/rustpkg", "a newer rustpkg")]);
+    writeFile(&channel.push_many([~"bin", ~"rustpkg"]), "a tampered rustpkg");
+    let dest = TempDir::new("test_self_update_dest").expect("test_self_update");
+    let dest = dest.path();
+
+    command_line_test_expect_fail(self_update_args(channel, sha256),
+                                  dest, Some(~[(~"RUST_PATH", dest.to_str())]),
+                                  UPDATE_FAILED_CODE);
+    assert!(!os::path_exists(&dest.push_many([~"bin", ~"rustpkg"])));
+}
+
+#[test]
+fn test_self_update_pins_manifest() {
+    let channel = TempDir::new("test_self_update_channel").expect("test_self_update");
+    let channel = channel.path();
+    let sha256 = mk_channel(channel, "999.0", [("bin/rustpkg", "a newer rustpkg")]);
+    // A channel whose files and hashes were both replaced
+    mk_channel(channel, "999.0", [("bin/rustpkg", "a tampered rustpkg")]);
+    let dest = TempDir::new("test_self_update_dest").expect("test_self_update");
+    let dest = dest.path();
+    let env = Some(~[(~"RUST_PATH", dest.to_str())]);
+
+    command_line_test_expect_fail(self_update_args(channel, sha256), dest, env.clone(),
+                                  UPDATE_FAILED_CODE);
+    assert!(!os::path_exists(&dest.push_many([~"bin", ~"rustpkg"])));
+    // And without a hash to check it against, nothing is read at all
+    command_line_test_expect_fail([~"self-update", ~"--user", ~"--channel", channel.to_str()],
+                                  dest, env, BAD_FLAG_CODE);
+}
+
+#[test]
+fn test_self_update_all_or_nothing() {
+    let channel = TempDir::new("test_self_update_channel").expect("test_self_update");
+    let channel = channel.path();
+    let sha256 = mk_channel(channel, "999.0", [("bin/rustpkg", "a newer rustpkg"),
+                                               ("lib/librustpkg.so", "a newer librustpkg")]);
+    let dest = TempDir::new("test_self_update_dest").expect("test_self_update");
+    let dest = dest.path();
+    // lib can't be made into a directory, so the library can't be installed
+    writeFile(&dest.push("lib"), "not a directory");
+
+    command_line_test_expect_fail(self_update_args(channel, sha256),
+                                  dest, Some(~[(~"RUST_PATH", dest.to_str())]),
+                                  UPDATE_FAILED_CODE);
+    // ...and nor is rustpkg, although it could have been
+    assert!(!os::path_exists(&dest.push_many([~"bin", ~"rustpkg"])));
+    assert!(!os::path_exists(&dest.push_many([~"bin", ~".rustpkg.new"])));
+}
+
+#[test]
+fn test_list_ignores_non_executables() {
+    let workspace = create_local_package(&PkgId::new("foo"));
+    let workspace = workspace.path();
+    command_line_test([~"install", ~"foo"], workspace);
+    assert!(os::mkdir_recursive(&workspace.push("bin"), U_RWX));
+    writeFile(&workspace.push_many([~"bin", ~"README.txt"]), "not a program");
+    let env = ~[(~"RUST_PATH", workspace.to_str())];
+    let list_output = command_line_test_output_with_env([~"list"], env);
+    assert!(list_output.iter().any(|x| x.starts_with("foo")));
+    assert!(!list_output.iter().any(|x| x.starts_with("README")));
+}
+
+#[test]
+#[cfg(unix)] // os::readlink is unix-only, and symlinks need privileges on Windows
+fn test_install_symlink() {
+    let p_id = PkgId::new("foo");
+    let workspace = create_local_package(&p_id);
+    let workspace = workspace.path();
+    command_line_test([~"install", ~"--symlink", ~"foo"], workspace);
+    let installed = target_executable_in_workspace(&p_id, workspace);
+    let built = built_executable_in_workspace(&p_id, workspace)
+        .expect("test_install_symlink: foo wasn't built");
+    assert!(os::path_is_symlink(&installed));
+    assert_eq!(os::readlink(&installed), Some(os::make_absolute(&built)));
+
+    // Installing without --symlink replaces the symlink with a copy
+    command_line_test([~"install", ~"foo"], workspace);
+    assert!(!os::path_is_symlink(&installed));
+    assert!(is_executable(&installed));
+    assert!(os::path_exists(&built));
+}
+
+#[test]
+fn test_mock_repo_checkout() {
+    use source_control::{VersionControl, vcs_at};
+
+    let repo = init_repo(&Path("foo"));
+    let repo = repo.path().push("foo");
+    writeFile(&repo.push("lib.rs"), "pub fn f() {}");
+    add_tag(&repo, ~"0.2");
+    writeFile(&repo.push("lib.rs"), "pub fn g() {}");
+    writeFile(&repo.push("new_file"), "hi");
+    add_tag(&repo, ~"0.10");
+
+    let vcs = vcs_at(&repo).expect("test_mock_repo_checkout: not a repository");
+    assert_eq!(vcs.tags(&repo), Ok(~[~"0.10", ~"0.2"]));
+    let clone = TempDir::new("mock_clone").expect("couldn't create temp dir");
+    let clone = clone.path().push("foo");
+    assert!(vcs.clone_repo(repo.to_str(), &clone).is_ok());
+    assert!(vcs.checkout(&clone, "0.2").is_ok());
+    assert!(!os::path_exists(&clone.push("new_file")));
+    assert_eq!(io::read_whole_file_str(&clone.push("lib.rs")), Ok(~"pub fn f() {}\n"));
+    assert!(vcs.checkout(&clone, "0.3").is_err());
 }
 
 #[test]
 fn test_non_numeric_tag() {
     let temp_pkg_id = git_repo_pkg();
-    let repo = init_git_repo(&temp_pkg_id.path);
+    let repo = init_repo(&temp_pkg_id.path);
     let repo = repo.path();
     let repo_subdir = repo.push_many([~"mockgithub.com", ~"catamorphism", ~"test-pkg"]);
     writeFile(&repo_subdir.push("foo"), "foo");
     writeFile(&repo_subdir.push("lib.rs"),
               "pub fn f() { let _x = (); }");
-    add_git_tag(&repo_subdir, ~"testbranch");
+    add_tag(&repo_subdir, ~"testbranch");
     writeFile(&repo_subdir.push("testbranch_only"), "hello");
-    add_git_tag(&repo_subdir, ~"another_tag");
+    add_tag(&repo_subdir, ~"another_tag");
     writeFile(&repo_subdir.push("not_on_testbranch_only"), "bye bye");
-    add_all_and_commit(&repo_subdir);
 
     command_line_test([~"install", format!("{}\\#testbranch", temp_pkg_id.path.to_str())], repo);
     let file1 = repo.push_many(["mockgithub.com", "catamorphism",
@@ -1160,8 +1753,7 @@ fn test_extern_mod() {
     command_line_test([~"install", ~"mockgithub.com/catamorphism/test_pkg"], lib_depend_dir);
 
     let exec_file = dir.push("out");
-    // Be sure to extend the existing environment
-    let env = Some([(~"RUST_PATH", lib_depend_dir.to_str())] + os::env());
+    let env = run::InheritPlus(~[(~"RUST_PATH", lib_depend_dir.to_str())]);
     let rustpkg_exec = rustpkg_exec();
     let rustc = rustpkg_exec.with_filename("rustc");
 
@@ -1173,8 +1765,9 @@ fn test_extern_mod() {
         dir: Some(dir),
         in_fd: None,
         out_fd: None,
-        err_fd: None
-    });
+        err_fd: None,
+        detach: false
+    }).unwrap();
     let outp = prog.finish_with_output();
     if outp.status != 0 {
         fail2!("output was {}, error was {}",
@@ -1205,8 +1798,7 @@ fn test_extern_mod_simpler() {
     command_line_test([~"install", ~"rust-awesomeness"], lib_depend_dir);
 
     let exec_file = dir.push("out");
-    // Be sure to extend the existing environment
-    let env = Some([(~"RUST_PATH", lib_depend_dir.to_str())] + os::env());
+    let env = run::InheritPlus(~[(~"RUST_PATH", lib_depend_dir.to_str())]);
     let rustpkg_exec = rustpkg_exec();
     let rustc = rustpkg_exec.with_filename("rustc");
     debug2!("RUST_PATH={} {} {} \n --sysroot {} -o {}",
@@ -1224,8 +1816,9 @@ fn test_extern_mod_simpler() {
         dir: Some(dir),
         in_fd: None,
         out_fd: None,
-        err_fd: None
-    });
+        err_fd: None,
+        detach: false
+    }).unwrap();
     let outp = prog.finish_with_output();
     if outp.status != 0 {
         fail2!("output was {}, error was {}",
@@ -1418,7 +2011,7 @@ fn rust_path_install_target() {
 
     let rust_path = Some(~[(~"RUST_PATH", format!("{}:{}", dir_to_install_to.to_str(),
                                                dir.to_str()))]);
-    let cwd = os::getcwd();
+    let cwd = outside_workspace();
     command_line_test_with_env([~"install", ~"foo"],
                                &cwd,
                                rust_path);
@@ -1512,6 +2105,24 @@ fn notrans_flag_fail() {
     }
 }
 
+#[test]
+fn pretty_modes() {
+    let p_id = PkgId::new("foo");
+    let workspace = create_local_package(&p_id);
+    let workspace = workspace.path();
+    let build_dir = target_build_dir(workspace).push("foo");
+    command_line_test([~"build", ~"--pretty", ~"foo"], workspace);
+    assert!(os::path_exists(&build_dir.push("main.normal.rs")));
+    for mode in [~"expanded", ~"typed", ~"expanded,identified"].iter() {
+        command_line_test([~"build", "--pretty=" + *mode, ~"foo"], workspace);
+        let printed = build_dir.push(format!("main.{}.rs", mode.replace(",", "-")));
+        assert!(os::path_exists(&printed));
+    }
+    assert!(!built_executable_exists(workspace, "foo"));
+    command_line_test_expect_fail([~"build", ~"--pretty=ugly", ~"foo"],
+                                  workspace, None, BAD_FLAG_CODE);
+}
+
 #[test]
 fn dash_S() {
     let p_id = PkgId::new("foo");
@@ -1570,7 +2181,7 @@ fn test_cfg_fail() {
                        ~"build",
                        ~"foo"],
                       workspace) {
-        Success(*) => fail2!("test_cfg_fail failed"),
+        Success => fail2!("test_cfg_fail failed"),
         _          => ()
     }
 }
@@ -1679,7 +2290,7 @@ fn test_build_install_flags_fail() {
                      ~[~"--target", host_triple()],
                      ~[~"--target-cpu", ~"generic"],
                      ~[~"-Z", ~"--time-passes"]];
-    let cwd = os::getcwd();
+    let cwd = outside_workspace();
     for flag in forbidden.iter() {
         command_line_test_expect_fail([test_sysroot().to_str(),
                            ~"list"] + *flag, &cwd, None, BAD_FLAG_CODE);
@@ -1699,6 +2310,20 @@ fn test_optimized_build() {
     assert!(built_executable_exists(workspace, "foo"));
 }
 
+#[test]
+fn test_bad_opt_level_fail() {
+    let p_id = PkgId::new("foo");
+    let workspace = create_local_package(&p_id);
+    let workspace = workspace.path();
+    command_line_test_expect_fail([test_sysroot().to_str(),
+                       ~"build",
+                       ~"--opt-level",
+                       ~"5",
+                       ~"foo"],
+                       workspace, None, BAD_FLAG_CODE);
+    assert!(!built_executable_exists(workspace, "foo"));
+}
+
 #[test]
 fn pkgid_pointing_to_subdir() {
     // The actual repo is mockgithub.com/mozilla/some_repo
@@ -1770,7 +2395,7 @@ fn test_install_to_rust_path() {
     command_line_test_with_env([test_sysroot().to_str(),
                        ~"install",
                        ~"foo"],
-                      &os::getcwd(), rust_path);
+                      &outside_workspace(), rust_path);
     assert!(!built_executable_exists(first_workspace, "foo"));
     assert!(built_executable_exists(second_workspace, "foo"));
     assert_executable_exists(first_workspace, "foo");
@@ -1910,11 +2535,25 @@ fn test_rustpkg_test_creates_exec() {
     assert!(test_executable_exists(foo_workspace, "foo"));
 }
 
+#[test]
+fn test_rustpkg_test_failure_status() {
+    let foo_id = PkgId::new("foo");
+    let foo_workspace = create_local_package(&foo_id);
+    let foo_workspace = foo_workspace.path();
+    writeFile(&foo_workspace.push_many(["src", "foo-0.1", "test.rs"]),
+              "#[test] fn f() { assert!('a' == 'b'); }");
+    // The test binary's status, not the one for rustpkg's own failures
+    match command_line_test_partial([~"test", ~"foo"], foo_workspace) {
+        Success => fail2!("test_rustpkg_test_failure_status: the failing test passed"),
+        Fail(status) => assert!(status != COPY_FAILED_CODE)
+    }
+}
+
 #[test]
 fn test_rustpkg_test_output() {
     let workspace = create_local_package_with_test(&PkgId::new("foo"));
-    let output = command_line_test([~"test", ~"foo"], workspace.path());
-    let output_str = str::from_utf8(output.output);
+    let output = command_line_test_subprocess([~"test", ~"foo"], workspace.path());
+    let output_str = str::from_utf8_owned(output.output);
     // The first two assertions are separate because test output may
     // contain color codes, which could appear between "test f" and "ok".
     assert!(output_str.contains("test f"));
@@ -1922,6 +2561,26 @@ fn test_rustpkg_test_output() {
     assert!(output_str.contains("1 passed; 0 failed; 0 ignored; 0 measured"));
 }
 
+#[test]
+fn test_rustpkg_test_timeout() {
+    let foo_id = PkgId::new("foo");
+    let foo_workspace = create_local_package(&foo_id);
+    let foo_workspace = foo_workspace.path();
+    writeFile(&foo_workspace.push_many(["src", "foo-0.1", "test.rs"]),
+              "#[test] fn f() { loop {} }");
+    command_line_test_expect_error([~"test", ~"--timeout", ~"1", ~"foo"], foo_workspace,
+                                   COPY_FAILED_CODE, "was killed after running for 1 seconds");
+}
+
+#[test]
+fn test_rustpkg_test_bad_timeout() {
+    let foo_id = PkgId::new("foo");
+    let foo_workspace = create_local_package(&foo_id);
+    command_line_test_expect_error([~"test", ~"--timeout", ~"soon", ~"foo"],
+                                   foo_workspace.path(), BAD_FLAG_CODE,
+                                   "expected a number of seconds for --timeout, not `soon`");
+}
+
 #[test]
 #[ignore(reason = "See issue #9441")]
 fn test_rebuild_when_needed() {
@@ -1937,7 +2596,7 @@ fn test_rebuild_when_needed() {
     frob_source_file(foo_workspace, &foo_id, "test.rs");
     chmod_read_only(&test_executable);
     match command_line_test_partial([~"test", ~"foo"], foo_workspace) {
-        Success(*) => fail2!("test_rebuild_when_needed didn't rebuild"),
+        Success => fail2!("test_rebuild_when_needed didn't rebuild"),
         Fail(status) if status == 65 => (), // ok
         Fail(_) => fail2!("test_rebuild_when_needed failed for some other reason")
     }
@@ -1956,7 +2615,7 @@ fn test_no_rebuilding() {
                             foo_workspace).expect("test_no_rebuilding failed");
     chmod_read_only(&test_executable);
     match command_line_test_partial([~"test", ~"foo"], foo_workspace) {
-        Success(*) => (), // ok
+        Success => (), // ok
         Fail(status) if status == 65 => fail2!("test_no_rebuilding failed: it rebuilt the tests"),
         Fail(_) => fail2!("test_no_rebuilding failed for some other reason")
     }
@@ -1967,7 +2626,7 @@ fn test_installed_read_only() {
     // Install sources from a "remote" (actually a local github repo)
     // Check that afterward, sources are read-only and installed under build/
     let temp_pkg_id = git_repo_pkg();
-    let repo = init_git_repo(&temp_pkg_id.path);
+    let repo = init_repo(&temp_pkg_id.path);
     let repo = repo.path();
     debug2!("repo = {}", repo.to_str());
     let repo_subdir = repo.push_many([~"mockgithub.com", ~"catamorphism", ~"test-pkg"]);
@@ -1977,7 +2636,7 @@ fn test_installed_read_only() {
               "fn main() { let _x = (); }");
     writeFile(&repo_subdir.push("lib.rs"),
               "pub fn f() { let _x = (); }");
-    add_git_tag(&repo_subdir, ~"0.1"); // this has the effect of committing the files
+    add_tag(&repo_subdir, ~"0.1");
 
     command_line_test([~"install", temp_pkg_id.path.to_str()], repo);
 
@@ -2006,7 +2665,7 @@ fn test_installed_read_only() {
 #[test]
 fn test_installed_local_changes() {
     let temp_pkg_id = git_repo_pkg();
-    let repo = init_git_repo(&temp_pkg_id.path);
+    let repo = init_repo(&temp_pkg_id.path);
     let repo = repo.path();
     debug2!("repo = {}", repo.to_str());
     let repo_subdir = repo.push_many([~"mockgithub.com", ~"catamorphism", ~"test-pkg"]);
@@ -2017,7 +2676,7 @@ fn test_installed_local_changes() {
               "fn main() { let _x = (); }");
     writeFile(&repo_subdir.push("lib.rs"),
               "pub fn f() { let _x = (); }");
-    add_git_tag(&repo_subdir, ~"0.1"); // this has the effect of committing the files
+    add_tag(&repo_subdir, ~"0.1");
 
     command_line_test([~"install", temp_pkg_id.path.to_str()], repo);
 
@@ -2062,7 +2721,7 @@ fn test_7402() {
     let dest_workspace = dest_workspace.path();
     let rust_path = Some(~[(~"RUST_PATH",
                             format!("{}:{}", dest_workspace.to_str(), dir.path().to_str()))]);
-    let cwd = os::getcwd();
+    let cwd = outside_workspace();
     command_line_test_with_env([~"install", ~"foo"], &cwd, rust_path);
     assert_executable_exists(dest_workspace, "foo");
 }
@@ -2077,19 +2736,331 @@ fn test_compile_error() {
     writeFile(&main_crate, "pub fn main() { if 42 != ~\"the answer\" { fail!(); } }");
     let result = command_line_test_partial([~"build", ~"foo"], foo_workspace);
     match result {
-        Success(*) => fail2!("Failed by succeeding!"), // should be a compile error
+        Success => fail2!("Failed by succeeding!"), // should be a compile error
         Fail(status) => {
             debug2!("Failed with status {:?}... that's good, right?", status);
         }
     }
 }
 
-/// Returns true if p exists and is executable
-fn is_executable(p: &Path) -> bool {
-    use std::libc::consts::os::posix88::{S_IXUSR};
+#[test]
+fn install_static_library() {
+    let workspace = mk_empty_workspace(&Path("foo"), &NoVersion, "temp_workspace");
+    let workspace = workspace.path();
+    writeFile(&workspace.push_many(["src", "foo-0.1", "lib.rs"]),
+              "#[crate_type = \"staticlib\"]; #[no_std]; pub fn f() {}");
+    command_line_test([~"install", ~"foo"], workspace);
+    let lib = installed_library_in_workspace(&Path("foo"), workspace)
+        .expect("install_static_library: no library was installed");
+    assert_eq!(lib.filetype(), Some(".a"));
+}
 
-    match p.get_mode() {
-        None => false,
-        Some(mode) => mode & S_IXUSR as uint == S_IXUSR as uint
+#[test]
+fn install_static_library_using_std() {
+    let workspace = mk_empty_workspace(&Path("foo"), &NoVersion, "temp_workspace");
+    let workspace = workspace.path();
+    writeFile(&workspace.push_many(["src", "foo-0.1", "lib.rs"]),
+              "#[crate_type = \"staticlib\"]; pub fn f() -> ~str { 1.to_str() }");
+    command_line_test([~"install", ~"foo"], workspace);
+    let lib = installed_library_in_workspace(&Path("foo"), workspace)
+        .expect("install_static_library_using_std: no library was installed");
+    let members = run::process_output("ar", [~"t", lib.to_str()]).unwrap();
+    assert_eq!(members.status, 0);
+    let members = str::from_utf8(members.output);
+    let members: ~[&str] = members.line_iter().collect();
+    debug2!("install_static_library_using_std: members are {:?}", members);
+    // libstd's archive bundles __morestack as well, but the library gets
+    // each object only once
+    let once = |prefix: &str| members.iter().count(|m| m.starts_with(prefix)) == 1;
+    assert!(once(format!("{}foo-", os::consts::DLL_PREFIX).as_slice()));
+    assert!(once(format!("{}std-", os::consts::DLL_PREFIX).as_slice()));
+    assert!(once("morestack"));
+}
+
+#[test]
+fn pkg_link_reaches_linker() {
+    let p_id = PkgId::new("foo");
+    let workspace = create_local_package(&p_id);
+    let workspace = workspace.path();
+    writeFile(&workspace.push_many(["src", "foo-0.1", "main.rs"]),
+              "#[pkg_link(lib = \"rustpkg_no_such_native_lib\", path = \"native\")]; \
+               fn main() {}");
+    // The build only fails if the -l flag made it to the linker
+    let output = command_line_test_expect_error([~"build", ~"foo"], workspace,
+                                                COPY_FAILED_CODE, "linking with `");
+    assert!(str::from_utf8(output.error).contains("-lrustpkg_no_such_native_lib"));
+    assert!(!built_executable_exists(workspace, "foo"));
+}
+
+#[test]
+fn linkage_static_requires_archive() {
+    let p_id = PkgId::new("foo");
+    let dep_id = PkgId::new("bar");
+    let workspace = create_local_package_with_dep(&p_id, &dep_id);
+    let workspace = workspace.path();
+    // bar is only built as a dynamic library, so it can't be linked statically
+    command_line_test_expect_error([~"build", ~"--linkage", ~"bar=static", ~"foo"],
+                                   workspace, COPY_FAILED_CODE,
+                                   "cannot link `bar` statically");
+    assert!(!built_executable_exists(workspace, "foo"));
+    // Whereas a preference for static linkage falls back to the dylib
+    command_line_test([~"build", ~"--linkage", ~"prefer-static", ~"foo"], workspace);
+    assert_built_executable_exists(workspace, "foo");
+}
+
+#[test]
+fn linkage_links_bundled_crates_once() {
+    let dir = TempDir::new("linkage_links_bundled_crates_once")
+        .expect("linkage_links_bundled_crates_once");
+    let dir = dir.path();
+    writeFile(&dir.push("bar.rs"), "#[link(name = \"bar\", vers = \"0.1\")]; \
+                                    pub fn f() -> int { 1 }");
+    writeFile(&dir.push("foo.rs"), "#[link(name = \"foo\", vers = \"0.1\")]; \
+                                    extern mod bar; pub fn g() -> int { bar::f() }");
+    writeFile(&dir.push("main.rs"), "extern mod foo; extern mod bar; \
+                                     fn main() { assert_eq!(foo::g(), bar::f()); }");
+    let rustc = |args: ~[~str]| {
+        let rustc = rustpkg_exec().with_filename("rustc");
+        let mut args = args;
+        args.push_all([~"--sysroot", test_sysroot().to_str(), ~"-L", dir.to_str()]);
+        let mut prog = run::Process::new(rustc.to_str(), args, run::ProcessOptions {
+            dir: Some(dir),
+            .. run::ProcessOptions::new()
+        }).unwrap();
+        prog.finish_with_output()
+    };
+    for krate in ["bar", "foo"].iter() {
+        for kind in ["--lib", "--staticlib"].iter() {
+            let out = rustc(~[kind.to_owned(), format!("{}.rs", *krate)]);
+            assert_eq!(out.status, 0);
+        }
+    }
+
+    // foo's archive bundles bar, so bar isn't linked on its own as well
+    let out = rustc(~[~"main.rs", ~"--linkage", ~"prefer-static", ~"-Z", ~"print-link-args"]);
+    assert_eq!(out.status, 0);
+    let link_args = str::from_utf8(out.output);
+    debug2!("linkage_links_bundled_crates_once: {}", link_args);
+    assert!(link_args.contains(format!("{}foo-", os::consts::DLL_PREFIX).as_slice()));
+    assert!(!link_args.contains(format!("{}bar-", os::consts::DLL_PREFIX).as_slice()));
+    assert!(!link_args.contains(" -lbar-"));
+
+    // Nor can it be linked dynamically next to the archive
+    let out = rustc(~[~"main.rs", ~"--linkage", ~"prefer-static",
+                      ~"--linkage", ~"bar=dynamic"]);
+    assert!(out.status != 0);
+    assert!(str::from_utf8(out.error).contains("cannot link `bar` dynamically: \
+                                                it is bundled into"));
+}
+
+#[test]
+fn dependency_lints_capped() {
+    let p_id = PkgId::new("foo");
+    let dep_id = PkgId::new("bar");
+    let workspace = create_local_package_with_dep(&p_id, &dep_id);
+    let workspace = workspace.path();
+    writeFile(&workspace.push_many([~"src", dep_id.to_str(), ~"lib.rs"]),
+              "#[deny(warnings)]; pub fn f() { let unused = 1; }");
+    // bar doesn't build on its own...
+    command_line_test_expect_error([~"build", ~"bar"], workspace, COPY_FAILED_CODE,
+                                   "unused variable: `unused`");
+    // ...but does as a dependency, where its lints are the author's business
+    command_line_test([~"build", ~"foo"], workspace);
+    assert_built_executable_exists(workspace, "foo");
+}
+
+#[test]
+fn bad_dependency_points_at_extern_mod() {
+    let p_id = PkgId::new("foo");
+    let workspace = create_local_package(&p_id);
+    let workspace = workspace.path();
+    writeFile(&workspace.push_many([~"src", ~"foo-0.1", ~"main.rs"]),
+              "// Not a package id\nextern mod quux = \"/an/absolute/pkgid\";\nfn main() {}");
+    let mut prog = run::Process::new(rustpkg_exec().to_str(), [~"build", ~"foo"],
+                                     run::ProcessOptions {
+        dir: Some(workspace),
+        .. run::ProcessOptions::new()
+    }).unwrap();
+    let output = prog.finish_with_output();
+    assert!(output.status != 0);
+    let error = str::from_utf8(output.error);
+    debug2!("bad_dependency_points_at_extern_mod: stderr was {}", error);
+    // The error carries the location of the `extern mod`, like any other
+    // compile error
+    assert!(error.contains("main.rs:2:"));
+    assert!(error.contains("invalid package id `/an/absolute/pkgid`: absolute pkgid"));
+}
+
+#[test]
+fn warnings_replayed_when_fresh() {
+    let p_id = PkgId::new("foo");
+    let workspace = create_local_package(&p_id);
+    let workspace = workspace.path();
+    writeFile(&workspace.push_many([~"src", ~"foo-0.1", ~"main.rs"]),
+              "fn main() { let unused = 1; }");
+    // The second build compiles nothing, but shows the same warning
+    for _ in range(0, 2) {
+        let mut prog = run::Process::new(rustpkg_exec().to_str(), [~"build", ~"foo"],
+                                         run::ProcessOptions {
+            dir: Some(workspace),
+            .. run::ProcessOptions::new()
+        }).unwrap();
+        let output = prog.finish_with_output();
+        assert_eq!(output.status, 0);
+        let error = str::from_utf8(output.error);
+        debug2!("warnings_replayed_when_fresh: stderr was {}", error);
+        assert!(error.contains("main.rs:1:"));
+        assert!(error.contains("unused variable: `unused`"));
+    }
+}
+
+#[test]
+fn build_waits_for_workspace_lock() {
+    let p_id = PkgId::new("foo");
+    let workspace = create_local_package(&p_id);
+    let workspace = workspace.path();
+    let lock_file = workspace_lock_file(workspace);
+    assert!(os::mkdir_recursive(&lock_file.dir_path(), U_RWX));
+    let mut prog = {
+        let _lock = os::lock_file(&lock_file).expect("couldn't lock the workspace");
+        writeFile(&lock_file, "12345");
+
+        let mut prog = run::Process::new(rustpkg_exec().to_str(), [~"build", ~"foo"],
+                                         run::ProcessOptions {
+            dir: Some(workspace),
+            .. run::ProcessOptions::new()
+        }).unwrap();
+        // Wait for rustpkg to say it's waiting before letting it go on
+        let mut line = ~"";
+        loop {
+            let b = prog.output().read_byte();
+            assert!(b >= 0, "rustpkg exited without waiting for the lock");
+            if b == '\n' as int {
+                debug2!("build_waits_for_workspace_lock: line was {}", line);
+                if line.contains("waiting for lock") {
+                    break;
+                }
+                line = ~"";
+            } else {
+                line.push_char(b as u8 as char);
+            }
+        }
+        assert!(line.contains("held by pid 12345"));
+        prog
+    };
+
+    let pid = prog.get_id();
+    assert_eq!(prog.finish(), 0);
+    assert_built_executable_exists(workspace, "foo");
+    // The lock file now names the process that last held it
+    assert_eq!(io::read_whole_file_str(&lock_file).unwrap().trim().to_owned(),
+               pid.to_str());
+}
+
+#[test]
+fn workspace_lock_is_reentrant_within_a_run() {
+    let dir = TempDir::new("workspace_lock").expect("workspace_lock_is_reentrant_within_a_run");
+    let workspace = dir.path();
+    let lock_file = workspace_lock_file(workspace);
+    let cx = fake_ctxt(test_sysroot()).context;
+    {
+        let _outer = lock_workspaces(&cx, [workspace.clone()]);
+        {
+            // As a dependency built in the same run would take it
+            let _inner = lock_workspaces(&cx.clone(), [workspace.clone()]);
+            assert!(os::try_lock_file(&lock_file).is_none());
+        }
+        // Letting go of the inner lock leaves the workspace locked
+        assert!(os::try_lock_file(&lock_file).is_none());
     }
+    assert!(os::try_lock_file(&lock_file).is_some());
+}
+
+#[test]
+fn install_with_alias() {
+    let p_id = PkgId::new("rust-foo");
+    let workspace = create_local_package(&p_id);
+    let workspace = workspace.path();
+    command_line_test([~"install", ~"rust-foo", ~"as", ~"foo"], workspace);
+    // The library is named after the alias, but is still found by its path
+    assert!(library_in_workspace(&p_id.path, "foo", Install, workspace, "lib",
+                                 &NoVersion).is_some());
+    assert!(library_in_workspace(&p_id.path, "rust-foo", Install, workspace, "lib",
+                                 &NoVersion).is_none());
+    assert_lib_exists(workspace, &p_id.path, NoVersion);
+    command_line_test_expect_fail([~"install", ~"rust-foo", ~"as"], workspace, None,
+                                  BAD_FLAG_CODE);
+}
+
+#[test]
+fn only_build_and_install_take_an_alias() {
+    let p_id = PkgId::new("foo");
+    let workspace = create_local_package(&p_id);
+    let workspace = workspace.path();
+    // For `do`, the argument after the package ID is a command name, even
+    // if that name is `as`
+    let output = rustpkg_subprocess([~"do", ~"foo", ~"as"], workspace, None);
+    assert!(!str::from_utf8(output.error).contains("expected a name after"));
+    assert!(output.status != BAD_FLAG_CODE);
+}
+
+#[test]
+fn dependency_keeps_alias() {
+    let p_id = PkgId::new("rust-foo");
+    let workspace = create_local_package(&p_id);
+    let workspace = workspace.path();
+    writeFile(&workspace.push_many([~"src", ~"rust-foo-0.1", ~"lib.rs"]), "pub fn f() {}");
+    command_line_test([~"install", ~"rust-foo", ~"as", ~"foo"], workspace);
+
+    let importer = PkgId::new("importer");
+    let importer_dir = create_local_package_in(&importer, workspace);
+    writeFile(&importer_dir.push("main.rs"),
+              "extern mod foo = \"rust-foo\";\nfn main() { foo::f(); }");
+    command_line_test([~"build", ~"importer"], workspace);
+    // Building the importer didn't install the dependency under its own name
+    assert!(library_in_workspace(&p_id.path, "rust-foo", Install, workspace, "lib",
+                                 &NoVersion).is_none());
+}
+
+#[test]
+fn declared_pkg_metadata() {
+    let p_id = PkgId::new("foo");
+    let workspace = create_local_package(&p_id);
+    let workspace = workspace.path();
+    writeFile(&workspace.push_many([~"src", ~"foo-0.1", ~"lib.rs"]),
+              "#[pkg(name = \"quux\", vers = \"0.3\", license = \"MIT\")];\npub fn f() {}");
+    command_line_test([~"build", ~"foo"], workspace);
+    // The library is named and versioned as declared, not after its directory
+    let declared = PkgId {
+        short_name: ~"quux",
+        version: ExactRevision(~"0.3"),
+        .. p_id.clone()
+    };
+    assert!(built_library_in_workspace(&declared, workspace).is_some());
+    assert!(built_library_in_workspace(&p_id, workspace).is_none());
+}
+
+#[test]
+fn dependency_by_declared_name() {
+    let p_id = PkgId::new("foo");
+    let workspace = create_local_package(&p_id);
+    let workspace = workspace.path();
+    writeFile(&workspace.push_many([~"src", ~"foo-0.1", ~"lib.rs"]),
+              "#[pkg(name = \"quux\")];\npub fn f() {}");
+    writeFile(&workspace.push_many([~"src", ~"foo-0.1", ~"test.rs"]),
+              "#[test] fn t() {}");
+
+    let importer = PkgId::new("bar");
+    let importer_dir = create_local_package_in(&importer, workspace);
+    writeFile(&importer_dir.push("main.rs"),
+              "extern mod quux;\nfn main() { quux::f(); }");
+    command_line_test([~"build", ~"bar"], workspace);
+    assert!(library_in_workspace(&p_id.path, "quux", Install, workspace, "lib",
+                                 &NoVersion).is_some());
+    assert_built_executable_exists(workspace, "bar");
+
+    // The package can be asked for by its declared name, too
+    command_line_test([~"build", ~"quux"], workspace);
+    assert!(built_library_in_workspace(&p_id.with_alias("quux"), workspace).is_some());
+    command_line_test([~"test", ~"quux"], workspace);
 }
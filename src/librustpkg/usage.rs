@@ -8,111 +8,143 @@
 // option. This file may not be copied, modified, or distributed
 // except according to those terms.
 
-use std::io;
+// What each command does, for its help. The options it accepts are listed
+// after this, from the ones its command line is parsed with.
 
-pub fn general() {
-    io::println("Usage: rustpkg [options] <cmd> [args..]
+pub fn general() -> &'static str {
+    "Usage: rustpkg [options] <cmd> [args..]
 
-Where <cmd> is one of:
-    build, clean, do, info, install, list, prefer, test, uninstall, unprefer
-
-Options:
-
-    -h, --help                  Display this message
-    --sysroot PATH              Override the system root
-    <cmd> -h, <cmd> --help      Display help for <cmd>");
+Run `rustpkg <cmd> --help` for help with <cmd>."
 }
 
-pub fn build() {
-    io::println("rustpkg build [options..] [package-ID]
+pub fn build() -> &'static str {
+    "rustpkg build [options..] [package-ID [as NAME]]
 
 Build the given package ID if specified. With no package ID argument,
 build the package in the current directory. In that case, the current
 directory must be a direct child of an `src` directory in a workspace.
+With `as NAME`, the package's library is named NAME rather than after
+the last component of the package ID.
+
+The pretty-printing modes, given as --pretty=MODE, are normal, expanded,
+typed, identified and expanded,identified. The linkage is one of
+prefer-static, prefer-dynamic, CRATE=static or CRATE=dynamic. See
+`rustc --help` for the -Z flags."
+}
 
-Options:
-    -c, --cfg      Pass a cfg flag to the package script
-    --no-link      Compile and assemble, but don't link (like -c in rustc)
-    --no-trans     Parse and translate, but don't generate any code
-    --pretty       Pretty-print the code, but don't generate output
-    --parse-only   Parse the code, but don't typecheck or generate code
-    -S             Generate assembly code, but don't assemble or link it
-    -S --emit-llvm Generate LLVM assembly code
-    --emit-llvm    Generate LLVM bitcode
-    --linker PATH  Use a linker other than the system linker
-    --link-args [ARG..] Extra arguments to pass to the linker
-    --opt-level=n  Set the optimization level (0 <= n <= 3)
-    -O             Equivalent to --opt-level=2
-    --save-temps   Don't delete temporary files
-    --target TRIPLE Set the target triple
-    --target-cpu CPU Set the target CPU
-    -Z FLAG        Enable an experimental rustc feature (see `rustc --help`)");
-}
-
-pub fn clean() {
-    io::println("rustpkg clean
+pub fn clean() -> &'static str {
+    "rustpkg clean
 
 Remove all build files in the work cache for the package in the current
-directory.");
+directory."
 }
 
-pub fn do_cmd() {
-    io::println("rustpkg do <cmd>
+pub fn completions() -> &'static str {
+    "rustpkg completions <bash|zsh>
+
+Print a script that makes the given shell complete rustpkg's commands,
+their options, and the names of installed packages. For example:
+
+    rustpkg completions bash > /etc/bash_completion.d/rustpkg
+    rustpkg completions zsh > ~/.zsh/completion/_rustpkg"
+}
+
+pub fn env() -> &'static str {
+    "rustpkg env [options..]
+
+Show the workspaces in the RUST_PATH and whether they exist, the sysroot,
+the workspace packages would be installed to, the cfgs, and the environment
+variables rustpkg reads. Each setting says where it came from."
+}
+
+pub fn do_cmd() -> &'static str {
+    "rustpkg do <cmd>
 
 Runs a command in the package script. You can listen to a command
-by tagging a function with the attribute `#[pkg_do(cmd)]`.");
+by tagging a function with the attribute `#[pkg_do(cmd)]`."
 }
 
-pub fn info() {
-    io::println("rustpkg [options..] info
+pub fn fetch() -> &'static str {
+    "rustpkg fetch [package-ID]
+
+Fetch the sources of the given package ID, or of the package in the
+current directory, and of every package they depend on with `extern mod`,
+into the workspace without building anything. Afterwards `build --offline`
+and `install --offline` need no network access.
 
-Probe the package script in the current directory for information.
+Packages found in a workspace are left as they are. The others are cloned
+into the build directory of the default workspace, where building them
+would put them. Dependencies are read from every crate in a package,
+whatever cfgs they depend on, and from its package script.
 
-Options:
-    -j, --json      Output the result as JSON");
+Example:
+    rustpkg fetch github.com/mozilla/servo
+    rustpkg build --offline github.com/mozilla/servo"
 }
 
-pub fn list() {
-    io::println("rustpkg list
+pub fn info() -> &'static str {
+    "rustpkg info <pkg-id>
 
-List all installed packages.");
+Show what the installed library for <pkg-id> records about itself:
+its name, version and hash, the license it declares, the crates it was
+built against, and the cfg it was compiled with."
 }
 
-pub fn install() {
-    io::println("rustpkg install [options..] [package-ID]
+pub fn list() -> &'static str {
+    "rustpkg list
+
+List all installed packages."
+}
+
+pub fn install() -> &'static str {
+    "rustpkg install [options..] [package-ID [as NAME]]
 
 Install the given package ID if specified. With no package ID
 argument, install the package in the current directory.
 In that case, the current directory must be a direct child of a
 `src` directory in a workspace.
+With `as NAME`, the package's library is installed as NAME rather than
+after the last component of the package ID, and packages that depend on
+it keep using that name.
+
+With --symlink, rebuilding the package updates the installed executables.
+Where symlinks can't be made, they're copied as usual. Documentation
+generated with --with-docs goes into the workspace's doc directory, and
+is installed along with the package from then on, even without the flag.
 
 Examples:
     rustpkg install
     rustpkg install github.com/mozilla/servo
     rustpkg install github.com/mozilla/servo#0.1.2
-
-Options:
-    -c, --cfg      Pass a cfg flag to the package script
-    --emit-llvm    Generate LLVM bitcode
-    --linker PATH  Use a linker other than the system linker
-    --link-args [ARG..] Extra arguments to pass to the linker
-    --opt-level=n  Set the optimization level (0 <= n <= 3)
-    -O             Equivalent to --opt-level=2
-    --save-temps   Don't delete temporary files
-    --target TRIPLE Set the target triple
-    --target-cpu CPU Set the target CPU
-    -Z FLAG        Enable an experimental rustc feature (see `rustc --help`)");
+    rustpkg install github.com/foo/rust-http as http"
 }
 
-pub fn uninstall() {
-    io::println("rustpkg uninstall <id|name>[@version]
+pub fn uninstall() -> &'static str {
+    "rustpkg uninstall [options..] <id|name>[@version]
 
 Remove a package by id or name and optionally version. If the package(s)
-is/are depended on by another package then they cannot be removed.");
+is/are depended on by another package then they cannot be removed.
+
+Exactly the files that installing the package wrote are removed. If any
+of them have changed since, nothing is removed."
 }
 
-pub fn prefer() {
-    io::println("rustpkg [options..] prefer <id|name>[@version]
+pub fn self_update() -> &'static str {
+    "rustpkg self-update [options..]
+
+Install a newer rustpkg from a channel, if the channel has one. A channel
+is a directory or a repository holding a channel.json manifest that lists
+the files of each component with their SHA-1 hashes. The manifest must
+have the SHA-256 given with --manifest-sha256, which should come from the
+channel's maintainers by a means you trust. Nothing is installed unless
+every file matches its hash, and then either every file is installed or
+none is.
+
+By default rustpkg is installed into the sysroot it's running from."
+}
+
+pub fn prefer() -> &'static str {
+    "rustpkg [options..] prefer <id|name>[@version]
 
 By default all binaries are given a unique name so that multiple versions can
 coexist. The prefer command will symlink the uniquely named binary to
@@ -126,33 +158,36 @@ Example:
     ==> v1.2.4
     rustpkg prefer machine@0.4.6
     machine -v
-    ==> v0.4.6");
+    ==> v0.4.6"
 }
 
-pub fn unprefer() {
-    io::println("rustpkg [options..] unprefer <id|name>[@version]
+pub fn unprefer() -> &'static str {
+    "rustpkg [options..] unprefer <id|name>[@version]
 
 Remove all symlinks from the store to the binary directory for a package
 name and optionally version. If version is not supplied, the latest version
 of the package will be unpreferred. See `rustpkg prefer -h` for more
-information.");
+information."
 }
 
-pub fn test() {
-    io::println("rustpkg [options..] test
+pub fn test() -> &'static str {
+    "rustpkg [options..] test
 
 Build all test crates in the current directory with the test flag.
 Then, run all the resulting test executables, redirecting the output
-and exit code.
+and exit code."
+}
+
+pub fn version() -> &'static str {
+    "rustpkg version [options..]
 
-Options:
-    -c, --cfg      Pass a cfg flag to the package script");
+Show the version of rustpkg and the commit it was built from, the version
+of rustc in the sysroot, and the host triple."
 }
 
-pub fn init() {
-    io::println("rustpkg init
+pub fn init() -> &'static str {
+    "rustpkg init
 
 This will turn the current working directory into a workspace. The first
-command you run when starting off a new project.
-");
+command you run when starting off a new project."
 }
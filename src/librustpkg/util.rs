@@ -8,10 +8,18 @@
 // option. This file may not be copied, modified, or distributed
 // except according to those terms.
 
+use std::cell::Cell;
+use std::comm::{GenericPort, oneshot};
+use std::io;
 use std::libc;
 use std::os;
+use std::rt::io::io_error;
+use std::run;
+use std::task;
+use std::util;
 use extra::workcache;
 use rustc::driver::{driver, session};
+use rustc::driver::driver::PpMode;
 use extra::getopts::groups::getopts;
 use syntax::ast_util::*;
 use syntax::codemap::{dummy_sp, Spanned};
@@ -23,21 +31,56 @@ use syntax::visit::Visitor;
 use rustc::back::link::output_type_exe;
 use rustc::back::link;
 use rustc::driver::session::{lib_crate, bin_crate};
-use context::{in_target, StopBefore, Link, Assemble, BuildContext};
+use rustc::metadata::filesearch::rust_path_lib_dir;
+use rustc::metadata::loader;
+use rustc::metadata::loader::CrateInfo;
+use diagnostics::{RecordedDiagnostic, diagnostics_file, write_diagnostics};
+use context::{in_target, StopBefore, Link, Assemble, BuildContext, Context};
+use context::{Nothing, Pretty, Trans, Analysis, LLVMAssemble, LLVMCompileBitcode};
+use conditions::{bad_pkg_id, nonexistent_package, missing_pkg_files};
 use package_id::PkgId;
 use package_source::PkgSrc;
-use workspace::pkg_parent_workspaces;
-use path_util::{U_RWX, system_library, target_build_dir};
-use path_util::{default_workspace, built_library_in_workspace};
+use workspace::{pkg_parent_workspaces, resolve_declared_name, lock_workspaces};
+use path_util::{U_RWX, system_library, target_build_dir, installed_library_in_workspace};
 pub use target::{OutputType, Main, Lib, Bench, Test, JustOne, lib_name_of, lib_crate_filename};
 use workcache_support::{digest_file_with_date, digest_only_date};
 
+/// Collects the native linking a crate asks rustpkg for, e.g.
+/// `#[pkg_link(lib = "z", path = "native", arg = "-pthread")];`.
+/// `path` is relative to `crate_dir`, the directory holding the crate file.
+pub fn crate_linker_args(sess: session::Session,
+                         crate: &ast::Crate,
+                         crate_dir: &Path) -> session::CrateLinkerArgs {
+    let mut linker_args = session::CrateLinkerArgs::new();
+    for attr in crate.attrs.iter().filter(|a| "pkg_link" == a.name()) {
+        let items = match attr.meta_item_list() {
+            Some(items) => items,
+            None => {
+                sess.span_err(attr.span, "expected #[pkg_link(key = \"value\", ...)]");
+                continue;
+            }
+        };
+        for mi in items.iter() {
+            match mi.name_str_pair() {
+                Some((key, value)) => match key.as_slice() {
+                    "lib"  => linker_args.libs.push(value.to_owned()),
+                    "path" => linker_args.search_paths.push(crate_dir.push_rel(&Path(value))),
+                    "arg"  => linker_args.args.push(value.to_owned()),
+                    _      => sess.span_err(mi.span, format!("unknown pkg_link key `{}`", key))
+                },
+                None => sess.span_err(mi.span, "expected a `key = \"value\"` pair in pkg_link")
+            }
+        }
+    }
+    linker_args
+}
+
 // It would be nice to have the list of commands in just one place -- for example,
 // you could update the match in rustpkg.rc but forget to update this list. I think
 // that should be fixed.
 static COMMANDS: &'static [&'static str] =
-    &["build", "clean", "do", "info", "init", "install", "list", "prefer", "test", "uninstall",
-      "unprefer"];
+    &["build", "clean", "completions", "do", "env", "fetch", "info", "init", "install", "list",
+      "prefer", "self-update", "test", "uninstall", "unprefer", "version"];
 
 
 pub type ExitCode = int; // For now
@@ -161,6 +204,37 @@ pub fn ready_crate(sess: session::Session,
     fold.fold_crate(crate)
 }
 
+/// Says which package an error or warning came from, since a build can
+/// compile several, and records what's printed so that it can be replayed
+/// when the crate is fresh
+struct PkgDiagnosticFilter {
+    pkg: ~str,
+    recorded: @mut ~[RecordedDiagnostic]
+}
+
+impl diagnostic::DiagnosticFilter for PkgDiagnosticFilter {
+    fn filter(&self, diag: diagnostic::Diagnostic) -> Option<diagnostic::Diagnostic> {
+        let mut diag = diag;
+        if diag.level != diagnostic::note {
+            diag.message = format!("{}: {}", self.pkg, diag.message);
+        }
+        self.recorded.push(RecordedDiagnostic::new(&diag));
+        Some(diag)
+    }
+}
+
+/// A library built for the package just before its binaries. The binaries
+/// link against `lib` directly, and are compiled in sessions derived from
+/// the library's if it was compiled in the same task.
+pub struct Sibling {
+    lib: Path,
+    // The library's session and where it records its diagnostics, unless
+    // the library was fresh in the workcache
+    sess: Option<(session::Session, @mut ~[RecordedDiagnostic])>
+}
+
+/// Returns the output, if any, the session the crate was compiled in and
+/// where the session records its diagnostics
 pub fn compile_input(context: &BuildContext,
                      exec: &mut workcache::Exec,
                      pkg_id: &PkgId,
@@ -169,7 +243,9 @@ pub fn compile_input(context: &BuildContext,
                      flags: &[~str],
                      cfgs: &[~str],
                      opt: bool,
-                     what: OutputType) -> Option<Path> {
+                     what: OutputType,
+                     sibling: Option<&Sibling>)
+                     -> (Option<Path>, session::Session, @mut ~[RecordedDiagnostic]) {
     assert!(in_file.components.len() > 1);
     let input = driver::file_input((*in_file).clone());
     debug2!("compile_input: {} / {:?}", in_file.to_str(), what);
@@ -217,7 +293,7 @@ pub fn compile_input(context: &BuildContext,
     let output_type = match context.compile_upto() {
         Assemble => link::output_type_assembly,
         Link     => link::output_type_object,
-        Pretty | Trans | Analysis => link::output_type_none,
+        Pretty(_) | Trans | Analysis => link::output_type_none,
         LLVMAssemble => link::output_type_llvm_assembly,
         LLVMCompileBitcode => link::output_type_bitcode,
         Nothing => link::output_type_exe
@@ -229,6 +305,8 @@ pub fn compile_input(context: &BuildContext,
         test: what == Test || what == Bench,
         maybe_sysroot: Some(sysroot_to_use),
         addl_lib_search_paths: @mut (~[]),
+        search_rust_path: true,
+        maybe_rust_path: Some(context.context.rust_path.clone()),
         output_type: output_type,
         .. (*driver::build_session_options(binary,
                                            &matches,
@@ -237,6 +315,9 @@ pub fn compile_input(context: &BuildContext,
     };
 
     let addl_lib_search_paths = @mut options.addl_lib_search_paths;
+    for sib in sibling.iter() {
+        addl_lib_search_paths.push(sib.lib.dir_path());
+    }
     // Make sure all the library directories actually exist, since the linker will complain
     // otherwise
     for p in addl_lib_search_paths.iter() {
@@ -248,28 +329,60 @@ pub fn compile_input(context: &BuildContext,
         }
     }
 
-    let sess = driver::build_session(options,
-                                     @diagnostic::DefaultEmitter as
-                                        @diagnostic::Emitter);
+    // A sibling session reports through the library's filter
+    let (sess, recorded) = match sibling.and_then(|sib| sib.sess.clone()) {
+        Some((lib_sess, recorded)) => {
+            (driver::build_sibling_session(lib_sess, options), recorded)
+        }
+        None => {
+            let recorded = @mut ~[];
+            let filter = @PkgDiagnosticFilter {
+                pkg: pkg_id.to_str(),
+                recorded: recorded
+            } as @diagnostic::DiagnosticFilter;
+            (driver::build_filtered_session(options,
+                                            filter,
+                                            @diagnostic::DefaultEmitter as
+                                             @diagnostic::Emitter),
+             recorded)
+        }
+    };
+
+    // rustc searches the lib directories of the RUST_PATH workspaces, given
+    // it as maybe_rust_path, by itself, so only dependencies installed
+    // elsewhere need a -L flag
+    let rust_path_lib_dirs = do context.context.rust_path.map |ws| {
+        rust_path_lib_dir(ws, sess.opts.target_triple)
+    };
 
     // Infer dependencies that rustpkg needs to build, by scanning for
     // `extern mod` directives.
     let cfg = driver::build_configuration(sess);
     let mut crate = driver::phase_1_parse_input(sess, cfg.clone(), &input);
+    // Pretty-printing without expansion shows the crate as it was parsed
+    let parsed_crate = match context.compile_upto() {
+        Pretty(ppm) if !ppm.expands() => Some(crate.clone()),
+        _ => None
+    };
     crate = driver::phase_2_configure_and_expand(sess, cfg.clone(), crate);
 
     find_and_install_dependencies(context, pkg_id, sess, exec, &crate,
+                                  sibling.map(|sib| &sib.lib),
                                   |p| {
                                       debug2!("a dependency: {}", p.to_str());
                                       // Pass the directory containing a dependency
                                       // as an additional lib search path
-                                      if !addl_lib_search_paths.contains(&p) {
+                                      if !addl_lib_search_paths.contains(&p) &&
+                                         !rust_path_lib_dirs.contains(&p) {
                                           // Might be inefficient, but this set probably
                                           // won't get too large -- tjc
                                           addl_lib_search_paths.push(p);
                                       }
                                   });
 
+    // Native libraries and linker flags the package declares for itself
+    *sess.opts.crate_linker_args = crate_linker_args(sess, &crate, &in_file.dir_path());
+
     // Inject the link attributes so we get the right package name and version
     if attr::find_linkage_metas(crate.attrs).is_empty() {
         let name_to_use = match what {
@@ -288,31 +401,37 @@ pub fn compile_input(context: &BuildContext,
         crate.attrs = ~[attr::mk_attr(attr::mk_list_item(@"link", link_options))];
     }
 
-    debug2!("calling compile_crate_from_input, workspace = {},
-           building_library = {:?}", out_dir.to_str(), sess.building_library);
-    let result = compile_crate_from_input(in_file,
-                                          exec,
-                                          context.compile_upto(),
-                                          &out_dir,
-                                          sess,
-                                          crate);
-    // Discover the output
-    let discovered_output = if what == Lib  {
-        built_library_in_workspace(pkg_id, workspace) // Huh???
-    }
-    else {
-        result
+    let result = match context.compile_upto() {
+        Pretty(ppm) => {
+            discover_source_files(exec, sess);
+            let crate = parsed_crate.unwrap_or(crate);
+            Some(pretty_print_to_build_dir(sess, in_file, &out_dir, &crate, ppm))
+        }
+        stop_before => {
+            debug2!("calling compile_crate_from_input, workspace = {},
+                   building_library = {:?}", out_dir.to_str(), sess.building_library);
+            compile_crate_from_input(in_file,
+                                     exec,
+                                     stop_before,
+                                     &out_dir,
+                                     sess,
+                                     crate)
+        }
     };
-    debug2!("About to discover output {}", discovered_output.to_str());
-    for p in discovered_output.iter() {
+    // Discover the output
+    debug2!("About to discover output {}", result.to_str());
+    for p in result.iter() {
         if os::path_exists(p) {
             debug2!("4. discovering output {}", p.to_str());
-            exec.discover_output("binary", p.to_str(), digest_only_date(p));
+            exec.discover_output("binary", p.normalize().to_str(), digest_only_date(p));
         }
         // Nothing to do if it doesn't exist -- that could happen if we had the
         // -S or -emit-llvm flags, etc.
     }
-    discovered_output
+    // Kept for when the crate is fresh
+    let diags = util::replace(&mut *recorded, ~[]);
+    write_diagnostics(&diagnostics_file(workspace, pkg_id, in_file), diags);
+    (result, sess, recorded)
 }
 
 // Should use workcache to avoid recompiling when not necessary
@@ -342,6 +461,8 @@ pub fn compile_crate_from_input(input: &Path,
            outputs.out_filename.to_str(),
            outputs.obj_filename.to_str(),
            sess.opts.output_type);
+    discover_source_files(exec, sess);
+
     debug2!("additional libraries:");
     for lib in sess.opts.addl_lib_search_paths.iter() {
         debug2!("an additional library: {}", lib.to_str());
@@ -352,19 +473,44 @@ pub fn compile_crate_from_input(input: &Path,
                                                         &analysis,
                                                         outputs);
     driver::phase_5_run_llvm_passes(sess, &translation, outputs);
+    // When nothing is linked, out_filename is the object, assembly or
+    // bitcode file that was just written.
     // The second check shouldn't be necessary, but rustc seems to ignore
     // -c
     if driver::stop_after_phase_5(sess)
         || stop_before == Link || stop_before == Assemble { return Some(outputs.out_filename); }
     driver::phase_6_link_output(sess, &translation, outputs);
 
-    // Register dependency on the source file
-    exec.discover_input("file", input.to_str(), digest_file_with_date(input));
+    let built = link::output_filename(sess, &outputs.out_filename, translation.link);
+    debug2!("Built {}, date = {:?}", built.to_str(), datestamp(&built));
 
-    debug2!("Built {}, date = {:?}", outputs.out_filename.to_str(),
-           datestamp(&outputs.out_filename));
+    Some(built)
+}
 
-    Some(outputs.out_filename)
+// Register dependencies on every source file the parser read, which
+// includes the crate file, its modules and any `include!`d files
+fn discover_source_files(exec: &mut workcache::Exec, sess: session::Session) {
+    for file in driver::source_files(sess).iter() {
+        exec.discover_input("file", file.normalize().to_str(), digest_file_with_date(file));
+    }
+}
+
+/// Pretty-prints `crate`, read from `in_file`, into a file in `out_dir` named
+/// after the crate file and the mode, like `main.expanded.rs`, and returns
+/// that file
+fn pretty_print_to_build_dir(sess: session::Session, in_file: &Path, out_dir: &Path,
+                             crate: &ast::Crate, ppm: PpMode) -> Path {
+    let mode = ppm.name().replace(",", "-");
+    let stem = in_file.filestem().expect("crate file has no name");
+    let out_file = out_dir.push(format!("{}.{}.rs", stem, mode));
+    match io::file_writer(&out_file, [io::Create, io::Truncate]) {
+        Ok(writer) => {
+            driver::pretty_print_crate(sess, &driver::file_input(in_file.clone()),
+                                       crate, ppm, writer);
+        }
+        Err(e) => sess.fatal(format!("couldn't write `{}`: {}", out_file.to_str(), e))
+    }
+    out_file
 }
 
 #[cfg(windows)]
@@ -388,7 +534,9 @@ pub fn compile_crate(ctxt: &BuildContext,
     for fl in flags.iter() {
         debug2!("+++ {}", *fl);
     }
-    compile_input(ctxt, exec, pkg_id, crate, workspace, flags, cfgs, opt, what)
+    let (result, _, _) = compile_input(ctxt, exec, pkg_id, crate, workspace, flags, cfgs, opt,
+                                       what, None);
+    result
 }
 
 struct ViewItemVisitor<'self> {
@@ -398,12 +546,44 @@ struct ViewItemVisitor<'self> {
     exec: &'self mut workcache::Exec,
     c: &'self ast::Crate,
     save: &'self fn(Path),
+    // The library built earlier in this task for the package being compiled
+    local_lib: Option<&'self Path>,
+}
+
+impl<'self> ViewItemVisitor<'self> {
+    /// Stops the build if the library `lib`, which satisfies the
+    /// `extern mod` at `sp`, was built for another target or without one
+    /// of the cfgs given on the command line, rather than leaving that to
+    /// show up as a confusing failure later on.
+    fn check_compatible(&self, lib: &Path, sp: codemap::Span) {
+        let info = match library_info(lib) {
+            Some(info) => info,
+            None => return
+        };
+        let cfg = driver::parse_cfgspecs(self.context.context.cfgs.clone(),
+                                         @diagnostic::DefaultEmitter as
+                                            @diagnostic::Emitter);
+        let triple = self.sess.targ_cfg.target_strs.target_triple.as_slice();
+        for why in info.incompatibility(triple, cfg).iter() {
+            self.sess.span_fatal(sp, format!("can't use {}: {}", lib.to_str(), *why));
+        }
+    }
 }
 
 impl<'self> Visitor<()> for ViewItemVisitor<'self> {
     fn visit_view_item(&mut self, vi: &ast::view_item, env: ()) {
         debug2!("A view item!");
         match vi.node {
+            // The package's own library, which was just built
+            ast::view_item_extern_mod(lib_ident, None, _, _)
+                if self.local_lib.is_some() &&
+                   self.parent.short_name.equiv(&self.sess.str_of(lib_ident)) => {
+                let lib = self.local_lib.unwrap();
+                debug2!("Using the library built with this crate: {}", lib.to_str());
+                self.exec.discover_input("binary",
+                                         lib.normalize().to_str(),
+                                         digest_only_date(lib));
+            }
             // ignore metadata, I guess
             ast::view_item_extern_mod(lib_ident, path_opt, _, _) => {
                 let lib_name = match path_opt {
@@ -422,15 +602,27 @@ impl<'self> Visitor<()> for ViewItemVisitor<'self> {
                         // Now we know that this crate has a discovered dependency on
                         // installed_path
                         self.exec.discover_input("binary",
-                                                 installed_path.to_str(),
+                                                 installed_path.normalize().to_str(),
                                                  digest_only_date(installed_path));
                     }
                     None => {
                         // FIXME #8711: need to parse version out of path_opt
                         debug2!("Trying to install library {}, rebuilding it",
                                lib_name.to_str());
+                        // Problems finding or building the dependency are
+                        // reported against the `extern mod` that asked for it
+                        let sess = self.sess;
+                        let sp = vi.span;
                         // Try to install it
-                        let pkg_id = PkgId::new(lib_name);
+                        let pkg_id = do bad_pkg_id::cond.trap(|(_, e)| {
+                            sess.span_fatal(sp, format!("invalid package id `{}`: {}",
+                                                        lib_name, e))
+                        }).inside {
+                            PkgId::new_in(lib_name, self.context.context.rust_path)
+                        };
+                        // The library may be named by a package's `pkg`
+                        // attribute rather than by its directory
+                        let pkg_id = resolve_declared_name(&self.context.context, pkg_id);
                         // Find all the workspaces in the RUST_PATH that contain this package.
                         let workspaces = pkg_parent_workspaces(&self.context.context,
                                                                &pkg_id);
@@ -442,21 +634,51 @@ impl<'self> Visitor<()> for ViewItemVisitor<'self> {
                         // (b) `workspaces` is non-empty -- we found a local source for this
                         // package.
                         let dest_workspace = if workspaces.is_empty() {
-                            default_workspace()
+                            self.context.context.default_workspace()
                         } else { workspaces[0] };
+                        // If the package was installed under an alias, keep
+                        // building it under that name
+                        let pkg_id = match installed_alias(&pkg_id, &dest_workspace) {
+                            Some(alias) => {
+                                debug2!("{} is installed as {}", pkg_id.to_str(), alias);
+                                pkg_id.with_alias(alias)
+                            }
+                            None => pkg_id
+                        };
                         // In this case, the source and destination workspaces are the same:
                         // Either it's a remote package, so the local sources don't exist
                         // and the `PkgSrc` constructor will detect that;
                         // or else it's already in a workspace and we'll build into that
                         // workspace
-                        let pkg_src = PkgSrc::new(dest_workspace.clone(),
-                                                  dest_workspace,
-                        // Use the rust_path_hack to search for dependencies iff
-                        // we were already using it
-                                                  self.context.context.use_rust_path_hack,
-                                                  pkg_id);
+                        let use_rust_path_hack = self.context.context.use_rust_path_hack;
+                        let offline = self.context.context.offline;
+                        // Fetching and installing the dependency writes to
+                        // its workspace, which this run may not have locked
+                        // yet
+                        let _locks = lock_workspaces(&self.context.context,
+                                                     [dest_workspace.clone()]);
+                        let pkg_src = do nonexistent_package::cond.trap(|(id, _)| {
+                            sess.span_fatal(sp, format!("can't find package `{}` in any \
+                                                         workspace", id.to_str()))
+                        }).inside {
+                            PkgSrc::new_in(&self.context.context,
+                                           dest_workspace.clone(),
+                                           dest_workspace.clone(),
+                                           // Use the rust_path_hack to search for
+                                           // dependencies iff we were already using it
+                                           use_rust_path_hack,
+                                           offline,
+                                           pkg_id.clone())
+                        };
                         let (outputs_disc, inputs_disc) =
-                            self.context.install(pkg_src, &JustOne(Path(lib_crate_filename)));
+                            do missing_pkg_files::cond.trap(|id| {
+                                sess.span_fatal(sp, format!("package `{}` has no crates \
+                                                             to build", id.to_str()))
+                            }).inside {
+                                self.context.for_dependency()
+                                    .install(pkg_src.clone(),
+                                             &JustOne(Path(lib_crate_filename)))
+                            };
                         debug2!("Installed {}, returned {:?} dependencies and \
                                {:?} transitive dependencies",
                                lib_name, outputs_disc.len(), inputs_disc.len());
@@ -467,8 +689,9 @@ impl<'self> Visitor<()> for ViewItemVisitor<'self> {
                         for dep in outputs_disc.iter() {
                             debug2!("Discovering a binary input: {}", dep.to_str());
                             self.exec.discover_input("binary",
-                                                     dep.to_str(),
+                                                     dep.normalize().to_str(),
                                                      digest_only_date(dep));
+                            self.check_compatible(dep, vi.span);
                             // Also, add an additional search path
                             debug2!("Installed {} into {}", dep.to_str(), dep.pop().to_str());
                             (self.save)(dep.pop());
@@ -506,6 +729,7 @@ pub fn find_and_install_dependencies(context: &BuildContext,
                                      sess: session::Session,
                                      exec: &mut workcache::Exec,
                                      c: &ast::Crate,
+                                     local_lib: Option<&Path>,
                                      save: &fn(Path)) {
     debug2!("In find_and_install_dependencies...");
     let mut visitor = ViewItemVisitor {
@@ -515,6 +739,7 @@ pub fn find_and_install_dependencies(context: &BuildContext,
         exec: exec,
         c: c,
         save: save,
+        local_lib: local_lib,
     };
     visit::walk_crate(&mut visitor, c, ())
 }
@@ -534,13 +759,17 @@ mod test {
     fn test_is_cmd() {
         assert!(is_cmd("build"));
         assert!(is_cmd("clean"));
+        assert!(is_cmd("completions"));
         assert!(is_cmd("do"));
+        assert!(is_cmd("env"));
         assert!(is_cmd("info"));
         assert!(is_cmd("install"));
         assert!(is_cmd("prefer"));
+        assert!(is_cmd("self-update"));
         assert!(is_cmd("test"));
         assert!(is_cmd("uninstall"));
         assert!(is_cmd("unprefer"));
+        assert!(is_cmd("version"));
     }
 
 }
@@ -557,6 +786,28 @@ fn debug_flags() -> ~[~str] { ~[] }
 // static DEBUG_FLAGS: ~[~str] = ~[~"-Z", ~"time-passes"];
 
 
+/// Reads the metadata rustc recorded in the library at `lib`, without
+/// invoking the compiler
+pub fn library_info(lib: &Path) -> Option<CrateInfo> {
+    let os = driver::get_os(driver::host_triple()).expect("unknown host OS");
+    loader::read_crate_info(session::sess_os_to_meta_os(os), lib)
+}
+
+/// The name the library of `pkg_id` is installed under in `workspace`,
+/// if it was installed under an alias rather than its own short name
+pub fn installed_alias(pkg_id: &PkgId, workspace: &Path) -> Option<~str> {
+    let lib = match installed_library_in_workspace(&pkg_id.path, workspace) {
+        Some(lib) => lib,
+        None => return None
+    };
+    match library_info(&lib) {
+        Some(ref info) if info.name.as_slice() != pkg_id.path_name() => {
+            Some(info.name.to_owned())
+        }
+        _ => None
+    }
+}
+
 /// Returns the last-modified date as an Option
 pub fn datestamp(p: &Path) -> Option<libc::time_t> {
     debug2!("Scrutinizing datestamp for {} - does it exist? {:?}", p.to_str(), os::path_exists(p));
@@ -564,3 +815,119 @@ pub fn datestamp(p: &Path) -> Option<libc::time_t> {
     debug2!("Date = {:?}", out);
     out.map(|t| { t as libc::time_t })
 }
+
+/// Runs `prog` as `run::process_output` does, but gives back an error
+/// message rather than raising `io_error` if it can't be started at all,
+/// for instance because it isn't installed
+pub fn process_output(prog: &str, args: &[~str]) -> Result<run::ProcessOutput, ~str> {
+    trap_spawn_error(prog, || run::process_output(prog, args))
+}
+
+/// Runs `prog` as `run::process_status` does, but gives back an error
+/// message rather than raising `io_error` if it can't be started at all
+pub fn process_status(prog: &str, args: &[~str]) -> Result<int, ~str> {
+    trap_spawn_error(prog, || run::process_status(prog, args))
+}
+
+/// As `process_output`, but runs `prog` in the working directory and with
+/// the environment variables of the run of rustpkg that `cx` describes.
+/// Its output is also shown as it's produced if `cx.verbose` is set, and
+/// it's killed if it runs for longer than `cx.timeout`.
+pub fn process_output_in(cx: &Context, prog: &str, args: &[~str])
+                         -> Result<run::ProcessOutput, ~str> {
+    let spawned = do trap_spawn_error(prog) {
+        let options = run::ProcessOptions {
+            env: run::InheritPlus(cx.env.clone()),
+            dir: Some(&cx.cwd),
+            .. run::ProcessOptions::new()
+        };
+        run::Process::new(prog, args, options)
+    };
+    let mut process = match spawned {
+        Ok(process) => process,
+        Err(e) => return Err(e)
+    };
+
+    // The output is read on another task while this one waits, so that a
+    // child that hangs with its pipes open can still be timed out
+    let port = Cell::new(process.output_port());
+    let verbose = cx.verbose;
+    let (done_port, done_chan) = oneshot();
+    let done_chan = Cell::new(done_chan);
+    do task::spawn {
+        let port = port.take();
+        let mut output = ~[];
+        let mut error = ~[];
+        loop {
+            match port.try_recv() {
+                Some(run::Stdout(data)) => {
+                    if verbose { io::stdout().write(data); }
+                    output.push_all_move(data);
+                }
+                Some(run::Stderr(data)) => {
+                    if verbose { io::stderr().write(data); }
+                    error.push_all_move(data);
+                }
+                None => break
+            }
+        }
+        done_chan.take().send((output, error));
+    }
+
+    let status = finish_in(cx, prog, &mut process);
+    let (output, error) = done_port.recv();
+    match status {
+        Ok(status) => Ok(run::ProcessOutput { status: status, output: output, error: error }),
+        Err(e) => Err(e)
+    }
+}
+
+/// As `process_status`, but runs `prog` in the working directory and with
+/// the environment variables of the run of rustpkg that `cx` describes, and
+/// kills it if it runs for longer than `cx.timeout`
+pub fn process_status_in(cx: &Context, prog: &str, args: &[~str]) -> Result<int, ~str> {
+    let spawned = do trap_spawn_error(prog) {
+        let options = run::ProcessOptions {
+            env: run::InheritPlus(cx.env.clone()),
+            dir: Some(&cx.cwd),
+            in_fd: Some(libc::STDIN_FILENO),
+            out_fd: Some(libc::STDOUT_FILENO),
+            err_fd: Some(libc::STDERR_FILENO),
+            detach: false
+        };
+        run::Process::new(prog, args, options)
+    };
+    match spawned {
+        Ok(process) => {
+            let mut process = process;
+            finish_in(cx, prog, &mut process)
+        }
+        Err(e) => Err(e)
+    }
+}
+
+// Waits for `process`, which is running `prog`, to exit, and kills it if
+// it runs for longer than `cx.timeout`
+fn finish_in(cx: &Context, prog: &str, process: &mut run::Process) -> Result<int, ~str> {
+    match cx.timeout {
+        None => Ok(process.finish()),
+        Some(secs) => match process.finish_with_timeout(secs * 1000) {
+            run::Exited(status) => Ok(status),
+            run::TimedOut => {
+                Err(format!("`{}` was killed after running for {} seconds", prog, secs))
+            }
+        }
+    }
+}
+
+fn trap_spawn_error<T>(prog: &str, spawn: &fn() -> Option<T>) -> Result<T, ~str> {
+    let mut error = None;
+    let result = do io_error::cond.trap(|e| error = Some(e)).inside { spawn() };
+    match result {
+        Some(result) => Ok(result),
+        None => {
+            let reason = error.map_default(~"unknown error", |e| e.desc.to_owned());
+            Err(format!("couldn't run `{}`: {}", prog, reason))
+        }
+    }
+}
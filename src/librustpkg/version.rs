@@ -14,9 +14,9 @@
 extern mod std;
 
 use extra::semver;
-use std::{char, os, result, run, str};
+use std::{char, result};
 use extra::tempfile::TempDir;
-use path_util::rust_path;
+use source_control::{vcs_at, vcs_for_source};
 
 #[deriving(Clone)]
 pub enum Version {
@@ -30,50 +30,99 @@ pub enum Version {
 // Equality on versions is non-symmetric: if self is NoVersion, it's equal to
 // anything; but if self is a precise version, it's not equal to NoVersion.
 // We should probably make equality symmetric, and use less-than and greater-than
-// where we currently use eq
+// where we currently use eq. Otherwise it agrees with the ordering, so 1.2
+// equals 1.2.0 as it's neither less nor greater.
 impl Eq for Version {
     fn eq(&self, other: &Version) -> bool {
-        match (self, other) {
-            (&ExactRevision(ref s1), &ExactRevision(ref s2)) => *s1 == *s2,
-            (&SemanticVersion(ref v1), &SemanticVersion(ref v2)) => *v1 == *v2,
-            (&NoVersion, _) => true,
-            _ => false
+        match *self {
+            NoVersion => true,
+            _ => self.compare(other) == Some(Equal)
         }
     }
 }
 
 impl Ord for Version {
     fn lt(&self, other: &Version) -> bool {
-        match (self, other) {
-            (&NoVersion, _) => true,
-            (&ExactRevision(ref f1), &ExactRevision(ref f2)) => f1 < f2,
-            (&SemanticVersion(ref v1), &SemanticVersion(ref v2)) => v1 < v2,
-            _ => false // incomparable, really
+        match *self {
+            NoVersion => true,
+            _ => self.compare(other) == Some(Less)
         }
     }
     fn le(&self, other: &Version) -> bool {
-        match (self, other) {
-            (&NoVersion, _) => true,
-            (&ExactRevision(ref f1), &ExactRevision(ref f2)) => f1 <= f2,
-            (&SemanticVersion(ref v1), &SemanticVersion(ref v2)) => v1 <= v2,
-            _ => false // incomparable, really
+        match *self {
+            NoVersion => true,
+            _ => match self.compare(other) {
+                Some(Less) | Some(Equal) => true,
+                _ => false
+            }
         }
     }
     fn ge(&self, other: &Version) -> bool {
-        match (self, other) {
-            (&ExactRevision(ref f1), &ExactRevision(ref f2)) => f1 > f2,
-            (&SemanticVersion(ref v1), &SemanticVersion(ref v2)) => v1 > v2,
-            _ => false // incomparable, really
+        match self.compare(other) {
+            Some(Greater) | Some(Equal) => true,
+            _ => false
         }
     }
     fn gt(&self, other: &Version) -> bool {
-        match (self, other) {
-            (&ExactRevision(ref f1), &ExactRevision(ref f2)) => f1 >= f2,
-            (&SemanticVersion(ref v1), &SemanticVersion(ref v2)) => v1 >= v2,
-            _ => false // incomparable, really
+        self.compare(other) == Some(Greater)
+    }
+}
+
+impl Version {
+    /// This version as a semantic version, if it can be read as one. Exact
+    /// revisions with fewer than three numbers have the rest filled in with
+    /// zeroes, so that e.g. 1.10 is newer than 1.9.
+    pub fn to_semver(&self) -> Option<semver::Version> {
+        match *self {
+            SemanticVersion(ref v) => Some(v.clone()),
+            ExactRevision(ref s) => {
+                let mut numbers = ~[];
+                for n in s.split_iter('.') {
+                    match from_str::<uint>(n) {
+                        Some(n) => numbers.push(n),
+                        None => return None
+                    }
+                }
+                if numbers.is_empty() || numbers.len() > 3 {
+                    return None;
+                }
+                while numbers.len() < 3 {
+                    numbers.push(0);
+                }
+                Some(semver::Version {
+                    major: numbers[0],
+                    minor: numbers[1],
+                    patch: numbers[2],
+                    pre: ~[],
+                    build: ~[]
+                })
+            }
+            Tagged(_) | NoVersion => None
+        }
+    }
+
+    /// Whether this version satisfies `req`. Versions that can't be read as
+    /// semantic versions only satisfy a requirement that allows any version.
+    pub fn satisfies(&self, req: &semver::VersionReq) -> bool {
+        match self.to_semver() {
+            Some(ref v) => req.matches(v),
+            None => *req == semver::VersionReq::any()
         }
     }
 
+    // How two versions compare by precedence, or None if they're
+    // incomparable. Revisions that aren't version numbers compare as strings.
+    fn compare(&self, other: &Version) -> Option<Ordering> {
+        match (self.to_semver(), other.to_semver()) {
+            (Some(ref v1), Some(ref v2)) => {
+                Some(if *v1 < *v2 { Less } else if *v2 < *v1 { Greater } else { Equal })
+            }
+            _ => match (self, other) {
+                (&ExactRevision(ref f1), &ExactRevision(ref f2)) => Some(f1.cmp(f2)),
+                _ => None
+            }
+        }
+    }
 }
 
 impl ToStr for Version {
@@ -93,38 +142,26 @@ pub fn parse_vers(vers: ~str) -> result::Result<semver::Version, ~str> {
     }
 }
 
-/// If `local_path` is a git repo in the RUST_PATH, and the most recent tag
+/// If `local_path` is a repository in a workspace of `rust_path`, and the most recent tag
 /// in that repo denotes a version, return it; otherwise, `None`
-pub fn try_getting_local_version(local_path: &Path) -> Option<Version> {
-    let rustpath = rust_path();
-    for rp in rustpath.iter() {
+pub fn try_getting_local_version(local_path: &Path, rust_path: &[Path]) -> Option<Version> {
+    for rp in rust_path.iter() {
         let local_path = rp.push_rel(local_path);
-        let git_dir = local_path.push(".git");
-        if !os::path_is_dir(&git_dir) {
-            continue;
-        }
-        let outp = run::process_output("git",
-                                   [format!("--git-dir={}", git_dir.to_str()), ~"tag", ~"-l"]);
-
-        debug2!("git --git-dir={} tag -l ~~~> {:?}", git_dir.to_str(), outp.status);
-
-        if outp.status != 0 {
-            continue;
-        }
-
-    let mut output = None;
-    let output_text = str::from_utf8(outp.output);
-    for l in output_text.line_iter() {
-        if !l.is_whitespace() {
-            output = Some(l);
-        }
-        match output.and_then(try_parsing_version) {
-            Some(v) => return Some(v),
-            None    => ()
+        let tags = match vcs_at(&local_path) {
+            Some(vcs) => match vcs.tags(&local_path) {
+                Ok(tags) => tags,
+                Err(_) => continue
+            },
+            None => continue
+        };
+        for tag in tags.iter() {
+            match try_parsing_version(tag.as_slice()) {
+                Some(v) => return Some(v),
+                None    => ()
+            }
         }
     }
-  }
-  None
+    None
 }
 
 /// If `remote_path` refers to a git repo that can be downloaded,
@@ -132,46 +169,29 @@ pub fn try_getting_local_version(local_path: &Path) -> Option<Version> {
 /// otherwise, `None`
 pub fn try_getting_version(remote_path: &Path) -> Option<Version> {
     if is_url_like(remote_path) {
-        let tmp_dir = TempDir::new("test");
-        let tmp_dir = tmp_dir.expect("try_getting_version: couldn't create temp dir");
-        let tmp_dir = tmp_dir.path();
-        debug2!("(to get version) executing \\{git clone https://{} {}\\}",
-               remote_path.to_str(),
-               tmp_dir.to_str());
-        let outp  = run::process_output("git", [~"clone",
-                                                format!("https://{}",
-                                                        remote_path.to_str()),
-                                                tmp_dir.to_str()]);
-        if outp.status == 0 {
-            debug2!("Cloned it... ( {}, {} )",
-                   str::from_utf8(outp.output),
-                   str::from_utf8(outp.error));
-            let mut output = None;
-            debug2!("(getting version, now getting tags) executing \\{git --git-dir={} tag -l\\}",
-                   tmp_dir.push(".git").to_str());
-            let outp = run::process_output("git",
-                                           [format!("--git-dir={}", tmp_dir.push(".git").to_str()),
-                                            ~"tag", ~"-l"]);
-            let output_text = str::from_utf8(outp.output);
-            debug2!("Full output: ( {} ) [{:?}]", output_text, outp.status);
-            for l in output_text.line_iter() {
-                debug2!("A line of output: {}", l);
-                if !l.is_whitespace() {
-                    output = Some(l);
-                }
-            }
-
-            output.and_then(try_parsing_version)
-        }
-        else {
-            None
-        }
+        try_getting_version_from_url(format!("https://{}", remote_path.to_str()))
     }
     else {
         None
     }
 }
 
+/// As `try_getting_version`, but for a repo given by its URL
+pub fn try_getting_version_from_url(url: &str) -> Option<Version> {
+    let tmp_dir = TempDir::new("test");
+    let tmp_dir = tmp_dir.expect("try_getting_version: couldn't create temp dir");
+    let tmp_dir = tmp_dir.path();
+    let vcs = vcs_for_source(url);
+    if vcs.clone_repo(url, tmp_dir).is_err() {
+        return None;
+    }
+    debug2!("Cloned {} into {}", url, tmp_dir.to_str());
+    match vcs.tags(tmp_dir) {
+        Ok(tags) => tags.last_opt().and_then(|t| try_parsing_version(t.as_slice())),
+        Err(_) => None
+    }
+}
+
 // Being lazy since we don't have a regexp library now
 #[deriving(Eq)]
 enum ParseState {
@@ -252,3 +272,39 @@ fn test_split_version() {
     assert!(split_version(s) == Some((s.slice(0, 1), ExactRevision(~"1.2"))));
     assert!(split_version("a#a#3.4") == None);
 }
+
+#[test]
+fn test_version_ordering() {
+    let v = |s: &str| try_parsing_version(s).unwrap();
+    assert!(v("1.10") > v("1.9"));
+    assert!(v("1.9") < v("1.10"));
+    assert!(v("1.2") >= v("1.2.0"));
+    assert!(v("2") > v("1.2.3"));
+    assert!(!(v("1.2") > v("1.2")));
+    assert!(v("1.2.3") < SemanticVersion(semver::parse("1.2.4-alpha").unwrap()));
+    assert!(NoVersion < v("0.1"));
+    assert!(!(Tagged(~"foo") < v("0.1")));
+    assert!(ExactRevision(~"abc") < ExactRevision(~"abd"));
+}
+
+#[test]
+fn test_version_equality() {
+    let v = |s: &str| try_parsing_version(s).unwrap();
+    assert!(v("1.2") == v("1.2.0"));
+    assert!(v("1.2.0") == SemanticVersion(semver::parse("1.2.0").unwrap()));
+    assert!(v("1.2") != v("1.2.1"));
+    assert!(ExactRevision(~"abc") == ExactRevision(~"abc"));
+    assert!(NoVersion == v("0.3"));
+    assert!(v("0.3") != NoVersion);
+}
+
+#[test]
+fn test_version_satisfies() {
+    let req = semver::VersionReq::parse(">=1.2, <2.0").unwrap();
+    assert!(try_parsing_version("1.10").unwrap().satisfies(&req));
+    assert!(!try_parsing_version("1.1").unwrap().satisfies(&req));
+    assert!(!try_parsing_version("2").unwrap().satisfies(&req));
+    assert!(!NoVersion.satisfies(&req));
+    assert!(NoVersion.satisfies(&semver::VersionReq::any()));
+    assert!(SemanticVersion(semver::parse("1.5.0").unwrap()).satisfies(&req));
+}
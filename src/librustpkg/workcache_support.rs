@@ -19,10 +19,9 @@ pub fn digest_file_with_date(path: &Path) -> ~str {
     use cond1 = conditions::bad_stat::cond;
 
     let mut sha = ~Sha1::new();
-    let s = io::read_whole_file_str(path);
-    match s {
-        Ok(s) => {
-            (*sha).input_str(s);
+    match io::MappedFile::open(path, io::MapReadOnly) {
+        Ok(file) => {
+            (*sha).input(file.as_slice());
             let st = match path.stat() {
                 Some(st) => st,
                 None => cond1.raise((path.clone(), format!("Couldn't get file access time")))
@@ -53,7 +52,49 @@ pub fn discover_outputs(e: &mut workcache::Exec, outputs: ~[Path]) {
     for p in outputs.iter() {
         debug2!("Discovering output! {}", p.to_str());
         // For now, assume that all discovered outputs are binaries
-        e.discover_output("binary", p.to_str(), digest_only_date(p));
+        e.discover_output("binary", p.normalize().to_str(), digest_only_date(p));
+    }
+}
+
+/// Says why the work an explanation is about was reused or redone, in the
+/// terms of the inputs rustpkg declares, for `--explain`
+pub fn describe_explanation(e: &workcache::Explanation) -> ~str {
+    let why = match e.decision {
+        workcache::Fresh if e.inputs == 1 => ~"fresh because its input is unchanged",
+        workcache::Fresh => format!("fresh because all {} inputs are unchanged", e.inputs),
+        workcache::NotCached => ~"built because it wasn't built before",
+        workcache::Stale(ref c) => format!("rebuilt because {}", describe_change(c))
+    };
+    format!("{}: {}", e.fn_name, why)
+}
+
+fn describe_change(c: &workcache::Change) -> ~str {
+    let show = |v: &Option<~str>| match *v {
+        Some(ref v) => format!("`{}`", *v),
+        None => ~"nothing"
+    };
+    let from_to = format!("from {} to {}", show(&c.old_value), show(&c.new_value));
+    match c.kind.as_slice() {
+        "file" | "binary" => {
+            let what = if "discovered output" == c.category {
+                format!("output {}", c.name)
+            } else {
+                c.name.clone()
+            };
+            match (&c.old_value, &c.new_value) {
+                (_, &None) => format!("{} is missing", what),
+                (&None, _) => format!("{} is a new input", what),
+                _ => format!("{} changed", what)
+            }
+        }
+        "value" if "rustc-flags" == c.name => format!("the rustc flags changed {}", from_to),
+        "value" if c.name.starts_with("flags ") => {
+            format!("the flags for {} changed {}", c.name.slice_from(6), from_to)
+        }
+        "value" if c.name.starts_with("cfgs") => format!("the cfgs changed {}", from_to),
+        "value" => format!("the {} changed {}", c.name, from_to),
+        "env" => format!("${} changed {}", c.name, from_to),
+        _ => format!("{} {}:{} changed {}", c.category, c.kind, c.name, from_to)
     }
 }
 
@@ -10,13 +10,17 @@
 
 // rustpkg utilities having to do with workspaces
 
-use std::{os,util};
+use std::{io, libc, os};
 use std::path::Path;
+use extra::arc::RWArc;
+use extra::sort;
 use context::Context;
-use path_util::{workspace_contains_package_id, find_dir_using_rust_path_hack, default_workspace};
-use path_util::rust_path;
+use messages::note;
+use path_util::{workspace_contains_package_id, find_dir_using_rust_path_hack, U_RWX};
 use util::option_to_vec;
+use metadata::declared_name;
 use package_id::PkgId;
+use version::{split_version_general, try_parsing_version};
 
 pub fn each_pkg_parent_workspace(cx: &Context, pkgid: &PkgId, action: &fn(&Path) -> bool) -> bool {
     // Using the RUST_PATH, find workspaces that contain
@@ -27,7 +31,7 @@ pub fn each_pkg_parent_workspace(cx: &Context, pkgid: &PkgId, action: &fn(&Path)
         fail2!("Package {} not found in any of \
                     the following workspaces: {}",
                    pkgid.path.to_str(),
-                   rust_path().to_str());
+                   cx.rust_path.to_str());
     }
     for ws in workspaces.iter() {
         if action(ws) {
@@ -40,48 +44,206 @@ pub fn each_pkg_parent_workspace(cx: &Context, pkgid: &PkgId, action: &fn(&Path)
 /// Given a package ID, return a vector of all of the workspaces in
 /// the RUST_PATH that contain it
 pub fn pkg_parent_workspaces(cx: &Context, pkgid: &PkgId) -> ~[Path] {
-    let rs: ~[Path] = rust_path().move_iter()
-        .filter(|ws| workspace_contains_package_id(pkgid, ws))
+    let rs: ~[Path] = cx.rust_path.iter()
+        .filter(|ws| workspace_contains_package_id(pkgid, *ws))
+        .map(|ws| ws.clone())
         .collect();
     if cx.use_rust_path_hack {
-        rs + option_to_vec(find_dir_using_rust_path_hack(pkgid))
+        rs + option_to_vec(find_dir_using_rust_path_hack(cx.rust_path, pkgid))
     }
     else {
         rs
     }
 }
 
+/// The ID to build `pkgid` under. A package can declare a name with
+/// `#[pkg(name = "...")]` that differs from its directory's; if no
+/// workspace has a package at `pkgid`'s path, this looks for one that
+/// declares `pkgid`'s name, and returns its ID aliased as that name.
+/// Otherwise `pkgid` is returned as it is.
+pub fn resolve_declared_name(cx: &Context, pkgid: PkgId) -> PkgId {
+    if pkgid.is_complex() || !pkg_parent_workspaces(cx, &pkgid).is_empty() {
+        return pkgid;
+    }
+    match find_declared_package(cx.rust_path, pkgid.short_name) {
+        Some(found) => {
+            debug2!("{} is declared by {}", pkgid.short_name, found.to_str());
+            found
+        }
+        None => pkgid
+    }
+}
+
+// Looks through the package sources in each workspace for the one whose
+// library crate (or, failing that, main crate) declares `name`
+fn find_declared_package(rust_path: &[Path], name: &str) -> Option<PkgId> {
+    for ws in rust_path.iter() {
+        let src_dir = ws.push("src");
+        if !os::path_is_dir(&src_dir) {
+            continue;
+        }
+        let mut walker = os::walk_dir(&src_dir);
+        loop {
+            let entry = match walker.next() {
+                Some(entry) => entry,
+                None => break
+            };
+            if entry.kind != os::Directory {
+                continue;
+            }
+            if entry.path.filename().map_default(false, |f| f.starts_with(".")) {
+                walker.skip_subtree();
+                continue;
+            }
+            let crate_file = [~"lib.rs", ~"main.rs"].iter()
+                .map(|f| entry.path.push(*f))
+                .find(|f| os::path_exists(f));
+            let crate_file = match crate_file {
+                Some(f) => f,
+                None => continue
+            };
+            // A package's crates are at the top of its directory, so
+            // there's no need to look further down
+            walker.skip_subtree();
+            if declared_name(&crate_file).map_default(false, |n| n.as_slice() == name) {
+                let rel = entry.path.relative_from(&src_dir).unwrap();
+                // A directory named like foo-0.3 holds version 0.3 of foo
+                let dir_name = rel.filename().unwrap();
+                let id = match split_version_general(dir_name, '-') {
+                    Some((stem, ref vers)) if try_parsing_version(vers.to_str()).is_some() => {
+                        let path = rel.with_filename(stem).components.connect("/");
+                        PkgId { version: vers.clone(), .. PkgId::new_in(path, rust_path) }
+                    }
+                    _ => PkgId::new_in(rel.components.connect("/"), rust_path)
+                };
+                return Some(id.with_alias(name));
+            }
+        }
+    }
+    None
+}
+
 pub fn is_workspace(p: &Path) -> bool {
     os::path_is_dir(&p.push("src"))
 }
 
 /// Construct a workspace and package-ID name based on the current directory.
 /// This gets used when rustpkg gets invoked without a package-ID argument.
-pub fn cwd_to_workspace() -> Option<(Path, PkgId)> {
-    let cwd = os::getcwd();
-    for path in rust_path().move_iter() {
+pub fn cwd_to_workspace(cx: &Context) -> Option<(Path, PkgId)> {
+    for path in cx.rust_path.iter() {
         let srcpath = path.push("src");
-        if srcpath.is_ancestor_of(&cwd) {
-            // I'd love to use srcpath.get_relative_to(cwd) but it behaves wrong
-            // I'd say broken, but it has tests enforcing the wrong behavior.
-            // instead, just hack up the components vec
-            let mut pkgid = cwd;
-            pkgid.is_absolute = false;
-            let comps = util::replace(&mut pkgid.components, ~[]);
-            pkgid.components = comps.move_iter().skip(srcpath.components.len()).collect();
-            return Some((path, PkgId::new(pkgid.components.connect("/"))))
+        if srcpath.is_ancestor_of(&cx.cwd) {
+            let pkgid = cx.cwd.relative_from(&srcpath).unwrap();
+            return Some((path.clone(), PkgId::new_in(pkgid.components.connect("/"),
+                                                     cx.rust_path)))
         }
     }
     None
 }
 
-/// If `workspace` is the same as `cwd`, and use_rust_path_hack is false,
-/// return `workspace`; otherwise, return the first workspace in the RUST_PATH.
-pub fn determine_destination(cwd: Path, use_rust_path_hack: bool, workspace: &Path) -> Path {
-    if workspace == &cwd && !use_rust_path_hack {
+/// If `workspace` is the same as the current directory, and use_rust_path_hack
+/// is false, return `workspace`; otherwise, return the first workspace in the
+/// RUST_PATH.
+pub fn determine_destination(cx: &Context, workspace: &Path) -> Path {
+    if workspace == &cx.cwd && !cx.use_rust_path_hack {
         workspace.clone()
     }
     else {
-        default_workspace()
+        cx.default_workspace()
+    }
+}
+
+/// The file whose lock keeps two rustpkg processes from working in
+/// `workspace` at once. It holds the pid of the process that last took it.
+pub fn workspace_lock_file(workspace: &Path) -> Path {
+    workspace.push_many([~".rustpkg", ~"lock"])
+}
+
+/// The locks a run of rustpkg holds on some workspaces, until it's dropped
+pub struct WorkspaceLocks {
+    priv files: ~[~str],
+    priv locks: ~[os::FileLock],
+    priv held: RWArc<~[~str]>
+}
+
+impl Drop for WorkspaceLocks {
+    fn drop(&mut self) {
+        let files = &self.files;
+        do self.held.write |held| {
+            held.retain(|f| !files.contains(f));
+        }
+        // The file locks themselves go once this returns
     }
 }
+
+/// Locks each of `workspaces` against other rustpkg processes (and other
+/// runs in this one), waiting for the ones that hold them to finish first.
+/// The locks are held until the returned `WorkspaceLocks` is dropped.
+///
+/// The locks are taken in a fixed order, so that two processes that want
+/// overlapping sets of workspaces can't each hold a lock the other is
+/// waiting for. They're reentrant for the run `cx` belongs to: a workspace
+/// it, or the build of a package it depends on, has locked already is
+/// passed over rather than waited for.
+pub fn lock_workspaces(cx: &Context, workspaces: &[Path]) -> WorkspaceLocks {
+    let mut files = workspaces.map(|ws| workspace_lock_file(&os::make_absolute(ws).normalize())
+                                        .to_str());
+    sort::quick_sort3(files);
+    files.dedup();
+    // Claim the ones this run doesn't hold yet before waiting for them, so
+    // a dependency of the same run doesn't wait for them too
+    let files = do cx.locked_workspaces.write |held| {
+        let new: ~[~str] = files.iter().filter(|f| !held.contains(*f))
+            .map(|f| f.clone()).collect();
+        held.push_all(new);
+        new
+    };
+    let locks = files.iter().filter_map(|f| lock_workspace_file(&Path(*f))).collect();
+    WorkspaceLocks {
+        files: files,
+        locks: locks,
+        held: cx.locked_workspaces.clone()
+    }
+}
+
+// Returns None, so the workspace goes unlocked, if the lock file can't be
+// made; the workspace is probably read-only then, and nothing will be
+// written to it anyway
+fn lock_workspace_file(file: &Path) -> Option<os::FileLock> {
+    if !os::path_is_dir(&file.dir_path()) && !os::mkdir_recursive(&file.dir_path(), U_RWX) {
+        debug2!("Couldn't create {}", file.dir_path().to_str());
+        return None;
+    }
+    let lock = match os::try_lock_file(file) {
+        Some(lock) => Some(lock),
+        None => {
+            // The holder writes its pid just after taking the lock, so
+            // it may not be there yet
+            let holder = match io::read_whole_file_str(file) {
+                Ok(s) => from_str::<int>(s.trim()),
+                Err(_) => None
+            };
+            match holder {
+                Some(pid) => note(format!("waiting for lock on {} held by pid {}",
+                                          file.dir_path().dir_path().to_str(), pid)),
+                None => note(format!("waiting for lock on {}",
+                                     file.dir_path().dir_path().to_str()))
+            }
+            os::lock_file(file)
+        }
+    };
+    if lock.is_none() {
+        debug2!("Couldn't lock {}", file.to_str());
+        return None;
+    }
+    match io::file_writer(file, [io::Create, io::Truncate]) {
+        Ok(w) => w.write_str(format!("{}\n", getpid())),
+        Err(e) => debug2!("Couldn't write our pid to {}: {}", file.to_str(), e)
+    }
+    lock
+}
+
+fn getpid() -> int {
+    #[fixed_stack_segment]; #[inline(never)];
+    unsafe { libc::getpid() as int }
+}
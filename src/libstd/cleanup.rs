@@ -10,10 +10,14 @@
 
 #[doc(hidden)];
 
+use container::Container;
+use iter::Iterator;
 use libc::c_void;
+use option::{Some, None};
 use ptr::null;
 use unstable::intrinsics::TyDesc;
 use unstable::raw;
+use vec::{OwnedVector, ImmutableVector};
 
 type DropGlue<'self> = &'self fn(**TyDesc, *c_void);
 
@@ -54,6 +58,23 @@ unsafe fn each_live_alloc(read_next_before: bool,
     return true;
 }
 
+// Print the boxes the annihilator found alive, grouped by type. Anything
+// left when a task exits was kept alive by a cycle or otherwise retained
+// unintentionally.
+fn report_leaks(leaks: &[(&'static str, uint, uint)]) {
+    use rt::logging::{Logger, StdErrLogger};
+
+    let (boxes, bytes) = leaks.iter().fold((0u, 0u), |(boxes, bytes), &(_, n, size)| {
+        (boxes + n, bytes + size)
+    });
+    let mut logger = StdErrLogger;
+    format_args!(|args| logger.log(args),
+                 "task leaked {} managed boxes ({} bytes):", boxes, bytes);
+    for &(name, n, size) in leaks.iter() {
+        format_args!(|args| logger.log(args), "  {} x {} ({} bytes)", n, name, size);
+    }
+}
+
 #[cfg(unix)]
 fn debug_mem() -> bool {
     // XXX: Need to port the environment struct to newsched
@@ -67,10 +88,14 @@ fn debug_mem() -> bool {
 
 /// Destroys all managed memory (i.e. @ boxes) held by the current task.
 pub unsafe fn annihilate() {
+    use rt::env;
     use rt::local_heap::local_free;
     use sys;
     use managed;
 
+    let report = env::report_leaks();
+    let mut leaks: ~[(&'static str, uint, uint)] = ~[];
+
     let mut stats = AnnihilateStats {
         n_total_boxes: 0,
         n_unique_boxes: 0,
@@ -87,10 +112,25 @@ pub unsafe fn annihilate() {
             stats.n_unique_boxes += 1;
         } else {
             (*box).ref_count = managed::RC_IMMORTAL;
+            if report {
+                let name = (*(*box).type_desc).name;
+                let size = (*(*box).type_desc).size + sys::size_of::<raw::Box<()>>();
+                match leaks.iter().position(|&(n, _, _)| n == name) {
+                    Some(i) => {
+                        let (_, count, bytes) = leaks[i];
+                        leaks[i] = (name, count + 1, bytes + size);
+                    }
+                    None => leaks.push((name, 1, size))
+                }
+            }
         }
         true
     };
 
+    if !leaks.is_empty() {
+        report_leaks(leaks);
+    }
+
     // Pass 2: Drop all boxes.
     //
     // In this pass, unique-managed boxes may get freed, but not
@@ -19,6 +19,8 @@ use kinds::Send;
 use option::Option;
 pub use rt::comm::SendDeferred;
 use rtcomm = rt::comm;
+use rt::shouldnt_be_public::{Scheduler, SelectInner, BlockedTask};
+use select::Select;
 
 /// A trait for things that can send multiple messages.
 pub trait GenericChan<T> {
@@ -98,6 +100,13 @@ impl<T: Send> PortOne<T> {
         let PortOne { x: p } = self;
         p.try_recv()
     }
+
+    /// As `recv`, but returns `None` if the task's token is cancelled
+    /// before a message arrives. See `task::CancelToken`.
+    pub fn recv_or_cancel(self) -> Option<T> {
+        let PortOne { x: p } = self;
+        p.recv_or_cancel()
+    }
 }
 
 impl<T: Send> Peekable<T>  for PortOne<T> {
@@ -107,6 +116,22 @@ impl<T: Send> Peekable<T>  for PortOne<T> {
     }
 }
 
+impl<T> SelectInner for PortOne<T> {
+    fn optimistic_check(&mut self) -> bool {
+        self.x.optimistic_check()
+    }
+
+    fn block_on(&mut self, sched: &mut Scheduler, task: BlockedTask) -> bool {
+        self.x.block_on(sched, task)
+    }
+
+    fn unblock_from(&mut self) -> bool {
+        self.x.unblock_from()
+    }
+}
+
+impl<T> Select for PortOne<T> { }
+
 impl<T: Send> GenericChan<T> for Chan<T> {
     fn send(&self, val: T) {
         let &Chan { x: ref c } = self;
@@ -133,6 +158,15 @@ impl<T: Send> SendDeferred<T> for Chan<T> {
     }
 }
 
+impl<T: Send> Port<T> {
+    /// As `recv`, but returns `None` if the task's token is cancelled
+    /// before a message arrives. See `task::CancelToken`.
+    pub fn recv_or_cancel(&self) -> Option<T> {
+        let &Port { x: ref p } = self;
+        p.recv_or_cancel()
+    }
+}
+
 impl<T: Send> GenericPort<T> for Port<T> {
     fn recv(&self) -> T {
         let &Port { x: ref p } = self;
@@ -152,6 +186,22 @@ impl<T: Send> Peekable<T> for Port<T> {
     }
 }
 
+impl<T> SelectInner for Port<T> {
+    fn optimistic_check(&mut self) -> bool {
+        self.x.optimistic_check()
+    }
+
+    fn block_on(&mut self, sched: &mut Scheduler, task: BlockedTask) -> bool {
+        self.x.block_on(sched, task)
+    }
+
+    fn unblock_from(&mut self) -> bool {
+        self.x.unblock_from()
+    }
+}
+
+impl<T> Select for Port<T> { }
+
 
 pub struct SharedChan<T> { x: rtcomm::SharedChan<T> }
 
@@ -202,6 +252,13 @@ impl<T: Send> SharedPort<T> {
         let Port { x: p } = p;
         SharedPort { x: rtcomm::SharedPort::new(p) }
     }
+
+    /// As `recv`, but returns `None` if the task's token is cancelled
+    /// before a message arrives. See `task::CancelToken`.
+    pub fn recv_or_cancel(&self) -> Option<T> {
+        let &SharedPort { x: ref p } = self;
+        p.recv_or_cancel()
+    }
 }
 
 impl<T: Send> GenericPort<T> for SharedPort<T> {
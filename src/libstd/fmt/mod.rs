@@ -330,7 +330,7 @@ width := count
 precision := count | '*'
 type := identifier | ''
 count := parameter | integer
-parameter := integer '$'
+parameter := argument '$'
 
 function_spec := plural | select
 select := 'select' ',' ( identifier arm ) *
@@ -391,7 +391,8 @@ defaults for numeric formatters is also a space but with right-alignment. If the
 
 The value for the width can also be provided as a `uint` in the list of
 parameters by using the `2$` syntax indicating that the second argument is a
-`uint` specifying the width.
+`uint` specifying the width. Named arguments work the same way, so
+`format!("{:>width$s}", name, width=10)` right-aligns `name` in 10 columns.
 
 ### Precision
 
@@ -659,6 +660,9 @@ impl<'self> Formatter<'self> {
                     let v = self.curarg.next().unwrap().value;
                     unsafe { *slot = Some(*(v as *util::Void as *uint)); }
                 }
+                parse::CountIsName(*) => {
+                    fail2!("named count wasn't resolved by format!")
+                }
             }
         };
 
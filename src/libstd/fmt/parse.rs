@@ -50,9 +50,9 @@ pub struct FormatSpec<'self> {
     /// Packed version of various flags provided
     flags: uint,
     /// The integer precision to use
-    precision: Count,
+    precision: Count<'self>,
     /// The string width requested for the resulting format
-    width: Count,
+    width: Count<'self>,
     /// The descriptor string representing the name of the format desired for
     /// this argument, this can be empty or any number of characters, although
     /// it is required to be one word.
@@ -86,8 +86,9 @@ pub enum Flag {
 /// can reference either an argument or a literal integer.
 #[deriving(Eq)]
 #[allow(missing_doc)]
-pub enum Count {
+pub enum Count<'self> {
     CountIs(uint),
+    CountIsName(&'self str),
     CountIsParam(uint),
     CountIsNextParam,
     CountImplied,
@@ -542,7 +543,7 @@ impl<'self> Parser<'self> {
     /// Parses a Count parameter at the current position. This does not check
     /// for 'CountIsNextParam' because that is only used in precision, not
     /// width.
-    fn count(&mut self) -> Count {
+    fn count(&mut self) -> Count<'self> {
         match self.integer() {
             Some(i) => {
                 if self.consume('$') {
@@ -551,7 +552,18 @@ impl<'self> Parser<'self> {
                     CountIs(i)
                 }
             }
-            None => { CountImplied }
+            None => {
+                // A word is only a count if it's followed by a `$`, otherwise
+                // it's the format type and has to be parsed again as such.
+                let start = self.cur.clone();
+                let word = self.word();
+                if word.is_empty() || !self.consume('$') {
+                    self.cur = start;
+                    CountImplied
+                } else {
+                    CountIsName(word)
+                }
+            }
         }
     }
 
@@ -777,6 +789,30 @@ mod tests {
             },
             method: None,
         })]);
+        same("{:a$.b$s}", ~[Argument(Argument {
+            position: ArgumentNext,
+            format: FormatSpec {
+                fill: None,
+                align: AlignUnknown,
+                flags: 0,
+                precision: CountIsName("b"),
+                width: CountIsName("a"),
+                ty: "s",
+            },
+            method: None,
+        })]);
+        same("{:>width$}", ~[Argument(Argument {
+            position: ArgumentNext,
+            format: FormatSpec {
+                fill: None,
+                align: AlignRight,
+                flags: 0,
+                precision: CountImplied,
+                width: CountIsName("width"),
+                ty: "",
+            },
+            method: None,
+        })]);
     }
     #[test]
     fn format_flags() {
@@ -38,8 +38,9 @@ pub struct FormatSpec {
     fill: char,
     align: parse::Alignment,
     flags: uint,
-    precision: parse::Count,
-    width: parse::Count,
+    // Named counts are turned into `CountIsParam` by the format! extension
+    precision: parse::Count<'static>,
+    width: parse::Count<'static>,
 }
 
 pub enum Position {
@@ -50,7 +50,7 @@ use cast;
 use cast::transmute;
 use clone::Clone;
 use c_str::ToCStr;
-use container::Container;
+use container::{Container, Mutable};
 use int;
 use iter::Iterator;
 use libc::consts::os::posix88::*;
@@ -62,11 +62,13 @@ use option::{Some, None};
 use os;
 use path::Path;
 use ptr;
+use rand;
 use result::{Result, Ok, Err};
 use str::{StrSlice, OwnedStr};
 use str;
 use to_str::ToStr;
 use uint;
+use unstable::raw;
 use vec::{MutableVector, ImmutableVector, OwnedVector, OwnedCopyableVector, CopyableVector};
 use vec;
 
@@ -1610,6 +1612,134 @@ pub fn file_writer(path: &Path, flags: &[FileFlag]) -> Result<@Writer, ~str> {
     mk_file_writer(path, flags).and_then(|w| Ok(w))
 }
 
+/**
+* Replaces the contents of the file at `path` with whatever `f` writes,
+* such that other readers, and the file itself after a crash, only ever
+* see either the old contents or all of the new ones.
+*
+* The new contents are written to a temporary file in the same directory,
+* synced to disk and then renamed over `path`, and the directory is synced
+* so that the rename itself survives a crash. The temporary file is
+* removed again if anything goes wrong, including `f` failing.
+*
+* # Example
+*
+* ```rust
+* do atomic_write(&Path("db.json")) |w| {
+*     w.write_str(json);
+* };
+* ```
+*/
+pub fn atomic_write(path: &Path, f: &fn(@Writer)) -> Result<(), ~str> {
+    #[fixed_stack_segment]; #[inline(never)];
+
+    let name = match path.filename() {
+        Some(name) => name,
+        None => return Err(format!("{} is not a file path", path.to_str()))
+    };
+    let tmp = path.with_filename(format!(".{}.{:x}.tmp", name, rand::random::<u32>()));
+
+    #[cfg(windows)]
+    fn flags() -> c_int {
+        (O_WRONLY | O_CREAT | O_EXCL | libc::consts::os::extra::O_BINARY) as c_int
+    }
+    #[cfg(unix)]
+    fn flags() -> c_int { (O_WRONLY | O_CREAT | O_EXCL) as c_int }
+
+    // rename() won't replace an existing file on Windows, and removing the
+    // old one first would leave a moment with no file at all
+    #[cfg(windows)]
+    fn replace_file(from: &Path, to: &Path) -> bool {
+        #[fixed_stack_segment]; #[inline(never)];
+        use libc::consts::os::extra::{MOVEFILE_REPLACE_EXISTING, MOVEFILE_WRITE_THROUGH};
+        use os::win32::as_utf16_p;
+        unsafe {
+            do as_utf16_p(from.to_str()) |fromp| {
+                do as_utf16_p(to.to_str()) |top| {
+                    libc::MoveFileExW(fromp, top,
+                                      MOVEFILE_REPLACE_EXISTING | MOVEFILE_WRITE_THROUGH) !=
+                        (0 as libc::BOOL)
+                }
+            }
+        }
+    }
+    #[cfg(unix)]
+    fn replace_file(from: &Path, to: &Path) -> bool { os::rename_file(from, to) }
+
+    // MOVEFILE_WRITE_THROUGH has already made the rename durable on
+    // Windows, which can't open a directory to sync it anyway
+    #[cfg(windows)]
+    fn sync_dir(_dir: &Path) -> bool { true }
+    #[cfg(unix)]
+    fn sync_dir(dir: &Path) -> bool {
+        #[fixed_stack_segment]; #[inline(never)];
+        let fd = unsafe {
+            do dir.with_c_str |pathbuf| {
+                libc::open(pathbuf, O_RDONLY as c_int, 0 as c_int)
+            }
+        };
+        if fd < (0 as c_int) {
+            return false;
+        }
+        let _fd = FdRes::new(fd);
+        os::fsync_fd(fd, fsync::FSync) == 0
+    }
+
+    // Keep the permissions of the file being replaced
+    let mode = match path.get_mode() {
+        Some(mode) => mode as c_int,
+        None => (S_IRUSR | S_IWUSR) as c_int
+    };
+    let fd = unsafe {
+        do tmp.with_c_str |pathbuf| {
+            libc::open(pathbuf, flags(), mode)
+        }
+    };
+    if fd < (0 as c_int) {
+        return Err(format!("error opening {}: {}", tmp.to_str(), os::last_os_error()));
+    }
+    let mut tmp_res = TempFileRes { path: tmp.clone(), keep: false };
+
+    {
+        let _fd = FdRes::new(fd);
+        let w = @BufferedWriter::new(@fd as @Writer);
+        f(w as @Writer);
+        if w.flush() != 0 {
+            return Err(format!("error writing {}: {}", tmp.to_str(), os::last_os_error()));
+        }
+        if os::fsync_fd(fd, fsync::FSync) != 0 {
+            return Err(format!("error syncing {}: {}", tmp.to_str(), os::last_os_error()));
+        }
+    }
+
+    if !replace_file(&tmp, path) {
+        return Err(format!("error renaming {} to {}: {}",
+                           tmp.to_str(), path.to_str(), os::last_os_error()));
+    }
+    tmp_res.keep = true;
+
+    let dir = path.dir_path();
+    if !sync_dir(&dir) {
+        return Err(format!("error syncing {}: {}", dir.to_str(), os::last_os_error()));
+    }
+    Ok(())
+}
+
+// Removes the temporary file `atomic_write` writes to, unless it's been
+// renamed into place, so that nothing is left behind when the writer fails
+struct TempFileRes {
+    path: Path,
+    keep: bool
+}
+
+impl Drop for TempFileRes {
+    fn drop(&mut self) {
+        if !self.keep {
+            os::remove_file(&self.path);
+        }
+    }
+}
+
 // FIXME (#2004) it would be great if this could be a const
 // FIXME (#2004) why are these different from the way stdin() is
 // implemented?
@@ -1734,6 +1864,296 @@ pub fn with_str_writer(f: &fn(@Writer)) -> ~str {
     str::from_utf8(with_bytes_writer(f))
 }
 
+// The buffer size used by `BufferedReader::new` and `BufferedWriter::new`.
+static DEFAULT_BUF_CAPACITY: uint = 64 * 1024;
+
+/**
+* A `Reader` that reads from another `Reader` in large blocks, to make many
+* small reads cheaper.
+*
+* # Example
+*
+* ```rust
+* let r = @BufferedReader::new(file_reader(&Path("input.txt")).unwrap());
+* for line in r.read_lines().iter() { ... }
+* ```
+*/
+pub struct BufferedReader {
+    priv inner: @Reader,
+    priv buf: @mut ~[u8],
+    priv pos: @mut uint,
+    priv capacity: uint
+}
+
+impl BufferedReader {
+    /// Wraps `inner` with a buffer of `capacity` bytes.
+    pub fn with_capacity(capacity: uint, inner: @Reader) -> BufferedReader {
+        assert!(capacity > 0);
+        BufferedReader {
+            inner: inner,
+            buf: @mut vec::with_capacity(capacity),
+            pos: @mut 0,
+            capacity: capacity
+        }
+    }
+
+    /// Wraps `inner` with a buffer of the default size.
+    pub fn new(inner: @Reader) -> BufferedReader {
+        BufferedReader::with_capacity(DEFAULT_BUF_CAPACITY, inner)
+    }
+
+    // Refills the buffer from the underlying reader if it has all been
+    // read. Returns the number of bytes left in the buffer, which is only
+    // 0 at the end of the stream.
+    fn fill(&self) -> uint {
+        let buf = &mut *self.buf;
+        if *self.pos == buf.len() {
+            let len = buf.len();
+            buf.grow(self.capacity - len, &0u8);
+            let n = self.inner.read(buf.mut_slice(0, self.capacity), self.capacity);
+            buf.truncate(n);
+            *self.pos = 0;
+        }
+        buf.len() - *self.pos
+    }
+}
+
+impl Reader for BufferedReader {
+    fn read(&self, bytes: &mut [u8], len: uint) -> uint {
+        let mut count = 0;
+        while count < len {
+            let available = self.fill();
+            if available == 0 {
+                break;
+            }
+            let n = num::min(len - count, available);
+            let pos = *self.pos;
+            vec::bytes::copy_memory(bytes.mut_slice(count, count + n),
+                                    self.buf.slice(pos, pos + n), n);
+            *self.pos += n;
+            count += n;
+        }
+        count
+    }
+
+    fn read_byte(&self) -> int {
+        if self.fill() == 0 {
+            return -1;
+        }
+        let b = self.buf[*self.pos];
+        *self.pos += 1;
+        b as int
+    }
+
+    fn eof(&self) -> bool {
+        *self.pos == self.buf.len() && self.inner.eof()
+    }
+
+    fn seek(&self, offset: int, whence: SeekStyle) {
+        // The underlying reader is ahead by whatever is still buffered
+        let offset = match whence {
+            SeekCur => offset - (self.buf.len() - *self.pos) as int,
+            SeekSet | SeekEnd => offset
+        };
+        self.buf.clear();
+        *self.pos = 0;
+        self.inner.seek(offset, whence);
+    }
+
+    fn tell(&self) -> uint {
+        self.inner.tell() - (self.buf.len() - *self.pos)
+    }
+}
+
+/**
+* A `Writer` that collects writes to another `Writer` in a buffer, passing
+* them on when the buffer fills up, when `flush` is called or when the
+* `BufferedWriter` is dropped.
+*
+* Errors from writes that happen on drop can't be reported, so call `flush`
+* once done writing to find out about them.
+*
+* # Example
+*
+* ```rust
+* let w = @BufferedWriter::new(file_writer(&Path("out.txt"), [Create]).unwrap());
+* for line in lines.iter() { w.write_line(*line); }
+* w.flush();
+* ```
+*/
+pub struct BufferedWriter {
+    priv inner: @Writer,
+    priv buf: @mut ~[u8],
+    priv capacity: uint
+}
+
+impl BufferedWriter {
+    /// Wraps `inner` with a buffer of `capacity` bytes.
+    pub fn with_capacity(capacity: uint, inner: @Writer) -> BufferedWriter {
+        BufferedWriter {
+            inner: inner,
+            buf: @mut vec::with_capacity(capacity),
+            capacity: capacity
+        }
+    }
+
+    /// Wraps `inner` with a buffer of the default size.
+    pub fn new(inner: @Writer) -> BufferedWriter {
+        BufferedWriter::with_capacity(DEFAULT_BUF_CAPACITY, inner)
+    }
+
+    // Passes everything buffered on to the underlying writer, without
+    // flushing it.
+    fn write_buffered(&self) {
+        if !self.buf.is_empty() {
+            self.inner.write(*self.buf);
+            self.buf.clear();
+        }
+    }
+}
+
+impl Writer for BufferedWriter {
+    fn write(&self, v: &[u8]) {
+        if self.buf.len() + v.len() > self.capacity {
+            self.write_buffered();
+        }
+        if v.len() >= self.capacity {
+            self.inner.write(v);
+        } else {
+            self.buf.push_all(v);
+        }
+    }
+
+    fn seek(&self, offset: int, whence: SeekStyle) {
+        self.write_buffered();
+        self.inner.seek(offset, whence);
+    }
+
+    fn tell(&self) -> uint {
+        self.inner.tell() + self.buf.len()
+    }
+
+    fn flush(&self) -> int {
+        self.write_buffered();
+        self.inner.flush()
+    }
+
+    fn get_type(&self) -> WriterType {
+        self.inner.get_type()
+    }
+}
+
+#[unsafe_destructor]
+impl Drop for BufferedWriter {
+    fn drop(&mut self) {
+        self.flush();
+    }
+}
+
+/// How `MappedFile::open` maps a file.
+#[deriving(Eq)]
+pub enum MapMode {
+    /// The mapping can only be read.
+    MapReadOnly,
+    /// The mapping can also be written to, but the changes are private to
+    /// it and never reach the file.
+    MapCopyOnWrite
+}
+
+/**
+* A file mapped into memory, for reading large files without copying them
+* through a buffer. The contents are only available through slices
+* borrowed from the `MappedFile`, so they can't outlive the mapping.
+*
+* Changes made to the file by other processes while it is mapped may or
+* may not be visible, and truncating it while it is mapped can crash the
+* process on some platforms.
+*
+* # Example
+*
+* ```rust
+* let file = MappedFile::open(&Path("big.bin"), MapReadOnly).unwrap();
+* sha.input(file.as_slice());
+* ```
+*/
+pub struct MappedFile {
+    // Empty files can't be mapped, so they have no mapping at all
+    priv map: Option<os::MemoryMap>,
+    priv len: uint,
+    priv mode: MapMode
+}
+
+impl MappedFile {
+    /// Maps the whole of the file at `path` into memory.
+    pub fn open(path: &Path, mode: MapMode) -> Result<MappedFile, ~str> {
+        #[fixed_stack_segment]; #[inline(never)];
+
+        #[cfg(windows)]
+        fn rb() -> c_int {
+            (O_RDONLY | libc::consts::os::extra::O_BINARY) as c_int
+        }
+
+        #[cfg(unix)]
+        fn rb() -> c_int { O_RDONLY as c_int }
+
+        let len = match path.get_size() {
+            Some(len) => len as uint,
+            None => return Err(format!("error opening {}: {}",
+                                       path.to_str(), os::last_os_error()))
+        };
+        if len == 0 {
+            return Ok(MappedFile { map: None, len: 0, mode: mode });
+        }
+
+        let fd = unsafe {
+            do path.with_c_str |pathbuf| {
+                libc::open(pathbuf, rb(), 0 as c_int)
+            }
+        };
+        if fd < (0 as c_int) {
+            return Err(format!("error opening {}: {}", path.to_str(), os::last_os_error()));
+        }
+        // The mapping stays valid after the file is closed
+        let _fd = FdRes::new(fd);
+
+        let options = match mode {
+            MapReadOnly => ~[os::MapReadable, os::MapFd(fd)],
+            MapCopyOnWrite => ~[os::MapReadable, os::MapWritable,
+                                os::MapCopyOnWrite, os::MapFd(fd)]
+        };
+        match os::MemoryMap::new(len, options) {
+            Ok(map) => Ok(MappedFile { map: Some(map), len: len, mode: mode }),
+            Err(e) => Err(format!("error mapping {}: {}", path.to_str(), e.to_str()))
+        }
+    }
+
+    /// The contents of the file.
+    pub fn as_slice<'a>(&'a self) -> &'a [u8] {
+        match self.map {
+            Some(ref map) => unsafe {
+                cast::transmute(raw::Slice { data: map.data as *u8, len: self.len })
+            },
+            None => &[]
+        }
+    }
+
+    /// The contents of the file, for changing in place. Fails unless the
+    /// file was mapped with `MapCopyOnWrite`.
+    pub fn as_mut_slice<'a>(&'a mut self) -> &'a mut [u8] {
+        if self.mode != MapCopyOnWrite {
+            fail2!("can't write to a read-only file mapping");
+        }
+        match self.map {
+            Some(ref map) => unsafe {
+                cast::transmute(raw::Slice { data: map.data as *u8, len: self.len })
+            },
+            None => unsafe {
+                cast::transmute(raw::Slice { data: ptr::null::<u8>(), len: 0 })
+            }
+        }
+    }
+}
+
 // Utility functions
 pub fn seek_in_buf(offset: int, pos: uint, len: uint, whence: SeekStyle) ->
    uint {
@@ -1885,6 +2305,7 @@ mod tests {
     use i32;
     use io::{BytesWriter, SeekCur, SeekEnd, SeekSet};
     use io;
+    use os;
     use path::Path;
     use result::{Ok, Err};
     use u64;
@@ -2074,6 +2495,112 @@ mod tests {
         assert!(*wr.bytes == ~[0u8, 9u8, 4u8, 5u8, 8u8, 7u8]);
     }
 
+    #[test]
+    fn atomic_write() {
+        let path = Path("tmp/lib-io-test-atomic-write.txt");
+        io::file_writer(&path, [io::Create, io::Truncate]).unwrap().write_str("old");
+        let res = do io::atomic_write(&path) |w| {
+            w.write_str("new contents");
+        };
+        assert_eq!(res, Ok(()));
+        assert_eq!(io::read_whole_file_str(&path), Ok(~"new contents"));
+        // Nothing is left behind
+        assert!(!os::list_dir(&path.dir_path()).iter().any(|f| {
+            f.starts_with(".lib-io-test-atomic-write.txt.")
+        }));
+        os::remove_file(&path);
+    }
+
+    #[test]
+    fn atomic_write_failure() {
+        use task;
+
+        let path = Path("tmp/lib-io-test-atomic-write-failure.txt");
+        io::file_writer(&path, [io::Create, io::Truncate]).unwrap().write_str("old");
+        let p = path.clone();
+        let res = do task::try {
+            do io::atomic_write(&p) |w| {
+                w.write_str("half of the new");
+                fail2!();
+            };
+        };
+        assert!(res.is_err());
+        // The old contents are untouched, and the temporary file is gone
+        assert_eq!(io::read_whole_file_str(&path), Ok(~"old"));
+        assert!(!os::list_dir(&path.dir_path()).iter().any(|f| {
+            f.starts_with(".lib-io-test-atomic-write-failure.txt.")
+        }));
+        os::remove_file(&path);
+    }
+
+    #[test]
+    fn mapped_file() {
+        let path = Path("tmp/lib-io-test-mapped-file.tmp");
+        io::file_writer(&path, [io::Create, io::Truncate]).unwrap().write_str("mapped");
+
+        let file = io::MappedFile::open(&path, io::MapReadOnly).unwrap();
+        assert_eq!(file.as_slice(), "mapped".as_bytes());
+
+        let mut file = io::MappedFile::open(&path, io::MapCopyOnWrite).unwrap();
+        file.as_mut_slice()[0] = 'n' as u8;
+        assert_eq!(file.as_slice(), "napped".as_bytes());
+        // The file itself is unchanged
+        assert_eq!(io::read_whole_file_str(&path), Ok(~"mapped"));
+        os::remove_file(&path);
+    }
+
+    #[test]
+    fn mapped_empty_file() {
+        let path = Path("tmp/lib-io-test-mapped-empty-file.tmp");
+        io::file_writer(&path, [io::Create, io::Truncate]).unwrap();
+        let file = io::MappedFile::open(&path, io::MapReadOnly).unwrap();
+        assert!(file.as_slice().is_empty());
+        os::remove_file(&path);
+    }
+
+    #[test]
+    fn buffered_writer() {
+        let bytes = @BytesWriter::new();
+        {
+            let w = io::BufferedWriter::with_capacity(4, bytes as @io::Writer);
+            w.write([0u8, 1u8]);
+            assert!(bytes.bytes.is_empty());
+            assert_eq!(w.tell(), 2);
+            w.write([2u8, 3u8, 4u8]);
+            assert!(*bytes.bytes == ~[0u8, 1u8]);
+            w.flush();
+            assert!(*bytes.bytes == ~[0u8, 1u8, 2u8, 3u8, 4u8]);
+            w.write([5u8]);
+        }
+        // The rest is written when the writer is dropped
+        assert!(*bytes.bytes == ~[0u8, 1u8, 2u8, 3u8, 4u8, 5u8]);
+    }
+
+    #[test]
+    fn buffered_writer_flushes_on_drop() {
+        let path = Path("tmp/lib-io-test-buffered-writer-drop.tmp");
+        {
+            let file = io::file_writer(&path, [io::Create, io::Truncate]).unwrap();
+            let w = @io::BufferedWriter::new(file) as @io::Writer;
+            w.write_str("dropped");
+        }
+        assert_eq!(io::read_whole_file_str(&path), Ok(~"dropped"));
+        os::remove_file(&path);
+    }
+
+    #[test]
+    fn buffered_reader() {
+        do io::with_str_reader("abcdefg\nhij\n") |inner| {
+            let r = @io::BufferedReader::with_capacity(3, inner) as @io::Reader;
+            assert_eq!(r.read_byte(), 'a' as int);
+            assert_eq!(r.tell(), 1);
+            assert_eq!(r.read_bytes(4), ~['b' as u8, 'c' as u8, 'd' as u8, 'e' as u8]);
+            r.seek(-2, SeekCur);
+            assert_eq!(r.read_lines(), ~[~"defg", ~"hij"]);
+            assert!(r.eof());
+        }
+    }
+
     #[test]
     fn test_read_write_le() {
         let path = Path("tmp/lib-io-test-read-write-le.tmp");
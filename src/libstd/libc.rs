@@ -108,6 +108,7 @@ pub use libc::funcs::posix88::mman::*;
 
 pub use libc::funcs::posix01::stat_::*;
 pub use libc::funcs::posix01::unistd::*;
+pub use libc::funcs::posix01::stdlib::*;
 pub use libc::funcs::posix01::glob::*;
 pub use libc::funcs::posix01::mman::*;
 pub use libc::funcs::posix08::unistd::*;
@@ -1211,6 +1212,9 @@ pub mod consts {
             pub static ERROR_FILE_INVALID : c_int = 1006;
             pub static INVALID_HANDLE_VALUE: c_int = -1;
 
+            pub static INVALID_FILE_ATTRIBUTES : DWORD = 0xffffffff;
+            pub static FILE_ATTRIBUTE_REPARSE_POINT : DWORD = 0x00000400;
+
             pub static DELETE : DWORD = 0x00010000;
             pub static READ_CONTROL : DWORD = 0x00020000;
             pub static SYNCHRONIZE : DWORD = 0x00100000;
@@ -1252,6 +1256,10 @@ pub mod consts {
             pub static DUPLICATE_CLOSE_SOURCE : DWORD = 0x00000001;
             pub static DUPLICATE_SAME_ACCESS : DWORD = 0x00000002;
 
+            pub static MOVEFILE_REPLACE_EXISTING : DWORD = 0x00000001;
+            pub static MOVEFILE_COPY_ALLOWED : DWORD = 0x00000002;
+            pub static MOVEFILE_WRITE_THROUGH : DWORD = 0x00000008;
+
             pub static INFINITE : DWORD = -1;
             pub static STILL_ACTIVE : DWORD = 259;
 
@@ -1346,6 +1354,7 @@ pub mod consts {
             pub static S_IFBLK : c_int = 24576;
             pub static S_IFDIR : c_int = 16384;
             pub static S_IFREG : c_int = 32768;
+            pub static S_IFLNK : c_int = 40960;
             pub static S_IFMT : c_int = 61440;
             pub static S_IEXEC : c_int = 64;
             pub static S_IWRITE : c_int = 128;
@@ -1556,6 +1565,7 @@ pub mod consts {
             pub static S_IFBLK : c_int = 24576;
             pub static S_IFDIR : c_int = 16384;
             pub static S_IFREG : c_int = 32768;
+            pub static S_IFLNK : c_int = 40960;
             pub static S_IFMT : c_int = 61440;
             pub static S_IEXEC : c_int = 64;
             pub static S_IWRITE : c_int = 128;
@@ -2000,6 +2010,7 @@ pub mod consts {
             pub static S_IFBLK : c_int = 24576;
             pub static S_IFDIR : c_int = 16384;
             pub static S_IFREG : c_int = 32768;
+            pub static S_IFLNK : c_int = 40960;
             pub static S_IFMT : c_int = 61440;
             pub static S_IEXEC : c_int = 64;
             pub static S_IWRITE : c_int = 128;
@@ -2342,6 +2353,7 @@ pub mod consts {
             pub static S_IFBLK : c_int = 24576;
             pub static S_IFDIR : c_int = 16384;
             pub static S_IFREG : c_int = 32768;
+            pub static S_IFLNK : c_int = 40960;
             pub static S_IFMT : c_int = 61440;
             pub static S_IEXEC : c_int = 64;
             pub static S_IWRITE : c_int = 128;
@@ -3195,6 +3207,17 @@ pub mod funcs {
             }
         }
 
+        #[nolink]
+        #[abi = "cdecl"]
+        pub mod stdlib {
+            use libc::types::os::arch::c95::c_char;
+
+            extern {
+                pub fn realpath(path: *c_char, resolved_path: *mut c_char)
+                                -> *c_char;
+            }
+        }
+
         #[nolink]
         #[abi = "cdecl"]
         pub mod wait {
@@ -3246,6 +3269,9 @@ pub mod funcs {
         pub mod unistd {
         }
 
+        pub mod stdlib {
+        }
+
         pub mod glob {
         }
 
@@ -3348,7 +3374,7 @@ pub mod funcs {
 
         pub mod kernel32 {
             use libc::types::os::arch::c95::{c_uint};
-            use libc::types::os::arch::extra::{BOOL, DWORD, SIZE_T, HMODULE};
+            use libc::types::os::arch::extra::{BOOL, BYTE, DWORD, SIZE_T, HMODULE};
             use libc::types::os::arch::extra::{LPCWSTR, LPWSTR, LPCTSTR,
                                                LPTSTR, LPTCH, LPDWORD, LPVOID,
                                                LPCVOID};
@@ -3382,6 +3408,15 @@ pub mod funcs {
                                         bFailIfExists: BOOL)
                                         -> BOOL;
                 pub fn DeleteFileW(lpPathName: LPCWSTR) -> BOOL;
+                pub fn GetFileAttributesW(lpFileName: LPCWSTR) -> DWORD;
+                pub fn MoveFileExW(lpExistingFileName: LPCWSTR,
+                                   lpNewFileName: LPCWSTR,
+                                   dwFlags: DWORD)
+                                   -> BOOL;
+                pub fn CreateSymbolicLinkW(lpSymlinkFileName: LPCWSTR,
+                                           lpTargetFileName: LPCWSTR,
+                                           dwFlags: DWORD)
+                                           -> BYTE;
                 pub fn RemoveDirectoryW(lpPathName: LPCWSTR) -> BOOL;
                 pub fn SetCurrentDirectoryW(lpPathName: LPCWSTR) -> BOOL;
                 pub fn GetLastError() -> DWORD;
@@ -3487,6 +3522,15 @@ pub mod funcs {
                                         bFailIfExists: BOOL)
                                         -> BOOL;
                 pub fn DeleteFileW(lpPathName: LPCWSTR) -> BOOL;
+                pub fn GetFileAttributesW(lpFileName: LPCWSTR) -> DWORD;
+                pub fn MoveFileExW(lpExistingFileName: LPCWSTR,
+                                   lpNewFileName: LPCWSTR,
+                                   dwFlags: DWORD)
+                                   -> BOOL;
+                pub fn CreateSymbolicLinkW(lpSymlinkFileName: LPCWSTR,
+                                           lpTargetFileName: LPCWSTR,
+                                           dwFlags: DWORD)
+                                           -> BYTE;
                 pub fn RemoveDirectoryW(lpPathName: LPCWSTR) -> BOOL;
                 pub fn SetCurrentDirectoryW(lpPathName: LPCWSTR) -> BOOL;
                 pub fn GetLastError() -> DWORD;
@@ -41,6 +41,7 @@ local_data::get(key_vector, |opt| assert_eq!(opt, Some(&~[4])));
 // magic.
 
 use cast;
+use hashmap::HashMap;
 use libc;
 use prelude::*;
 use rt::task::{Task, LocalStorage};
@@ -83,28 +84,73 @@ impl<T: 'static> LocalData for T {}
 // pointers from being moved under our feet so long as LLVM doesn't go too crazy
 // with the optimizations.
 //
-// n.b. If TLS is used heavily in future, this could be made more efficient with
-//      a proper map.
+// To keep lookups cheap with many keys, each key is given a slot in the
+// vector when it is first set, and a hash table maps keys to their slots.
+// Emptied slots are reused by the next new key.
+//
+// Each entry also records when it was set, relative to the other entries,
+// because slots are reused and so the vector's order means nothing. When the
+// task exits its local data is destroyed in the reverse of that order.
 #[doc(hidden)]
-pub type Map = ~[Option<(*libc::c_void, TLSValue, LoanState)>];
+pub struct Map {
+    priv entries: ~[Option<(*libc::c_void, TLSValue, LoanState, uint)>],
+    priv slots: HashMap<*libc::c_void, uint>,
+    priv free: ~[uint],
+    priv next_seq: uint
+}
 type TLSValue = ~LocalData;
 
+impl Map {
+    fn new() -> Map {
+        Map {
+            entries: ~[],
+            // Keys are addresses of statics, so there's nothing to gain from
+            // random hash keys, and getting some would need TLS itself.
+            slots: HashMap::with_capacity_and_keys(0, 0, 8),
+            free: ~[],
+            next_seq: 0
+        }
+    }
+
+    fn slot(&self, key: *libc::c_void) -> Option<uint> {
+        self.slots.find_copy(&key)
+    }
+
+    // Remove the entry in slot `i`, freeing the slot.
+    fn remove(&mut self, i: uint) -> Option<(*libc::c_void, TLSValue, LoanState, uint)> {
+        let entry = util::replace(&mut self.entries[i], None);
+        match entry {
+            Some((key, _, _, _)) => {
+                self.slots.pop(&key);
+                self.free.push(i);
+            }
+            None => {}
+        }
+        entry
+    }
+}
+
 // Gets the map from the runtime. Lazily initialises if not done so already.
 unsafe fn get_local_map() -> &mut Map {
     use rt::local::Local;
 
     let task: *mut Task = Local::unsafe_borrow();
-    match &mut (*task).storage {
+    let storage: &mut LocalStorage = &mut (*task).storage;
+    if storage.destroyed {
+        fail2!("task-local data accessed after the task's local data \
+                was destroyed");
+    }
+    match storage.map {
         // If the at_exit function is already set, then we just need to take
         // a loan out on the TLS map stored inside
-        &LocalStorage(Some(ref mut map_ptr)) => {
+        Some(ref mut map_ptr) => {
             return map_ptr;
         }
         // If this is the first time we've accessed TLS, perform similar
         // actions to the oldsched way of doing things.
-        &LocalStorage(ref mut slot) => {
-            *slot = Some(~[]);
-            match *slot {
+        None => {
+            storage.map = Some(Map::new());
+            match storage.map {
                 Some(ref mut map_ptr) => { return map_ptr }
                 None => abort()
             }
@@ -112,6 +158,47 @@ unsafe fn get_local_map() -> &mut Map {
     }
 }
 
+/// Destroys all of the current task's local data, most recently set first,
+/// so that each value's destructor may still use the values set before it.
+/// Any later use of TLS by the task fails. Called by the runtime when the
+/// task exits, before the task's heap is torn down.
+#[doc(hidden)]
+pub unsafe fn destroy_all() {
+    use rt::local::Local;
+
+    let task: *mut Task = Local::unsafe_borrow();
+    loop {
+        // Destructors may set new values, so look for the latest entry
+        // again each time round.
+        let latest = match (*task).storage.map {
+            Some(ref mut map) => {
+                let mut latest: Option<(uint, uint)> = None;
+                for (i, entry) in map.entries.iter().enumerate() {
+                    match *entry {
+                        Some((_, _, _, seq)) => {
+                            match latest {
+                                Some((_, latest_seq)) if latest_seq > seq => {}
+                                _ => latest = Some((i, seq))
+                            }
+                        }
+                        None => {}
+                    }
+                }
+                latest.map(|(i, _)| map.remove(i))
+            }
+            None => None
+        };
+        match latest {
+            // Any loan is left over from failure inside `get`, so it's
+            // safe to drop the value, which runs its destructor.
+            Some(entry) => { let _ = entry; }
+            None => break
+        }
+    }
+    (*task).storage.map = None;
+    (*task).storage.destroyed = true;
+}
+
 #[deriving(Eq)]
 enum LoanState {
     NoLoan, ImmLoan, MutLoan
@@ -140,35 +227,33 @@ pub fn pop<T: 'static>(key: Key<T>) -> Option<T> {
     let map = unsafe { get_local_map() };
     let key_value = key_to_key_value(key);
 
-    for entry in map.mut_iter() {
-        match *entry {
-            Some((k, _, loan)) if k == key_value => {
-                if loan != NoLoan {
-                    fail2!("TLS value cannot be removed because it is currently \
-                          borrowed as {}", loan.describe());
-                }
-                // Move the data out of the `entry` slot via util::replace.
-                // This is guaranteed to succeed because we already matched
-                // on `Some` above.
-                let data = match util::replace(entry, None) {
-                    Some((_, data, _)) => data,
-                    None => abort()
-                };
-
-                // Move `data` into transmute to get out the memory that it
-                // owns, we must free it manually later.
-                let (_vtable, box): (uint, ~T) = unsafe {
-                    cast::transmute(data)
-                };
-
-                // Now that we own `box`, we can just move out of it as we would
-                // with any other data.
-                return Some(*box);
-            }
-            _ => {}
+    let i = match map.slot(key_value) {
+        Some(i) => i,
+        None => return None
+    };
+    match map.entries[i] {
+        Some((_, _, loan, _)) if loan != NoLoan => {
+            fail2!("TLS value cannot be removed because it is currently \
+                  borrowed as {}", loan.describe());
         }
+        _ => {}
     }
-    return None;
+    // Move the data out of the slot. This is guaranteed to succeed because
+    // the key maps to it.
+    let data = match map.remove(i) {
+        Some((_, data, _, _)) => data,
+        None => abort()
+    };
+
+    // Move `data` into transmute to get out the memory that it
+    // owns, we must free it manually later.
+    let (_vtable, box): (uint, ~T) = unsafe {
+        cast::transmute(data)
+    };
+
+    // Now that we own `box`, we can just move out of it as we would
+    // with any other data.
+    return Some(*box);
 }
 
 /// Retrieves a value from TLS. The closure provided is yielded `Some` of a
@@ -222,18 +307,13 @@ fn get_with<T: 'static, U>(key: Key<T>,
     let map = unsafe { get_local_map() };
     let key_value = key_to_key_value(key);
 
-    let pos = map.iter().position(|entry| {
-        match *entry {
-            Some((k, _, _)) if k == key_value => true, _ => false
-        }
-    });
-    match pos {
+    match map.slot(key_value) {
         None => { return f(None); }
         Some(i) => {
             let ret;
             let mut return_loan = false;
-            match map[i] {
-                Some((_, ref data, ref mut loan)) => {
+            match map.entries[i] {
+                Some((_, ref data, ref mut loan, _)) => {
                     match (state, *loan) {
                         (_, NoLoan) => {
                             *loan = state;
@@ -266,8 +346,8 @@ fn get_with<T: 'static, U>(key: Key<T>,
             // in turn relocated the vector. Hence we do another lookup here to
             // fixup the loans.
             if return_loan {
-                match map[i] {
-                    Some((_, _, ref mut loan)) => { *loan = NoLoan; }
+                match map.entries[i] {
+                    Some((_, _, ref mut loan, _)) => { *loan = NoLoan; }
                     None => abort()
                 }
             }
@@ -298,36 +378,35 @@ pub fn set<T: 'static>(key: Key<T>, data: T) {
     // traits have enough information about them to destroy themselves.
     let data = ~data as ~LocalData:;
 
-    fn insertion_position(map: &mut Map,
-                          key: *libc::c_void) -> Option<uint> {
-        // First see if the map contains this key already
-        let curspot = map.iter().position(|entry| {
-            match *entry {
-                Some((ekey, _, loan)) if key == ekey => {
-                    if loan != NoLoan {
-                        fail2!("TLS value cannot be overwritten because it is
-                               already borrowed as {}", loan.describe())
-                    }
-                    true
-                }
-                _ => false,
-            }
-        });
-        // If it doesn't contain the key, just find a slot that's None
-        match curspot {
-            Some(i) => Some(i),
-            None => map.iter().position(|entry| entry.is_none())
-        }
-    }
-
     // The type of the local data map must ascribe to Send, so we do the
     // transmute here to add the Send bound back on. This doesn't actually
     // matter because TLS will always own the data (until its moved out) and
     // we're not actually sending it to other schedulers or anything.
     let data: ~LocalData = unsafe { cast::transmute(data) };
-    match insertion_position(map, keyval) {
-        Some(i) => { map[i] = Some((keyval, data, NoLoan)); }
-        None => { map.push(Some((keyval, data, NoLoan))); }
+    let seq = map.next_seq;
+    map.next_seq += 1;
+    match map.slot(keyval) {
+        Some(i) => {
+            match map.entries[i] {
+                Some((_, _, loan, _)) if loan != NoLoan => {
+                    fail2!("TLS value cannot be overwritten because it is
+                           already borrowed as {}", loan.describe())
+                }
+                _ => {}
+            }
+            map.entries[i] = Some((keyval, data, NoLoan, seq));
+        }
+        None => {
+            let i = match map.free.pop_opt() {
+                Some(i) => i,
+                None => {
+                    map.entries.push(None);
+                    map.entries.len() - 1
+                }
+            };
+            map.entries[i] = Some((keyval, data, NoLoan, seq));
+            map.slots.insert(keyval, i);
+        }
     }
 }
 
@@ -565,4 +644,68 @@ mod tests {
             get_mut(key, |_| {})
         }
     }
+
+    #[test]
+    fn test_destroyed_in_reverse_order() {
+        use comm::{stream, SharedChan};
+
+        static key1: Key<Noisy> = &Key;
+        static key2: Key<Noisy> = &Key;
+        static key3: Key<Noisy> = &Key;
+
+        // Reports its id, and whether the value set before it still
+        // exists, when destroyed.
+        struct Noisy { id: uint, prev: Option<Key<Noisy>>, chan: SharedChan<(uint, bool)> }
+        impl Drop for Noisy {
+            fn drop(&mut self) {
+                let prev_alive = match self.prev {
+                    Some(key) => get(key, |v| v.is_some()),
+                    None => true
+                };
+                self.chan.send((self.id, prev_alive));
+            }
+        }
+
+        let (port, chan) = stream();
+        let chan = SharedChan::new(chan);
+        do task::spawn {
+            set(key2, Noisy { id: 2, prev: None, chan: chan.clone() });
+            set(key1, Noisy { id: 1, prev: Some(key2), chan: chan.clone() });
+            set(key3, Noisy { id: 3, prev: Some(key1), chan: chan.clone() });
+        }
+        assert_eq!(port.recv(), (3, true));
+        assert_eq!(port.recv(), (1, true));
+        assert_eq!(port.recv(), (2, true));
+    }
+}
+
+#[cfg(test)]
+mod bench {
+    use extra::test::BenchHarness;
+    use prelude::*;
+    use super::*;
+
+    // Each element is a distinct key. Lookups should take about as long
+    // with many keys set as with one.
+    static keys: [KeyValue<uint>, ..64] = [Key, ..64];
+
+    fn get_with_many_keys(bh: &mut BenchHarness, n: uint) {
+        for i in range(0, n) {
+            set(&keys[i], i);
+        }
+        let last = &keys[n - 1];
+        do bh.iter {
+            get(last, |v| assert!(v.is_some()));
+        }
+    }
+
+    #[bench]
+    fn get_with_one_key(bh: &mut BenchHarness) {
+        get_with_many_keys(bh, 1);
+    }
+
+    #[bench]
+    fn get_with_64_keys(bh: &mut BenchHarness) {
+        get_with_many_keys(bh, 64);
+    }
 }
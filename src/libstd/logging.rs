@@ -82,6 +82,26 @@ What this means is that each of these macros are very cheap at runtime if
 they're turned off (just a load and an integer comparison). This also means that
 if logging is disabled, none of the components of the log will be executed.
 
+## Replacing the logger
+
+Log messages are written to stderr by default, or to the file named by the
+`RUST_LOG_FILE` environment variable. A task can send its own messages
+elsewhere by installing a `Logger` of its own with `set_logger`. This affects
+only the task that installs it, and is also used for the message reporting
+the task's failure.
+
+```rust
+struct Prefixed;
+
+impl Logger for Prefixed {
+    fn log(&mut self, args: &fmt::Arguments) {
+        println!("worker: {}", fmt::format(args));
+    }
+}
+
+logging::set_logger(~Prefixed as ~Logger);
+```
+
 ## Useful Values
 
 For convenience, if a value of `::help` is set for `RUST_LOG`, a program will
@@ -92,8 +112,10 @@ start, print out all modules registered for logging, and then exit.
 use fmt;
 use option::*;
 use rt::local::Local;
-use rt::logging::{Logger, StdErrLogger};
 use rt::task::Task;
+use util;
+
+pub use rt::logging::{Logger, StdErrLogger};
 
 /// This function is called directly by the compiler when using the logging
 /// macros. This function does not take into account whether the log level
@@ -107,8 +129,19 @@ pub fn log(_level: u32, args: &fmt::Arguments) {
         let optional_task: Option<*mut Task> = Local::try_unsafe_borrow();
         match optional_task {
             Some(local) => {
-                // Use the available logger
-                (*local).logger.log(args);
+                // Take the task's logger out while it's in use, so that if it
+                // logs anything itself that goes to stderr.
+                match util::replace(&mut (*local).logger, None) {
+                    Some(logger) => {
+                        let mut logger = logger;
+                        logger.log(args);
+                        (*local).logger = Some(logger);
+                    }
+                    None => {
+                        let mut logger = StdErrLogger;
+                        logger.log(args);
+                    }
+                }
             }
             None => {
                 // There is no logger anywhere, just write to stderr
@@ -118,3 +151,56 @@ pub fn log(_level: u32, args: &fmt::Arguments) {
         }
     }
 }
+
+/// Replace the current task's logger, returning the one it had, if it had
+/// one other than the default.
+pub fn set_logger(logger: ~Logger) -> Option<~Logger> {
+    do Local::borrow |task: &mut Task| {
+        util::replace(&mut task.logger, Some(logger))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use cell::Cell;
+    use comm::{stream, Chan};
+    use fmt;
+    use prelude::*;
+    use super::{Logger, set_logger};
+    use task;
+
+    struct ChanLogger {
+        chan: Chan<~str>
+    }
+
+    impl Logger for ChanLogger {
+        fn log(&mut self, args: &fmt::Arguments) {
+            self.chan.send(fmt::format(args));
+        }
+    }
+
+    #[test]
+    fn replaced_logger_gets_messages() {
+        let (port, chan) = stream();
+        let chan = Cell::new(chan);
+        do task::spawn {
+            assert!(set_logger(~ChanLogger { chan: chan.take() } as ~Logger).is_none());
+            error2!("logged {}", 1);
+        }
+        assert_eq!(port.recv(), ~"logged 1");
+    }
+
+    #[test]
+    fn replaced_logger_is_per_task() {
+        let (port, chan) = stream();
+        let chan = Cell::new(chan);
+        do task::spawn {
+            set_logger(~ChanLogger { chan: chan.take() } as ~Logger);
+            do task::spawn {
+                error2!("from the child");
+            }
+            error2!("from the parent");
+        }
+        assert_eq!(port.recv(), ~"from the parent");
+    }
+}
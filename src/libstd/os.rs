@@ -63,6 +63,9 @@ pub mod rustrt {
     extern {
         pub fn rust_path_is_dir(path: *libc::c_char) -> c_int;
         pub fn rust_path_exists(path: *libc::c_char) -> c_int;
+        pub fn rust_set_file_times(path: *libc::c_char, atime: i64, mtime: i64) -> c_int;
+        pub fn rust_lock_fd(fd: c_int, wait: c_int) -> c_int;
+        pub fn rust_unlock_fd(fd: c_int) -> c_int;
     }
 }
 
@@ -603,13 +606,84 @@ pub fn tmpdir() -> Path {
     }
 }
 
-/// Recursively walk a directory structure
-pub fn walk_dir(p: &Path, f: &fn(&Path) -> bool) -> bool {
-    let r = list_dir(p);
-    r.iter().advance(|q| {
-        let path = &p.push(*q);
-        f(path) && (!path_is_dir(path) || walk_dir(path, |p| f(p)))
-    })
+/// The kind of file found by `walk_dir`.
+#[deriving(Eq, Clone)]
+pub enum FileKind {
+    RegularFile,
+    Directory,
+    SymbolicLink
+}
+
+/// An entry found by `walk_dir`.
+#[deriving(Clone)]
+pub struct DirEntry {
+    /// The entry's path, starting with the path the walk started from
+    path: Path,
+    /// What kind of file the entry is. Symbolic links aren't followed.
+    kind: FileKind,
+    /// How many directories down from the start the entry is, with the
+    /// contents of the starting directory at depth 0
+    depth: uint
+}
+
+/// An iterator over the contents of a directory tree, created by `walk_dir`.
+pub struct WalkDir {
+    // Entries still to visit, with the next one at the end
+    priv stack: ~[(Path, uint)],
+    // The last directory yielded, whose contents haven't been listed yet
+    priv pending: Option<(Path, uint)>
+}
+
+/**
+ * Recursively walk a directory structure, depth first.
+ *
+ * Each directory is yielded before its contents, which are only listed
+ * once the iterator moves past it, so calling `skip_subtree` straight
+ * after a directory is yielded keeps the walk out of it.
+ */
+pub fn walk_dir(p: &Path) -> WalkDir {
+    WalkDir {
+        stack: ~[],
+        pending: Some((p.clone(), 0))
+    }
+}
+
+impl WalkDir {
+    /// Don't walk into the contents of the directory that was just yielded.
+    /// Does nothing if the last entry wasn't a directory.
+    pub fn skip_subtree(&mut self) {
+        self.pending = None;
+    }
+}
+
+impl Iterator<DirEntry> for WalkDir {
+    fn next(&mut self) -> Option<DirEntry> {
+        match self.pending.take() {
+            Some((dir, depth)) => {
+                let mut names = list_dir(&dir);
+                names.reverse();
+                for name in names.move_iter() {
+                    self.stack.push((dir.push(name), depth));
+                }
+            }
+            None => ()
+        }
+
+        match self.stack.pop_opt() {
+            Some((path, depth)) => {
+                let kind = if path_is_symlink(&path) {
+                    SymbolicLink
+                } else if path_is_dir(&path) {
+                    self.pending = Some((path.clone(), depth + 1));
+                    Directory
+                } else {
+                    RegularFile
+                };
+                Some(DirEntry { path: path, kind: kind, depth: depth })
+            }
+            None => None
+        }
+    }
 }
 
 /// Indicates whether a path represents a directory
@@ -650,6 +724,45 @@ pub fn make_absolute(p: &Path) -> Path {
     }
 }
 
+/**
+ * Returns the canonical absolute form of a path, with every symlink in it
+ * followed and every '.' and '..' component resolved, or None if the path
+ * doesn't exist.
+ *
+ * Unlike `Path::normalize`, this consults the filesystem, so a '..' after a
+ * symlink leads to the parent of the link's target. On Windows symlinks are
+ * not followed; the path is only made absolute and normalized.
+ */
+pub fn canonicalize(p: &Path) -> Option<Path> {
+    return canonicalize_(p);
+
+    #[cfg(windows)]
+    fn canonicalize_(p: &Path) -> Option<Path> {
+        if path_exists(p) {
+            Some(make_absolute(p).normalize())
+        } else {
+            None
+        }
+    }
+
+    #[cfg(unix)]
+    fn canonicalize_(p: &Path) -> Option<Path> {
+        #[fixed_stack_segment]; #[inline(never)];
+        do p.with_c_str |buf| {
+            unsafe {
+                let resolved = libc::realpath(buf, ptr::mut_null());
+                if resolved.is_null() {
+                    None
+                } else {
+                    let path = Path(str::raw::from_c_str(resolved));
+                    libc::free(resolved as *c_void);
+                    Some(path)
+                }
+            }
+        }
+    }
+}
+
 
 /// Creates a directory at the specified path
 pub fn make_dir(p: &Path, mode: c_int) -> bool {
@@ -809,24 +922,27 @@ pub fn list_dir_path(p: &Path) -> ~[Path] {
 /// Removes a directory at the specified path, after removing
 /// all its contents. Use carefully!
 pub fn remove_dir_recursive(p: &Path) -> bool {
-    let mut error_happened = false;
-    do walk_dir(p) |inner| {
-        if !error_happened {
-            if path_is_dir(inner) {
-                if !remove_dir_recursive(inner) {
-                    error_happened = true;
-                }
-            }
-            else {
-                if !remove_file(inner) {
-                    error_happened = true;
-                }
+    let mut walker = walk_dir(p);
+    loop {
+        let entry = match walker.next() {
+            Some(entry) => entry,
+            None => break
+        };
+        let removed = match entry.kind {
+            Directory => {
+                walker.skip_subtree();
+                remove_dir_recursive(&entry.path)
             }
+            RegularFile => remove_file(&entry.path),
+            // A link to a directory is removed as a directory on Windows
+            SymbolicLink => remove_file(&entry.path) || remove_dir(&entry.path)
+        };
+        if !removed {
+            return false;
         }
-        true
-    };
+    }
     // Directory should now be empty
-    !error_happened && remove_dir(p)
+    remove_dir(p)
 }
 
 /// Removes a directory at the specified path
@@ -985,6 +1101,312 @@ pub fn remove_file(p: &Path) -> bool {
     }
 }
 
+/// Options for `copy_dir_recursive`.
+pub struct CopyOptions {
+    /// Give the copies the same access and modification times as the
+    /// originals. Otherwise they are as of the time of copying.
+    preserve_times: bool,
+    /// Replace files that already exist in the destination. Otherwise they
+    /// are left alone and reported as errors.
+    overwrite: bool,
+    /// Called with the path, relative to the source directory, of each file
+    /// and directory in it. Those it returns false for aren't copied, nor is
+    /// anything in such a directory.
+    filter: Option<extern fn(&Path) -> bool>,
+}
+
+impl CopyOptions {
+    /// Returns options that preserve times, don't overwrite anything, and
+    /// copy everything.
+    pub fn new() -> CopyOptions {
+        CopyOptions {
+            preserve_times: true,
+            overwrite: false,
+            filter: None,
+        }
+    }
+}
+
+/**
+ * Copies the directory `src` and everything in it to `dst`, which is created
+ * if it doesn't exist yet. Permission bits are kept as they are, as with
+ * `copy_file`. Symbolic links are copied as links, pointing where the
+ * originals do, rather than followed, so a link to a directory that
+ * contains it can't make copying go on forever.
+ *
+ * Copying carries on past files that can't be copied. If there were any,
+ * they're returned along with a description of what went wrong.
+ */
+pub fn copy_dir_recursive(src: &Path, dst: &Path,
+                          options: CopyOptions) -> Result<(), ~[(Path, ~str)]> {
+    let mut errors = ~[];
+    copy_dir_into(src, dst, &Path(""), &options, &mut errors);
+    if errors.is_empty() { Ok(()) } else { Err(errors) }
+}
+
+// `rel` is `src` relative to the directory being copied
+fn copy_dir_into(src: &Path, dst: &Path, rel: &Path, options: &CopyOptions,
+                 errors: &mut ~[(Path, ~str)]) {
+    if !path_is_dir(dst) {
+        let mode = match src.get_mode() {
+            Some(mode) => mode as c_int,
+            None => libc::S_IRWXU as c_int
+        };
+        if !make_dir(dst, mode) {
+            errors.push((dst.clone(), last_os_error()));
+            return;
+        }
+    }
+
+    for name in list_dir(src).iter() {
+        let from = src.push(*name);
+        let to = dst.push(*name);
+        let from_rel = rel.push(*name);
+        match options.filter {
+            Some(f) if !f(&from_rel) => continue,
+            _ => ()
+        }
+        if path_is_symlink(&from) {
+            copy_symlink(&from, &to, options, errors);
+            continue;
+        }
+        if path_is_dir(&from) {
+            copy_dir_into(&from, &to, &from_rel, options, errors);
+            continue;
+        }
+        if !options.overwrite && path_exists(&to) {
+            errors.push((to, ~"destination already exists"));
+            continue;
+        }
+        if !copy_file(&from, &to) {
+            errors.push((from, last_os_error()));
+            continue;
+        }
+        if options.preserve_times && !copy_times(&from, &to) {
+            errors.push((to, last_os_error()));
+        }
+    }
+
+    // Copying the entries changed the directory's own times, so they go last
+    if options.preserve_times && !copy_times(src, dst) {
+        errors.push((dst.clone(), last_os_error()));
+    }
+}
+
+// Makes `to` a symbolic link to wherever the link `from` points. The link
+// itself has no times worth keeping.
+#[cfg(unix)]
+fn copy_symlink(from: &Path, to: &Path, options: &CopyOptions,
+                errors: &mut ~[(Path, ~str)]) {
+    if path_exists(to) || path_is_symlink(to) {
+        if !options.overwrite {
+            errors.push((to.clone(), ~"destination already exists"));
+            return;
+        }
+        if !remove_file(to) {
+            errors.push((to.clone(), last_os_error()));
+            return;
+        }
+    }
+    match readlink(from) {
+        Some(target) => {
+            if !symlink(&target, to) {
+                errors.push((to.clone(), last_os_error()));
+            }
+        }
+        None => errors.push((from.clone(), ~"couldn't read symbolic link"))
+    }
+}
+
+// There's no reading where a link points on Windows, so it can't be copied
+#[cfg(windows)]
+fn copy_symlink(from: &Path, _to: &Path, _options: &CopyOptions,
+                errors: &mut ~[(Path, ~str)]) {
+    errors.push((from.clone(), ~"can't copy symbolic links on Windows"));
+}
+
+// Give `to` the same access and modification times as `from`, to the second.
+fn copy_times(from: &Path, to: &Path) -> bool {
+    #[fixed_stack_segment]; #[inline(never)];
+    match (from.get_atime(), from.get_mtime()) {
+        (Some((atime, _)), Some((mtime, _))) => {
+            do to.with_c_str |buf| {
+                unsafe { rustrt::rust_set_file_times(buf, atime, mtime) == 0 }
+            }
+        }
+        _ => false
+    }
+}
+
+/**
+ * An exclusive advisory lock on a file, held until the `FileLock` is
+ * dropped.
+ *
+ * The lock is only advisory: it keeps out other processes that take the
+ * lock through `lock_file` or `try_lock_file` (or `flock`/`LockFileEx`),
+ * but not ones that just open the file.
+ */
+pub struct FileLock {
+    priv fd: c_int
+}
+
+impl Drop for FileLock {
+    fn drop(&mut self) {
+        #[fixed_stack_segment]; #[inline(never)];
+        unsafe {
+            rustrt::rust_unlock_fd(self.fd);
+            libc::close(self.fd);
+        }
+    }
+}
+
+/**
+ * Locks the file at `p`, creating it if it doesn't exist, and waits for
+ * any other process holding the lock to let go of it first.
+ *
+ * Returns `None` if the file couldn't be opened or locked.
+ */
+pub fn lock_file(p: &Path) -> Option<FileLock> {
+    // Waiting for another process can take arbitrarily long, so keep the
+    // rest of the scheduler running meanwhile
+    do ::rt::blocking {
+        lock_file_with(p, true)
+    }
+}
+
+/**
+ * Like `lock_file`, but returns `None` straight away if another process
+ * holds the lock.
+ */
+pub fn try_lock_file(p: &Path) -> Option<FileLock> {
+    lock_file_with(p, false)
+}
+
+fn lock_file_with(p: &Path, wait: bool) -> Option<FileLock> {
+    #[fixed_stack_segment]; #[inline(never)];
+    let fd = do p.with_c_str |buf| {
+        unsafe {
+            libc::open(buf, libc::O_RDWR | libc::O_CREAT,
+                       libc::S_IRUSR | libc::S_IWUSR)
+        }
+    };
+    if fd < 0 {
+        return None;
+    }
+    if unsafe { rustrt::rust_lock_fd(fd, wait as c_int) } != 0 {
+        unsafe { libc::close(fd); }
+        return None;
+    }
+    Some(FileLock { fd: fd })
+}
+
+/**
+ * Creates a symbolic link at `link` which points to `target`. A relative
+ * `target` is resolved relative to the directory containing the link, not the
+ * current working directory.
+ *
+ * On Windows this needs the privilege to create symbolic links, which
+ * ordinary users may not have, and returns false without it.
+ */
+pub fn symlink(target: &Path, link: &Path) -> bool {
+    return do_symlink(target, link);
+
+    #[cfg(windows)]
+    fn do_symlink(target: &Path, link: &Path) -> bool {
+        #[fixed_stack_segment]; #[inline(never)];
+        static SYMBOLIC_LINK_FLAG_DIRECTORY: libc::DWORD = 1;
+        // Windows needs to be told up front whether the link is to a directory,
+        // which is determined relative to the link like the target itself.
+        let flags = if path_is_dir(&link.dir_path().push_rel(target)) {
+            SYMBOLIC_LINK_FLAG_DIRECTORY
+        } else {
+            0
+        };
+        unsafe {
+            use os::win32::as_utf16_p;
+            do as_utf16_p(link.to_str()) |linkp| {
+                do as_utf16_p(target.to_str()) |targetp| {
+                    libc::CreateSymbolicLinkW(linkp, targetp, flags) != 0
+                }
+            }
+        }
+    }
+
+    #[cfg(unix)]
+    fn do_symlink(target: &Path, link: &Path) -> bool {
+        #[fixed_stack_segment]; #[inline(never)];
+        do target.with_c_str |targetp| {
+            do link.with_c_str |linkp| {
+                unsafe { libc::symlink(targetp, linkp) == (0 as c_int) }
+            }
+        }
+    }
+}
+
+/**
+ * Returns the path that the symbolic link `p` points to, exactly as it was
+ * written when the link was created. Returns None if `p` isn't a symbolic
+ * link.
+ *
+ * Only on Unix: a Windows link's target is kept in a reparse point, which
+ * there's no way to read here yet.
+ */
+#[cfg(unix)]
+pub fn readlink(p: &Path) -> Option<Path> {
+    #[fixed_stack_segment]; #[inline(never)];
+    do p.with_c_str |pathp| {
+        let mut size = TMPBUF_SZ;
+        let mut target = None;
+        loop {
+            let mut buf = vec::from_elem(size, 0 as c_char);
+            let len = do buf.as_mut_buf |b, sz| {
+                unsafe { libc::readlink(pathp, b, sz as size_t) }
+            };
+            if len < 0 {
+                break;
+            }
+            // readlink truncates silently, so try again with more room
+            // if the buffer filled up
+            if (len as uint) < size {
+                let bytes = buf.slice_to(len as uint).map(|&c| c as u8);
+                target = Some(Path(str::from_utf8(bytes)));
+                break;
+            }
+            size *= 2;
+        }
+        target
+    }
+}
+
+/**
+ * Indicates whether a path is a symbolic link, without following it. On
+ * Windows that's any reparse point, which includes directory junctions.
+ */
+pub fn path_is_symlink(p: &Path) -> bool {
+    return is_symlink(p);
+
+    #[cfg(windows)]
+    fn is_symlink(p: &Path) -> bool {
+        #[fixed_stack_segment]; #[inline(never)];
+        use libc::consts::os::extra::{INVALID_FILE_ATTRIBUTES, FILE_ATTRIBUTE_REPARSE_POINT};
+        unsafe {
+            use os::win32::as_utf16_p;
+            do as_utf16_p(p.to_str()) |pathp| {
+                let attrs = libc::GetFileAttributesW(pathp);
+                attrs != INVALID_FILE_ATTRIBUTES && attrs & FILE_ATTRIBUTE_REPARSE_POINT != 0
+            }
+        }
+    }
+
+    #[cfg(unix)]
+    fn is_symlink(p: &Path) -> bool {
+        match p.lstat() {
+            Some(st) => (st.st_mode as c_int) & libc::S_IFMT == libc::S_IFLNK,
+            None => false
+        }
+    }
+}
+
 /// Renames an existing file or directory
 pub fn rename_file(old: &Path, new: &Path) -> bool {
     #[fixed_stack_segment]; #[inline(never)];
@@ -1353,7 +1775,10 @@ pub enum MapOption {
     MapExecutable,
     MapAddr(*c_void),
     MapFd(c_int),
-    MapOffset(uint)
+    MapOffset(uint),
+    /// Keep writes to a file mapping private to it instead of writing
+    /// them to the file. File mappings are always private on unix.
+    MapCopyOnWrite
 }
 
 pub enum MapError {
@@ -1422,6 +1847,7 @@ impl MemoryMap {
                     fd = fd_;
                 },
                 MapOffset(offset_) => { offset = offset_ as off_t; }
+                MapCopyOnWrite => ()
             }
         }
         if fd == -1 { flags |= libc::MAP_ANON; }
@@ -1487,6 +1913,7 @@ impl MemoryMap {
         let mut executable = false;
         let mut fd: c_int = -1;
         let mut offset: uint = 0;
+        let mut copy_on_write = false;
         let len = round_up(min_len, page_size()) as SIZE_T;
 
         for &o in options.iter() {
@@ -1497,12 +1924,14 @@ impl MemoryMap {
                 MapAddr(addr_) => { lpAddress = addr_ as LPVOID; },
                 MapFd(fd_) => { fd = fd_; },
                 MapOffset(offset_) => { offset = offset_; }
+                MapCopyOnWrite => { copy_on_write = true; }
             }
         }
 
         let flProtect = match (executable, readable, writable) {
             (false, false, false) if fd == -1 => libc::PAGE_NOACCESS,
             (false, true, false) => libc::PAGE_READONLY,
+            (false, true, true) if copy_on_write && fd != -1 => libc::PAGE_WRITECOPY,
             (false, true, true) => libc::PAGE_READWRITE,
             (true, false, false) if fd == -1 => libc::PAGE_EXECUTE,
             (true, true, false) => libc::PAGE_EXECUTE_READ,
@@ -1531,6 +1960,7 @@ impl MemoryMap {
         } else {
             let dwDesiredAccess = match (executable, readable, writable) {
                 (false, true, false) => libc::FILE_MAP_READ,
+                (false, true, true) if copy_on_write => libc::FILE_MAP_COPY,
                 (false, true, true) => libc::FILE_MAP_WRITE,
                 (true, true, false) => libc::FILE_MAP_READ | libc::FILE_MAP_EXECUTE,
                 (true, true, true) => libc::FILE_MAP_WRITE | libc::FILE_MAP_EXECUTE,
@@ -1708,6 +2138,7 @@ pub mod consts {
 #[cfg(test)]
 mod tests {
     use c_str::ToCStr;
+    use io;
     use libc::{c_int, c_void, size_t};
     use libc;
     use option::Some;
@@ -1897,6 +2328,57 @@ mod tests {
         }
     }
 
+    #[test]
+    fn walk_dir() {
+        let dir = os::getcwd().push("walk-dir-test");
+        assert!(os::mkdir_recursive(&dir.push_many(["a", "b"]), libc::S_IRWXU as c_int));
+        assert!(os::mkdir_recursive(&dir.push("c"), libc::S_IRWXU as c_int));
+        for name in [~"a/b/f.txt", ~"c/g.txt"].iter() {
+            let w = io::file_writer(&dir.push_rel(&Path(*name)), [io::Create]).unwrap();
+            w.write_str(*name);
+        }
+
+        let mut seen = ~[];
+        for entry in os::walk_dir(&dir) {
+            let rel = entry.path.relative_from(&dir).unwrap();
+            seen.push((rel.to_str(), entry.kind, entry.depth));
+        }
+        // The order of directory listings isn't defined
+        let expected = [(~"a", os::Directory, 0),
+                        (~"a/b", os::Directory, 1),
+                        (~"a/b/f.txt", os::RegularFile, 2),
+                        (~"c", os::Directory, 0),
+                        (~"c/g.txt", os::RegularFile, 1)];
+        assert_eq!(seen.len(), expected.len());
+        for e in expected.iter() {
+            assert!(seen.contains(e));
+        }
+        // Directories come before their contents
+        let index_of = |name: &str| seen.iter().position(|&(ref n, _, _)| n.as_slice() == name);
+        assert!(index_of("a") < index_of("a/b"));
+        assert!(index_of("a/b") < index_of("a/b/f.txt"));
+
+        let mut walker = os::walk_dir(&dir);
+        let mut paths = ~[];
+        loop {
+            match walker.next() {
+                Some(entry) => {
+                    if entry.path.filename() == Some("a") {
+                        walker.skip_subtree();
+                    }
+                    paths.push(entry.path.relative_from(&dir).unwrap().to_str());
+                }
+                None => break
+            }
+        }
+        assert_eq!(paths.len(), 3);
+        for name in [~"a", ~"c", ~"c/g.txt"].iter() {
+            assert!(paths.contains(name));
+        }
+
+        assert!(os::remove_dir_recursive(&dir));
+    }
+
     #[test]
     fn list_dir_empty_path() {
         let dirs = os::list_dir(&Path(""));
@@ -1930,6 +2412,103 @@ mod tests {
                      "test/nonexistent-bogus-path"))));
     }
 
+    #[test]
+    fn canonicalize() {
+        let cwd = os::canonicalize(&os::getcwd()).unwrap();
+        assert!(cwd.is_absolute);
+        assert_eq!(os::canonicalize(&Path(".")), Some(cwd.clone()));
+        let parent = os::canonicalize(&Path("..")).unwrap();
+        assert!(parent.is_ancestor_of(&cwd));
+        assert!(os::canonicalize(&Path("test/nonexistent-bogus-path")).is_none());
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn symlinks() {
+        let dir = os::getcwd();
+        let target = dir.push("symlink-target-dir");
+        let link = dir.push("symlink-link");
+        assert!(os::make_dir(&target, libc::S_IRWXU as c_int));
+
+        assert!(os::symlink(&Path("symlink-target-dir"), &link));
+        assert!(os::path_is_symlink(&link));
+        assert!(os::path_is_dir(&link));
+        assert!(!os::path_is_symlink(&target));
+        assert_eq!(os::readlink(&link), Some(Path("symlink-target-dir")));
+        assert_eq!(os::readlink(&target), None);
+
+        assert!(os::remove_file(&link));
+        assert!(os::remove_dir(&target));
+    }
+
+    #[test]
+    fn copy_dir_recursive() {
+        let dir = os::getcwd().push("copy-dir-recursive");
+        let src = dir.push("src");
+        let dst = dir.push("dst");
+        assert!(os::mkdir_recursive(&src.push("sub"), libc::S_IRWXU as c_int));
+        for name in [~"a.txt", ~"sub/b.txt"].iter() {
+            let path = src.push_rel(&Path(*name));
+            let w = io::file_writer(&path, [io::Create]).unwrap();
+            w.write_str(*name);
+        }
+
+        let options = os::CopyOptions::new();
+        assert!(os::copy_dir_recursive(&src, &dst, options).is_ok());
+        for name in [~"a.txt", ~"sub/b.txt"].iter() {
+            let (from, to) = (src.push_rel(&Path(*name)), dst.push_rel(&Path(*name)));
+            assert_eq!(io::read_whole_file_str(&to), Ok(name.clone()));
+            assert_eq!(to.get_mode(), from.get_mode());
+            assert_eq!(to.get_mtime().map(|(s, _)| s), from.get_mtime().map(|(s, _)| s));
+        }
+
+        // Copying again fails for every file, since nothing is overwritten
+        match os::copy_dir_recursive(&src, &dst, options) {
+            Err(errors) => assert_eq!(errors.len(), 2),
+            Ok(()) => fail2!("copied over existing files")
+        }
+
+        // Filtering out a directory leaves out everything in it
+        fn not_sub(p: &Path) -> bool { *p != Path("sub") }
+        let filtered = dir.push("filtered");
+        let options = os::CopyOptions { filter: Some(not_sub), .. os::CopyOptions::new() };
+        assert!(os::copy_dir_recursive(&src, &filtered, options).is_ok());
+        assert!(os::path_exists(&filtered.push("a.txt")));
+        assert!(!os::path_exists(&filtered.push("sub")));
+
+        assert!(os::remove_dir_recursive(&dir));
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn copy_dir_recursive_symlinks() {
+        let dir = os::getcwd().push("copy-dir-recursive-symlinks");
+        let src = dir.push("src");
+        let dst = dir.push("dst");
+        assert!(os::mkdir_recursive(&src, libc::S_IRWXU as c_int));
+        // A link back to the directory it's in would be followed forever
+        assert!(os::symlink(&Path("."), &src.push("self")));
+
+        assert!(os::copy_dir_recursive(&src, &dst, os::CopyOptions::new()).is_ok());
+        assert!(os::path_is_symlink(&dst.push("self")));
+        assert_eq!(os::readlink(&dst.push("self")), Some(Path(".")));
+
+        assert!(os::remove_dir_recursive(&dir));
+    }
+
+    #[test]
+    fn lock_file() {
+        let path = os::getcwd().push("lock-file-test.lock");
+        {
+            let _lock = os::lock_file(&path).expect("couldn't lock file");
+            assert!(os::path_exists(&path));
+            assert!(os::try_lock_file(&path).is_none());
+        }
+        // Released on drop, so it can be taken again
+        assert!(os::try_lock_file(&path).is_some());
+        assert!(os::remove_file(&path));
+    }
+
     #[test]
     fn copy_file_does_not_exist() {
       assert!(!os::copy_file(&Path("test/nonexistent-bogus-path"),
@@ -1971,7 +2550,7 @@ mod tests {
             }
             assert!((rs));
             let rslt = run::process_status("diff", [input.to_str(), out.to_str()]);
-            assert_eq!(rslt, 0);
+            assert_eq!(rslt, Some(0));
             assert_eq!(out.get_mode(), in_mode);
             assert!((remove_file(&input)));
             assert!((remove_file(&out)));
@@ -182,6 +182,10 @@ pub trait GenericPath : Clone + Eq + ToStr {
     /// Returns a new path that names the same file as `self`, without containing
     /// any '.', '..', or empty components. On Windows, uppercases the drive letter
     /// as well.
+    ///
+    /// This is purely lexical: a '..' following a symlink is resolved against
+    /// the link, not its target. See `os::canonicalize` for a version that
+    /// consults the filesystem.
     fn normalize(&self) -> Self;
 
     /// Returns `true` if `self` is an absolute path.
@@ -199,6 +203,11 @@ pub trait GenericPath : Clone + Eq + ToStr {
     }
 
     /// Finds the relative path from one file to another.
+    ///
+    /// Both paths are taken to name files, so the result leads from the
+    /// directory containing `self` to the directory containing `abs2`, and
+    /// leaves off `abs2`'s file name. Use `relative_from` instead.
+    #[deprecated = "treats both paths as files; use relative_from"]
     fn get_relative_to(&self, abs2: (&Self)) -> Self {
         assert!(self.is_absolute());
         assert!(abs2.is_absolute());
@@ -234,6 +243,17 @@ pub trait GenericPath : Clone + Eq + ToStr {
     }
 
 
+    /// Returns the relative path that leads from the directory `base` to
+    /// `self`, so that `base.push_rel(&result)` names the same file as `self`.
+    /// If they're the same, that's the empty path.
+    ///
+    /// Both paths are normalized first. Returns `None` if there is no such
+    /// path: when only one of them is absolute, when they're on different
+    /// Windows drives, or when `base` still starts with '..' components
+    /// that `self` doesn't share.
+    // See `test_relative_from` for examples.
+    fn relative_from(&self, base: &Self) -> Option<Self>;
+
     /// Returns `true` iff `child` is a suffix of `parent`. See the test
     /// case for examples.
     fn is_parent_of(&self, child: &Self) -> bool {
@@ -753,10 +773,20 @@ impl GenericPath for PosixPath {
         false
     }
 
+    fn relative_from(&self, base: &PosixPath) -> Option<PosixPath> {
+        if self.is_absolute != base.is_absolute {
+            return None;
+        }
+        do relative_components(self.components, base.components,
+                               self.is_absolute).map |cs| {
+            PosixPath { is_absolute: false, components: cs }
+        }
+    }
+
     fn normalize(&self) -> PosixPath {
         PosixPath {
             is_absolute: self.is_absolute,
-            components: normalize(self.components),
+            components: normalize_components(self.components, self.is_absolute),
         } // ..self }
     }
 
@@ -966,6 +996,18 @@ impl GenericPath for WindowsPath {
         }
     }
 
+    fn relative_from(&self, base: &WindowsPath) -> Option<WindowsPath> {
+        let (this, base) = (self.normalize(), base.normalize());
+        if this.is_absolute != base.is_absolute || this.host != base.host
+            || this.device != base.device {
+            return None;
+        }
+        do relative_components(this.components, base.components,
+                               this.is_absolute).map |cs| {
+            WindowsPath { host: None, device: None, is_absolute: false, components: cs }
+        }
+    }
+
     fn normalize(&self) -> WindowsPath {
         WindowsPath {
             host: self.host.clone(),
@@ -977,7 +1019,7 @@ impl GenericPath for WindowsPath {
                 Some(ref device) => Some(device.to_ascii().to_upper().to_str_ascii())
             },
             is_absolute: self.is_absolute,
-            components: normalize(self.components)
+            components: normalize_components(self.components, self.is_absolute)
         }
     }
 
@@ -994,7 +1036,8 @@ pub fn normalize(components: &[~str]) -> ~[~str] {
     for c in components.iter() {
         if *c == ~"." && components.len() > 1 { continue; }
         if *c == ~"" { continue; }
-        if *c == ~".." && cs.len() != 0 {
+        // A '..' can only cancel out a real directory, not another '..'
+        if *c == ~".." && cs.len() != 0 && *cs.last() != ~".." {
             cs.pop();
             continue;
         }
@@ -1003,6 +1046,41 @@ pub fn normalize(components: &[~str]) -> ~[~str] {
     cs
 }
 
+// As `normalize`, but also drops the '..' components that would go above
+// the root of an absolute path, since the root is its own parent.
+fn normalize_components(components: &[~str], is_absolute: bool) -> ~[~str] {
+    let cs = normalize(components);
+    if is_absolute {
+        cs.move_iter().skip_while(|c| *c == ~"..").collect()
+    } else {
+        cs
+    }
+}
+
+// The components leading from the directory `base` to `path`, see
+// `GenericPath::relative_from`.
+fn relative_components(path: &[~str], base: &[~str],
+                       is_absolute: bool) -> Option<~[~str]> {
+    let path = normalize_components(path, is_absolute);
+    let base = normalize_components(base, is_absolute);
+
+    let mut common = 0;
+    while common < path.len() && common < base.len() && path[common] == base[common] {
+        common += 1;
+    }
+
+    let mut cs = ~[];
+    for c in base.slice_from(common).iter() {
+        // We can't know which directory a '..' came out of
+        if *c == ~".." { return None; }
+        if *c != ~"." { cs.push(~".."); }
+    }
+    for c in path.slice_from(common).iter() {
+        if *c != ~"." { cs.push((*c).clone()); }
+    }
+    Some(cs)
+}
+
 // Various posix helpers.
 pub mod posix {
 
@@ -1061,6 +1139,8 @@ pub mod windows {
 
 #[cfg(test)]
 mod tests {
+    #[allow(deprecated)];
+
     use option::{None, Some};
     use path::{PosixPath, WindowsPath, windows};
 
@@ -1176,6 +1256,8 @@ mod tests {
         t(&(PosixPath("hi/there.txt")
             .with_dirname(".").normalize()), "there.txt");
 
+        t(&(PosixPath("../../a/./b/../c").normalize()), "../../a/c");
+        t(&(PosixPath("/../a/../../b").normalize()), "/b");
         t(&(PosixPath("a/b/../c/././/../foo.txt/").normalize()),
           "a/foo.txt");
 
@@ -1323,6 +1405,29 @@ mod tests {
         assert_eq!(WindowsPath("c:\\prn.exe").is_restricted(), true);
     }
 
+    #[test]
+    fn test_relative_from() {
+        fn t(path: &str, base: &str, expected: Option<&str>) {
+            let rel = PosixPath(path).relative_from(&PosixPath(base));
+            assert_eq!(rel.map(|p| p.to_str()), expected.map(|s| s.to_owned()));
+        }
+
+        t("/a/b/c", "/a", Some("b/c"));
+        t("/a/b/c", "/a/b/c", Some(""));
+        t("/a/b/c", "/a/d", Some("../b/c"));
+        t("/a", "/a/b/c", Some("../.."));
+        t("/a/./b/../c", "/a/c/", Some(""));
+        t("a/b", "a", Some("b"));
+        t("../a", "..", Some("a"));
+        t("a", "../b", None);
+        t("/a", "a", None);
+        t("a", "/a", None);
+
+        let rel = WindowsPath("C:\\a\\b").relative_from(&WindowsPath("c:\\a"));
+        assert_eq!(rel.map(|p| p.to_str()), Some(~"b"));
+        assert!(WindowsPath("C:\\a").relative_from(&WindowsPath("D:\\a")).is_none());
+    }
+
     #[test]
     fn test_is_ancestor_of() {
         assert!(&PosixPath("/a/b").is_ancestor_of(&PosixPath("/a/b/c/d")));
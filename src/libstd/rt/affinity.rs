@@ -0,0 +1,254 @@
+// Copyright 2013 The Rust Project Developers. See the COPYRIGHT
+// file at the top-level directory of this distribution and at
+// http://rust-lang.org/COPYRIGHT.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Pinning scheduler threads to CPUs.
+//!
+//! Left alone, the OS moves scheduler threads from core to core, which
+//! costs cache locality, most of all across sockets. With an affinity
+//! mask set, through `RUST_SCHED_AFFINITY` or `env::set_sched_affinity`,
+//! each of the schedulers the runtime starts with is pinned to a CPU in
+//! the mask, going round-robin over them. `RUST_SCHED_AFFINITY` is either
+//! `round-robin`, for every CPU, or a mask in hex such as `0xf0`, of any
+//! length up to `MAX_CPUS` bits.
+//!
+//! Pinning is best effort: where the platform doesn't support it, or the
+//! CPU isn't available to the process, the scheduler runs unpinned.
+
+use cast;
+use char;
+use cmp;
+use container::Container;
+use iter::{Iterator, range};
+use libc;
+use option::{Option, Some, None};
+use ptr;
+use ptr::RawPtr;
+use rt::env;
+use rt::util;
+use str::StrSlice;
+use unstable::sync::Exclusive;
+use vec::{OwnedVector, ImmutableVector};
+
+/// The most CPUs a `CpuSet` can hold: as many as Linux's `cpu_set_t`.
+pub static MAX_CPUS: uint = 1024;
+
+/// A set of CPUs, such as the ones to pin schedulers to.
+pub struct CpuSet {
+    // Bit `cpu % 64` of word `cpu / 64` is set for each CPU in the set
+    priv bits: [u64, ..MAX_CPUS / 64]
+}
+
+impl CpuSet {
+    /// An empty set.
+    pub fn new() -> CpuSet {
+        CpuSet { bits: [0, ..MAX_CPUS / 64] }
+    }
+
+    /// The set of every CPU.
+    pub fn all() -> CpuSet {
+        CpuSet { bits: [!0, ..MAX_CPUS / 64] }
+    }
+
+    /// Adds `cpu`, which must be less than `MAX_CPUS`, to the set.
+    pub fn insert(&mut self, cpu: uint) {
+        rtassert!(cpu < MAX_CPUS);
+        self.bits[cpu / 64] |= 1u64 << (cpu % 64);
+    }
+
+    /// Whether `cpu` is in the set.
+    pub fn contains(&self, cpu: uint) -> bool {
+        cpu < MAX_CPUS && self.bits[cpu / 64] & (1u64 << (cpu % 64)) != 0
+    }
+
+    /// Whether the set has no CPUs in it.
+    pub fn is_empty(&self) -> bool {
+        self.bits.iter().all(|&word| word == 0)
+    }
+
+    /// The CPUs in the set out of the first `ncpus`, in order.
+    pub fn cpus(&self, ncpus: uint) -> ~[uint] {
+        range(0, cmp::min(ncpus, MAX_CPUS)).filter(|&cpu| self.contains(cpu)).collect()
+    }
+}
+
+/// A scheduler that has been pinned to a CPU.
+#[deriving(Clone, Eq)]
+pub struct SchedCpu {
+    /// The scheduler's id, as given by `task::SchedulerHandle::id`.
+    sched_id: uint,
+    /// The CPU the scheduler's thread is pinned to.
+    cpu: uint
+}
+
+// Initialized by `rt::init`, before any schedulers exist, and destroyed
+// by `rt::cleanup` after they have all exited.
+static mut PINNED: *mut Exclusive<~[SchedCpu]> = 0 as *mut Exclusive<~[SchedCpu]>;
+
+pub fn init() {
+    unsafe {
+        rtassert!(PINNED.is_null());
+        let pinned = ~Exclusive::new(~[]);
+        PINNED = cast::transmute(pinned);
+    }
+}
+
+pub fn cleanup() {
+    unsafe {
+        rtassert!(!PINNED.is_null());
+        let _pinned: ~Exclusive<~[SchedCpu]> = cast::transmute(PINNED);
+        PINNED = ptr::mut_null();
+    }
+}
+
+fn with_pinned<T>(f: &fn(&mut ~[SchedCpu]) -> T) -> Option<T> {
+    unsafe {
+        if PINNED.is_null() {
+            None
+        } else {
+            Some((*PINNED).with(f))
+        }
+    }
+}
+
+/// Parses an affinity setting: `round-robin`, or a hex mask of CPUs. A
+/// mask naming no CPUs, or CPUs from `MAX_CPUS` on, is rejected.
+pub fn parse_affinity(s: &str) -> Option<CpuSet> {
+    if "round-robin" == s {
+        return Some(CpuSet::all());
+    }
+    let digits = if s.starts_with("0x") { s.slice_from(2) } else { s };
+    let mut set = CpuSet::new();
+    // The last digit holds CPUs 0 to 3
+    for (i, c) in digits.rev_iter().enumerate() {
+        let digit = match char::to_digit(c, 16) {
+            Some(digit) => digit,
+            None => return None
+        };
+        for bit in range(0u, 4) {
+            if digit & (1 << bit) != 0 {
+                let cpu = i * 4 + bit;
+                if cpu >= MAX_CPUS {
+                    return None;
+                }
+                set.insert(cpu);
+            }
+        }
+    }
+    if set.is_empty() { None } else { Some(set) }
+}
+
+/// The CPU that the `n`th of the runtime's schedulers should be pinned to,
+/// if any.
+pub fn cpu_for_sched(n: uint) -> Option<uint> {
+    let cpus = match env::sched_affinity() {
+        Some(set) => set.cpus(util::num_cpus()),
+        None => return None
+    };
+    if cpus.is_empty() {
+        None
+    } else {
+        Some(cpus[n % cpus.len()])
+    }
+}
+
+/// Pins the calling thread, which drives the scheduler `sched_id`, to
+/// `cpu`. Returns false if it couldn't be pinned.
+pub fn pin_sched(sched_id: uint, cpu: uint) -> bool {
+    #[fixed_stack_segment]; #[inline(never)];
+
+    if unsafe { rust_pin_thread_to_cpu(cpu as libc::uintptr_t) } == 0 {
+        rtdebug!("couldn't pin scheduler {} to cpu {}", sched_id, cpu);
+        return false;
+    }
+    do with_pinned |pinned| {
+        pinned.push(SchedCpu { sched_id: sched_id, cpu: cpu });
+    };
+    true
+}
+
+/// Forgets the pinning of the scheduler `sched_id`, which is exiting.
+pub fn unpin_sched(sched_id: uint) {
+    do with_pinned |pinned| {
+        pinned.retain(|p| p.sched_id != sched_id);
+    };
+}
+
+/// The running schedulers that are pinned, with their CPUs.
+pub fn sched_cpus() -> ~[SchedCpu] {
+    with_pinned(|pinned| pinned.clone()).unwrap_or(~[])
+}
+
+/// The CPU the running scheduler `sched_id` is pinned to, if it is.
+pub fn sched_cpu(sched_id: uint) -> Option<uint> {
+    do with_pinned |pinned| {
+        pinned.iter().find(|p| p.sched_id == sched_id).map(|p| p.cpu)
+    }.unwrap_or(None)
+}
+
+/// The CPU the calling thread is running on, where the platform can say.
+pub fn current_cpu() -> Option<uint> {
+    #[fixed_stack_segment]; #[inline(never)];
+
+    match unsafe { rust_get_current_cpu() } {
+        cpu if cpu < 0 => None,
+        cpu => Some(cpu as uint)
+    }
+}
+
+extern {
+    fn rust_pin_thread_to_cpu(cpu: libc::uintptr_t) -> libc::uintptr_t;
+    fn rust_get_current_cpu() -> libc::intptr_t;
+}
+
+#[cfg(test)]
+mod test {
+    use super::{CpuSet, MAX_CPUS, parse_affinity};
+    use str::StrSlice;
+
+    fn cpus(s: &str, ncpus: uint) -> Option<~[uint]> {
+        parse_affinity(s).map(|set| set.cpus(ncpus))
+    }
+
+    #[test]
+    fn affinity_settings() {
+        assert_eq!(cpus("round-robin", 4), Some(~[0, 1, 2, 3]));
+        assert_eq!(cpus("0xf0", 8), Some(~[4, 5, 6, 7]));
+        assert_eq!(cpus("5", 8), Some(~[0, 2]));
+        assert_eq!(cpus("0", 8), None);
+        assert_eq!(cpus("0x", 8), None);
+        assert_eq!(cpus("all", 8), None);
+    }
+
+    #[test]
+    fn masks_past_64_cpus() {
+        // CPUs 64 and 200
+        let mask = format!("1{}1{}", "0".repeat(33), "0".repeat(16));
+        assert_eq!(cpus(mask, 256), Some(~[64, 200]));
+        assert_eq!(cpus(mask, 100), Some(~[64]));
+
+        // Up to MAX_CPUS bits, but no further
+        let top = format!("8{}", "0".repeat(MAX_CPUS / 4 - 1));
+        assert_eq!(cpus(top, MAX_CPUS), Some(~[MAX_CPUS - 1]));
+        let past_top = format!("1{}", "0".repeat(MAX_CPUS / 4));
+        assert_eq!(cpus(past_top, MAX_CPUS), None);
+    }
+
+    #[test]
+    fn cpu_sets() {
+        let mut set = CpuSet::new();
+        assert!(set.is_empty());
+        set.insert(3);
+        set.insert(130);
+        assert!(set.contains(130) && !set.contains(2));
+        assert_eq!(set.cpus(200), ~[3, 130]);
+        assert_eq!(CpuSet::all().cpus(100).len(), 100);
+        assert_eq!(CpuSet::all().cpus(MAX_CPUS + 1).len(), MAX_CPUS);
+    }
+}
@@ -0,0 +1,211 @@
+// Copyright 2013 The Rust Project Developers. See the COPYRIGHT
+// file at the top-level directory of this distribution and at
+// http://rust-lang.org/COPYRIGHT.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Running blocking foreign calls without stalling a scheduler.
+//!
+//! A green task that calls a blocking C function ties up the OS thread
+//! driving its scheduler, and with it every other task queued there.
+//! `blocking` moves the calling task to one of a pool of dedicated
+//! schedulers, each on its own thread, for the duration of the call and
+//! then sends it back to the scheduler it came from.
+
+use cast;
+use cell::Cell;
+use option::{Option, Some, None};
+use ptr;
+use ptr::RawPtr;
+use rt::in_green_task_context;
+use rt::local::Local;
+use rt::sched::{Scheduler, SchedHandle, Shutdown};
+use rt::sleeper_list::SleeperList;
+use rt::task::{Task, Sched, AnySched};
+use rt::thread::Thread;
+use rt::uv::uvio::UvEventLoop;
+use rt::work_queue::WorkQueue;
+use task::unkillable;
+use unstable::finally::Finally;
+use unstable::sync::Exclusive;
+use vec::OwnedVector;
+
+struct BlockingPool {
+    /// Handles to the schedulers that aren't running a blocking call.
+    idle: ~[SchedHandle],
+    /// Every thread started by the pool, joined during cleanup.
+    threads: ~[Thread]
+}
+
+// Initialized by `rt::init`, before any schedulers exist, and destroyed
+// by `rt::cleanup` after they have all exited.
+static mut POOL: *mut Exclusive<BlockingPool> = 0 as *mut Exclusive<BlockingPool>;
+
+pub fn init() {
+    unsafe {
+        rtassert!(POOL.is_null());
+        let pool = ~Exclusive::new(BlockingPool { idle: ~[], threads: ~[] });
+        POOL = cast::transmute(pool);
+    }
+}
+
+pub fn cleanup() {
+    unsafe {
+        rtassert!(!POOL.is_null());
+        let pool: ~Exclusive<BlockingPool> = cast::transmute(POOL);
+        POOL = ptr::mut_null();
+
+        let pool = *pool;
+        let BlockingPool { idle: idle, threads: threads } = pool.unwrap();
+        for handle in idle.move_iter() {
+            let mut handle = handle;
+            handle.send(Shutdown);
+        }
+        for thread in threads.move_iter() {
+            thread.join();
+        }
+    }
+}
+
+fn with_pool<T>(f: &fn(&mut BlockingPool) -> T) -> Option<T> {
+    unsafe {
+        if POOL.is_null() {
+            None
+        } else {
+            Some((*POOL).with(f))
+        }
+    }
+}
+
+/// Run `f`, which is expected to block the OS thread, on a dedicated
+/// scheduler thread so that the other tasks on the current scheduler can
+/// keep running. The task returns to its home scheduler afterwards, even
+/// if `f` fails.
+///
+/// Outside of a green task, or if the runtime wasn't initialized with
+/// `rt::init`, `f` is simply called in place.
+pub fn blocking<T>(f: &fn() -> T) -> T {
+    if !in_green_task_context() {
+        return f();
+    }
+
+    let mut dest = match with_pool(|pool| pool.idle.pop_opt()) {
+        Some(Some(handle)) => handle,
+        Some(None) => new_blocking_sched(),
+        None => return f()
+    };
+
+    // Hold on to where we came from. The pool scheduler will pin the task
+    // to itself when it arrives, overwriting the original home.
+    let (home, origin) = do unkillable {
+        let home = do Local::borrow |task: &mut Task| {
+            task.take_unwrap_home()
+        };
+        let mut sched: ~Scheduler = Local::take();
+        let origin = sched.make_handle();
+        sched.send_running_task_to(&mut dest);
+        (home, origin)
+    };
+
+    let home = Cell::new(home);
+    let origin = Cell::new(origin);
+    do f.finally {
+        do unkillable {
+            let pool_home = do Local::borrow |task: &mut Task| {
+                let pool_home = task.take_unwrap_home();
+                task.give_home(home.take());
+                pool_home
+            };
+            let mut origin = origin.take();
+            let sched: ~Scheduler = Local::take();
+            sched.send_running_task_home(&mut origin);
+
+            // Back where we started. The pool scheduler is idle again.
+            match pool_home {
+                Sched(handle) => {
+                    let handle = Cell::new(handle);
+                    do with_pool |pool| {
+                        pool.idle.push(handle.take());
+                    };
+                }
+                AnySched => rtabort!("blocking task wasn't pinned to the pool")
+            }
+        }
+    }
+}
+
+// Start a new scheduler thread for the pool, returning a handle to it.
+// The scheduler only runs tasks pinned to it and never steals.
+fn new_blocking_sched() -> SchedHandle {
+    let work_queue = WorkQueue::new();
+    let mut sched = ~Scheduler::new_special(~UvEventLoop::new(),
+                                            work_queue.clone(),
+                                            ~[work_queue],
+                                            SleeperList::new(),
+                                            false,
+                                            None);
+    let handle = sched.make_handle();
+
+    let sched = Cell::new(sched);
+    let thread = do Thread::start {
+        let mut sched = sched.take();
+        let bootstrap_task = ~do Task::new_root(&mut sched.stack_pool, None) || {
+            rtdebug!("bootstrapping a blocking scheduler");
+        };
+        sched.bootstrap(bootstrap_task);
+    };
+
+    let thread = Cell::new(thread);
+    do with_pool |pool| {
+        pool.threads.push(thread.take());
+    };
+
+    return handle;
+}
+
+#[cfg(test)]
+mod test {
+    use rt::test::*;
+    use super::blocking;
+    use rt::local::Local;
+    use rt::sched::Scheduler;
+
+    fn current_sched_id() -> uint {
+        do Local::borrow |sched: &mut Scheduler| {
+            sched.sched_id()
+        }
+    }
+
+    #[test]
+    fn returns_value() {
+        do run_in_newsched_task {
+            assert_eq!(blocking(|| 10), 10);
+        }
+    }
+
+    #[test]
+    fn runs_elsewhere_and_returns_home() {
+        do run_in_newsched_task {
+            let home = current_sched_id();
+            let there = do blocking {
+                current_sched_id()
+            };
+            assert!(there != home);
+            assert_eq!(current_sched_id(), home);
+        }
+    }
+
+    #[test]
+    fn failure_unwinds_through() {
+        do run_in_newsched_task {
+            let res = do spawntask_try {
+                do blocking { fail2!() }
+            };
+            assert!(res.is_err());
+        }
+    }
+}
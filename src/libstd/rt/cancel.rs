@@ -0,0 +1,415 @@
+// Copyright 2013 The Rust Project Developers. See the COPYRIGHT
+// file at the top-level directory of this distribution and at
+// http://rust-lang.org/COPYRIGHT.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Cooperative cancellation of tasks.
+//!
+//! Killing a task, as linked failure does, unwinds it from wherever it is
+//! blocked. Cancelling a `CancelToken` instead asks the tasks holding it
+//! to wrap up: the runtime's blocking operations check the running task's
+//! token and return early once it has been cancelled, so that the task
+//! can notice and return of its own accord.
+//!
+//! * `recv_or_cancel` on a `PortOne`, `Port` or `SharedPort` returns
+//!   `None`. A stream's port is left as it was, and can still be received
+//!   from later. `recv` and `try_recv` aren't affected, and wait for a
+//!   message or for the channel to close as before.
+//! * `timer::sleep` returns before the time is up.
+//!
+//! A message that has already arrived is still received by a cancelled
+//! task. `select` is never cut short.
+//!
+//! A task spawned with a token (see `TaskBuilder::cancel_token`) hands it
+//! down to the tasks it spawns, so cancelling it reaches the whole subtree.
+//! `CancelToken::child` makes a token for part of that subtree: it is
+//! cancelled along with its parent, but can also be cancelled by itself.
+
+use cell::Cell;
+use clone::Clone;
+use iter::Iterator;
+use option::{Option, Some, None};
+use rt::comm::{oneshot, ChanOne};
+use rt::local::Local;
+use rt::select::SelectInner;
+use rt::task::Task;
+use select::select_any;
+use unstable::atomics::{AtomicBool, AtomicUint, INIT_ATOMIC_UINT, SeqCst};
+use unstable::finally::Finally;
+use unstable::sync::{Exclusive, UnsafeArc};
+use util;
+use vec::{ImmutableVector, OwnedVector};
+
+/// A flag that asks the tasks holding it, and those holding its children,
+/// to return early.
+pub struct CancelToken {
+    priv inner: UnsafeArc<TokenInner>
+}
+
+struct TokenInner {
+    parent: Option<CancelToken>,
+    cancelled: AtomicBool,
+    /// The tasks blocked on this token or one of its children.
+    waiters: Exclusive<~[Waiter]>
+}
+
+// A task blocked in a cancellable operation. It's registered with its
+// token and all of that token's ancestors, and whichever of them is
+// cancelled first takes the channel to wake it.
+#[deriving(Clone)]
+struct Waiter {
+    id: uint,
+    chan: Exclusive<Option<ChanOne<()>>>
+}
+
+static mut NEXT_WAITER_ID: AtomicUint = INIT_ATOMIC_UINT;
+
+impl CancelToken {
+    /// A token that hasn't been cancelled.
+    pub fn new() -> CancelToken {
+        CancelToken::with_parent(None)
+    }
+
+    /// A token that is cancelled when this one is, but can also be
+    /// cancelled without affecting this one.
+    pub fn child(&self) -> CancelToken {
+        CancelToken::with_parent(Some(self.clone()))
+    }
+
+    fn with_parent(parent: Option<CancelToken>) -> CancelToken {
+        CancelToken {
+            inner: UnsafeArc::new(TokenInner {
+                parent: parent,
+                cancelled: AtomicBool::new(false),
+                waiters: Exclusive::new(~[])
+            })
+        }
+    }
+
+    /// Cancels this token and its children, waking the tasks blocked on
+    /// any of them.
+    pub fn cancel(&self) {
+        unsafe {
+            let inner = self.inner.get();
+            if (*inner).cancelled.swap(true, SeqCst) {
+                return;
+            }
+            // Sending can't be done while holding the lock
+            let waiters = (*inner).waiters.with(|waiters| util::replace(waiters, ~[]));
+            for waiter in waiters.iter() {
+                waiter.wake();
+            }
+        }
+    }
+
+    /// Whether this token, or one of its ancestors, has been cancelled.
+    pub fn is_cancelled(&self) -> bool {
+        let mut token = self;
+        loop {
+            let inner: &TokenInner = unsafe { &*token.inner.get_immut() };
+            if inner.cancelled.load(SeqCst) {
+                return true;
+            }
+            match inner.parent {
+                Some(ref parent) => token = parent,
+                None => return false
+            }
+        }
+    }
+
+    // Registers `waiter` with this token and its ancestors. Returns false,
+    // leaving it registered nowhere, if one of them was already cancelled.
+    fn register(&self, waiter: &Waiter) -> bool {
+        let mut token = self;
+        loop {
+            let inner: &TokenInner = unsafe { &*token.inner.get_immut() };
+            unsafe {
+                inner.waiters.with(|waiters| waiters.push(waiter.clone()));
+            }
+            // A `cancel` that got in first has set the flag by now, and
+            // one that comes later will find the waiter.
+            if inner.cancelled.load(SeqCst) {
+                self.deregister(waiter.id);
+                return false;
+            }
+            match inner.parent {
+                Some(ref parent) => token = parent,
+                None => return true
+            }
+        }
+    }
+
+    fn deregister(&self, id: uint) {
+        let mut token = self;
+        loop {
+            let inner: &TokenInner = unsafe { &*token.inner.get_immut() };
+            unsafe {
+                inner.waiters.with(|waiters| waiters.retain(|w| w.id != id));
+            }
+            match inner.parent {
+                Some(ref parent) => token = parent,
+                None => return
+            }
+        }
+    }
+}
+
+impl Clone for CancelToken {
+    fn clone(&self) -> CancelToken {
+        CancelToken { inner: self.inner.clone() }
+    }
+}
+
+impl Waiter {
+    fn new(chan: ChanOne<()>) -> Waiter {
+        Waiter {
+            id: unsafe { NEXT_WAITER_ID.fetch_add(1, SeqCst) },
+            chan: Exclusive::new(Some(chan))
+        }
+    }
+
+    fn wake(&self) {
+        let chan = unsafe { self.chan.with(|chan| chan.take()) };
+        match chan {
+            Some(chan) => { chan.try_send_deferred(()); }
+            None => ()
+        }
+    }
+}
+
+fn with_current<U>(f: &fn(Option<&CancelToken>) -> U) -> U {
+    unsafe {
+        let task: Option<*mut Task> = Local::try_unsafe_borrow();
+        match task {
+            Some(task) => f((*task).cancel_token.as_ref()),
+            None => f(None)
+        }
+    }
+}
+
+/// The running task's token, if it has one.
+pub fn current() -> Option<CancelToken> {
+    with_current(|token| token.map(|token| token.clone()))
+}
+
+/// Whether the running task's token has been cancelled. Work that doesn't
+/// block can check this to return early too.
+pub fn cancelled() -> bool {
+    with_current(|token| token.map_default(false, |token| token.is_cancelled()))
+}
+
+/// Whether the running task's blocking operations may be cut short.
+pub fn is_cancellable() -> bool {
+    with_current(|token| token.is_some())
+}
+
+/// Runs `f` with `token` as the running task's token in place of its own.
+pub fn with_token<U>(token: Option<CancelToken>, f: &fn() -> U) -> U {
+    let old = Cell::new(swap_token(token));
+    do f.finally {
+        swap_token(old.take());
+    }
+}
+
+/// Runs `f` without a token, so that its blocking operations run to the
+/// end even if the task is cancelled.
+pub fn uncancellable<U>(f: &fn() -> U) -> U {
+    with_token(None, f)
+}
+
+fn swap_token(token: Option<CancelToken>) -> Option<CancelToken> {
+    let token = Cell::new(token);
+    do Local::borrow |task: &mut Task| {
+        util::replace(&mut task.cancel_token, token.take())
+    }
+}
+
+/// Blocks until `port` is ready, as `select` would, unless the running
+/// task's token is cancelled first. Returns false if it was, having left
+/// the port as it was. If the port is ready already, that wins.
+///
+/// # Failure
+///
+/// Fails if the running task has no token.
+pub fn select_or_cancel(port: &mut SelectInner) -> bool {
+    let token = match current() {
+        Some(token) => token,
+        None => fail2!("select_or_cancel: the running task has no token")
+    };
+    let (cancel_port, cancel_chan) = oneshot();
+    let waiter = Waiter::new(cancel_chan);
+    if !token.register(&waiter) {
+        // Already cancelled. Let the select find out, after the port has
+        // had the chance to win.
+        waiter.wake();
+    }
+    let mut cancel_port = cancel_port;
+    let ready = {
+        let mut ports = [port, &mut cancel_port as &mut SelectInner];
+        select_any(ports)
+    };
+    token.deregister(waiter.id);
+    ready == 0
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use cell::Cell;
+    use comm::{stream, oneshot, GenericChan, GenericPort};
+    use option::{Some, None};
+    use rt::io::timer;
+    use rt::test::*;
+    use rt::util::precise_time_ns;
+    use task;
+
+    #[test]
+    fn cancel_wakes_blocked_recv() {
+        do run_in_newsched_task {
+            let token = CancelToken::new();
+            let (result_port, result_chan) = oneshot();
+            // Kept open, so that only the cancellation can end the receive
+            let (data_port, _data_chan) = stream::<int>();
+            let data_port = Cell::new(data_port);
+            let result_chan = Cell::new(result_chan);
+            let mut builder = task::task();
+            builder.cancel_token(token.clone());
+            do builder.spawn {
+                result_chan.take().send(data_port.take().recv_or_cancel());
+            }
+            timer::sleep(10);
+            token.cancel();
+            assert!(result_port.recv().is_none());
+        }
+    }
+
+    #[test]
+    fn cancelled_recv_leaves_stream_usable() {
+        do run_in_newsched_task {
+            let token = CancelToken::new();
+            token.cancel();
+            let (port, chan) = stream();
+            do with_token(Some(token.clone())) {
+                assert!(cancelled());
+                assert_eq!(port.recv_or_cancel(), None);
+            }
+            chan.send(8);
+            assert_eq!(port.recv(), 8);
+        }
+    }
+
+    #[test]
+    fn cancel_leaves_recv_waiting() {
+        do run_in_newsched_task {
+            let token = CancelToken::new();
+            token.cancel();
+            do with_token(Some(token.clone())) {
+                let (port, chan) = oneshot();
+                let chan = Cell::new(chan);
+                do task::spawn_unlinked {
+                    do uncancellable { timer::sleep(10) }
+                    chan.take().send(5);
+                }
+                assert_eq!(port.recv(), 5);
+            }
+        }
+    }
+
+    #[test]
+    fn cancel_leaves_try_recv_waiting() {
+        do run_in_newsched_task {
+            let token = CancelToken::new();
+            token.cancel();
+            do with_token(Some(token.clone())) {
+                let (port, chan) = stream();
+                let chan = Cell::new(chan);
+                do task::spawn_unlinked {
+                    do uncancellable { timer::sleep(10) }
+                    chan.take().send(5);
+                }
+                // Only a closed channel ends a try_recv with `None`
+                assert_eq!(port.try_recv(), Some(5));
+                assert_eq!(port.try_recv(), None);
+            }
+        }
+    }
+
+    #[test]
+    fn children_inherit_tokens() {
+        do run_in_newsched_task {
+            let token = CancelToken::new();
+            let (port, chan) = oneshot();
+            let chan = Cell::new(chan);
+            let mut builder = task::task();
+            builder.cancel_token(token.clone());
+            do builder.spawn {
+                let chan = Cell::new(chan.take());
+                do task::spawn {
+                    while !cancelled() {
+                        timer::sleep(1);
+                    }
+                    chan.take().send(());
+                }
+            }
+            token.cancel();
+            port.recv();
+        }
+    }
+
+    #[test]
+    fn child_tokens() {
+        do run_in_newsched_task {
+            let parent = CancelToken::new();
+            let child = parent.child();
+            let other_child = parent.child();
+            child.cancel();
+            assert!(child.is_cancelled());
+            assert!(!parent.is_cancelled());
+            assert!(!other_child.is_cancelled());
+            parent.cancel();
+            assert!(other_child.is_cancelled());
+        }
+    }
+
+    #[test]
+    fn cancel_cuts_sleep_short() {
+        do run_in_newsched_task {
+            let token = CancelToken::new();
+            let (port, chan) = oneshot();
+            let chan = Cell::new(chan);
+            let mut builder = task::task();
+            builder.cancel_token(token.clone());
+            do builder.spawn {
+                let start = precise_time_ns();
+                timer::sleep(60 * 1000);
+                chan.take().send(precise_time_ns() - start);
+            }
+            timer::sleep(10);
+            token.cancel();
+            assert!(port.recv() < 30 * 1000 * 1000 * 1000);
+        }
+    }
+
+    #[test]
+    fn uncancellable_ignores_token() {
+        do run_in_newsched_task {
+            let token = CancelToken::new();
+            token.cancel();
+            do with_token(Some(token.clone())) {
+                let (port, chan) = oneshot();
+                let port = Cell::new(port);
+                let chan = Cell::new(chan);
+                do task::spawn_unlinked {
+                    do uncancellable { timer::sleep(10) }
+                    chan.take().send(3);
+                }
+                assert_eq!(uncancellable(|| port.take().recv()), 3);
+            }
+        }
+    }
+}
@@ -16,6 +16,7 @@ use ops::Drop;
 use rt::kill::BlockedTask;
 use kinds::Send;
 use rt;
+use rt::cancel;
 use rt::sched::Scheduler;
 use rt::local::Local;
 use rt::select::{SelectInner, SelectPortInner};
@@ -192,8 +193,9 @@ impl<T> PortOne<T> {
     }
 
     /// Wait for a message on the one-shot port. Fails if the send end is closed.
+    /// Cancelling the task's token doesn't cut the wait short.
     pub fn recv(self) -> T {
-        match self.try_recv() {
+        match self.wait(false) {
             Some(val) => val,
             None => {
                 fail2!("receiving on closed channel");
@@ -203,16 +205,40 @@ impl<T> PortOne<T> {
 
     /// As `recv`, but returns `None` if the send end is closed rather than failing.
     pub fn try_recv(self) -> Option<T> {
+        self.wait(false)
+    }
+
+    /// As `recv`, but returns `None` if the task's token is cancelled before
+    /// a message arrives (see `rt::cancel`). Fails if the send end is closed,
+    /// unless the task has been cancelled by then.
+    pub fn recv_or_cancel(self) -> Option<T> {
+        match self.wait(cancel::is_cancellable()) {
+            None if !cancel::cancelled() => fail2!("receiving on closed channel"),
+            res => res
+        }
+    }
+
+    // Waits for a message, or for the task's token to be cancelled too if
+    // `cancellable`. Only `recv_or_cancel` waits on the token, so that
+    // other receives, such as those in the runtime, aren't cut short.
+    fn wait(self, cancellable: bool) -> Option<T> {
         let mut this = self;
 
         // Optimistic check. If data was sent already, we don't even need to block.
         // No release barrier needed here; we're not handing off our task pointer yet.
         if !this.optimistic_check() {
-            // No data available yet.
-            // Switch to the scheduler to put the ~Task into the Packet state.
-            let sched: ~Scheduler = Local::take();
-            do sched.deschedule_running_task_and_then |sched, task| {
-                this.block_on(sched, task);
+            if cancellable {
+                // Wait on the task's token too, as select would.
+                if !cancel::select_or_cancel(&mut this as &mut SelectInner) {
+                    return None;
+                }
+            } else {
+                // No data available yet.
+                // Switch to the scheduler to put the ~Task into the Packet state.
+                let sched: ~Scheduler = Local::take();
+                do sched.deschedule_running_task_and_then |sched, task| {
+                    this.block_on(sched, task);
+                }
             }
         }
 
@@ -490,19 +516,19 @@ impl<T: Send> SendDeferred<T> for Chan<T> {
     }
 }
 
-impl<T> GenericPort<T> for Port<T> {
-    fn recv(&self) -> T {
-        match self.try_recv() {
-            Some(val) => val,
-            None => {
-                fail2!("receiving on closed channel");
+impl<T> Port<T> {
+    // As PortOne::wait
+    fn wait(&self, cancellable: bool) -> Option<T> {
+        if cancellable && !self.next.is_empty() {
+            // Don't let the next port go if the task is cancelled.
+            let mut this = self;
+            if !cancel::select_or_cancel(&mut this as &mut SelectInner) {
+                return None;
             }
+            return this.recv_ready();
         }
-    }
-
-    fn try_recv(&self) -> Option<T> {
         do self.next.take_opt().map_default(None) |pone| {
-            match pone.try_recv() {
+            match pone.wait(false) {
                 Some(StreamPayload { val, next }) => {
                     self.next.put_back(next);
                     Some(val)
@@ -513,6 +539,31 @@ impl<T> GenericPort<T> for Port<T> {
     }
 }
 
+impl<T> Port<T> {
+    /// As PortOne::recv_or_cancel
+    pub fn recv_or_cancel(&self) -> Option<T> {
+        match self.wait(cancel::is_cancellable()) {
+            None if !cancel::cancelled() => fail2!("receiving on closed channel"),
+            res => res
+        }
+    }
+}
+
+impl<T> GenericPort<T> for Port<T> {
+    fn recv(&self) -> T {
+        match self.wait(false) {
+            Some(val) => val,
+            None => {
+                fail2!("receiving on closed channel");
+            }
+        }
+    }
+
+    fn try_recv(&self) -> Option<T> {
+        self.wait(false)
+    }
+}
+
 impl<T> Peekable<T> for Port<T> {
     fn peek(&self) -> bool {
         self.next.with_mut_ref(|p| p.peek())
@@ -643,25 +694,29 @@ impl<T> SharedPort<T> {
         let next_link = AtomicOption::new(~next_link_port);
         SharedPort { next_link: UnsafeArc::new(next_link) }
     }
-}
 
-impl<T: Send> GenericPort<T> for SharedPort<T> {
-    fn recv(&self) -> T {
-        match self.try_recv() {
-            Some(val) => val,
-            None => {
-                fail2!("receiving on a closed channel");
-            }
+    /// As PortOne::recv_or_cancel
+    pub fn recv_or_cancel(&self) -> Option<T> {
+        match self.wait(cancel::is_cancellable()) {
+            None if !cancel::cancelled() => fail2!("receiving on a closed channel"),
+            res => res
         }
     }
 
-    fn try_recv(&self) -> Option<T> {
+    // As PortOne::wait
+    fn wait(&self, cancellable: bool) -> Option<T> {
         unsafe {
             let (next_link_port, next_link_chan) = oneshot();
             let link_port = (*self.next_link.get()).swap(~next_link_port, SeqCst);
-            let link_port = link_port.unwrap();
-            let data_port = link_port.recv();
-            let (next_data_port, res) = match data_port.try_recv() {
+            // Other receivers are waiting for the link, so only the wait
+            // for data may be cut short.
+            let mut data_port = link_port.unwrap().recv();
+            if cancellable &&
+                    !cancel::select_or_cancel(&mut data_port as &mut SelectInner) {
+                next_link_chan.send(data_port);
+                return None;
+            }
+            let (next_data_port, res) = match data_port.wait(false) {
                 Some(StreamPayload { val, next }) => {
                     (next, Some(val))
                 }
@@ -676,6 +731,21 @@ impl<T: Send> GenericPort<T> for SharedPort<T> {
     }
 }
 
+impl<T: Send> GenericPort<T> for SharedPort<T> {
+    fn recv(&self) -> T {
+        match self.wait(false) {
+            Some(val) => val,
+            None => {
+                fail2!("receiving on a closed channel");
+            }
+        }
+    }
+
+    fn try_recv(&self) -> Option<T> {
+        self.wait(false)
+    }
+}
+
 impl<T> Clone for SharedPort<T> {
     fn clone(&self) -> SharedPort<T> {
         SharedPort {
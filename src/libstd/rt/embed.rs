@@ -0,0 +1,220 @@
+// Copyright 2013 The Rust Project Developers. See the COPYRIGHT
+// file at the top-level directory of this distribution and at
+// http://rust-lang.org/COPYRIGHT.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Running the runtime inside a program that doesn't belong to Rust.
+//!
+//! A Rust library embedded in a C host doesn't own `main`, so it can't
+//! use `rt::start`. Instead the host starts the runtime in place, which
+//! creates the schedulers on threads of their own, spawns tasks onto it
+//! from the host thread and finally shuts it down, which waits for all
+//! spawned tasks before stopping and joining the scheduler threads.
+//!
+//! # Example
+//!
+//! ```
+//! let mut rt = rt::start_in_place(Default::default());
+//! do rt.spawn {
+//!     println("hello from the runtime");
+//! }
+//! rt.shutdown();
+//! ```
+
+use cell::Cell;
+use default::Default;
+use option::{Some, None};
+use ops::Drop;
+use ptr;
+use rt::sched::{Scheduler, SchedHandle, Shutdown, TaskFromFriend};
+use rt::sleeper_list::SleeperList;
+use rt::stack::StackPool;
+use rt::task::Task;
+use rt::thread::Thread;
+use rt::util::default_sched_threads;
+use rt::uv::uvio::UvEventLoop;
+use rt::work_queue::WorkQueue;
+use unstable::sync::{UnsafeArc, LittleLock};
+use util::replace;
+use vec;
+use vec::{OwnedVector, ImmutableVector};
+use container::Container;
+use iter::Iterator;
+
+/// Configuration for an embedded runtime.
+pub struct RuntimeConfig {
+    /// The number of scheduler threads to start.
+    sched_threads: uint
+}
+
+impl Default for RuntimeConfig {
+    fn default() -> RuntimeConfig {
+        RuntimeConfig {
+            sched_threads: default_sched_threads()
+        }
+    }
+}
+
+/// A running runtime, started with `start_in_place`.
+///
+/// The handle must be shut down with `shutdown` before it goes out of
+/// scope.
+pub struct RuntimeHandle {
+    priv handles: ~[SchedHandle],
+    priv threads: ~[Thread],
+    priv tracker: UnsafeArc<TaskTracker>,
+    priv next_sched: uint,
+    priv shut_down: bool
+}
+
+// Counts the tasks spawned through a RuntimeHandle that are still alive,
+// so that the host thread can block until they have all exited.
+struct TaskTracker {
+    lock: LittleLock,
+    live: uint,
+    failed: uint
+}
+
+/// Initialize the runtime and start its schedulers, without taking over
+/// the calling thread.
+///
+/// Only one runtime may be running in a process at a time.
+pub fn start_in_place(config: RuntimeConfig) -> RuntimeHandle {
+    let nscheds = config.sched_threads;
+    rtassert!(nscheds > 0);
+
+    unsafe { super::init(0, ptr::null()); }
+
+    let sleepers = SleeperList::new();
+    let work_queues: ~[WorkQueue<~Task>] = vec::from_fn(nscheds, |_| WorkQueue::new());
+
+    let mut handles = ~[];
+    let mut threads = ~[];
+
+    for work_queue in work_queues.iter() {
+        let loop_ = ~UvEventLoop::new();
+        let mut sched = ~Scheduler::new(loop_,
+                                        work_queue.clone(),
+                                        work_queues.clone(),
+                                        sleepers.clone());
+        handles.push(sched.make_handle());
+
+        let sched_cell = Cell::new(sched);
+        let thread = do Thread::start {
+            let mut sched = sched_cell.take();
+            let bootstrap_task = ~do Task::new_root(&mut sched.stack_pool, None) || {
+                rtdebug!("bootstrapping an embedded scheduler");
+            };
+            sched.bootstrap(bootstrap_task);
+        };
+        threads.push(thread);
+    }
+
+    RuntimeHandle {
+        handles: handles,
+        threads: threads,
+        tracker: UnsafeArc::new(TaskTracker {
+            lock: LittleLock::new(),
+            live: 0,
+            failed: 0
+        }),
+        next_sched: 0,
+        shut_down: false
+    }
+}
+
+impl RuntimeHandle {
+    /// Spawn a new root task on one of the runtime's schedulers.
+    pub fn spawn(&mut self, f: ~fn()) {
+        rtassert!(!self.shut_down);
+
+        unsafe {
+            let tracker = self.tracker.get();
+            do (*tracker).lock.lock {
+                (*tracker).live += 1;
+            }
+        }
+
+        let tracker = Cell::new(self.tracker.clone());
+        let on_exit: ~fn(bool) = |success| {
+            let tracker = tracker.take();
+            unsafe {
+                let tracker = tracker.get();
+                do (*tracker).lock.lock {
+                    (*tracker).live -= 1;
+                    if !success {
+                        (*tracker).failed += 1;
+                    }
+                    if (*tracker).live == 0 {
+                        (*tracker).lock.signal();
+                    }
+                }
+            }
+        };
+
+        // The stack pool only hands out new segments, so a temporary one
+        // will do when we're not on a scheduler.
+        let mut stack_pool = StackPool::new();
+        let mut task = ~Task::new_root(&mut stack_pool, None, f);
+        task.death.on_exit = Some(on_exit);
+
+        // Hand out new tasks round-robin. Work stealing evens things out.
+        let index = self.next_sched % self.handles.len();
+        self.next_sched += 1;
+        self.handles[index].send(TaskFromFriend(task));
+    }
+
+    /// Block the calling thread until every task spawned so far has exited.
+    /// Returns `true` if none of them failed.
+    ///
+    /// This blocks the OS thread and must not be called from a task.
+    pub fn wait(&mut self) -> bool {
+        unsafe {
+            let tracker = self.tracker.get();
+            do (*tracker).lock.lock {
+                while (*tracker).live > 0 {
+                    (*tracker).lock.wait();
+                }
+                let failed = (*tracker).failed;
+                (*tracker).failed = 0;
+                failed == 0
+            }
+        }
+    }
+
+    /// Wait for all spawned tasks to exit, then stop the schedulers, join
+    /// their threads and clean up the runtime. Returns `true` if none of
+    /// the tasks failed.
+    pub fn shutdown(self) -> bool {
+        let mut this = self;
+        let success = this.wait();
+
+        let handles = replace(&mut this.handles, ~[]);
+        for handle in handles.move_iter() {
+            let mut handle = handle;
+            handle.send(Shutdown);
+        }
+
+        // Join in creation order so that shutdown happens the same way
+        // every time.
+        let threads = replace(&mut this.threads, ~[]);
+        for thread in threads.move_iter() {
+            thread.join();
+        }
+
+        super::cleanup();
+        this.shut_down = true;
+        return success;
+    }
+}
+
+impl Drop for RuntimeHandle {
+    fn drop(&mut self) {
+        rtassert!(self.shut_down);
+    }
+}
@@ -11,14 +11,23 @@
 //! Runtime environment settings
 
 use from_str::FromStr;
-use option::{Some, None};
+use option::{Option, Some, None};
 use os;
+use rt::affinity;
+use rt::affinity::CpuSet;
 
 // Note that these are all accessed without any synchronization.
 // They are expected to be initialized once then left alone.
 
 static mut MIN_STACK: uint = 2000000;
 static mut DEBUG_BORROW: bool = false;
+static mut DUMP_TASK_STATS: bool = false;
+static mut REPORT_LEAKS: bool = false;
+static mut SCHED_SPIN: uint = 100;
+static mut DETERMINISTIC_SCHED: bool = false;
+static mut SCHED_SEED: Option<u32> = None;
+static mut NATIVE_THREADS: bool = false;
+static mut SCHED_AFFINITY: Option<CpuSet> = None;
 
 pub fn init() {
     unsafe {
@@ -29,10 +38,44 @@ pub fn init() {
             },
             None => ()
         }
+        match os::getenv("RUST_SCHED_SPIN") {
+            Some(s) => match FromStr::from_str(s) {
+                Some(i) => SCHED_SPIN = i,
+                None => ()
+            },
+            None => ()
+        }
         match os::getenv("RUST_DEBUG_BORROW") {
             Some(_) => DEBUG_BORROW = true,
             None => ()
         }
+        match os::getenv("RUST_TASK_STATS") {
+            Some(_) => DUMP_TASK_STATS = true,
+            None => ()
+        }
+        match os::getenv("RUST_REPORT_LEAKS") {
+            Some(_) => REPORT_LEAKS = true,
+            None => ()
+        }
+        // Either a seed for the scheduler's random decisions or anything
+        // else, e.g. `fifo`, for strict FIFO scheduling without them.
+        match os::getenv("RUST_DETERMINISTIC_SCHED") {
+            Some(s) => {
+                DETERMINISTIC_SCHED = true;
+                SCHED_SEED = FromStr::from_str(s);
+            }
+            None => ()
+        }
+        // `native` gives every task an OS thread of its own, `green`
+        // multiplexes tasks over the scheduler threads.
+        match os::getenv("RUST_THREADING") {
+            Some(s) => NATIVE_THREADS = "native" == s,
+            None => ()
+        }
+        match os::getenv("RUST_SCHED_AFFINITY") {
+            Some(s) => SCHED_AFFINITY = affinity::parse_affinity(s),
+            None => ()
+        }
     }
 }
 
@@ -40,6 +83,57 @@ pub fn min_stack() -> uint {
     unsafe { MIN_STACK }
 }
 
+/// How many times an idle scheduler checks for new work before going to
+/// sleep. Zero makes it sleep straight away.
+pub fn sched_spin() -> uint {
+    unsafe { SCHED_SPIN }
+}
+
 pub fn debug_borrow() -> bool {
     unsafe { DEBUG_BORROW }
 }
+
+pub fn dump_task_stats() -> bool {
+    unsafe { DUMP_TASK_STATS }
+}
+
+/// Whether to report the managed boxes still alive when each task exits,
+/// which the annihilator has to destroy.
+pub fn report_leaks() -> bool {
+    unsafe { REPORT_LEAKS }
+}
+
+/// Whether the runtime should use a single scheduler with deterministic
+/// task ordering, so that concurrency bugs reproduce from run to run.
+pub fn deterministic_sched() -> bool {
+    unsafe { DETERMINISTIC_SCHED }
+}
+
+/// The seed for the deterministic scheduler's random yields, if it
+/// should make any.
+pub fn sched_seed() -> Option<u32> {
+    unsafe { SCHED_SEED }
+}
+
+/// Whether every task runs on an OS thread of its own rather than sharing
+/// the scheduler threads.
+pub fn native_threads() -> bool {
+    unsafe { NATIVE_THREADS }
+}
+
+/// Choose the threading model. Only safe before the schedulers start.
+pub unsafe fn set_native_threads(native: bool) {
+    NATIVE_THREADS = native;
+}
+
+/// The CPUs to pin the runtime's schedulers to, round-robin, if they
+/// should be pinned at all.
+pub fn sched_affinity() -> Option<CpuSet> {
+    unsafe { SCHED_AFFINITY }
+}
+
+/// Choose the CPUs to pin the schedulers to. Only safe before the
+/// schedulers start.
+pub unsafe fn set_sched_affinity(cpus: Option<CpuSet>) {
+    SCHED_AFFINITY = cpus;
+}
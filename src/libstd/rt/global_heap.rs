@@ -9,6 +9,7 @@
 // except according to those terms.
 
 use libc::{c_void, c_char, size_t, uintptr_t, free, malloc, realloc};
+use unstable::atomics::{AtomicInt, INIT_ATOMIC_INT, Relaxed};
 use unstable::intrinsics::TyDesc;
 use unstable::raw;
 use sys::size_of;
@@ -16,6 +17,29 @@ use sys::size_of;
 extern {
     #[rust_stack]
     fn abort();
+    #[rust_stack]
+    fn rust_malloc_usable_size(p: *c_void) -> size_t;
+}
+
+// Bytes currently allocated on the exchange heap, as reported by the
+// allocator. Signed because memory freed here may have been allocated by
+// foreign code.
+static mut EXCHANGE_BYTES: AtomicInt = INIT_ATOMIC_INT;
+
+/// The number of bytes currently allocated on the exchange heap.
+pub fn exchange_heap_bytes() -> uint {
+    let bytes = unsafe { EXCHANGE_BYTES.load(Relaxed) };
+    if bytes < 0 { 0 } else { bytes as uint }
+}
+
+#[inline]
+unsafe fn note_alloc(p: *c_void) {
+    EXCHANGE_BYTES.fetch_add(rust_malloc_usable_size(p) as int, Relaxed);
+}
+
+#[inline]
+unsafe fn note_free(p: *c_void) {
+    EXCHANGE_BYTES.fetch_sub(rust_malloc_usable_size(p) as int, Relaxed);
 }
 
 #[inline]
@@ -43,6 +67,7 @@ pub unsafe fn malloc_raw(size: uint) -> *c_void {
         // we need a non-allocating way to print an error here
         abort();
     }
+    note_alloc(p);
     p
 }
 
@@ -50,11 +75,13 @@ pub unsafe fn malloc_raw(size: uint) -> *c_void {
 pub unsafe fn realloc_raw(ptr: *mut c_void, size: uint) -> *mut c_void {
     #[fixed_stack_segment]; #[inline(never)];
 
+    note_free(ptr as *c_void);
     let p = realloc(ptr, size as size_t);
     if p.is_null() {
         // we need a non-allocating way to print an error here
         abort();
     }
+    note_alloc(p as *c_void);
     p
 }
 
@@ -102,6 +129,7 @@ pub unsafe fn exchange_free_(ptr: *c_char) {
 pub unsafe fn exchange_free(ptr: *c_char) {
     #[fixed_stack_segment]; #[inline(never)];
 
+    note_free(ptr as *c_void);
     free(ptr as *c_void);
 }
 
@@ -21,6 +21,13 @@ All operations in this module, including those as part of `FileStream` et al
 block the task during execution. Most will raise `std::rt::io::{io_error,read_error}`
 conditions in the event of failure.
 
+Only the calling task blocks, not its scheduler: the operations are handed to
+the event loop, which performs them on a pool of worker threads and wakes the
+task once they're done, so other tasks keep running meanwhile. Since every
+call is a round trip to another thread, many small reads or writes are best
+made through the buffered streams returned by `open_buffered_reader` and
+`open_buffered_writer`.
+
 Also included in this module are the `FileInfo` and `DirectoryInfo` traits. When
 `use`'d alongside a value whose type implements them (A `std::path::Path` impl is
 a part of this module), they expose a set of functions for operations against
@@ -32,6 +39,7 @@ free function counterparts.
 use prelude::*;
 use super::support::PathLike;
 use super::{Reader, Writer, Seek};
+use super::buffered::{BufferedReader, BufferedWriter};
 use super::{SeekStyle, Read, Write};
 use rt::rtio::{RtioFileStream, IoFactory, IoFactoryObject};
 use rt::io::{io_error, read_error, EndOfFile,
@@ -552,6 +560,22 @@ pub trait FileInfo : FileSystemInfo {
         }
     }
 
+    /// Attempts to open a regular file in read-only mode, with its reads
+    /// buffered
+    ///
+    /// See `file::open` for more information on runtime semantics and error conditions
+    fn open_buffered_reader(&self, mode: FileMode) -> Option<BufferedReader<FileReader>> {
+        self.open_reader(mode).map(|r| BufferedReader::new(r))
+    }
+
+    /// Attempts to open a regular file in write-only mode, with its writes
+    /// buffered. Buffered data is only written out on `flush`.
+    ///
+    /// See `file::open` for more information on runtime semantics and error conditions
+    fn open_buffered_writer(&self, mode: FileMode) -> Option<BufferedWriter<FileWriter>> {
+        self.open_writer(mode).map(|w| BufferedWriter::new(w))
+    }
+
     /// Attempt to remove a file from the filesystem
     ///
     /// See `file::unlink` for more information on runtime semantics and error conditions
@@ -973,4 +997,26 @@ mod test {
             dir.rmdir();
         }
     }
+
+    #[test]
+    fn file_test_buffered_lines() {
+        do run_in_mt_newsched_task {
+            let filename = &Path("./tmp/file_rt_io_file_test_buffered.txt");
+            {
+                let mut w = filename.open_buffered_writer(Create).unwrap();
+                for n in range(0, 100) {
+                    w.write(format!("line {}\n", n).as_bytes());
+                }
+                w.flush();
+            }
+            {
+                let mut r = filename.open_buffered_reader(Open).unwrap();
+                for n in range(0, 100) {
+                    assert_eq!(r.read_line(), Some(format!("line {}\n", n)));
+                }
+                assert!(r.read_line().is_none());
+            }
+            filename.unlink();
+        }
+    }
 }
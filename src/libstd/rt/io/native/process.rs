@@ -136,7 +136,7 @@ impl Process {
      * take_output)
      */
     pub fn output<'a>(&'a mut self) -> &'a mut io::Reader {
-        match self.input {
+        match self.output {
             Some(ref mut fd) => fd as &mut io::Reader,
             None => fail2!("This process has no stdout")
         }
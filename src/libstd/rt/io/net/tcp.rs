@@ -21,12 +21,13 @@ use rt::rtio::{IoFactory, IoFactoryObject,
 use rt::local::Local;
 
 pub struct TcpStream {
-    priv obj: ~RtioTcpStreamObject
+    priv obj: ~RtioTcpStreamObject,
+    priv eof: bool
 }
 
 impl TcpStream {
     fn new(s: ~RtioTcpStreamObject) -> TcpStream {
-        TcpStream { obj: s }
+        TcpStream { obj: s, eof: false }
     }
 
     pub fn connect(addr: SocketAddr) -> Option<TcpStream> {
@@ -68,6 +69,33 @@ impl TcpStream {
             }
         }
     }
+
+    /// Disable or enable Nagle's algorithm. With `nodelay` set, small writes
+    /// are sent straight away instead of being held back to be coalesced.
+    pub fn set_nodelay(&mut self, nodelay: bool) {
+        let r = if nodelay {
+            self.obj.nodelay()
+        } else {
+            self.obj.control_congestion()
+        };
+        match r {
+            Ok(()) => (),
+            Err(ioerr) => io_error::cond.raise(ioerr)
+        }
+    }
+
+    /// Enable TCP keepalive probes, starting after the connection has been
+    /// idle for the given number of seconds, or disable them with `None`.
+    pub fn set_keepalive(&mut self, delay_in_seconds: Option<uint>) {
+        let r = match delay_in_seconds {
+            Some(delay) => self.obj.keepalive(delay),
+            None => self.obj.letdie()
+        };
+        match r {
+            Ok(()) => (),
+            Err(ioerr) => io_error::cond.raise(ioerr)
+        }
+    }
 }
 
 impl Reader for TcpStream {
@@ -76,7 +104,9 @@ impl Reader for TcpStream {
             Ok(read) => Some(read),
             Err(ioerr) => {
                 // EOF is indicated by returning None
-                if ioerr.kind != EndOfFile {
+                if ioerr.kind == EndOfFile {
+                    self.eof = true;
+                } else {
                     read_error::cond.raise(ioerr);
                 }
                 return None;
@@ -84,7 +114,9 @@ impl Reader for TcpStream {
         }
     }
 
-    fn eof(&mut self) -> bool { fail2!() }
+    /// Whether a read has reached the end of the stream. Until a read
+    /// finds the peer has closed its end this is `false`, even if it has.
+    fn eof(&mut self) -> bool { self.eof }
 }
 
 impl Writer for TcpStream {
@@ -255,6 +287,63 @@ mod test {
         }
     }
 
+    #[test]
+    fn eof_after_reading_to_end() {
+        do run_in_mt_newsched_task {
+            let addr = next_test_ip4();
+            let (port, chan) = oneshot();
+            let port = Cell::new(port);
+            let chan = Cell::new(chan);
+
+            do spawntask {
+                let mut acceptor = TcpListener::bind(addr).listen();
+                chan.take().send(());
+                let mut stream = acceptor.accept();
+                let mut buf = [0];
+                assert!(!stream.eof());
+                assert_eq!(stream.read(buf), Some(1));
+                assert!(!stream.eof());
+                assert!(stream.read(buf).is_none());
+                assert!(stream.eof());
+            }
+
+            do spawntask {
+                port.take().recv();
+                let mut stream = TcpStream::connect(addr);
+                stream.write([99]);
+            }
+        }
+    }
+
+    #[test]
+    fn set_socket_options() {
+        do run_in_mt_newsched_task {
+            let addr = next_test_ip4();
+            let (port, chan) = oneshot();
+            let port = Cell::new(port);
+            let chan = Cell::new(chan);
+
+            do spawntask {
+                let mut acceptor = TcpListener::bind(addr).listen();
+                chan.take().send(());
+                let mut stream = acceptor.accept();
+                let mut buf = [0];
+                stream.read(buf);
+                assert!(buf[0] == 99);
+            }
+
+            do spawntask {
+                port.take().recv();
+                let mut stream = TcpStream::connect(addr).unwrap();
+                stream.set_nodelay(true);
+                stream.set_keepalive(Some(30));
+                stream.set_nodelay(false);
+                stream.set_keepalive(None);
+                stream.write([99]);
+            }
+        }
+    }
+
     #[test]
     fn read_eof_ip4() {
         do run_in_mt_newsched_task {
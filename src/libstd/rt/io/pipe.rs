@@ -11,7 +11,8 @@
 //! Synchronous, in-memory pipes.
 //!
 //! Currently these aren't particularly useful, there only exists bindings
-//! enough so that pipes can be created to child processes.
+//! enough so that pipes can be created to child processes. Reads and writes
+//! block only the calling task, not the scheduler it runs on.
 
 use prelude::*;
 use super::{Reader, Writer};
@@ -21,7 +22,8 @@ use rt::rtio::{RtioPipe, RtioPipeObject, IoFactoryObject, IoFactory};
 use rt::rtio::RtioUnboundPipeObject;
 
 pub struct PipeStream {
-    priv obj: RtioPipeObject
+    priv obj: RtioPipeObject,
+    priv eof: bool
 }
 
 // This should not be a newtype, but rt::uv::process::set_stdio needs to reach
@@ -46,7 +48,7 @@ impl PipeStream {
     }
 
     pub fn bind(inner: RtioPipeObject) -> PipeStream {
-        PipeStream { obj: inner }
+        PipeStream { obj: inner, eof: false }
     }
 }
 
@@ -56,7 +58,9 @@ impl Reader for PipeStream {
             Ok(read) => Some(read),
             Err(ioerr) => {
                 // EOF is indicated by returning None
-                if ioerr.kind != EndOfFile {
+                if ioerr.kind == EndOfFile {
+                    self.eof = true;
+                } else {
                     read_error::cond.raise(ioerr);
                 }
                 return None;
@@ -64,7 +68,7 @@ impl Reader for PipeStream {
         }
     }
 
-    fn eof(&mut self) -> bool { fail2!() }
+    fn eof(&mut self) -> bool { self.eof }
 }
 
 impl Writer for PipeStream {
@@ -77,5 +81,6 @@ impl Writer for PipeStream {
         }
     }
 
-    fn flush(&mut self) { fail2!() }
+    // Writes go straight to the pipe, there's nothing buffered to flush.
+    fn flush(&mut self) {}
 }
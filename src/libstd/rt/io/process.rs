@@ -12,11 +12,15 @@
 
 use prelude::*;
 
+use cell::Cell;
 use libc;
+use os;
+use rt::comm::{oneshot, PortOne};
 use rt::io;
 use rt::io::io_error;
 use rt::local::Local;
 use rt::rtio::{RtioProcess, RtioProcessObject, IoFactoryObject, IoFactory};
+use task;
 
 // windows values don't matter as long as they're at least one of unix's
 // TERM/KILL/INT signals
@@ -27,6 +31,7 @@ use rt::rtio::{RtioProcess, RtioProcessObject, IoFactoryObject, IoFactory};
 
 pub struct Process {
     priv handle: ~RtioProcessObject,
+    priv detached: bool,
     io: ~[Option<io::PipeStream>],
 }
 
@@ -57,7 +62,12 @@ pub struct ProcessConfig<'self> {
     ///     0 - stdin
     ///     1 - stdout
     ///     2 - stderr
-    io: ~[StdioContainer]
+    io: ~[StdioContainer],
+
+    /// Whether to start the process in a process group of its own (on
+    /// Windows, a new process group with no console), so that it and
+    /// everything it spawns can be signalled together with `signal_group`.
+    detach: bool,
 }
 
 /// Describes what to do with a standard io stream for a child process.
@@ -92,6 +102,7 @@ impl Process {
     /// Creates a new pipe initialized, but not bound to any particular
     /// source/destination
     pub fn new(config: ProcessConfig) -> Option<Process> {
+        let detached = config.detach;
         let process = unsafe {
             let io: *mut IoFactoryObject = Local::unsafe_borrow();
             (*io).spawn(config)
@@ -99,6 +110,7 @@ impl Process {
         match process {
             Ok((p, io)) => Some(Process{
                 handle: p,
+                detached: detached,
                 io: io.move_iter().map(|p|
                     p.map(|p| io::PipeStream::bind(p))
                 ).collect()
@@ -129,10 +141,73 @@ impl Process {
         }
     }
 
+    /// Sends the specified signal to the child's whole process group, which
+    /// also reaches any processes it has spawned that haven't left the group.
+    /// This works even once the child itself has exited.
+    ///
+    /// Only a child spawned with `detach` set has a group of its own. For
+    /// any other child, and on Windows, just the child is signalled.
+    ///
+    /// If the signal delivery fails, then the `io_error` condition is raised.
+    #[cfg(unix)]
+    pub fn signal_group(&mut self, signal: int) {
+        #[fixed_stack_segment]; #[inline(never)];
+
+        if !self.detached {
+            return self.signal(signal);
+        }
+        // The group id is the child's pid, which can't be reused while
+        // anything is left in the group.
+        let pgid = self.id();
+        let ret = unsafe {
+            libc::funcs::posix88::signal::kill(-pgid, signal as libc::c_int)
+        };
+        if ret != 0 {
+            io_error::cond.raise(io::IoError {
+                kind: io::OtherIoError,
+                desc: "failed to signal process group",
+                detail: Some(os::last_os_error()),
+            });
+        }
+    }
+
+    /// Sends the specified signal to the child's whole process group, which
+    /// also reaches any processes it has spawned that haven't left the group.
+    ///
+    /// Windows has no equivalent, so this just signals the child.
+    #[cfg(windows)]
+    pub fn signal_group(&mut self, signal: int) {
+        self.signal(signal)
+    }
+
     /// Wait for the child to exit completely, returning the status that it
     /// exited with. This function will continue to have the same return value
     /// after it has been called at least once.
     pub fn wait(&mut self) -> int { self.handle.wait() }
+
+    /// Wait for the child to exit for at most `msecs` milliseconds. Returns
+    /// the exit status if it exited in time, and `None` if it's still running.
+    pub fn wait_timeout(&mut self, msecs: u64) -> Option<int> {
+        self.handle.wait_timeout(msecs)
+    }
+
+    /// Wait for the child to exit on a separate task, returning a port which
+    /// will receive the exit status. This lets a task carry on with other work
+    /// (or select over several children) instead of blocking in `wait`.
+    ///
+    /// Any pipes still in `io` are closed before waiting, so take out the ones
+    /// which are still needed first.
+    pub fn exit_status(self) -> PortOne<int> {
+        let (port, chan) = oneshot();
+        let chan = Cell::new(chan);
+        let this = Cell::new(self);
+        do task::spawn {
+            let mut this = this.take();
+            this.io = ~[];
+            chan.take().send(this.wait());
+        }
+        return port;
+    }
 }
 
 impl Drop for Process {
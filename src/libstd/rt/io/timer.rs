@@ -10,6 +10,9 @@
 
 use option::{Option, Some, None};
 use result::{Ok, Err};
+use rt::cancel;
+use rt::comm::PortOne;
+use rt::select::SelectInner;
 use rt::io::{io_error};
 use rt::rtio::{IoFactory, IoFactoryObject,
                RtioTimer, RtioTimerObject};
@@ -19,7 +22,8 @@ pub struct Timer {
     priv obj: ~RtioTimerObject
 }
 
-/// Sleep the current task for `msecs` milliseconds.
+/// Sleep the current task for `msecs` milliseconds, or until it's
+/// cancelled (see `rt::cancel`).
 pub fn sleep(msecs: u64) {
     let mut timer = Timer::new().expect("timer::sleep: could not create a Timer");
 
@@ -46,7 +50,20 @@ impl Timer {
     }
 
     pub fn sleep(&mut self, msecs: u64) {
-        self.obj.sleep(msecs);
+        if !cancel::is_cancellable() {
+            self.obj.sleep(msecs);
+            return;
+        }
+        // Wait for the timer and the task's token at once
+        let mut port = self.oneshot(msecs);
+        cancel::select_or_cancel(&mut port as &mut SelectInner);
+    }
+
+    /// A port that receives `()` once `msecs` milliseconds have passed,
+    /// without blocking the task in the meantime. The timer is stopped if
+    /// it's dropped before then.
+    pub fn oneshot(&mut self, msecs: u64) -> PortOne<()> {
+        self.obj.oneshot(msecs)
     }
 }
 
@@ -22,6 +22,7 @@ type MemoryRegion = c_void;
 
 struct Env { priv opaque: () }
 
+// The leading fields of boxed_region in rt/boxed_region.h
 struct BoxedRegion {
     env: *Env,
     backing_region: *MemoryRegion,
@@ -117,7 +118,15 @@ pub fn live_allocs() -> *raw::Box<()> {
     return unsafe { (*region).live_allocs };
 }
 
+/// The number of bytes currently allocated on the local heaps of all tasks.
+pub fn live_bytes() -> uint {
+    #[fixed_stack_segment]; #[inline(never)];
+
+    unsafe { rust_local_heap_bytes() as uint }
+}
+
 extern {
+    fn rust_local_heap_bytes() -> uintptr_t;
     fn rust_new_memory_region(detailed_leaks: uintptr_t,
                                poison_on_free: uintptr_t) -> *MemoryRegion;
     fn rust_delete_memory_region(region: *MemoryRegion);
@@ -8,12 +8,16 @@
 // option. This file may not be copied, modified, or distributed
 // except according to those terms.
 
+use c_str::ToCStr;
 use fmt;
 use from_str::from_str;
+use libc;
 use libc::exit;
 use option::{Some, None, Option};
+use path::Path;
+use ptr;
 use rt::crate_map::{ModEntry, CrateMap, iter_crate_map, get_crate_map};
-use str::StrSlice;
+use str::{StrSlice, OwnedStr};
 use u32;
 use vec::ImmutableVector;
 #[cfg(test)] use cast::transmute;
@@ -27,6 +31,11 @@ static MAX_LOG_LEVEL: u32 = 255;
 static DEFAULT_LOG_LEVEL: u32 = 1;
 static log_level_names : &'static[&'static str] = &'static["error", "warn", "info", "debug"];
 
+// The file descriptor that log output and the runtime's error messages go
+// to in place of stderr, or -1 for stderr. Like the settings in rt::env it
+// is expected to be set once, early on, then left alone.
+static mut LOG_FILE_FD: libc::c_int = -1;
+
 /// Parse an individual log level that is either a number or a symbolic log level
 fn parse_log_level(level: &str) -> Option<u32> {
     let num = from_str::<u32>(level);
@@ -177,11 +186,93 @@ impl Logger for StdErrLogger {
     }
 }
 
+/// Send log output, and runtime error messages such as task failures, to
+/// the file at `path` instead of stderr. The file is created if it doesn't
+/// exist and appended to otherwise. Returns `false`, leaving the destination
+/// unchanged, if the file can't be opened.
+///
+/// This isn't synchronized with logging on other threads, so it should be
+/// called before any tasks are spawned.
+#[fixed_stack_segment] #[inline(never)]
+pub fn set_log_file(path: &Path) -> bool {
+    use libc::{O_WRONLY, O_APPEND, O_CREAT, S_IRUSR, S_IWUSR};
+
+    let fd = do path.with_c_str |buf| {
+        unsafe {
+            libc::open(buf, (O_WRONLY | O_APPEND | O_CREAT) as libc::c_int,
+                       (S_IRUSR | S_IWUSR) as libc::c_int)
+        }
+    };
+    if fd < 0 {
+        return false;
+    }
+    unsafe {
+        if LOG_FILE_FD >= 0 {
+            libc::close(LOG_FILE_FD);
+        }
+        LOG_FILE_FD = fd;
+    }
+    return true;
+}
+
+/// Send log output back to stderr, closing the log file if there is one.
+#[fixed_stack_segment] #[inline(never)]
+pub fn log_to_stderr() {
+    unsafe {
+        if LOG_FILE_FD >= 0 {
+            libc::close(LOG_FILE_FD);
+            LOG_FILE_FD = -1;
+        }
+    }
+}
+
+/// Write a line to the log file set by `RUST_LOG_FILE` or `set_log_file`.
+/// Returns `false` without writing anything if output goes to stderr.
+///
+/// The line goes out in a single write, so lines logged concurrently from
+/// several threads don't get mixed up in the file.
+#[fixed_stack_segment] #[inline(never)]
+pub fn write_log_file_line(args: &fmt::Arguments) -> bool {
+    #[cfg(windows)] type wlen = libc::c_uint;
+    #[cfg(not(windows))] type wlen = libc::size_t;
+
+    let fd = unsafe { LOG_FILE_FD };
+    if fd < 0 {
+        return false;
+    }
+
+    let mut line = fmt::format(args);
+    line.push_char('\n');
+    do line.as_imm_buf |buf, len| {
+        let mut buf = buf;
+        let mut len = len;
+        while len > 0 {
+            let ret = unsafe { libc::write(fd, buf as *libc::c_void, len as wlen) };
+            if ret <= 0 {
+                break;
+            }
+            unsafe { buf = ptr::offset(buf, ret as int); }
+            len -= ret as uint;
+        }
+    }
+    return true;
+}
+
 /// Configure logging by traversing the crate map and setting the
 /// per-module global logging flags based on the logging spec
 pub fn init() {
     use os;
 
+    match os::getenv("RUST_LOG_FILE") {
+        Some(file) => {
+            if !set_log_file(&Path(file)) {
+                rterrln!("warning: couldn't open RUST_LOG_FILE {}, logging to stderr",
+                         file);
+            }
+        }
+        None => ()
+    }
+
     let log_spec = os::getenv("RUST_LOG");
     match get_crate_map() {
         Some(crate_map) => {
@@ -86,16 +86,25 @@ pub use self::util::set_exit_status;
 // method...
 pub use self::util::default_sched_threads;
 
+// Lets a program clean up or report before the runtime aborts the process.
+pub use self::util::set_abort_hook;
+
+// Run a blocking foreign call without stalling the current scheduler.
+pub use self::blocking_pool::blocking;
+
+// Starting and stopping the runtime from a host program.
+pub use self::embed::{start_in_place, RuntimeConfig, RuntimeHandle};
+
 // XXX: these probably shouldn't be public...
 #[doc(hidden)]
 pub mod shouldnt_be_public {
-    pub use super::sched::Scheduler;
+    pub use super::sched::{Scheduler, SchedHandle};
     pub use super::kill::KillHandle;
     pub use super::thread::Thread;
     pub use super::work_queue::WorkQueue;
-    pub use super::select::SelectInner;
     pub use super::rtio::EventLoop;
     pub use super::select::{SelectInner, SelectPortInner};
+    pub use super::kill::BlockedTask;
     pub use super::local_ptr::maybe_tls_key;
 }
 
@@ -114,6 +123,21 @@ mod kill;
 /// The coroutine task scheduler, built on the `io` event loop.
 mod sched;
 
+/// A pool of schedulers for running blocking foreign calls.
+mod blocking_pool;
+
+/// Pinning scheduler threads to CPUs.
+pub mod affinity;
+
+/// Shutting down gracefully, and running handlers at exit.
+pub mod shutdown;
+
+/// Asking groups of tasks to return early.
+pub mod cancel;
+
+/// Starting the runtime without owning the main thread.
+mod embed;
+
 /// Synchronous I/O.
 pub mod io;
 
@@ -143,7 +167,7 @@ pub mod stack;
 mod context;
 
 /// Bindings to system threading libraries.
-mod thread;
+pub mod thread;
 
 /// The runtime configuration, read from environment variables.
 pub mod env;
@@ -222,6 +246,24 @@ pub fn start_on_main_thread(argc: int, argv: **u8, main: ~fn()) -> int {
     return exit_code;
 }
 
+/// Like `start` but runs every task, the main task included, on an OS
+/// thread of its own instead of multiplexing tasks over a pool of
+/// scheduler threads.
+///
+/// Tasks keep their usual failure, linking and local data semantics; each
+/// one simply gets a scheduler to itself, as with `spawn_sched(SingleThreaded)`.
+/// This suits programs that call foreign code expecting to stay on one
+/// thread, and makes tasks line up with threads in a debugger. Setting
+/// `RUST_THREADING=native` does the same for a program started with `start`.
+pub fn start_native(argc: int, argv: **u8, main: ~fn()) -> int {
+    init(argc, argv);
+    unsafe { env::set_native_threads(true); }
+    let exit_code = run(main);
+    cleanup();
+
+    return exit_code;
+}
+
 /// One-time runtime initialization.
 ///
 /// Initializes global state, including frobbing
@@ -234,11 +276,45 @@ pub fn init(argc: int, argv: **u8) {
         args::init(argc, argv);
         env::init();
         logging::init();
+        stack::init();
+        blocking_pool::init();
+        affinity::init();
+        shutdown::init();
+    }
+}
+
+/// A snapshot of the runtime's memory use, process-wide.
+#[deriving(Clone)]
+pub struct RuntimeStats {
+    /// Bytes allocated on the exchange heap.
+    exchange_heap_bytes: uint,
+    /// Bytes allocated on the local heaps of all live tasks.
+    local_heap_bytes: uint,
+    /// Bytes of stack segments cached for reuse by new tasks.
+    cached_stack_bytes: uint,
+    /// The number of green tasks alive.
+    live_tasks: uint
+}
+
+/// Gather the runtime's memory statistics.
+///
+/// The counters are read independently of each other while other threads
+/// keep allocating, so they are only approximately consistent.
+pub fn stats() -> RuntimeStats {
+    RuntimeStats {
+        exchange_heap_bytes: global_heap::exchange_heap_bytes(),
+        local_heap_bytes: local_heap::live_bytes(),
+        cached_stack_bytes: stack::stats().cached_bytes,
+        live_tasks: task::live_tasks()
     }
 }
 
 /// One-time runtime cleanup.
 pub fn cleanup() {
+    shutdown::cleanup();
+    affinity::cleanup();
+    blocking_pool::cleanup();
+    stack::cleanup();
     args::cleanup();
 }
 
@@ -258,7 +334,14 @@ pub fn run_on_main_thread(main: ~fn()) -> int {
 fn run_(main: ~fn(), use_main_sched: bool) -> int {
     static DEFAULT_ERROR_CODE: int = 101;
 
-    let nscheds = util::default_sched_threads();
+    // A deterministic schedule is only possible with a single scheduler.
+    // With native threads the main task is the only one run by the
+    // schedulers started here, so one will do as well.
+    let nscheds = if env::deterministic_sched() || env::native_threads() {
+        1
+    } else {
+        util::default_sched_threads()
+    };
 
     let main = Cell::new(main);
 
@@ -275,7 +358,7 @@ fn run_(main: ~fn(), use_main_sched: bool) -> int {
     // sent the Shutdown message to terminate the schedulers.
     let mut handles = ~[];
 
-    for work_queue in work_queues.iter() {
+    for (i, work_queue) in work_queues.iter().enumerate() {
         rtdebug!("inserting a regular scheduler");
 
         // Every scheduler is driven by an I/O event loop.
@@ -284,6 +367,7 @@ fn run_(main: ~fn(), use_main_sched: bool) -> int {
                                         work_queue.clone(),
                                         work_queues.clone(),
                                         sleepers.clone());
+        sched.cpu = affinity::cpu_for_sched(i);
         let handle = sched.make_handle();
 
         scheds.push(sched);
@@ -310,6 +394,7 @@ fn run_(main: ~fn(), use_main_sched: bool) -> int {
                                                      sleepers.clone(),
                                                      false,
                                                      Some(friend_handle));
+        main_sched.cpu = affinity::cpu_for_sched(nscheds);
         let main_handle = main_sched.make_handle();
         handles.push(main_handle);
         Some(main_sched)
@@ -334,6 +419,9 @@ fn run_(main: ~fn(), use_main_sched: bool) -> int {
                     "the runtime already exited");
         }
 
+        // Unless a graceful shutdown already has
+        shutdown::run_at_exit();
+
         let mut handles = handles.take();
         for handle in handles.mut_iter() {
             handle.send(Shutdown);
@@ -13,6 +13,7 @@ use option::*;
 use result::*;
 use libc::c_int;
 
+use rt::comm::PortOne;
 use rt::io::IoError;
 use super::io::process::ProcessConfig;
 use super::io::net::ip::{IpAddr, SocketAddr};
@@ -132,6 +133,9 @@ pub trait RtioUdpSocket : RtioSocket {
 
 pub trait RtioTimer {
     fn sleep(&mut self, msecs: u64);
+    /// Starts the timer, returning a port that receives once it goes off.
+    /// Restarting or dropping the timer before then closes the port.
+    fn oneshot(&mut self, msecs: u64) -> PortOne<()>;
 }
 
 pub trait RtioFileStream {
@@ -148,6 +152,7 @@ pub trait RtioProcess {
     fn id(&self) -> libc::pid_t;
     fn kill(&mut self, signal: int) -> Result<(), IoError>;
     fn wait(&mut self) -> int;
+    fn wait_timeout(&mut self, msecs: u64) -> Option<int>;
 }
 
 pub trait RtioPipe {
@@ -24,9 +24,14 @@ use rt::kill::BlockedTask;
 use rt::local_ptr;
 use rt::local::Local;
 use rt::rtio::{RemoteCallback, PausibleIdleCallback};
+use rt::affinity;
+use rt::env;
+use rt::util;
+use rt::shutdown::TaskRegistry;
+use unstable::atomics;
 use borrow::{to_uint};
 use cell::Cell;
-use rand::{XorShiftRng, Rng, Rand};
+use rand::{XorShiftRng, Rng, Rand, SeedableRng};
 use iter::range;
 use vec::{OwnedVector};
 
@@ -85,7 +90,17 @@ pub struct Scheduler {
     yield_check_count: uint,
     /// A flag to tell the scheduler loop it needs to do some stealing
     /// in order to introduce randomness as part of a yield
-    steal_for_yield: bool
+    steal_for_yield: bool,
+    /// Run tasks in the order they became runnable instead of LIFO.
+    /// Set for deterministic scheduling.
+    priv fifo: bool,
+    /// Should `maybe_yield` occasionally yield? Turned off for
+    /// deterministic scheduling without a seed.
+    priv random_yields: bool,
+    /// The CPU to pin the scheduler's thread to when it starts, if any.
+    cpu: Option<uint>,
+    /// The green tasks started here, for a graceful shutdown to find.
+    task_registry: TaskRegistry
 }
 
 /// An indication of how hard to work on a given operation, the difference
@@ -98,6 +113,10 @@ enum EffortLevel {
 
 static MAX_YIELD_CHECKS: uint = 200;
 
+// The longest pause, in iterations of a busy loop, between the checks for
+// work an idle scheduler makes before going to sleep.
+static MAX_SPIN_BACKOFF: uint = 64;
+
 fn reset_yield_check(rng: &mut XorShiftRng) -> uint {
     let r: uint = Rand::rand(rng);
     r % MAX_YIELD_CHECKS + 1
@@ -143,14 +162,35 @@ impl Scheduler {
             rng: new_sched_rng(),
             idle_callback: None,
             yield_check_count: 0,
-            steal_for_yield: false
+            steal_for_yield: false,
+            fifo: false,
+            random_yields: true,
+            cpu: None,
+            task_registry: TaskRegistry::new()
         };
 
+        if env::deterministic_sched() {
+            sched.make_deterministic(env::sched_seed());
+        }
+
         sched.yield_check_count = reset_yield_check(&mut sched.rng);
 
         return sched;
     }
 
+    /// Make scheduling decisions reproducible: tasks run in FIFO order
+    /// and the scheduler's rng is seeded with `seed`. Without a seed
+    /// tasks only yield when they ask to. Only deterministic when this is
+    /// the only scheduler.
+    pub fn make_deterministic(&mut self, seed: Option<u32>) {
+        self.fifo = true;
+        self.random_yields = seed.is_some();
+        let seed = seed.unwrap_or(0);
+        // XorShift can't be seeded with all zeros.
+        self.rng = SeedableRng::from_seed([seed, 0x193a6754, 0xa8a7d469, 0x97830e05]);
+        self.yield_check_count = reset_yield_check(&mut self.rng);
+    }
+
     // XXX: This may eventually need to be refactored so that
     // the scheduler itself doesn't have to call event_loop.run.
     // That will be important for embedding the runtime into external
@@ -161,6 +201,15 @@ impl Scheduler {
     pub fn bootstrap(~self, task: ~Task) {
 
         let mut this = self;
+        let sched_id = this.sched_id();
+
+        // This is the thread the scheduler is going to run on.
+        match this.cpu {
+            Some(cpu) => if !affinity::pin_sched(sched_id, cpu) {
+                this.cpu = None;
+            },
+            None => ()
+        }
 
         // Build an Idle callback.
         this.idle_callback = Some(this.event_loop.pausible_idle_callback());
@@ -214,6 +263,8 @@ impl Scheduler {
         rtassert!(message.is_none());
 
         stask.destroyed = true;
+
+        affinity::unpin_sched(sched_id);
     }
 
     // This does not return a scheduler, as the scheduler is placed
@@ -274,6 +325,14 @@ impl Scheduler {
             None => return
         };
 
+        // Work often turns up soon after we run out, e.g. the reply to a
+        // message just sent, so keep looking for a little while before
+        // paying for a trip through the sleeper list and the OS.
+        let sched = match sched.spin_for_work() {
+            Some(sched) => sched,
+            None => return
+        };
+
         // Now, before sleeping we need to find out if there really
         // were any messages. Give it your best!
         let mut sched = match sched.interpret_message_queue(GiveItYourBest) {
@@ -364,6 +423,38 @@ impl Scheduler {
         }
     }
 
+    // Poll the message queue and the work queues a bounded number of
+    // times, backing off exponentially between polls. Like the functions
+    // above this returns None if the scheduler gets used.
+    fn spin_for_work(~self) -> Option<~Scheduler> {
+        let mut this = self;
+
+        // No point spinning if we're already asleep or on the way out.
+        if this.sleepy || this.no_sleep {
+            return Some(this);
+        }
+
+        let mut backoff = 1;
+        for _ in range(0, env::sched_spin()) {
+            for _ in range(0, backoff) {
+                atomics::fence(atomics::SeqCst);
+            }
+            if backoff < MAX_SPIN_BACKOFF {
+                backoff *= 2;
+            }
+
+            this = match this.interpret_message_queue(DontTryTooHard) {
+                Some(sched) => sched,
+                None => return None
+            };
+            this = match this.do_work() {
+                Some(sched) => sched,
+                None => return None
+            };
+        }
+        return Some(this);
+    }
+
     fn do_work(~self) -> Option<~Scheduler> {
         let mut this = self;
 
@@ -393,7 +484,14 @@ impl Scheduler {
     fn find_work(&mut self) -> Option<~Task> {
         rtdebug!("scheduler looking for work");
         if !self.steal_for_yield {
-            match self.work_queue.pop() {
+            // The work queue is a deque. Taking from the stealing end
+            // gives us the oldest task.
+            let task = if self.fifo {
+                self.work_queue.steal()
+            } else {
+                self.work_queue.pop()
+            };
+            match task {
                 Some(task) => {
                     rtdebug!("found a task locally");
                     return Some(task)
@@ -518,6 +616,8 @@ impl Scheduler {
     pub fn enqueue_task(&mut self, task: ~Task) {
 
         let this = self;
+        let mut task = task;
+        task.stats.note_runnable(util::precise_time_ns());
 
         // We push the task onto our local queue clone.
         this.work_queue.push(task);
@@ -561,12 +661,19 @@ impl Scheduler {
         // The current task is grabbed from TLS, not taken as an input.
         // Doing an unsafe_take to avoid writing back a null pointer -
         // We're going to call `put` later to do that.
-        let current_task: ~Task = unsafe { Local::unsafe_take() };
+        let mut current_task: ~Task = unsafe { Local::unsafe_take() };
 
         // Check that the task is not in an atomically() section (e.g.,
         // holding a pthread mutex, which could deadlock the scheduler).
         current_task.death.assert_may_sleep();
 
+        // Account for the time slice that is ending and the one that is
+        // about to begin.
+        let mut next_task = next_task;
+        let now = util::precise_time_ns();
+        current_task.stats.note_descheduled(now);
+        next_task.stats.note_resumed(now);
+
         // These transmutes do something fishy with a closure.
         let f_fake_region = unsafe {
             transmute::<&fn(&mut Scheduler, ~Task),
@@ -579,7 +686,6 @@ impl Scheduler {
         this.cleanup_job = Some(CleanupJob::new(current_task, f_opaque));
 
         // The scheduler is then placed inside the next task.
-        let mut next_task = next_task;
         next_task.sched = Some(this);
 
         // However we still need an internal mutable pointer to the
@@ -701,6 +807,39 @@ impl Scheduler {
         }
     }
 
+    /// Deschedule the running task and send it to the scheduler behind
+    /// `dest`, which will resume it immediately and pin it there.
+    ///
+    /// The running task must be unkillable, so that it can't be stolen
+    /// by a killer while in transit.
+    pub fn send_running_task_to(~self, dest: &mut SchedHandle) {
+        do self.deschedule_running_task_and_then |_, task| {
+            match task.wake() {
+                Some(task) => dest.send(PinnedTask(task)),
+                None => rtabort!("task was killed while migrating")
+            }
+        }
+    }
+
+    /// Deschedule the running task and send it to its home scheduler or,
+    /// if it has no home, to the scheduler behind `friend`.
+    ///
+    /// As with `send_running_task_to` the running task must be unkillable.
+    pub fn send_running_task_home(~self, friend: &mut SchedHandle) {
+        do self.deschedule_running_task_and_then |_, task| {
+            match task.wake() {
+                Some(task) => {
+                    if task.homed() {
+                        Scheduler::send_task_home(task);
+                    } else {
+                        friend.send(TaskFromFriend(task));
+                    }
+                }
+                None => rtabort!("task was killed while migrating")
+            }
+        }
+    }
+
     fn switch_task(sched: ~Scheduler, task: ~Task) {
         do sched.switch_running_tasks_and_then(task) |sched, last_task| {
             sched.enqueue_blocked_task(last_task);
@@ -717,6 +856,9 @@ impl Scheduler {
         let mut this = self;
         let stask = this.sched_task.take_unwrap();
         do this.change_task_context(stask) |sched, mut dead_task| {
+            if env::dump_task_stats() {
+                dead_task.dump_stats();
+            }
             let coroutine = dead_task.coroutine.take_unwrap();
             coroutine.recycle(&mut sched.stack_pool);
         }
@@ -751,6 +893,10 @@ impl Scheduler {
     pub fn maybe_yield(~self) {
         // The number of times to do the yield check before yielding, chosen arbitrarily.
         let mut this = self;
+        if !this.random_yields {
+            Local::put(this);
+            return;
+        }
         rtassert!(this.yield_check_count > 0);
         this.yield_check_count -= 1;
         if this.yield_check_count == 0 {
@@ -861,7 +1007,6 @@ fn new_sched_rng() -> XorShiftRng {
     use c_str::ToCStr;
     use vec::MutableVector;
     use iter::Iterator;
-    use rand::SeedableRng;
 
     let fd = do "/dev/urandom".with_c_str |name| {
         unsafe { libc::open(name, libc::O_RDONLY, 0) }
@@ -1385,4 +1530,68 @@ mod test {
         do spawn { }
         do spawn { }
     }
+
+    #[test]
+    fn deterministic_fifo_order() {
+        let mut order = ~[];
+        let order_ptr: *mut ~[uint] = &mut order;
+        do run_in_deterministic_task(None) {
+            for i in range(0u, 5) {
+                do spawntask_later {
+                    unsafe { (*order_ptr).push(i); }
+                }
+            }
+        }
+        assert_eq!(order, ~[0, 1, 2, 3, 4]);
+    }
+
+    #[test]
+    fn deterministic_seeded_schedule_repeats() {
+        fn run(seed: u32) -> ~[uint] {
+            let mut order = ~[];
+            let order_ptr: *mut ~[uint] = &mut order;
+            do run_in_deterministic_task(Some(seed)) {
+                for i in range(0u, 20) {
+                    do spawntask_random {
+                        unsafe { (*order_ptr).push(i); }
+                    }
+                }
+            }
+            order
+        }
+        assert_eq!(run(42), run(42));
+    }
+}
+
+#[cfg(test)]
+mod bench {
+    use extra::test::BenchHarness;
+    use cell::Cell;
+    use comm::{stream, GenericChan, GenericPort};
+    use option::{Some, None};
+    use task;
+
+    // The round trip time of a message to a task on another scheduler,
+    // which is mostly the time it takes the other scheduler to wake up.
+    #[bench]
+    fn ping_pong_latency(bh: &mut BenchHarness) {
+        let (ping_port, ping_chan) = stream::<uint>();
+        let (pong_port, pong_chan) = stream::<uint>();
+        let ping_port = Cell::new(ping_port);
+        let pong_chan = Cell::new(pong_chan);
+        do task::spawn_sched(task::SingleThreaded) {
+            let ping_port = ping_port.take();
+            let pong_chan = pong_chan.take();
+            loop {
+                match ping_port.try_recv() {
+                    Some(n) => pong_chan.send(n),
+                    None => break
+                }
+            }
+        }
+        do bh.iter {
+            ping_chan.send(1);
+            pong_port.recv();
+        }
+    }
 }
@@ -0,0 +1,251 @@
+// Copyright 2013 The Rust Project Developers. See the COPYRIGHT
+// file at the top-level directory of this distribution and at
+// http://rust-lang.org/COPYRIGHT.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Shutting the runtime down gracefully.
+//!
+//! `shutdown` stops the runtime from taking on new work, gives the tasks
+//! already running until a deadline to finish, and then kills the ones
+//! left, on every scheduler. Once they're gone, or have had a while to
+//! unwind, it runs the handlers registered with `at_exit`. Those otherwise
+//! run when the main task exits, before the schedulers are shut down.
+//!
+//! Each scheduler keeps the tasks it starts in a `TaskRegistry` of its
+//! own, so starting and finishing a task only takes that scheduler's lock.
+//!
+//! Tasks spawned once a shutdown has begun, or spawned before but not yet
+//! started, exit straight away without running their bodies, as though
+//! they had been killed before they could start.
+
+use cast;
+use cell::Cell;
+use cmp;
+use container::Container;
+use hashmap::HashSet;
+use iter::Iterator;
+use option::{Option, Some, None};
+use ptr;
+use ptr::RawPtr;
+use rt::io::timer;
+use rt::kill::KillHandle;
+use rt::local::Local;
+use rt::sched::Scheduler;
+use rt::task::Task;
+use rt::util::precise_time_ns;
+use unstable::atomics::{AtomicBool, INIT_ATOMIC_BOOL, SeqCst};
+use unstable::sync::Exclusive;
+use util;
+use vec::OwnedVector;
+
+/// How long to wait, in milliseconds, between checks on the tasks left.
+static POLL_INTERVAL: u64 = 10;
+
+/// How long to give killed tasks, in milliseconds, to unwind.
+static UNWIND_TIMEOUT: u64 = 1000;
+
+struct ShutdownState {
+    /// The registries of the schedulers created so far.
+    registries: ~[TaskRegistry],
+    /// Handlers not yet run, in the order they were registered.
+    at_exit: ~[~fn()]
+}
+
+/// What became of the tasks that were running when a shutdown began.
+#[deriving(Clone, Eq)]
+pub struct ShutdownResult {
+    /// The number of tasks that finished before the deadline.
+    finished: uint,
+    /// The number of tasks that were killed at the deadline.
+    killed: uint,
+    /// The number of killed tasks still running when shutdown stopped
+    /// waiting for them to unwind, for instance because they're unkillable.
+    stuck: uint
+}
+
+/// The kill handles of the green tasks running on one scheduler. A task
+/// deregisters from the registry it registered with, even if it finishes
+/// on another scheduler.
+#[deriving(Clone)]
+pub struct TaskRegistry {
+    priv tasks: Exclusive<HashSet<KillHandle>>
+}
+
+impl TaskRegistry {
+    /// A registry that a shutdown will find the tasks in.
+    pub fn new() -> TaskRegistry {
+        let registry = TaskRegistry {
+            tasks: Exclusive::new(HashSet::with_capacity_and_keys(0, 0, 32))
+        };
+        do with_state |state| {
+            state.registries.push(registry.clone());
+        };
+        registry
+    }
+
+    // Called by each green task as it starts and finishes running.
+    pub fn register(&self, handle: &KillHandle) {
+        unsafe {
+            do self.tasks.with |tasks| {
+                tasks.insert(handle.clone());
+            }
+        }
+    }
+
+    pub fn deregister(&self, handle: &KillHandle) {
+        unsafe {
+            do self.tasks.with |tasks| {
+                tasks.remove(handle);
+            }
+        }
+    }
+}
+
+static mut SHUTTING_DOWN: AtomicBool = INIT_ATOMIC_BOOL;
+
+// Initialized by `rt::init`, before any schedulers exist, and destroyed
+// by `rt::cleanup` after they have all exited.
+static mut STATE: *mut Exclusive<ShutdownState> = 0 as *mut Exclusive<ShutdownState>;
+
+pub fn init() {
+    unsafe {
+        rtassert!(STATE.is_null());
+        let state = ~Exclusive::new(ShutdownState {
+            registries: ~[],
+            at_exit: ~[]
+        });
+        STATE = cast::transmute(state);
+        SHUTTING_DOWN.store(false, SeqCst);
+    }
+}
+
+pub fn cleanup() {
+    unsafe {
+        rtassert!(!STATE.is_null());
+        let _state: ~Exclusive<ShutdownState> = cast::transmute(STATE);
+        STATE = ptr::mut_null();
+    }
+}
+
+fn with_state<T>(f: &fn(&mut ShutdownState) -> T) -> Option<T> {
+    unsafe {
+        if STATE.is_null() {
+            None
+        } else {
+            Some((*STATE).with(f))
+        }
+    }
+}
+
+/// Whether new tasks may still start, i.e. no shutdown has begun.
+pub fn accepting_tasks() -> bool {
+    unsafe { !SHUTTING_DOWN.load(SeqCst) }
+}
+
+/// Registers `f` to be run once, on the way out of the runtime.
+pub fn at_exit(f: ~fn()) {
+    let f = Cell::new(f);
+    do with_state |state| {
+        state.at_exit.push(f.take());
+    };
+    if !f.is_empty() {
+        rtabort!("at_exit called without a runtime");
+    }
+}
+
+/// Runs the handlers registered with `at_exit` that haven't been run yet.
+pub fn run_at_exit() {
+    loop {
+        // Handlers may register more handlers
+        let handlers = with_state(|state| util::replace(&mut state.at_exit, ~[]));
+        match handlers {
+            Some(handlers) if !handlers.is_empty() => {
+                for f in handlers.move_iter() {
+                    f();
+                }
+            }
+            _ => return
+        }
+    }
+}
+
+// The tasks running besides the calling one, on every scheduler
+fn other_tasks(me: &KillHandle) -> ~[KillHandle] {
+    let registries = with_state(|state| state.registries.clone()).unwrap_or(~[]);
+    let mut others = ~[];
+    for registry in registries.iter() {
+        unsafe {
+            do registry.tasks.with |tasks| {
+                for t in tasks.iter() {
+                    if *t != *me {
+                        others.push(t.clone());
+                    }
+                }
+            }
+        }
+    }
+    others
+}
+
+// Waits until the other tasks are gone or the deadline, in milliseconds,
+// has passed. Returns the number of tasks left.
+fn wait_for_others(me: &KillHandle, deadline: u64) -> uint {
+    loop {
+        let left = other_tasks(me).len();
+        let now = precise_time_ns() / 1000000;
+        if left == 0 || now >= deadline {
+            return left;
+        }
+        timer::sleep(cmp::min(deadline - now, POLL_INTERVAL));
+    }
+}
+
+/// Shuts the runtime down gracefully: stops new tasks from starting,
+/// waits up to `deadline_ms` milliseconds for every task but the calling
+/// one to finish, then kills those left and gives them a second to unwind.
+/// Finally runs the `at_exit` handlers, even if some killed tasks are
+/// still running; the result counts those as stuck.
+///
+/// The runtime itself exits once the main task does, so this is usually
+/// called from the main task just before it returns. Called from another
+/// task, it treats the main task like any other, and killing it makes the
+/// program's exit code a failing one.
+pub fn shutdown(deadline_ms: u64) -> ShutdownResult {
+    let me = do Local::borrow |task: &mut Task| {
+        task.death.kill_handle.get_ref().clone()
+    };
+
+    unsafe { SHUTTING_DOWN.store(true, SeqCst); }
+    let running = other_tasks(&me).len();
+    rtdebug!("shutting down with {} tasks running", running);
+
+    let deadline = precise_time_ns() / 1000000 + deadline_ms;
+    wait_for_others(&me, deadline);
+
+    let mut killed = 0;
+    for handle in other_tasks(&me).move_iter() {
+        let mut handle = handle;
+        do handle.kill().map |killed_task| {
+            let killed_task = Cell::new(killed_task);
+            do Local::borrow |sched: &mut Scheduler| {
+                sched.enqueue_task(killed_task.take());
+            }
+        };
+        killed += 1;
+    }
+    let stuck = wait_for_others(&me, precise_time_ns() / 1000000 + UNWIND_TIMEOUT);
+    if stuck > 0 {
+        rtdebug!("{} killed tasks didn't unwind in time", stuck);
+    }
+
+    run_at_exit();
+    // Tasks that started late, before seeing the shutdown, may have been
+    // killed too
+    let finished = if running > killed { running - killed } else { 0 };
+    ShutdownResult { finished: finished, killed: killed, stuck: stuck }
+}
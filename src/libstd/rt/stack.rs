@@ -8,9 +8,18 @@
 // option. This file may not be copied, modified, or distributed
 // except according to those terms.
 
+use cast;
+use cell::Cell;
 use container::Container;
+use iter::Iterator;
+use option::{Option, Some, None};
+use ptr;
 use ptr::RawPtr;
+use unstable::atomics::{AtomicUint, INIT_ATOMIC_UINT, SeqCst};
+use unstable::sync::Exclusive;
+use util;
 use vec;
+use vec::{OwnedVector, ImmutableVector};
 use ops::Drop;
 use libc::{c_uint, uintptr_t};
 
@@ -63,16 +72,190 @@ impl Drop for StackSegment {
     }
 }
 
-pub struct StackPool(());
+/// A cache of stack segments.
+///
+/// Each scheduler keeps a few freed segments of its own, which it can reuse
+/// without synchronization. Beyond that, freed segments overflow into a pool
+/// shared by all schedulers, which a scheduler with an empty cache draws on
+/// before allocating, so that a multi-threaded runtime doesn't hold on to
+/// the same stacks once per thread.
+pub struct StackPool {
+    priv stacks: ~[StackSegment],
+    // The pool segments overflow into, if there is one
+    priv shared: Option<Exclusive<SharedStacks>>
+}
+
+// The most segments a single StackPool caches before overflowing into the
+// global pool, and the most the global pool holds before freeing them.
+static MAX_LOCAL_STACKS: uint = 4;
+static MAX_GLOBAL_STACKS: uint = 64;
+
+// Initialized by `rt::init` and destroyed by `rt::cleanup`. Until then
+// pools neither overflow into it nor draw on it.
+static mut GLOBAL_POOL: *mut Exclusive<SharedStacks> =
+    0 as *mut Exclusive<~[StackSegment]>;
+
+static mut SEGMENTS_ALLOCATED: AtomicUint = INIT_ATOMIC_UINT;
+static mut LOCAL_REUSES: AtomicUint = INIT_ATOMIC_UINT;
+static mut GLOBAL_REUSES: AtomicUint = INIT_ATOMIC_UINT;
+static mut CACHED_BYTES: AtomicUint = INIT_ATOMIC_UINT;
+
+/// Counters describing the process-wide use of stack segments.
+#[deriving(Clone, Eq)]
+pub struct StackStats {
+    /// Segments that had to be freshly allocated.
+    segments_allocated: uint,
+    /// Segments reused from the taking scheduler's own cache.
+    local_reuses: uint,
+    /// Segments reused from the global pool.
+    global_reuses: uint,
+    /// Bytes of stack currently cached in all pools.
+    cached_bytes: uint
+}
+
+// The segments that pools overflow into. Whatever is still cached here
+// when the last handle goes away is freed.
+struct SharedStacks {
+    stacks: ~[StackSegment]
+}
+
+impl SharedStacks {
+    fn new() -> SharedStacks {
+        SharedStacks { stacks: ~[] }
+    }
+}
+
+impl Drop for SharedStacks {
+    fn drop(&mut self) {
+        for stack in self.stacks.iter() {
+            unsafe { CACHED_BYTES.fetch_sub(stack.buf.len(), SeqCst); }
+        }
+    }
+}
+
+pub fn init() {
+    unsafe {
+        rtassert!(GLOBAL_POOL.is_null());
+        let pool = ~Exclusive::new(SharedStacks::new());
+        GLOBAL_POOL = cast::transmute(pool);
+    }
+}
+
+pub fn cleanup() {
+    unsafe {
+        rtassert!(!GLOBAL_POOL.is_null());
+        let pool: ~Exclusive<SharedStacks> = cast::transmute(GLOBAL_POOL);
+        GLOBAL_POOL = ptr::mut_null();
+
+        // Any StackPool still alive holds on to the pool itself, but not
+        // to the segments in it
+        let _stacks = pool.with(|shared| util::replace(shared, SharedStacks::new()));
+    }
+}
+
+/// Returns the current stack segment counters.
+pub fn stats() -> StackStats {
+    unsafe {
+        StackStats {
+            segments_allocated: SEGMENTS_ALLOCATED.load(SeqCst),
+            local_reuses: LOCAL_REUSES.load(SeqCst),
+            global_reuses: GLOBAL_REUSES.load(SeqCst),
+            cached_bytes: CACHED_BYTES.load(SeqCst)
+        }
+    }
+}
+
+fn global_pool() -> Option<Exclusive<SharedStacks>> {
+    unsafe {
+        if GLOBAL_POOL.is_null() {
+            None
+        } else {
+            Some((*GLOBAL_POOL).clone())
+        }
+    }
+}
+
+// Remove a segment of at least `min_size` bytes from `stacks`.
+fn take_fitting(stacks: &mut ~[StackSegment], min_size: uint) -> Option<StackSegment> {
+    match stacks.iter().position(|s| s.buf.len() >= min_size) {
+        Some(i) => {
+            let stack = stacks.swap_remove(i);
+            unsafe { CACHED_BYTES.fetch_sub(stack.buf.len(), SeqCst); }
+            Some(stack)
+        }
+        None => None
+    }
+}
+
+// Give a segment to the shared pool, or free it if the pool is full.
+fn overflow(shared: &Option<Exclusive<SharedStacks>>, stack: StackSegment) {
+    let stack = Cell::new(stack);
+    for shared in shared.iter() {
+        unsafe {
+            do shared.with |shared| {
+                if shared.stacks.len() < MAX_GLOBAL_STACKS {
+                    let stack = stack.take();
+                    CACHED_BYTES.fetch_add(stack.buf.len(), SeqCst);
+                    shared.stacks.push(stack);
+                }
+            }
+        }
+    }
+}
 
 impl StackPool {
-    pub fn new() -> StackPool { StackPool(()) }
+    /// A pool that overflows into the global pool, if the runtime has
+    /// set one up.
+    pub fn new() -> StackPool {
+        StackPool::sharing(global_pool())
+    }
+
+    // A pool that overflows into `shared`
+    fn sharing(shared: Option<Exclusive<SharedStacks>>) -> StackPool {
+        StackPool { stacks: ~[], shared: shared }
+    }
+
+    pub fn take_segment(&mut self, min_size: uint) -> StackSegment {
+        match take_fitting(&mut self.stacks, min_size) {
+            Some(stack) => {
+                unsafe { LOCAL_REUSES.fetch_add(1, SeqCst); }
+                return stack;
+            }
+            None => ()
+        }
+
+        for shared in self.shared.iter() {
+            match unsafe { shared.with(|shared| take_fitting(&mut shared.stacks, min_size)) } {
+                Some(stack) => {
+                    unsafe { GLOBAL_REUSES.fetch_add(1, SeqCst); }
+                    return stack;
+                }
+                None => ()
+            }
+        }
 
-    fn take_segment(&self, min_size: uint) -> StackSegment {
+        unsafe { SEGMENTS_ALLOCATED.fetch_add(1, SeqCst); }
         StackSegment::new(min_size)
     }
 
-    fn give_segment(&self, _stack: StackSegment) {
+    pub fn give_segment(&mut self, stack: StackSegment) {
+        if self.stacks.len() < MAX_LOCAL_STACKS {
+            unsafe { CACHED_BYTES.fetch_add(stack.buf.len(), SeqCst); }
+            self.stacks.push(stack);
+        } else {
+            overflow(&self.shared, stack);
+        }
+    }
+}
+
+impl Drop for StackPool {
+    fn drop(&mut self) {
+        // A scheduler that goes away leaves its stacks to the others.
+        let stacks = util::replace(&mut self.stacks, ~[]);
+        for stack in stacks.move_iter() {
+            unsafe { CACHED_BYTES.fetch_sub(stack.buf.len(), SeqCst); }
+            overflow(&self.shared, stack);
+        }
     }
 }
 
@@ -80,3 +263,50 @@ extern {
     fn rust_valgrind_stack_register(start: *uintptr_t, end: *uintptr_t) -> c_uint;
     fn rust_valgrind_stack_deregister(id: c_uint);
 }
+
+#[cfg(test)]
+mod test {
+    use super::{StackPool, SharedStacks};
+    use unstable::sync::Exclusive;
+
+    // The tests share a pool of their own rather than the global one,
+    // which the other tests' schedulers are using at the same time. A
+    // segment is known to be reused when it's at the same address.
+
+    #[test]
+    fn reuses_local_segments() {
+        let mut pool = StackPool::sharing(None);
+        let stack = pool.take_segment(4096);
+        let start = stack.start();
+        pool.give_segment(stack);
+        let stack = pool.take_segment(4096);
+        assert_eq!(stack.start(), start);
+        assert!(stack.buf.len() >= 4096);
+    }
+
+    #[test]
+    fn reuses_segments_from_other_pools() {
+        let shared = Exclusive::new(SharedStacks::new());
+        let mut b = StackPool::sharing(Some(shared.clone()));
+        let start;
+        {
+            let mut a = StackPool::sharing(Some(shared.clone()));
+            let stack = a.take_segment(4096);
+            start = stack.start();
+            a.give_segment(stack);
+            // Dropping a pool donates its cache to the shared pool.
+        }
+        let stack = b.take_segment(4096);
+        assert_eq!(stack.start(), start);
+        b.give_segment(stack);
+    }
+
+    #[test]
+    fn doesnt_reuse_small_segments() {
+        let mut pool = StackPool::sharing(None);
+        let stack = pool.take_segment(1024);
+        pool.give_segment(stack);
+        let stack = pool.take_segment(8192);
+        assert!(stack.buf.len() >= 8192);
+    }
+}
@@ -22,19 +22,29 @@ use prelude::*;
 use option::{Option, Some, None};
 use rt::borrowck;
 use rt::borrowck::BorrowRecord;
+use rt::cancel::CancelToken;
 use rt::env;
 use rt::kill::Death;
 use rt::local::Local;
-use rt::logging::StdErrLogger;
+use rt::logging::{Logger, StdErrLogger};
 use super::local_heap::LocalHeap;
 use rt::sched::{Scheduler, SchedHandle};
 use rt::stack::{StackSegment, StackPool};
 use rt::context::Context;
+use unstable::atomics::{AtomicUint, INIT_ATOMIC_UINT, SeqCst};
 use unstable::finally::Finally;
 use task::spawn::Taskgroup;
 use cell::Cell;
 use send_str::SendStr;
 
+// The number of green tasks that have been created and not yet dropped.
+static mut LIVE_TASKS: AtomicUint = INIT_ATOMIC_UINT;
+
+/// The number of green tasks currently alive in the process.
+pub fn live_tasks() -> uint {
+    unsafe { LIVE_TASKS.load(SeqCst) }
+}
+
 // The Task struct represents all state associated with a rust
 // task. There are at this point two primary "subtypes" of task,
 // however instead of using a subtype we just have a "task_type" field
@@ -45,17 +55,20 @@ pub struct Task {
     heap: LocalHeap,
     gc: GarbageCollector,
     storage: LocalStorage,
-    logger: StdErrLogger,
+    logger: Option<~Logger>,
     unwinder: Unwinder,
     taskgroup: Option<Taskgroup>,
     death: Death,
+    /// Checked by blocking operations; see `rt::cancel`.
+    cancel_token: Option<CancelToken>,
     destroyed: bool,
     name: Option<SendStr>,
     coroutine: Option<Coroutine>,
     sched: Option<~Scheduler>,
     task_type: TaskType,
     // Dynamic borrowck debugging info
-    borrow_list: Option<~[BorrowRecord]>
+    borrow_list: Option<~[BorrowRecord]>,
+    stats: TaskStats
 }
 
 pub enum TaskType {
@@ -80,10 +93,32 @@ pub enum SchedHome {
 }
 
 pub struct GarbageCollector;
-pub struct LocalStorage(Option<local_data::Map>);
+pub struct LocalStorage {
+    map: Option<local_data::Map>,
+    /// Set once the task's local data has been destroyed on exit. Using
+    /// TLS after that is an error.
+    destroyed: bool
+}
 
 pub struct Unwinder {
     unwinding: bool,
+    /// The message the task failed with, kept for `task::try_block`.
+    cause: Option<~str>
+}
+
+/// Scheduling statistics for a task, updated by the scheduler at
+/// context switch points.
+#[deriving(Clone)]
+pub struct TaskStats {
+    /// Nanoseconds the task has spent running on a scheduler.
+    cpu_time_ns: u64,
+    /// The number of times the task has been context switched to.
+    times_scheduled: uint,
+    /// Nanoseconds the task has spent descheduled and waiting to
+    /// become runnable again.
+    blocked_time_ns: u64,
+    priv running_since: Option<u64>,
+    priv descheduled_since: Option<u64>
 }
 
 impl Task {
@@ -130,17 +165,19 @@ impl Task {
         Task {
             heap: LocalHeap::new(),
             gc: GarbageCollector,
-            storage: LocalStorage(None),
-            logger: StdErrLogger,
-            unwinder: Unwinder { unwinding: false },
+            storage: LocalStorage::new(),
+            logger: None,
+            unwinder: Unwinder::new(),
             taskgroup: None,
             death: Death::new(),
+            cancel_token: None,
             destroyed: false,
             coroutine: Some(Coroutine::empty()),
             name: None,
             sched: None,
             task_type: SchedTask,
-            borrow_list: None
+            borrow_list: None,
+            stats: TaskStats::new()
         }
     }
 
@@ -161,20 +198,23 @@ impl Task {
                           stack_size: Option<uint>,
                           home: SchedHome,
                           start: ~fn()) -> Task {
+        unsafe { LIVE_TASKS.fetch_add(1, SeqCst); }
         Task {
             heap: LocalHeap::new(),
             gc: GarbageCollector,
-            storage: LocalStorage(None),
-            logger: StdErrLogger,
-            unwinder: Unwinder { unwinding: false },
+            storage: LocalStorage::new(),
+            logger: None,
+            unwinder: Unwinder::new(),
             taskgroup: None,
             death: Death::new(),
+            cancel_token: None,
             destroyed: false,
             name: None,
             coroutine: Some(Coroutine::new(stack_pool, stack_size, start)),
             sched: None,
             task_type: GreenTask(Some(home)),
-            borrow_list: None
+            borrow_list: None,
+            stats: TaskStats::new()
         }
     }
 
@@ -183,21 +223,24 @@ impl Task {
                            stack_size: Option<uint>,
                            home: SchedHome,
                            start: ~fn()) -> Task {
+        unsafe { LIVE_TASKS.fetch_add(1, SeqCst); }
         Task {
             heap: LocalHeap::new(),
             gc: GarbageCollector,
-            storage: LocalStorage(None),
-            logger: StdErrLogger,
-            unwinder: Unwinder { unwinding: false },
+            storage: LocalStorage::new(),
+            logger: None,
+            unwinder: Unwinder::new(),
             taskgroup: None,
             // FIXME(#7544) make watching optional
             death: self.death.new_child(),
+            cancel_token: None,
             destroyed: false,
             name: None,
             coroutine: Some(Coroutine::new(stack_pool, stack_size, start)),
             sched: None,
             task_type: GreenTask(Some(home)),
-            borrow_list: None
+            borrow_list: None,
+            stats: TaskStats::new()
         }
     }
 
@@ -227,32 +270,28 @@ impl Task {
     pub fn run(&mut self, f: &fn()) {
         rtdebug!("run called on task: {}", borrow::to_uint(self));
 
+        // Let a shutdown find the task, to wait for it or kill it
+        let registry = match self.task_type {
+            GreenTask(_) => self.sched.as_ref().map(|sched| sched.task_registry.clone()),
+            SchedTask => None
+        };
+        for registry in registry.iter() {
+            registry.register(self.death.kill_handle.get_ref());
+        }
+
         // The only try/catch block in the world. Attempt to run the task's
         // client-specified code and catch any failures.
         do self.unwinder.try {
 
             // Run the task main function, then do some cleanup.
             do f.finally {
-                // First, destroy task-local storage. This may run user dtors.
-                //
-                // FIXME #8302: Dear diary. I'm so tired and confused.
-                // There's some interaction in rustc between the box
-                // annihilator and the TLS dtor by which TLS is
-                // accessed from annihilated box dtors *after* TLS is
-                // destroyed. Somehow setting TLS back to null, as the
-                // old runtime did, makes this work, but I don't currently
-                // understand how. I would expect that, if the annihilator
-                // reinvokes TLS while TLS is uninitialized, that
-                // TLS would be reinitialized but never destroyed,
-                // but somehow this works. I have no idea what's going
-                // on but this seems to make things magically work. FML.
-                //
-                // (added after initial comment) A possible interaction here is
-                // that the destructors for the objects in TLS themselves invoke
-                // TLS, or possibly some destructors for those objects being
-                // annihilated invoke TLS. Sadly these two operations seemed to
-                // be intertwined, and miraculously work for now...
-                self.storage.take();
+                // First, destroy task-local storage. This may run user dtors,
+                // which may themselves use TLS values set earlier, so values
+                // are destroyed in the reverse of the order they were set.
+                // Afterwards TLS is poisoned: box destructors run by the
+                // annihilator below fail if they try to use it, instead of
+                // silently reinitializing it.
+                unsafe { local_data::destroy_all(); }
 
                 // Destroy remaining boxes. Also may run user dtors.
                 unsafe { cleanup::annihilate(); }
@@ -262,6 +301,12 @@ impl Task {
         // Cleanup the dynamic borrowck debugging info
         borrowck::clear_task_borrow_list();
 
+        // The registry's handle has to be gone before `collect_failure`
+        // unwraps the kill handle.
+        for registry in registry.iter() {
+            registry.deregister(self.death.kill_handle.get_ref());
+        }
+
         // NB. We pass the taskgroup into death so that it can be dropped while
         // the unkillable counter is set. This is necessary for when the
         // taskgroup destruction code drops references on KillHandles, which
@@ -270,6 +315,20 @@ impl Task {
         self.destroyed = true;
     }
 
+    /// The scheduling statistics of this task, including the time slice
+    /// in progress if the task is currently running.
+    pub fn current_stats(&self) -> TaskStats {
+        use rt::util;
+        self.stats.snapshot(util::precise_time_ns())
+    }
+
+    /// Print this task's name and scheduling statistics, as they are now,
+    /// to stderr.
+    pub fn dump_stats(&self) {
+        let name = self.name.as_ref().map(|n| n.as_slice()).unwrap_or("<unnamed>");
+        rterrln!("task '{}': {}", name, self.current_stats().to_str());
+    }
+
     // New utility functions for homes.
 
     pub fn is_home_no_tls(&self, sched: &~Scheduler) -> bool {
@@ -327,10 +386,80 @@ impl Task {
     }
 }
 
+impl LocalStorage {
+    pub fn new() -> LocalStorage {
+        LocalStorage {
+            map: None,
+            destroyed: false
+        }
+    }
+}
+
+impl TaskStats {
+    pub fn new() -> TaskStats {
+        TaskStats {
+            cpu_time_ns: 0,
+            times_scheduled: 0,
+            blocked_time_ns: 0,
+            running_since: None,
+            descheduled_since: None
+        }
+    }
+
+    /// The task was just context switched to.
+    pub fn note_resumed(&mut self, now: u64) {
+        self.note_runnable(now);
+        self.times_scheduled += 1;
+        self.running_since = Some(now);
+    }
+
+    /// The task was just context switched away from.
+    pub fn note_descheduled(&mut self, now: u64) {
+        match self.running_since.take() {
+            Some(start) => self.cpu_time_ns += now - start,
+            None => ()
+        }
+        self.descheduled_since = Some(now);
+    }
+
+    /// The task was placed back on a work queue.
+    pub fn note_runnable(&mut self, now: u64) {
+        match self.descheduled_since.take() {
+            Some(start) => self.blocked_time_ns += now - start,
+            None => ()
+        }
+    }
+
+    /// A copy of the statistics with the time slice currently in
+    /// progress, if any, accounted for.
+    pub fn snapshot(&self, now: u64) -> TaskStats {
+        let mut stats = self.clone();
+        match stats.running_since {
+            Some(start) => {
+                stats.cpu_time_ns += now - start;
+                stats.running_since = Some(now);
+            }
+            None => ()
+        }
+        return stats;
+    }
+}
+
+impl ToStr for TaskStats {
+    fn to_str(&self) -> ~str {
+        format!("cpu {} ns, scheduled {} times, blocked {} ns",
+                self.cpu_time_ns, self.times_scheduled, self.blocked_time_ns)
+    }
+}
+
 impl Drop for Task {
     fn drop(&mut self) {
         rtdebug!("called drop for a task: {}", borrow::to_uint(self));
-        rtassert!(self.destroyed)
+        rtassert!(self.destroyed);
+        match self.task_type {
+            GreenTask(_) => unsafe { LIVE_TASKS.fetch_sub(1, SeqCst); },
+            SchedTask => ()
+        }
     }
 }
 
@@ -420,6 +549,13 @@ impl Coroutine {
 static UNWIND_TOKEN: uintptr_t = 839147;
 
 impl Unwinder {
+    pub fn new() -> Unwinder {
+        Unwinder {
+            unwinding: false,
+            cause: None
+        }
+    }
+
     pub fn try(&mut self, f: &fn()) {
         use unstable::raw::Closure;
 
@@ -471,7 +607,6 @@ pub fn begin_unwind(msg: *c_char, file: *c_char, line: size_t) -> ! {
     use rt::in_green_task_context;
     use rt::task::Task;
     use rt::local::Local;
-    use rt::logging::Logger;
     use str::Str;
     use c_str::CString;
 
@@ -490,9 +625,12 @@ pub fn begin_unwind(msg: *c_char, file: *c_char, line: size_t) -> ! {
             // have been failing due to a lack of memory in the first place...
             do Local::borrow |task: &mut Task| {
                 let n = task.name.as_ref().map(|n| n.as_slice()).unwrap_or("<unnamed>");
-                format_args!(|args| { task.logger.log(args) },
-                             "task '{}' failed at '{}', {}:{}",
-                             n, msg, file, line);
+                format_args!(|args| {
+                    match task.logger {
+                        Some(ref mut logger) => logger.log(args),
+                        None => { let mut logger = StdErrLogger; logger.log(args) }
+                    }
+                }, "task '{}' failed at '{}', {}:{}", n, msg, file, line);
             }
         } else {
             rterrln!("failed in non-task context at '{}', {}:{}",
@@ -503,6 +641,8 @@ pub fn begin_unwind(msg: *c_char, file: *c_char, line: size_t) -> ! {
         if (*task).unwinder.unwinding {
             rtabort!("unwinding again");
         }
+        // This does allocate, but only once the failure has been logged.
+        (*task).unwinder.cause = Some(msg.to_owned());
         (*task).unwinder.begin_unwind();
     }
 }
@@ -646,4 +786,19 @@ mod test {
             assert_eq!(result.unwrap().recv(), Failure);
         }
     }
+
+    #[test]
+    fn runtime_stats() {
+        do run_in_newsched_task {
+            use rt;
+            use vec;
+
+            assert!(rt::stats().live_tasks >= 1);
+            let boxes = vec::from_fn(100, |i| @i);
+            assert!(rt::stats().local_heap_bytes > 0);
+            let owned = vec::from_elem(1000, 0u8);
+            assert!(rt::stats().exchange_heap_bytes >= owned.len());
+            assert_eq!(boxes.len(), owned.len() / 10);
+        }
+    }
 }
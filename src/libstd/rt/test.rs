@@ -50,10 +50,29 @@ pub fn run_in_newsched_task(f: ~fn()) {
 }
 
 pub fn run_in_newsched_task_core(f: ~fn()) {
+    run_in_newsched_task_core_(f, false, None)
+}
+
+/// Run a function in a task on a single scheduler that runs tasks in FIFO
+/// order. If a seed is given the scheduler's random decisions, such as when
+/// to yield, are made with an rng seeded with it; otherwise tasks only yield
+/// when asked to. Either way a failing test will fail the same way every
+/// time it's run.
+pub fn run_in_deterministic_task(seed: Option<u32>, f: ~fn()) {
+    let f = Cell::new(f);
+    do run_in_bare_thread {
+        run_in_newsched_task_core_(f.take(), true, seed);
+    }
+}
+
+fn run_in_newsched_task_core_(f: ~fn(), deterministic: bool, seed: Option<u32>) {
 
     use rt::sched::Shutdown;
 
     let mut sched = ~new_test_uv_sched();
+    if deterministic {
+        sched.make_deterministic(seed);
+    }
     let exit_handle = Cell::new(sched.make_handle());
 
     let on_exit: ~fn(bool) = |exit_status| {
@@ -155,6 +174,7 @@ pub fn prepare_for_lots_of_tests() {
 pub fn run_in_mt_newsched_task(f: ~fn()) {
     use os;
     use from_str::FromStr;
+    use rt::env;
     use rt::sched::Shutdown;
     use rt::util;
 
@@ -167,7 +187,10 @@ pub fn run_in_mt_newsched_task(f: ~fn()) {
         let nthreads = match os::getenv("RUST_RT_TEST_THREADS") {
             Some(nstr) => FromStr::from_str(nstr).unwrap(),
             None => {
-                if util::limit_thread_creation_due_to_osx_and_valgrind() {
+                if env::deterministic_sched() {
+                    // A deterministic schedule needs a single scheduler.
+                    1
+                } else if util::limit_thread_creation_due_to_osx_and_valgrind() {
                     1
                 } else {
                     // Using more threads than cores in test code
@@ -261,10 +284,14 @@ pub fn spawntask_later(f: ~fn()) {
 }
 
 pub fn spawntask_random(f: ~fn()) {
-    use rand::{Rand, rng};
+    use rand::Rand;
+    use rt::local::Local;
 
-    let mut rng = rng();
-    let run_now: bool = Rand::rand(&mut rng);
+    // Use the scheduler's rng so that deterministic schedulers make the
+    // same choice every time.
+    let run_now: bool = do Local::borrow |sched: &mut Scheduler| {
+        Rand::rand(&mut sched.rng)
+    };
 
     if run_now {
         spawntask(f)
@@ -388,3 +415,50 @@ pub fn stress_factor() -> uint {
         None => 1
     }
 }
+
+#[cfg(test)]
+mod bench {
+    use extra::test::BenchHarness;
+    use iter::range;
+    use option::None;
+    use at_vec;
+    use vec;
+
+    // Boxes freed as soon as they're allocated, so the same slot is reused
+    #[bench]
+    fn alloc_managed_churn(bh: &mut BenchHarness) {
+        do bh.iter {
+            for i in range(0, 1000) {
+                @i;
+            }
+        }
+    }
+
+    // Many boxes of one size alive at once
+    #[bench]
+    fn alloc_managed_many_live(bh: &mut BenchHarness) {
+        do bh.iter {
+            let _boxes = vec::from_fn(1000, |i| @i);
+        }
+    }
+
+    // Boxes of every size class, and some too big for any
+    #[bench]
+    fn alloc_managed_mixed_sizes(bh: &mut BenchHarness) {
+        do bh.iter {
+            let _boxes = vec::from_fn(200, |i| at_vec::from_elem(i * 4, 0u8));
+        }
+    }
+
+    // A managed vector outgrowing its slot over and over
+    #[bench]
+    fn realloc_managed_growing(bh: &mut BenchHarness) {
+        do bh.iter {
+            do at_vec::build(None) |push| {
+                for i in range(0u, 1000) {
+                    push(i);
+                }
+            };
+        }
+    }
+}
@@ -17,6 +17,7 @@ use os;
 use str::StrSlice;
 use unstable::atomics::{AtomicInt, INIT_ATOMIC_INT, SeqCst};
 use unstable::running_on_valgrind;
+use util;
 
 // Indicates whether we should perform expensive sanity checks, including rtassert!
 // XXX: Once the runtime matures remove the `true` below to turn off rtassert, etc.
@@ -69,13 +70,52 @@ pub fn default_sched_threads() -> uint {
     }
 }
 
+/// The current value of a high-resolution performance counter in
+/// nanoseconds since an unspecified epoch. Used by the scheduler for
+/// cheap task accounting.
+pub fn precise_time_ns() -> u64 {
+    #[fixed_stack_segment]; #[inline(never)];
+
+    unsafe {
+        let mut ns = 0u64;
+        rust_precise_time_ns(&mut ns);
+        return ns;
+    }
+
+    extern {
+        #[link_name = "precise_time_ns"]
+        fn rust_precise_time_ns(ns: &mut u64);
+    }
+}
+
 pub fn dumb_println(args: &fmt::Arguments) {
     use rt::io::native::stdio::stderr;
     use rt::io::Writer;
+    use rt::logging::write_log_file_line;
+    if write_log_file_line(args) {
+        return;
+    }
     let mut out = stderr();
     fmt::writeln(&mut out as &mut Writer, args);
 }
 
+// Called with the message when the runtime is about to abort the process.
+static mut ABORT_HOOK: Option<fn(&str)> = None;
+
+/// Register a function to be called when the runtime aborts the process,
+/// which it does on fatal errors such as a task failing while it's already
+/// unwinding. The hook runs after the error has been reported and may be used
+/// to flush logs, write a crash report or notify a supervisor. It runs in
+/// whatever state the runtime was in when it gave up, so it should do as
+/// little as possible. If it returns, the process aborts.
+///
+/// Returns the previously registered hook, if any, so that hooks can be
+/// chained. Like the runtime's other process-wide settings this isn't
+/// synchronized, and should be set once, early on.
+pub fn set_abort_hook(hook: fn(&str)) -> Option<fn(&str)> {
+    unsafe { util::replace(&mut ABORT_HOOK, Some(hook)) }
+}
+
 pub fn abort(msg: &str) -> ! {
     let msg = if !msg.is_empty() { msg } else { "aborted" };
     let hash = msg.iter().fold(0, |accum, val| accum + (val as uint) );
@@ -128,6 +168,12 @@ memory and partly incapable of presentation to others.",
     rterrln!("{}", "");
     rterrln!("fatal runtime error: {}", msg);
 
+    // Take the hook out first so that it can't recurse if it aborts too.
+    match unsafe { util::replace(&mut ABORT_HOOK, None) } {
+        Some(hook) => hook(msg),
+        None => ()
+    }
+
     abort();
 
     fn abort() -> ! {
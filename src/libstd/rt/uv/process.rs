@@ -47,6 +47,7 @@ impl Process {
                     -> Result<~[Option<UvPipeStream>], uv::UvError>
     {
         let cwd = config.cwd.map(|s| s.to_c_str());
+        let flags = if config.detach { uvll::PROCESS_DETACHED } else { 0 };
 
         extern fn on_exit(p: *uvll::uv_process_t,
                           exit_status: libc::c_int,
@@ -86,7 +87,7 @@ impl Process {
                         Some(ref cwd) => cwd.with_ref(|p| p),
                         None => ptr::null(),
                     },
-                    flags: 0,
+                    flags: flags as libc::c_uint,
                     stdio_count: stdio.len() as libc::c_int,
                     stdio: stdio.as_imm_buf(|p, _| p),
                     uid: 0,
@@ -23,6 +23,7 @@ use rt::io::IoError;
 use rt::io::net::ip::{SocketAddr, IpAddr};
 use rt::io::{standard_error, OtherIoError, SeekStyle, SeekSet, SeekCur, SeekEnd};
 use rt::io::process::ProcessConfig;
+use rt::comm::{oneshot, PortOne};
 use rt::kill::BlockedTask;
 use rt::local::Local;
 use rt::rtio::*;
@@ -49,7 +50,6 @@ use task;
                             next_test_ip4,
                             run_in_mt_newsched_task};
 #[cfg(test)] use iter::{Iterator, range};
-#[cfg(test)] use rt::comm::oneshot;
 
 // XXX we should not be calling uvll functions in here.
 
@@ -1391,6 +1391,20 @@ impl RtioTimer for UvTimer {
             self_.watcher.stop();
         }
     }
+
+    fn oneshot(&mut self, msecs: u64) -> PortOne<()> {
+        let (port, chan) = oneshot();
+        let chan = Cell::new(chan);
+        do self.home_for_io |self_| {
+            let chan = Cell::new(chan.take());
+            do self_.watcher.start(msecs, 0) |_, status| {
+                assert!(status.is_none());
+                // Called in the scheduler's context
+                chan.take().send_deferred(());
+            }
+        }
+        port
+    }
 }
 
 pub struct UvFileStream {
@@ -1604,6 +1618,50 @@ impl RtioProcess for UvProcess {
 
         self.exit_status.unwrap()
     }
+
+    fn wait_timeout(&mut self, msecs: u64) -> Option<int> {
+        do self.home_for_io |self_| {
+            if self_.exit_status.is_none() {
+                // Sleep until either the exit_cb or the timer wakes us up,
+                // whichever fires first takes the task and the other one
+                // finds nothing to do.
+                let self_ptr = ptr::to_mut_unsafe_ptr(self_);
+                let mut loop_ = self_.process.event_loop();
+                let timer = Cell::new(TimerWatcher::new(&mut loop_));
+                let scheduler: ~Scheduler = Local::take();
+                do scheduler.deschedule_running_task_and_then |_, task| {
+                    assert!(self_.descheduled.is_none());
+                    self_.descheduled = Some(task);
+                    let mut watcher = timer.take();
+                    do watcher.start(msecs, 0) |_, status| {
+                        assert!(status.is_none());
+                        match unsafe { (*self_ptr).descheduled.take() } {
+                            Some(task) => {
+                                let scheduler: ~Scheduler = Local::take();
+                                scheduler.resume_blocked_task_immediately(task);
+                            }
+                            None => {}
+                        }
+                    }
+                    timer.put_back(watcher);
+                }
+
+                let mut watcher = timer.take();
+                watcher.stop();
+                let watcher = Cell::new(watcher);
+                let scheduler: ~Scheduler = Local::take();
+                do scheduler.deschedule_running_task_and_then |_, task| {
+                    let task = Cell::new(task);
+                    do watcher.take().close {
+                        let scheduler: ~Scheduler = Local::take();
+                        scheduler.resume_blocked_task_immediately(task.take());
+                    }
+                }
+            }
+        }
+
+        self.exit_status
+    }
 }
 
 #[test]
@@ -13,13 +13,20 @@
 #[allow(missing_doc)];
 
 use cell::Cell;
-use comm::{stream, SharedChan};
+use comm::{oneshot, stream, Port, SharedChan};
 use libc::{pid_t, c_int};
 use libc;
+use os;
 use prelude::*;
-use rt::io::native::process;
+use rt::in_green_task_context;
+use rt::io::process;
+use rt::io::pipe::PipeStream;
 use rt::io;
+use native = rt::io::native::process;
+use rt::thread::Thread;
 use task;
+use unstable::sync::UnsafeArc;
+use util;
 
 /**
  * A value representing a child process.
@@ -27,22 +34,30 @@ use task;
  * The lifetime of this value is linked to the lifetime of the actual
  * process - the Process destructor calls self.finish() which waits
  * for the process to terminate.
+ *
+ * In a green task, the process is driven by the task's event loop, and
+ * waiting for it only blocks the task. Anywhere else, such as on a thread
+ * with no scheduler, there's no event loop to use, so the process is
+ * started and waited for with blocking system calls instead. That native
+ * fallback can't start a process in a group of its own (`detach`), wait
+ * with a timeout, or stream output to a port, and it doesn't raise
+ * `io_error` when the program can't be started: the process exits with a
+ * nonzero status instead.
  */
 pub struct Process {
-    priv inner: process::Process,
+    priv inner: Inner,
+}
+
+enum Inner {
+    Green(process::Process),
+    Native(native::Process),
 }
 
 /// Options that can be given when starting a Process.
 pub struct ProcessOptions<'self> {
 
-    /**
-     * If this is None then the new process will have the same initial
-     * environment as the parent process.
-     *
-     * If this is Some(vec-of-names-and-values) then the new process will
-     * have an environment containing the given named values only.
-     */
-    env: Option<~[(~str, ~str)]>,
+    /// The environment of the new process. See ProcessEnv.
+    env: ProcessEnv,
 
     /**
      * If this is None then the new process will use the same initial working
@@ -82,19 +97,92 @@ pub struct ProcessOptions<'self> {
      * and Process.error() will fail.
      */
     err_fd: Option<c_int>,
+
+    /**
+     * If this is true then the new process is started in a process group of
+     * its own, along with anything it goes on to spawn. destroy_group and
+     * force_destroy_group then reach all of them, and so does killing the
+     * process when finish_with_timeout times out.
+     */
+    detach: bool,
+}
+
+/// The environment to start a Process with.
+#[deriving(Clone)]
+pub enum ProcessEnv {
+    /// The same environment as the parent process.
+    InheritEnv,
+    /// The parent's environment, with the given variables added to it. These
+    /// replace any variables of the same name that the parent has.
+    InheritPlus(~[(~str, ~str)]),
+    /// The given variables only.
+    Exactly(~[(~str, ~str)]),
+}
+
+impl ProcessEnv {
+    // The variables to give the process, or None to inherit them.
+    fn resolve(self) -> Option<~[(~str, ~str)]> {
+        match self {
+            InheritEnv => None,
+            InheritPlus(additions) => {
+                let mut env = os::env();
+                for (key, value) in additions.move_iter() {
+                    set_var(&mut env, key, value);
+                }
+                Some(env)
+            }
+            Exactly(env) => Some(env)
+        }
+    }
+}
+
+fn set_var(env: &mut ~[(~str, ~str)], key: ~str, value: ~str) {
+    match env.iter().position(|&(ref k, _)| *k == key) {
+        Some(i) => env[i] = (key, value),
+        None => env.push((key, value))
+    }
 }
 
 impl <'self> ProcessOptions<'self> {
-    /// Return a ProcessOptions that has None in every field.
+    /// Return a ProcessOptions that has None in every field, and inherits
+    /// the parent's environment.
     pub fn new<'a>() -> ProcessOptions<'a> {
         ProcessOptions {
-            env: None,
+            env: InheritEnv,
             dir: None,
             in_fd: None,
             out_fd: None,
             err_fd: None,
+            detach: false,
         }
     }
+
+    /// Set the environment variable `key` to `value` for the new process,
+    /// keeping the rest of its environment as it is.
+    pub fn set_env(&mut self, key: &str, value: &str) {
+        let (key, value) = (key.to_owned(), value.to_owned());
+        match self.env {
+            InheritPlus(ref mut env) | Exactly(ref mut env) => {
+                set_var(env, key, value);
+                return;
+            }
+            InheritEnv => {}
+        }
+        self.env = InheritPlus(~[(key, value)]);
+    }
+
+    /// Remove the environment variable `key` from the new process's
+    /// environment, keeping the rest of it as it is.
+    pub fn unset_env(&mut self, key: &str) {
+        // A variable can't be taken away from an inherited environment, so
+        // this always leaves an exact copy of it.
+        let mut env = match util::replace(&mut self.env, InheritEnv).resolve() {
+            Some(env) => env,
+            None => os::env()
+        };
+        env.retain(|&(ref k, _)| k.as_slice() != key);
+        self.env = Exactly(env);
+    }
 }
 
 /// The output of a finished process.
@@ -110,6 +198,39 @@ pub struct ProcessOutput {
     error: ~[u8],
 }
 
+/// How a process waited on with a timeout finished.
+#[deriving(Eq)]
+pub enum ProcessExit {
+    /// The process exited by itself, with the given exit code.
+    Exited(int),
+    /// The process was still running when the timeout expired, and was
+    /// killed.
+    TimedOut,
+}
+
+/// A piece of output read from a running process.
+pub enum ProcessChunk {
+    /// Data the process wrote to stdout.
+    Stdout(~[u8]),
+    /// Data the process wrote to stderr.
+    Stderr(~[u8]),
+}
+
+// Read from a pipe until end-of-file, handing each chunk to `f` as it
+// arrives. A missing pipe reads as empty.
+fn read_chunks(pipe: Option<PipeStream>, f: &fn(~[u8])) {
+    let mut buf = [0, ..1024];
+    for pipe in pipe.move_iter() {
+        let mut pipe = pipe;
+        loop {
+            match pipe.read(buf) {
+                Some(n) => f(buf.slice_to(n).to_owned()),
+                None => break
+            }
+        }
+    }
+}
+
 impl Process {
     /**
      * Spawns a new Process.
@@ -120,16 +241,70 @@ impl Process {
      * * args - Vector of arguments to pass to the child process.
      * * options - Options to configure the environment of the process,
      *             the working directory and the standard IO streams.
+     *
+     * # Failure
+     *
+     * Raises the `io_error` condition and returns None if the program
+     * couldn't be started, for instance because it doesn't exist.
      */
-    pub fn new(prog: &str, args: &[~str], options: ProcessOptions) -> Process {
-        let ProcessOptions { env, dir, in_fd, out_fd, err_fd } = options;
-        let inner = process::Process::new(prog, args, env, dir,
-                                          in_fd, out_fd, err_fd);
-        Process { inner: inner }
+    pub fn new(prog: &str, args: &[~str], options: ProcessOptions) -> Option<Process> {
+        let ProcessOptions { env, dir, in_fd, out_fd, err_fd, detach } = options;
+
+        if !in_green_task_context() {
+            let inner = native::Process::new(prog, args, env.resolve(), dir,
+                                             in_fd, out_fd, err_fd);
+            return Some(Process { inner: Native(inner) });
+        }
+
+        // Redirected streams are inherited by the child as they are, the
+        // rest get a fresh pipe. The pipes only block the calling task.
+        fn stdio(fd: Option<c_int>, readable: bool) -> process::StdioContainer {
+            match fd {
+                Some(fd) => process::InheritFd(fd),
+                None => {
+                    let pipe = PipeStream::new().expect("failed to create a pipe");
+                    process::CreatePipe(pipe, readable, !readable)
+                }
+            }
+        }
+
+        let env = env.resolve();
+        let dir = dir.map(|dir| dir.to_str());
+        let config = process::ProcessConfig {
+            program: prog,
+            args: args,
+            env: env.as_ref().map(|env| env.as_slice()),
+            cwd: dir.as_ref().map(|dir| dir.as_slice()),
+            io: ~[stdio(in_fd, true), stdio(out_fd, false), stdio(err_fd, false)],
+            detach: detach
+        };
+        process::Process::new(config).map(|inner| Process { inner: Green(inner) })
     }
 
     /// Returns the unique id of the process
-    pub fn get_id(&self) -> pid_t { self.inner.id() }
+    pub fn get_id(&self) -> pid_t {
+        match self.inner {
+            Green(ref p) => p.id(),
+            Native(ref p) => p.id()
+        }
+    }
+
+    // Only for a green process
+    fn stream<'a>(p: &'a mut process::Process, fd: uint) -> &'a mut PipeStream {
+        match p.io[fd] {
+            Some(ref mut pipe) => pipe,
+            None => fail2!("this process's fd {} was redirected or closed", fd)
+        }
+    }
+
+    // Fails for a process started outside a green task, which can't do
+    // `what`, and otherwise returns the green process
+    fn green<'a>(&'a mut self, what: &str) -> &'a mut process::Process {
+        match self.inner {
+            Green(ref mut p) => p,
+            Native(_) => fail2!("can't {} outside a green task", what)
+        }
+    }
 
     /**
      * Returns an io::Writer that can be used to write to this Process's stdin.
@@ -137,7 +312,12 @@ impl Process {
      * Fails if there is no stdin available (it's already been removed by
      * take_input)
      */
-    pub fn input<'a>(&'a mut self) -> &'a mut io::Writer { self.inner.input() }
+    pub fn input<'a>(&'a mut self) -> &'a mut io::Writer {
+        match self.inner {
+            Green(ref mut p) => Process::stream(p, 0) as &mut io::Writer,
+            Native(ref mut p) => p.input()
+        }
+    }
 
     /**
      * Returns an io::Reader that can be used to read from this Process's stdout.
@@ -145,7 +325,12 @@ impl Process {
      * Fails if there is no stdout available (it's already been removed by
      * take_output)
      */
-    pub fn output<'a>(&'a mut self) -> &'a mut io::Reader { self.inner.output() }
+    pub fn output<'a>(&'a mut self) -> &'a mut io::Reader {
+        match self.inner {
+            Green(ref mut p) => Process::stream(p, 1) as &mut io::Reader,
+            Native(ref mut p) => p.output()
+        }
+    }
 
     /**
      * Returns an io::Reader that can be used to read from this Process's stderr.
@@ -153,18 +338,21 @@ impl Process {
      * Fails if there is no stderr available (it's already been removed by
      * take_error)
      */
-    pub fn error<'a>(&'a mut self) -> &'a mut io::Reader { self.inner.error() }
+    pub fn error<'a>(&'a mut self) -> &'a mut io::Reader {
+        match self.inner {
+            Green(ref mut p) => Process::stream(p, 2) as &mut io::Reader,
+            Native(ref mut p) => p.error()
+        }
+    }
 
     /**
      * Closes the handle to the child process's stdin.
      */
     pub fn close_input(&mut self) {
-        self.inner.take_input();
-    }
-
-    fn close_outputs(&mut self) {
-        self.inner.take_output();
-        self.inner.take_error();
+        match self.inner {
+            Green(ref mut p) => { p.io[0] = None; }
+            Native(ref mut p) => { p.take_input(); }
+        }
     }
 
     /**
@@ -173,7 +361,32 @@ impl Process {
      *
      * If the child has already been finished then the exit code is returned.
      */
-    pub fn finish(&mut self) -> int { self.inner.wait() }
+    pub fn finish(&mut self) -> int {
+        self.close_input();
+        match self.inner {
+            Green(ref mut p) => p.wait(),
+            Native(ref mut p) => p.wait()
+        }
+    }
+
+    /**
+     * Closes the handle to stdin and waits at most `msecs` milliseconds for
+     * the child process to terminate. If it's still running after that it
+     * is killed, as with force_destroy_group, and TimedOut is returned.
+     *
+     * Fails outside a green task.
+     */
+    pub fn finish_with_timeout(&mut self, msecs: u64) -> ProcessExit {
+        self.close_input();
+        let status = self.green("wait for a process with a timeout").wait_timeout(msecs);
+        match status {
+            Some(status) => Exited(status),
+            None => {
+                self.force_destroy_group();
+                TimedOut
+            }
+        }
+    }
 
     /**
      * Closes the handle to stdin, waits for the child process to terminate, and
@@ -187,57 +400,80 @@ impl Process {
      * were redirected to existing file descriptors.
      */
     pub fn finish_with_output(&mut self) -> ProcessOutput {
-        self.inner.take_input(); // close stdin
-        let output = Cell::new(self.inner.take_output());
-        let error = Cell::new(self.inner.take_error());
+        self.close_input();
+        let (output, error) = match self.inner {
+            Green(ref mut p) => (p.io[1].take(), Cell::new(p.io[2].take())),
+            Native(ref mut p) => {
+                let (outs, errs) = read_native_output(p);
+                let status = p.wait();
+                return ProcessOutput { status: status, output: outs, error: errs };
+            }
+        };
 
-        fn read_everything(r: &mut io::Reader) -> ~[u8] {
+        fn read_everything(r: Option<PipeStream>) -> ~[u8] {
             let mut ret = ~[];
-            let mut buf = [0, ..1024];
-            loop {
-                match r.read(buf) {
-                    Some(n) => { ret.push_all(buf.slice_to(n)); }
-                    None => break
-                }
+            do read_chunks(r) |data| {
+                ret.push_all_move(data);
             }
             return ret;
         }
 
-        // Spawn two entire schedulers to read both stdout and sterr
-        // in parallel so we don't deadlock while blocking on one
-        // or the other. FIXME (#2625): Surely there's a much more
-        // clever way to do this.
-        let (p, ch) = stream();
-        let ch = SharedChan::new(ch);
-        let ch_clone = ch.clone();
-        do task::spawn_sched(task::SingleThreaded) {
-            match error.take() {
-                Some(ref mut e) => ch.send((2, read_everything(*e))),
-                None => ch.send((2, ~[]))
-            }
-        }
-        do task::spawn_sched(task::SingleThreaded) {
-            match output.take() {
-                Some(ref mut e) => ch_clone.send((1, read_everything(*e))),
-                None => ch_clone.send((1, ~[]))
-            }
+        // Read stderr on another task while this one reads stdout, so that
+        // a child blocked writing to one doesn't deadlock us reading the
+        // other. Pipe reads only block the task, not the scheduler.
+        let (p, ch) = oneshot();
+        let ch = Cell::new(ch);
+        do task::spawn {
+            ch.take().send(read_everything(error.take()));
         }
+        let outs = read_everything(output);
+        let errs = p.recv();
 
         let status = self.finish();
 
-        let (errs, outs) = match (p.recv(), p.recv()) {
-            ((1, o), (2, e)) => (e, o),
-            ((2, e), (1, o)) => (e, o),
-            ((x, _), (y, _)) => {
-                fail2!("unexpected file numbers: {}, {}", x, y);
-            }
-        };
-
         return ProcessOutput {status: status,
                               output: outs,
                               error: errs};
     }
 
+    /**
+     * Starts reading the child process's stdout and stderr while it runs,
+     * sending each chunk on the returned port as soon as it has been read.
+     * Once both streams have reached end-of-file the port is closed, and
+     * `try_recv` returns None.
+     *
+     * Use this instead of finish_with_output to show a child's output as
+     * it's produced, or to avoid holding all of it in memory. Streams that
+     * were redirected to existing file descriptors, or that have already
+     * been read, produce no chunks.
+     *
+     * Fails outside a green task.
+     */
+    pub fn output_port(&mut self) -> Port<ProcessChunk> {
+        let (output, error) = {
+            let p = self.green("stream a process's output");
+            (Cell::new(p.io[1].take()), Cell::new(p.io[2].take()))
+        };
+
+        let (port, chan) = stream();
+        let chan = SharedChan::new(chan);
+        let out_chan = Cell::new(chan.clone());
+        let err_chan = Cell::new(chan);
+        do task::spawn {
+            let chan = out_chan.take();
+            do read_chunks(output.take()) |data| {
+                chan.send(Stdout(data));
+            }
+        }
+        do task::spawn {
+            let chan = err_chan.take();
+            do read_chunks(error.take()) |data| {
+                chan.send(Stderr(data));
+            }
+        }
+        return port;
+    }
+
     /**
      * Terminates the process, giving it a chance to clean itself up if
      * this is supported by the operating system.
@@ -246,7 +482,7 @@ impl Process {
      * TerminateProcess(..) will be called.
      */
     pub fn destroy(&mut self) {
-        self.inner.signal(io::process::PleaseExitSignal);
+        self.signal(process::PleaseExitSignal);
         self.finish();
     }
 
@@ -258,9 +494,84 @@ impl Process {
      * TerminateProcess(..) will be called.
      */
     pub fn force_destroy(&mut self) {
-        self.inner.signal(io::process::MustDieSignal);
+        self.signal(process::MustDieSignal);
+        self.finish();
+    }
+
+    /**
+     * As destroy, but also terminates everything in the process's process
+     * group, if it was started with `detach`. Outside a green task, where
+     * there are no process groups, this is just destroy.
+     */
+    pub fn destroy_group(&mut self) {
+        self.signal_group(process::PleaseExitSignal);
+        self.finish();
+    }
+
+    /**
+     * As force_destroy, but also kills everything in the process's process
+     * group, if it was started with `detach`. Outside a green task, where
+     * there are no process groups, this is just force_destroy.
+     */
+    pub fn force_destroy_group(&mut self) {
+        self.signal_group(process::MustDieSignal);
         self.finish();
     }
+
+    // The child may well have exited already, which isn't an error here.
+    fn signal(&mut self, signal: int) {
+        match self.inner {
+            Green(ref mut p) => {
+                do io::io_error::cond.trap(|_| ()).inside {
+                    p.signal(signal);
+                }
+            }
+            Native(ref mut p) => { p.signal(signal); }
+        }
+    }
+
+    fn signal_group(&mut self, signal: int) {
+        match self.inner {
+            Green(ref mut p) => {
+                do io::io_error::cond.trap(|_| ()).inside {
+                    p.signal_group(signal);
+                }
+            }
+            Native(ref mut p) => { p.signal(signal); }
+        }
+    }
+}
+
+// Reads all of a natively started process's stdout and stderr. There are
+// no tasks to read them in parallel with, so stderr is read on a thread of
+// its own, lest a child blocked writing to one deadlock us reading the other.
+fn read_native_output(p: &mut native::Process) -> (~[u8], ~[u8]) {
+    fn read_everything(r: Option<~io::Reader>) -> ~[u8] {
+        let mut ret = ~[];
+        let mut buf = [0, ..1024];
+        for r in r.move_iter() {
+            let mut r = r;
+            loop {
+                match r.read(buf) {
+                    Some(n) => ret.push_all(buf.slice_to(n)),
+                    None => break
+                }
+            }
+        }
+        ret
+    }
+
+    let error = Cell::new(p.take_error());
+    let (errs, errs_for_thread) = UnsafeArc::new2(None);
+    let errs_for_thread = Cell::new(errs_for_thread);
+    let thread = do Thread::start {
+        let errs = errs_for_thread.take();
+        unsafe { *errs.get() = Some(read_everything(error.take())); }
+    };
+    let outs = read_everything(p.take_output());
+    thread.join();
+    let errs = unsafe { (*errs.get()).take().unwrap_or(~[]) };
+    (outs, errs)
 }
 
 /**
@@ -274,18 +585,22 @@ impl Process {
  *
  * # Return value
  *
- * The process's exit code
+ * The process's exit code, or None if it couldn't be started, in which
+ * case the `io_error` condition is raised first
  */
-#[fixed_stack_segment] #[inline(never)]
-pub fn process_status(prog: &str, args: &[~str]) -> int {
-    let mut prog = Process::new(prog, args, ProcessOptions {
-        env: None,
+pub fn process_status(prog: &str, args: &[~str]) -> Option<int> {
+    let prog = Process::new(prog, args, ProcessOptions {
+        env: InheritEnv,
         dir: None,
-        in_fd: Some(unsafe { libc::dup(libc::STDIN_FILENO) }),
-        out_fd: Some(unsafe { libc::dup(libc::STDOUT_FILENO) }),
-        err_fd: Some(unsafe { libc::dup(libc::STDERR_FILENO) })
+        in_fd: Some(libc::STDIN_FILENO),
+        out_fd: Some(libc::STDOUT_FILENO),
+        err_fd: Some(libc::STDERR_FILENO),
+        detach: false
     });
-    prog.finish()
+    do prog.map |prog| {
+        let mut prog = prog;
+        prog.finish()
+    }
 }
 
 /**
@@ -298,11 +613,15 @@ pub fn process_status(prog: &str, args: &[~str]) -> int {
  *
  * # Return value
  *
- * The process's stdout/stderr output and exit code.
+ * The process's stdout/stderr output and exit code, or None if it couldn't
+ * be started, in which case the `io_error` condition is raised first
  */
-pub fn process_output(prog: &str, args: &[~str]) -> ProcessOutput {
-    let mut prog = Process::new(prog, args, ProcessOptions::new());
-    prog.finish_with_output()
+pub fn process_output(prog: &str, args: &[~str]) -> Option<ProcessOutput> {
+    let prog = Process::new(prog, args, ProcessOptions::new());
+    do prog.map |prog| {
+        let mut prog = prog;
+        prog.finish_with_output()
+    }
 }
 
 #[cfg(test)]
@@ -315,19 +634,55 @@ mod tests {
     use str;
     use unstable::running_on_valgrind;
     use rt::io::native::file;
-    use rt::io::{Writer, Reader};
+    use rt::io::{Writer, Reader, io_error};
 
     #[test]
     #[cfg(not(target_os="android"))]
     fn test_process_status() {
-        assert_eq!(run::process_status("false", []), 1);
-        assert_eq!(run::process_status("true", []), 0);
+        assert_eq!(run::process_status("false", []), Some(1));
+        assert_eq!(run::process_status("true", []), Some(0));
     }
     #[test]
     #[cfg(target_os="android")]
     fn test_process_status() {
-        assert_eq!(run::process_status("/system/bin/sh", [~"-c",~"false"]), 1);
-        assert_eq!(run::process_status("/system/bin/sh", [~"-c",~"true"]), 0);
+        assert_eq!(run::process_status("/system/bin/sh", [~"-c",~"false"]), Some(1));
+        assert_eq!(run::process_status("/system/bin/sh", [~"-c",~"true"]), Some(0));
+    }
+
+    #[test]
+    fn test_spawn_failure_raises_io_error() {
+        let mut raised = false;
+        let status = do io_error::cond.trap(|_| raised = true).inside {
+            run::process_status("no-such-program-to-run-for-the-test", [])
+        };
+        assert!(raised);
+        assert_eq!(status, None);
+    }
+
+    #[test]
+    #[cfg(not(target_os="android"))]
+    fn test_process_output_outside_green_task() {
+        use cell::Cell;
+        use rt::thread::Thread;
+        use unstable::sync::UnsafeArc;
+
+        // A thread of its own has no scheduler to drive rt::io::process
+        let (result, for_thread) = UnsafeArc::new2(None);
+        let for_thread = Cell::new(for_thread);
+        let thread = do Thread::start {
+            let result = for_thread.take();
+            unsafe { *result.get() = run::process_output("echo", [~"hello"]); }
+        };
+        thread.join();
+
+        let run::ProcessOutput {status, output, error} =
+            unsafe { (*result.get()).take().unwrap() };
+        assert_eq!(status, 0);
+        assert_eq!(str::from_utf8_owned(output).trim().to_owned(), ~"hello");
+        // FIXME #7224
+        if !running_on_valgrind() {
+            assert_eq!(error, ~[]);
+        }
     }
 
     #[test]
@@ -335,8 +690,8 @@ mod tests {
     fn test_process_output_output() {
 
         let run::ProcessOutput {status, output, error}
-             = run::process_output("echo", [~"hello"]);
-        let output_str = str::from_utf8(output);
+             = run::process_output("echo", [~"hello"]).unwrap();
+        let output_str = str::from_utf8_owned(output);
 
         assert_eq!(status, 0);
         assert_eq!(output_str.trim().to_owned(), ~"hello");
@@ -350,8 +705,8 @@ mod tests {
     fn test_process_output_output() {
 
         let run::ProcessOutput {status, output, error}
-             = run::process_output("/system/bin/sh", [~"-c",~"echo hello"]);
-        let output_str = str::from_utf8(output);
+             = run::process_output("/system/bin/sh", [~"-c",~"echo hello"]).unwrap();
+        let output_str = str::from_utf8_owned(output);
 
         assert_eq!(status, 0);
         assert_eq!(output_str.trim().to_owned(), ~"hello");
@@ -366,7 +721,7 @@ mod tests {
     fn test_process_output_error() {
 
         let run::ProcessOutput {status, output, error}
-             = run::process_output("mkdir", [~"."]);
+             = run::process_output("mkdir", [~"."]).unwrap();
 
         assert_eq!(status, 1);
         assert_eq!(output, ~[]);
@@ -377,7 +732,7 @@ mod tests {
     fn test_process_output_error() {
 
         let run::ProcessOutput {status, output, error}
-             = run::process_output("/system/bin/mkdir", [~"."]);
+             = run::process_output("/system/bin/mkdir", [~"."]).unwrap();
 
         assert_eq!(status, 255);
         assert_eq!(output, ~[]);
@@ -393,11 +748,12 @@ mod tests {
 
         let mut proc = run::Process::new("cat", [], run::ProcessOptions {
             dir: None,
-            env: None,
+            env: run::InheritEnv,
             in_fd: Some(pipe_in.input),
             out_fd: Some(pipe_out.out),
-            err_fd: Some(pipe_err.out)
-        });
+            err_fd: Some(pipe_err.out),
+            detach: false
+        }).unwrap();
 
         os::close(pipe_in.input);
         os::close(pipe_out.out);
@@ -433,21 +789,21 @@ mod tests {
     #[test]
     #[cfg(not(target_os="android"))]
     fn test_finish_once() {
-        let mut prog = run::Process::new("false", [], run::ProcessOptions::new());
+        let mut prog = run::Process::new("false", [], run::ProcessOptions::new()).unwrap();
         assert_eq!(prog.finish(), 1);
     }
     #[test]
     #[cfg(target_os="android")]
     fn test_finish_once() {
         let mut prog = run::Process::new("/system/bin/sh", [~"-c",~"false"],
-                                         run::ProcessOptions::new());
+                                         run::ProcessOptions::new()).unwrap();
         assert_eq!(prog.finish(), 1);
     }
 
     #[test]
     #[cfg(not(target_os="android"))]
     fn test_finish_twice() {
-        let mut prog = run::Process::new("false", [], run::ProcessOptions::new());
+        let mut prog = run::Process::new("false", [], run::ProcessOptions::new()).unwrap();
         assert_eq!(prog.finish(), 1);
         assert_eq!(prog.finish(), 1);
     }
@@ -455,7 +811,7 @@ mod tests {
     #[cfg(target_os="android")]
     fn test_finish_twice() {
         let mut prog = run::Process::new("/system/bin/sh", [~"-c",~"false"],
-                                         run::ProcessOptions::new());
+                                         run::ProcessOptions::new()).unwrap();
         assert_eq!(prog.finish(), 1);
         assert_eq!(prog.finish(), 1);
     }
@@ -464,10 +820,10 @@ mod tests {
     #[cfg(not(target_os="android"))]
     fn test_finish_with_output_once() {
 
-        let mut prog = run::Process::new("echo", [~"hello"], run::ProcessOptions::new());
+        let mut prog = run::Process::new("echo", [~"hello"], run::ProcessOptions::new()).unwrap();
         let run::ProcessOutput {status, output, error}
             = prog.finish_with_output();
-        let output_str = str::from_utf8(output);
+        let output_str = str::from_utf8_owned(output);
 
         assert_eq!(status, 0);
         assert_eq!(output_str.trim().to_owned(), ~"hello");
@@ -481,10 +837,10 @@ mod tests {
     fn test_finish_with_output_once() {
 
         let mut prog = run::Process::new("/system/bin/sh", [~"-c",~"echo hello"],
-                                         run::ProcessOptions::new());
+                                         run::ProcessOptions::new()).unwrap();
         let run::ProcessOutput {status, output, error}
             = prog.finish_with_output();
-        let output_str = str::from_utf8(output);
+        let output_str = str::from_utf8_owned(output);
 
         assert_eq!(status, 0);
         assert_eq!(output_str.trim().to_owned(), ~"hello");
@@ -498,11 +854,11 @@ mod tests {
     #[cfg(not(target_os="android"))]
     fn test_finish_with_output_twice() {
 
-        let mut prog = run::Process::new("echo", [~"hello"], run::ProcessOptions::new());
+        let mut prog = run::Process::new("echo", [~"hello"], run::ProcessOptions::new()).unwrap();
         let run::ProcessOutput {status, output, error}
             = prog.finish_with_output();
 
-        let output_str = str::from_utf8(output);
+        let output_str = str::from_utf8_owned(output);
 
         assert_eq!(status, 0);
         assert_eq!(output_str.trim().to_owned(), ~"hello");
@@ -526,11 +882,11 @@ mod tests {
     fn test_finish_with_output_twice() {
 
         let mut prog = run::Process::new("/system/bin/sh", [~"-c",~"echo hello"],
-                                         run::ProcessOptions::new());
+                                         run::ProcessOptions::new()).unwrap();
         let run::ProcessOutput {status, output, error}
             = prog.finish_with_output();
 
-        let output_str = str::from_utf8(output);
+        let output_str = str::from_utf8_owned(output);
 
         assert_eq!(status, 0);
         assert_eq!(output_str.trim().to_owned(), ~"hello");
@@ -550,19 +906,67 @@ mod tests {
         }
     }
 
+    #[test]
+    #[cfg(unix,not(target_os="android"))]
+    fn test_finish_with_timeout() {
+        let mut prog = run::Process::new("true", [], run::ProcessOptions::new()).unwrap();
+        assert_eq!(prog.finish_with_timeout(10000), run::Exited(0));
+
+        let mut prog = run::Process::new("sleep", [~"1000"], run::ProcessOptions::new()).unwrap();
+        assert_eq!(prog.finish_with_timeout(10), run::TimedOut);
+        assert!(prog.finish() != 0);
+    }
+
+    #[test]
+    #[cfg(unix,not(target_os="android"))]
+    fn test_timeout_kills_group() {
+        // The shell forks a grandchild which holds on to the stdout pipe. If
+        // it survived the timeout, reading stdout would never finish.
+        let mut prog = run::Process::new("sh", [~"-c", ~"sleep 1000; true"],
+                                         run::ProcessOptions {
+            detach: true,
+            .. run::ProcessOptions::new()
+        }).unwrap();
+        assert_eq!(prog.finish_with_timeout(10), run::TimedOut);
+        assert_eq!(prog.finish_with_output().output, ~[]);
+    }
+
+    #[test]
+    #[cfg(unix,not(target_os="android"))]
+    fn test_output_port() {
+        use comm::GenericPort;
+
+        let mut prog = run::Process::new("sh", [~"-c", ~"echo out; echo err >&2"],
+                                         run::ProcessOptions::new()).unwrap();
+        let port = prog.output_port();
+        let mut output = ~[];
+        let mut error = ~[];
+        loop {
+            match port.try_recv() {
+                Some(run::Stdout(data)) => output.push_all_move(data),
+                Some(run::Stderr(data)) => error.push_all_move(data),
+                None => break
+            }
+        }
+
+        assert_eq!(prog.finish(), 0);
+        assert_eq!(str::from_utf8_owned(output), ~"out\n");
+        assert_eq!(str::from_utf8_owned(error), ~"err\n");
+    }
+
     #[cfg(unix,not(target_os="android"))]
     fn run_pwd(dir: Option<&Path>) -> run::Process {
         run::Process::new("pwd", [], run::ProcessOptions {
             dir: dir,
             .. run::ProcessOptions::new()
-        })
+        }).unwrap()
     }
     #[cfg(unix,target_os="android")]
     fn run_pwd(dir: Option<&Path>) -> run::Process {
         run::Process::new("/system/bin/sh", [~"-c",~"pwd"], run::ProcessOptions {
             dir: dir,
             .. run::ProcessOptions::new()
-        })
+        }).unwrap()
     }
 
     #[cfg(windows)]
@@ -570,14 +974,14 @@ mod tests {
         run::Process::new("cmd", [~"/c", ~"cd"], run::ProcessOptions {
             dir: dir,
             .. run::ProcessOptions::new()
-        })
+        }).unwrap()
     }
 
     #[test]
     fn test_keep_current_working_dir() {
         let mut prog = run_pwd(None);
 
-        let output = str::from_utf8(prog.finish_with_output().output);
+        let output = str::from_utf8_owned(prog.finish_with_output().output);
         let parent_dir = os::getcwd().normalize();
         let child_dir = Path(output.trim()).normalize();
 
@@ -595,7 +999,7 @@ mod tests {
         let parent_dir = os::getcwd().dir_path().normalize();
         let mut prog = run_pwd(Some(&parent_dir));
 
-        let output = str::from_utf8(prog.finish_with_output().output);
+        let output = str::from_utf8_owned(prog.finish_with_output().output);
         let child_dir = Path(output.trim()).normalize();
 
         let parent_stat = parent_dir.stat().unwrap();
@@ -606,26 +1010,26 @@ mod tests {
     }
 
     #[cfg(unix,not(target_os="android"))]
-    fn run_env(env: Option<~[(~str, ~str)]>) -> run::Process {
+    fn run_env(env: run::ProcessEnv) -> run::Process {
         run::Process::new("env", [], run::ProcessOptions {
             env: env,
             .. run::ProcessOptions::new()
-        })
+        }).unwrap()
     }
     #[cfg(unix,target_os="android")]
-    fn run_env(env: Option<~[(~str, ~str)]>) -> run::Process {
+    fn run_env(env: run::ProcessEnv) -> run::Process {
         run::Process::new("/system/bin/sh", [~"-c",~"set"], run::ProcessOptions {
             env: env,
             .. run::ProcessOptions::new()
-        })
+        }).unwrap()
     }
 
     #[cfg(windows)]
-    fn run_env(env: Option<~[(~str, ~str)]>) -> run::Process {
+    fn run_env(env: run::ProcessEnv) -> run::Process {
         run::Process::new("cmd", [~"/c", ~"set"], run::ProcessOptions {
             env: env,
             .. run::ProcessOptions::new()
-        })
+        }).unwrap()
     }
 
     #[test]
@@ -633,8 +1037,8 @@ mod tests {
     fn test_inherit_env() {
         if running_on_valgrind() { return; }
 
-        let mut prog = run_env(None);
-        let output = str::from_utf8(prog.finish_with_output().output);
+        let mut prog = run_env(run::InheritEnv);
+        let output = str::from_utf8_owned(prog.finish_with_output().output);
 
         let r = os::env();
         for &(ref k, ref v) in r.iter() {
@@ -647,8 +1051,8 @@ mod tests {
     fn test_inherit_env() {
         if running_on_valgrind() { return; }
 
-        let mut prog = run_env(None);
-        let output = str::from_utf8(prog.finish_with_output().output);
+        let mut prog = run_env(run::InheritEnv);
+        let output = str::from_utf8_owned(prog.finish_with_output().output);
 
         let r = os::env();
         for &(ref k, ref v) in r.iter() {
@@ -663,12 +1067,27 @@ mod tests {
     #[test]
     fn test_add_to_env() {
 
-        let mut new_env = os::env();
-        new_env.push((~"RUN_TEST_NEW_ENV", ~"123"));
-
-        let mut prog = run_env(Some(new_env));
-        let output = str::from_utf8(prog.finish_with_output().output);
+        let mut prog = run_env(run::InheritPlus(~[(~"RUN_TEST_NEW_ENV", ~"123")]));
+        let output = str::from_utf8_owned(prog.finish_with_output().output);
 
         assert!(output.contains("RUN_TEST_NEW_ENV=123"));
     }
+
+    #[test]
+    fn test_set_and_unset_env() {
+        let mut options = run::ProcessOptions::new();
+        options.set_env("RUN_TEST_NEW_ENV", "123");
+        options.set_env("RUN_TEST_NEW_ENV", "456");
+        options.set_env("RUN_TEST_OTHER_ENV", "789");
+        options.unset_env("RUN_TEST_OTHER_ENV");
+
+        match options.env {
+            run::Exactly(ref env) => {
+                assert_eq!(env.len(), os::env().len() + 1);
+                assert!(env.contains(&(~"RUN_TEST_NEW_ENV", ~"456")));
+                assert!(!env.iter().any(|&(ref k, _)| *k == ~"RUN_TEST_OTHER_ENV"));
+            }
+            _ => fail2!("unset_env should have copied the environment")
+        }
+    }
 }
@@ -13,14 +13,20 @@ use comm;
 use container::Container;
 use iter::{Iterator, DoubleEndedIterator};
 use option::*;
+use rt::comm::PortOne;
+use rt::io::timer::Timer;
 // use either::{Either, Left, Right};
-// use rt::kill::BlockedTask;
 use rt::local::Local;
-use rt::shouldnt_be_public::{EventLoop, Scheduler, SelectInner, SelectPortInner};
+use rt::shouldnt_be_public::{BlockedTask, EventLoop, Scheduler, SelectPortInner};
 use task;
 use unstable::finally::Finally;
 use vec::{OwnedVector, MutableVector};
 
+// What `select_any` takes the ports as, and so what `select!` casts them
+// to. The methods are only for the runtime's use.
+#[doc(hidden)]
+pub use rt::shouldnt_be_public::SelectInner;
+
 /// Trait for message-passing primitives that can be select()ed on.
 pub trait Select : SelectInner { }
 
@@ -32,6 +38,14 @@ pub trait SelectPort<T> : SelectPortInner<T> { }
 /// Receive a message from any one of many ports at once. Returns the index of the
 /// port whose data is ready. (If multiple are ready, returns the lowest index.)
 pub fn select<A: Select>(ports: &mut [A]) -> uint {
+    let mut ports: ~[&mut SelectInner] =
+        ports.mut_iter().map(|p| p as &mut SelectInner).collect();
+    select_any(ports)
+}
+
+/// Like `select`, but the ports may carry different types of messages.
+/// This is what the `select!` macro expands to.
+pub fn select_any(ports: &mut [&mut SelectInner]) -> uint {
     if ports.is_empty() {
         fail2!("can't select on an empty list");
     }
@@ -95,6 +109,49 @@ pub fn select<A: Select>(ports: &mut [A]) -> uint {
     return ready_index;
 }
 
+/// A port that receives `()` once some milliseconds have passed, made by
+/// `timeout`.
+pub struct Timeout {
+    priv port: PortOne<()>,
+    // Owned by the port so that dropping it stops the timer
+    priv timer: Timer
+}
+
+/// A port that receives `()` once `msecs` milliseconds have passed, such as
+/// for the timeout arm of `select!`.
+///
+/// The timer runs on the scheduler's event loop rather than in a task of
+/// its own, and is stopped when the port is dropped.
+pub fn timeout(msecs: u64) -> Timeout {
+    let mut timer = Timer::new().expect("select::timeout: could not create a Timer");
+    let port = timer.oneshot(msecs);
+    Timeout { port: port, timer: timer }
+}
+
+impl Timeout {
+    /// Waits for the time to pass.
+    pub fn recv(self) {
+        let Timeout { port, timer: _timer } = self;
+        port.recv()
+    }
+}
+
+impl SelectInner for Timeout {
+    fn optimistic_check(&mut self) -> bool {
+        self.port.optimistic_check()
+    }
+
+    fn block_on(&mut self, sched: &mut Scheduler, task: BlockedTask) -> bool {
+        self.port.block_on(sched, task)
+    }
+
+    fn unblock_from(&mut self) -> bool {
+        self.port.unblock_from()
+    }
+}
+
+impl Select for Timeout { }
+
 /* FIXME(#5121, #7914) This all should be legal, but rust is not clever enough yet.
 
 impl <'self> Select for &'self mut Select {
@@ -225,6 +282,45 @@ mod test {
         }
     }
 
+    #[test]
+    fn select_macro() {
+        use comm;
+        use comm::{GenericChan, GenericPort};
+
+        do run_in_newsched_task {
+            let (mut numbers, numbers_chan) = comm::stream::<int>();
+            let (mut names, names_chan) = comm::oneshot::<~str>();
+            names_chan.send(~"name");
+            let got = select!(
+                numbers -> _ => false,
+                names -> name => name == ~"name"
+            );
+            assert!(got);
+
+            numbers_chan.send(5);
+            let got = select!(
+                timeout(1000) => 0,
+                numbers -> n => n
+            );
+            assert_eq!(got, 5);
+
+            let got = select!(
+                timeout(1) => true,
+                numbers -> _ => false
+            );
+            assert!(got);
+        }
+    }
+
+    #[test]
+    fn timeout_stops_when_dropped() {
+        // If the timer kept running, the scheduler would wait an hour for
+        // it before it could shut down
+        do run_in_newsched_task {
+            let _timeout = timeout(60 * 60 * 1000);
+        }
+    }
+
     #[test]
     fn select_unkillable() {
         do run_in_newsched_task {
@@ -168,6 +168,7 @@ pub mod trie;
 pub mod task;
 pub mod comm;
 pub mod select;
+pub mod sync;
 pub mod local_data;
 
 
@@ -224,4 +225,6 @@ mod std {
     pub use fmt;
     pub use to_bytes;
     pub use logging;
+    pub use rt;
+    pub use select;
 }
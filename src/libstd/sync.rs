@@ -0,0 +1,598 @@
+// Copyright 2013 The Rust Project Developers. See the COPYRIGHT
+// file at the top-level directory of this distribution and at
+// http://rust-lang.org/COPYRIGHT.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+/*!
+ * Locks and barriers for tasks.
+ *
+ * A task that has to wait for one of these is descheduled, so the other
+ * tasks on its scheduler keep running, unlike with a lock that blocks the
+ * whole OS thread. Waiting tasks queue up in order, and the task that
+ * releases a lock hands it straight to the next one in the queue.
+ *
+ * While a task waits it can't be killed by linked failure; a kill takes
+ * effect once it has the lock, or has passed the barrier.
+ *
+ * `Once` runs a function exactly once however many tasks, on however many
+ * schedulers, try to, and can live in a static for global initialization.
+ *
+ * # Example
+ *
+ * ```
+ * let m = Mutex::new();
+ * let m2 = m.clone();
+ * do spawn {
+ *     do m2.lock { update_shared_state() }
+ * }
+ * do m.lock { read_shared_state() }
+ * ```
+ */
+
+use cast;
+use cell::Cell;
+use clone::Clone;
+use container::Container;
+use iter::Iterator;
+use kinds::Send;
+use option::{Some, None};
+use rt::in_green_task_context;
+use rt::local::Local;
+use rt::shouldnt_be_public::{Scheduler, BlockedTask};
+use task;
+use unstable::atomics::{AtomicUint, AtomicFlag, INIT_ATOMIC_UINT, INIT_ATOMIC_FLAG};
+use unstable::atomics::{Acquire, Release, Relaxed, SeqCst};
+use unstable::finally::Finally;
+use unstable::sync::{UnsafeArc, LittleLock};
+use util;
+use vec::OwnedVector;
+
+// The state of a lock or barrier, shared by all its handles. The LittleLock
+// only guards the state for a moment at a time and is never held while a
+// task is descheduled. Queued tasks are kept as BlockedTask::cast_to_uint
+// gives them, as in a pipe's state flag.
+struct Shared<S> {
+    lock: LittleLock,
+    state: S
+}
+
+fn new_shared<S: Send>(state: S) -> UnsafeArc<Shared<S>> {
+    UnsafeArc::new(Shared { lock: LittleLock::new(), state: state })
+}
+
+fn access<S: Send, U>(shared: &UnsafeArc<Shared<S>>, f: &fn(&mut S) -> U) -> U {
+    unsafe {
+        let shared = shared.get();
+        do (*shared).lock.lock { f(&mut (*shared).state) }
+    }
+}
+
+// Deschedules the running task and passes it to `enqueue`, which queues it
+// to be woken once what it waits for is its. `enqueue` returns the tasks
+// that can run now, which includes this one if what it waits for was
+// released meanwhile. Waiting is unkillable so that whoever wakes the task
+// can rely on it taking what it's given.
+fn block(enqueue: &fn(uint) -> ~[uint]) {
+    if !in_green_task_context() {
+        fail2!("std::sync primitives can only be waited on by green tasks");
+    }
+    do task::unkillable {
+        let sched: ~Scheduler = Local::take();
+        do sched.deschedule_running_task_and_then |sched, task| {
+            let runnable = enqueue(unsafe { task.cast_to_uint() });
+            for task in runnable.move_iter() {
+                sched.enqueue_blocked_task(unsafe { BlockedTask::cast_from_uint(task) });
+            }
+        }
+    }
+}
+
+// Makes tasks queued by `block` runnable again. Only for task context;
+// `block` wakes tasks itself.
+fn wake(tasks: ~[uint]) {
+    for task in tasks.move_iter() {
+        let task = Cell::new(unsafe { BlockedTask::cast_from_uint(task) });
+        do Local::borrow |sched: &mut Scheduler| {
+            sched.enqueue_blocked_task(task.take());
+        }
+    }
+}
+
+struct MutexState {
+    locked: bool,
+    waiting: ~[uint]
+}
+
+/// A lock for mutual exclusion between tasks. Clones are handles to the
+/// same lock.
+pub struct Mutex {
+    priv shared: UnsafeArc<Shared<MutexState>>
+}
+
+impl Mutex {
+    /// Create an unlocked mutex
+    pub fn new() -> Mutex {
+        Mutex { shared: new_shared(MutexState { locked: false, waiting: ~[] }) }
+    }
+
+    /// Run `blk` while holding the lock, first waiting for it if another
+    /// task has it. The lock is released even if `blk` fails.
+    pub fn lock<U>(&self, blk: &fn() -> U) -> U {
+        let got_it = do access(&self.shared) |s| {
+            if s.locked {
+                false
+            } else {
+                s.locked = true;
+                true
+            }
+        };
+        if !got_it {
+            do block |task| {
+                do access(&self.shared) |s| {
+                    if s.locked {
+                        s.waiting.push(task);
+                        ~[]
+                    } else {
+                        s.locked = true;
+                        ~[task]
+                    }
+                }
+            }
+        }
+        do blk.finally {
+            self.unlock();
+        }
+    }
+
+    fn unlock(&self) {
+        let next = do access(&self.shared) |s| {
+            match s.waiting.shift_opt() {
+                // Stays locked, now on behalf of the next task
+                Some(task) => ~[task],
+                None => { s.locked = false; ~[] }
+            }
+        };
+        wake(next);
+    }
+}
+
+impl Clone for Mutex {
+    fn clone(&self) -> Mutex {
+        Mutex { shared: self.shared.clone() }
+    }
+}
+
+enum Access {
+    Read,
+    Write
+}
+
+struct RWLockState {
+    readers: uint,
+    writing: bool,
+    waiting: ~[(uint, Access)]
+}
+
+impl RWLockState {
+    // Takes the lock for the tasks at the front of the queue that can have
+    // it now: one writer, or every reader up to the next writer. Returns
+    // the tasks to wake.
+    fn grant(&mut self) -> ~[uint] {
+        let mut granted = ~[];
+        while !self.writing && !self.waiting.is_empty() {
+            match self.waiting[0] {
+                (_, Write) if self.readers > 0 => break,
+                (_, Write) => self.writing = true,
+                (_, Read) => self.readers += 1
+            }
+            let (task, _) = self.waiting.shift();
+            granted.push(task);
+        }
+        granted
+    }
+}
+
+/// A lock that lets many tasks read at once, or one task write. Readers
+/// that come after a waiting writer wait for it, so writers aren't starved.
+/// Clones are handles to the same lock.
+pub struct RWLock {
+    priv shared: UnsafeArc<Shared<RWLockState>>
+}
+
+impl RWLock {
+    /// Create an unlocked reader-writer lock
+    pub fn new() -> RWLock {
+        RWLock {
+            shared: new_shared(RWLockState { readers: 0, writing: false, waiting: ~[] })
+        }
+    }
+
+    /// Run `blk` with read access, alongside any other readers
+    pub fn read<U>(&self, blk: &fn() -> U) -> U {
+        self.acquire(Read);
+        do blk.finally {
+            self.release(Read);
+        }
+    }
+
+    /// Run `blk` with write access, while no other task has the lock
+    pub fn write<U>(&self, blk: &fn() -> U) -> U {
+        self.acquire(Write);
+        do blk.finally {
+            self.release(Write);
+        }
+    }
+
+    fn acquire(&self, access_kind: Access) {
+        let got_it = do access(&self.shared) |s| {
+            if s.writing || !s.waiting.is_empty() {
+                false
+            } else {
+                match access_kind {
+                    Read => { s.readers += 1; true }
+                    Write if s.readers == 0 => { s.writing = true; true }
+                    Write => false
+                }
+            }
+        };
+        if !got_it {
+            do block |task| {
+                do access(&self.shared) |s| {
+                    // If the lock was released meanwhile, this task may be
+                    // granted it straight away
+                    s.waiting.push((task, access_kind));
+                    s.grant()
+                }
+            }
+        }
+    }
+
+    fn release(&self, access_kind: Access) {
+        let next = do access(&self.shared) |s| {
+            match access_kind {
+                Read => s.readers -= 1,
+                Write => s.writing = false
+            }
+            s.grant()
+        };
+        wake(next);
+    }
+}
+
+impl Clone for RWLock {
+    fn clone(&self) -> RWLock {
+        RWLock { shared: self.shared.clone() }
+    }
+}
+
+struct BarrierState {
+    parties: uint,
+    waiting: ~[uint]
+}
+
+/// Lets a fixed number of tasks wait for each other: each call to `wait`
+/// blocks until that many tasks have called it, then they all go on. The
+/// barrier can then be used again. Clones are handles to the same barrier.
+pub struct Barrier {
+    priv shared: UnsafeArc<Shared<BarrierState>>
+}
+
+impl Barrier {
+    /// Create a barrier for `parties` tasks
+    pub fn new(parties: uint) -> Barrier {
+        assert!(parties > 0);
+        Barrier { shared: new_shared(BarrierState { parties: parties, waiting: ~[] }) }
+    }
+
+    /// Wait until all the parties have called `wait`. Returns true in
+    /// exactly one of them, the last to arrive.
+    pub fn wait(&self) -> bool {
+        // Checking and taking the others must be one step, or another task
+        // could find the same full queue and also go through
+        let waiting = do access(&self.shared) |s| {
+            if s.waiting.len() + 1 == s.parties {
+                Some(util::replace(&mut s.waiting, ~[]))
+            } else {
+                None
+            }
+        };
+        match waiting {
+            Some(waiting) => {
+                wake(waiting);
+                return true;
+            }
+            None => ()
+        }
+        let mut last = false;
+        do block |task| {
+            do access(&self.shared) |s| {
+                if s.waiting.len() + 1 == s.parties {
+                    // The others arrived while this task was descheduling
+                    last = true;
+                    let mut runnable = util::replace(&mut s.waiting, ~[]);
+                    runnable.push(task);
+                    runnable
+                } else {
+                    s.waiting.push(task);
+                    ~[]
+                }
+            }
+        }
+        last
+    }
+}
+
+impl Clone for Barrier {
+    fn clone(&self) -> Barrier {
+        Barrier { shared: self.shared.clone() }
+    }
+}
+
+static INCOMPLETE: uint = 0;
+static RUNNING: uint = 1;
+static COMPLETE: uint = 2;
+
+/// Runs a function once, for one-time initialization. Being made of
+/// atomics only, it can be a `static mut` initialized to `ONCE_INIT`:
+///
+/// ```
+/// static mut INIT: Once = ONCE_INIT;
+///
+/// unsafe { INIT.doit(|| init_library()) }
+/// ```
+pub struct Once {
+    priv state: AtomicUint,
+    // A spinlock guarding `waiters`, held only to push or take them
+    priv lock: AtomicFlag,
+    // The queued tasks as a transmuted ~~[uint], or 0 if there are none
+    priv waiters: AtomicUint
+}
+
+/// A `Once` that hasn't run yet
+pub static ONCE_INIT: Once = Once {
+    state: INIT_ATOMIC_UINT,
+    lock: INIT_ATOMIC_FLAG,
+    waiters: INIT_ATOMIC_UINT
+};
+
+impl Once {
+    /// Run `f` if no call to `doit` has run its function yet. A task that
+    /// calls this while another task runs its function waits until it's
+    /// done, so when `doit` returns the function has run. If the function
+    /// fails, the next caller, or one of those waiting, runs its own.
+    pub fn doit(&mut self, f: &fn()) {
+        loop {
+            match self.state.compare_and_swap(INCOMPLETE, RUNNING, SeqCst) {
+                COMPLETE => return,
+                INCOMPLETE => {
+                    do f.finally {
+                        let failed = in_green_task_context() && task::failing();
+                        self.state.store(if failed { INCOMPLETE } else { COMPLETE }, SeqCst);
+                        let waiters = do self.with_waiters |w, _| { util::replace(w, ~[]) };
+                        wake(waiters);
+                    }
+                    return;
+                }
+                _ => {
+                    do block |task| {
+                        do self.with_waiters |w, state| {
+                            // Finished before this task could queue
+                            if state == RUNNING {
+                                w.push(task);
+                                ~[]
+                            } else {
+                                ~[task]
+                            }
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    // Runs `f` on the queued tasks and the current state, with the lock held
+    fn with_waiters<U>(&mut self, f: &fn(&mut ~[uint], uint) -> U) -> U {
+        while self.lock.test_and_set(Acquire) { }
+        unsafe {
+            let ptr = self.waiters.load(Relaxed);
+            let mut waiters: ~~[uint] = if ptr == 0 { ~~[] } else { cast::transmute(ptr) };
+            let result = f(&mut *waiters, self.state.load(SeqCst));
+            let ptr = if waiters.is_empty() { 0 } else { cast::transmute(waiters) };
+            self.waiters.store(ptr, Relaxed);
+            self.lock.clear(Release);
+            result
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Mutex, RWLock, Barrier, Once, ONCE_INIT};
+    use cell::Cell;
+    use comm;
+    use comm::{GenericChan, GenericPort, Peekable};
+    use iter::range;
+    use rt::test::{run_in_newsched_task, run_in_mt_newsched_task};
+    use task;
+    use unstable::sync::UnsafeArc;
+
+    #[test]
+    fn mutex_excludes() {
+        do run_in_newsched_task {
+            let m = Mutex::new();
+            let count = UnsafeArc::new(0);
+            let (port, chan) = comm::stream();
+            let chan = comm::SharedChan::new(chan);
+            for _ in range(0, 10) {
+                let (m, count, chan) = (m.clone(), count.clone(), chan.clone());
+                do task::spawn {
+                    do m.lock {
+                        let before = unsafe { *count.get() };
+                        // Let the others try to get in
+                        task::deschedule();
+                        unsafe { *count.get() = before + 1; }
+                    }
+                    chan.send(());
+                }
+            }
+            for _ in range(0, 10) { port.recv(); }
+            assert_eq!(unsafe { *count.get() }, 10);
+        }
+    }
+
+    #[test]
+    fn mutex_released_on_failure() {
+        do run_in_newsched_task {
+            let m = Mutex::new();
+            let m2 = m.clone();
+            let result = do task::try {
+                do m2.lock { fail2!() }
+            };
+            assert!(result.is_err());
+            do m.lock { }
+        }
+    }
+
+    #[test]
+    fn rwlock_readers_share() {
+        do run_in_newsched_task {
+            let l = RWLock::new();
+            let (port, chan) = comm::stream();
+            let reader = Cell::new((l.clone(), chan));
+            do l.read {
+                // Only gets in while this task reads too
+                do task::spawn {
+                    let (l, chan) = reader.take();
+                    do l.read { chan.send(()); }
+                }
+                port.recv();
+            }
+        }
+    }
+
+    #[test]
+    fn rwlock_writer_excludes() {
+        do run_in_newsched_task {
+            let l = RWLock::new();
+            let value = UnsafeArc::new(0);
+            let (port, chan) = comm::stream();
+            let chan = comm::SharedChan::new(chan);
+            for i in range(0, 6) {
+                let (l, value, chan) = (l.clone(), value.clone(), chan.clone());
+                do task::spawn {
+                    if i % 2 == 0 {
+                        do l.write {
+                            let before = unsafe { *value.get() };
+                            task::deschedule();
+                            unsafe { *value.get() = before + 1; }
+                        }
+                    } else {
+                        do l.read {
+                            let before = unsafe { *value.get() };
+                            task::deschedule();
+                            assert_eq!(unsafe { *value.get() }, before);
+                        }
+                    }
+                    chan.send(());
+                }
+            }
+            for _ in range(0, 6) { port.recv(); }
+            assert_eq!(unsafe { *value.get() }, 3);
+        }
+    }
+
+    #[test]
+    fn barrier() {
+        do run_in_newsched_task {
+            let b = Barrier::new(5);
+            let (port, chan) = comm::stream();
+            let chan = comm::SharedChan::new(chan);
+            for _ in range(0, 4) {
+                let (b, chan) = (b.clone(), chan.clone());
+                do task::spawn {
+                    chan.send(b.wait());
+                }
+            }
+            // None of the others can have gone through yet
+            assert!(!port.peek());
+            let mut leaders = if b.wait() { 1 } else { 0 };
+            for _ in range(0, 4) {
+                if port.recv() { leaders += 1; }
+            }
+            assert_eq!(leaders, 1);
+        }
+    }
+
+    #[test]
+    fn barrier_on_many_schedulers() {
+        static PARTIES: uint = 8;
+        static ROUNDS: uint = 100;
+
+        do run_in_mt_newsched_task {
+            let b = Barrier::new(PARTIES);
+            let (port, chan) = comm::stream();
+            let chan = comm::SharedChan::new(chan);
+            for _ in range(0, PARTIES) {
+                let (b, chan) = (b.clone(), chan.clone());
+                do task::spawn {
+                    let mut leaders = 0;
+                    for _ in range(0, ROUNDS) {
+                        if b.wait() { leaders += 1; }
+                    }
+                    chan.send(leaders);
+                }
+            }
+            // Exactly one leader per round
+            let mut leaders = 0;
+            for _ in range(0, PARTIES) { leaders += port.recv(); }
+            assert_eq!(leaders, ROUNDS);
+        }
+    }
+
+    #[test]
+    fn once_runs_once() {
+        static mut ONCE: Once = ONCE_INIT;
+        static mut RUNS: uint = 0;
+
+        do run_in_newsched_task {
+            let (port, chan) = comm::stream();
+            let chan = comm::SharedChan::new(chan);
+            for _ in range(0, 5) {
+                let chan = chan.clone();
+                do task::spawn {
+                    unsafe {
+                        do ONCE.doit {
+                            // Let the other tasks find it running
+                            task::deschedule();
+                            RUNS += 1;
+                        }
+                        chan.send(RUNS);
+                    }
+                }
+            }
+            for _ in range(0, 5) {
+                assert_eq!(port.recv(), 1);
+            }
+        }
+    }
+
+    #[test]
+    fn once_retries_after_failure() {
+        do run_in_newsched_task {
+            let mut once = ONCE_INIT;
+            let mut ran = false;
+            let result = do task::try_block {
+                do once.doit { fail2!() }
+            };
+            assert!(result.is_err());
+            do once.doit { ran = true; }
+            assert!(ran);
+            do once.doit { fail2!() }
+        }
+    }
+}
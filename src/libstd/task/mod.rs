@@ -59,11 +59,17 @@ use cell::Cell;
 use comm::{stream, Chan, GenericChan, GenericPort, Port};
 use result::Result;
 use result;
+use rt::affinity;
 use rt::in_green_task_context;
 use rt::local::Local;
 use unstable::finally::Finally;
 use util;
 use send_str::{SendStr, IntoSendStr};
+use rt::shouldnt_be_public::SchedHandle;
+use unstable::sync::UnsafeArc;
+
+pub use rt::task::TaskStats;
+pub use rt::cancel::{CancelToken, cancelled};
 
 #[cfg(test)] use cast;
 #[cfg(test)] use comm::SharedChan;
@@ -111,6 +117,30 @@ pub struct SchedOpts {
     mode: SchedMode,
 }
 
+/**
+ * A handle to a scheduler, which tasks can use to migrate to it.
+ *
+ * Handles can be cloned and sent to other tasks. While any handle to a
+ * scheduler exists, that scheduler's event loop will not exit.
+ */
+#[deriving(Clone)]
+pub struct SchedulerHandle {
+    priv handle: UnsafeArc<SchedHandle>
+}
+
+impl SchedulerHandle {
+    /// An identifier for the scheduler, unique among running schedulers.
+    pub fn id(&self) -> uint {
+        unsafe { (*self.handle.get()).sched_id }
+    }
+
+    /// The CPU the scheduler's thread is pinned to, if it is. See
+    /// `rt::affinity`.
+    pub fn cpu(&self) -> Option<uint> {
+        affinity::sched_cpu(self.id())
+    }
+}
+
 /**
  * Task configuration options
  *
@@ -151,7 +181,8 @@ pub struct TaskOpts {
     notify_chan: Option<Chan<TaskResult>>,
     name: Option<SendStr>,
     sched: SchedOpts,
-    stack_size: Option<uint>
+    stack_size: Option<uint>,
+    cancel_token: Option<CancelToken>
 }
 
 /**
@@ -198,6 +229,7 @@ impl TaskBuilder {
         let gen_body = self.gen_body.take();
         let notify_chan = self.opts.notify_chan.take();
         let name = self.opts.name.take();
+        let cancel_token = self.opts.cancel_token.take();
         TaskBuilder {
             opts: TaskOpts {
                 linked: self.opts.linked,
@@ -207,7 +239,8 @@ impl TaskBuilder {
                 notify_chan: notify_chan,
                 name: name,
                 sched: self.opts.sched,
-                stack_size: self.opts.stack_size
+                stack_size: self.opts.stack_size,
+                cancel_token: cancel_token
             },
             gen_body: gen_body,
             can_not_copy: None,
@@ -300,6 +333,12 @@ impl TaskBuilder {
         self.opts.name = Some(name.into_send_str());
     }
 
+    /// Give the child task a cancellation token of its own, instead of
+    /// sharing its parent's. It passes the token on to the tasks it spawns.
+    pub fn cancel_token(&mut self, token: CancelToken) {
+        self.opts.cancel_token = Some(token);
+    }
+
     /// Configure a custom scheduler mode for the task.
     pub fn sched_mode(&mut self, mode: SchedMode) {
         self.opts.sched.mode = mode;
@@ -353,6 +392,7 @@ impl TaskBuilder {
         let gen_body = self.gen_body.take();
         let notify_chan = self.opts.notify_chan.take();
         let name = self.opts.name.take();
+        let cancel_token = self.opts.cancel_token.take();
         let x = self.consume();
         let opts = TaskOpts {
             linked: x.opts.linked,
@@ -362,7 +402,8 @@ impl TaskBuilder {
             notify_chan: notify_chan,
             name: name,
             sched: x.opts.sched,
-            stack_size: x.opts.stack_size
+            stack_size: x.opts.stack_size,
+            cancel_token: cancel_token
         };
         let f = match gen_body {
             Some(gen) => {
@@ -434,7 +475,8 @@ pub fn default_task_opts() -> TaskOpts {
         sched: SchedOpts {
             mode: DefaultScheduler,
         },
-        stack_size: None
+        stack_size: None,
+        cancel_token: None
     }
 }
 
@@ -529,6 +571,47 @@ pub fn try<T:Send>(f: ~fn() -> T) -> Result<T,()> {
 }
 
 
+/**
+ * Run a function on the current task, catching failure.
+ *
+ * Returns the function's result, or the message it failed with. Unlike
+ * `try` no new task is spawned: if `f` fails, it unwinds only as far as
+ * this call and the current task carries on as usual. Failure caused by a
+ * linked task being killed is not caught.
+ *
+ * # Example
+ *
+ * ```
+ * let res = do task::try_block { parse(input) };
+ * ```
+ */
+pub fn try_block<T>(f: &fn() -> T) -> Result<T, ~str> {
+    use rt::task::Task;
+
+    if !in_green_task_context() {
+        fail2!("cannot catch failure in non-green task context")
+    }
+
+    let mut result = None;
+    unsafe {
+        let task: *mut Task = Local::unsafe_borrow();
+        assert!(!(*task).unwinder.unwinding);
+
+        do (*task).unwinder.try {
+            result = Some(f());
+        }
+
+        if (*task).unwinder.unwinding {
+            (*task).unwinder.unwinding = false;
+            let cause = (*task).unwinder.cause.take();
+            // A kill from linked failure must still take the task down.
+            (*task).death.check_killed(false);
+            return Err(cause.unwrap_or(~"<unknown>"));
+        }
+    }
+    Ok(result.unwrap())
+}
+
 /* Lifecycle functions */
 
 /// Read the name of the current task.
@@ -547,6 +630,71 @@ pub fn with_task_name<U>(blk: &fn(Option<&str>) -> U) -> U {
     }
 }
 
+/// Read the scheduling statistics of the current task: the time it has
+/// spent running, the number of times it has been scheduled and the time
+/// it has spent waiting to become runnable.
+pub fn stats() -> TaskStats {
+    use rt::task::Task;
+
+    if in_green_task_context() {
+        do Local::borrow |task: &mut Task| {
+            task.current_stats()
+        }
+    } else {
+        fail2!("no task statistics exist in non-green task context")
+    }
+}
+
+/// Print the current task's scheduling statistics to stderr as they are
+/// now, in the form that setting `RUST_TASK_STATS` prints every task's
+/// when it exits. That's too late for a task that runs for as long as the
+/// program does, which can call this whenever it wants to report.
+pub fn dump_stats() {
+    use rt::task::Task;
+
+    if in_green_task_context() {
+        do Local::borrow |task: &mut Task| {
+            task.dump_stats()
+        }
+    } else {
+        fail2!("no task statistics exist in non-green task context")
+    }
+}
+
+/// Get a handle to the scheduler the current task is running on.
+pub fn current_scheduler() -> SchedulerHandle {
+    use rt::shouldnt_be_public::Scheduler;
+
+    if in_green_task_context() {
+        do Local::borrow |sched: &mut Scheduler| {
+            SchedulerHandle { handle: UnsafeArc::new(sched.make_handle()) }
+        }
+    } else {
+        fail2!("no scheduler exists in non-green task context")
+    }
+}
+
+/**
+ * Move the current task to the scheduler behind `dest`.
+ *
+ * The task is descheduled and resumes on the target scheduler, where it
+ * stays pinned from then on, as if it had been spawned there. This is
+ * useful to co-locate a task with data or resources that belong to a
+ * particular scheduler thread.
+ */
+pub fn migrate_to(dest: &SchedulerHandle) {
+    use rt::shouldnt_be_public::Scheduler;
+
+    if !in_green_task_context() {
+        fail2!("cannot migrate in non-green task context")
+    }
+
+    do unkillable {
+        let sched: ~Scheduler = Local::take();
+        unsafe { sched.send_running_task_to(&mut *dest.handle.get()); }
+    }
+}
+
 pub fn deschedule() {
     //! Yield control to the task scheduler
 
@@ -1398,3 +1546,52 @@ fn test_indestructible() {
         assert!(result.is_ok());
     }
 }
+
+#[test]
+fn test_stats() {
+    use rt::test::run_in_newsched_task;
+
+    do run_in_newsched_task {
+        let before = stats();
+        deschedule();
+        let after = stats();
+        assert!(after.times_scheduled > before.times_scheduled);
+        assert!(after.cpu_time_ns >= before.cpu_time_ns);
+        assert!(after.to_str().starts_with("cpu "));
+        dump_stats();
+    }
+}
+
+#[test]
+fn test_migrate_to() {
+    use rt::test::run_in_newsched_task;
+
+    do run_in_newsched_task {
+        let (port, chan) = stream();
+        do spawn_sched(SingleThreaded) {
+            chan.send(current_scheduler());
+        }
+        let dest = port.recv();
+        assert!(current_scheduler().id() != dest.id());
+        migrate_to(&dest);
+        assert_eq!(current_scheduler().id(), dest.id());
+    }
+}
+
+#[test]
+fn test_try_block() {
+    use rt::test::run_in_newsched_task;
+
+    do run_in_newsched_task {
+        assert_eq!(try_block(|| 10), Ok(10));
+
+        let res: Result<(), ~str> = do try_block { fail2!("oops") };
+        assert_eq!(res, Err(~"oops"));
+
+        // The task is usable again, including for further failure.
+        assert!(!failing());
+        let res: Result<(), ~str> = try_block(|| fail2!());
+        assert!(res.is_err());
+        assert_eq!(try_block(|| 20), Ok(20));
+    }
+}
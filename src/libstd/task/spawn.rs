@@ -86,10 +86,13 @@ use task::unkillable;
 use uint;
 use util;
 use unstable::sync::Exclusive;
+use rt::cancel;
+use rt::env;
 use rt::in_green_task_context;
 use rt::local::Local;
 use rt::task::{Task, Sched};
 use rt::shouldnt_be_public::{Scheduler, KillHandle, WorkQueue, Thread};
+use rt::shutdown;
 use rt::uv::uvio::UvEventLoop;
 
 #[cfg(test)] use task::default_task_opts;
@@ -579,8 +582,9 @@ pub fn spawn_raw(mut opts: TaskOpts, f: ~fn()) {
                 }
             }
         };
-        // Should be run after the local-borrowed task is returned.
-        if enlist_success {
+        // Should be run after the local-borrowed task is returned. Once the
+        // runtime is shutting down no task starts work.
+        if enlist_success && shutdown::accepting_tasks() {
             if indestructible {
                 do unkillable { f() }
             } else {
@@ -589,7 +593,10 @@ pub fn spawn_raw(mut opts: TaskOpts, f: ~fn()) {
         }
     };
 
-    let mut task = if opts.sched.mode != SingleThreaded {
+    // With native threads every task is spawned as a SingleThreaded one is,
+    // onto a new scheduler with a thread of its own.
+    let one_to_one = opts.sched.mode == SingleThreaded || env::native_threads();
+    let mut task = if !one_to_one {
         if opts.watched {
             Task::build_child(opts.stack_size, child_wrapper)
         } else {
@@ -684,6 +691,11 @@ pub fn spawn_raw(mut opts: TaskOpts, f: ~fn()) {
     }
 
     task.name = opts.name.take();
+    // The child shares its parent's token unless given one of its own
+    task.cancel_token = match opts.cancel_token.take() {
+        Some(token) => Some(token),
+        None => cancel::current()
+    };
     debug2!("spawn calling run_task");
     Scheduler::run_task(task);
 
@@ -334,6 +334,18 @@ impl LittleLock {
             }
         }
     }
+
+    /// Release the lock and block the OS thread until another thread calls
+    /// `signal`, then reacquire it. Must only be called from within `lock`.
+    pub unsafe fn wait(&self) {
+        rust_wait_little_lock(self.l);
+    }
+
+    /// Wake a thread blocked in `wait`. Must only be called from within
+    /// `lock`.
+    pub unsafe fn signal(&self) {
+        rust_signal_little_lock(self.l);
+    }
 }
 
 struct ExData<T> {
@@ -415,6 +427,8 @@ externfn!(fn rust_create_little_lock() -> rust_little_lock)
 externfn!(fn rust_destroy_little_lock(lock: rust_little_lock))
 externfn!(fn rust_lock_little_lock(lock: rust_little_lock))
 externfn!(fn rust_unlock_little_lock(lock: rust_little_lock))
+externfn!(fn rust_wait_little_lock(lock: rust_little_lock))
+externfn!(fn rust_signal_little_lock(lock: rust_little_lock))
 
 #[cfg(test)]
 mod tests {
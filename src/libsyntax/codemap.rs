@@ -247,13 +247,38 @@ impl FileMap {
 }
 
 pub struct CodeMap {
-    files: @mut ~[@FileMap]
+    files: @mut ~[@FileMap],
+    // Stripped from file names that end up in compiled code; see
+    // display_filename
+    priv path_prefix: @mut Option<~str>,
 }
 
 impl CodeMap {
     pub fn new() -> CodeMap {
         CodeMap {
             files: @mut ~[],
+            path_prefix: @mut None,
+        }
+    }
+
+    /// Makes file names under the directory `dir` show up relative to it in
+    /// compiled code, so that where the sources live doesn't change the output
+    pub fn set_path_prefix(&self, dir: &str) {
+        *self.path_prefix = if dir.is_empty() || dir.ends_with("/") {
+            Some(dir.to_owned())
+        } else {
+            Some(format!("{}/", dir))
+        };
+    }
+
+    /// The name to give `filename` in compiled code, such as the messages of
+    /// `fail!()` or debug info. Diagnostics still use the name it was read as.
+    pub fn display_filename(&self, filename: FileName) -> FileName {
+        match *self.path_prefix {
+            Some(ref prefix) if filename.starts_with(*prefix) => {
+                filename.slice_from(prefix.len()).to_managed()
+            }
+            _ => filename
         }
     }
 
@@ -499,4 +524,15 @@ mod test {
         fm.next_line(BytePos(10));
         fm.next_line(BytePos(2));
     }
+
+    #[test]
+    fn t3 () {
+        let cm = CodeMap::new();
+        assert_eq!(cm.display_filename(@"/ws/src/foo/lib.rs"), @"/ws/src/foo/lib.rs");
+        cm.set_path_prefix("/ws/src/foo");
+        assert_eq!(cm.display_filename(@"/ws/src/foo/lib.rs"), @"lib.rs");
+        assert_eq!(cm.display_filename(@"/ws/src/foo/bar/mod.rs"), @"bar/mod.rs");
+        assert_eq!(cm.display_filename(@"/ws/src/foobar.rs"), @"/ws/src/foobar.rs");
+        assert_eq!(cm.display_filename(@"<anon>"), @"<anon>");
+    }
 }
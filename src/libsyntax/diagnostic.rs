@@ -13,7 +13,9 @@ use codemap;
 
 use std::io;
 use std::local_data;
+use extra::json;
 use extra::term;
+use extra::treemap::TreeMap;
 
 pub trait Emitter {
     fn emit(&self,
@@ -166,7 +168,7 @@ pub fn mk_handler(emitter: Option<@Emitter>) -> @mut handler {
     } as @mut handler
 }
 
-#[deriving(Eq)]
+#[deriving(Eq, Clone)]
 pub enum level {
     fatal,
     error,
@@ -224,7 +226,9 @@ fn print_maybe_styled(msg: &str, color: term::attr::Attr) {
     }
 }
 
-fn print_diagnostic(topic: &str, lvl: level, msg: &str) {
+/// Prints a diagnostic as the default emitter does, with `topic` (usually
+/// a location) before it, but with no excerpt of the source
+pub fn print_diagnostic(topic: &str, lvl: level, msg: &str) {
     let stderr = io::stderr();
 
     if !topic.is_empty() {
@@ -257,6 +261,170 @@ impl Emitter for DefaultEmitter {
     }
 }
 
+/// A diagnostic with its location worked out, as handed to a
+/// `DiagnosticFilter`
+#[deriving(Clone)]
+pub struct Diagnostic {
+    level: level,
+    message: ~str,
+    span: Option<DiagnosticSpan>,
+    priv cmsp: Option<(@codemap::CodeMap, Span)>
+}
+
+impl Diagnostic {
+    pub fn new(cmsp: Option<(@codemap::CodeMap, Span)>, msg: &str, lvl: level) -> Diagnostic {
+        Diagnostic {
+            level: lvl,
+            message: msg.to_owned(),
+            span: cmsp.map(|(cm, sp)| DiagnosticSpan::new(cm, cm.adjust_span(sp))),
+            cmsp: cmsp
+        }
+    }
+
+    /// Hands the diagnostic to an ordinary emitter
+    pub fn emit_to(&self, out: @Emitter) {
+        out.emit(self.cmsp, self.message, self.level);
+    }
+}
+
+/// Where a diagnostic points. Lines are 1-based and columns 0-based, as in
+/// the human-readable output.
+#[deriving(Clone, Eq)]
+pub struct DiagnosticSpan {
+    file_name: @str,
+    line_start: uint,
+    column_start: uint,
+    line_end: uint,
+    column_end: uint
+}
+
+impl DiagnosticSpan {
+    pub fn new(cm: @codemap::CodeMap, sp: Span) -> DiagnosticSpan {
+        let lo = cm.lookup_char_pos_adj(sp.lo);
+        let hi = cm.lookup_char_pos_adj(sp.hi);
+        DiagnosticSpan {
+            file_name: lo.filename,
+            line_start: lo.line,
+            column_start: lo.col.to_uint(),
+            line_end: hi.line,
+            column_end: hi.col.to_uint()
+        }
+    }
+}
+
+/// Lets a tool that embeds the compiler see each diagnostic before it's
+/// printed
+pub trait DiagnosticFilter {
+    /// Returns the diagnostic to print, possibly changed, or None to
+    /// suppress it
+    fn filter(&self, diag: Diagnostic) -> Option<Diagnostic>;
+}
+
+/// Passes each diagnostic through `filter` and prints whatever comes out
+/// with `out`
+pub struct FilteringEmitter {
+    filter: @DiagnosticFilter,
+    out: @Emitter
+}
+
+impl Emitter for FilteringEmitter {
+    fn emit(&self,
+            cmsp: Option<(@codemap::CodeMap, Span)>,
+            msg: &str,
+            lvl: level) {
+        match self.filter.filter(Diagnostic::new(cmsp, msg, lvl)) {
+            Some(diag) => diag.emit_to(self.out),
+            None => ()
+        }
+    }
+}
+
+/// The ways the compiler can render diagnostics
+#[deriving(Eq, Clone)]
+pub enum ErrorFormat {
+    HumanReadable,
+    Json,
+}
+
+pub fn parse_error_format(name: &str) -> Option<ErrorFormat> {
+    match name {
+        "human" => Some(HumanReadable),
+        "json" => Some(Json),
+        _ => None
+    }
+}
+
+impl ErrorFormat {
+    pub fn emitter(&self) -> @Emitter {
+        match *self {
+            HumanReadable => @DefaultEmitter as @Emitter,
+            Json => @JsonEmitter as @Emitter
+        }
+    }
+}
+
+/// Writes each diagnostic to stderr as one line of JSON, for tools that
+/// would otherwise have to scrape the human-readable output
+pub struct JsonEmitter;
+
+impl Emitter for JsonEmitter {
+    fn emit(&self,
+            cmsp: Option<(@codemap::CodeMap, Span)>,
+            msg: &str,
+            lvl: level) {
+        io::stderr().write_line(json_diagnostic(cmsp, msg, lvl).to_str());
+    }
+}
+
+fn json_diagnostic(cmsp: Option<(@codemap::CodeMap, Span)>,
+                   msg: &str,
+                   lvl: level) -> json::Json {
+    let mut spans = ~[];
+    let mut notes = ~[];
+    match cmsp {
+        Some((cm, sp)) => {
+            let sp = cm.adjust_span(sp);
+            spans.push(json_span(cm, sp));
+            json_macro_backtrace(cm, sp, &mut notes);
+        }
+        None => ()
+    }
+    let mut obj = ~TreeMap::new();
+    obj.insert(~"level", json::String(diagnosticstr(lvl)));
+    obj.insert(~"message", json::String(msg.to_owned()));
+    obj.insert(~"spans", json::List(spans));
+    obj.insert(~"notes", json::List(notes));
+    json::Object(obj)
+}
+
+fn json_span(cm: @codemap::CodeMap, sp: Span) -> json::Json {
+    let span = DiagnosticSpan::new(cm, sp);
+    let mut obj = ~TreeMap::new();
+    obj.insert(~"file_name", json::String(span.file_name.to_owned()));
+    obj.insert(~"line_start", json::Number(span.line_start as f64));
+    obj.insert(~"column_start", json::Number(span.column_start as f64));
+    obj.insert(~"line_end", json::Number(span.line_end as f64));
+    obj.insert(~"column_end", json::Number(span.column_end as f64));
+    json::Object(obj)
+}
+
+fn json_note(msg: ~str, spans: ~[json::Json]) -> json::Json {
+    let mut obj = ~TreeMap::new();
+    obj.insert(~"message", json::String(msg));
+    obj.insert(~"spans", json::List(spans));
+    json::Object(obj)
+}
+
+// The JSON counterpart of print_macro_backtrace
+fn json_macro_backtrace(cm: @codemap::CodeMap, sp: Span, notes: &mut ~[json::Json]) {
+    for ei in sp.expn_info.iter() {
+        let callee_spans = ei.callee.span.iter().map(|span| json_span(cm, *span)).collect();
+        notes.push(json_note(format!("in expansion of {}!", ei.callee.name), callee_spans));
+        notes.push(json_note(~"expansion site", ~[json_span(cm, ei.call_site)]));
+        json_macro_backtrace(cm, ei.call_site, notes);
+    }
+}
+
 fn highlight_lines(cm: @codemap::CodeMap,
                    sp: Span,
                    lvl: level,
@@ -354,3 +522,65 @@ pub fn expect<T:Clone>(diag: @mut span_handler,
        None => diag.handler().bug(msg()),
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use super::json_diagnostic;
+    use codemap::{BytePos, CodeMap, Span};
+
+    struct Recorder {
+        seen: @mut ~[~str]
+    }
+
+    impl Emitter for Recorder {
+        fn emit(&self, _: Option<(@CodeMap, Span)>, msg: &str, lvl: level) {
+            self.seen.push(format!("{:?}: {}", lvl, msg));
+        }
+    }
+
+    struct OnlyErrors;
+
+    impl DiagnosticFilter for OnlyErrors {
+        fn filter(&self, diag: Diagnostic) -> Option<Diagnostic> {
+            if diag.level == error {
+                let mut diag = diag;
+                diag.message = format!("[foo] {}", diag.message);
+                Some(diag)
+            } else {
+                None
+            }
+        }
+    }
+
+    #[test]
+    fn filtering_emitter() {
+        let seen = @mut ~[];
+        let emitter = FilteringEmitter {
+            filter: @OnlyErrors as @DiagnosticFilter,
+            out: @Recorder { seen: seen } as @Emitter
+        };
+        emitter.emit(None, "unused variable", warning);
+        emitter.emit(None, "unresolved name", error);
+        assert_eq!(*seen, ~[~"error: [foo] unresolved name"]);
+    }
+
+    #[test]
+    fn json_diagnostic_without_span() {
+        let out = json_diagnostic(None, "oops", error).to_str();
+        assert_eq!(out, ~"{\"level\":\"error\",\"message\":\"oops\",\"notes\":[],\"spans\":[]}");
+    }
+
+    #[test]
+    fn json_diagnostic_with_span() {
+        let cm = @CodeMap::new();
+        let fm = cm.new_filemap(@"blork.rs", @"first line.\nsecond line");
+        fm.next_line(BytePos(0));
+        fm.next_line(BytePos(12));
+        let sp = Span { lo: BytePos(12), hi: BytePos(18), expn_info: None };
+        let out = json_diagnostic(Some((cm, sp)), "oops", warning).to_str();
+        assert!(out.contains("\"level\":\"warning\""));
+        assert!(out.contains("{\"column_end\":6,\"column_start\":0,\"file_name\":\"blork.rs\",\
+                              \"line_end\":2,\"line_start\":2}"));
+    }
+}
@@ -1055,6 +1055,44 @@ pub fn std_macros() -> @str {
         )
     )
 
+    // select! waits until one of several ports has a message, receives it
+    // and runs the arm for that port, like so:
+    //
+    // select!(
+    //     timeout(1000) => fail!(),
+    //     numbers -> n => total += n,
+    //     names -> name => println(name)
+    // )
+    //
+    // The ports, which may carry different types, have to be mutable
+    // variables, since they're borrowed mutably to wait on. If several are
+    // ready, the earliest arm wins. The optional timeout arm comes first,
+    // but only runs if no port has a message within that many milliseconds.
+    macro_rules! select (
+        (timeout($ms:expr) => $timeout:expr, $($port:expr -> $pat:pat => $code:expr),+) => ({
+            let mut __timeout = ::std::select::timeout($ms);
+            select!($($port -> $pat => $code),+, __timeout -> () => $timeout)
+        });
+        ($($port:expr -> $pat:pat => $code:expr),+) => ({
+            let __ready = {
+                let mut __ports = [$(
+                    &mut $port as &mut ::std::select::SelectInner
+                ),+];
+                ::std::select::select_any(__ports)
+            };
+            let mut __index = 0u;
+            let mut __result = None;
+            $(
+                if __ready == __index {
+                    __result = Some(match $port.recv() { $pat => $code });
+                }
+                __index += 1;
+            )+
+            assert!(__ready < __index);
+            __result.unwrap()
+        })
+    )
+
     // externfn! declares a wrapper for an external function.
     // It is intended to be used like:
     //
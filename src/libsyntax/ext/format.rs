@@ -177,6 +177,9 @@ impl Context {
             parse::CountIsParam(i) => {
                 self.verify_arg_type(Left(i), Unsigned);
             }
+            parse::CountIsName(s) => {
+                self.verify_arg_type(Right(s.to_managed()), Unsigned);
+            }
             parse::CountIsNextParam => {
                 if self.check_positional_ok() {
                     self.verify_arg_type(Left(self.next_arg), Unsigned);
@@ -368,6 +371,18 @@ impl Context {
                     self.ecx.expr_call_global(sp, ctpath("CountIsParam"),
                                               ~[self.ecx.expr_uint(sp, i)])
                 }
+                // Named arguments come after the positional ones, as with
+                // `ArgumentNamed` below
+                parse::CountIsName(n) => {
+                    let n = n.to_managed();
+                    let i = match self.name_positions.find_copy(&n) {
+                        Some(i) => i,
+                        None => 0, // error already emitted elsewhere
+                    };
+                    let i = i + self.args.len();
+                    self.ecx.expr_call_global(sp, ctpath("CountIsParam"),
+                                              ~[self.ecx.expr_uint(sp, i)])
+                }
                 parse::CountImplied => {
                     let path = self.ecx.path_global(sp, ctpath("CountImplied"));
                     self.ecx.expr_path(path)
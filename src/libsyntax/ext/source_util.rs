@@ -56,7 +56,7 @@ pub fn expand_file(cx: @ExtCtxt, sp: Span, tts: &[ast::token_tree])
 
     let topmost = topmost_expn_info(cx.backtrace().unwrap());
     let loc = cx.codemap().lookup_char_pos(topmost.call_site.lo);
-    let filename = loc.file.name;
+    let filename = cx.codemap().display_filename(loc.file.name);
     base::MRExpr(cx.expr_str(topmost.call_site, filename))
 }
 
@@ -78,6 +78,29 @@ pub fn parse_crate_from_file(
     // why is there no p.abort_if_errors here?
 }
 
+// Parses only the inner attributes at the top of a crate file, for tools
+// that want a crate's metadata without reading the rest of it.
+pub fn parse_crate_attrs_from_file(
+    input: &Path,
+    cfg: ast::CrateConfig,
+    sess: @mut ParseSess
+) -> ~[ast::Attribute] {
+    let p = new_parser_from_file(sess, cfg, input);
+    let (inner, _) = p.parse_inner_attrs_and_next();
+    maybe_aborted(inner, p)
+}
+
+pub fn parse_crate_attrs_from_source_str(
+    name: @str,
+    source: @str,
+    cfg: ast::CrateConfig,
+    sess: @mut ParseSess
+) -> ~[ast::Attribute] {
+    let p = new_parser_from_source_str(sess, cfg, name, source);
+    let (inner, _) = p.parse_inner_attrs_and_next();
+    maybe_aborted(inner, p)
+}
+
 pub fn parse_crate_from_source_str(
     name: @str,
     source: @str,
@@ -322,6 +345,7 @@ mod test {
     use opt_vec;
     use ast;
     use abi;
+    use attr::AttrMetaMethods;
     use parse::parser::Parser;
     use parse::token::{str_to_ident};
     use util::parser_testing::{string_to_tts, string_to_parser};
@@ -747,4 +771,16 @@ mod test {
 }");
     }
 
+    #[test] fn crate_attrs_only() {
+        // Nothing after the crate attributes is read, so it needn't parse
+        let attrs = parse_crate_attrs_from_source_str(
+            @"bogofile",
+            @"#[pkg(name = \"foo\")];\n#[license = \"MIT\"];\nfn main( {",
+            ~[],
+            new_parse_sess(None));
+        assert_eq!(attrs.len(), 2);
+        assert_eq!(attrs[0].name(), @"pkg");
+        assert_eq!(attrs[1].name(), @"license");
+    }
+
 }
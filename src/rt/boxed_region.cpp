@@ -15,10 +15,95 @@
 
 // #define DUMP_BOXED_REGION
 
+// The bookkeeping before each box. Padded so that boxes are as aligned as
+// the allocations they're carved from, which box_body() relies on.
+#define BOX_PREFIX_SIZE 16
+
+static inline uintptr_t &box_size_class(rust_opaque_box *box) {
+    return *(uintptr_t*)((char*)box - BOX_PREFIX_SIZE);
+}
+
+// The size class of a box of total_size bytes, or 0 if it's too big for one
+static inline size_t size_class_for(size_t total_size) {
+    size_t size_class =
+        (total_size + BOX_SIZE_CLASS_GRAIN - 1) / BOX_SIZE_CLASS_GRAIN;
+    return size_class <= BOX_SIZE_CLASSES ? size_class : 0;
+}
+
+boxed_region::boxed_region(memory_region *br, bool poison_on_free)
+    : poison_on_free(poison_on_free)
+    , backing_region(br)
+    , live_allocs(NULL)
+    , chunks(NULL)
+    , chunk_cursor(NULL)
+    , chunk_end(NULL)
+{
+    memset(free_lists, 0, sizeof(free_lists));
+}
+
+boxed_region::~boxed_region() {
+    // Any boxes still alive have leaked. Their chunks are left allocated so
+    // that the backing region reports the leak.
+    if (live_allocs) return;
+
+    while (chunks) {
+        void *next = *(void**)chunks;
+        backing_region->free(chunks);
+        chunks = next;
+    }
+}
+
+rust_opaque_box *boxed_region::carve(size_t size_class) {
+    rust_opaque_box *box = free_lists[size_class];
+    if (box) {
+        free_lists[size_class] = box->next;
+        return box;
+    }
+
+    size_t slot_size = BOX_PREFIX_SIZE + size_class * BOX_SIZE_CLASS_GRAIN;
+    if ((size_t)(chunk_end - chunk_cursor) < slot_size) {
+        // Whatever is left of the current chunk is abandoned
+        char *chunk = (char*)backing_region->malloc(BOX_CHUNK_SIZE, "@chunk");
+        *(void**)chunk = chunks;
+        chunks = chunk;
+        // The link takes up as much room as a prefix, keeping slots aligned
+        chunk_cursor = chunk + BOX_PREFIX_SIZE;
+        chunk_end = chunk + BOX_CHUNK_SIZE;
+    }
+    box = (rust_opaque_box*)(chunk_cursor + BOX_PREFIX_SIZE);
+    chunk_cursor += slot_size;
+    box_size_class(box) = size_class;
+    return box;
+}
+
+// Allocates room for a box of total_size bytes, header included
+rust_opaque_box *boxed_region::alloc_box(size_t total_size) {
+    size_t size_class = size_class_for(total_size);
+    if (size_class != 0) {
+        return carve(size_class);
+    }
+
+    char *mem = (char*)backing_region->malloc(total_size + BOX_PREFIX_SIZE,
+                                              "@");
+    rust_opaque_box *box = (rust_opaque_box*)(mem + BOX_PREFIX_SIZE);
+    box_size_class(box) = 0;
+    return box;
+}
+
+// Gives back the room taken by a box that's been unlinked
+void boxed_region::release_box(rust_opaque_box *box) {
+    size_t size_class = box_size_class(box);
+    if (size_class == 0) {
+        backing_region->free((char*)box - BOX_PREFIX_SIZE);
+    } else {
+        box->next = free_lists[size_class];
+        free_lists[size_class] = box;
+    }
+}
+
 rust_opaque_box *boxed_region::malloc(type_desc *td, size_t body_size) {
     size_t total_size = get_box_size(body_size, td->align);
-    rust_opaque_box *box =
-      (rust_opaque_box*)backing_region->malloc(total_size, "@");
+    rust_opaque_box *box = alloc_box(total_size);
     box->td = td;
     box->ref_count = 1;
     box->prev = NULL;
@@ -39,8 +124,22 @@ rust_opaque_box *boxed_region::realloc(rust_opaque_box *box,
                                        size_t new_size) {
 
     size_t total_size = new_size + sizeof(rust_opaque_box);
-    rust_opaque_box *new_box =
-        (rust_opaque_box*)backing_region->realloc(box, total_size);
+    size_t size_class = box_size_class(box);
+    rust_opaque_box *new_box;
+    if (size_class == 0) {
+        char *mem = (char*)backing_region->realloc(
+            (char*)box - BOX_PREFIX_SIZE, total_size + BOX_PREFIX_SIZE);
+        new_box = (rust_opaque_box*)(mem + BOX_PREFIX_SIZE);
+    } else if (total_size <= size_class * BOX_SIZE_CLASS_GRAIN) {
+        // It still fits in its slot
+        return box;
+    } else {
+        // Move it, header and all, to a slot or allocation big enough
+        new_box = alloc_box(total_size);
+        memcpy(new_box, box, size_class * BOX_SIZE_CLASS_GRAIN);
+        box->td = NULL;
+        release_box(box);
+    }
     if (new_box->prev) new_box->prev->next = new_box;
     if (new_box->next) new_box->next->prev = new_box;
     if (live_allocs == box) live_allocs = new_box;
@@ -85,7 +184,7 @@ void boxed_region::free(rust_opaque_box *box) {
     box->next = NULL;
     box->td = NULL;
 
-    backing_region->free(box);
+    release_box(box);
 }
 
 //
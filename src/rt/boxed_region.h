@@ -13,21 +13,42 @@
 
 #include <stdlib.h>
 
+// Boxes of up to BOX_SIZE_CLASSES * BOX_SIZE_CLASS_GRAIN bytes, header
+// included, are carved out of chunks of BOX_CHUNK_SIZE bytes.
+#define BOX_SIZE_CLASS_GRAIN 16
+#define BOX_SIZE_CLASSES 32
+#define BOX_CHUNK_SIZE (64 * 1024)
+
 struct type_desc;
 class memory_region;
 struct rust_opaque_box;
 struct rust_env;
 
 /* Tracks the data allocated by a particular task in the '@' region.
- * Currently still relies on the standard malloc as a backing allocator, but
- * this could be improved someday if necessary. Every allocation must provide
- * a type descr which describes the payload (what follows the header). */
+ * Small boxes are carved out of chunks obtained from the backing region, with
+ * a free list per size class; larger ones are allocated from the backing
+ * region individually. Every allocation must provide a type descr which
+ * describes the payload (what follows the header).
+ *
+ * Every box is preceded by a word of bookkeeping recording its size class, or
+ * 0 if it has an allocation of its own. Live boxes stay linked into
+ * live_allocs however they were allocated, since the annihilator walks that
+ * list when the task exits. */
 class boxed_region {
 private:
+    // The first three fields are read by std::rt::local_heap; keep them first.
     bool poison_on_free;
     memory_region *backing_region;
     rust_opaque_box *live_allocs;
 
+    // Freed boxes of each size class, linked through their next pointers
+    rust_opaque_box *free_lists[BOX_SIZE_CLASSES + 1];
+    // Every chunk allocated so far, linked through their first words
+    void *chunks;
+    // The unused part of the newest chunk
+    char *chunk_cursor;
+    char *chunk_end;
+
     size_t align_to(size_t v, size_t align) {
         size_t alignm1 = align - 1;
         v += alignm1;
@@ -35,17 +56,18 @@ private:
         return v;
     }
 
+    rust_opaque_box *alloc_box(size_t total_size);
+    rust_opaque_box *carve(size_t size_class);
+    void release_box(rust_opaque_box *box);
+
 private:
     // private and undefined to disable copying
     boxed_region(const boxed_region& rhs);
     boxed_region& operator=(const boxed_region& rhs);
 
 public:
-    boxed_region(memory_region *br, bool poison_on_free)
-        : poison_on_free(poison_on_free)
-        , backing_region(br)
-        , live_allocs(NULL)
-    {}
+    boxed_region(memory_region *br, bool poison_on_free);
+    ~boxed_region();
 
     rust_opaque_box *first_live_alloc() { return live_allocs; }
 
@@ -10,6 +10,7 @@
 
 
 #include "memory_region.h"
+#include "rust_util.h"
 
 #if RUSTRT_TRACK_ALLOCATIONS >= 3
 #include <execinfo.h>
@@ -25,6 +26,21 @@
 #  define HEADER_SIZE 0
 #endif
 
+// Bytes allocated by every memory region in the process, headers included.
+static uintptr_t live_bytes = 0;
+
+uintptr_t memory_region::total_live_bytes() {
+    return live_bytes;
+}
+
+static inline void add_live_bytes(void *block) {
+    __sync_fetch_and_add(&live_bytes, rust_usable_size(block));
+}
+
+static inline void sub_live_bytes(void *block) {
+    __sync_fetch_and_sub(&live_bytes, rust_usable_size(block));
+}
+
 memory_region::alloc_header *memory_region::get_header(void *mem) {
     return (alloc_header *)((char *)mem - HEADER_SIZE);
 }
@@ -76,6 +92,7 @@ void memory_region::free(void *mem) {
     }
     release_alloc(mem);
     maybe_poison(mem);
+    sub_live_bytes(alloc);
     ::free(alloc);
 }
 
@@ -90,6 +107,9 @@ memory_region::realloc(void *mem, size_t orig_size) {
     assert(alloc->magic == MAGIC);
 #   endif
 
+    if (mem) {
+        sub_live_bytes(alloc);
+    }
     size_t size = orig_size + HEADER_SIZE;
     alloc_header *newMem = (alloc_header *)::realloc(alloc, size);
     if (newMem == NULL) {
@@ -99,6 +119,7 @@ memory_region::realloc(void *mem, size_t orig_size) {
                 (long int) size);
         abort();
     }
+    add_live_bytes(newMem);
 
 #   if RUSTRT_TRACK_ALLOCATIONS >= 1
     assert(newMem->magic == MAGIC);
@@ -137,6 +158,7 @@ memory_region::malloc(size_t size, const char *tag) {
                 (long int) size);
         abort();
     }
+    add_live_bytes(mem);
 
 #   if RUSTRT_TRACK_ALLOCATIONS >= 1
     mem->magic = MAGIC;
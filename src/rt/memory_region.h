@@ -77,6 +77,8 @@ private:
 
 public:
     memory_region(bool detailed_leaks, bool poison_on_free);
+    // Bytes currently allocated by all regions in the process.
+    static uintptr_t total_live_bytes();
     memory_region(memory_region *parent);
     void *malloc(size_t size, const char *tag);
     void *realloc(void *mem, size_t size);
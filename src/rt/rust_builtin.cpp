@@ -33,6 +33,10 @@
 extern char **environ;
 #endif
 
+#if defined(__linux__)
+#include <sched.h>
+#endif
+
 #ifdef __ANDROID__
 time_t
 timegm(struct tm *tm)
@@ -124,6 +128,63 @@ rust_path_exists(char *path) {
     return 1;
 }
 
+#if defined(__WIN32__)
+#include <sys/utime.h>
+#else
+#include <utime.h>
+#endif
+
+extern "C" CDECL int
+rust_set_file_times(char *path, int64_t atime, int64_t mtime) {
+#if defined(__WIN32__)
+    struct _utimbuf buf;
+    buf.actime = (time_t)atime;
+    buf.modtime = (time_t)mtime;
+    return _utime(path, &buf);
+#else
+    struct utimbuf buf;
+    buf.actime = (time_t)atime;
+    buf.modtime = (time_t)mtime;
+    return utime(path, &buf);
+#endif
+}
+
+#if defined(__WIN32__)
+#include <io.h>
+#else
+#include <sys/file.h>
+#endif
+
+// Take an exclusive advisory lock on an open file, waiting for it to be
+// released by any other process if `wait` is set.
+extern "C" CDECL int
+rust_lock_fd(int fd, int wait) {
+#if defined(__WIN32__)
+    HANDLE handle = (HANDLE)_get_osfhandle(fd);
+    OVERLAPPED overlapped;
+    memset(&overlapped, 0, sizeof(overlapped));
+    DWORD flags = LOCKFILE_EXCLUSIVE_LOCK;
+    if (!wait) {
+        flags |= LOCKFILE_FAIL_IMMEDIATELY;
+    }
+    return LockFileEx(handle, flags, 0, MAXDWORD, MAXDWORD, &overlapped) ? 0 : -1;
+#else
+    return flock(fd, wait ? LOCK_EX : LOCK_EX | LOCK_NB);
+#endif
+}
+
+extern "C" CDECL int
+rust_unlock_fd(int fd) {
+#if defined(__WIN32__)
+    HANDLE handle = (HANDLE)_get_osfhandle(fd);
+    OVERLAPPED overlapped;
+    memset(&overlapped, 0, sizeof(overlapped));
+    return UnlockFileEx(handle, 0, MAXDWORD, MAXDWORD, &overlapped) ? 0 : -1;
+#else
+    return flock(fd, LOCK_UN);
+#endif
+}
+
 extern "C" CDECL FILE* rust_get_stdin() {return stdin;}
 extern "C" CDECL FILE* rust_get_stdout() {return stdout;}
 extern "C" CDECL FILE* rust_get_stderr() {return stderr;}
@@ -340,6 +401,26 @@ rust_unlock_little_lock(lock_and_signal *lock) {
     lock->unlock();
 }
 
+extern "C" void
+rust_wait_little_lock(lock_and_signal *lock) {
+    lock->wait();
+}
+
+extern "C" void
+rust_signal_little_lock(lock_and_signal *lock) {
+    lock->signal();
+}
+
+extern "C" size_t
+rust_malloc_usable_size(void *mem) {
+    return mem == NULL ? 0 : rust_usable_size(mem);
+}
+
+extern "C" uintptr_t
+rust_local_heap_bytes() {
+    return memory_region::total_live_bytes();
+}
+
 class raw_thread: public rust_thread {
 public:
     fn_env_pair fn;
@@ -534,6 +615,42 @@ rust_get_num_cpus() {
     return get_num_cpus();
 }
 
+// Pins the calling thread to a single CPU. Returns 0 where that isn't
+// possible, including for CPUs the platform's affinity mask can't name.
+extern "C" CDECL uintptr_t
+rust_pin_thread_to_cpu(uintptr_t cpu) {
+#if defined(__linux__)
+    if (cpu >= CPU_SETSIZE) {
+        return 0;
+    }
+    cpu_set_t set;
+    CPU_ZERO(&set);
+    CPU_SET(cpu, &set);
+    // A pid of 0 is the calling thread
+    return sched_setaffinity(0, sizeof(set), &set) == 0;
+#elif defined(__WIN32__)
+    // The mask only covers the first 32 or 64 CPUs of the thread's
+    // processor group, and shifting past its width is undefined
+    if (cpu >= sizeof(DWORD_PTR) * 8) {
+        return 0;
+    }
+    return SetThreadAffinityMask(GetCurrentThread(),
+                                 (DWORD_PTR)1 << cpu) != 0;
+#else
+    return 0;
+#endif
+}
+
+// The CPU the calling thread is running on, or -1 if that can't be told
+extern "C" CDECL intptr_t
+rust_get_current_cpu() {
+#if defined(__linux__) && !defined(__ANDROID__)
+    return sched_getcpu();
+#else
+    return -1;
+#endif
+}
+
 static lock_and_signal global_args_lock;
 static uintptr_t global_args_ptr = 0;
 
@@ -683,6 +800,42 @@ rust_win32_rand_release() {
 }
 
 #endif
+
+// The miniz compressor and decompressor state is opaque to Rust, so these
+// allocate and initialize it for the streaming APIs in extra::flate.
+#define MINIZ_HEADER_FILE_ONLY
+#include "miniz.cpp"
+
+extern "C" CDECL tdefl_compressor*
+rust_tdefl_new(int flags) {
+    tdefl_compressor *d = (tdefl_compressor*)malloc(sizeof(tdefl_compressor));
+    if (d != NULL && tdefl_init(d, NULL, NULL, flags) != TDEFL_STATUS_OKAY) {
+        free(d);
+        return NULL;
+    }
+    return d;
+}
+
+extern "C" CDECL void
+rust_tdefl_free(tdefl_compressor *d) {
+    free(d);
+}
+
+extern "C" CDECL tinfl_decompressor*
+rust_tinfl_new() {
+    tinfl_decompressor *r =
+        (tinfl_decompressor*)malloc(sizeof(tinfl_decompressor));
+    if (r != NULL) {
+        tinfl_init(r);
+    }
+    return r;
+}
+
+extern "C" CDECL void
+rust_tinfl_free(tinfl_decompressor *r) {
+    free(r);
+}
+
 //
 // Local Variables:
 // mode: C++
@@ -14,6 +14,14 @@
 #include <limits.h>
 #include "rust_type.h"
 
+#if defined(__APPLE__)
+#include <malloc/malloc.h>
+#elif defined(__FreeBSD__)
+#include <malloc_np.h>
+#else
+#include <malloc.h>
+#endif
+
 // Inline fn used regularly elsewhere.
 
 // Rounds |size| to the nearest |alignment|. Invariant: |alignment| is a power
@@ -54,4 +62,16 @@ inline size_t get_box_size(size_t body_size, size_t body_align) {
 // End:
 //
 
+// The number of bytes actually reserved for a block returned by malloc,
+// which may be more than was asked for.
+inline size_t rust_usable_size(void *mem) {
+#if defined(__APPLE__)
+    return malloc_size(mem);
+#elif defined(__WIN32__)
+    return _msize(mem);
+#else
+    return malloc_usable_size(mem);
+#endif
+}
+
 #endif
@@ -74,7 +74,7 @@ LLVMRustCreateTargetMachine(const char *triple,
     const llvm::Target *TheTarget = TargetRegistry::lookupTarget(Trip.getTriple(),
                                                                  Error);
     if (TheTarget == NULL) {
-        LLVMRustError = Error.c_str();
+        LLVMRustSetLastError(Error.c_str());
         return NULL;
     }
 
@@ -170,7 +170,7 @@ LLVMRustWriteOutputFile(LLVMTargetMachineRef Target,
   std::string ErrorInfo;
   raw_fd_ostream OS(path, ErrorInfo, sys::fs::F_Binary);
   if (ErrorInfo != "") {
-    LLVMRustError = ErrorInfo.c_str();
+    LLVMRustSetLastError(ErrorInfo.c_str());
     return false;
   }
   formatted_raw_ostream FOS(OS);
@@ -20,18 +20,131 @@
 using namespace llvm;
 using namespace llvm::sys;
 
-const char *LLVMRustError;
+// The message of the last error in each thread, for LLVMRustGetLastError.
+// Codegen units are compiled on several threads at once, so one thread's
+// error mustn't be reported as another's.
+static sys::ThreadLocal<char> LastError;
+
+void LLVMRustSetLastError(const char *Err) {
+  free(LastError.get());
+  LastError.set(strdup(Err));
+}
 
 extern "C" LLVMMemoryBufferRef
 LLVMRustCreateMemoryBufferWithContentsOfFile(const char *Path) {
   LLVMMemoryBufferRef MemBuf = NULL;
-  LLVMCreateMemoryBufferWithContentsOfFile(Path, &MemBuf,
-    const_cast<char **>(&LLVMRustError));
+  char *Err = NULL;
+  if (LLVMCreateMemoryBufferWithContentsOfFile(Path, &MemBuf, &Err)) {
+    LLVMRustSetLastError(Err);
+    LLVMDisposeMessage(Err);
+    MemBuf = NULL;
+  }
   return MemBuf;
 }
 
+extern "C" LLVMModuleRef
+LLVMRustParseBitcodeFile(LLVMContextRef C, const char *Path) {
+  LLVMMemoryBufferRef MemBuf =
+    LLVMRustCreateMemoryBufferWithContentsOfFile(Path);
+  if (!MemBuf)
+    return NULL;
+  LLVMModuleRef M = NULL;
+  char *Err = NULL;
+  if (LLVMParseBitcodeInContext(C, MemBuf, &M, &Err)) {
+    LLVMRustSetLastError(Err);
+    LLVMDisposeMessage(Err);
+    M = NULL;
+  }
+  LLVMDisposeMemoryBuffer(MemBuf);
+  return M;
+}
+
+// Makes a local definition external (but hidden), so that the other codegen
+// units can still refer to it once their objects are linked together. The
+// name gets the crate's prefix, since a local's name is only unique within
+// its module and a hidden symbol can still clash with another crate's in a
+// static link.
+static void PromoteLocal(GlobalValue *GV, const char *Prefix) {
+  if (!GV->hasLocalLinkage())
+    return;
+  std::string Name(Prefix);
+  Name += ".";
+  Name += GV->hasName() ? GV->getName().str() : "anon";
+  GV->setName(Name);
+  GV->setLinkage(GlobalValue::ExternalLinkage);
+  GV->setVisibility(GlobalValue::HiddenVisibility);
+}
+
+// Turns a copy of the crate's module into codegen unit `Unit` of `Units`.
+// Every unit starts from the same module, so walking the definitions in
+// order and dealing them out round-robin gives each one to exactly one
+// unit, and promoting the locals names them the same way in every unit.
+// Aliases go wherever their aliasee does.
+extern "C" void
+LLVMRustPartitionModule(LLVMModuleRef M, unsigned Unit, unsigned Units,
+                        const char *Prefix) {
+  Module *Mod = unwrap(M);
+  unsigned Next = 0;
+
+  for (Module::iterator F = Mod->begin(), E = Mod->end(); F != E; ++F) {
+    if (F->isDeclaration())
+      continue;
+    PromoteLocal(F, Prefix);
+    if (Next++ % Units != Unit)
+      F->deleteBody();
+  }
+
+  // llvm.global_ctors and friends are appended to by every object they
+  // appear in, so only the first unit keeps them.
+  std::vector<GlobalVariable*> Appending;
+  for (Module::global_iterator G = Mod->global_begin(),
+       E = Mod->global_end(); G != E; ++G) {
+    if (G->hasAppendingLinkage()) {
+      Appending.push_back(G);
+      continue;
+    }
+    if (G->isDeclaration())
+      continue;
+    PromoteLocal(G, Prefix);
+    if (Next++ % Units != Unit) {
+      G->setInitializer(NULL);
+      G->setLinkage(GlobalValue::ExternalLinkage);
+    }
+  }
+  if (Unit != 0) {
+    for (unsigned i = 0; i < Appending.size(); ++i)
+      Appending[i]->eraseFromParent();
+  }
+
+  // An alias has to point at a definition, so one whose aliasee went to
+  // another unit is replaced by a plain declaration of the same name.
+  std::vector<GlobalAlias*> Aliases;
+  for (Module::alias_iterator A = Mod->alias_begin(),
+       E = Mod->alias_end(); A != E; ++A)
+    Aliases.push_back(A);
+  for (unsigned i = 0; i < Aliases.size(); ++i) {
+    GlobalAlias *A = Aliases[i];
+    PromoteLocal(A, Prefix);
+    const GlobalValue *Aliasee = A->resolveAliasedGlobal(false);
+    if (Aliasee && !Aliasee->isDeclaration())
+      continue;
+
+    Type *Ty = A->getType()->getElementType();
+    GlobalValue *Decl;
+    if (FunctionType *FTy = dyn_cast<FunctionType>(Ty))
+      Decl = Function::Create(FTy, GlobalValue::ExternalLinkage, "", Mod);
+    else
+      Decl = new GlobalVariable(*Mod, Ty, false, GlobalValue::ExternalLinkage,
+                                NULL, "");
+    Decl->setVisibility(A->getVisibility());
+    A->replaceAllUsesWith(Decl);
+    Decl->takeName(A);
+    A->eraseFromParent();
+  }
+}
+
 extern "C" const char *LLVMRustGetLastError(void) {
-  return LLVMRustError;
+  return LastError.get();
 }
 
 // Custom memory manager for MCJITting. It needs special features
@@ -265,7 +378,7 @@ LLVMRustLoadCrate(void* mem, const char* crate) {
   assert(manager);
 
   if(!manager->loadCrate(crate, &Err)) {
-    LLVMRustError = Err.c_str();
+    LLVMRustSetLastError(Err.c_str());
     return false;
   }
 
@@ -298,7 +411,7 @@ LLVMRustBuildJIT(void* mem,
     .create();
 
   if(!EE || Err != "") {
-    LLVMRustError = Err.c_str();
+    LLVMRustSetLastError(Err.c_str());
     // The EngineBuilder only takes ownership of these two structures if the
     // create() call is successful, but here it wasn't successful.
     LLVMDisposeModule(M);
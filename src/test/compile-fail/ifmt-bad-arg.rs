@@ -16,6 +16,7 @@ fn main() {
     format!("{1}", 1);        //~ ERROR: invalid reference to argument `1`
                             //~^ ERROR: argument never used
     format!("{foo}");         //~ ERROR: no argument named `foo`
+    format!("{:foo$s}", "a"); //~ ERROR: no argument named `foo`
 
     format!("{}", 1, 2);               //~ ERROR: argument never used
     format!("{1}", 1, 2);              //~ ERROR: argument never used
@@ -25,6 +26,7 @@ fn main() {
 
     format!("{0:d} {0:s}", 1);         //~ ERROR: redeclared with type `s`
     format!("{foo:d} {foo:s}", foo=1); //~ ERROR: redeclared with type `s`
+    format!("{:w$s} {w:s}", "a", w=1u); //~ ERROR: attempted to be used as `s`
 
     format!("{foo}", foo=1, foo=2);    //~ ERROR: duplicate argument
     format!("#");                      //~ ERROR: `#` reference used
@@ -0,0 +1,28 @@
+// Copyright 2013 The Rust Project Developers. See the COPYRIGHT
+// file at the top-level directory of this distribution and at
+// http://rust-lang.org/COPYRIGHT.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+// xfail-fast (exec-env not supported in fast mode)
+// exec-env:RUST_REPORT_LEAKS=1
+// error-pattern:task leaked 2 managed boxes
+// error-pattern:Node (
+
+// The cycle outlives the task's stack, so the annihilator reports it. The
+// task fails only so that the test can check what it wrote to stderr.
+
+struct Node {
+    next: Option<@mut Node>
+}
+
+fn main() {
+    let a = @mut Node { next: None };
+    let b = @mut Node { next: Some(a) };
+    a.next = Some(b);
+    fail2!();
+}
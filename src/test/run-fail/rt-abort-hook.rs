@@ -0,0 +1,40 @@
+// Copyright 2013 The Rust Project Developers. See the COPYRIGHT
+// file at the top-level directory of this distribution and at
+// http://rust-lang.org/COPYRIGHT.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+// Failing while already unwinding aborts the process, but only after the
+// abort hook has run. This hook exits with the usual failure status so that
+// the test harness accepts it.
+
+// error-pattern:abort hook called: unwinding again
+
+use std::io;
+use std::libc;
+use std::rt;
+
+fn hook(msg: &str) {
+    #[fixed_stack_segment]; #[inline(never)];
+
+    io::stderr().write_line(format!("abort hook called: {}", msg));
+    unsafe { libc::exit(101); }
+}
+
+struct FailOnDrop;
+
+impl Drop for FailOnDrop {
+    fn drop(&mut self) {
+        fail2!("failing again");
+    }
+}
+
+fn main() {
+    rt::set_abort_hook(hook);
+    let _x = FailOnDrop;
+    fail2!("failing once");
+}
@@ -0,0 +1,77 @@
+// Copyright 2013 The Rust Project Developers. See the COPYRIGHT
+// file at the top-level directory of this distribution and at
+// http://rust-lang.org/COPYRIGHT.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+// xfail-fast compile-flags directive doesn't work for check-fast
+// compile-flags: --codegen-units 3
+
+// Private functions, statics, closures, generics and trait objects all
+// end up as definitions spread over the codegen units, many of them local
+// symbols that have to stay reachable from the other units.
+
+static COUNT: uint = 4;
+static NAMES: [&'static str, ..3] = ["a", "b", "c"];
+
+mod inner {
+    static mut CALLS: uint = 0;
+
+    fn bump() -> uint {
+        unsafe {
+            CALLS += 1;
+            CALLS
+        }
+    }
+
+    pub fn calls() -> uint {
+        bump();
+        bump()
+    }
+}
+
+trait Shape {
+    fn area(&self) -> uint;
+}
+
+struct Square(uint);
+struct Rect(uint, uint);
+
+impl Shape for Square {
+    fn area(&self) -> uint { let Square(s) = *self; s * s }
+}
+
+impl Shape for Rect {
+    fn area(&self) -> uint { let Rect(w, h) = *self; w * h }
+}
+
+fn total(shapes: &[@Shape]) -> uint {
+    shapes.iter().fold(0, |acc, s| acc + s.area())
+}
+
+fn twice<T: Clone>(x: T) -> (T, T) {
+    (x.clone(), x)
+}
+
+fn apply(f: &fn(uint) -> uint, x: uint) -> uint {
+    f(x)
+}
+
+fn main() {
+    let shapes = ~[@Square(3) as @Shape, @Rect(2, 5) as @Shape];
+    assert_eq!(total(shapes), 19);
+
+    assert_eq!(twice(~"x"), (~"x", ~"x"));
+    assert_eq!(twice(COUNT), (4, 4));
+
+    let offset = 10;
+    assert_eq!(apply(|x| x + offset, COUNT), 14);
+
+    assert_eq!(NAMES[2], "c");
+    assert_eq!(inner::calls(), 2);
+    assert_eq!(inner::calls(), 4);
+}
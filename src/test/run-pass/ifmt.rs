@@ -120,6 +120,11 @@ pub fn main() {
     t!(format!("{:.*s}", 4, "aaaaaaaaaaaaaaaaaa"), "aaaa");
     t!(format!("{:.1$s}", "aaaaaaaaaaaaaaaaaa", 4), "aaaa");
     t!(format!("{:1$s}", "a", 4), "a   ");
+    t!(format!("{:a$s}", "a", a=4), "a   ");
+    t!(format!("{:>width$s}", "a", width=4), "   a");
+    t!(format!("{:.prec$s}", "aaaaaaaaaaaaaaaaaa", prec=4), "aaaa");
+    t!(format!("{:w$.p$s}", "aaaaaa", w=8, p=4), "aaaa    ");
+    t!(format!("{:>width$u}", 12u, width=4u), "  12");
     t!(format!("{:-#s}", "a"), "a");
     t!(format!("{:+#s}", "a"), "a");
 
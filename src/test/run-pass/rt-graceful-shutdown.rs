@@ -0,0 +1,72 @@
+// Copyright 2013 The Rust Project Developers. See the COPYRIGHT
+// file at the top-level directory of this distribution and at
+// http://rust-lang.org/COPYRIGHT.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+// xfail-fast
+
+// A graceful shutdown lets tasks finish until the deadline, kills the ones
+// that don't, refuses to start new ones and then runs the at_exit handlers.
+
+use std::comm::{stream, SharedChan};
+use std::rt;
+use std::rt::io::timer;
+use std::rt::shutdown;
+use std::task;
+
+fn spawn_detached(f: ~fn()) {
+    let mut builder = task::task();
+    builder.unlinked();
+    builder.unwatched();
+    builder.spawn(f);
+}
+
+#[start]
+fn start(argc: int, argv: **u8) -> int {
+    do rt::start(argc, argv) {
+        let (started_port, started_chan) = stream();
+        let started_chan = SharedChan::new(started_chan);
+
+        // Finishes well before the deadline
+        let chan = started_chan.clone();
+        do spawn_detached {
+            chan.send(());
+            timer::sleep(10);
+        }
+
+        // Never finishes by itself
+        let (never_port, _never_chan) = stream::<()>();
+        let chan = started_chan.clone();
+        do spawn_detached {
+            chan.send(());
+            never_port.recv();
+        }
+
+        started_port.recv();
+        started_port.recv();
+
+        let (exit_port, exit_chan) = stream();
+        let exit_chan = SharedChan::new(exit_chan);
+        do shutdown::at_exit {
+            exit_chan.send(());
+        }
+
+        let result = shutdown::shutdown(200);
+        assert_eq!(result.finished, 1);
+        assert_eq!(result.killed, 1);
+        assert_eq!(result.stuck, 0);
+        assert_eq!(exit_port.try_recv(), Some(()));
+
+        // Too late to start anything
+        let (late_port, late_chan) = stream();
+        do spawn_detached {
+            late_chan.send(());
+        }
+        assert_eq!(late_port.try_recv(), None);
+    }
+}
@@ -0,0 +1,34 @@
+// Copyright 2012 The Rust Project Developers. See the COPYRIGHT
+// file at the top-level directory of this distribution and at
+// http://rust-lang.org/COPYRIGHT.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+// xfail-fast
+
+extern mod extra;
+
+use extra::tempfile;
+use std::io;
+use std::rt::logging;
+
+pub fn main() {
+    let dir = tempfile::TempDir::new("rt-log-file").unwrap();
+    let path = dir.path().push("log");
+
+    assert!(logging::set_log_file(&path));
+    error2!("to the log file: {}", 1);
+    error2!("to the log file: {}", 2);
+    logging::log_to_stderr();
+    error2!("to stderr");
+
+    let contents = io::read_whole_file_str(&path).unwrap();
+    let lines: ~[&str] = contents.line_iter().collect();
+    assert_eq!(lines.len(), 2);
+    assert!(lines[0].ends_with("to the log file: 1"));
+    assert!(lines[1].ends_with("to the log file: 2"));
+}
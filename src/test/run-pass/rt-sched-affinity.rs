@@ -0,0 +1,42 @@
+// Copyright 2013 The Rust Project Developers. See the COPYRIGHT
+// file at the top-level directory of this distribution and at
+// http://rust-lang.org/COPYRIGHT.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+// xfail-fast
+
+// With an affinity mask each scheduler is pinned to a CPU, which it then
+// stays on. Pinning can fail, e.g. where the process may not use every
+// CPU, in which case the scheduler simply isn't pinned.
+
+use std::rt;
+use std::rt::affinity;
+use std::task;
+
+#[start]
+fn start(argc: int, argv: **u8) -> int {
+    rt::init(argc, argv);
+    unsafe { rt::env::set_sched_affinity(affinity::parse_affinity("round-robin")); }
+    let exit_code = do rt::run {
+        let sched = task::current_scheduler();
+        match sched.cpu() {
+            Some(cpu) => {
+                // Not every platform can tell which CPU a thread is on
+                for &running_on in affinity::current_cpu().iter() {
+                    assert_eq!(running_on, cpu);
+                }
+                assert!(affinity::sched_cpus().iter().any(|p| {
+                    p.sched_id == sched.id() && p.cpu == cpu
+                }));
+            }
+            None => assert!(affinity::sched_cpus().iter().all(|p| p.sched_id != sched.id()))
+        }
+    };
+    rt::cleanup();
+    exit_code
+}
@@ -0,0 +1,41 @@
+// Copyright 2013 The Rust Project Developers. See the COPYRIGHT
+// file at the top-level directory of this distribution and at
+// http://rust-lang.org/COPYRIGHT.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+// xfail-fast windows uses a different test runner
+
+// Start the runtime without giving it the main thread, as a library
+// embedded in a C program would.
+
+use std::default::Default;
+use std::rt;
+use std::unstable::atomics::{AtomicUint, SeqCst};
+use std::unstable::sync::UnsafeArc;
+
+#[start]
+fn start(_argc: int, _argv: **u8) -> int {
+    let count = UnsafeArc::new(AtomicUint::new(0));
+
+    let mut rt = rt::start_in_place(Default::default());
+    for _ in range(0, 10) {
+        let count = count.clone();
+        do rt.spawn {
+            unsafe { (*count.get()).fetch_add(1, SeqCst); }
+        }
+    }
+    assert!(rt.wait());
+    unsafe { assert_eq!((*count.get()).load(SeqCst), 10); }
+
+    do rt.spawn {
+        fail!();
+    }
+    assert!(!rt.shutdown());
+
+    0
+}
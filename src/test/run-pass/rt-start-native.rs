@@ -0,0 +1,59 @@
+// Copyright 2013 The Rust Project Developers. See the COPYRIGHT
+// file at the top-level directory of this distribution and at
+// http://rust-lang.org/COPYRIGHT.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+// xfail-fast
+
+// With native threads every task gets a scheduler, and a thread, of its own,
+// but failure and local data work as they do for green tasks.
+
+use std::comm::{stream, SharedChan};
+use std::local_data;
+use std::rt;
+use std::task;
+
+local_data_key!(key: int)
+
+#[start]
+fn start(argc: int, argv: **u8) -> int {
+    do rt::start_native(argc, argv) {
+        let main_sched = task::current_scheduler().id();
+
+        let (port, chan) = stream();
+        let chan = SharedChan::new(chan);
+        let mut releases = ~[];
+        for i in range(0, 4) {
+            let chan = chan.clone();
+            let (release_port, release_chan) = stream();
+            releases.push(release_chan);
+            do spawn {
+                local_data::set(key, i);
+                let value = local_data::get(key, |v| *v.unwrap());
+                chan.send((task::current_scheduler().id(), value));
+                // Scheduler ids are only unique among running schedulers
+                release_port.recv();
+            }
+        }
+
+        let mut scheds = ~[main_sched];
+        for _ in range(0, 4) {
+            let (sched, value) = port.recv();
+            assert!(!scheds.contains(&sched));
+            assert!(value < 4);
+            scheds.push(sched);
+        }
+        for release in releases.iter() {
+            release.send(());
+        }
+
+        assert!(task::try(|| fail!()).is_err());
+        local_data::set(key, 10);
+        assert_eq!(local_data::get(key, |v| *v.unwrap()), 10);
+    }
+}
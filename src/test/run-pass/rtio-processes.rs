@@ -38,6 +38,7 @@ fn smoke() {
         env: None,
         cwd: None,
         io: io,
+        detach: false,
     };
     let p = Process::new(args);
     assert!(p.is_some());
@@ -55,6 +56,7 @@ fn smoke_failure() {
         env: None,
         cwd: None,
         io: io,
+        detach: false,
     };
     let p = Process::new(args);
     assert!(p.is_some());
@@ -72,6 +74,7 @@ fn exit_reported_right() {
         env: None,
         cwd: None,
         io: io,
+        detach: false,
     };
     let p = Process::new(args);
     assert!(p.is_some());
@@ -113,6 +116,7 @@ fn stdout_works() {
         env: None,
         cwd: None,
         io: io,
+        detach: false,
     };
     assert_eq!(run_output(args), ~"foobar\n");
 }
@@ -129,6 +133,7 @@ fn set_cwd_works() {
         env: None,
         cwd: cwd,
         io: io,
+        detach: false,
     };
     assert_eq!(run_output(args), ~"/\n");
 }
@@ -146,6 +151,7 @@ fn stdin_works() {
         env: None,
         cwd: None,
         io: io,
+        detach: false,
     };
     let mut p = Process::new(args).expect("didn't create a proces?!");
     p.io[0].get_mut_ref().write("foobar".as_bytes());
@@ -154,3 +160,23 @@ fn stdin_works() {
     assert_eq!(p.wait(), 0);
     assert_eq!(out, ~"foobar\n");
 }
+
+#[test]
+#[cfg(unix, not(target_os="android"))]
+fn exit_status_future() {
+    let output = PipeStream::new().unwrap();
+    let io = ~[Ignored, CreatePipe(output, false, true)];
+    let args = ProcessConfig {
+        program: "/bin/sh",
+        args: [~"-c", ~"echo foobar; exit 3"],
+        env: None,
+        cwd: None,
+        io: io,
+        detach: false,
+    };
+    let mut p = Process::new(args).expect("didn't create a process?!");
+    let mut out = p.io[1].take_unwrap();
+    let status = p.exit_status();
+    assert_eq!(read_all(&mut out as &mut Reader), ~"foobar\n");
+    assert_eq!(status.recv(), 3);
+}
@@ -20,7 +20,7 @@
 
 extern mod extra;
 
-use extra::tempfile::TempDir;
+use extra::tempfile::{TempDir, NamedTempFile};
 use std::os;
 use std::libc::consts::os::posix88::{S_IRUSR, S_IWUSR, S_IXUSR};
 use std::task;
@@ -78,6 +78,42 @@ fn test_rm_tempdir() {
     assert!(!os::path_exists(&path));
 }
 
+fn test_keep_tempdir() {
+    let path;
+    {
+        let mut tmp = TempDir::new("test_keep_tempdir").unwrap();
+        tmp.keep();
+        path = tmp.path().clone();
+    }
+    assert!(os::path_is_dir(&path));
+    os::remove_dir_recursive(&path);
+
+    let tmp = TempDir::new("test_keep_tempdir").unwrap();
+    let path = tmp.into_path();
+    assert!(os::path_is_dir(&path));
+    os::remove_dir_recursive(&path);
+}
+
+fn test_named_tempfile() {
+    let path = {
+        let f = NamedTempFile::new_in(&Path("."), ".txt").unwrap();
+        let p = f.path();
+        assert!(p.to_str().ends_with(".txt"));
+        assert!(os::path_exists(p) && !os::path_is_dir(p));
+        assert_eq!(p.get_size(), Some(0));
+        p.clone()
+    };
+    assert!(!os::path_exists(&path));
+
+    let a = NamedTempFile::new("test_named_tempfile").unwrap();
+    let b = NamedTempFile::new("test_named_tempfile").unwrap();
+    assert!(a.path() != b.path());
+
+    let path = a.into_path();
+    assert!(os::path_exists(&path));
+    os::remove_file(&path);
+}
+
 // Ideally these would be in std::os but then core would need
 // to depend on std
 fn recursive_mkdir_rel() {
@@ -143,6 +179,8 @@ fn in_tmpdir(f: &fn()) {
 fn main() {
     in_tmpdir(test_tempdir);
     in_tmpdir(test_rm_tempdir);
+    in_tmpdir(test_keep_tempdir);
+    in_tmpdir(test_named_tempfile);
     in_tmpdir(recursive_mkdir_rel);
     in_tmpdir(recursive_mkdir_dot);
     in_tmpdir(recursive_mkdir_rel_2);